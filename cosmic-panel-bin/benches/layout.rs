@@ -0,0 +1,37 @@
+//! Benchmarks for a full relayout of a panel and a dock, with a few to about
+//! fifty applets.
+//!
+//! The applets are wayland clients in the same process, see
+//! `src/space/bench.rs`. Run with `cargo bench --features layout-bench`.
+
+use cosmic_panel::{BenchApplet, LayoutBench};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const APPLET_COUNTS: &[usize] = &[6, 24, 48];
+const OUTPUT_LENGTH: i32 = 3840;
+
+/// Applets of a spread of lengths and priorities, which fit in every section
+/// of the panel. Every fifth applet has no priority.
+fn applets(n: usize) -> Vec<BenchApplet> {
+    (0..n)
+        .map(|i| BenchApplet {
+            length: 24 + 8 * (i % 4) as i32,
+            shrink_priority: (i % 5 != 0).then_some((i * 7 % 11) as u32),
+        })
+        .collect()
+}
+
+fn bench_layout(c: &mut Criterion) {
+    for (name, expand_to_edges) in [("panel", true), ("dock", false)] {
+        let mut group = c.benchmark_group(format!("layout/{name}"));
+        for &n in APPLET_COUNTS {
+            let mut bench = LayoutBench::new(OUTPUT_LENGTH, expand_to_edges, &applets(n))
+                .expect("Failed to set up the panel");
+            group.bench_function(BenchmarkId::from_parameter(n), |b| b.iter(|| bench.layout()));
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_layout);
+criterion_main!(benches);
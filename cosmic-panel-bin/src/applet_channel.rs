@@ -0,0 +1,174 @@
+//! Channel of the applet messages from the panel to the process manager.
+//!
+//! Messages are queued by class, and the process manager takes cleanups before
+//! replaced client sockets, and those before new processes, so a storm of
+//! restarting applets can't delay stopping the applets of removed panels. Each
+//! queue is bounded. Sending from the event loop drops the message if its queue
+//! is full, and sending from the exit handlers of applets waits for room only
+//! for a while, as the process manager may be waiting for that handler itself.
+//! Dropped messages are counted for the control interface.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::sync::mpsc::{
+    self,
+    error::{SendTimeoutError, TrySendError},
+};
+use tracing::warn;
+
+use crate::space::AppletMsg;
+
+/// Capacity of the cleanup, restart and spawn queues.
+const CAPACITY: [usize; 3] = [64, 200, 200];
+/// How long an exit handler waits for room in a full queue.
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppletMsgClass {
+    /// stopping the processes of removed panels
+    Cleanup,
+    /// replacing the sockets and fds of restarted applets
+    Restart,
+    /// starting applets and hooks
+    Spawn,
+}
+
+impl AppletMsgClass {
+    const ALL: [Self; 3] = [Self::Cleanup, Self::Restart, Self::Spawn];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Cleanup => "cleanup",
+            Self::Restart => "restart",
+            Self::Spawn => "spawn",
+        }
+    }
+}
+
+impl AppletMsg {
+    pub fn class(&self) -> AppletMsgClass {
+        match self {
+            AppletMsg::Cleanup(_) | AppletMsg::Shutdown(_) => AppletMsgClass::Cleanup,
+            AppletMsg::ClientSocketPair(_)
+            | AppletMsg::Restart(_)
+            | AppletMsg::NeedNewNotificationFd(..)
+            | AppletMsg::NotificationsConnected { .. } => AppletMsgClass::Restart,
+            AppletMsg::NewProcess(..)
+            | AppletMsg::NewNotificationsProcess(..)
+            | AppletMsg::Launch(_)
+            | AppletMsg::RunHook(_)
+            | AppletMsg::RunPendingHooks => AppletMsgClass::Spawn,
+        }
+    }
+}
+
+/// Sent and dropped applet messages by class, shared with the control
+/// interface.
+#[derive(Debug, Default, Clone)]
+pub struct AppletChannelStats {
+    sent: Arc<[AtomicU64; 3]>,
+    dropped: Arc<[AtomicU64; 3]>,
+}
+
+impl AppletChannelStats {
+    fn sent(&self, class: AppletMsgClass) {
+        self.sent[class as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn dropped(&self, class: AppletMsgClass) {
+        let dropped = self.dropped[class as usize].fetch_add(1, Ordering::Relaxed) + 1;
+        // log the first drops, and then less often during a storm
+        if dropped.is_power_of_two() {
+            warn!("Dropped a {} applet message, {dropped} dropped so far", class.name());
+        }
+    }
+
+    /// Sent and dropped messages of each class since the panel started.
+    pub fn summary(&self) -> HashMap<String, u64> {
+        let mut summary = HashMap::new();
+        for class in AppletMsgClass::ALL {
+            let i = class as usize;
+            summary.insert(format!("{}_sent", class.name()), self.sent[i].load(Ordering::Relaxed));
+            summary.insert(
+                format!("{}_dropped", class.name()),
+                self.dropped[i].load(Ordering::Relaxed),
+            );
+        }
+        summary
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AppletSender {
+    queues: [mpsc::Sender<AppletMsg>; 3],
+    stats: AppletChannelStats,
+}
+
+impl AppletSender {
+    /// Queue `msg` without waiting, or drop it if its queue is full.
+    pub fn try_send(&self, msg: AppletMsg) -> Result<(), TrySendError<AppletMsg>> {
+        let class = msg.class();
+        let res = self.queues[class as usize].try_send(msg);
+        match &res {
+            Ok(()) => self.stats.sent(class),
+            Err(TrySendError::Full(_)) => self.stats.dropped(class),
+            Err(TrySendError::Closed(_)) => {},
+        }
+        res
+    }
+
+    /// Queue `msg`, waiting a while for room if its queue is full.
+    pub async fn send(&self, msg: AppletMsg) -> Result<(), SendTimeoutError<AppletMsg>> {
+        let class = msg.class();
+        let res = self.queues[class as usize].send_timeout(msg, SEND_TIMEOUT).await;
+        match &res {
+            Ok(()) => self.stats.sent(class),
+            Err(SendTimeoutError::Timeout(_)) => self.stats.dropped(class),
+            Err(SendTimeoutError::Closed(_)) => {},
+        }
+        res
+    }
+
+    pub fn stats(&self) -> AppletChannelStats {
+        self.stats.clone()
+    }
+}
+
+#[derive(Debug)]
+pub struct AppletReceiver {
+    queues: [mpsc::Receiver<AppletMsg>; 3],
+}
+
+impl AppletReceiver {
+    /// The next message of the most urgent class, or `None` once every sender
+    /// is dropped.
+    pub async fn recv(&mut self) -> Option<AppletMsg> {
+        let [cleanup, restart, spawn] = &mut self.queues;
+        tokio::select! {
+            biased;
+            Some(msg) = cleanup.recv() => Some(msg),
+            Some(msg) = restart.recv() => Some(msg),
+            Some(msg) = spawn.recv() => Some(msg),
+            else => None,
+        }
+    }
+}
+
+pub fn channel() -> (AppletSender, AppletReceiver) {
+    let [(cleanup_tx, cleanup_rx), (restart_tx, restart_rx), (spawn_tx, spawn_rx)] =
+        CAPACITY.map(mpsc::channel);
+    (
+        AppletSender {
+            queues: [cleanup_tx, restart_tx, spawn_tx],
+            stats: AppletChannelStats::default(),
+        },
+        AppletReceiver { queues: [cleanup_rx, restart_rx, spawn_rx] },
+    )
+}
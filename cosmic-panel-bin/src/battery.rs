@@ -0,0 +1,68 @@
+//! Battery state from UPower, for the battery conditions of panel rules.
+
+use crate::PanelCalloopMsg;
+use cosmic::iced::futures::StreamExt;
+use smithay::reexports::calloop;
+use zbus::{proxy, Connection};
+
+#[proxy(
+    default_service = "org.freedesktop.UPower",
+    interface = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower"
+)]
+trait UPower {
+    #[zbus(property)]
+    fn on_battery(&self) -> zbus::Result<bool>;
+}
+
+#[proxy(
+    default_service = "org.freedesktop.UPower",
+    interface = "org.freedesktop.UPower.Device",
+    default_path = "/org/freedesktop/UPower/devices/DisplayDevice"
+)]
+trait DisplayDevice {
+    #[zbus(property)]
+    fn is_present(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn percentage(&self) -> zbus::Result<f64>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Battery {
+    pub on_battery: bool,
+    /// charge of all batteries, from 0 to 100
+    pub percentage: f32,
+}
+
+/// Forward changes of the battery state to the panel until the connection to
+/// UPower is lost. Nothing is sent for systems without a battery.
+pub async fn watch(tx: calloop::channel::Sender<PanelCalloopMsg>) -> zbus::Result<()> {
+    let conn = Connection::system().await?;
+    let upower = UPowerProxy::new(&conn).await?;
+    let device = DisplayDeviceProxy::new(&conn).await?;
+    if !device.is_present().await.unwrap_or_default() {
+        return Ok(());
+    }
+    let mut on_battery_changed = upower.receive_on_battery_changed().await;
+    let mut percentage_changed = device.receive_percentage_changed().await;
+
+    let mut battery = Battery {
+        on_battery: upower.on_battery().await?,
+        percentage: device.percentage().await? as f32,
+    };
+    loop {
+        if tx.send(PanelCalloopMsg::Battery(battery)).is_err() {
+            return Ok(());
+        }
+        tokio::select! {
+            Some(changed) = on_battery_changed.next() => {
+                battery.on_battery = changed.get().await?;
+            },
+            Some(changed) = percentage_changed.next() => {
+                battery.percentage = changed.get().await? as f32;
+            },
+            else => return Ok(()),
+        }
+    }
+}
@@ -3,19 +3,93 @@ use std::collections::HashMap;
 use crate::xdg_shell_wrapper::shared_state::GlobalState;
 use anyhow::anyhow;
 use cosmic::{
-    cosmic_config::{ConfigGet, CosmicConfigEntry},
+    cosmic_config::{Config, ConfigGet, CosmicConfigEntry},
     theme,
 };
-use cosmic_panel_config::{CosmicPanelConfig, CosmicPanelContainerConfig};
+use cosmic_panel_config::{
+    AutoHide, CosmicPanelConfig, CosmicPanelContainerConfig, OutputPlugins, DO_NOT_DISTURB,
+};
 use cosmic_theme::{Theme, ThemeMode};
 use notify::RecommendedWatcher;
 use smithay::reexports::calloop::{channel, LoopHandle};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[derive(Debug, Clone)]
 enum ConfigUpdate {
     Entries(Vec<String>),
     EntryChanged(CosmicPanelConfig),
+    /// keys of the named entry which are applied to its last loaded config
+    KeysChanged(String, Vec<KeyUpdate>),
+    DoNotDisturb(bool),
+}
+
+/// A change of a single key of a panel config entry.
+///
+/// Writing a config entry changes its keys one after the other, so reloading
+/// the whole entry for each of them may see a partly written entry and
+/// recreate the panel for it. Keys which can be applied on their own are read
+/// alone, and merged into the config the panel already has.
+#[derive(Debug, Clone)]
+enum KeyUpdate {
+    Opacity(f32),
+    Autohide(Option<AutoHide>),
+    PluginsWings(Option<(Vec<String>, Vec<String>)>),
+    PluginsCenter(Option<Vec<String>>),
+    OutputPlugins(Vec<OutputPlugins>),
+}
+
+impl KeyUpdate {
+    /// Read `key` from `helper`, if it can be applied on its own.
+    fn read(helper: &Config, key: &str) -> Option<Self> {
+        let update = match key {
+            "opacity" => KeyUpdate::Opacity(helper.get(key).ok()?),
+            "autohide" => KeyUpdate::Autohide(helper.get(key).ok()?),
+            "plugins_wings" => KeyUpdate::PluginsWings(helper.get(key).ok()?),
+            "plugins_center" => KeyUpdate::PluginsCenter(helper.get(key).ok()?),
+            "output_plugins" => KeyUpdate::OutputPlugins(helper.get(key).ok()?),
+            _ => return None,
+        };
+        Some(update)
+    }
+
+    fn apply(self, entry: &mut CosmicPanelConfig) {
+        match self {
+            KeyUpdate::Opacity(opacity) => entry.opacity = opacity,
+            KeyUpdate::Autohide(autohide) => entry.autohide = autohide,
+            KeyUpdate::PluginsWings(plugins) => entry.plugins_wings = plugins,
+            KeyUpdate::PluginsCenter(plugins) => entry.plugins_center = plugins,
+            KeyUpdate::OutputPlugins(plugins) => entry.output_plugins = plugins,
+        }
+    }
+}
+
+/// The update for a change of `keys` of the entry `name`, only the changed
+/// keys if they can all be applied on their own, or else the whole entry.
+fn entry_update(helper: &Config, name: &str, keys: &[String]) -> ConfigUpdate {
+    let updates = keys.iter().map(|key| KeyUpdate::read(helper, key)).collect::<Option<Vec<_>>>();
+    if let Some(updates) = updates.filter(|u| !u.is_empty()) {
+        return ConfigUpdate::KeysChanged(name.to_string(), updates);
+    }
+    let entry = match CosmicPanelConfig::get_entry(helper) {
+        Ok(entry) => entry,
+        Err((err, entry)) => {
+            for error in err {
+                error!("Failed to get entry value: {:?}", error);
+            }
+            entry
+        },
+    };
+    ConfigUpdate::EntryChanged(entry)
+}
+
+/// Warn about the conflicts of the changed entry `name` with the other entries,
+/// like those of all entries when the config is loaded.
+fn warn_conflicts(config: &CosmicPanelContainerConfig, name: &str) {
+    for conflict in config.clone().resolve_conflicts() {
+        if conflict.involves(name) {
+            warn!("Panel config conflict: {conflict}");
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -26,6 +100,8 @@ enum ThemeUpdate {
     Dark(theme::CosmicTheme),
     /// light theme bg change,
     Light(theme::CosmicTheme),
+    /// reload all of the theme, e.g. for changes before the watchers started
+    Reapply,
 }
 
 pub fn watch_cosmic_theme(
@@ -47,6 +123,9 @@ pub fn watch_cosmic_theme(
             channel::Event::Msg(ThemeUpdate::Light(theme)) => {
                 state.space.set_light(theme);
             },
+            channel::Event::Msg(ThemeUpdate::Reapply) => {
+                state.space.reapply_theme();
+            },
             channel::Event::Closed => {},
         };
     })?;
@@ -96,12 +175,18 @@ pub fn watch_cosmic_theme(
         })
         .map_err(|e| anyhow!(format!("{:?}", e)))?;
 
+    // the panels were themed before the watchers were added
+    entries_tx.send(ThemeUpdate::Reapply)?;
+
     Ok(vec![theme_watcher_dark, theme_watcher_light, theme_watcher_mode])
 }
 
+/// Watch the entries of `config`, and with `watch_entries` the list of entries
+/// for added and removed panels.
 pub fn watch_config(
     config: &CosmicPanelContainerConfig,
     handle: LoopHandle<GlobalState>,
+    watch_entries: bool,
 ) -> Result<HashMap<String, RecommendedWatcher>, Box<dyn std::error::Error>> {
     let (entries_tx, entries_rx) = channel::sync_channel::<ConfigUpdate>(30);
 
@@ -140,23 +225,15 @@ pub fn watch_config(
                     let helper = CosmicPanelConfig::cosmic_config(&name_clone)
                         .expect("Failed to load cosmic config");
                     let watcher = helper
-                        .watch(move |helper, _keys| {
-                            let new = match CosmicPanelConfig::get_entry(helper) {
-                                Ok(entry) => entry,
-                                Err((err, entry)) => {
-                                    for error in err {
-                                        error!("Failed to get entry value: {:?}", error);
-                                    }
-                                    entry
-                                },
-                            };
+                        .watch(move |helper, keys| {
                             entries_tx_clone
-                                .send(ConfigUpdate::EntryChanged(new))
+                                .send(entry_update(helper, &name_clone, keys))
                                 .expect("Failed to send Config Update");
                         })
                         .expect("Failed to watch cosmic config");
                     state.space.watchers.insert(entry.name.clone(), watcher);
 
+                    let name = entry.name.clone();
                     state.space.update_space(
                         entry,
                         &state.client_state.compositor_state,
@@ -167,6 +244,7 @@ pub fn watch_config(
                         None,
                         state.client_state.overlap_notify.clone(),
                     );
+                    warn_conflicts(&state.space.config, &name);
                 }
                 info!("Removing entries: {:?}", entries);
                 let to_remove = state
@@ -180,8 +258,38 @@ pub fn watch_config(
                 for entry in to_remove {
                     state.space.remove_space(entry);
                 }
+                state.space.sync_activation_shortcuts();
+            },
+            channel::Event::Msg(ConfigUpdate::DoNotDisturb(enabled)) => {
+                state.space.set_do_not_disturb(enabled);
+            },
+            channel::Event::Msg(ConfigUpdate::KeysChanged(name, updates)) => {
+                let Some(mut config) = state.space.configured.get(&name).map(|(c, _)| c.clone())
+                else {
+                    return;
+                };
+                for update in updates {
+                    update.apply(&mut config);
+                }
+                let name = config.name.clone();
+                // only a change of the applets in a section recreates the panel,
+                // the other keys are animated or applied to the existing spaces
+                state.space.update_space(
+                    config,
+                    &state.client_state.compositor_state,
+                    state.client_state.fractional_scaling_manager.as_ref(),
+                    state.client_state.viewporter_state.as_ref(),
+                    &mut state.client_state.layer_state,
+                    &state.client_state.queue_handle,
+                    None,
+                    state.client_state.overlap_notify.clone(),
+                );
+                state.space.sync_clones(&name);
+                state.space.sync_activation_shortcuts();
+                warn_conflicts(&state.space.config, &name);
             },
             channel::Event::Msg(ConfigUpdate::EntryChanged(config)) => {
+                let name = config.name.clone();
                 state.space.update_space(
                     config,
                     &state.client_state.compositor_state,
@@ -192,30 +300,46 @@ pub fn watch_config(
                     None,
                     state.client_state.overlap_notify.clone(),
                 );
+                state.space.sync_clones(&name);
+                state.space.sync_activation_shortcuts();
+                warn_conflicts(&state.space.config, &name);
             },
             channel::Event::Closed => {},
         };
     })?;
 
-    let cosmic_config_entries =
-        CosmicPanelContainerConfig::cosmic_config().expect("Failed to load cosmic config");
-    info!("Watching panel config entries for changes {:?}", cosmic_config_entries);
-
-    let entries_tx_clone = entries_tx.clone();
-    let entries_watcher = cosmic_config_entries
-        .watch(move |helper, keys| match helper.get::<Vec<String>>(&keys[0]) {
-            Ok(entries) => {
-                entries_tx_clone
-                    .send(ConfigUpdate::Entries(entries))
-                    .expect("Failed to send entries");
-            },
-            Err(err) => {
-                error!("Failed to get entries: {:?}", err);
-            },
-        })
-        .expect("Failed to watch cosmic config");
+    let mut watchers = HashMap::new();
+    if watch_entries {
+        let cosmic_config_entries =
+            CosmicPanelContainerConfig::cosmic_config().expect("Failed to load cosmic config");
+        info!("Watching panel config entries for changes {:?}", cosmic_config_entries);
 
-    let mut watchers = HashMap::from([("entries".to_string(), entries_watcher)]);
+        let entries_tx_clone = entries_tx.clone();
+        let entries_watcher = cosmic_config_entries
+            .watch(move |helper, keys| {
+                for key in keys {
+                    if key == DO_NOT_DISTURB {
+                        let enabled = helper.get::<bool>(key).unwrap_or_default();
+                        entries_tx_clone
+                            .send(ConfigUpdate::DoNotDisturb(enabled))
+                            .expect("Failed to send do not disturb");
+                        continue;
+                    }
+                    match helper.get::<Vec<String>>(key) {
+                        Ok(entries) => {
+                            entries_tx_clone
+                                .send(ConfigUpdate::Entries(entries))
+                                .expect("Failed to send entries");
+                        },
+                        Err(err) => {
+                            error!("Failed to get entries: {:?}", err);
+                        },
+                    }
+                }
+            })
+            .expect("Failed to watch cosmic config");
+        watchers.insert("entries".to_string(), entries_watcher);
+    }
 
     for entry in &config.config_list {
         let entries_tx_clone = entries_tx.clone();
@@ -226,17 +350,8 @@ pub fn watch_config(
         let watcher = helper
             .watch(move |helper, keys| {
                 info!("Entry changed: {:?}", keys);
-                let new = match CosmicPanelConfig::get_entry(helper) {
-                    Ok(entry) => entry,
-                    Err((err, entry)) => {
-                        for error in err {
-                            error!("Failed to get entry value: {:?}", error);
-                        }
-                        entry
-                    },
-                };
                 entries_tx_clone
-                    .send(ConfigUpdate::EntryChanged(new))
+                    .send(entry_update(helper, &name_clone, keys))
                     .expect("Failed to send Config Update");
             })
             .expect("Failed to watch cosmic config");
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use zbus::{proxy, Connection};
+
+#[proxy(
+    default_service = "com.system76.CosmicPanel",
+    interface = "com.system76.CosmicPanel",
+    default_path = "/com/system76/CosmicPanel"
+)]
+trait Panel {
+    fn logs(&self, limit: u32) -> zbus::Result<Vec<String>>;
+
+    fn log_filter(&self) -> zbus::Result<String>;
+
+    fn set_log_filter(&self, directives: &str) -> zbus::Result<()>;
+
+    fn frame_stats(&self) -> zbus::Result<HashMap<String, u64>>;
+    fn applet_channel_stats(&self) -> zbus::Result<HashMap<String, u64>>;
+
+    fn set_debug_overlay(&self, enabled: bool) -> zbus::Result<()>;
+
+    fn set_edit_mode(&self, enabled: bool) -> zbus::Result<()>;
+
+    fn set_presentation_mode(&self, enabled: bool) -> zbus::Result<()>;
+
+    fn toggle_presentation_reveal(&self) -> zbus::Result<()>;
+
+    fn set_visibility_override(&self, panel: &str, visibility: &str) -> zbus::Result<()>;
+
+    fn toggle_panel(&self, panel: &str) -> zbus::Result<()>;
+
+    fn duplicate_panel(&self, panel: &str, output: &str) -> zbus::Result<String>;
+
+    fn detach_panel(&self, panel: &str) -> zbus::Result<()>;
+
+    fn do_not_disturb(&self) -> zbus::Result<bool>;
+
+    fn set_do_not_disturb(&self, enabled: bool) -> zbus::Result<()>;
+
+    fn panels(&self) -> zbus::Result<Vec<(String, String, String, String, i32, i32, i32)>>;
+
+    fn applets(&self) -> zbus::Result<Vec<(String, String, String, u32, u32, i32, u64, u64)>>;
+
+    fn restart_applet(&self, panel: &str, name: &str) -> zbus::Result<()>;
+
+    fn activate_applet(&self, index: u32) -> zbus::Result<()>;
+
+    fn snapshot(
+        &self,
+        panel: &str,
+        output: &str,
+        width: i32,
+        height: i32,
+        scale: f64,
+        theme: &str,
+    ) -> zbus::Result<Vec<u8>>;
+
+    fn show_osd(&self, output: &str, icon: &str, text: &str, value: i32) -> zbus::Result<()>;
+
+    fn reapply_theme(&self) -> zbus::Result<()>;
+}
+
+const USAGE: &str = "USAGE: cosmic-panel-ctl <COMMAND>
+
+COMMANDS:
+    logs [LIMIT]                print the most recent log records of the running panel
+    log-filter [DIRECTIVES]     print or replace the tracing filter, e.g. `warn,cosmic_panel=debug`
+    frame-stats                 print rolling render statistics
+    channel-stats               print sent and dropped messages to the applet process manager
+    debug-overlay <on|off>      draw damage, element bounds and input regions
+    edit-mode <on|off>          reorder or remove applets by dragging them on the panels
+    presentation <on|off>       hide all panels until presentation mode is turned off
+    presentation-reveal         show or hide the panels during a presentation
+    visibility [PANEL] <auto|visible|hidden>
+                                keep a panel, or all panels, shown or hidden regardless of autohide
+    toggle [PANEL]              reveal autohidden panels with keyboard focus, or hide them again, for a shortcut
+    duplicate <PANEL> <OUTPUT>  clone a panel onto another output, following its config until detached
+    detach <PANEL>              let a clone be configured on its own
+    dnd [on|off]                print or set do not disturb, silencing applet badges
+    panels                      print the state of every mapped panel
+    applets                     print the process state of every applet
+    restart-applet <PANEL> <NAME>
+                                restart one applet, e.g. `restart-applet Panel com.system76.CosmicAppletTime`
+    activate-applet <N>         click the Nth applet of the panel with activation shortcuts, for Super+N
+    snapshot <PANEL> <FILE> [output=NAME] [size=WxH] [scale=S] [theme=light|dark]
+                                render a panel to a PNG file
+    osd <ICON> <TEXT> [value=N] [output=NAME]
+                                show an on-screen display, with a level bar from 0 to 100
+    reapply-theme               reload the system theme and apply it to every panel";
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+
+    let conn = Connection::session().await?;
+    let proxy = PanelProxy::new(&conn).await?;
+
+    match args.as_slice() {
+        ["logs"] => print_lines(proxy.logs(0).await?),
+        ["logs", limit] => print_lines(proxy.logs(limit.parse()?).await?),
+        ["log-filter"] => println!("{}", proxy.log_filter().await?),
+        ["log-filter", directives] => proxy.set_log_filter(directives).await?,
+        ["frame-stats"] => {
+            let mut stats: Vec<_> = proxy.frame_stats().await?.into_iter().collect();
+            stats.sort();
+            print_lines(stats.into_iter().map(|(k, v)| format!("{k}: {v}")).collect());
+        },
+        ["channel-stats"] => {
+            let mut stats: Vec<_> = proxy.applet_channel_stats().await?.into_iter().collect();
+            stats.sort();
+            print_lines(stats.into_iter().map(|(k, v)| format!("{k}: {v}")).collect());
+        },
+        ["debug-overlay", "on"] => proxy.set_debug_overlay(true).await?,
+        ["debug-overlay", "off"] => proxy.set_debug_overlay(false).await?,
+        ["edit-mode", "on"] => proxy.set_edit_mode(true).await?,
+        ["edit-mode", "off"] => proxy.set_edit_mode(false).await?,
+        ["presentation", "on"] => proxy.set_presentation_mode(true).await?,
+        ["presentation", "off"] => proxy.set_presentation_mode(false).await?,
+        ["presentation-reveal"] => proxy.toggle_presentation_reveal().await?,
+        ["visibility", visibility] => proxy.set_visibility_override("", visibility).await?,
+        ["visibility", panel, visibility] => {
+            proxy.set_visibility_override(panel, visibility).await?
+        },
+        ["toggle"] => proxy.toggle_panel("").await?,
+        ["toggle", panel] => proxy.toggle_panel(panel).await?,
+        ["duplicate", panel, output] => println!("{}", proxy.duplicate_panel(panel, output).await?),
+        ["detach", panel] => proxy.detach_panel(panel).await?,
+        ["dnd"] => println!("{}", if proxy.do_not_disturb().await? { "on" } else { "off" }),
+        ["dnd", "on"] => proxy.set_do_not_disturb(true).await?,
+        ["dnd", "off"] => proxy.set_do_not_disturb(false).await?,
+        ["panels"] => {
+            for (name, output, anchor, visibility, w, h, exclusive_zone) in proxy.panels().await? {
+                println!(
+                    "{name} on {output} ({anchor}): {visibility}, {w}x{h}, exclusive zone \
+                     {exclusive_zone}"
+                );
+            }
+        },
+        ["applets"] => {
+            for (panel, output, name, pid, restarts, exit_code, uptime, rss) in
+                proxy.applets().await?
+            {
+                println!(
+                    "{name} in {panel} on {output}: pid {pid}, up {uptime}s, rss {} KiB, \
+                     {restarts} restarts (last exit code {exit_code})",
+                    rss / 1024
+                );
+            }
+        },
+        ["restart-applet", panel, name] => proxy.restart_applet(panel, name).await?,
+        ["activate-applet", index] => proxy.activate_applet(index.parse()?).await?,
+        ["snapshot", panel, file, options @ ..] => {
+            let (mut output, mut width, mut height, mut scale, mut theme) = ("", 0, 0, 0., "");
+            for option in options {
+                match option.split_once('=') {
+                    Some(("output", o)) => output = o,
+                    Some(("size", size)) => {
+                        let (w, h) = size.split_once('x').context("size must be WxH")?;
+                        (width, height) = (w.parse()?, h.parse()?);
+                    },
+                    Some(("scale", s)) => scale = s.parse()?,
+                    Some(("theme", t)) => theme = t,
+                    _ => bail!("Unknown snapshot option {option}"),
+                }
+            }
+            let png = proxy.snapshot(panel, output, width, height, scale, theme).await?;
+            std::fs::write(file, png)?;
+        },
+        ["osd", icon, text, options @ ..] => {
+            let (mut output, mut value) = ("", -1);
+            for option in options {
+                match option.split_once('=') {
+                    Some(("output", o)) => output = o,
+                    Some(("value", v)) => value = v.parse()?,
+                    _ => bail!("Unknown osd option {option}"),
+                }
+            }
+            proxy.show_osd(output, icon, text, value).await?;
+        },
+        ["reapply-theme"] => proxy.reapply_theme().await?,
+        _ => {
+            println!("{}", USAGE);
+            std::process::exit(1);
+        },
+    }
+    Ok(())
+}
+
+fn print_lines(lines: Vec<String>) {
+    for line in lines {
+        println!("{}", line);
+    }
+}
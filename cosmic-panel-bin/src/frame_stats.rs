@@ -0,0 +1,83 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::iced::buffer_pool::TextureUsage;
+
+/// Number of frames kept for the rolling statistics.
+const SAMPLES: usize = 240;
+
+/// Timing of a single iteration of the main loop which rendered at least one
+/// panel.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameSample {
+    /// time spent dispatching and flushing the embedded applet clients
+    pub dispatch: Duration,
+    /// CPU time spent building and submitting the frame
+    pub render: Duration,
+    /// time spent in `swap_buffers`, which only includes GPU work the driver
+    /// waits for when swapping, not a measure of the GPU time of the frame
+    pub swap: Duration,
+    /// damaged area in physical pixels
+    pub damage_area: u64,
+}
+
+impl FrameSample {
+    pub fn merge(&mut self, other: FrameSample) {
+        self.dispatch += other.dispatch;
+        self.render += other.render;
+        self.swap += other.swap;
+        self.damage_area += other.damage_area;
+    }
+}
+
+/// Rolling frame statistics and current texture memory usage, shared with the
+/// control interface.
+#[derive(Debug, Default, Clone)]
+pub struct FrameStats {
+    samples: Arc<Mutex<VecDeque<FrameSample>>>,
+    textures: Arc<Mutex<TextureUsage>>,
+}
+
+impl FrameStats {
+    pub fn push(&self, sample: FrameSample) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() >= SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    pub fn set_texture_usage(&self, usage: TextureUsage) {
+        *self.textures.lock().unwrap() = usage;
+    }
+
+    /// Average and maximum of each measurement over the buffered frames, and
+    /// the current texture usage. Durations are in microseconds, areas in
+    /// physical pixels, memory in bytes.
+    pub fn summary(&self) -> HashMap<String, u64> {
+        let samples = self.samples.lock().unwrap();
+        let n = samples.len().max(1) as u64;
+        let mut summary = HashMap::new();
+        summary.insert("frames".to_string(), samples.len() as u64);
+
+        let mut add = |name: &str, values: Vec<u64>| {
+            summary.insert(format!("avg_{name}"), values.iter().sum::<u64>() / n);
+            summary.insert(format!("max_{name}"), values.into_iter().max().unwrap_or_default());
+        };
+        add("dispatch_us", samples.iter().map(|s| s.dispatch.as_micros() as u64).collect());
+        add("render_us", samples.iter().map(|s| s.render.as_micros() as u64).collect());
+        add("swap_us", samples.iter().map(|s| s.swap.as_micros() as u64).collect());
+        add("damage_px", samples.iter().map(|s| s.damage_area).collect());
+
+        let textures = *self.textures.lock().unwrap();
+        summary.insert("texture_element_bytes".to_string(), textures.element_bytes as u64);
+        summary.insert("texture_pooled_bytes".to_string(), textures.pooled_bytes as u64);
+        summary.insert("texture_applet_bytes".to_string(), textures.applet_bytes as u64);
+        summary.insert("texture_budget_bytes".to_string(), textures.budget_bytes as u64);
+        summary.insert("texture_evictions".to_string(), textures.evictions);
+        summary
+    }
+}
@@ -0,0 +1,163 @@
+//! Lifecycle hooks, user commands run on events of a panel.
+//!
+//! Hooks let users automate around the panels, e.g. pause compositing effects
+//! while the dock is hidden. A hook is run by `sh -c` through launch-pad like
+//! an applet, but it is not restarted when it exits. Each hook of a panel is
+//! run at most once per [`HOOK_INTERVAL`], so a flapping autohide or a crashing
+//! applet can't flood the session with processes. Of the events in between,
+//! the last one is run once the interval passed, so the hook always sees the
+//! final state.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use cosmic_panel_config::PanelHooks;
+use launch_pad::{process::Process, ProcessManager};
+use tracing::{debug, error};
+
+use crate::{applet_channel::AppletSender, space::AppletMsg};
+
+/// Minimum time between two runs of the same hook of a panel.
+pub const HOOK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PanelHook {
+    Shown,
+    Hidden,
+    OutputChanged,
+    AppletCrashed,
+}
+
+impl PanelHook {
+    /// The value of `COSMIC_PANEL_EVENT`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Shown => "shown",
+            Self::Hidden => "hidden",
+            Self::OutputChanged => "output-changed",
+            Self::AppletCrashed => "applet-crashed",
+        }
+    }
+
+    /// The command configured for the hook, if any.
+    pub fn command(self, hooks: &PanelHooks) -> Option<&str> {
+        match self {
+            Self::Shown => hooks.shown.as_deref(),
+            Self::Hidden => hooks.hidden.as_deref(),
+            Self::OutputChanged => hooks.output_changed.as_deref(),
+            Self::AppletCrashed => hooks.applet_crashed.as_deref(),
+        }
+        .filter(|c| !c.trim().is_empty())
+    }
+}
+
+/// A hook to run, sent to the thread of the process manager.
+#[derive(Debug, Clone)]
+pub struct HookRun {
+    pub hook: PanelHook,
+    pub command: String,
+    pub panel: String,
+    pub output: String,
+    /// the applet which caused the event
+    pub applet: Option<String>,
+}
+
+impl HookRun {
+    /// The run of `hook` for the panel, or `None` if it is not configured.
+    pub fn new(
+        hook: PanelHook,
+        hooks: &PanelHooks,
+        panel: &str,
+        output: &str,
+        applet: Option<&str>,
+    ) -> Option<Self> {
+        Some(Self {
+            hook,
+            command: hook.command(hooks)?.to_string(),
+            panel: panel.to_string(),
+            output: output.to_string(),
+            applet: applet.map(str::to_string),
+        })
+    }
+
+    fn process(self) -> Process {
+        let mut env = vec![
+            ("COSMIC_PANEL_NAME".to_string(), self.panel),
+            ("COSMIC_PANEL_OUTPUT".to_string(), self.output),
+            ("COSMIC_PANEL_EVENT".to_string(), self.hook.name().to_string()),
+        ];
+        if let Some(applet) = self.applet {
+            env.push(("COSMIC_PANEL_APPLET".to_string(), applet));
+        }
+        Process::new()
+            .with_executable("sh")
+            .with_args(vec!["-c".to_string(), self.command])
+            .with_env(env)
+            .with_on_exit(|mut pman, key, _err_code, _is_restarting| async move {
+                _ = pman.stop_process(key).await;
+            })
+    }
+}
+
+/// A hook of a panel on an output.
+type HookKey = (String, String, PanelHook);
+
+/// Debounces the hooks of every panel.
+#[derive(Debug)]
+pub struct HookRunner {
+    last_run: HashMap<HookKey, Instant>,
+    /// the last event of each hook within its interval, run once it passed
+    pending: HashMap<HookKey, HookRun>,
+    /// sends [`AppletMsg::RunPendingHooks`] once an interval passed
+    applet_tx: AppletSender,
+}
+
+impl HookRunner {
+    pub fn new(applet_tx: AppletSender) -> Self {
+        Self { last_run: HashMap::new(), pending: HashMap::new(), applet_tx }
+    }
+
+    pub async fn run(&mut self, process_manager: &ProcessManager, run: HookRun) {
+        let key = (run.panel.clone(), run.output.clone(), run.hook);
+        let since = self.last_run.get(&key).map(Instant::elapsed);
+        if let Some(since) = since.filter(|since| *since < HOOK_INTERVAL) {
+            debug!("Delaying the {} hook of {}", run.hook.name(), run.panel);
+            if self.pending.insert(key, run).is_none() {
+                let applet_tx = self.applet_tx.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(HOOK_INTERVAL - since).await;
+                    _ = applet_tx.try_send(AppletMsg::RunPendingHooks);
+                });
+            }
+            return;
+        }
+        self.start(process_manager, key, run).await;
+    }
+
+    /// Run the last events of the hooks whose interval passed.
+    pub async fn run_pending(&mut self, process_manager: &ProcessManager) {
+        let due: Vec<_> = self
+            .pending
+            .keys()
+            .filter(|key| self.last_run.get(key).map_or(true, |t| t.elapsed() >= HOOK_INTERVAL))
+            .cloned()
+            .collect();
+        for key in due {
+            if let Some(run) = self.pending.remove(&key) {
+                self.start(process_manager, key, run).await;
+            }
+        }
+    }
+
+    async fn start(&mut self, process_manager: &ProcessManager, key: HookKey, run: HookRun) {
+        self.last_run.insert(key, Instant::now());
+
+        let name = run.hook.name();
+        let panel = run.panel.clone();
+        if let Err(err) = process_manager.start(run.process()).await {
+            error!("Failed to run the {} hook of {}: {:?}", name, panel, err);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Pool of memory render buffers shared by all iced elements, and the memory
+//! budget for element and applet textures.
+//!
+//! Elements release their buffers here when they are resized, leave an output
+//! or are dropped, and take them back when they need a buffer of the same
+//! size. During a panel size animation this turns a fresh allocation per
+//! element and frame into a lookup. Released buffers are kept for a short time
+//! and within a memory budget, and the pool is trimmed once per loop
+//! iteration.
+//!
+//! When the buffers of all elements together with the textures of the applets
+//! exceed the texture budget, the buffers of the elements which have not been
+//! rendered for the longest time are dropped, and allocated again when the
+//! element is drawn next. Applet textures are owned by smithay and only
+//! counted.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    sync::{Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use smithay::{
+    backend::{allocator::Fourcc, renderer::element::memory::MemoryRenderBuffer},
+    utils::{Buffer as BufferCoords, Size, Transform},
+};
+
+/// How long an unused buffer is kept for reuse.
+const MAX_IDLE: Duration = Duration::from_secs(5);
+/// Upper bound for the memory held by unused buffers.
+const MAX_BYTES: usize = 32 * 1024 * 1024;
+/// Elements rendered more recently than this are never evicted.
+const MIN_EVICT_AGE: Duration = Duration::from_secs(1);
+
+/// Memory budget for textures in bytes, set in MiB with
+/// `COSMIC_PANEL_TEXTURE_BUDGET`.
+static BUDGET: Lazy<usize> = Lazy::new(|| {
+    std::env::var("COSMIC_PANEL_TEXTURE_BUDGET")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(64)
+        .saturating_mul(1024 * 1024)
+});
+
+thread_local! {
+    static POOL: RefCell<BufferPool> = RefCell::new(BufferPool::default());
+}
+
+/// An element holding memory render buffers which may be evicted.
+pub trait BufferOwner {
+    /// bytes held by the buffers of the element
+    fn buffer_bytes(&self) -> usize;
+    /// when the element was last rendered
+    fn last_render(&self) -> Instant;
+    /// Drop all buffers of the element, returning the bytes freed.
+    fn evict_buffers(&mut self) -> usize;
+}
+
+/// Texture memory in use, as reported through the control interface.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TextureUsage {
+    /// buffers held by iced elements
+    pub element_bytes: usize,
+    /// unused buffers kept in the pool
+    pub pooled_bytes: usize,
+    /// estimated size of the textures of applet surfaces
+    pub applet_bytes: usize,
+    pub budget_bytes: usize,
+    /// element buffers evicted since startup
+    pub evictions: u64,
+}
+
+#[derive(Default)]
+struct BufferPool {
+    free: HashMap<(i32, i32), Vec<(MemoryRenderBuffer, Instant)>>,
+    // release order, oldest first, for trimming to the memory budget
+    released: VecDeque<((i32, i32), Instant)>,
+    bytes: usize,
+    owners: Vec<Weak<Mutex<dyn BufferOwner>>>,
+    evictions: u64,
+}
+
+pub fn byte_size(size: Size<i32, BufferCoords>) -> usize {
+    size.w.max(0) as usize * size.h.max(0) as usize * 4
+}
+
+impl BufferPool {
+    fn take(&mut self, key: (i32, i32)) -> Option<MemoryRenderBuffer> {
+        let (buffer, released_at) = self.free.get_mut(&key)?.pop()?;
+        if let Some(i) = self.released.iter().position(|r| *r == (key, released_at)) {
+            self.released.remove(i);
+        }
+        self.bytes -= byte_size(key.into());
+        Some(buffer)
+    }
+
+    fn put(&mut self, key: (i32, i32), buffer: MemoryRenderBuffer) {
+        let now = Instant::now();
+        self.free.entry(key).or_default().push((buffer, now));
+        self.released.push_back((key, now));
+        self.bytes += byte_size(key.into());
+        self.trim(now, MAX_BYTES);
+    }
+
+    fn trim(&mut self, now: Instant, max_bytes: usize) {
+        while let Some(&(key, released_at)) = self.released.front() {
+            if self.bytes <= max_bytes && now.duration_since(released_at) < MAX_IDLE {
+                break;
+            }
+            self.released.pop_front();
+            if let Some(buffers) = self.free.get_mut(&key) {
+                // the oldest buffer of a size is always first
+                buffers.remove(0);
+                if buffers.is_empty() {
+                    self.free.remove(&key);
+                }
+            }
+            self.bytes -= byte_size(key.into());
+        }
+    }
+}
+
+/// Take a buffer of `size` from the pool, or allocate one.
+pub fn acquire(size: Size<i32, BufferCoords>) -> MemoryRenderBuffer {
+    POOL.with_borrow_mut(|pool| pool.take((size.w, size.h))).unwrap_or_else(|| {
+        MemoryRenderBuffer::new(Fourcc::Argb8888, size, 1, Transform::Normal, None)
+    })
+}
+
+/// Hand a buffer of `size` back to the pool. The caller must not keep another
+/// handle to it.
+pub fn release(size: Size<i32, BufferCoords>, buffer: MemoryRenderBuffer) {
+    if size.w <= 0 || size.h <= 0 || byte_size(size) > MAX_BYTES {
+        return;
+    }
+    // elements may be dropped while the thread is being torn down
+    _ = POOL.try_with(|pool| pool.borrow_mut().put((size.w, size.h), buffer));
+}
+
+/// Track the buffers of `owner` for eviction.
+pub fn register(owner: Weak<Mutex<dyn BufferOwner>>) {
+    POOL.with_borrow_mut(|pool| pool.owners.push(owner));
+}
+
+/// Drop buffers which have been unused for too long, and evict element
+/// buffers while the total exceeds the texture budget. `applet_bytes` is the
+/// estimated size of the applet textures.
+pub fn trim(applet_bytes: usize) -> TextureUsage {
+    POOL.with_borrow_mut(|pool| {
+        let now = Instant::now();
+        pool.owners.retain(|o| o.strong_count() > 0);
+        let mut owners: Vec<_> = pool
+            .owners
+            .iter()
+            .filter_map(|o| o.upgrade())
+            .filter_map(|o| {
+                // an element which is locked is being used right now
+                let (bytes, last_render) = {
+                    let guard = o.try_lock().ok()?;
+                    (guard.buffer_bytes(), guard.last_render())
+                };
+                Some((o, bytes, last_render))
+            })
+            .collect();
+        let mut element_bytes: usize = owners.iter().map(|(_, bytes, _)| bytes).sum();
+
+        let budget = *BUDGET;
+        let in_use = element_bytes + applet_bytes;
+        pool.trim(now, MAX_BYTES.min(budget.saturating_sub(in_use)));
+
+        if in_use + pool.bytes > budget {
+            owners.sort_by_key(|(_, _, last_render)| *last_render);
+            for (owner, bytes, last_render) in owners {
+                if element_bytes + applet_bytes + pool.bytes <= budget
+                    || now.duration_since(last_render) < MIN_EVICT_AGE
+                {
+                    break;
+                }
+                if bytes == 0 {
+                    continue;
+                }
+                if let Ok(mut guard) = owner.try_lock() {
+                    element_bytes -= guard.evict_buffers().min(element_bytes);
+                    pool.evictions += 1;
+                }
+            }
+        }
+
+        TextureUsage {
+            element_bytes,
+            pooled_bytes: pool.bytes,
+            applet_bytes,
+            budget_bytes: budget,
+            evictions: pool.evictions,
+        }
+    })
+}
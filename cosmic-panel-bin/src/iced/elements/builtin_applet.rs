@@ -0,0 +1,411 @@
+// Element for the applets rendered by the panel itself.
+//
+// Trivial widgets don't need a process of their own, which would cost more
+// memory than the widget is worth. They are configured like other applets, by
+// a name in one of the applet lists:
+// - `builtin:clock`
+// - `builtin:separator`
+// - `builtin:cpu`
+// - `builtin:launcher:<desktop id>`
+// - `builtin:plugin:<id>`, with the `plugin-api` feature
+
+use std::{ffi::OsStr, fs};
+
+use calloop::LoopHandle;
+use cosmic::{
+    iced::{Alignment, Length},
+    theme::Button,
+    widget::{button, column, divider, icon, text},
+    Theme,
+};
+use cosmic_panel_config::{CosmicPanelConfig, Side};
+use freedesktop_desktop_entry::{DesktopEntry, Iter};
+use launch_pad::process::Process;
+use smithay::utils::{Logical, Size};
+use tracing::{error, warn};
+
+#[cfg(feature = "plugin-api")]
+use crate::plugin::{create_plugin, PanelPlugin, PluginContext, PluginMessage};
+use crate::{
+    iced::{Element, IcedElement, Program},
+    space::AppletMsg,
+    xdg_shell_wrapper::{shared_state::GlobalState, util::local_time},
+};
+
+pub const BUILTIN_PREFIX: &str = "builtin:";
+
+pub type BuiltinAppletElement = IcedElement<BuiltinApplet>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuiltinKind {
+    Clock,
+    Separator,
+    CpuMeter,
+    /// an icon starting the desktop entry with the given id
+    Launcher(String),
+    /// a plugin registered with the given id
+    #[cfg(feature = "plugin-api")]
+    Plugin(String),
+}
+
+impl BuiltinKind {
+    /// The builtin applet configured as `name`, or `None` for applets which run
+    /// in their own process.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.strip_prefix(BUILTIN_PREFIX)? {
+            "clock" => Some(Self::Clock),
+            "separator" => Some(Self::Separator),
+            "cpu" => Some(Self::CpuMeter),
+            kind => match kind.split_once(':') {
+                Some(("launcher", id)) if !id.is_empty() => Some(Self::Launcher(id.to_string())),
+                #[cfg(feature = "plugin-api")]
+                Some(("plugin", id)) if !id.is_empty() => Some(Self::Plugin(id.to_string())),
+                _ => {
+                    warn!("Unknown builtin applet {}", name);
+                    None
+                },
+            },
+        }
+    }
+
+    /// Whether the applet is updated by [`Message::Tick`].
+    pub fn ticks(&self) -> bool {
+        match self {
+            Self::Clock | Self::CpuMeter => true,
+            #[cfg(feature = "plugin-api")]
+            Self::Plugin(_) => true,
+            _ => false,
+        }
+    }
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    name.starts_with(BUILTIN_PREFIX)
+}
+
+pub fn builtin_applet_element(
+    name: String,
+    kind: BuiltinKind,
+    section: Side,
+    config: &CosmicPanelConfig,
+    loop_handle: LoopHandle<'static, GlobalState>,
+    theme: Theme,
+    panel_id: usize,
+) -> BuiltinAppletElement {
+    let icon_size = config.get_applet_icon_size(true) as u16;
+    let padding = config.get_applet_padding(true);
+    let thickness = (icon_size + padding * 2) as i32;
+    let is_horizontal = config.is_horizontal();
+
+    let launcher = match &kind {
+        BuiltinKind::Launcher(id) => launcher_entry(id),
+        _ => None,
+    };
+    #[cfg(feature = "plugin-api")]
+    let context = PluginContext { section, is_horizontal, icon_size, padding };
+    #[cfg(feature = "plugin-api")]
+    let plugin = match &kind {
+        BuiltinKind::Plugin(id) => create_plugin(id, &context),
+        _ => None,
+    };
+    let mut applet = BuiltinApplet {
+        name,
+        kind,
+        section,
+        icon_size,
+        padding,
+        is_horizontal,
+        launcher,
+        #[cfg(feature = "plugin-api")]
+        plugin,
+        revision: 0,
+        time: (0, 0),
+        cpu_sample: (0, 0),
+        cpu_usage: 0,
+    };
+    applet.tick();
+
+    let lengthwise = match &applet.kind {
+        BuiltinKind::Separator => padding as i32,
+        #[cfg(feature = "plugin-api")]
+        BuiltinKind::Plugin(_) => {
+            applet.plugin.as_ref().and_then(|p| p.length(&context)).unwrap_or(thickness)
+        },
+        _ => thickness,
+    };
+    let size: Size<i32, Logical> =
+        if is_horizontal { (lengthwise, thickness) } else { (thickness, lengthwise) }.into();
+    let e = IcedElement::new(applet, size, loop_handle, theme, panel_id, true);
+
+    // text may need more room than an icon
+    let min = e.minimum_size();
+    e.resize(if is_horizontal {
+        (size.w.max(min.w), size.h).into()
+    } else {
+        (size.w, size.h.max(min.h)).into()
+    });
+    e
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// refresh the state of the clock, the CPU meter and plugins
+    Tick,
+    Launch,
+    #[cfg(feature = "plugin-api")]
+    Plugin(PluginMessage),
+}
+
+#[derive(Debug, Clone)]
+pub struct Launcher {
+    pub icon: String,
+    pub exec: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct BuiltinApplet {
+    /// the configured name, including the prefix
+    pub name: String,
+    pub kind: BuiltinKind,
+    pub section: Side,
+    icon_size: u16,
+    padding: u16,
+    is_horizontal: bool,
+    launcher: Option<Launcher>,
+    #[cfg(feature = "plugin-api")]
+    plugin: Option<Box<dyn PanelPlugin>>,
+    /// incremented whenever a tick changes the view
+    revision: u64,
+    /// local hour and minute
+    time: (i32, i32),
+    /// total and idle jiffies of the last sample
+    cpu_sample: (u64, u64),
+    /// percentage of the time between the last samples which wasn't idle
+    cpu_usage: u64,
+}
+
+impl BuiltinApplet {
+    /// Changes whenever [`Message::Tick`] changes the view.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Whether the applet was created as `name` in `section` of a panel with
+    /// `config`.
+    pub fn is_configured(&self, name: &str, section: Side, config: &CosmicPanelConfig) -> bool {
+        self.name == name
+            && self.section == section
+            && self.icon_size == config.get_applet_icon_size(true) as u16
+            && self.padding == config.get_applet_padding(true)
+            && self.is_horizontal == config.is_horizontal()
+    }
+
+    fn tick(&mut self) {
+        let changed = match self.kind {
+            BuiltinKind::Clock => {
+                let prev = self.time;
+                self.time = local_time().unwrap_or_default();
+                self.time != prev
+            },
+            BuiltinKind::CpuMeter => {
+                let Some((total, idle)) = cpu_sample() else {
+                    return;
+                };
+                let prev = self.cpu_usage;
+                let (prev_total, prev_idle) = self.cpu_sample;
+                let d_total = total.saturating_sub(prev_total);
+                let d_idle = idle.saturating_sub(prev_idle);
+                if d_total > 0 {
+                    self.cpu_usage = 100 * d_total.saturating_sub(d_idle) / d_total;
+                }
+                self.cpu_sample = (total, idle);
+                self.cpu_usage != prev
+            },
+            #[cfg(feature = "plugin-api")]
+            BuiltinKind::Plugin(_) => self.plugin.as_mut().is_some_and(|p| p.tick()),
+            _ => false,
+        };
+        if changed {
+            self.revision = self.revision.wrapping_add(1);
+        }
+    }
+
+    /// Start the desktop entry of a launcher with the process manager, like
+    /// a hook it isn't restarted when it exits, and keeps running when the
+    /// panel is removed.
+    fn launch(&self, loop_handle: &LoopHandle<'static, GlobalState>) {
+        let Some(exec) = self.launcher.as_ref().and_then(|l| l.exec.as_deref()) else {
+            return;
+        };
+        let Some((program, args)) = launch_command(exec) else {
+            error!("Failed to parse the exec key of {}", self.name);
+            return;
+        };
+        let process = Process::new().with_executable(program).with_args(args).with_on_exit(
+            |mut pman, key, _err_code, _is_restarting| async move {
+                _ = pman.stop_process(key).await;
+            },
+        );
+        let name = self.name.clone();
+        loop_handle.insert_idle(move |state| {
+            if let Err(err) = state.space.applet_tx.try_send(AppletMsg::Launch(process)) {
+                error!("Failed to launch {}: {}", name, err);
+            }
+        });
+    }
+}
+
+impl Program for BuiltinApplet {
+    type Message = Message;
+
+    fn update(
+        &mut self,
+        message: Message,
+        loop_handle: &LoopHandle<'static, GlobalState>,
+    ) -> cosmic::Task<Message> {
+        match message {
+            Message::Tick => self.tick(),
+            Message::Launch => self.launch(loop_handle),
+            #[cfg(feature = "plugin-api")]
+            Message::Plugin(message) => {
+                if let Some(plugin) = self.plugin.as_mut() {
+                    plugin.update(message);
+                }
+            },
+        }
+        cosmic::Task::none()
+    }
+
+    fn view(&self) -> Element<'_, Message> {
+        let content: Element<'_, Message> = match &self.kind {
+            BuiltinKind::Clock => {
+                let (h, m) = self.time;
+                if self.is_horizontal {
+                    text::body(format!("{h:02}:{m:02}")).into()
+                } else {
+                    column::with_capacity(2)
+                        .push(text::body(format!("{h:02}")))
+                        .push(text::body(format!("{m:02}")))
+                        .align_x(Alignment::Center)
+                        .into()
+                }
+            },
+            BuiltinKind::CpuMeter => text::body(format!("{}%", self.cpu_usage)).into(),
+            BuiltinKind::Separator => {
+                if self.is_horizontal {
+                    divider::vertical::default().into()
+                } else {
+                    divider::horizontal::default().into()
+                }
+            },
+            BuiltinKind::Launcher(id) => {
+                let name = self.launcher.as_ref().map_or(id, |l| &l.icon).clone();
+                return button::custom(icon::from_name(name).size(self.icon_size).icon())
+                    .padding(self.padding)
+                    .class(Button::AppletIcon)
+                    .on_press(Message::Launch)
+                    .into();
+            },
+            #[cfg(feature = "plugin-api")]
+            BuiltinKind::Plugin(id) => match self.plugin.as_ref() {
+                Some(plugin) => return plugin.view().map(Message::Plugin),
+                None => text::body(id.clone()).into(),
+            },
+        };
+        cosmic::widget::container(content).padding([0, self.padding]).center(Length::Fill).into()
+    }
+}
+
+/// The icon and exec key of the desktop entry `id`.
+fn launcher_entry(id: &str) -> Option<Launcher> {
+    let locales = freedesktop_desktop_entry::get_languages_from_env();
+    let path = Iter::new(freedesktop_desktop_entry::default_paths())
+        .find(|p| p.file_stem() == Some(OsStr::new(id)))?;
+    let bytes = fs::read_to_string(&path).ok()?;
+    let entry = DesktopEntry::from_str(&path, &bytes, &locales).ok()?;
+    Some(Launcher {
+        icon: entry.icon().unwrap_or(id).to_string(),
+        exec: entry.exec().map(str::to_string),
+    })
+}
+
+/// The program and arguments of the exec key of a desktop entry. Field codes
+/// are not supported, and are dropped.
+fn launch_command(exec: &str) -> Option<(String, Vec<String>)> {
+    let mut args = shlex::split(exec)?.into_iter().filter(|a| !a.starts_with('%'));
+    let program = args.next()?;
+    Some((program, args.collect()))
+}
+
+/// Total and idle jiffies of all CPUs.
+fn cpu_sample() -> Option<(u64, u64)> {
+    parse_cpu_sample(&fs::read_to_string("/proc/stat").ok()?)
+}
+
+/// Total and idle jiffies of all CPUs in the contents of `/proc/stat`.
+fn parse_cpu_sample(stat: &str) -> Option<(u64, u64)> {
+    let values: Vec<u64> = stat
+        .lines()
+        .next()?
+        .strip_prefix("cpu ")?
+        .split_whitespace()
+        .filter_map(|v| v.parse().ok())
+        .collect();
+    // idle and iowait
+    let idle = values.get(3)? + values.get(4).copied().unwrap_or_default();
+    Some((values.iter().sum(), idle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_applets_are_parsed_from_their_name() {
+        assert_eq!(BuiltinKind::parse("builtin:clock"), Some(BuiltinKind::Clock));
+        assert_eq!(BuiltinKind::parse("builtin:separator"), Some(BuiltinKind::Separator));
+        assert_eq!(BuiltinKind::parse("builtin:cpu"), Some(BuiltinKind::CpuMeter));
+        assert_eq!(
+            BuiltinKind::parse("builtin:launcher:org.gnome.Nautilus"),
+            Some(BuiltinKind::Launcher("org.gnome.Nautilus".to_string()))
+        );
+    }
+
+    #[test]
+    fn other_names_are_not_builtin_applets() {
+        assert_eq!(BuiltinKind::parse("com.system76.CosmicAppletTime"), None);
+        assert_eq!(BuiltinKind::parse("builtin:"), None);
+        assert_eq!(BuiltinKind::parse("builtin:weather"), None);
+        assert_eq!(BuiltinKind::parse("builtin:launcher:"), None);
+        assert_eq!(BuiltinKind::parse("clock"), None);
+    }
+
+    #[test]
+    fn cpu_samples_are_parsed_from_proc_stat() {
+        let stat = "cpu  100 20 30 400 50 6 7 8 0 0\ncpu0 50 10 15 200 25 3 3 4 0 0\n";
+        // idle and iowait are idle
+        assert_eq!(parse_cpu_sample(stat), Some((621, 450)));
+        assert_eq!(parse_cpu_sample("cpu  1 2 3 4\n"), Some((10, 4)));
+    }
+
+    #[test]
+    fn invalid_cpu_samples_are_rejected() {
+        assert_eq!(parse_cpu_sample(""), None);
+        assert_eq!(parse_cpu_sample("cpu0 1 2 3 4 5\n"), None);
+        assert_eq!(parse_cpu_sample("cpu  1 2 3\n"), None);
+    }
+
+    #[test]
+    fn field_codes_are_dropped_from_the_exec_key() {
+        assert_eq!(
+            launch_command("nautilus --new-window %U"),
+            Some(("nautilus".to_string(), vec!["--new-window".to_string()]))
+        );
+        assert_eq!(
+            launch_command("\"my app\" -x"),
+            Some(("my app".to_string(), vec!["-x".to_string()]))
+        );
+        assert_eq!(launch_command("%U"), None);
+        assert_eq!(launch_command("\"unterminated"), None);
+    }
+}
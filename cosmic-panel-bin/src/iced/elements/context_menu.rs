@@ -0,0 +1,113 @@
+// Element for rendering the panel context menu of an applet slot
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+
+use calloop::LoopHandle;
+use cosmic::{
+    iced::{Alignment, Color, Length},
+    theme,
+    widget::{column, container, text},
+    Theme,
+};
+
+use crate::{
+    iced::{Element, IcedElement, Program},
+    xdg_shell_wrapper::shared_state::GlobalState,
+};
+
+pub const MENU_WIDTH: i32 = 240;
+pub const ROW_HEIGHT: i32 = 32;
+
+pub type ContextMenuElement = IcedElement<ContextMenu>;
+
+pub fn context_menu_element(
+    title: String,
+    entries: Vec<String>,
+    about: Vec<String>,
+    hovered: Arc<AtomicUsize>,
+    show_about: Arc<AtomicBool>,
+    loop_handle: LoopHandle<'static, GlobalState>,
+    theme: Theme,
+    panel_id: usize,
+) -> ContextMenuElement {
+    let rows = 1 + entries.len().max(about.len()) as i32;
+    IcedElement::new(
+        ContextMenu { title, entries, about, hovered, show_about },
+        (MENU_WIDTH, rows * ROW_HEIGHT),
+        loop_handle,
+        theme,
+        panel_id,
+        false,
+    )
+}
+
+pub struct ContextMenu {
+    pub title: String,
+    pub entries: Vec<String>,
+    /// lines shown instead of the entries after "About applet" was chosen
+    pub about: Vec<String>,
+    /// index of the hovered entry, or `usize::MAX`
+    pub hovered: Arc<AtomicUsize>,
+    pub show_about: Arc<AtomicBool>,
+}
+
+impl Program for ContextMenu {
+    type Message = ();
+
+    fn view(&self) -> Element<'_, ()> {
+        let hovered = self.hovered.load(Ordering::Relaxed);
+        let show_about = self.show_about.load(Ordering::Relaxed);
+        let row = |label: String, highlighted: bool| {
+            container(text::body(label))
+                .width(Length::Fill)
+                .height(Length::Fixed(ROW_HEIGHT as f32))
+                .padding([0, 12])
+                .align_y(Alignment::Center)
+                .class(theme::Container::custom(move |theme| {
+                    let cosmic = theme.cosmic();
+                    container::Style {
+                        text_color: Some(cosmic.background.on.into()),
+                        background: highlighted
+                            .then(|| Color::from(cosmic.background.component.hover).into()),
+                        ..Default::default()
+                    }
+                }))
+        };
+
+        let mut rows = column::with_capacity(1 + self.entries.len()).push(
+            container(text::heading(self.title.clone()))
+                .width(Length::Fill)
+                .height(Length::Fixed(ROW_HEIGHT as f32))
+                .padding([0, 12])
+                .align_y(Alignment::Center),
+        );
+        if show_about {
+            for line in &self.about {
+                rows = rows.push(row(line.clone(), false));
+            }
+        } else {
+            for (i, entry) in self.entries.iter().enumerate() {
+                rows = rows.push(row(entry.clone(), i == hovered));
+            }
+        }
+
+        Element::from(container(rows).width(Length::Fill).height(Length::Fill).class(
+            theme::Container::custom(|theme| {
+                let cosmic = theme.cosmic();
+                container::Style {
+                    text_color: Some(cosmic.background.on.into()),
+                    background: Some(Color::from(cosmic.background.base).into()),
+                    border: cosmic::iced::Border {
+                        radius: cosmic.corner_radii.radius_s.into(),
+                        width: 1.0,
+                        color: cosmic.background.divider.into(),
+                    },
+                    ..Default::default()
+                }
+            }),
+        ))
+    }
+}
@@ -0,0 +1,82 @@
+// Element for the labeled applet placeholders and the trash target of edit mode
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use calloop::LoopHandle;
+use cosmic::{
+    iced::{Alignment, Border, Length},
+    theme,
+    widget::{icon, row, text},
+    Theme,
+};
+use smithay::utils::{Logical, Size};
+
+use crate::{
+    iced::{Element, IcedElement, Program},
+    xdg_shell_wrapper::shared_state::GlobalState,
+};
+
+pub type EditSlotElement = IcedElement<EditSlot>;
+
+pub fn edit_slot_element(
+    label: String,
+    trash: bool,
+    size: Size<i32, Logical>,
+    highlighted: Arc<AtomicBool>,
+    loop_handle: LoopHandle<'static, GlobalState>,
+    theme: Theme,
+    panel_id: usize,
+) -> EditSlotElement {
+    IcedElement::new(
+        EditSlot { label, trash, highlighted },
+        size,
+        loop_handle,
+        theme,
+        panel_id,
+        false,
+    )
+}
+
+pub struct EditSlot {
+    pub label: String,
+    /// the slot removes applets dropped onto it
+    pub trash: bool,
+    /// an applet is dragged over the slot
+    pub highlighted: Arc<AtomicBool>,
+}
+
+impl Program for EditSlot {
+    type Message = ();
+
+    fn view(&self) -> Element<'_, ()> {
+        let highlighted = self.highlighted.load(Ordering::Relaxed);
+        let trash = self.trash;
+        let content = row::with_capacity(2)
+            .push_maybe(trash.then(|| icon::from_name("user-trash-symbolic").size(16).icon()))
+            .push(text::caption(self.label.clone()))
+            .spacing(4)
+            .align_y(Alignment::Center);
+        Element::from(cosmic::widget::container(content).center(Length::Fill).padding(2).class(
+            theme::Container::custom(move |theme| {
+                let cosmic = theme.cosmic();
+                let accent = if trash { cosmic.destructive_color() } else { cosmic.accent_color() };
+                let mut background = accent;
+                background.alpha = if highlighted { 0.5 } else { 0.15 };
+                cosmic::widget::container::Style {
+                    text_color: Some(cosmic.background.on.into()),
+                    icon_color: Some(cosmic.background.on.into()),
+                    background: Some(cosmic::iced::Color::from(background).into()),
+                    border: Border {
+                        color: accent.into(),
+                        width: 1.0,
+                        radius: cosmic.corner_radii.radius_s.into(),
+                    },
+                    ..Default::default()
+                }
+            }),
+        ))
+    }
+}
@@ -0,0 +1,68 @@
+// Element for rendering the frame statistics debug HUD
+
+use calloop::LoopHandle;
+use cosmic::{
+    iced::{Color, Length},
+    theme,
+    widget::text,
+    Theme,
+};
+
+use crate::{
+    frame_stats::FrameStats,
+    iced::{Element, IcedElement, Program},
+    xdg_shell_wrapper::shared_state::GlobalState,
+};
+
+pub type FrameStatsHudElement = IcedElement<FrameStatsHud>;
+
+pub fn frame_stats_hud_element(
+    stats: FrameStats,
+    logical_width: i32,
+    logical_height: i32,
+    loop_handle: LoopHandle<'static, GlobalState>,
+    theme: Theme,
+    panel_id: usize,
+) -> FrameStatsHudElement {
+    IcedElement::new(
+        FrameStatsHud { stats },
+        (logical_width, logical_height),
+        loop_handle,
+        theme,
+        panel_id,
+        false,
+    )
+}
+
+pub struct FrameStatsHud {
+    pub stats: FrameStats,
+}
+
+impl Program for FrameStatsHud {
+    type Message = ();
+
+    fn view(&self) -> Element<'_, ()> {
+        let s = self.stats.summary();
+        let get = |k: &str| s.get(k).copied().unwrap_or_default();
+        let label = format!(
+            "render {}/{}us swap {}/{}us\ndispatch {}/{}us damage {}px",
+            get("avg_render_us"),
+            get("max_render_us"),
+            get("avg_swap_us"),
+            get("max_swap_us"),
+            get("avg_dispatch_us"),
+            get("max_dispatch_us"),
+            get("avg_damage_px"),
+        );
+        Element::from(
+            cosmic::widget::container(text::caption(label))
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .class(theme::Container::custom(|_| cosmic::widget::container::Style {
+                    text_color: Some(Color::WHITE),
+                    background: Some(Color::from_rgba(0., 0., 0., 0.7).into()),
+                    ..Default::default()
+                })),
+        )
+    }
+}
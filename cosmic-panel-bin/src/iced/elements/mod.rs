@@ -1,4 +1,9 @@
 pub mod background;
+pub mod builtin_applet;
+pub mod context_menu;
+pub mod edit_slot;
+pub mod frame_stats_hud;
+pub mod osd;
 pub mod overflow_button;
 pub mod overflow_popup;
 pub mod target;
@@ -6,6 +11,7 @@ pub mod target;
 use std::borrow::Cow;
 
 use background::BackgroundElement;
+use builtin_applet::BuiltinAppletElement;
 use overflow_button::OverflowButtonElement;
 use overflow_popup::OverflowPopupElement;
 use smithay::{
@@ -19,7 +25,8 @@ space_elements! {
     pub CosmicMappedInternal;
     OverflowButton=OverflowButtonElement,
     Window=Window,
-    Background=BackgroundElement
+    Background=BackgroundElement,
+    Builtin=BuiltinAppletElement
 }
 
 impl CosmicMappedInternal {
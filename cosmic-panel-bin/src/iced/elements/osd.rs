@@ -0,0 +1,100 @@
+// Element for rendering on-screen displays, like the volume or brightness
+// level, and notification toasts
+
+use calloop::LoopHandle;
+use cosmic::{
+    iced::{widget::progress_bar, Alignment, Color, Length},
+    theme,
+    widget::{column, container, icon, row, text},
+    Theme,
+};
+
+use crate::{
+    iced::{Element, IcedElement, Program},
+    xdg_shell_wrapper::shared_state::GlobalState,
+};
+
+pub const OSD_WIDTH: i32 = 280;
+pub const OSD_HEIGHT: i32 = 56;
+
+pub type OsdElement = IcedElement<Osd>;
+
+pub fn osd_element(
+    content: OsdContent,
+    loop_handle: LoopHandle<'static, GlobalState>,
+    theme: Theme,
+    panel_id: usize,
+) -> OsdElement {
+    IcedElement::new(Osd { content }, (OSD_WIDTH, OSD_HEIGHT), loop_handle, theme, panel_id, false)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OsdContent {
+    /// icon name, or empty
+    pub icon: String,
+    pub text: String,
+    /// level in percent shown as a bar, e.g. the volume
+    pub value: Option<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// replace the content of a shown OSD
+    Set(OsdContent),
+}
+
+pub struct Osd {
+    pub content: OsdContent,
+}
+
+impl Program for Osd {
+    type Message = Message;
+
+    fn update(
+        &mut self,
+        message: Message,
+        _loop_handle: &LoopHandle<'static, GlobalState>,
+    ) -> cosmic::Task<Message> {
+        match message {
+            Message::Set(content) => self.content = content,
+        }
+        cosmic::Task::none()
+    }
+
+    fn view(&self) -> Element<'_, Message> {
+        let mut details = column::with_capacity(2).spacing(4).width(Length::Fill);
+        if !self.content.text.is_empty() {
+            details = details.push(text::body(self.content.text.clone()));
+        }
+        if let Some(value) = self.content.value {
+            details = details
+                .push(progress_bar(0.0..=100.0, value.min(100) as f32).height(Length::Fixed(4.)));
+        }
+        let mut content = row::with_capacity(2).spacing(12).align_y(Alignment::Center);
+        if !self.content.icon.is_empty() {
+            content = content.push(icon::from_name(self.content.icon.clone()).size(24).icon());
+        }
+        content = content.push(details);
+
+        Element::from(
+            container(content)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .padding([0, 16])
+                .align_y(Alignment::Center)
+                .class(theme::Container::custom(|theme| {
+                    let cosmic = theme.cosmic();
+                    container::Style {
+                        text_color: Some(cosmic.background.on.into()),
+                        background: Some(Color::from(cosmic.background.base).into()),
+                        border: cosmic::iced::Border {
+                            radius: cosmic.corner_radii.radius_m.into(),
+                            width: 1.0,
+                            color: cosmic.background.divider.into(),
+                        },
+                        ..Default::default()
+                    }
+                })),
+        )
+    }
+}
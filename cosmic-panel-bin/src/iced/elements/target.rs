@@ -1,4 +1,7 @@
-use super::{overflow_button::OverflowButtonElement, CosmicMappedInternal};
+use super::{
+    builtin_applet::BuiltinAppletElement, overflow_button::OverflowButtonElement,
+    CosmicMappedInternal,
+};
 use crate::xdg_shell_wrapper::shared_state::GlobalState;
 
 use anyhow::bail;
@@ -6,13 +9,14 @@ use smithay::{
     input::{keyboard::KeyboardTarget, pointer::PointerTarget},
     reexports::wayland_server::protocol::wl_surface::WlSurface,
     utils::IsAlive,
-    wayland::seat::WaylandFocus,
+    wayland::{compositor::get_parent, seat::WaylandFocus},
 };
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum SpaceTarget {
     Surface(WlSurface),
     OverflowButton(OverflowButtonElement),
+    Builtin(BuiltinAppletElement),
 }
 
 impl TryFrom<CosmicMappedInternal> for SpaceTarget {
@@ -24,12 +28,25 @@ impl TryFrom<CosmicMappedInternal> for SpaceTarget {
                 Ok(SpaceTarget::Surface(w.toplevel().unwrap().wl_surface().clone()))
             },
             CosmicMappedInternal::OverflowButton(b) => Ok(SpaceTarget::OverflowButton(b)),
+            CosmicMappedInternal::Builtin(b) => Ok(SpaceTarget::Builtin(b)),
             CosmicMappedInternal::_GenericCatcher(_) => bail!("Cannot convert generic catcher"),
             CosmicMappedInternal::Background(_) => bail!("Cannot convert background"),
         }
     }
 }
 
+impl SpaceTarget {
+    /// The surface of the applet the target belongs to, which is the parent
+    /// of a hovered subsurface.
+    pub fn root_surface(&self) -> Option<WlSurface> {
+        let mut surface = self.wl_surface()?.into_owned();
+        while let Some(parent) = get_parent(&surface) {
+            surface = parent;
+        }
+        Some(surface)
+    }
+}
+
 impl From<WlSurface> for SpaceTarget {
     fn from(surface: WlSurface) -> Self {
         SpaceTarget::Surface(surface)
@@ -47,6 +64,7 @@ impl IsAlive for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => s.alive(),
             SpaceTarget::OverflowButton(b) => b.alive(),
+            SpaceTarget::Builtin(b) => b.alive(),
         }
     }
 }
@@ -61,6 +79,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::enter(s, seat, data, event),
             SpaceTarget::OverflowButton(b) => PointerTarget::enter(b, seat, data, event),
+            SpaceTarget::Builtin(b) => PointerTarget::enter(b, seat, data, event),
         }
     }
 
@@ -73,6 +92,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::motion(s, seat, data, event),
             SpaceTarget::OverflowButton(b) => b.motion(seat, data, event),
+            SpaceTarget::Builtin(b) => b.motion(seat, data, event),
         }
     }
 
@@ -85,6 +105,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::relative_motion(s, seat, data, event),
             SpaceTarget::OverflowButton(b) => b.relative_motion(seat, data, event),
+            SpaceTarget::Builtin(b) => b.relative_motion(seat, data, event),
         }
     }
 
@@ -97,6 +118,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::button(s, seat, data, event),
             SpaceTarget::OverflowButton(b) => b.button(seat, data, event),
+            SpaceTarget::Builtin(b) => b.button(seat, data, event),
         }
     }
 
@@ -109,6 +131,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::axis(s, seat, data, frame),
             SpaceTarget::OverflowButton(b) => b.axis(seat, data, frame),
+            SpaceTarget::Builtin(b) => b.axis(seat, data, frame),
         }
     }
 
@@ -116,6 +139,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::frame(s, seat, data),
             SpaceTarget::OverflowButton(b) => b.frame(seat, data),
+            SpaceTarget::Builtin(b) => b.frame(seat, data),
         }
     }
 
@@ -128,6 +152,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::gesture_swipe_begin(s, seat, data, event),
             SpaceTarget::OverflowButton(b) => b.gesture_swipe_begin(seat, data, event),
+            SpaceTarget::Builtin(b) => b.gesture_swipe_begin(seat, data, event),
         }
     }
 
@@ -140,6 +165,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::gesture_swipe_update(s, seat, data, event),
             SpaceTarget::OverflowButton(b) => b.gesture_swipe_update(seat, data, event),
+            SpaceTarget::Builtin(b) => b.gesture_swipe_update(seat, data, event),
         }
     }
 
@@ -152,6 +178,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::gesture_swipe_end(s, seat, data, event),
             SpaceTarget::OverflowButton(b) => b.gesture_swipe_end(seat, data, event),
+            SpaceTarget::Builtin(b) => b.gesture_swipe_end(seat, data, event),
         }
     }
 
@@ -164,6 +191,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::gesture_pinch_begin(s, seat, data, event),
             SpaceTarget::OverflowButton(b) => b.gesture_pinch_begin(seat, data, event),
+            SpaceTarget::Builtin(b) => b.gesture_pinch_begin(seat, data, event),
         }
     }
 
@@ -176,6 +204,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::gesture_pinch_update(s, seat, data, event),
             SpaceTarget::OverflowButton(b) => b.gesture_pinch_update(seat, data, event),
+            SpaceTarget::Builtin(b) => b.gesture_pinch_update(seat, data, event),
         }
     }
 
@@ -188,6 +217,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::gesture_pinch_end(s, seat, data, event),
             SpaceTarget::OverflowButton(b) => b.gesture_pinch_end(seat, data, event),
+            SpaceTarget::Builtin(b) => b.gesture_pinch_end(seat, data, event),
         }
     }
 
@@ -200,6 +230,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::gesture_hold_begin(s, seat, data, event),
             SpaceTarget::OverflowButton(b) => b.gesture_hold_begin(seat, data, event),
+            SpaceTarget::Builtin(b) => b.gesture_hold_begin(seat, data, event),
         }
     }
 
@@ -212,6 +243,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::gesture_hold_end(s, seat, data, event),
             SpaceTarget::OverflowButton(b) => b.gesture_hold_end(seat, data, event),
+            SpaceTarget::Builtin(b) => b.gesture_hold_end(seat, data, event),
         }
     }
 
@@ -225,6 +257,7 @@ impl PointerTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => PointerTarget::leave(s, seat, data, serial, time),
             SpaceTarget::OverflowButton(b) => PointerTarget::leave(b, seat, data, serial, time),
+            SpaceTarget::Builtin(b) => PointerTarget::leave(b, seat, data, serial, time),
         }
     }
 }
@@ -240,6 +273,7 @@ impl KeyboardTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => KeyboardTarget::enter(s, seat, data, keys, serial),
             SpaceTarget::OverflowButton(b) => KeyboardTarget::enter(b, seat, data, keys, serial),
+            SpaceTarget::Builtin(b) => KeyboardTarget::enter(b, seat, data, keys, serial),
         }
     }
 
@@ -252,6 +286,7 @@ impl KeyboardTarget<GlobalState> for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => KeyboardTarget::leave(s, seat, data, serial),
             SpaceTarget::OverflowButton(b) => KeyboardTarget::leave(b, seat, data, serial),
+            SpaceTarget::Builtin(b) => KeyboardTarget::leave(b, seat, data, serial),
         }
     }
 
@@ -269,6 +304,7 @@ impl KeyboardTarget<GlobalState> for SpaceTarget {
             SpaceTarget::OverflowButton(b) => {
                 KeyboardTarget::key(b, seat, data, key, state, serial, time)
             },
+            SpaceTarget::Builtin(b) => KeyboardTarget::key(b, seat, data, key, state, serial, time),
         }
     }
 
@@ -284,6 +320,7 @@ impl KeyboardTarget<GlobalState> for SpaceTarget {
             SpaceTarget::OverflowButton(b) => {
                 KeyboardTarget::modifiers(b, seat, data, modifiers, serial)
             },
+            SpaceTarget::Builtin(b) => KeyboardTarget::modifiers(b, seat, data, modifiers, serial),
         }
     }
 }
@@ -297,6 +334,7 @@ impl WaylandFocus for SpaceTarget {
         match self {
             SpaceTarget::Surface(s) => Some(std::borrow::Cow::Borrowed(s)),
             SpaceTarget::OverflowButton(b) => b.wl_surface(),
+            SpaceTarget::Builtin(b) => b.wl_surface(),
         }
     }
 }
@@ -8,6 +8,7 @@ use std::{
 };
 
 use crate::xdg_shell_wrapper::shared_state::GlobalState;
+use buffer_pool::BufferOwner;
 use cosmic::{
     iced::{
         advanced::widget::Tree,
@@ -36,7 +37,6 @@ use once_cell::sync::Lazy;
 use ordered_float::OrderedFloat;
 use smithay::{
     backend::{
-        allocator::Fourcc,
         input::{ButtonState, KeyState},
         renderer::{
             element::{
@@ -66,11 +66,16 @@ use smithay::{
     wayland::seat::WaylandFocus,
 };
 
+pub mod buffer_pool;
 pub mod elements;
 pub mod panel_message;
 
 static ID: Lazy<Id> = Lazy::new(|| Id::new("Program"));
 
+/// How long the buffer of a scale is kept after the last output with that
+/// scale left, so an output coming back reuses it.
+const BUFFER_GRACE: Duration = Duration::from_secs(2);
+
 pub type Element<'a, Message> = cosmic::iced::Element<'a, Message, cosmic::Theme, cosmic::Renderer>;
 
 pub struct IcedElement<P: Program + Send + 'static>(Arc<Mutex<IcedElementInternal<P>>>);
@@ -148,6 +153,13 @@ struct IcedElementInternal<P: Program + Send + 'static> {
     // draw buffer
     outputs: HashSet<Output>,
     buffers: HashMap<OrderedFloat<f64>, (MemoryRenderBuffer, Color)>,
+    // scales of buffers no output uses anymore, with the time the last one
+    // left
+    unused_since: HashMap<OrderedFloat<f64>, Instant>,
+    // buffers were dropped to stay within the texture budget, and are
+    // allocated again on the next render
+    evicted: bool,
+    last_render: Instant,
     pending_update: Option<Instant>,
     request_redraws: bool,
 
@@ -196,7 +208,18 @@ impl<P: Program + Send + Clone + 'static> Clone for IcedElementInternal<P> {
 
         IcedElementInternal {
             outputs: self.outputs.clone(),
-            buffers: self.buffers.clone(),
+            // the clone must not draw into the buffers of the original
+            buffers: self
+                .buffers
+                .keys()
+                .map(|scale| {
+                    let buffer = buffer_pool::acquire(buffer_size(self.size, **scale));
+                    (*scale, (buffer, cosmic::iced::Color::TRANSPARENT))
+                })
+                .collect(),
+            unused_since: self.unused_since.clone(),
+            evicted: self.evicted,
+            last_render: self.last_render,
             pending_update: self.pending_update,
             size: self.size,
             cursor_pos: self.cursor_pos,
@@ -236,6 +259,9 @@ impl<P: Program + Send + 'static> fmt::Debug for IcedElementInternal<P> {
 impl<P: Program + Send + 'static> Drop for IcedElementInternal<P> {
     fn drop(&mut self) {
         self.handle.remove(self.executor_token.take().unwrap());
+        for (scale, (buffer, _)) in self.buffers.drain() {
+            buffer_pool::release(buffer_size(self.size, *scale), buffer);
+        }
     }
 }
 
@@ -271,6 +297,9 @@ impl<P: Program + Send + 'static> IcedElement<P> {
         let mut internal = IcedElementInternal {
             outputs: HashSet::new(),
             buffers: HashMap::new(),
+            unused_since: HashMap::new(),
+            evicted: false,
+            last_render: Instant::now(),
             pending_update: None,
             size,
             cursor_pos: None,
@@ -287,7 +316,14 @@ impl<P: Program + Send + 'static> IcedElement<P> {
         };
         let _ = internal.update(true);
 
-        IcedElement(Arc::new(Mutex::new(internal)))
+        IcedElement::from_internal(internal)
+    }
+
+    fn from_internal(internal: IcedElementInternal<P>) -> Self {
+        let internal = Arc::new(Mutex::new(internal));
+        let owner: Arc<Mutex<dyn BufferOwner>> = internal.clone();
+        buffer_pool::register(Arc::downgrade(&owner));
+        IcedElement(internal)
     }
 
     pub fn with_program<R>(&self, func: impl FnOnce(&P) -> R) -> R {
@@ -323,12 +359,10 @@ impl<P: Program + Send + 'static> IcedElement<P> {
             return;
         }
 
-        internal_ref.size = size;
+        let old_size = std::mem::replace(&mut internal_ref.size, size);
         for (scale, (buffer, ..)) in internal_ref.buffers.iter_mut() {
-            let buffer_size =
-                internal_ref.size.to_f64().to_buffer(**scale, Transform::Normal).to_i32_round();
-            *buffer =
-                MemoryRenderBuffer::new(Fourcc::Argb8888, buffer_size, 1, Transform::Normal, None);
+            let old = std::mem::replace(buffer, buffer_pool::acquire(buffer_size(size, **scale)));
+            buffer_pool::release(buffer_size(old_size, **scale), old);
         }
 
         if internal_ref.pending_update.is_none() {
@@ -345,6 +379,13 @@ impl<P: Program + Send + 'static> IcedElement<P> {
         guard.theme = theme.clone();
     }
 
+    /// Queue a message for the program, as if it was produced by a widget.
+    pub fn queue_message(&self, message: P::Message) {
+        let mut internal = self.0.lock().unwrap();
+        internal.state.queue_message(message);
+        let _ = internal.update(true);
+    }
+
     pub fn force_redraw(&self) {
         let mut internal = self.0.lock().unwrap();
 
@@ -358,7 +399,32 @@ impl<P: Program + Send + 'static + Clone> IcedElement<P> {
         if !internal.state.is_queue_empty() {
             self.force_update();
         }
-        IcedElement(Arc::new(Mutex::new(internal.clone())))
+        IcedElement::from_internal(internal.clone())
+    }
+}
+
+fn buffer_size(size: Size<i32, Logical>, scale: f64) -> Size<i32, BufferCoords> {
+    size.to_f64().to_buffer(scale, Transform::Normal).to_i32_round()
+}
+
+impl<P: Program + Send + 'static> BufferOwner for IcedElementInternal<P> {
+    fn buffer_bytes(&self) -> usize {
+        self.buffers
+            .keys()
+            .map(|scale| buffer_pool::byte_size(buffer_size(self.size, **scale)))
+            .sum()
+    }
+
+    fn last_render(&self) -> Instant {
+        self.last_render
+    }
+
+    fn evict_buffers(&mut self) -> usize {
+        let freed = self.buffer_bytes();
+        self.buffers.clear();
+        self.unused_since.clear();
+        self.evicted = true;
+        freed
     }
 }
 
@@ -656,22 +722,13 @@ impl<P: Program + Send + 'static> SpaceElement for IcedElement<P> {
     fn output_enter(&self, output: &Output, _overlap: Rectangle<i32, Logical>) {
         let mut internal = self.0.lock().unwrap();
         let scale = output.current_scale().fractional_scale();
+        // a buffer kept from an output which left is reused with its contents
+        internal.unused_since.remove(&OrderedFloat(scale));
         if !internal.buffers.contains_key(&OrderedFloat(scale)) {
-            let buffer_size =
-                internal.size.to_f64().to_buffer(scale, Transform::Normal).to_i32_round();
-            internal.buffers.insert(
-                OrderedFloat(scale),
-                (
-                    MemoryRenderBuffer::new(
-                        Fourcc::Argb8888,
-                        buffer_size,
-                        1,
-                        Transform::Normal,
-                        None,
-                    ),
-                    cosmic::iced::Color::TRANSPARENT,
-                ),
-            );
+            let buffer = buffer_pool::acquire(buffer_size(internal.size, scale));
+            internal
+                .buffers
+                .insert(OrderedFloat(scale), (buffer, cosmic::iced::Color::TRANSPARENT));
         }
         internal.outputs.insert(output.clone());
     }
@@ -690,34 +747,38 @@ impl<P: Program + Send + 'static> SpaceElement for IcedElement<P> {
         let mut internal = self.0.lock().unwrap();
         // makes partial borrows easier
         let internal_ref = &mut *internal;
-        internal_ref.buffers.retain(|scale, _| {
-            internal_ref.outputs.iter().any(|o| o.current_scale().fractional_scale() == **scale)
-        });
-        let mut changed = false;
-        for scale in internal_ref
+        let in_use = internal_ref
             .outputs
             .iter()
             .map(|o| OrderedFloat(o.current_scale().fractional_scale()))
-            .filter(|scale| !internal_ref.buffers.contains_key(scale))
+            .collect::<HashSet<_>>();
+        internal_ref.unused_since.retain(|scale, _| !in_use.contains(scale));
+        let now = Instant::now();
+        for scale in internal_ref
+            .buffers
+            .keys()
+            .filter(|scale| !in_use.contains(scale))
+            .copied()
             .collect::<Vec<_>>()
+        {
+            let since = *internal_ref.unused_since.entry(scale).or_insert(now);
+            if now.duration_since(since) < BUFFER_GRACE {
+                continue;
+            }
+            internal_ref.unused_since.remove(&scale);
+            if let Some((buffer, _)) = internal_ref.buffers.remove(&scale) {
+                buffer_pool::release(buffer_size(internal_ref.size, *scale), buffer);
+            }
+        }
+        let mut changed = false;
+        for scale in in_use
             .into_iter()
+            .filter(|_| !internal_ref.evicted)
+            .filter(|scale| !internal_ref.buffers.contains_key(scale))
         {
             changed = true;
-            let buffer_size =
-                internal_ref.size.to_f64().to_buffer(*scale, Transform::Normal).to_i32_round();
-            internal_ref.buffers.insert(
-                scale,
-                (
-                    MemoryRenderBuffer::new(
-                        Fourcc::Argb8888,
-                        buffer_size,
-                        1,
-                        Transform::Normal,
-                        None,
-                    ),
-                    cosmic::iced::Color::TRANSPARENT,
-                ),
-            );
+            let buffer = buffer_pool::acquire(buffer_size(internal_ref.size, *scale));
+            internal_ref.buffers.insert(scale, (buffer, cosmic::iced::Color::TRANSPARENT));
         }
         internal.update(changed);
     }
@@ -753,9 +814,20 @@ where
             internal_ref.pending_update = None;
         }
         let _ = internal_ref.update(force);
+        internal_ref.last_render = Instant::now();
+        if std::mem::take(&mut internal_ref.evicted) {
+            for scale in internal_ref
+                .outputs
+                .iter()
+                .map(|o| OrderedFloat(o.current_scale().fractional_scale()))
+                .collect::<HashSet<_>>()
+            {
+                let buffer = buffer_pool::acquire(buffer_size(internal_ref.size, *scale));
+                internal_ref.buffers.insert(scale, (buffer, cosmic::iced::Color::TRANSPARENT));
+            }
+        }
         if let Some((buffer, _)) = internal_ref.buffers.get_mut(&OrderedFloat(scale.x)) {
-            let size: Size<i32, BufferCoords> =
-                internal_ref.size.to_f64().to_buffer(scale.x, Transform::Normal).to_i32_round();
+            let size = buffer_size(internal_ref.size, scale.x);
             if size.w > 0 && size.h > 0 {
                 let state_ref = &internal_ref.state;
                 let mut clip_mask = tiny_skia::Mask::new(size.w as u32, size.h as u32).unwrap();
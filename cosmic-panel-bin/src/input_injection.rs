@@ -0,0 +1,174 @@
+//! Synthetic input for automated tests, enabled with the `input-injection`
+//! feature.
+//!
+//! Pointer events go through the same path as the generated autohover clicks,
+//! so they are routed to applets, popups and overflow buttons exactly like
+//! events from the host compositor.
+
+use calloop::channel::Sender;
+use sctk::seat::{
+    keyboard::KeyCode,
+    pointer::{AxisScroll, PointerEvent, PointerEventKind},
+};
+use smithay::{backend::input::KeyState, input::keyboard::FilterResult, utils::SERIAL_COUNTER};
+use tracing::warn;
+use zbus::{fdo, interface};
+
+use crate::{xdg_shell_wrapper::shared_state::GlobalState, PanelCalloopMsg};
+
+#[derive(Debug, Clone, Copy)]
+pub enum InjectedInput {
+    /// move the pointer to a surface local position of the panel
+    PointerMotion { x: f64, y: f64 },
+    /// press or release a pointer button, using evdev button codes
+    PointerButton { x: f64, y: f64, button: u32, pressed: bool },
+    /// scroll vertically by `value` at a surface local position
+    PointerAxis { x: f64, y: f64, value: f64 },
+    /// press or release a key, using evdev key codes
+    Key { keycode: u32, pressed: bool },
+}
+
+/// Feed `input` to the panel named `panel` on `output`, or on its first output
+/// if `output` is empty.
+pub fn inject(state: &mut GlobalState, panel: &str, output: &str, input: InjectedInput) {
+    let Some(space) = state.space.space_list.iter().find(|s| {
+        s.config.name == panel
+            && (output.is_empty()
+                || s.output.as_ref().is_some_and(|o| o.2.name.as_deref() == Some(output)))
+    }) else {
+        warn!("Can't inject input, no panel named {} on {:?}", panel, output);
+        return;
+    };
+    let Some(surface) = space.panel_surface().cloned() else {
+        warn!("Can't inject input, panel {} is not mapped", panel);
+        return;
+    };
+    let Some(seat) = state.server_state.seats.first() else {
+        warn!("Can't inject input without a seat");
+        return;
+    };
+
+    match input {
+        InjectedInput::Key { keycode, pressed } => {
+            let Some(kbd) = seat.server.seat.get_keyboard() else {
+                return;
+            };
+            let time = state.start_time.elapsed().as_millis() as u32;
+            kbd.input::<(), _>(
+                state,
+                KeyCode::new(keycode.saturating_add(8)),
+                if pressed { KeyState::Pressed } else { KeyState::Released },
+                SERIAL_COUNTER.next_serial(),
+                time,
+                |_, _, _| FilterResult::Forward,
+            );
+        },
+        InjectedInput::PointerMotion { x, y }
+        | InjectedInput::PointerButton { x, y, .. }
+        | InjectedInput::PointerAxis { x, y, .. } => {
+            let Some(pointer) = seat.client.ptr.as_ref().map(|p| p.pointer().clone()) else {
+                warn!("Can't inject pointer input without a pointer");
+                return;
+            };
+            let mut events = vec![PointerEvent {
+                surface: surface.clone(),
+                position: (x, y),
+                kind: PointerEventKind::Motion { time: 0 },
+            }];
+            match input {
+                InjectedInput::PointerButton { button, pressed: true, .. } => {
+                    events.push(PointerEvent {
+                        surface,
+                        position: (x, y),
+                        kind: PointerEventKind::Press { time: 0, button, serial: 0 },
+                    })
+                },
+                InjectedInput::PointerButton { button, pressed: false, .. } => {
+                    events.push(PointerEvent {
+                        surface,
+                        position: (x, y),
+                        kind: PointerEventKind::Release { time: 0, button, serial: 0 },
+                    })
+                },
+                InjectedInput::PointerAxis { value, .. } => events.push(PointerEvent {
+                    surface,
+                    position: (x, y),
+                    kind: PointerEventKind::Axis {
+                        time: 0,
+                        horizontal: Default::default(),
+                        vertical: AxisScroll { absolute: value, ..Default::default() },
+                        source: None,
+                    },
+                }),
+                _ => {},
+            }
+            state.update_generated_event_serial(&mut events);
+            let conn = state.client_state.connection.clone();
+            state.pointer_frame_inner(&conn, &pointer, &events);
+        },
+    }
+}
+
+/// Testing interface, served next to the control interface.
+#[derive(Debug)]
+pub struct InputInjectionInterface {
+    panel_tx: Sender<PanelCalloopMsg>,
+}
+
+impl InputInjectionInterface {
+    pub fn new(panel_tx: Sender<PanelCalloopMsg>) -> Self {
+        Self { panel_tx }
+    }
+
+    fn send(&self, panel: String, output: String, input: InjectedInput) -> fdo::Result<()> {
+        self.panel_tx
+            .send(PanelCalloopMsg::InjectInput { panel, output, input })
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+}
+
+#[interface(name = "com.system76.CosmicPanel.Testing")]
+impl InputInjectionInterface {
+    async fn pointer_motion(
+        &self,
+        panel: String,
+        output: String,
+        x: f64,
+        y: f64,
+    ) -> fdo::Result<()> {
+        self.send(panel, output, InjectedInput::PointerMotion { x, y })
+    }
+
+    async fn pointer_button(
+        &self,
+        panel: String,
+        output: String,
+        x: f64,
+        y: f64,
+        button: u32,
+        pressed: bool,
+    ) -> fdo::Result<()> {
+        self.send(panel, output, InjectedInput::PointerButton { x, y, button, pressed })
+    }
+
+    async fn pointer_axis(
+        &self,
+        panel: String,
+        output: String,
+        x: f64,
+        y: f64,
+        value: f64,
+    ) -> fdo::Result<()> {
+        self.send(panel, output, InjectedInput::PointerAxis { x, y, value })
+    }
+
+    async fn key(
+        &self,
+        panel: String,
+        output: String,
+        keycode: u32,
+        pressed: bool,
+    ) -> fdo::Result<()> {
+        self.send(panel, output, InjectedInput::Key { keycode, pressed })
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+
+use calloop::channel::Sender;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+use zbus::{connection::Builder, fdo, interface, zvariant::Type, Connection, SignalContext};
+
+use crate::{
+    applet_channel::AppletChannelStats, frame_stats::FrameStats, log_buffer::LogBuffer,
+    PanelCalloopMsg,
+};
+
+pub const DBUS_NAME: &str = "com.system76.CosmicPanel";
+pub const DBUS_PATH: &str = "/com/system76/CosmicPanel";
+
+pub type FilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// State of a single panel, as reported over the control interface.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct PanelState {
+    pub name: String,
+    pub output: String,
+    pub anchor: String,
+    /// one of `visible`, `hidden`, `transition-to-visible` or
+    /// `transition-to-hidden`
+    pub visibility: String,
+    /// logical size of the layer surface
+    pub width: i32,
+    pub height: i32,
+    /// logical exclusive zone of the layer surface, or -1 or 0 if other
+    /// surfaces may overlap it
+    pub exclusive_zone: i32,
+}
+
+/// Change of a panel, published as a signal of the control interface.
+#[derive(Debug, Clone)]
+pub enum PanelEvent {
+    /// a panel was mapped, or its geometry, visibility or exclusive zone
+    /// changed
+    Changed(PanelState),
+    /// the panel `name` on `output` was removed
+    Removed { name: String, output: String },
+}
+
+/// Process of an applet, as reported over the control interface.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Type)]
+pub struct AppletState {
+    pub panel: String,
+    pub output: String,
+    pub name: String,
+    /// 0 if unknown
+    pub pid: u32,
+    pub restarts: u32,
+    /// exit code of the last process which failed, or 0
+    pub last_exit_code: i32,
+    /// seconds since the current process was started
+    pub uptime: u64,
+    /// resident set size in bytes, or 0 if unknown
+    pub rss: u64,
+}
+
+/// Control interface of the panel, used by `cosmic-panel-ctl`.
+pub struct PanelInterface {
+    logs: LogBuffer,
+    filter: FilterHandle,
+    frame_stats: FrameStats,
+    applet_channel: AppletChannelStats,
+    panel_tx: Sender<PanelCalloopMsg>,
+}
+
+impl std::fmt::Debug for PanelInterface {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PanelInterface").field("logs", &self.logs).finish_non_exhaustive()
+    }
+}
+
+impl PanelInterface {
+    pub fn new(
+        logs: LogBuffer,
+        filter: FilterHandle,
+        frame_stats: FrameStats,
+        applet_channel: AppletChannelStats,
+        panel_tx: Sender<PanelCalloopMsg>,
+    ) -> Self {
+        Self { logs, filter, frame_stats, applet_channel, panel_tx }
+    }
+
+    fn send(&self, msg: PanelCalloopMsg) -> fdo::Result<()> {
+        self.panel_tx.send(msg).map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+
+    /// Send a message to the panel event loop and wait for its reply.
+    async fn query<T>(
+        &self,
+        msg: impl FnOnce(oneshot::Sender<T>) -> PanelCalloopMsg,
+    ) -> fdo::Result<T> {
+        let (tx, rx) = oneshot::channel();
+        self.send(msg(tx))?;
+        rx.await.map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+}
+
+#[interface(name = "com.system76.CosmicPanel")]
+impl PanelInterface {
+    /// Most recent log records, oldest first. A limit of 0 returns all of them.
+    async fn logs(&self, limit: u32) -> Vec<String> {
+        self.logs.records(limit as usize)
+    }
+
+    /// The active tracing filter directives.
+    async fn log_filter(&self) -> fdo::Result<String> {
+        self.filter
+            .with_current(|filter| filter.to_string())
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+
+    /// Replace the tracing filter, using the same syntax as `RUST_LOG`.
+    /// e.g. `warn,cosmic_panel::xdg_shell_wrapper::client::handlers=trace`
+    async fn set_log_filter(&self, directives: String) -> fdo::Result<()> {
+        let filter = EnvFilter::try_new(&directives)
+            .map_err(|err| fdo::Error::InvalidArgs(err.to_string()))?;
+        self.filter.reload(filter).map_err(|err| fdo::Error::Failed(err.to_string()))?;
+        info!("Log filter changed to {}", directives);
+        Ok(())
+    }
+
+    /// Rolling frame statistics: average and maximum render, buffer swap
+    /// and dispatch times in microseconds, damaged area in physical pixels, and
+    /// current texture memory usage and budget in bytes.
+    async fn frame_stats(&self) -> HashMap<String, u64> {
+        self.frame_stats.summary()
+    }
+
+    /// Sent and dropped messages to the process manager of the applets, by
+    /// class.
+    async fn applet_channel_stats(&self) -> HashMap<String, u64> {
+        self.applet_channel.summary()
+    }
+
+    /// State of every panel that is currently mapped.
+    async fn panels(&self) -> fdo::Result<Vec<PanelState>> {
+        self.query(PanelCalloopMsg::PanelStates).await
+    }
+
+    /// Process state of every applet of the mapped panels.
+    async fn applets(&self) -> fdo::Result<Vec<AppletState>> {
+        self.query(PanelCalloopMsg::AppletStates).await
+    }
+
+    /// Restart the applet `name` of the panel `panel`, leaving other applets
+    /// running.
+    async fn restart_applet(&self, panel: String, name: String) -> fdo::Result<()> {
+        let error = format!("No applet {name} in panel {panel}");
+        if self.query(|tx| PanelCalloopMsg::RestartApplet { panel, name, tx }).await? {
+            Ok(())
+        } else {
+            Err(fdo::Error::InvalidArgs(error))
+        }
+    }
+
+    /// Click the applet at the 1-based `index` of the panel with activation
+    /// shortcuts enabled, preferring the one on the output of the active
+    /// window. Meant to be bound to Super+1..9.
+    async fn activate_applet(&self, index: u32) -> fdo::Result<()> {
+        if self.query(|tx| PanelCalloopMsg::ActivateApplet { index, tx }).await? {
+            Ok(())
+        } else {
+            Err(fdo::Error::InvalidArgs(format!("No applet {index} to activate")))
+        }
+    }
+
+    /// Render the panel `panel` on `output` offscreen and return it as a PNG.
+    /// An empty `output` selects the first output of the panel. A `width` and
+    /// `height` of 0 and a `scale` of 0 keep the size and scale of the panel,
+    /// otherwise the panel is scaled to fit and centered. Sides longer than
+    /// 8192 pixels are clamped, and a negative size or a scale which isn't
+    /// finite is rejected. `theme` is "light" or "dark" to override the panel
+    /// background, or empty.
+    async fn snapshot(
+        &self,
+        panel: String,
+        output: String,
+        width: i32,
+        height: i32,
+        scale: f64,
+        theme: String,
+    ) -> fdo::Result<Vec<u8>> {
+        self.query(|tx| PanelCalloopMsg::Snapshot {
+            panel,
+            output,
+            width,
+            height,
+            scale,
+            theme,
+            tx,
+        })
+        .await?
+        .map_err(fdo::Error::InvalidArgs)
+    }
+
+    /// Emitted when a panel is mapped, or when its geometry, visibility or
+    /// exclusive zone changes.
+    #[zbus(signal)]
+    async fn panel_changed(ctxt: &SignalContext<'_>, panel: PanelState) -> zbus::Result<()>;
+
+    /// Emitted when the panel `name` on `output` is removed.
+    #[zbus(signal)]
+    async fn panel_removed(ctxt: &SignalContext<'_>, name: &str, output: &str) -> zbus::Result<()>;
+
+    /// Toggle drawing damage regions, element geometries and input regions on
+    /// top of every panel.
+    async fn set_debug_overlay(&self, enabled: bool) -> fdo::Result<()> {
+        self.send(PanelCalloopMsg::SetDebugOverlay(enabled))
+    }
+
+    /// Toggle edit mode, where applets are covered by labeled placeholders and
+    /// are reordered by dragging them within and between the sections of every
+    /// panel, or removed by dropping them onto the trash target.
+    async fn set_edit_mode(&self, enabled: bool) -> fdo::Result<()> {
+        self.send(PanelCalloopMsg::SetEditMode(enabled))
+    }
+
+    /// Hide all panels, e.g. while the screen is shared. Panels are also hidden
+    /// while the active window is fullscreen.
+    async fn set_presentation_mode(&self, enabled: bool) -> fdo::Result<()> {
+        self.send(PanelCalloopMsg::SetPresentation(enabled))
+    }
+
+    /// Show or hide the panels again during a presentation. Meant to be bound
+    /// to a shortcut, as nothing else reveals panels while presenting.
+    async fn toggle_presentation_reveal(&self) -> fdo::Result<()> {
+        self.send(PanelCalloopMsg::TogglePresentationReveal)
+    }
+
+    /// Keep the panel `panel`, or every panel if it is empty, shown with
+    /// `visibility` "visible" or hidden with "hidden" regardless of autohide,
+    /// or let it follow its config again with "auto". Meant to be bound to a
+    /// shortcut to pin a dock during a task. It is kept across restarts.
+    async fn set_visibility_override(&self, panel: String, visibility: String) -> fdo::Result<()> {
+        let visibility = visibility.parse().map_err(fdo::Error::InvalidArgs)?;
+        let error = format!("No panel {panel}");
+        if self.query(|tx| PanelCalloopMsg::SetVisibilityOverride { panel, visibility, tx }).await?
+        {
+            Ok(())
+        } else {
+            Err(fdo::Error::InvalidArgs(error))
+        }
+    }
+
+    /// Reveal the panel `panel`, or every panel with autohide if it is empty,
+    /// and let it take keyboard focus on demand, or hide it again if this
+    /// revealed it. Meant to be bound to a shortcut, e.g. Super+B.
+    async fn toggle_panel(&self, panel: String) -> fdo::Result<()> {
+        let error = if panel.is_empty() {
+            "No panel with autohide".to_string()
+        } else {
+            format!("No panel {panel}")
+        };
+        if self.query(|tx| PanelCalloopMsg::TogglePanel { panel, tx }).await? {
+            Ok(())
+        } else {
+            Err(fdo::Error::InvalidArgs(error))
+        }
+    }
+
+    /// Clone the panel `panel` onto the output `output` and return the name
+    /// of the clone. The clone keeps following the config of the panel, e.g.
+    /// for the same dock on every monitor, until it is detached.
+    async fn duplicate_panel(&self, panel: String, output: String) -> fdo::Result<String> {
+        self.query(|tx| PanelCalloopMsg::DuplicatePanel { panel, output, tx })
+            .await?
+            .map_err(fdo::Error::InvalidArgs)
+    }
+
+    /// Stop the clone `panel` from following the config of its original, so
+    /// it can be configured on its own.
+    async fn detach_panel(&self, panel: String) -> fdo::Result<()> {
+        self.query(|tx| PanelCalloopMsg::DetachPanel { panel, tx })
+            .await?
+            .map_err(fdo::Error::InvalidArgs)
+    }
+
+    /// Whether do not disturb is on.
+    async fn do_not_disturb(&self) -> fdo::Result<bool> {
+        self.query(PanelCalloopMsg::DoNotDisturb).await
+    }
+
+    /// Turn do not disturb on or off. Applets get the state as the
+    /// `do_not_disturb` entry of their live config, and silence their badges
+    /// and attention animations while it is on. It persists in the panel
+    /// config.
+    async fn set_do_not_disturb(&self, enabled: bool) -> fdo::Result<()> {
+        self.send(PanelCalloopMsg::SetDoNotDisturb(enabled))
+    }
+
+    /// Show an on-screen display with the icon `icon` and `text` on `output`,
+    /// next to its panel if it has one, or on the output of the first panel
+    /// if `output` is empty. A `value` from 0 to 100 is shown as a level bar,
+    /// a negative one hides it. Showing another OSD on the same output
+    /// replaces it.
+    async fn show_osd(
+        &self,
+        output: String,
+        icon: String,
+        text: String,
+        value: i32,
+    ) -> fdo::Result<()> {
+        self.send(PanelCalloopMsg::ShowOsd { output, icon, text, value })
+    }
+
+    /// Reload the theme mode and the light and dark theme, and apply them to
+    /// every panel and its applets, even if the config didn't change.
+    async fn reapply_theme(&self) -> fdo::Result<()> {
+        self.send(PanelCalloopMsg::ReapplyTheme)
+    }
+}
+
+/// Serve the control interface on the session bus.
+pub async fn serve(interface: PanelInterface) -> zbus::Result<Connection> {
+    #[cfg(feature = "input-injection")]
+    let testing = crate::input_injection::InputInjectionInterface::new(interface.panel_tx.clone());
+    let builder = Builder::session()?.name(DBUS_NAME)?.serve_at(DBUS_PATH, interface)?;
+    #[cfg(feature = "input-injection")]
+    let builder = builder.serve_at(DBUS_PATH, testing)?;
+    let conn = builder.build().await?;
+    info!("Serving control interface at {}", DBUS_NAME);
+    Ok(conn)
+}
+
+/// Emit the signals of the control interface for panel events sent by the
+/// event loop, until the channel is closed.
+pub async fn publish(conn: Connection, mut rx: mpsc::UnboundedReceiver<PanelEvent>) {
+    let iface = match conn.object_server().interface::<_, PanelInterface>(DBUS_PATH).await {
+        Ok(iface) => iface,
+        Err(err) => {
+            warn!("Failed to get the panel control interface {:?}", err);
+            return;
+        },
+    };
+    while let Some(event) = rx.recv().await {
+        let ctxt = iface.signal_context();
+        let res = match event {
+            PanelEvent::Changed(panel) => PanelInterface::panel_changed(ctxt, panel).await,
+            PanelEvent::Removed { name, output } => {
+                PanelInterface::panel_removed(ctxt, &name, &output).await
+            },
+        };
+        if let Err(err) = res {
+            warn!("Failed to emit a panel signal {:?}", err);
+        }
+    }
+}
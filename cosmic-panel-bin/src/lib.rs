@@ -0,0 +1,714 @@
+//! The COSMIC panel, which hosts applets in panels and docks on each output.
+//!
+//! The `cosmic-panel` binary only calls [`run`]. With the `plugin-api`
+//! feature, other binaries can register their own panel elements with
+//! [`plugin::register_plugin`] before running the panel.
+
+mod applet_channel;
+mod battery;
+mod config_watching;
+mod frame_stats;
+mod hooks;
+mod iced;
+#[cfg(feature = "input-injection")]
+mod input_injection;
+mod ipc;
+mod log_buffer;
+mod minimize;
+mod notifications;
+#[cfg(feature = "plugin-api")]
+pub mod plugin;
+mod portal;
+mod reconnect;
+mod resume;
+mod sandbox;
+mod session_lock;
+mod space;
+mod space_container;
+mod startup;
+mod xdg_shell_wrapper;
+
+#[cfg(feature = "layout-bench")]
+#[doc(hidden)]
+pub use space::bench::{BenchApplet, LayoutBench};
+#[cfg(feature = "golden-tests")]
+#[doc(hidden)]
+pub use space::{golden::BackgroundRenderer, section_layout};
+
+use crate::{
+    iced::elements::osd::OsdContent,
+    space_container::visibility_override::VisibilityOverride,
+    xdg_shell_wrapper::{
+        client_state::ClientState, server_state::ServerState, shared_state::GlobalState,
+    },
+};
+use anyhow::Result;
+use calloop::channel::Sender;
+use cctk::{
+    cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_handle_v1,
+    wayland_client::protocol::wl_output::WlOutput,
+};
+use config_watching::{watch_config, watch_cosmic_theme};
+use cosmic::cosmic_config::CosmicConfigEntry;
+use cosmic_panel_config::CosmicPanelConfig;
+use launch_pad::{process::Process, ProcessKey, ProcessManager};
+use log_buffer::LogBuffer;
+use minimize::MinimizeApplet;
+use notifications::{notifications_conn, SharedNotificationsConnection};
+use smithay::reexports::{calloop, wayland_server::backend::ClientId};
+use std::{
+    collections::HashMap,
+    mem,
+    os::fd::{AsRawFd, OwnedFd},
+    time::Duration,
+};
+use tokio::{
+    runtime,
+    sync::{mpsc, oneshot},
+};
+use tracing::{error, info, warn};
+use tracing_subscriber::{fmt, prelude::*, reload, EnvFilter};
+
+#[derive(Debug)]
+pub(crate) enum PanelCalloopMsg {
+    ClientSocketPair(ClientId),
+    RestartSpace(CosmicPanelConfig, WlOutput),
+    MinimizeRect {
+        output: String,
+        applet_info: MinimizeApplet,
+    },
+    UpdateToplevel(zcosmic_toplevel_handle_v1::ZcosmicToplevelHandleV1),
+    SetDebugOverlay(bool),
+    SetEditMode(bool),
+    PanelStates(oneshot::Sender<Vec<ipc::PanelState>>),
+    AppletStates(oneshot::Sender<Vec<ipc::AppletState>>),
+    RestartApplet {
+        panel: String,
+        name: String,
+        tx: oneshot::Sender<bool>,
+    },
+    /// click the Nth applet of the panel with activation shortcuts
+    ActivateApplet {
+        index: u32,
+        tx: oneshot::Sender<bool>,
+    },
+    Snapshot {
+        panel: String,
+        output: String,
+        width: i32,
+        height: i32,
+        scale: f64,
+        theme: String,
+        tx: oneshot::Sender<Result<Vec<u8>, String>>,
+    },
+    SessionLocked(bool),
+    SetPresentation(bool),
+    SetDoNotDisturb(bool),
+    DoNotDisturb(oneshot::Sender<bool>),
+    TogglePresentationReveal,
+    SetVisibilityOverride {
+        panel: String,
+        visibility: VisibilityOverride,
+        tx: oneshot::Sender<bool>,
+    },
+    TogglePanel {
+        panel: String,
+        tx: oneshot::Sender<bool>,
+    },
+    /// clone a panel onto another output, replying with the name of the clone
+    DuplicatePanel {
+        panel: String,
+        output: String,
+        tx: oneshot::Sender<Result<String, String>>,
+    },
+    DetachPanel {
+        panel: String,
+        tx: oneshot::Sender<Result<(), String>>,
+    },
+    Battery(battery::Battery),
+    /// grants of flatpak applets from the permission store of the portal
+    PortalGrants(HashMap<String, bool>),
+    ShowOsd {
+        output: String,
+        icon: String,
+        text: String,
+        value: i32,
+    },
+    /// reload the system theme and apply it to all panels
+    ReapplyTheme,
+    /// restart notification applets with fds from connections older than the
+    /// given generation
+    RestartNotificationApplets(u64),
+    #[cfg(feature = "input-injection")]
+    InjectInput {
+        panel: String,
+        output: String,
+        input: input_injection::InjectedInput,
+    },
+}
+
+/// What the panel is run for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PanelMode {
+    /// panels of a user session
+    #[default]
+    Desktop,
+    /// panels of the login screen, with a restricted set of applets and
+    /// without privileged protocols
+    Greeter,
+    /// a single panel rendered into a window, for a live preview in the
+    /// settings
+    Preview,
+}
+
+/// Run the panel with the arguments of the process, until it exits.
+pub fn run() -> Result<()> {
+    let fmt_layer = fmt::layer().with_target(false);
+    let filter_layer =
+        EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("warn")).unwrap();
+    let (filter_layer, filter_handle) = reload::Layer::new(filter_layer);
+    let log_buffer = LogBuffer::new();
+    if let Ok(journal_layer) = tracing_journald::layer() {
+        tracing_subscriber::registry()
+            .with(filter_layer)
+            .with(journal_layer)
+            .with(log_buffer.clone())
+            .init();
+    } else {
+        tracing_subscriber::registry()
+            .with(filter_layer)
+            .with(fmt_layer)
+            .with(log_buffer.clone())
+            .init();
+    }
+
+    log_panics::init();
+
+    // the socket of the session is used by the first connection only
+    let notifications_socket = notifications::take_session_socket();
+
+    #[cfg(feature = "plugin-api")]
+    plugin::register_plugins();
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let startup_report = args.iter().any(|a| a == "--startup-report");
+    args.retain(|a| a != "--startup-report");
+    startup::begin(startup_report);
+    let usage =
+        "USAGE: cosmic-panel [--mode desktop|greeter | --preview <ENTRY>] [--startup-report]";
+    let config_span = startup::span(startup::Phase::ConfigLoad);
+    let (mode, config) = match args.iter().map(|s| s.as_str()).collect::<Vec<_>>().as_slice() {
+        [arg] if *arg == "--help" || *arg == "-h" => {
+            println!("{}", usage);
+            std::process::exit(1);
+        },
+        [] | ["--mode", "desktop"] => {
+            let config = match cosmic_panel_config::CosmicPanelContainerConfig::load() {
+                Ok(c) => c,
+                Err((errors, c)) => {
+                    for e in errors {
+                        error!("Panel Entry Error: {:?}", e);
+                    }
+                    let _ = c.write_entries();
+                    c
+                },
+            };
+            sandbox::migrate_privileged_applets(&config);
+            (PanelMode::Desktop, config)
+        },
+        ["--mode", "greeter"] => {
+            // the greeter config is read only
+            let config = cosmic_panel_config::CosmicPanelContainerConfig::load_greeter()
+                .unwrap_or_else(|(errors, c)| {
+                    for e in errors {
+                        error!("Panel Entry Error: {:?}", e);
+                    }
+                    c
+                });
+            (PanelMode::Greeter, config)
+        },
+        ["--preview", entry] => {
+            // the entry is usually a copy of a panel edited by the settings, which
+            // isn't in the entries of the real panel
+            let config = CosmicPanelConfig::cosmic_config(entry)
+                .map_err(|err| anyhow::anyhow!("Failed to load panel entry {entry}: {err:?}"))?;
+            let entry = CosmicPanelConfig::get_entry(&config).unwrap_or_else(|(errors, c)| {
+                for e in errors {
+                    error!("Panel Entry Error: {:?}", e);
+                }
+                c
+            });
+            let config = cosmic_panel_config::CosmicPanelContainerConfig {
+                config_list: vec![space::preview_config(entry)],
+            };
+            (PanelMode::Preview, config)
+        },
+        _ => {
+            println!("{}", usage);
+            std::process::exit(1);
+        },
+    };
+
+    drop(config_span);
+    startup::mark(startup::Phase::ConfigLoad);
+
+    let (applet_tx, mut applet_rx) = applet_channel::channel();
+    let applet_channel_stats = applet_tx.stats();
+    let shutdown_tx = applet_tx.clone();
+    let (calloop_tx, calloop_rx): (Sender<PanelCalloopMsg>, _) = calloop::channel::channel();
+
+    let event_loop = calloop::EventLoop::try_new()?;
+
+    let mut space = space_container::SpaceContainer::new(
+        config,
+        applet_tx.clone(),
+        calloop_tx.clone(),
+        event_loop.handle(),
+    );
+    space.mode = mode;
+    space.restore_runtime_state();
+    space.sync_activation_shortcuts();
+    let (panel_event_tx, panel_event_rx) = mpsc::unbounded_channel();
+    if mode != PanelMode::Preview {
+        space.panel_events = Some(panel_event_tx);
+    }
+
+    let frame_stats = space.frame_stats.clone();
+    let ipc_panel_tx = calloop_tx.clone();
+    let session_lock_tx = calloop_tx.clone();
+    let battery_tx = calloop_tx.clone();
+    let portal_tx = calloop_tx.clone();
+    let resume_tx = calloop_tx.clone();
+
+    let handle = event_loop.handle();
+    if mode != PanelMode::Greeter {
+        // the preview follows its entry, but not the entries of the real panel
+        match watch_config(&space.config, handle, mode == PanelMode::Desktop) {
+            Ok(watchers) => {
+                info!("Watching panel config successful");
+                space.watchers = watchers;
+            },
+            Err(e) => warn!("Failed to watch config: {:?}", e),
+        };
+    }
+    match watch_cosmic_theme(event_loop.handle()) {
+        Ok(w) => mem::forget(w),
+        Err(e) => error!("Error while watching cosmic theme: {:?}", e),
+    };
+
+    event_loop
+        .handle()
+        .insert_source(
+            calloop::timer::Timer::from_duration(Duration::from_secs(1)),
+            |_, _, state: &mut GlobalState| {
+                tracing::trace!("Updating hidden applets");
+
+                state.space.update_hidden_applet_frame();
+                state.space.tick_builtin_applets();
+                state.space.release_staged_applets(false);
+                space_container::appearance::update_appearance_profiles(state);
+                calloop::timer::TimeoutAction::ToDuration(Duration::from_secs(1))
+            },
+        )
+        .expect("failed to insert hidden applet frame timer");
+
+    let mut fd_audit = space_container::fd_audit::FdAudit::default();
+    event_loop
+        .handle()
+        .insert_source(
+            calloop::timer::Timer::from_duration(space_container::fd_audit::AUDIT_INTERVAL),
+            move |_, _, state: &mut GlobalState| {
+                state.space.audit_fds(&mut fd_audit);
+                calloop::timer::TimeoutAction::ToDuration(space_container::fd_audit::AUDIT_INTERVAL)
+            },
+        )
+        .expect("failed to insert fd audit timer");
+
+    event_loop
+        .handle()
+        .insert_source(calloop_rx, move |e, _, state: &mut GlobalState| {
+            tracing::trace!("Panel Event: {e:?}");
+            match e {
+                calloop::channel::Event::Msg(e) => match e {
+                    PanelCalloopMsg::ClientSocketPair(client_id) => {
+                        state.space.cleanup_client(client_id);
+                    },
+                    PanelCalloopMsg::RestartSpace(config, o) => {
+                        state.space.update_space(
+                            config,
+                            &state.client_state.compositor_state,
+                            state.client_state.fractional_scaling_manager.as_ref(),
+                            state.client_state.viewporter_state.as_ref(),
+                            &mut state.client_state.layer_state,
+                            &state.client_state.queue_handle,
+                            Some(o),
+                            state.client_state.overlap_notify.clone(),
+                        );
+                    },
+                    PanelCalloopMsg::UpdateToplevel(toplevel) => {
+                        minimize::update_toplevel(state, toplevel)
+                    },
+                    PanelCalloopMsg::MinimizeRect { output, applet_info } => {
+                        minimize::set_rectangles(state, output, applet_info)
+                    },
+                    PanelCalloopMsg::SetDebugOverlay(enabled) => {
+                        state.space.set_debug_overlay(enabled)
+                    },
+                    PanelCalloopMsg::SetEditMode(enabled) => state.space.set_edit_mode(enabled),
+                    PanelCalloopMsg::PanelStates(tx) => {
+                        _ = tx.send(state.space.panel_states());
+                    },
+                    PanelCalloopMsg::AppletStates(tx) => {
+                        _ = tx.send(state.space.applet_states());
+                    },
+                    PanelCalloopMsg::RestartApplet { panel, name, tx } => {
+                        _ = tx.send(state.space.restart_applet(&panel, &name));
+                    },
+                    PanelCalloopMsg::ActivateApplet { index, tx } => {
+                        _ = tx.send(space_container::activation::activate_applet(state, index));
+                    },
+                    PanelCalloopMsg::Snapshot {
+                        panel,
+                        output,
+                        width,
+                        height,
+                        scale,
+                        theme,
+                        tx,
+                    } => {
+                        _ = tx.send(state.space.snapshot(
+                            &panel,
+                            &output,
+                            (width, height).into(),
+                            scale,
+                            &theme,
+                        ));
+                    },
+                    PanelCalloopMsg::SessionLocked(locked) => {
+                        session_lock::set_locked(state, locked)
+                    },
+                    PanelCalloopMsg::SetDoNotDisturb(enabled) => {
+                        state.space.set_do_not_disturb(enabled)
+                    },
+                    PanelCalloopMsg::DoNotDisturb(tx) => {
+                        _ = tx.send(state.space.do_not_disturb);
+                    },
+                    PanelCalloopMsg::SetPresentation(enabled) => {
+                        state.space.presentation_requested = enabled;
+                    },
+                    PanelCalloopMsg::TogglePresentationReveal => {
+                        if state.space.presenting {
+                            state.space.presentation_revealed ^= true;
+                        }
+                    },
+                    PanelCalloopMsg::SetVisibilityOverride { panel, visibility, tx } => {
+                        _ = tx.send(state.space.set_visibility_override(&panel, visibility));
+                    },
+                    PanelCalloopMsg::TogglePanel { panel, tx } => {
+                        _ = tx.send(state.space.toggle_reveal(&panel));
+                    },
+                    PanelCalloopMsg::DuplicatePanel { panel, output, tx } => {
+                        _ = tx.send(state.space.duplicate_panel(&panel, &output));
+                    },
+                    PanelCalloopMsg::DetachPanel { panel, tx } => {
+                        _ = tx.send(state.space.detach_panel(&panel));
+                    },
+                    PanelCalloopMsg::Battery(battery) => state.space.battery = Some(battery),
+                    PanelCalloopMsg::PortalGrants(grants) => state.space.set_portal_grants(grants),
+                    PanelCalloopMsg::ReapplyTheme => state.space.reapply_theme(),
+                    PanelCalloopMsg::ShowOsd { output, icon, text, value } => {
+                        let content =
+                            OsdContent { icon, text, value: u8::try_from(value.min(100)).ok() };
+                        state.space.show_osd(
+                            &output,
+                            content,
+                            &state.client_state.compositor_state,
+                            &mut state.client_state.layer_state,
+                            state.client_state.fractional_scaling_manager.as_ref(),
+                            state.client_state.viewporter_state.as_ref(),
+                            &state.client_state.queue_handle,
+                        );
+                    },
+                    PanelCalloopMsg::RestartNotificationApplets(generation) => {
+                        state.space.restart_notification_applets(generation)
+                    },
+                    #[cfg(feature = "input-injection")]
+                    PanelCalloopMsg::InjectInput { panel, output, input } => {
+                        input_injection::inject(state, &panel, &output, input)
+                    },
+                },
+                calloop::channel::Event::Closed => {},
+            };
+        })
+        .expect("failed to insert dbus event source");
+
+    std::thread::spawn(move || -> anyhow::Result<()> {
+        let rt = runtime::Builder::new_current_thread().enable_all().build()?;
+        let mut process_ids: HashMap<String, Vec<ProcessKey>> = HashMap::new();
+
+        rt.block_on(async move {
+            let process_manager = ProcessManager::new().await;
+            let _ = process_manager
+                .set_restart_mode(launch_pad::RestartMode::ExponentialBackoff(
+                    Duration::from_millis(2),
+                ))
+                .await;
+            let _ = process_manager.set_max_restarts(999999).await;
+
+            // the control interface belongs to the real panel
+            let _ipc_conn = if mode == PanelMode::Preview {
+                None
+            } else {
+                match ipc::serve(ipc::PanelInterface::new(
+                    log_buffer,
+                    filter_handle,
+                    frame_stats,
+                    applet_channel_stats,
+                    ipc_panel_tx,
+                ))
+                .await
+                {
+                    Ok(conn) => {
+                        tokio::spawn(ipc::publish(conn.clone(), panel_event_rx));
+                        Some(conn)
+                    },
+                    Err(err) => {
+                        error!("Failed to serve the panel control interface {:?}", err);
+                        None
+                    },
+                }
+            };
+
+            tokio::spawn(async move {
+                if let Err(err) = session_lock::watch(session_lock_tx).await {
+                    warn!("Failed to watch the lock state of the session {:?}", err);
+                }
+            });
+            tokio::spawn(async move {
+                if let Err(err) = battery::watch(battery_tx).await {
+                    warn!("Failed to watch the battery state {:?}", err);
+                }
+            });
+            tokio::spawn(async move {
+                if let Err(err) = portal::watch(portal_tx).await {
+                    warn!("Failed to watch the permission store of the portal {:?}", err);
+                }
+            });
+            tokio::spawn(async move {
+                if let Err(err) = resume::watch(resume_tx).await {
+                    warn!("Failed to watch for resume from suspend {:?}", err);
+                }
+            });
+
+            let notifications_conn_shared = SharedNotificationsConnection::default();
+            // the preview doesn't start notification applets
+            if mode != PanelMode::Preview {
+                match tokio::time::timeout(
+                    Duration::from_secs(1),
+                    notifications_conn(notifications_socket),
+                )
+                .await
+                {
+                    Ok(Ok(p)) => notifications_conn_shared.lock().await.set(p),
+                    err => error!("Failed to connect to the notifications daemon {:?}", err),
+                };
+                let notifications_tx = applet_tx.clone();
+                tokio::spawn(notifications::keep_connected(
+                    notifications_conn_shared.clone(),
+                    move |restarted| {
+                        _ = notifications_tx
+                            .try_send(space::AppletMsg::NotificationsConnected { restarted });
+                    },
+                ));
+            }
+            // started or restarted notification applets waiting for a connection
+            let mut pending_notification_processes = Vec::new();
+            let mut pending_notification_fds = Vec::new();
+            let mut hook_runner = hooks::HookRunner::new(applet_tx.clone());
+
+            while let Some(msg) = applet_rx.recv().await {
+                tracing::trace!("Applet Message: {msg:?}");
+                match msg {
+                    space::AppletMsg::NewProcess(id, process) => {
+                        if let Ok(key) = process_manager.start(process).await {
+                            startup::mark(startup::Phase::FirstAppletSpawn);
+                            let entry = process_ids.entry(id).or_default();
+                            entry.push(key);
+                        }
+                    },
+                    space::AppletMsg::NewNotificationsProcess(id, process, env, fds, applet) => {
+                        info!("Getting fd for notifications applet {}", applet.name);
+                        let Some((notif_fd, generation)) =
+                            notifications::get_fd(&notifications_conn_shared).await
+                        else {
+                            warn!(
+                                "Delaying {} until the notifications daemon is connected",
+                                applet.name
+                            );
+                            pending_notification_processes.push((id, process, env, fds, applet));
+                            continue;
+                        };
+                        applet.set_generation(generation);
+                        start_notifications_process(
+                            &process_manager,
+                            &mut process_ids,
+                            (id, process, env, fds),
+                            notif_fd,
+                        )
+                        .await;
+                    },
+                    space::AppletMsg::ClientSocketPair(client_id) => {
+                        let _ = calloop_tx.send(PanelCalloopMsg::ClientSocketPair(client_id));
+                    },
+                    space::AppletMsg::Restart(key) => {
+                        // the process manager restarts the process once it exited, and
+                        // its exit handler gives it a new client
+                        match process_manager.get_pid(key).await {
+                            Ok(Some(pid)) if pid > 0 => {
+                                // SAFETY: kill has no memory safety requirements
+                                unsafe { libc::kill(pid as i32, libc::SIGTERM) };
+                            },
+                            _ => warn!("Failed to find the process of an applet to restart"),
+                        }
+                    },
+                    space::AppletMsg::Cleanup(id) => {
+                        pending_notification_processes.retain(|(p_id, ..)| p_id != &id);
+                        for id in process_ids.remove(&id).unwrap_or_default() {
+                            let _ = process_manager.stop_process(id).await;
+                        }
+                    },
+                    space::AppletMsg::Launch(process) => {
+                        if let Err(err) = process_manager.start(process).await {
+                            error!("Failed to launch a process {:?}", err);
+                        }
+                    },
+                    space::AppletMsg::RunHook(run) => hook_runner.run(&process_manager, run).await,
+                    space::AppletMsg::RunPendingHooks => {
+                        hook_runner.run_pending(&process_manager).await
+                    },
+                    space::AppletMsg::Shutdown(done) => {
+                        pending_notification_processes.clear();
+                        for (_, keys) in process_ids.drain() {
+                            for key in keys {
+                                let _ = process_manager.stop_process(key).await;
+                            }
+                        }
+                        _ = done.send(());
+                    },
+                    space::AppletMsg::NeedNewNotificationFd(applet, sender) => {
+                        let Some((fd, generation)) =
+                            notifications::get_fd(&notifications_conn_shared).await
+                        else {
+                            warn!(
+                                "Delaying {} until the notifications daemon is connected",
+                                applet.name
+                            );
+                            pending_notification_fds.push((applet, sender));
+                            continue;
+                        };
+                        applet.set_generation(generation);
+                        _ = sender.send(fd);
+                    },
+                    space::AppletMsg::NotificationsConnected { restarted } => {
+                        if restarted {
+                            // running applets hold fds of the old daemon, and get new ones
+                            // when they are restarted
+                            let generation = notifications_conn_shared.lock().await.generation;
+                            info!("Notifications daemon restarted, restarting its applets");
+                            let _ = calloop_tx
+                                .send(PanelCalloopMsg::RestartNotificationApplets(generation));
+                        }
+                        for (applet, sender) in mem::take(&mut pending_notification_fds) {
+                            let Some((fd, generation)) =
+                                notifications::get_fd(&notifications_conn_shared).await
+                            else {
+                                pending_notification_fds.push((applet, sender));
+                                continue;
+                            };
+                            applet.set_generation(generation);
+                            _ = sender.send(fd);
+                        }
+                        for (id, process, env, fds, applet) in
+                            mem::take(&mut pending_notification_processes)
+                        {
+                            let Some((notif_fd, generation)) =
+                                notifications::get_fd(&notifications_conn_shared).await
+                            else {
+                                pending_notification_processes
+                                    .push((id, process, env, fds, applet));
+                                continue;
+                            };
+                            applet.set_generation(generation);
+                            start_notifications_process(
+                                &process_manager,
+                                &mut process_ids,
+                                (id, process, env, fds),
+                                notif_fd,
+                            )
+                            .await;
+                        }
+                    },
+                };
+            }
+        });
+
+        Ok(())
+    });
+
+    let server_display = smithay::reexports::wayland_server::Display::new().unwrap();
+    let s_dh = server_display.handle();
+
+    let mut server_state = ServerState::new(s_dh.clone());
+
+    let binds_span = startup::span(startup::Phase::GlobalBinds);
+    let mut client_state = ClientState::new(event_loop.handle(), &mut space, &mut server_state)?;
+    drop(binds_span);
+    startup::mark(startup::Phase::GlobalBinds);
+    client_state.init_workspace_state();
+    client_state.init_toplevel_info_state();
+    space.geometry_overlaps = client_state.overlap_notify.is_none()
+        && (client_state.toplevel_info_state.is_some()
+            || client_state.wlr_toplevel_manager.is_some());
+    // applets of the greeter must not manage windows
+    if mode != PanelMode::Greeter {
+        client_state.init_toplevel_manager_state();
+    }
+    if let Err(err) =
+        xdg_shell_wrapper::run(space, client_state, server_state, event_loop, server_display)
+    {
+        if err.is::<xdg_shell_wrapper::ConnectionLost>() {
+            error!("{err}");
+            return Err(reconnect::restart());
+        }
+        return Err(err);
+    }
+
+    // the event loop returns once the panel exits, e.g. when the preview is
+    // closed, and the applets are stopped with it
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+    if shutdown_tx.try_send(space::AppletMsg::Shutdown(done_tx)).is_ok()
+        && done_rx.recv_timeout(Duration::from_secs(5)).is_err()
+    {
+        warn!("Timed out stopping the applets");
+    }
+    Ok(())
+}
+
+/// Start a notifications applet with the daemon connection `notif_fd`.
+async fn start_notifications_process(
+    process_manager: &ProcessManager,
+    process_ids: &mut HashMap<String, Vec<ProcessKey>>,
+    (id, mut process, mut env, mut fds): (String, Process, Vec<(String, String)>, Vec<OwnedFd>),
+    notif_fd: OwnedFd,
+) {
+    env.push(("COSMIC_NOTIFICATIONS".to_string(), notif_fd.as_raw_fd().to_string()));
+    fds.push(notif_fd);
+    process = process.with_fds(move || fds);
+    process = process.with_env(env);
+    info!("Starting notifications applet");
+    if let Ok(key) = process_manager.start(process).await {
+        startup::mark(startup::Phase::FirstAppletSpawn);
+        let entry = process_ids.entry(id).or_default();
+        entry.push(key);
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_subscriber::{layer::Context, Layer};
+
+/// Number of records kept when `COSMIC_PANEL_LOG_BUFFER` is not set.
+const DEFAULT_CAPACITY: usize = 2000;
+
+/// In-memory ring buffer of the most recent log records.
+///
+/// This is queryable over the control interface, so logs can be collected
+/// even when journald is not capturing the session.
+#[derive(Debug, Clone)]
+pub struct LogBuffer {
+    records: Arc<Mutex<VecDeque<String>>>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    pub fn new() -> Self {
+        let capacity = std::env::var("COSMIC_PANEL_LOG_BUFFER")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_CAPACITY);
+        Self { records: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))), capacity }
+    }
+
+    /// Returns up to `limit` of the most recent records, oldest first.
+    /// A limit of 0 returns every buffered record.
+    pub fn records(&self, limit: usize) -> Vec<String> {
+        let records = self.records.lock().unwrap();
+        let skip = if limit == 0 { 0 } else { records.len().saturating_sub(limit) };
+        records.iter().skip(skip).cloned().collect()
+    }
+
+    fn push(&self, record: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut records = self.records.lock().unwrap();
+        while records.len() >= self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+}
+
+#[derive(Default)]
+struct RecordVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for RecordVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogBuffer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut visitor = RecordVisitor::default();
+        event.record(&mut visitor);
+
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+        self.push(format!(
+            "{timestamp:.3} {:>5} {}: {}{}",
+            metadata.level(),
+            metadata.target(),
+            visitor.message,
+            visitor.fields
+        ));
+    }
+}
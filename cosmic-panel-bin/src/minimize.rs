@@ -1,15 +1,59 @@
 use crate::xdg_shell_wrapper::shared_state::GlobalState;
 use cctk::{
     cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_handle_v1,
+    toplevel_info::ToplevelInfo,
     wayland_client::{protocol::wl_surface::WlSurface, Proxy},
 };
-use smithay::utils::{Logical, Rectangle};
+use smithay::{
+    reexports::wayland_server::{backend::ClientId, Resource},
+    utils::{Logical, Rectangle},
+};
 
 #[derive(Debug, Clone)]
 pub struct MinimizeApplet {
     pub priority: i32,
     pub rect: Rectangle<i32, Logical>,
     pub surface: WlSurface,
+    /// the applet client
+    pub client: ClientId,
+    /// app ids of the windows minimizing to this applet, or empty for all
+    pub app_ids: Vec<String>,
+}
+
+/// The applet which windows of `app_id` minimize to. Applets for the app id
+/// are preferred over applets for all windows, and applets for other app ids
+/// are only used if there is nothing else.
+fn minimize_target<'a>(applets: &'a [MinimizeApplet], app_id: &str) -> Option<&'a MinimizeApplet> {
+    applets
+        .iter()
+        .filter(|a| a.surface.is_alive())
+        .max_by_key(|a| target_rank(&a.app_ids, a.priority, app_id))
+}
+
+/// Ordering key of an applet for `app_ids` as the target of windows of
+/// `app_id`, the greatest is chosen.
+fn target_rank(app_ids: &[String], priority: i32, app_id: &str) -> (bool, bool, i32) {
+    (app_ids.iter().any(|id| id == app_id), app_ids.is_empty(), priority)
+}
+
+/// The app ids of the `X-MinimizeAppIds` key of an applet's desktop entry.
+pub fn parse_app_ids(value: &str) -> Vec<String> {
+    value.split(';').map(str::trim).filter(|id| !id.is_empty()).map(str::to_string).collect()
+}
+
+/// The rectangle an applet set for the window of `info`, moved to the layer
+/// surface of its panel.
+fn toplevel_target(
+    state: &GlobalState,
+    info: &ToplevelInfo,
+) -> Option<(WlSurface, Rectangle<i32, Logical>)> {
+    let identifier = state.forwarded_identifier(&info.identifier)?;
+    let (surface, rect) = state.space.toplevel_minimize_rects.get(&identifier)?;
+    let client = surface.client()?;
+    let space = state.space.space_list.iter().find(|s| s.has_client(&client))?;
+    let loc = space.surface_location(surface)?;
+    let layer = space.panel_surface()?.clone();
+    Some((layer, Rectangle::from_loc_and_size(rect.loc + loc, rect.size)))
 }
 
 pub fn update_toplevel(
@@ -24,68 +68,101 @@ pub fn update_toplevel(
         return;
     };
 
-    if let Some((_, info)) = minimized_applets.iter().find(|(output_name, _)| {
-        toplevel_info.output.iter().any(|o| {
-            let Some(i) = state.client_state.output_state.info(o) else {
-                return false;
-            };
-            i.name.as_ref() == Some(output_name)
+    // a rectangle for the window itself, or else the first output of the
+    // toplevel with a panel minimize applet
+    if let Some((surface, rect)) = toplevel_target(state, toplevel_info).or_else(|| {
+        toplevel_info.output.iter().find_map(|o| {
+            let name = state.client_state.output_state.info(o)?.name?;
+            minimize_target(minimized_applets.get(&name)?, &toplevel_info.app_id)
+                .map(|info| (info.surface.clone(), info.rect))
         })
     }) {
         toplevel_mngr.manager.set_rectangle(
             &toplevel,
-            &info.surface,
-            info.rect.loc.x,
-            info.rect.loc.y,
-            info.rect.size.w,
-            info.rect.size.h,
+            &surface,
+            rect.loc.x,
+            rect.loc.y,
+            rect.size.w,
+            rect.size.h,
         );
     }
 }
 
 pub fn set_rectangles(state: &mut GlobalState, output: String, info: MinimizeApplet) {
-    // if surface matches cur & is different, or is higher priority, replace
-    let mut changed = false;
-    let minimized_applets = &mut state.space.minimized_applets;
-
-    let old_info = minimized_applets.entry(output.clone()).or_insert_with(|| {
-        changed = true;
-        info.clone()
-    });
-
-    if !changed {
-        if old_info.surface == info.surface && old_info.rect != info.rect {
-            old_info.rect = info.rect;
-            changed = true;
-        } else if old_info.priority < info.priority || !old_info.surface.is_alive() {
-            *old_info = info.clone();
-            changed = true;
+    let applets = state.space.minimized_applets.entry(output.clone()).or_default();
+    applets.retain(|a| a.surface.is_alive());
+    if let Some(old_info) = applets.iter_mut().find(|a| a.client == info.client) {
+        if old_info.surface == info.surface
+            && old_info.rect == info.rect
+            && old_info.app_ids == info.app_ids
+        {
+            return;
         }
+        *old_info = info;
+    } else {
+        applets.push(info);
     }
 
-    // if changed, send rect for all toplevels for the given out
-    if changed {
-        let Some(toplevel_mngr) = state.client_state.toplevel_manager_state.as_ref() else {
-            return;
-        };
-
-        for (toplevel, toplevel_info) in &mut state.space.toplevels {
-            if !toplevel_info.output.iter().any(|o| {
-                let Some(i) = state.client_state.output_state.info(o) else {
-                    return false;
-                };
-                i.name.as_ref() == Some(&output)
-            }) {
-                continue;
-            }
-            toplevel_mngr.manager.set_rectangle(
-                toplevel,
-                &info.surface,
-                info.rect.loc.x,
-                info.rect.loc.y,
-                info.rect.size.w,
-                info.rect.size.h,
-            );
-        }
+    // if changed, send rects for all toplevels on the given output
+    let toplevels: Vec<_> = state
+        .space
+        .toplevels
+        .iter()
+        .filter(|(_, toplevel_info)| {
+            toplevel_info.output.iter().any(|o| {
+                state
+                    .client_state
+                    .output_state
+                    .info(o)
+                    .is_some_and(|i| i.name.as_deref() == Some(output.as_str()))
+            })
+        })
+        .map(|(toplevel, _)| toplevel.clone())
+        .collect();
+    for toplevel in toplevels {
+        update_toplevel(state, toplevel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    /// The index of the applet windows of `app_id` minimize to.
+    fn target(applets: &[(Vec<String>, i32)], app_id: &str) -> Option<usize> {
+        (0..applets.len()).max_by_key(|&i| target_rank(&applets[i].0, applets[i].1, app_id))
+    }
+
+    #[test]
+    fn windows_minimize_to_the_applet_of_their_app_id() {
+        let applets = [(ids(&[]), 10), (ids(&["org.gnome.Nautilus"]), 0), (ids(&["firefox"]), 0)];
+        assert_eq!(target(&applets, "org.gnome.Nautilus"), Some(1));
+        assert_eq!(target(&applets, "firefox"), Some(2));
+    }
+
+    #[test]
+    fn other_windows_minimize_to_the_applet_for_all_windows() {
+        let applets = [(ids(&["firefox"]), 10), (ids(&[]), 1), (ids(&[]), 2)];
+        assert_eq!(target(&applets, "org.gnome.Nautilus"), Some(2));
+    }
+
+    #[test]
+    fn applets_for_other_app_ids_are_the_last_resort() {
+        assert_eq!(target(&[(ids(&["firefox"]), 0)], "org.gnome.Nautilus"), Some(0));
+        assert_eq!(target(&[], "firefox"), None);
+    }
+
+    #[test]
+    fn app_ids_are_parsed_from_the_desktop_entry() {
+        assert_eq!(
+            parse_app_ids("firefox;org.gnome.Nautilus;"),
+            ids(&["firefox", "org.gnome.Nautilus"])
+        );
+        assert_eq!(parse_app_ids(" firefox ; ;"), ids(&["firefox"]));
+        assert!(parse_app_ids("").is_empty());
     }
 }
@@ -1,15 +1,41 @@
 use anyhow::{Context, Result};
 use cosmic_notifications_util::PANEL_NOTIFICATIONS_FD;
-use smithay::reexports::rustix::{
-    io::{fcntl_getfd, fcntl_setfd, FdFlags},
-    {self},
+use smithay::reexports::rustix::io::{fcntl_getfd, fcntl_setfd, FdFlags};
+use std::{
+    os::{
+        fd::{FromRawFd, OwnedFd, RawFd},
+        unix::net::UnixStream,
+    },
+    sync::Arc,
+    time::Duration,
 };
-use std::os::{
-    fd::{FromRawFd, RawFd},
-    unix::net::UnixStream,
-};
-use tracing::info;
-use zbus::{connection::Builder, proxy};
+use tokio::{sync::Mutex, time::timeout};
+use tracing::{debug, error, info, warn};
+use zbus::{connection::Builder, proxy, Connection};
+
+/// Interval of the checks of an established connection.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Longest wait between two attempts to connect to the daemon.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The connection to the notifications daemon, shared by the applet loop and
+/// [`keep_connected`].
+#[derive(Default)]
+pub struct NotificationsConnection {
+    proxy: Option<NotificationsSocketProxy<'static>>,
+    /// incremented with each new connection, so applets holding fds of an
+    /// earlier daemon can be told apart
+    pub generation: u64,
+}
+
+pub type SharedNotificationsConnection = Arc<Mutex<NotificationsConnection>>;
+
+impl NotificationsConnection {
+    pub fn set(&mut self, proxy: NotificationsSocketProxy<'static>) {
+        self.proxy = Some(proxy);
+        self.generation += 1;
+    }
+}
 
 #[proxy(
     default_service = "com.system76.NotificationsSocket",
@@ -20,11 +46,36 @@ trait NotificationsSocket {
     /// get an fd for an applet
     fn get_fd(&self) -> zbus::Result<zbus::zvariant::OwnedFd>;
 }
-pub async fn notifications_conn() -> Result<NotificationsSocketProxy<'static>> {
+
+/// Take the socket to the notifications daemon passed by the session, and
+/// clear its variable so applets don't inherit it. Changing the environment
+/// isn't thread safe, so this must be called before other threads start.
+pub fn take_session_socket() -> Option<OwnedFd> {
+    let fd_num = std::env::var(PANEL_NOTIFICATIONS_FD).ok()?;
+    std::env::remove_var(PANEL_NOTIFICATIONS_FD);
+    match fd_num.parse::<RawFd>() {
+        // SAFETY: the session passes the panel an fd it owns
+        Ok(fd) => Some(unsafe { OwnedFd::from_raw_fd(fd) }),
+        Err(err) => {
+            error!("Invalid {PANEL_NOTIFICATIONS_FD} {fd_num:?}: {err}");
+            None
+        },
+    }
+}
+
+/// Connect to the notifications daemon, with the socket passed by the session
+/// if there is one, or on the session bus, where a restarted daemon can be
+/// found.
+pub async fn notifications_conn(
+    session_socket: Option<OwnedFd>,
+) -> Result<NotificationsSocketProxy<'static>> {
     info!("Connecting to notifications daemon");
-    let fd_num = std::env::var(PANEL_NOTIFICATIONS_FD)?;
-    let fd = fd_num.parse::<RawFd>()?;
-    let fd = unsafe { rustix::fd::OwnedFd::from_raw_fd(fd) };
+    let Some(fd) = session_socket else {
+        let conn = Connection::session().await?;
+        let proxy = NotificationsSocketProxy::new(&conn).await?;
+        info!("Connected to notifications on the session bus");
+        return Ok(proxy);
+    };
 
     let res = fcntl_getfd(&fd).and_then(|flags| fcntl_setfd(&fd, FdFlags::CLOEXEC.union(flags)));
 
@@ -46,3 +97,61 @@ pub async fn notifications_conn() -> Result<NotificationsSocketProxy<'static>> {
 
     Ok(proxy)
 }
+
+/// Keep `proxy` connected to the notifications daemon. The connection is
+/// checked periodically, and made again with an exponential backoff when it
+/// is lost. `connected` is called after each new connection, with whether an
+/// earlier connection was lost, so applets holding fds of the old daemon can be
+/// restarted.
+pub async fn keep_connected(conn: SharedNotificationsConnection, connected: impl Fn(bool)) {
+    let mut backoff = Duration::from_secs(1);
+    let mut was_connected = conn.lock().await.proxy.is_some();
+    loop {
+        let alive = match conn.lock().await.proxy.as_ref() {
+            Some(p) => {
+                matches!(timeout(Duration::from_secs(1), p.inner().introspect()).await, Ok(Ok(_)))
+            },
+            None => false,
+        };
+        if alive {
+            backoff = Duration::from_secs(1);
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            continue;
+        }
+        if conn.lock().await.proxy.take().is_some() {
+            warn!("Lost the connection to the notifications daemon");
+        }
+
+        match timeout(Duration::from_secs(1), notifications_conn(None)).await {
+            Ok(Ok(p)) => {
+                conn.lock().await.set(p);
+                connected(was_connected);
+                was_connected = true;
+            },
+            err => {
+                debug!("Failed to connect to the notifications daemon {:?}", err);
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            },
+        }
+    }
+}
+
+/// Get a new fd for a notifications applet, with the generation of the
+/// connection it is from, or `None` without a connection. Each applet gets its
+/// own fd, so there may be one on every panel.
+pub async fn get_fd(conn: &SharedNotificationsConnection) -> Option<(OwnedFd, u64)> {
+    let conn = conn.lock().await;
+    let proxy = conn.proxy.as_ref()?;
+    match timeout(Duration::from_secs(1), proxy.get_fd()).await {
+        Ok(Ok(fd)) => Some((fd.into(), conn.generation)),
+        Ok(Err(err)) => {
+            error!("Failed to get fd for the notifications applet {}", err);
+            None
+        },
+        Err(err) => {
+            error!("Failed to get fd for the notifications applet {}", err);
+            None
+        },
+    }
+}
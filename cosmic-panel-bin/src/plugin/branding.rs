@@ -0,0 +1,65 @@
+//! The logo of the distribution, opening the system details when pressed.
+
+use std::fs;
+
+use cosmic::{
+    theme::Button,
+    widget::{button, icon},
+};
+use tracing::error;
+
+use super::{Element, PanelPlugin, PluginContext, PluginMessage};
+
+const DEFAULT_LOGO: &str = "distributor-logo";
+
+#[derive(Debug, Clone, Copy)]
+struct OpenAbout;
+
+#[derive(Debug)]
+struct Branding {
+    logo: String,
+    icon_size: u16,
+    padding: u16,
+}
+
+pub(super) fn branding_plugin(context: &PluginContext) -> Box<dyn PanelPlugin> {
+    Box::new(Branding {
+        logo: os_release_logo().unwrap_or_else(|| DEFAULT_LOGO.to_string()),
+        icon_size: context.icon_size,
+        padding: context.padding,
+    })
+}
+
+impl PanelPlugin for Branding {
+    fn update(&mut self, message: PluginMessage) {
+        if message.downcast_ref::<OpenAbout>().is_none() {
+            return;
+        }
+        match std::process::Command::new("cosmic-settings").arg("about").spawn() {
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+            },
+            Err(err) => error!("Failed to open the system details: {}", err),
+        }
+    }
+
+    fn view(&self) -> Element<'_> {
+        button::custom(icon::from_name(self.logo.clone()).size(self.icon_size).icon())
+            .padding(self.padding)
+            .class(Button::AppletIcon)
+            .on_press(PluginMessage::new(OpenAbout))
+            .into()
+    }
+}
+
+/// The icon name of the distribution logo, from the `LOGO` key of
+/// os-release(5).
+fn os_release_logo() -> Option<String> {
+    let os_release = fs::read_to_string("/etc/os-release")
+        .or_else(|_| fs::read_to_string("/usr/lib/os-release"))
+        .ok()?;
+    os_release.lines().find_map(|l| {
+        let logo = l.strip_prefix("LOGO=")?.trim_matches(|c| c == '"' || c == '\'');
+        (!logo.is_empty()).then(|| logo.to_string())
+    })
+}
@@ -0,0 +1,102 @@
+//! Panel elements compiled into the panel, enabled with the `plugin-api`
+//! feature.
+//!
+//! A plugin implements [`PanelPlugin`] and is registered with
+//! [`register_plugin`] before the panel starts, either in [`register_plugins`]
+//! or by a binary which runs the panel with [`crate::run`].
+//! It is configured like the builtin applets, by adding
+//! `builtin:plugin:<id>` to one of the applet lists of a panel, and gets the
+//! same layout, theme and pointer handling as them. The rest of the iced
+//! element abstraction is internal to the panel, as it exposes the state of its
+//! event loop.
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use cosmic_panel_config::Side;
+use once_cell::sync::Lazy;
+use tracing::warn;
+
+mod branding;
+
+pub type Element<'a> = crate::iced::Element<'a, PluginMessage>;
+
+/// Creates the plugin for a panel, once for each time it is configured.
+pub type PluginFactory = fn(&PluginContext) -> Box<dyn PanelPlugin>;
+
+static PLUGINS: Lazy<Mutex<HashMap<&'static str, PluginFactory>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Message of a plugin, produced by its view and passed back to
+/// [`PanelPlugin::update`].
+#[derive(Clone)]
+pub struct PluginMessage(Arc<dyn Any + Send + Sync>);
+
+impl PluginMessage {
+    pub fn new<T: Any + Send + Sync>(message: T) -> Self {
+        Self(Arc::new(message))
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+}
+
+impl fmt::Debug for PluginMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PluginMessage").finish_non_exhaustive()
+    }
+}
+
+/// Where a plugin is placed, and the sizes of the applets around it.
+#[derive(Debug, Clone)]
+pub struct PluginContext {
+    pub section: Side,
+    pub is_horizontal: bool,
+    pub icon_size: u16,
+    pub padding: u16,
+}
+
+pub trait PanelPlugin: fmt::Debug + Send {
+    /// Logical length of the plugin along the panel. Defaults to the thickness
+    /// of the panel, and grows to fit the view.
+    fn length(&self, context: &PluginContext) -> Option<i32> {
+        let _ = context;
+        None
+    }
+
+    fn update(&mut self, message: PluginMessage) {
+        let _ = message;
+    }
+
+    /// Called every second, returns whether the view changed.
+    fn tick(&mut self) -> bool {
+        false
+    }
+
+    fn view(&self) -> Element<'_>;
+}
+
+/// Make the plugin `id` available to the panels, replacing any plugin
+/// registered before with the same id.
+pub fn register_plugin(id: &'static str, factory: PluginFactory) {
+    PLUGINS.lock().unwrap().insert(id, factory);
+}
+
+/// Register the plugins compiled into the panel, unless a plugin with the same
+/// id was registered already.
+pub(crate) fn register_plugins() {
+    PLUGINS.lock().unwrap().entry("branding").or_insert(branding::branding_plugin);
+}
+
+pub(crate) fn create_plugin(id: &str, context: &PluginContext) -> Option<Box<dyn PanelPlugin>> {
+    let Some(factory) = PLUGINS.lock().unwrap().get(id).copied() else {
+        warn!("No panel plugin registered as {}", id);
+        return None;
+    };
+    Some(factory(context))
+}
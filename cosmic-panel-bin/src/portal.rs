@@ -0,0 +1,130 @@
+//! Privileges of flatpak applets from the permission store of the desktop
+//! portal.
+//!
+//! Flatpak applets which request the host display only get the privileged
+//! socket of the panel, which also allows e.g. screencopy, if they are granted
+//! the `host-display` permission of the `cosmic-panel` table, e.g. with
+//! `flatpak permission-set cosmic-panel host-display <APP_ID> yes`. Otherwise
+//! they get a restricted socket, regardless of the allowlist of the panel
+//! config. The grants are cached and kept up to date from the changes of the
+//! store, and applets whose grant changed are restarted with their new socket.
+//!
+//! The panel only reads the store and never asks the user for the permission,
+//! since there is no portal dialog for it. It has to be set with `flatpak
+//! permission-set` or by a settings app.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::PanelCalloopMsg;
+use cosmic::iced::futures::StreamExt;
+use smithay::reexports::calloop;
+use zbus::{
+    proxy,
+    zvariant::{OwnedValue, Value},
+    Connection,
+};
+
+const TABLE: &str = "cosmic-panel";
+const HOST_DISPLAY: &str = "host-display";
+
+#[proxy(
+    default_service = "org.freedesktop.impl.portal.PermissionStore",
+    interface = "org.freedesktop.impl.portal.PermissionStore",
+    default_path = "/org/freedesktop/impl/portal/PermissionStore"
+)]
+trait PermissionStore {
+    fn lookup(
+        &self,
+        table: &str,
+        id: &str,
+    ) -> zbus::Result<(HashMap<String, Vec<String>>, OwnedValue)>;
+
+    #[zbus(signal)]
+    fn changed(
+        &self,
+        table: &str,
+        id: &str,
+        deleted: bool,
+        data: Value<'_>,
+        permissions: HashMap<String, Vec<String>>,
+    ) -> zbus::Result<()>;
+}
+
+/// Whether flatpak applets may use the privileged socket, by app id.
+pub type PortalGrants = Arc<Mutex<HashMap<String, bool>>>;
+
+/// Whether the flatpak applet `app_id` may use the privileged socket.
+pub fn granted(grants: &PortalGrants, app_id: &str) -> bool {
+    grants.lock().unwrap().get(app_id).copied().unwrap_or_default()
+}
+
+fn grants(permissions: HashMap<String, Vec<String>>) -> HashMap<String, bool> {
+    permissions.into_iter().map(|(app_id, p)| (app_id, p.iter().any(|p| p == "yes"))).collect()
+}
+
+/// Forward the grants of the permission store to the panel, and their changes
+/// until the connection to the store is lost.
+pub async fn watch(tx: calloop::channel::Sender<PanelCalloopMsg>) -> zbus::Result<()> {
+    let conn = Connection::session().await?;
+    let store = PermissionStoreProxy::new(&conn).await?;
+    let mut changed = store.receive_changed().await?;
+
+    // the table doesn't exist until a permission of it is set
+    let permissions = store.lookup(TABLE, HOST_DISPLAY).await.map(|(p, _)| p).unwrap_or_default();
+    if tx.send(PanelCalloopMsg::PortalGrants(grants(permissions))).is_err() {
+        return Ok(());
+    }
+    while let Some(signal) = changed.next().await {
+        let args = signal.args()?;
+        if args.table != TABLE || args.id != HOST_DISPLAY {
+            continue;
+        }
+        let permissions = if args.deleted { HashMap::new() } else { args.permissions };
+        if tx.send(PanelCalloopMsg::PortalGrants(grants(permissions))).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permissions(entries: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        entries
+            .iter()
+            .map(|(app_id, p)| (app_id.to_string(), p.iter().map(|p| p.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn only_yes_grants_the_privileged_socket() {
+        let grants = grants(permissions(&[
+            ("org.example.Yes", &["yes"]),
+            ("org.example.No", &["no"]),
+            ("org.example.Empty", &[]),
+            ("org.example.Other", &["ask", "yes"]),
+        ]));
+        assert_eq!(
+            grants,
+            HashMap::from([
+                ("org.example.Yes".to_string(), true),
+                ("org.example.No".to_string(), false),
+                ("org.example.Empty".to_string(), false),
+                ("org.example.Other".to_string(), true),
+            ])
+        );
+    }
+
+    #[test]
+    fn unknown_applets_are_not_granted() {
+        let grants: PortalGrants =
+            Arc::new(Mutex::new(grants(permissions(&[("org.example.Yes", &["yes"])]))));
+        assert!(granted(&grants, "org.example.Yes"));
+        assert!(!granted(&grants, "org.example.Unknown"));
+    }
+}
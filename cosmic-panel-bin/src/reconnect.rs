@@ -0,0 +1,63 @@
+//! Restart after the connection to the compositor was lost.
+//!
+//! All of the client state refers to objects of the old connection, and the
+//! spaces of the panel hold its outputs and layer surfaces, so the panel
+//! doesn't rebuild them in place. It waits until the compositor accepts
+//! connections again, e.g. after it restarted, and replaces itself with a new
+//! panel, which binds the globals and creates its surfaces anew. Applets are
+//! connected to the embedded server of the panel, which doesn't outlive it, so
+//! they are started again by the new panel.
+
+use std::{
+    env,
+    os::unix::{net::UnixStream, process::CommandExt},
+    path::PathBuf,
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use tracing::info;
+
+/// How long to wait for the compositor to come back.
+const MAX_WAIT: Duration = Duration::from_secs(30);
+const RETRY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The path of the socket of the compositor, or `None` if the panel was passed
+/// a connected socket instead.
+fn socket_path() -> Option<PathBuf> {
+    if env::var_os("WAYLAND_SOCKET").is_some() {
+        return None;
+    }
+    let display = PathBuf::from(env::var_os("WAYLAND_DISPLAY").unwrap_or("wayland-0".into()));
+    if display.is_absolute() {
+        return Some(display);
+    }
+    Some(PathBuf::from(env::var_os("XDG_RUNTIME_DIR")?).join(display))
+}
+
+/// Wait for the compositor to accept connections again, and restart the panel
+/// with the same arguments.
+///
+/// Only returns if the panel can't be restarted.
+pub fn restart() -> anyhow::Error {
+    let Some(path) = socket_path() else {
+        return anyhow!("The panel was started with a socket, and can't reconnect");
+    };
+    info!("Waiting for the compositor at {}", path.display());
+    let start = Instant::now();
+    while UnixStream::connect(&path).is_err() {
+        if start.elapsed() > MAX_WAIT {
+            return anyhow!("The compositor didn't come back within {MAX_WAIT:?}");
+        }
+        std::thread::sleep(RETRY_INTERVAL);
+    }
+
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(err) => return anyhow!("Failed to find the panel executable: {err}"),
+    };
+    info!("Compositor is back, restarting the panel");
+    let err = Command::new(exe).args(env::args_os().skip(1)).exec();
+    anyhow!("Failed to restart the panel: {err}")
+}
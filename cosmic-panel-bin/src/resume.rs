@@ -0,0 +1,39 @@
+//! Resume from suspend.
+//!
+//! Theme changes can be missed while the system is suspended, e.g. when the
+//! automatic light and dark switching happened in between, so the theme is
+//! reapplied to all panels on resume.
+
+use crate::PanelCalloopMsg;
+use cosmic::iced::futures::StreamExt;
+use smithay::reexports::calloop;
+use zbus::{proxy, Connection};
+
+#[proxy(
+    default_service = "org.freedesktop.login1",
+    interface = "org.freedesktop.login1.Manager",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    /// emitted with `start` before suspending, and without it after resuming
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+/// Ask the panel to reapply the theme after every resume, until the
+/// connection to logind is lost.
+pub async fn watch(tx: calloop::channel::Sender<PanelCalloopMsg>) -> zbus::Result<()> {
+    let conn = Connection::system().await?;
+    let manager = ManagerProxy::new(&conn).await?;
+    let mut prepare_for_sleep = manager.receive_prepare_for_sleep().await?;
+
+    while let Some(signal) = prepare_for_sleep.next().await {
+        if signal.args()?.start {
+            continue;
+        }
+        if tx.send(PanelCalloopMsg::ReapplyTheme).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! Host sockets of applets which use the display of the compositor.
+//!
+//! Applets which request the host display with `X-HostWaylandDisplay` get a
+//! socket to the compositor with a security context, passed in
+//! `X_PRIVILEGED_WAYLAND_SOCKET` where applets look for it. Only the applets in
+//! the `privileged_applets` allowlist of the panel config get the context of the
+//! panel, which the compositor grants privileged protocols, and
+//! `WAYLAND_DISPLAY`. The others get a restricted context instead.
+//!
+//! The default schema allows the stock applets. Setups from before the
+//! allowlist get one of their own, with the configured applets which request
+//! the host display, so they keep working. Without any allowlist, e.g. with a
+//! greeter installed without the schema, every applet is restricted.
+
+use std::{collections::HashSet, ffi::OsStr, fs, os::unix::net::UnixStream};
+
+use cctk::wayland_client::QueueHandle;
+use cosmic_panel_config::{CosmicPanelContainerConfig, NAME};
+use freedesktop_desktop_entry::{DesktopEntry, Iter};
+use tracing::{error, info};
+use wayland_protocols::wp::security_context::v1::client::wp_security_context_v1::WpSecurityContextV1;
+
+use crate::{
+    space_container::SpaceContainer,
+    xdg_shell_wrapper::{
+        shared_state::GlobalState,
+        wp_security_context::{SecurityContext, SecurityContextManager},
+    },
+};
+
+/// Sandbox engine of applets without privileged access. Unlike the engine of
+/// the panel itself, the compositor doesn't grant it privileged protocols.
+const RESTRICTED_SANDBOX_ENGINE: &str = "com.system76.CosmicPanel.Applet";
+
+/// Create a socket to the compositor for the applet `name`, with a privileged
+/// security context if `privileged`.
+pub fn host_socket(
+    manager: &SecurityContextManager,
+    qh: &QueueHandle<GlobalState>,
+    name: &str,
+    privileged: bool,
+) -> Option<(WpSecurityContextV1, UnixStream)> {
+    let security_context = match manager.create_listener::<SpaceContainer>(qh) {
+        Ok(security_context) => security_context,
+        Err(why) => {
+            error!(?why, "Failed to create a listener");
+            return None;
+        },
+    };
+    if privileged {
+        security_context.set_sandbox_engine(NAME.to_string());
+    } else {
+        security_context.set_sandbox_engine(RESTRICTED_SANDBOX_ENGINE.to_string());
+        security_context.set_app_id(name.to_string());
+    }
+    security_context.commit();
+
+    let socket = security_context.data::<SecurityContext>()?.conn.lock().unwrap().take()?;
+    Some((security_context, socket))
+}
+
+/// Create the allowlist of privileged applets if the user has none, from the
+/// default allowlist and the applets of `config` which request the host
+/// display.
+pub fn migrate_privileged_applets(config: &CosmicPanelContainerConfig) {
+    if CosmicPanelContainerConfig::user_privileged_applets().is_some() {
+        return;
+    }
+    let configured: HashSet<String> = config
+        .config_list
+        .iter()
+        .flat_map(|c| {
+            let outputs = c.output_plugins.iter().map(|o| Some(o.output.as_str()));
+            std::iter::once(None).chain(outputs).flat_map(|output| {
+                [c.plugins_left_on(output), c.plugins_center_on(output), c.plugins_right_on(output)]
+            })
+        })
+        .flatten()
+        .flatten()
+        .collect();
+
+    let locales = freedesktop_desktop_entry::get_languages_from_env();
+    let default = CosmicPanelContainerConfig::privileged_applets().unwrap_or_default();
+    let mut missing = Vec::new();
+    for path in Iter::new(freedesktop_desktop_entry::default_paths()) {
+        let Some(name) = path.file_stem().and_then(OsStr::to_str) else {
+            continue;
+        };
+        if !configured.contains(name) || default.iter().chain(&missing).any(|p| p == name) {
+            continue;
+        }
+        let Ok(bytes) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let Ok(entry) = DesktopEntry::from_str(&path, &bytes, &locales) else {
+            continue;
+        };
+        if entry.desktop_entry("X-HostWaylandDisplay").is_some() {
+            missing.push(name.to_string());
+        }
+    }
+    // the default allowlist is kept following the schema, if it has every
+    // configured applet which requests the host display
+    if missing.is_empty() {
+        return;
+    }
+    let mut privileged = default;
+    privileged.extend(missing);
+    privileged.sort();
+    privileged.dedup();
+
+    info!("Allowing the privileged protocols for the configured applets {privileged:?}");
+    if let Err(err) = CosmicPanelContainerConfig::set_privileged_applets(privileged) {
+        error!("Failed to save the privileged applets: {err:?}");
+    }
+}
@@ -0,0 +1,110 @@
+//! Session lock awareness.
+//!
+//! Clients other than the locker can't observe ext-session-lock, so the lock
+//! state of the session is taken from logind. While the session is locked the
+//! panels are moved to the background layer without keyboard interactivity,
+//! their popups are closed and no input is forwarded to applets. This keeps
+//! panels from being drawn over the lock screen on compositors which don't
+//! hide layer surfaces while locked.
+
+use crate::{
+    xdg_shell_wrapper::{shared_state::GlobalState, space::InputRouter},
+    PanelCalloopMsg,
+};
+use cosmic::iced::futures::StreamExt;
+use smithay::{input::pointer::MotionEvent, reexports::calloop, utils::SERIAL_COUNTER};
+use tracing::{info, warn};
+use zbus::{proxy, Connection};
+
+#[proxy(
+    default_service = "org.freedesktop.login1",
+    interface = "org.freedesktop.login1.Session",
+    default_path = "/org/freedesktop/login1/session/auto"
+)]
+trait Session {
+    /// emitted when the session should be locked
+    #[zbus(signal)]
+    fn lock(&self) -> zbus::Result<()>;
+
+    /// emitted when the session should be unlocked
+    #[zbus(signal)]
+    fn unlock(&self) -> zbus::Result<()>;
+
+    /// set by the locker while the lock screen is shown
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+}
+
+/// Forward changes of the lock state of the session to the panel until the
+/// connection to logind is lost.
+pub async fn watch(tx: calloop::channel::Sender<PanelCalloopMsg>) -> zbus::Result<()> {
+    let conn = Connection::system().await?;
+    let session = SessionProxy::new(&conn).await?;
+    let mut lock = session.receive_lock().await?;
+    let mut unlock = session.receive_unlock().await?;
+    let mut locked_hint = session.receive_locked_hint_changed().await;
+
+    if session.locked_hint().await.unwrap_or_default() {
+        _ = tx.send(PanelCalloopMsg::SessionLocked(true));
+    }
+
+    loop {
+        let locked = tokio::select! {
+            Some(_) = lock.next() => true,
+            Some(_) = unlock.next() => false,
+            Some(changed) = locked_hint.next() => match changed.get().await {
+                Ok(locked) => locked,
+                Err(err) => {
+                    warn!("Failed to read the locked hint of the session {:?}", err);
+                    continue;
+                },
+            },
+            else => return Ok(()),
+        };
+        if tx.send(PanelCalloopMsg::SessionLocked(locked)).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Hide the panels and stop forwarding input while `locked`, and restore them
+/// when the session is unlocked.
+pub fn set_locked(state: &mut GlobalState, locked: bool) {
+    if state.space.session_locked == locked {
+        return;
+    }
+    info!("Session {}", if locked { "locked" } else { "unlocked" });
+    state.space.session_locked = locked;
+    state.space.apply_suppression();
+    if !locked {
+        return;
+    }
+
+    // drop all focus, so nothing stays revealed or keeps routing input to an
+    // applet behind the lock screen. Held keys and buttons are released by the
+    // input watchdog once their surfaces lost the focus.
+    state.client_state.focused_surface.borrow_mut().clear();
+    state.client_state.hovered_surface.borrow_mut().clear();
+    let time = state.start_time.elapsed().as_millis().try_into().unwrap_or_default();
+    let seats: Vec<_> =
+        state.server_state.seats.iter().map(|s| (s.name.clone(), s.server.seat.clone())).collect();
+    for (name, seat) in seats {
+        state.space.keyboard_leave(&name, None);
+        state.space.pointer_leave(&name, None);
+        if let Some(kbd) = seat.get_keyboard() {
+            kbd.set_focus(state, None, SERIAL_COUNTER.next_serial());
+        }
+        if let Some(ptr) = seat.get_pointer() {
+            ptr.motion(
+                state,
+                None,
+                &MotionEvent {
+                    location: (0.0, 0.0).into(),
+                    serial: SERIAL_COUNTER.next_serial(),
+                    time,
+                },
+            );
+            ptr.frame(state);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Applets of all panels by their client, shared by the panels and the exit
+//! handlers of the applets.
+//!
+//! Finding the panel or section of a client would otherwise lock and scan the
+//! applet lists of every panel, which happens for each surface commit and
+//! pointer event of an applet. Applets are added when a panel spawns them,
+//! their client is replaced when they restart, and they are removed with their
+//! panel. An applet keeps the list of its section, which identifies its panel
+//! even if the config of the panel changes.
+//!
+//! The registry is an immutable snapshot, which writers copy, change and swap.
+//! Readers load it without a lock, so pointer handling reads the names and
+//! hover settings of applets without waiting for an applet list which a
+//! restarting applet holds.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use arc_swap::ArcSwap;
+use cosmic_panel_config::Side;
+use smithay::reexports::wayland_server::backend::ClientId;
+
+use super::panel_space::{AppletAutoClickAnchor, Clients, PanelClient};
+
+#[derive(Debug, Clone)]
+pub struct AppletHandle {
+    /// the applets of the section of the applet in its panel
+    pub clients: Clients,
+    pub side: Side,
+    pub name: String,
+    pub display_name: Option<String>,
+    pub auto_popup_hover_press: Option<AppletAutoClickAnchor>,
+    pub hover_popup_delay: Option<Duration>,
+    pub no_generated_clicks: bool,
+}
+
+impl AppletHandle {
+    pub fn new(c: &PanelClient, clients: Clients, side: Side) -> Self {
+        Self {
+            clients,
+            side,
+            name: c.name.clone(),
+            display_name: c.display_name.clone(),
+            auto_popup_hover_press: c.auto_popup_hover_press,
+            hover_popup_delay: c.hover_popup_delay,
+            no_generated_clicks: c.no_generated_clicks,
+        }
+    }
+}
+
+pub type AppletSnapshot = Arc<HashMap<ClientId, AppletHandle>>;
+
+#[derive(Debug, Default, Clone)]
+pub struct AppletRegistry(Arc<ArcSwap<HashMap<ClientId, AppletHandle>>>);
+
+impl AppletRegistry {
+    /// Change a copy of the current snapshot, and swap it in. `f` runs again
+    /// on the new snapshot if another writer swapped it in the meantime.
+    fn update(&self, f: impl Fn(&mut HashMap<ClientId, AppletHandle>)) {
+        // snapshots held by readers are left as they are
+        self.0.rcu(|applets| {
+            let mut applets = HashMap::clone(applets);
+            f(&mut applets);
+            applets
+        });
+    }
+
+    pub fn insert(&self, client: ClientId, applet: AppletHandle) {
+        self.update(|applets| {
+            applets.insert(client.clone(), applet.clone());
+        });
+    }
+
+    /// Move the applet of the client `old` to the restarted client `new`, if
+    /// its panel still exists.
+    pub fn replace(&self, old: &ClientId, new: ClientId) {
+        self.update(|applets| {
+            if let Some(applet) = applets.remove(old) {
+                applets.insert(new.clone(), applet);
+            }
+        });
+    }
+
+    pub fn remove(&self, clients: impl IntoIterator<Item = ClientId>) {
+        let clients: Vec<_> = clients.into_iter().collect();
+        self.update(|applets| {
+            for client in &clients {
+                applets.remove(client);
+            }
+        });
+    }
+
+    /// The current applets, which don't change while they are held.
+    pub fn snapshot(&self) -> AppletSnapshot {
+        self.0.load_full()
+    }
+
+    pub fn get(&self, client: &ClientId) -> Option<AppletHandle> {
+        self.0.load().get(client).cloned()
+    }
+}
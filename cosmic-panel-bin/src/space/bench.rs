@@ -0,0 +1,399 @@
+//! A panel with in-process applets, for benchmarking its layout without a
+//! compositor.
+//!
+//! Every applet is a wayland client of an embedded display, which maps a
+//! toplevel with a shm buffer of its size, so the panel places the windows of
+//! the applets like those of real applets. The panel has neither an output
+//! nor a layer surface, so a layout stops once the applets are placed, before
+//! the background and input region of the panel are updated.
+
+use std::{
+    cell::RefCell,
+    io,
+    os::{fd::AsFd, unix::net::UnixStream},
+    rc::Rc,
+};
+
+use anyhow::Context;
+use cosmic_panel_config::{CosmicPanelConfig, PanelAnchor, Side};
+use sctk::reexports::client::{
+    backend::WaylandError,
+    delegate_noop,
+    protocol::{wl_buffer, wl_compositor, wl_registry, wl_shm, wl_shm_pool, wl_surface},
+    Connection, Dispatch, EventQueue, QueueHandle,
+};
+use smithay::{
+    backend::renderer::utils::on_commit_buffer_handler,
+    delegate_compositor, delegate_shm, delegate_xdg_shell,
+    desktop::Window,
+    reexports::wayland_server::{
+        protocol::{wl_buffer::WlBuffer, wl_seat, wl_surface::WlSurface},
+        Client, Display, DisplayHandle,
+    },
+    utils::Serial,
+    wayland::{
+        buffer::BufferHandler,
+        compositor::{CompositorClientState, CompositorHandler, CompositorState},
+        shell::xdg::{
+            PopupSurface, PositionerState, ToplevelSurface, XdgShellHandler, XdgShellState,
+        },
+        shm::{ShmHandler, ShmState},
+    },
+};
+use wayland_protocols::xdg::shell::client::{xdg_surface, xdg_toplevel, xdg_wm_base};
+
+use super::{panel_space::PanelClient, AppletHandle, PanelSpace};
+use crate::{
+    applet_channel,
+    xdg_shell_wrapper::{
+        client_state::WrapperClientCompositorState,
+        shared_state::GlobalState,
+        space::{ClientHost, Visibility},
+        util::get_client_sock,
+    },
+    PanelCalloopMsg,
+};
+
+/// Thickness of the applets, which is that of a medium panel.
+const APPLET_THICKNESS: i32 = 40;
+
+/// An applet of a benchmarked panel.
+#[derive(Debug, Clone, Copy)]
+pub struct BenchApplet {
+    /// logical length of the applet along the panel
+    pub length: i32,
+    /// priority of the applet when its section overflows
+    pub shrink_priority: Option<u32>,
+}
+
+/// A panel along the top edge with in-process applets, split evenly over its
+/// sections. See the [module docs](self).
+pub struct LayoutBench {
+    display: Display<BenchServer>,
+    server: BenchServer,
+    applets: Vec<AppletClient>,
+    // kept for the panel, which holds their handles
+    _host: UnixStream,
+    _event_loop: calloop::EventLoop<'static, GlobalState>,
+    _panel_rx: calloop::channel::Channel<PanelCalloopMsg>,
+    _applet_rx: applet_channel::AppletReceiver,
+}
+
+impl std::fmt::Debug for LayoutBench {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LayoutBench").field("applets", &self.applets.len()).finish_non_exhaustive()
+    }
+}
+
+impl LayoutBench {
+    /// A panel of `length` with `applets`, or a dock if `expand_to_edges` is
+    /// false. Returns once every applet is mapped and laid out.
+    pub fn new(
+        length: i32,
+        expand_to_edges: bool,
+        applets: &[BenchApplet],
+    ) -> anyhow::Result<Self> {
+        let display = Display::<BenchServer>::new()?;
+        let mut dh = display.handle();
+        let names: Vec<String> = (0..applets.len()).map(|i| format!("bench-applet-{i}")).collect();
+        let third = applets.len() / 3;
+        let config = CosmicPanelConfig {
+            name: "bench".to_string(),
+            anchor: PanelAnchor::Top,
+            expand_to_edges,
+            plugins_wings: Some((names[..third].to_vec(), names[2 * third..].to_vec())),
+            plugins_center: Some(names[third..2 * third].to_vec()),
+            ..Default::default()
+        };
+
+        // the host connection of the panel is never used by the layout
+        let (host, host_client) = UnixStream::pair()?;
+        let host_conn = Connection::from_socket(host_client)?;
+        let event_loop = calloop::EventLoop::try_new()?;
+        let (applet_tx, applet_rx) = applet_channel::channel();
+        let (panel_tx, panel_rx) = calloop::channel::channel();
+        let mut space = PanelSpace::new(
+            config,
+            Rc::new(RefCell::new(Vec::new())),
+            Rc::new(RefCell::new(Vec::new())),
+            applet_tx,
+            cosmic::Theme::dark(),
+            dh.clone(),
+            None,
+            &host_conn,
+            panel_tx,
+            Visibility::Visible,
+            event_loop.handle(),
+        );
+        space.suggested_length = Some(length as u32);
+        space.dimensions = (length, APPLET_THICKNESS).into();
+
+        let mut clients = Vec::with_capacity(applets.len());
+        for (i, (applet, name)) in applets.iter().zip(names).enumerate() {
+            let (client, stream) = get_client_sock(&mut dh);
+            let mut panel_client = PanelClient::new(name, client.clone(), None);
+            panel_client.shrink_priority = applet.shrink_priority;
+            let side = match i {
+                i if i < third => Side::WingStart,
+                i if i < 2 * third => Side::Center,
+                _ => Side::WingEnd,
+            };
+            let list = space.section_clients(side).clone();
+            space.applets.insert(client.id(), AppletHandle::new(&panel_client, list.clone(), side));
+            list.lock().unwrap().push(panel_client);
+            clients.push(AppletClient::new(stream, (applet.length, APPLET_THICKNESS))?);
+        }
+
+        let server = BenchServer {
+            compositor_state: CompositorState::new::<BenchServer>(&dh),
+            xdg_shell_state: XdgShellState::new::<BenchServer>(&dh),
+            shm_state: ShmState::new::<BenchServer>(&dh, vec![]),
+            display_handle: dh,
+            space,
+        };
+        let mut bench = Self {
+            display,
+            server,
+            applets: clients,
+            _host: host,
+            _event_loop: event_loop,
+            _panel_rx: panel_rx,
+            _applet_rx: applet_rx,
+        };
+
+        // binding the globals, the initial commit, its configure and the commit
+        // of the buffer each take a round trip
+        for _ in 0..4 {
+            bench.roundtrip()?;
+        }
+        let mapped = bench.server.space.space.elements().count();
+        anyhow::ensure!(mapped == applets.len(), "{mapped} of {} applets mapped", applets.len());
+        // the first layout resizes the panel to the thickness of the applets
+        for _ in 0..2 {
+            bench.layout();
+            if let Some(dimensions) = bench.server.space.pending_dimensions.take() {
+                bench.server.space.dimensions = dimensions;
+            }
+        }
+        Ok(bench)
+    }
+
+    /// Lay out every applet of the panel again, as after a change of the
+    /// panel.
+    pub fn layout(&mut self) {
+        let space = &mut self.server.space;
+        space.layout_cache.invalidate();
+        // the layout ends at the missing output
+        let _ = space.layout_();
+    }
+
+    /// Send the requests of every applet, handle them, and handle the events
+    /// sent back.
+    fn roundtrip(&mut self) -> anyhow::Result<()> {
+        for applet in &mut self.applets {
+            applet.flush()?;
+        }
+        self.display.dispatch_clients(&mut self.server)?;
+        self.display.flush_clients()?;
+        for applet in &mut self.applets {
+            applet.dispatch()?;
+        }
+        Ok(())
+    }
+}
+
+/// The embedded display of the benchmark, which maps the toplevels of its
+/// clients in the panel.
+struct BenchServer {
+    compositor_state: CompositorState,
+    xdg_shell_state: XdgShellState,
+    shm_state: ShmState,
+    display_handle: DisplayHandle,
+    space: PanelSpace,
+}
+
+impl CompositorHandler for BenchServer {
+    fn compositor_state(&mut self) -> &mut CompositorState {
+        &mut self.compositor_state
+    }
+
+    fn commit(&mut self, surface: &WlSurface) {
+        on_commit_buffer_handler::<BenchServer>(surface);
+        let dh = self.display_handle.clone();
+        self.space.dirty_window(&dh, surface);
+    }
+
+    fn client_compositor_state<'a>(&self, client: &'a Client) -> &'a CompositorClientState {
+        &client.get_data::<WrapperClientCompositorState>().unwrap().compositor_state
+    }
+}
+
+impl BufferHandler for BenchServer {
+    fn buffer_destroyed(&mut self, _buffer: &WlBuffer) {}
+}
+
+impl ShmHandler for BenchServer {
+    fn shm_state(&self) -> &ShmState {
+        &self.shm_state
+    }
+}
+
+impl XdgShellHandler for BenchServer {
+    fn xdg_shell_state(&mut self) -> &mut XdgShellState {
+        &mut self.xdg_shell_state
+    }
+
+    fn new_toplevel(&mut self, surface: ToplevelSurface) {
+        self.space.add_window(Window::new_wayland_window(surface.clone()));
+        surface.send_configure();
+    }
+
+    fn new_popup(&mut self, _surface: PopupSurface, _positioner: PositionerState) {}
+
+    fn grab(&mut self, _surface: PopupSurface, _seat: wl_seat::WlSeat, _serial: Serial) {}
+
+    fn reposition_request(
+        &mut self,
+        _surface: PopupSurface,
+        _positioner: PositionerState,
+        _token: u32,
+    ) {
+    }
+}
+
+delegate_compositor!(BenchServer);
+delegate_shm!(BenchServer);
+delegate_xdg_shell!(BenchServer);
+
+/// The client side of an applet, which maps a toplevel with a buffer of its
+/// size once the globals it needs are bound.
+struct AppletClient {
+    conn: Connection,
+    queue: EventQueue<AppletState>,
+    state: AppletState,
+}
+
+#[derive(Debug)]
+struct AppletState {
+    size: (i32, i32),
+    compositor: Option<wl_compositor::WlCompositor>,
+    shm: Option<wl_shm::WlShm>,
+    wm_base: Option<xdg_wm_base::XdgWmBase>,
+    toplevel: Option<(wl_surface::WlSurface, xdg_toplevel::XdgToplevel, wl_buffer::WlBuffer)>,
+}
+
+impl AppletClient {
+    fn new(stream: UnixStream, size: (i32, i32)) -> anyhow::Result<Self> {
+        stream.set_nonblocking(true)?;
+        let conn = Connection::from_socket(stream)?;
+        let queue = conn.new_event_queue();
+        conn.display().get_registry(&queue.handle(), ());
+        let state =
+            AppletState { size, compositor: None, shm: None, wm_base: None, toplevel: None };
+        Ok(Self { conn, queue, state })
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.conn.flush().context("Failed to flush applet connection")
+    }
+
+    /// Handle the events sent to the applet, and map its toplevel once it
+    /// can.
+    fn dispatch(&mut self) -> anyhow::Result<()> {
+        if let Some(guard) = self.queue.prepare_read() {
+            match guard.read() {
+                Ok(_) => {},
+                Err(WaylandError::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => {},
+                Err(err) => return Err(err.into()),
+            }
+        }
+        self.queue.dispatch_pending(&mut self.state)?;
+
+        let AppletState { size: (w, h), compositor, shm, wm_base, toplevel } = &mut self.state;
+        if let (None, Some(compositor), Some(shm), Some(wm_base)) =
+            (toplevel.as_ref(), compositor, shm, wm_base)
+        {
+            let qh = self.queue.handle();
+            let surface = compositor.create_surface(&qh, ());
+            let xdg_surface = wm_base.get_xdg_surface(&surface, &qh, ());
+            let xdg_toplevel = xdg_surface.get_toplevel(&qh, ());
+            surface.commit();
+
+            let stride = *w * 4;
+            let file = tempfile::tempfile()?;
+            file.set_len((stride * *h) as u64)?;
+            let pool = shm.create_pool(file.as_fd(), stride * *h, &qh, ());
+            let buffer = pool.create_buffer(0, *w, *h, stride, wl_shm::Format::Argb8888, &qh, ());
+            pool.destroy();
+            *toplevel = Some((surface, xdg_toplevel, buffer));
+        }
+        Ok(())
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for AppletState {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        let wl_registry::Event::Global { name, interface, version } = event else {
+            return;
+        };
+        match interface.as_str() {
+            "wl_compositor" => {
+                state.compositor = Some(registry.bind(name, version.min(5), qh, ()));
+            },
+            "wl_shm" => state.shm = Some(registry.bind(name, 1, qh, ())),
+            "xdg_wm_base" => state.wm_base = Some(registry.bind(name, 1, qh, ())),
+            _ => {},
+        }
+    }
+}
+
+impl Dispatch<xdg_wm_base::XdgWmBase, ()> for AppletState {
+    fn event(
+        _: &mut Self,
+        wm_base: &xdg_wm_base::XdgWmBase,
+        event: xdg_wm_base::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            wm_base.pong(serial);
+        }
+    }
+}
+
+impl Dispatch<xdg_surface::XdgSurface, ()> for AppletState {
+    fn event(
+        state: &mut Self,
+        xdg_surface: &xdg_surface::XdgSurface,
+        event: xdg_surface::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let xdg_surface::Event::Configure { serial } = event else {
+            return;
+        };
+        xdg_surface.ack_configure(serial);
+        if let Some((surface, _, buffer)) = state.toplevel.as_ref() {
+            let (w, h) = state.size;
+            surface.attach(Some(buffer), 0, 0);
+            surface.damage_buffer(0, 0, w, h);
+            surface.commit();
+        }
+    }
+}
+
+delegate_noop!(AppletState: wl_compositor::WlCompositor);
+delegate_noop!(AppletState: wl_shm_pool::WlShmPool);
+delegate_noop!(AppletState: ignore wl_shm::WlShm);
+delegate_noop!(AppletState: ignore wl_buffer::WlBuffer);
+delegate_noop!(AppletState: ignore wl_surface::WlSurface);
+delegate_noop!(AppletState: ignore xdg_toplevel::XdgToplevel);
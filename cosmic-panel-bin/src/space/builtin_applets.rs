@@ -0,0 +1,90 @@
+//! Applets rendered by the panel itself, configured with the `builtin:` prefix
+//! in the applet lists. They are laid out like the windows of applet clients,
+//! but are never shrunk or moved to an overflow popup.
+
+use cosmic_panel_config::Side;
+use smithay::{desktop::space::SpaceElement, utils::Rectangle};
+
+use crate::iced::elements::{
+    builtin_applet::{builtin_applet_element, BuiltinKind, Message},
+    CosmicMappedInternal,
+};
+
+use super::PanelSpace;
+
+impl PanelSpace {
+    /// Map an element for each builtin applet of the config. The elements are
+    /// kept while the config has the same builtin applets at the same size, and
+    /// are created again when it changes.
+    pub(super) fn map_builtin_applets(&mut self) {
+        let [left, center, right] = self.section_plugins(&self.config);
+        let configured: Vec<_> =
+            [(Side::WingStart, left), (Side::Center, center), (Side::WingEnd, right)]
+                .into_iter()
+                .flat_map(|(side, list)| {
+                    list.unwrap_or_default().into_iter().filter_map(move |name| {
+                        BuiltinKind::parse(&name).map(|kind| (side, name, kind))
+                    })
+                })
+                .collect();
+        let mut mapped: Vec<_> = self
+            .space
+            .elements()
+            .filter_map(|e| match e {
+                CosmicMappedInternal::Builtin(b) => Some(b.clone()),
+                _ => None,
+            })
+            .collect();
+
+        let unchanged = configured.len() == mapped.len()
+            && configured.iter().all(|(side, name, _)| {
+                let i = mapped
+                    .iter()
+                    .position(|b| b.with_program(|p| p.is_configured(name, *side, &self.config)));
+                i.map(|i| mapped.swap_remove(i)).is_some()
+            });
+        if unchanged {
+            return;
+        }
+        for e in self.space.elements().cloned().collect::<Vec<_>>() {
+            if matches!(e, CosmicMappedInternal::Builtin(_)) {
+                self.space.unmap_elem(&e);
+            }
+        }
+
+        for (side, name, kind) in configured {
+            let e = builtin_applet_element(
+                name,
+                kind,
+                side,
+                &self.config,
+                self.loop_handle.clone(),
+                self.colors.theme.clone(),
+                self.space.id(),
+            );
+            if let Some(output) = self.output.as_ref() {
+                e.output_enter(&output.1, Rectangle::default());
+            }
+            self.space.map_element(CosmicMappedInternal::Builtin(e), (0, 0), false);
+        }
+        self.layout_cache.invalidate();
+    }
+
+    /// Refresh the clocks, CPU meters and plugins of the panel, redrawing it if
+    /// any of them changed.
+    pub fn tick_builtin_applets(&mut self) {
+        for e in self.space.elements() {
+            let CosmicMappedInternal::Builtin(b) = e else {
+                continue;
+            };
+            if !b.with_program(|p| p.kind.ticks()) {
+                continue;
+            }
+            let prev = b.with_program(|p| p.revision());
+            b.queue_message(Message::Tick);
+            if b.with_program(|p| p.revision()) != prev {
+                self.is_dirty = true;
+            }
+        }
+    }
+}
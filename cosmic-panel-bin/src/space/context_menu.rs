@@ -0,0 +1,348 @@
+//! Context menu of the panel for applet slots, opened by a right click on an
+//! applet which doesn't open a popup of its own in response.
+//!
+//! The menu is a popup of the panel surface rendering an iced element. Its
+//! entries write the config of the panel directly, like edit mode.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use calloop::timer::{TimeoutAction, Timer};
+use cctk::{
+    sctk::shell::xdg::{popup, XdgPositioner},
+    wayland_client::{protocol::wl_seat::WlSeat, QueueHandle},
+};
+use cosmic_panel_config::{PanelAnchor, Side};
+use sctk::{
+    seat::pointer::{PointerEvent, PointerEventKind, BTN_LEFT},
+    shell::WaylandSurface,
+};
+use smithay::{
+    backend::renderer::damage::OutputDamageTracker,
+    desktop::space::SpaceElement,
+    reexports::wayland_server::Resource,
+    utils::{Logical, Point, Rectangle},
+};
+use wayland_protocols::xdg::shell::client::xdg_positioner::{self, Anchor, Gravity};
+
+use crate::{
+    iced::elements::context_menu::{context_menu_element, ContextMenuElement, ROW_HEIGHT},
+    xdg_shell_wrapper::{
+        shared_state::GlobalState,
+        space::{PanelPopup, PopupAnimation, WrapperPopupState},
+        wp_fractional_scaling::FractionalScalingManager,
+        wp_viewporter::ViewporterState,
+    },
+};
+
+use super::{edit_mode::DropTarget, PanelSpace};
+
+/// How long an applet may take to open its own popup for a right click before
+/// the panel menu is shown instead.
+const CONTEXT_MENU_DELAY: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAction {
+    Move(Side),
+    Remove,
+    About,
+}
+
+#[derive(Debug)]
+pub struct ContextMenu {
+    pub popup: PanelPopup,
+    pub element: ContextMenuElement,
+    pub applet: String,
+    pub section: Side,
+    pub actions: Vec<MenuAction>,
+    pub hovered: Arc<AtomicUsize>,
+    pub show_about: Arc<AtomicBool>,
+}
+
+impl PanelSpace {
+    fn section_label(&self, side: Side) -> &'static str {
+        match (side, self.config.is_horizontal()) {
+            (Side::WingStart, true) => "left",
+            (Side::WingStart, false) => "top",
+            (Side::Center, _) => "center",
+            (Side::WingEnd, true) => "right",
+            (Side::WingEnd, false) => "bottom",
+        }
+    }
+
+    /// Show the panel menu for the applet at `pos` after a right click, unless
+    /// the applet opens a popup of its own meanwhile.
+    pub(crate) fn schedule_context_menu(&mut self, seat_name: &str, pos: Point<f64, Logical>) {
+        if self.edit_mode.is_some() {
+            return;
+        }
+        let Some((section, name, _, geo)) =
+            self.applet_slots().into_iter().find(|(.., geo)| geo.to_f64().contains(pos))
+        else {
+            return;
+        };
+        let Some(client) = self.applets.snapshot().iter().find_map(|(client, a)| {
+            (a.name == name && Arc::ptr_eq(&a.clients, self.section_clients(section)))
+                .then(|| client.clone())
+        }) else {
+            return;
+        };
+
+        let panel_id = self.space.id();
+        let seat_name = seat_name.to_string();
+        _ =
+            self.loop_handle.insert_source(
+                Timer::from_duration(CONTEXT_MENU_DELAY),
+                move |_, _, data| {
+                    // the applet claimed the click
+                    let claimed = data.space.space_list.iter().any(|s| {
+                        s.popups.iter().any(|p| {
+                            p.s_surface.wl_surface().client().is_some_and(|c| c.id() == client)
+                        })
+                    });
+                    let seat =
+                        data.server_state.seats.iter().find(|s| s.name == seat_name).map(|seat| {
+                            (seat.client.last_pointer_press.0, seat.client._seat.clone())
+                        });
+                    if let (false, Some(seat)) = (claimed, seat) {
+                        data.space.open_context_menu(
+                            panel_id,
+                            name.clone(),
+                            section,
+                            geo,
+                            &data.client_state.compositor_state,
+                            data.client_state.fractional_scaling_manager.as_ref(),
+                            data.client_state.viewporter_state.as_ref(),
+                            &data.client_state.queue_handle,
+                            &mut data.client_state.xdg_shell_state,
+                            seat,
+                        );
+                    }
+                    TimeoutAction::Drop
+                },
+            );
+    }
+
+    pub fn open_context_menu(
+        &mut self,
+        applet: String,
+        section: Side,
+        geo: Rectangle<i32, Logical>,
+        compositor_state: &sctk::compositor::CompositorState,
+        fractional_scale_manager: Option<&FractionalScalingManager>,
+        viewport: Option<&ViewporterState>,
+        qh: &QueueHandle<GlobalState>,
+        xdg_shell_state: &mut sctk::shell::xdg::XdgShell,
+        seat: (u32, WlSeat),
+    ) -> anyhow::Result<()> {
+        self.close_popups(|_| false);
+        let Some(parent) = self.panel_surface().cloned() else {
+            anyhow::bail!("Panel has no surface");
+        };
+
+        let (title, about) = {
+            let clients = [&self.clients_left, &self.clients_center, &self.clients_right];
+            let mut found = None;
+            for clients in clients {
+                if let Some(c) = clients.lock().unwrap().iter().find(|c| c.name == applet) {
+                    let pid = c.process.pid.unwrap_or_default();
+                    found = Some((
+                        c.display_name.clone().unwrap_or_else(|| applet.clone()),
+                        vec![
+                            applet.clone(),
+                            c.exec.clone().unwrap_or_default(),
+                            format!("pid {pid}, {} restarts", c.process.restarts),
+                        ],
+                    ));
+                    break;
+                }
+            }
+            found.unwrap_or_else(|| (applet.clone(), vec![applet.clone()]))
+        };
+        let mut actions: Vec<_> = [Side::WingStart, Side::Center, Side::WingEnd]
+            .into_iter()
+            .filter(|side| *side != section)
+            .map(MenuAction::Move)
+            .collect();
+        actions.extend([MenuAction::Remove, MenuAction::About]);
+        let entries = actions
+            .iter()
+            .map(|action| match action {
+                MenuAction::Move(side) => format!("Move to {} section", self.section_label(*side)),
+                MenuAction::Remove => "Remove from panel".to_string(),
+                MenuAction::About => "About applet".to_string(),
+            })
+            .collect();
+
+        let hovered = Arc::new(AtomicUsize::new(usize::MAX));
+        let show_about = Arc::new(AtomicBool::new(false));
+        let element = context_menu_element(
+            title,
+            entries,
+            about,
+            hovered.clone(),
+            show_about.clone(),
+            self.loop_handle.clone(),
+            self.colors.theme.clone(),
+            self.space.id(),
+        );
+        if let Some((_, output, _)) = self.output.as_ref() {
+            element.output_enter(output, Rectangle::default());
+        }
+        let size = element.bbox().size;
+
+        let c_wl_surface = compositor_state.create_surface(qh);
+        let positioner = XdgPositioner::new(xdg_shell_state)?;
+        positioner.set_anchor_rect(geo.loc.x, geo.loc.y, geo.size.w, geo.size.h);
+        let pixel_offset = 8;
+        let (offset, anchor, gravity) = match self.config.anchor {
+            PanelAnchor::Left => ((pixel_offset, 0), Anchor::Right, Gravity::Right),
+            PanelAnchor::Right => ((-pixel_offset, 0), Anchor::Left, Gravity::Left),
+            PanelAnchor::Top => ((0, pixel_offset), Anchor::Bottom, Gravity::Bottom),
+            PanelAnchor::Bottom => ((0, -pixel_offset), Anchor::Top, Gravity::Top),
+        };
+        positioner.set_anchor(anchor);
+        positioner.set_gravity(gravity);
+        positioner.set_constraint_adjustment(
+            xdg_positioner::ConstraintAdjustment::FlipY
+                | xdg_positioner::ConstraintAdjustment::FlipX
+                | xdg_positioner::ConstraintAdjustment::SlideX
+                | xdg_positioner::ConstraintAdjustment::SlideY,
+        );
+        positioner.set_offset(offset.0, offset.1);
+        positioner.set_size(size.w, size.h);
+        let c_popup = popup::Popup::from_surface(
+            self.popup_parent(),
+            &positioner,
+            qh,
+            c_wl_surface.clone(),
+            xdg_shell_state,
+        )?;
+        c_popup.xdg_popup().grab(&seat.1, seat.0);
+        c_popup.xdg_surface().set_window_geometry(0, 0, size.w.max(1), size.h.max(1));
+        if let Some(layer) = self.layer.as_ref() {
+            layer.get_popup(c_popup.xdg_popup());
+        }
+
+        let fractional_scale =
+            fractional_scale_manager.map(|f| f.fractional_scaling(&c_wl_surface, qh));
+        let viewport = viewport.map(|v| {
+            let viewport = v.get_viewport(&c_wl_surface, qh);
+            viewport.set_destination(size.w.max(1), size.h.max(1));
+            viewport
+        });
+        if fractional_scale.is_none() {
+            c_wl_surface.set_buffer_scale(self.scale as i32);
+        }
+        // must be done after role is assigned as popup
+        c_wl_surface.commit();
+
+        self.context_menu = Some(ContextMenu {
+            popup: PanelPopup {
+                damage_tracked_renderer: OutputDamageTracker::new(
+                    size.to_f64().to_physical(self.scale).to_i32_round(),
+                    self.scale,
+                    smithay::utils::Transform::Flipped180,
+                ),
+                c_popup,
+                egl_surface: None,
+                dirty: false,
+                rectangle: Rectangle::from_loc_and_size((0, 0), size),
+                state: Some(WrapperPopupState::WaitConfigure),
+                wrapper_rectangle: Rectangle::from_loc_and_size((0, 0), size),
+                positioner,
+                has_frame: true,
+                fractional_scale,
+                viewport,
+                scale: self.scale,
+                input_region: None,
+                parent,
+                animation: PopupAnimation::Shown,
+            },
+            element,
+            applet,
+            section,
+            actions,
+            hovered,
+            show_about,
+        });
+        Ok(())
+    }
+
+    pub(crate) fn close_context_menu(&mut self) {
+        if let Some(menu) = self.context_menu.take() {
+            let surface = menu.popup.c_popup.wl_surface().clone();
+            self.c_focused_surface.borrow_mut().retain(|s| s.0 != surface);
+            self.c_hovered_surface.borrow_mut().retain(|s| s.0 != surface);
+            menu.popup.c_popup.xdg_popup().destroy();
+            surface.destroy();
+        }
+    }
+
+    /// Handle a pointer event over the context menu. Returns whether the event
+    /// was consumed.
+    pub fn context_menu_pointer_event(&mut self, e: &PointerEvent) -> bool {
+        let Some(menu) =
+            self.context_menu.as_mut().filter(|m| m.popup.c_popup.wl_surface() == &e.surface)
+        else {
+            return false;
+        };
+        let row = (e.position.1 / ROW_HEIGHT as f64).floor() as i64 - 1;
+        let hovered = usize::try_from(row).ok().filter(|i| *i < menu.actions.len());
+        match e.kind {
+            PointerEventKind::Enter { .. } | PointerEventKind::Motion { .. } => {
+                let hovered = hovered.unwrap_or(usize::MAX);
+                if menu.hovered.swap(hovered, Ordering::Relaxed) != hovered {
+                    menu.element.force_redraw();
+                    menu.popup.dirty = true;
+                }
+            },
+            PointerEventKind::Leave { .. } => {
+                if menu.hovered.swap(usize::MAX, Ordering::Relaxed) != usize::MAX {
+                    menu.element.force_redraw();
+                    menu.popup.dirty = true;
+                }
+            },
+            PointerEventKind::Release { button: BTN_LEFT, .. } => {
+                if menu.show_about.load(Ordering::Relaxed) {
+                    self.close_context_menu();
+                    return true;
+                }
+                let Some(action) = hovered.map(|i| menu.actions[i]) else {
+                    return true;
+                };
+                let (applet, section) = (menu.applet.clone(), menu.section);
+                match action {
+                    MenuAction::Move(side) => {
+                        self.close_context_menu();
+                        self.write_applet_move(
+                            &applet,
+                            section,
+                            DropTarget::Slot {
+                                section: side,
+                                before: None,
+                                indicator: Rectangle::default(),
+                            },
+                        );
+                    },
+                    MenuAction::Remove => {
+                        self.close_context_menu();
+                        self.write_applet_move(&applet, section, DropTarget::Trash);
+                    },
+                    MenuAction::About => {
+                        menu.show_about.store(true, Ordering::Relaxed);
+                        menu.element.force_redraw();
+                        menu.popup.dirty = true;
+                    },
+                }
+            },
+            _ => {},
+        }
+        true
+    }
+}
@@ -1,11 +1,13 @@
-use std::cell::RefCell;
+use std::{cell::RefCell, collections::VecDeque};
 
 use smithay::{
     backend::renderer::{
         element::{Element, Kind, RenderElement, UnderlyingStorage},
         gles::{
             element::PixelShaderElement,
-            ffi::{BLEND, FUNC_ADD, SRC_ALPHA, ZERO},
+            ffi::{
+                BLEND, FUNC_ADD, ONE, ONE_MINUS_DST_ALPHA, ONE_MINUS_DST_COLOR, SRC_ALPHA, ZERO,
+            },
             GlesError, GlesFrame, GlesPixelProgram, GlesRenderer, Uniform, UniformName,
             UniformType,
         },
@@ -29,9 +31,32 @@ pub struct RoundedRectangleSettings {
     pub drop_shadow: f32,
     pub bg_color: [f32; 4],
     pub border_color: [f32; 4],
+    /// seconds for the effects, wrapping every hour
+    pub time: f32,
+    /// strength of the gradient effect, from 0 to 1
+    pub gradient: f32,
+    /// progress of the shimmer effect, from 0 to 1, or 0 without a shimmer
+    pub shimmer: f32,
+    /// glow of a swipe on the panel, from -1 to 1, growing from the end of the
+    /// direction of its sign
+    pub swipe: f32,
+    /// samples per pixel along each axis
+    pub samples: f32,
+    /// width of the smoothed edge relative to the default, 0 for a hard edge
+    pub smoothing: f32,
 }
 
-pub struct RoundedRectangleShaderElement(PixelShaderElement);
+/// The rounded rectangle, which masks the content drawn before it, or with a
+/// `drop_shadow` draws a shadow behind the content.
+pub struct RoundedRectangleShaderElement {
+    element: PixelShaderElement,
+    shadow: bool,
+}
+
+/// Number of elements with different settings which are kept.
+const ELEMENT_CACHE_SIZE: usize = 4;
+
+type ElementCache = VecDeque<(RoundedRectangleSettings, PixelShaderElement)>;
 
 impl RoundedRectangleShader {
     pub fn get(renderer: &GlesRenderer) -> GlesPixelProgram {
@@ -50,17 +75,17 @@ impl RoundedRectangleShader {
         settings: RoundedRectangleSettings,
     ) -> RoundedRectangleShaderElement {
         let user_data = renderer.egl_context().user_data();
-        user_data.insert_if_missing(|| {
-            RefCell::new(None::<(RoundedRectangleSettings, PixelShaderElement)>)
-        });
-        let mut cache = user_data
-            .get::<RefCell<Option<(RoundedRectangleSettings, PixelShaderElement)>>>()
-            .unwrap()
-            .borrow_mut();
+        user_data.insert_if_missing(|| RefCell::new(ElementCache::new()));
+        let mut cache = user_data.get::<RefCell<ElementCache>>().unwrap().borrow_mut();
 
-        let elem = cache.take().filter(|(s, _)| *s == settings).unwrap_or_else(|| {
+        // the panel and its popups draw with different settings, so a few
+        // elements are kept to keep their ids and damage
+        let i = cache.iter().position(|(s, _)| *s == settings).unwrap_or_else(|| {
             let shader = Self::get(renderer);
-            (
+            if cache.len() >= ELEMENT_CACHE_SIZE {
+                cache.pop_front();
+            }
+            cache.push_back((
                 settings,
                 PixelShaderElement::new(
                     shader,
@@ -78,18 +103,24 @@ impl RoundedRectangleShader {
                         Uniform::new("drop_shadow", settings.drop_shadow),
                         Uniform::new("bg_color", settings.bg_color),
                         Uniform::new("border_color", settings.border_color),
+                        Uniform::new("time", settings.time),
+                        Uniform::new("gradient", settings.gradient),
+                        Uniform::new("shimmer", settings.shimmer),
+                        Uniform::new("swipe", settings.swipe),
+                        Uniform::new("samples", settings.samples),
+                        Uniform::new("smoothing", settings.smoothing),
                     ],
                     Kind::Unspecified,
                 ),
-            )
+            ));
+            cache.len() - 1
         });
-        *cache = Some(elem);
 
-        let elem = &mut cache.as_mut().unwrap().1;
+        let elem = &mut cache[i].1;
         if elem.geometry(1.0.into()).to_logical(1) != geo {
             elem.resize(geo, None);
         }
-        RoundedRectangleShaderElement(elem.clone())
+        RoundedRectangleShaderElement { element: elem.clone(), shadow: settings.drop_shadow > 0. }
     }
 }
 
@@ -114,6 +145,12 @@ pub fn init_shaders(gles_renderer: &mut GlesRenderer) -> Result<(), GlesError> {
             UniformName::new("drop_shadow", UniformType::_1f),
             UniformName::new("bg_color", UniformType::_4f),
             UniformName::new("border_color", UniformType::_4f),
+            UniformName::new("time", UniformType::_1f),
+            UniformName::new("gradient", UniformType::_1f),
+            UniformName::new("shimmer", UniformType::_1f),
+            UniformName::new("swipe", UniformType::_1f),
+            UniformName::new("samples", UniformType::_1f),
+            UniformName::new("smoothing", UniformType::_1f),
         ],
     )?;
 
@@ -125,19 +162,19 @@ pub fn init_shaders(gles_renderer: &mut GlesRenderer) -> Result<(), GlesError> {
 
 impl Element for RoundedRectangleShaderElement {
     fn id(&self) -> &smithay::backend::renderer::element::Id {
-        self.0.id()
+        self.element.id()
     }
 
     fn current_commit(&self) -> smithay::backend::renderer::utils::CommitCounter {
-        self.0.current_commit()
+        self.element.current_commit()
     }
 
     fn src(&self) -> Rectangle<f64, Buffer> {
-        self.0.src()
+        self.element.src()
     }
 
     fn geometry(&self, scale: smithay::utils::Scale<f64>) -> Rectangle<i32, Physical> {
-        self.0.geometry(scale)
+        self.element.geometry(scale)
     }
 }
 
@@ -150,12 +187,21 @@ impl RenderElement<GlesRenderer> for RoundedRectangleShaderElement {
         damage: &[Rectangle<i32, Physical>],
         opaque_regions: &[smithay::utils::Rectangle<i32, Physical>],
     ) -> Result<(), GlesError> {
+        let shadow = self.shadow;
         _ = frame.with_context(|gl| unsafe {
             gl.Enable(BLEND);
-            gl.BlendFuncSeparate(ZERO, SRC_ALPHA, ZERO, SRC_ALPHA);
+            if shadow {
+                // the shadow is drawn behind the content
+                gl.BlendFunc(ONE_MINUS_DST_ALPHA, ONE);
+            } else {
+                // the content is masked by the alpha, and the white light of the
+                // effects is blended over it with the opacity of its color, so
+                // it shows on dark backgrounds too
+                gl.BlendFuncSeparate(ONE_MINUS_DST_COLOR, SRC_ALPHA, ZERO, SRC_ALPHA);
+            }
             gl.BlendEquation(FUNC_ADD);
         });
-        let res = self.0.draw(frame, src, dst, damage, opaque_regions);
+        let res = self.element.draw(frame, src, dst, damage, opaque_regions);
         _ = frame.with_context(|gl| unsafe {
             gl.Disable(BLEND);
         });
@@ -163,6 +209,6 @@ impl RenderElement<GlesRenderer> for RoundedRectangleShaderElement {
     }
 
     fn underlying_storage(&self, renderer: &mut GlesRenderer) -> Option<UnderlyingStorage> {
-        self.0.underlying_storage(renderer)
+        self.element.underlying_storage(renderer)
     }
 }
@@ -23,6 +23,17 @@ uniform float drop_shadow;
 uniform vec4 bg_color;
 uniform vec4 border_color;
 
+// effects, which blend white light over the masked content
+uniform float time;
+uniform float gradient;
+uniform float shimmer;
+uniform float swipe;
+
+// anti-aliasing, with samples per pixel along each axis and the width of the
+// smoothed edge relative to the default
+uniform float samples;
+uniform float smoothing;
+
 float sdRoundBox(in vec2 p,in vec2 b,in vec4 r)
 {
     r.xy=(p.x>0.)?r.xy:r.zw;
@@ -39,21 +50,24 @@ float myfwidth(float x){
     #endif
 }
 
-void main()
+// coverage of the rounded rectangle at pos
+float coverage(vec2 pos)
 {
-    vec2 p=2.*gl_FragCoord.xy-(rect_size+loc*2.);
+    vec2 p=2.*pos-(rect_size+loc*2.);
     
     vec2 si=rect_size;
     vec4 ra=2.*vec4(rad_tr,rad_br,rad_tl,rad_bl);
     ra=min(ra,min(si.x,si.y));
     
     float d=sdRoundBox(p,si,ra);
+    if(smoothing<=0.){
+        return 1.-step(0.,d);
+    }
     
     vec2 tl_corner=vec2(loc.x,loc.y+rect_size.y);
     vec2 tr_corner=vec2(loc.x+rect_size.x,loc.y+rect_size.y);
     vec2 bl_corner=vec2(loc.x,loc.y);
     vec2 br_corner=vec2(loc.x+rect_size.x,loc.y);
-    vec2 pos=gl_FragCoord.xy;
     
     float delta=0.;
     float d_tl;
@@ -71,7 +85,6 @@ void main()
         d_bl=abs(bl_corner.y-pos.y);
         d_br=abs(br_corner.y-pos.y);
     }
-    vec4 calc_bg_color;
     
     if(d_tl<=rad_tl){
         delta=(ra.z-d_tl)/rad_tl*myfwidth(d)/2.;
@@ -82,9 +95,63 @@ void main()
     }else if(d_br<=rad_br){
         delta=(ra.y-d_br)/rad_br*myfwidth(d)/2.;
     }
+    delta*=smoothing;
+    
+    return 1.-smoothstep(1.-5.*delta/6.,1.+delta/6.,1.+d);
+}
+
+void main()
+{
+    vec2 pos=gl_FragCoord.xy;
+    
+    // a shadow of the color bg_color fading out over drop_shadow pixels around
+    // the rectangle, which is blended behind the content
+    if(drop_shadow>0.){
+        vec2 p=2.*pos-(rect_size+loc*2.);
+        vec4 ra=2.*vec4(rad_tr,rad_br,rad_tl,rad_bl);
+        ra=min(ra,min(rect_size.x,rect_size.y));
+        float d=max(sdRoundBox(p,rect_size,ra)/2.,0.);
+        float s=1.-smoothstep(0.,drop_shadow,d);
+        gl_FragColor=vec4(bg_color.rgb*bg_color.a,bg_color.a)*s*s;
+        return;
+    }
     
-    float a=1.-smoothstep(1.-5.*delta/6.,1.+delta/6.,1.+d);
+    // average the coverage of a grid of samples in the pixel
+    float n=clamp(samples,1.,4.);
+    float a=0.;
+    for(int i=0;i<4;i++){
+        if(float(i)>=n){
+            break;
+        }
+        for(int j=0;j<4;j++){
+            if(float(j)>=n){
+                break;
+            }
+            vec2 offset=(vec2(float(i),float(j))+.5)/n-.5;
+            a+=coverage(pos+offset);
+        }
+    }
+    a/=n*n;
+    
+    // position along the panel, from 0 to 1
+    vec2 uv=(pos-loc)/max(rect_size,vec2(1.));
+    float along=rect_size.x>=rect_size.y?uv.x:uv.y;
+    
+    // a gradient which shifts along the panel once a minute
+    float light=gradient*.04*(.5+.5*sin(6.2832*(along-time/60.)));
+    // a band sweeping along the panel while shimmer goes from 0 to 1
+    if(shimmer>0.){
+        float band=shimmer*1.4-.2;
+        light+=.08*exp(-pow((along-band)*10.,2.));
+    }
+    // a glow growing from the end of the panel a swipe goes to
+    if(swipe!=0.){
+        float edge=swipe>0.?along:1.-along;
+        light+=.12*abs(swipe)*smoothstep(1.-.5*abs(swipe),1.,edge);
+    }
     
-    gl_FragColor=vec4(0.,0.,0.,a);
+    // with the blending of the element, this is white with the opacity light
+    // over the content, masked by the coverage
+    gl_FragColor=vec4(vec3(a*light),a);
 }
 
@@ -0,0 +1,57 @@
+//! Developer overlay drawing the last damage, the element geometries and the
+//! input region of a panel on top of its contents.
+
+use smithay::{
+    backend::renderer::element::{
+        solid::{SolidColorBuffer, SolidColorRenderElement},
+        Kind,
+    },
+    utils::{Logical, Physical, Rectangle},
+};
+
+const DAMAGE_COLOR: [f32; 4] = [0.3, 0.0, 0.0, 0.3];
+const ELEMENT_COLOR: [f32; 4] = [0.0, 0.2, 0.0, 0.2];
+const INPUT_COLOR: [f32; 4] = [0.0, 0.0, 0.2, 0.2];
+
+#[derive(Debug, Default)]
+pub struct DebugOverlay {
+    /// damage of the previously rendered frame
+    pub last_damage: Vec<Rectangle<i32, Physical>>,
+    // buffers are reused between frames so that an unchanged overlay does not
+    // add damage of its own
+    buffers: Vec<SolidColorBuffer>,
+}
+
+impl DebugOverlay {
+    pub fn render_elements(
+        &mut self,
+        elements: impl Iterator<Item = Rectangle<i32, Logical>>,
+        input_region: Rectangle<i32, Logical>,
+        scale: f64,
+    ) -> Vec<SolidColorRenderElement> {
+        let rects: Vec<_> = self
+            .last_damage
+            .iter()
+            .map(|r| (r.to_f64().to_logical(scale).to_i32_round(), DAMAGE_COLOR))
+            .chain(elements.map(|r| (r, ELEMENT_COLOR)))
+            .chain(std::iter::once((input_region, INPUT_COLOR)))
+            .filter(|(r, _)| r.size.w > 0 && r.size.h > 0)
+            .collect();
+
+        self.buffers.resize_with(rects.len(), Default::default);
+        self.buffers
+            .iter_mut()
+            .zip(rects)
+            .map(|(buffer, (rect, color))| {
+                buffer.update(rect.size, color);
+                SolidColorRenderElement::from_buffer(
+                    buffer,
+                    rect.loc.to_f64().to_physical(scale).to_i32_round(),
+                    scale,
+                    1.0,
+                    Kind::Unspecified,
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,476 @@
+//! Edit mode of a panel, where applets are reordered by dragging them within
+//! or between the sections of the panel, or removed by dropping them onto the
+//! trash target. Edit mode is started over the control interface, e.g. by
+//! cosmic-settings.
+//!
+//! Pointer input over the panel is handled here instead of being forwarded to
+//! the applets, which are covered by labeled placeholders. Dropping an applet
+//! writes the new `plugins_wings` and `plugins_center` to cosmic-config, and
+//! the panel follows the config update.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use cosmic::cosmic_config::ConfigSet;
+use cosmic_panel_config::{CosmicPanelConfig, Side};
+use sctk::seat::pointer::{PointerEvent, PointerEventKind, BTN_LEFT};
+use smithay::{
+    backend::renderer::{
+        element::{
+            memory::MemoryRenderBufferRenderElement,
+            solid::{SolidColorBuffer, SolidColorRenderElement},
+            AsRenderElements, Kind,
+        },
+        gles::GlesRenderer,
+    },
+    desktop::space::SpaceElement,
+    output::Output,
+    reexports::wayland_server::Resource,
+    utils::{Logical, Physical, Point, Rectangle, Size},
+};
+use tracing::error;
+
+use crate::{
+    iced::elements::{
+        edit_slot::{edit_slot_element, EditSlotElement},
+        CosmicMappedInternal,
+    },
+    space::PanelSpace,
+};
+
+use super::render::PanelRenderElement;
+
+/// How far the pointer moves before a press on an applet starts a drag.
+const DRAG_THRESHOLD: f64 = 8.0;
+/// Length of the trash target along the panel.
+const TRASH_LENGTH: i32 = 96;
+const GHOST_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 0.4];
+const INDICATOR_COLOR: [f32; 4] = [0.58, 0.92, 0.92, 1.0];
+const INDICATOR_WIDTH: i32 = 2;
+
+/// Where a dragged applet goes when dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DropTarget {
+    Slot {
+        section: Side,
+        /// the applet it is inserted before, or the end of the section
+        before: Option<String>,
+        /// the insertion indicator drawn on the panel
+        indicator: Rectangle<i32, Logical>,
+    },
+    /// the applet is removed from the panel
+    Trash,
+}
+
+#[derive(Debug)]
+pub struct AppletDrag {
+    pub seat_name: String,
+    pub name: String,
+    pub section: Side,
+    /// the slot of the applet when the drag started
+    pub geo: Rectangle<i32, Logical>,
+    pub start: Point<f64, Logical>,
+    pub pos: Point<f64, Logical>,
+    /// the pointer moved past the threshold since the press
+    pub active: bool,
+    pub target: Option<DropTarget>,
+}
+
+/// A labeled slot drawn on top of the panel.
+#[derive(Debug)]
+pub struct SlotOverlay {
+    pub geo: Rectangle<i32, Logical>,
+    pub element: EditSlotElement,
+    pub highlighted: Arc<AtomicBool>,
+}
+
+impl SlotOverlay {
+    fn set_highlighted(&self, highlighted: bool) {
+        if self.highlighted.swap(highlighted, Ordering::Relaxed) != highlighted {
+            self.element.force_redraw();
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct EditMode {
+    pub drag: Option<AppletDrag>,
+    /// placeholders of the applets by name
+    pub placeholders: Vec<(String, SlotOverlay)>,
+    /// the trash target, shown during a drag
+    pub trash: Option<SlotOverlay>,
+    // buffers are reused between frames so that an unchanged ghost does not
+    // add damage of its own
+    buffers: [SolidColorBuffer; 2],
+}
+
+impl EditMode {
+    /// The placeholders, trash target, ghost of the dragged applet and
+    /// insertion indicator, moved by `offset`, with the topmost first.
+    pub fn render_elements(
+        &mut self,
+        renderer: &mut GlesRenderer,
+        offset: Point<i32, Logical>,
+        scale: f64,
+    ) -> Vec<PanelRenderElement> {
+        let to_physical = |loc: Point<i32, Logical>| -> Point<i32, Physical> {
+            (loc + offset).to_f64().to_physical(scale).to_i32_round()
+        };
+        let slots: Vec<PanelRenderElement> = self
+            .trash
+            .iter()
+            .chain(self.placeholders.iter().map(|(_, slot)| slot))
+            .flat_map(|slot| {
+                slot.element
+                    .render_elements::<MemoryRenderBufferRenderElement<GlesRenderer>>(
+                        renderer,
+                        to_physical(slot.geo.loc),
+                        scale.into(),
+                        1.0,
+                    )
+                    .into_iter()
+                    .map(PanelRenderElement::Iced)
+            })
+            .collect();
+
+        let Some(drag) = self.drag.as_ref().filter(|d| d.active) else {
+            return slots;
+        };
+        let mut ghost = drag.geo;
+        ghost.loc += (drag.pos - drag.start).to_i32_round();
+        let indicator = match drag.target.as_ref() {
+            Some(DropTarget::Slot { indicator, .. }) => Some((*indicator, INDICATOR_COLOR)),
+            _ => None,
+        };
+        let rects = std::iter::once((ghost, GHOST_COLOR)).chain(indicator);
+
+        self.buffers
+            .iter_mut()
+            .zip(rects)
+            .map(|(buffer, (rect, color))| {
+                buffer.update(rect.size, color);
+                PanelRenderElement::Solid(SolidColorRenderElement::from_buffer(
+                    buffer,
+                    to_physical(rect.loc),
+                    scale,
+                    1.0,
+                    Kind::Unspecified,
+                ))
+            })
+            .chain(slots)
+            .collect()
+    }
+}
+
+/// Whether each section of `a` holds the same applets as in `b` on every
+/// output, so that only their order differs.
+pub fn same_sections(a: &CosmicPanelConfig, b: &CosmicPanelConfig) -> bool {
+    let sorted = |list: Option<Vec<String>>| {
+        let mut list = list.unwrap_or_default();
+        list.sort();
+        list
+    };
+    let outputs = a.output_plugins.iter().chain(&b.output_plugins).map(|o| Some(o.output.as_str()));
+    std::iter::once(None).chain(outputs).all(|output| {
+        sorted(a.plugins_left_on(output)) == sorted(b.plugins_left_on(output))
+            && sorted(a.plugins_center_on(output)) == sorted(b.plugins_center_on(output))
+            && sorted(a.plugins_right_on(output)) == sorted(b.plugins_right_on(output))
+    })
+}
+
+impl PanelSpace {
+    /// The section, name, label and geometry of the mapped applets, in layout
+    /// order.
+    pub(crate) fn applet_slots(&self) -> Vec<(Side, String, String, Rectangle<i32, Logical>)> {
+        // read from the registry, as this is done for pointer events
+        let applets = self.applets.snapshot();
+        let mut slots: Vec<_> =
+            self.space
+                .elements()
+                .filter_map(|e| {
+                    let CosmicMappedInternal::Window(w) = e else {
+                        return None;
+                    };
+                    let client = w.toplevel()?.wl_surface().client()?.id();
+                    let a = applets
+                        .get(&client)
+                        .filter(|a| Arc::ptr_eq(&a.clients, self.section_clients(a.side)))?;
+                    let geo = self.space.element_geometry(e)?;
+                    let label = a.display_name.clone().unwrap_or_else(|| {
+                        a.name.rsplit('.').next().unwrap_or(&a.name).to_string()
+                    });
+                    Some((a.side, a.name.clone(), label, geo))
+                })
+                .collect();
+        let section = |side: Side| match side {
+            Side::WingStart => 0,
+            Side::Center => 1,
+            Side::WingEnd => 2,
+        };
+        slots.sort_by_key(|(side, .., geo)| {
+            (section(*side), if self.config.is_horizontal() { geo.loc.x } else { geo.loc.y })
+        });
+        slots
+    }
+
+    /// The trash target at the end of the panel. It may cover the last applets,
+    /// which stay drop targets, see [`PanelSpace::drop_target`].
+    fn trash_geometry(&self) -> Rectangle<i32, Logical> {
+        if self.config.is_horizontal() {
+            let w = TRASH_LENGTH.min(self.dimensions.w);
+            Rectangle::from_loc_and_size((self.dimensions.w - w, 0), (w, self.dimensions.h))
+        } else {
+            let h = TRASH_LENGTH.min(self.dimensions.h);
+            Rectangle::from_loc_and_size((0, self.dimensions.h - h), (self.dimensions.w, h))
+        }
+    }
+
+    fn new_edit_slot(
+        &self,
+        label: String,
+        trash: bool,
+        geo: Rectangle<i32, Logical>,
+        output: &Output,
+    ) -> SlotOverlay {
+        let highlighted = Arc::new(AtomicBool::new(false));
+        let element = edit_slot_element(
+            label,
+            trash,
+            geo.size,
+            highlighted.clone(),
+            self.loop_handle.clone(),
+            self.colors.theme.clone(),
+            self.space.id(),
+        );
+        element.output_enter(output, Rectangle::default());
+        SlotOverlay { geo, element, highlighted }
+    }
+
+    /// Keep the placeholders on top of the applets, and show the trash target
+    /// during a drag.
+    pub(crate) fn update_edit_slots(&mut self) {
+        let (Some(edit), Some((_, output, _))) = (self.edit_mode.as_mut(), self.output.as_ref())
+        else {
+            return;
+        };
+        let dragging = edit.drag.as_ref().is_some_and(|d| d.active);
+        let mut old = std::mem::take(&mut edit.placeholders);
+        let old_trash = edit.trash.take();
+
+        let mut placeholders = Vec::new();
+        for (_, name, label, geo) in self.applet_slots() {
+            let slot = match old.iter().position(|(n, _)| *n == name) {
+                Some(i) => {
+                    let (_, mut slot) = old.swap_remove(i);
+                    if slot.geo.size != geo.size {
+                        slot.element.resize(geo.size);
+                    }
+                    slot.geo = geo;
+                    slot
+                },
+                None => self.new_edit_slot(label, false, geo, output),
+            };
+            placeholders.push((name, slot));
+        }
+
+        let trash_geo = self.trash_geometry();
+        let trash = match old_trash {
+            Some(mut trash) if dragging => {
+                if trash.geo.size != trash_geo.size {
+                    trash.element.resize(trash_geo.size);
+                }
+                trash.geo = trash_geo;
+                Some(trash)
+            },
+            None if dragging => {
+                Some(self.new_edit_slot("Remove".to_string(), true, trash_geo, output))
+            },
+            _ => None,
+        };
+
+        if let Some(edit) = self.edit_mode.as_mut() {
+            edit.placeholders = placeholders;
+            edit.trash = trash;
+        }
+    }
+
+    /// The insertion point for the applet `dragged` nearest to `pos` along
+    /// the panel, or the trash target. The applets are excluded from the
+    /// trash target, so an applet is never removed by reordering it.
+    fn drop_target(&self, pos: Point<f64, Logical>, dragged: &str) -> Option<DropTarget> {
+        let all_slots = self.applet_slots();
+        if self.trash_geometry().to_f64().contains(pos)
+            && !all_slots.iter().any(|(.., geo)| geo.to_f64().contains(pos))
+        {
+            return Some(DropTarget::Trash);
+        }
+        let horizontal = self.config.is_horizontal();
+        let main = |p: Point<f64, Logical>| if horizontal { p.x } else { p.y };
+        let slots: Vec<_> = all_slots.into_iter().filter(|(_, name, ..)| name != dragged).collect();
+        let (i, (section, name, _, geo)) = slots.iter().enumerate().min_by(|(_, a), (_, b)| {
+            let a = (main(a.3.to_f64().center()) - main(pos)).abs();
+            let b = (main(b.3.to_f64().center()) - main(pos)).abs();
+            a.total_cmp(&b)
+        })?;
+
+        let after = main(pos) > main(geo.to_f64().center());
+        let before = if after {
+            slots.get(i + 1).filter(|(s, ..)| s == section).map(|(_, name, ..)| name.clone())
+        } else {
+            Some(name.clone())
+        };
+        let edge = if after {
+            geo.loc + Point::from(if horizontal { (geo.size.w, 0) } else { (0, geo.size.h) })
+        } else {
+            geo.loc
+        };
+        let indicator = if horizontal {
+            Rectangle::from_loc_and_size(
+                (edge.x - INDICATOR_WIDTH / 2, geo.loc.y),
+                Size::from((INDICATOR_WIDTH, geo.size.h)),
+            )
+        } else {
+            Rectangle::from_loc_and_size(
+                (geo.loc.x, edge.y - INDICATOR_WIDTH / 2),
+                Size::from((geo.size.w, INDICATOR_WIDTH)),
+            )
+        };
+        Some(DropTarget::Slot { section: *section, before, indicator })
+    }
+
+    /// Handle a pointer event over the panel in edit mode. Returns whether the
+    /// event was consumed, and must not be forwarded to the applets.
+    pub fn edit_pointer_event(&mut self, seat_name: &str, e: &PointerEvent) -> bool {
+        if self.edit_mode.is_none() {
+            return false;
+        }
+        let pos: Point<f64, Logical> = e.position.into();
+        match e.kind {
+            PointerEventKind::Enter { .. } | PointerEventKind::Leave { .. } => return false,
+            PointerEventKind::Press { button: BTN_LEFT, .. } => {
+                let slot =
+                    self.applet_slots().into_iter().find(|(.., geo)| geo.to_f64().contains(pos));
+                if let (Some(edit), Some((section, name, _, geo))) = (self.edit_mode.as_mut(), slot)
+                {
+                    edit.drag = Some(AppletDrag {
+                        seat_name: seat_name.to_string(),
+                        name,
+                        section,
+                        geo,
+                        start: pos,
+                        pos,
+                        active: false,
+                        target: None,
+                    });
+                }
+            },
+            PointerEventKind::Motion { .. } => {
+                let Some(drag) = self
+                    .edit_mode
+                    .as_ref()
+                    .and_then(|edit| edit.drag.as_ref())
+                    .filter(|d| d.seat_name == seat_name)
+                else {
+                    return true;
+                };
+                let delta = pos - drag.start;
+                let active = drag.active || delta.x.hypot(delta.y) > DRAG_THRESHOLD;
+                let target = if active { self.drop_target(pos, &drag.name) } else { None };
+                if let Some(edit) = self.edit_mode.as_mut() {
+                    if let Some(trash) = edit.trash.as_ref() {
+                        trash.set_highlighted(target == Some(DropTarget::Trash));
+                    }
+                    if let Some(drag) = edit.drag.as_mut() {
+                        drag.pos = pos;
+                        drag.active = active;
+                        drag.target = target;
+                    }
+                }
+                self.is_dirty = true;
+            },
+            PointerEventKind::Release { button: BTN_LEFT, .. } => {
+                let Some(edit) = self.edit_mode.as_mut() else {
+                    return true;
+                };
+                if edit.drag.as_ref().map_or(true, |d| d.seat_name != seat_name) {
+                    return true;
+                }
+                let Some(drag) = edit.drag.take() else {
+                    return true;
+                };
+                if let Some(target) = drag.target.filter(|_| drag.active) {
+                    self.write_applet_move(&drag.name, drag.section, target);
+                }
+                self.is_dirty = true;
+            },
+            _ => {},
+        }
+        true
+    }
+
+    /// Move the applet `name` from `section` to `target` in the config of the
+    /// panel.
+    pub(super) fn write_applet_move(&self, name: &str, section: Side, target: DropTarget) {
+        let current = self.section_plugins(&self.config);
+        let mut lists = current.clone().map(Option::unwrap_or_default);
+        let index = |side: Side| match side {
+            Side::WingStart => 0,
+            Side::Center => 1,
+            Side::WingEnd => 2,
+        };
+        let from = &mut lists[index(section)];
+        let Some(i) = from.iter().position(|n| n == name) else {
+            return;
+        };
+        from.remove(i);
+        if let DropTarget::Slot { section, before, .. } = target {
+            let to = &mut lists[index(section)];
+            let i =
+                before.and_then(|before| to.iter().position(|n| *n == before)).unwrap_or(to.len());
+            to.insert(i, name.to_string());
+        }
+
+        if lists.iter().zip(&current).all(|(list, current)| Some(list) == current.as_ref()) {
+            return;
+        }
+        let [left, center, right] = lists;
+        let output = self.output.as_ref().and_then(|o| o.2.name.as_deref());
+        let overridden =
+            self.config.output_plugins.iter().position(|o| Some(o.output.as_str()) == output);
+        let res = CosmicPanelConfig::cosmic_config(&self.config.name).and_then(|config| {
+            // the applets of an output with its own applets are written to its
+            // override, which then replaces every section on the output
+            if let Some(i) = overridden {
+                let mut output_plugins = self.config.output_plugins.clone();
+                output_plugins[i].plugins_wings = Some((left, right));
+                output_plugins[i].plugins_center = Some(center);
+                return config.set("output_plugins", output_plugins);
+            }
+            let tx = config.transaction();
+            tx.set("plugins_wings", Some((left, right)))?;
+            tx.set("plugins_center", Some(center))?;
+            tx.commit()
+        });
+        if let Err(err) = res {
+            error!("Failed to write the applets of {}: {err:?}", self.config.name);
+        }
+    }
+
+    /// Sort the applets of each section by their position in `config`.
+    pub(crate) fn sort_applets(&mut self, config: &CosmicPanelConfig) {
+        let [left, center, right] = self.section_plugins(config);
+        for (clients, list) in [
+            (&self.clients_left, left),
+            (&self.clients_center, center),
+            (&self.clients_right, right),
+        ] {
+            let list = list.unwrap_or_default();
+            clients
+                .lock()
+                .unwrap()
+                .sort_by_key(|c| list.iter().position(|n| *n == c.name).unwrap_or(usize::MAX));
+        }
+    }
+}
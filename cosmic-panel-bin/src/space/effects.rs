@@ -0,0 +1,80 @@
+//! Animated effects of the panel background, a slowly shifting gradient and a
+//! shimmer sweeping along the panel when the pointer enters it, and the glow
+//! of a swipe on the panel.
+//!
+//! The effects are drawn by the rounded rectangle shader which masks the
+//! panel, from the time and progress passed as uniforms. Frames are only
+//! requested while an effect is running on a visible panel.
+
+use std::time::{Duration, Instant};
+
+use crate::xdg_shell_wrapper::{client_state::FocusStatus, space::Visibility};
+
+use super::PanelSpace;
+
+/// How long the shimmer takes to sweep along the panel.
+const SHIMMER_DURATION: Duration = Duration::from_millis(1200);
+
+#[derive(Debug, Default)]
+pub struct EffectsState {
+    /// whether the pointer was over the panel in the last frame
+    hovered: bool,
+    shimmer_started: Option<Instant>,
+}
+
+impl PanelSpace {
+    fn pointer_over_panel(&self) -> bool {
+        let Some(surface) = self.panel_surface() else {
+            return false;
+        };
+        self.c_hovered_surface
+            .borrow()
+            .iter()
+            .any(|(s, _, f)| s == surface && matches!(f, FocusStatus::Focused))
+    }
+
+    /// Whether any effect is drawn on the panel.
+    pub(crate) fn has_effects(&self) -> bool {
+        self.config.effects.gradient
+            || self.effects.shimmer_started.is_some()
+            || self.swipe_active()
+    }
+
+    /// Whether an effect is animating on the visible panel, so it needs a
+    /// frame even without damage.
+    pub(crate) fn effects_active(&self) -> bool {
+        // panels without autohide are shown in any visibility state
+        let hidden =
+            self.config.autohide.is_some() && !matches!(self.visibility, Visibility::Visible);
+        self.has_effects() && !hidden && !self.suppressed
+    }
+
+    /// Advance the effects, and pass their state to the shader.
+    pub(crate) fn update_effects(&mut self) {
+        let hovered = self.config.effects.hover_shimmer && self.pointer_over_panel();
+        if hovered && !self.effects.hovered {
+            self.effects.shimmer_started = Some(Instant::now());
+        }
+        self.effects.hovered = hovered;
+
+        let shimmer = self
+            .effects
+            .shimmer_started
+            .map(|t| t.elapsed().as_secs_f32() / SHIMMER_DURATION.as_secs_f32());
+        if shimmer.is_some_and(|p| p >= 1.) {
+            // draw the panel once more without the shimmer
+            self.effects.shimmer_started = None;
+            self.is_dirty = true;
+        }
+
+        let swipe = self.swipe_glow();
+        let active = self.effects_active();
+        let settings = &mut self.panel_rect_settings;
+        settings.gradient = if self.config.effects.gradient { 1. } else { 0. };
+        settings.shimmer = shimmer.filter(|p| *p < 1.).unwrap_or_default();
+        settings.swipe = swipe;
+        if active {
+            settings.time = self.start_instant.elapsed().as_secs_f32() % 3600.;
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Extra length requested by applets with the `request_expansion` request of
+//! the live config protocol, e.g. for an expanding search field.
+//!
+//! The granted length is limited by the config of the panel and by the free
+//! space the last layout left in the section of the applet. It is animated,
+//! and the section is laid out again on each step with the animated length
+//! reserved after the applet, which is centered in it. The length is also sent
+//! to the applet as the `expansion` entry of its live config, so the applet
+//! may grow its toplevel into the reserved length.
+//!
+//! A dock which grows with its applets isn't limited by the free space of the
+//! last layout, but grants extra length until it reaches its longest length.
+//! The length of the dock follows the length its applets need with the same
+//! animation, clamped to its shortest and longest length. The applets which
+//! don't fit in its longest length overflow.
+
+use std::time::{Duration, Instant};
+
+use cosmic_panel_config::Side;
+use smithay::reexports::wayland_server::backend::ClientId;
+
+use crate::{iced::elements::CosmicMappedInternal, xdg_shell_wrapper::util::smootherstep};
+
+use super::{layout::applet_logical_size, PanelSpace};
+
+/// How long granting or reclaiming the extra length takes.
+const EXPANSION_DURATION: Duration = Duration::from_millis(200);
+
+#[derive(Debug)]
+pub struct Expansion {
+    /// granted length in logical pixels at the start of the animation
+    from: u32,
+    /// granted length the animation ends at, 0 after a release
+    target: u32,
+    started: Instant,
+    /// granted length in the current frame
+    pub(crate) current: u32,
+    /// length of the applet along the panel before anything was granted
+    base: f64,
+}
+
+/// Animated length of a dock which grows with its applets.
+#[derive(Debug)]
+pub struct DockGrowth {
+    /// length in logical pixels at the start of the animation
+    from: i32,
+    target: i32,
+    started: Instant,
+}
+
+impl DockGrowth {
+    fn current(&self, reduce_motion: bool) -> i32 {
+        let progress = if reduce_motion {
+            1.
+        } else {
+            self.started.elapsed().as_secs_f32() / EXPANSION_DURATION.as_secs_f32()
+        };
+        let progress = smootherstep(progress.min(1.));
+        (self.from as f32 + (self.target - self.from) as f32 * progress).round() as i32
+    }
+}
+
+impl PanelSpace {
+    /// The section of the applet `client`, if it is in this panel.
+    fn applet_section(&self, client: &ClientId) -> Option<usize> {
+        self.applet_side(client).map(|side| match side {
+            Side::WingStart => 0,
+            Side::Center => 1,
+            Side::WingEnd => 2,
+        })
+    }
+
+    /// The length of the applet `client` along the panel.
+    fn applet_length(&self, client: &ClientId) -> f64 {
+        self.space
+            .elements()
+            .find(|w| {
+                w.toplevel()
+                    .and_then(|t| t.wl_surface().client())
+                    .is_some_and(|c| c.id() == *client)
+            })
+            .map_or(0., |w| {
+                let size = applet_logical_size(w);
+                if self.config.is_horizontal() {
+                    size.w
+                } else {
+                    size.h
+                }
+            })
+    }
+
+    /// The length reserved after the applet `w` for its granted length, as far
+    /// as it didn't grow its toplevel into it yet.
+    pub(crate) fn expansion_reserve(&self, w: &CosmicMappedInternal) -> f64 {
+        let Some(e) = w
+            .toplevel()
+            .and_then(|t| t.wl_surface().client())
+            .and_then(|c| self.expansions.get(&c.id()))
+        else {
+            return 0.;
+        };
+        let size = applet_logical_size(w);
+        let length = if self.config.is_horizontal() { size.w } else { size.h };
+        (e.base + e.current as f64 - length).max(0.)
+    }
+
+    /// Grant the applet `client` up to `length` extra logical pixels, or none
+    /// if it isn't in this panel.
+    pub(crate) fn request_expansion(&mut self, client: &ClientId, length: u32) -> bool {
+        let Some(section) = self.applet_section(client) else {
+            return false;
+        };
+        // docks lay out all applets in the center section
+        let section = if self.config.expand_to_edges() { section } else { 1 };
+        let (current, base) = match self.expansions.get(client) {
+            Some(e) => (e.current, e.base),
+            None => (0, self.applet_length(client)),
+        };
+        let free = match self.config.dock_auto_grow.filter(|_| !self.config.expand_to_edges()) {
+            Some(grow) => (grow.max_length as f64 - self.container_length as f64).max(0.),
+            None => self.layout_cache.free_lengths().map_or(0., |f| f[section]),
+        };
+        let target = length.min(self.config.max_applet_expansion).min(free as u32 + current);
+        self.expansions.insert(
+            client.clone(),
+            Expansion { from: current, target, started: Instant::now(), current, base },
+        );
+        true
+    }
+
+    /// Reclaim the extra length of the applet `client`.
+    pub(crate) fn release_expansion(&mut self, client: &ClientId) {
+        if let Some(e) = self.expansions.get_mut(client) {
+            e.from = e.current;
+            e.target = 0;
+            e.started = Instant::now();
+        }
+    }
+
+    /// Whether the length of the dock is animating.
+    pub(crate) fn dock_growing(&self) -> bool {
+        self.dock_growth.as_ref().is_some_and(|g| g.current(self.config.reduce_motion) != g.target)
+    }
+
+    /// The length of a dock which grows with its applets, for the `needed`
+    /// length of its applets, or `None` if it doesn't grow.
+    pub(crate) fn grown_dock_length(&mut self, needed: i32) -> Option<i32> {
+        let Some(grow) = self.config.dock_auto_grow.filter(|_| !self.config.expand_to_edges())
+        else {
+            self.dock_growth = None;
+            return None;
+        };
+        let reduce_motion = self.config.reduce_motion;
+        let target = needed.clamp(grow.min_length as i32, grow.max_length as i32);
+        match self.dock_growth.as_mut() {
+            Some(g) if g.target != target => {
+                g.from = g.current(reduce_motion);
+                g.target = target;
+                g.started = Instant::now();
+            },
+            Some(_) => {},
+            None => {
+                self.dock_growth =
+                    Some(DockGrowth { from: target, target, started: Instant::now() })
+            },
+        }
+        let length = self.dock_growth.as_ref().map_or(target, |g| g.current(reduce_motion));
+        if length != target {
+            self.is_dirty = true;
+        }
+        Some(length)
+    }
+
+    /// Advance the animations of granted lengths, and drop the ones of removed
+    /// applets or which are released.
+    pub(crate) fn update_expansions(&mut self) {
+        if self.expansions.is_empty() {
+            return;
+        }
+        let removed: Vec<_> =
+            self.expansions.keys().filter(|c| self.applet_section(c).is_none()).cloned().collect();
+        for client in removed {
+            self.expansions.remove(&client);
+        }
+        let reduce_motion = self.config.reduce_motion;
+        let mut changed = false;
+        self.expansions.retain(|_, e| {
+            let progress = if reduce_motion {
+                1.
+            } else {
+                e.started.elapsed().as_secs_f32() / EXPANSION_DURATION.as_secs_f32()
+            };
+            let progress = smootherstep(progress.min(1.));
+            let current =
+                (e.from as f32 + (e.target as f32 - e.from as f32) * progress).round() as u32;
+            changed |= current != e.current;
+            e.current = current;
+            e.current > 0 || e.target > 0
+        });
+        // the reserved lengths changed, which the layout cache doesn't track
+        if changed {
+            self.layout_cache.invalidate();
+            self.is_dirty = true;
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Offscreen rendering of the panel background for the golden-image tests in
+//! `tests/golden.rs`.
+//!
+//! The rounded rectangle shader is rendered on its own over a background
+//! color, without effects, so the result only depends on the size, scale and
+//! radius of the panel.
+
+use anyhow::{Context, Result};
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        egl::{EGLContext, EGLDevice, EGLDisplay},
+        renderer::{
+            element::{Element, RenderElement},
+            gles::{GlesRenderbuffer, GlesRenderer},
+            Bind, Color32F, ExportMem, Frame, Offscreen, Renderer,
+        },
+    },
+    utils::{Logical, Rectangle, Size, Transform},
+};
+
+use super::corner_element::{init_shaders, RoundedRectangleSettings, RoundedRectangleShader};
+
+/// Renders panel backgrounds into a renderbuffer.
+#[derive(Debug)]
+pub struct BackgroundRenderer {
+    renderer: GlesRenderer,
+}
+
+impl BackgroundRenderer {
+    /// A renderer on the first EGL device with a render node.
+    pub fn new() -> Result<Self> {
+        let device = EGLDevice::enumerate()?
+            .find(|d| d.try_get_render_node().ok().flatten().is_some())
+            .context("No EGL device with a render node")?;
+        let display = unsafe { EGLDisplay::new(device)? };
+        let context = EGLContext::new(&display)?;
+        let mut renderer = unsafe { GlesRenderer::new(context)? };
+        init_shaders(&mut renderer)?;
+        Ok(Self { renderer })
+    }
+
+    /// Render the background of a panel of the logical `size` at `scale`,
+    /// with corners of `radius`, over `bg_color`. Returns the physical size
+    /// and the RGBA8 pixels of the render.
+    pub fn render(
+        &mut self,
+        size: (i32, i32),
+        scale: f64,
+        radius: f32,
+        bg_color: [f32; 4],
+    ) -> Result<((u32, u32), Vec<u8>)> {
+        let renderer = &mut self.renderer;
+        let size =
+            Size::<i32, Logical>::from(size).to_f64().to_physical(scale).to_i32_round::<i32>();
+        let buffer: GlesRenderbuffer = renderer
+            .create_buffer(Fourcc::Abgr8888, size.to_logical(1).to_buffer(1, Transform::Normal))?;
+        renderer.bind(buffer)?;
+
+        let radius = radius * scale as f32;
+        let settings = RoundedRectangleSettings {
+            rad_tl: radius,
+            rad_tr: radius,
+            rad_bl: radius,
+            rad_br: radius,
+            loc: [0., 0.],
+            rect_size: [size.w as f32, size.h as f32],
+            border_width: 0.0,
+            drop_shadow: 0.0,
+            bg_color: [0.0, 0.0, 0.0, 1.0],
+            border_color: [0.0, 0.0, 0.0, 0.0],
+            samples: 1.0,
+            smoothing: 1.0,
+            // without effects
+            ..Default::default()
+        };
+        let element = RoundedRectangleShader::element(
+            renderer,
+            Rectangle::from_loc_and_size((0, 0), size.to_logical(1)),
+            settings,
+        );
+
+        let dst = Rectangle::from_loc_and_size((0, 0), size);
+        let mut frame = renderer.render(size, Transform::Normal)?;
+        frame.clear(Color32F::from(bg_color), &[dst])?;
+        element.draw(&mut frame, element.src(), dst, &[dst], &[])?;
+        frame.finish()?.wait()?;
+
+        let region = Rectangle::from_loc_and_size(
+            (0, 0),
+            size.to_logical(1).to_buffer(1, Transform::Normal),
+        );
+        let mapping = renderer.copy_framebuffer(region, Fourcc::Abgr8888)?;
+        let pixels = renderer.map_texture(&mapping)?.to_vec();
+        Ok(((size.w as u32, size.h as u32), pixels))
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     i32,
     slice::IterMut,
     sync::{atomic::AtomicBool, Arc, MutexGuard},
@@ -24,19 +25,26 @@ use crate::{
 };
 
 use super::{
-    panel_space::{ClientShrinkSize, PanelClient},
+    panel_space::{ClientShrinkSize, Clients, PanelClient},
+    section_layout::{
+        place_slots, plan_shrink, pushed_length, section_targets, SectionSums, SectionTargets,
+        ShrinkCandidate, Slot,
+    },
     PanelSpace,
 };
 use crate::xdg_shell_wrapper::space::WrapperSpace;
 use anyhow::bail;
+use cctk::wayland_client::protocol::wl_surface::WlSurface;
 use cosmic::widget::Id;
-use cosmic_panel_config::PanelAnchor;
+use cosmic_panel_config::{HandlePosition, PanelAnchor, PinPosition, Side};
 use itertools::{chain, Itertools};
-use sctk::shell::WaylandSurface;
 use smithay::{
     desktop::{space::SpaceElement, Space, Window},
-    reexports::wayland_server::Resource,
-    utils::{IsAlive, Physical, Rectangle, Size},
+    reexports::wayland_server::{
+        backend::{ClientId, ObjectId},
+        Resource,
+    },
+    utils::{IsAlive, Logical, Physical, Rectangle, Size},
     wayland::{
         compositor::with_states, fractional_scale::with_fractional_scale, seat::WaylandFocus,
     },
@@ -47,17 +55,20 @@ impl PanelSpace {
     pub(crate) fn layout_(&mut self) -> anyhow::Result<()> {
         self.remap_attempts = self.remap_attempts.saturating_sub(1);
 
-        let make_indices_contiguous = |windows: &mut Vec<(usize, Window, Option<u32>)>| {
-            windows.sort_by(|(a_i, ..), (b_i, ..)| a_i.cmp(b_i));
-            for (j, (i, ..)) in windows.iter_mut().enumerate() {
-                *i = j;
-            }
-        };
+        let make_indices_contiguous =
+            |windows: &mut Vec<(usize, CosmicMappedInternal, Option<u32>)>| {
+                windows.sort_by(|(a_i, ..), (b_i, ..)| a_i.cmp(b_i));
+                for (j, (i, ..)) in windows.iter_mut().enumerate() {
+                    *i = j;
+                }
+            };
 
         let mut left_overflow_button = None;
         let mut right_overflow_button = None;
         let mut center_overflow_button = None;
 
+        let mut builtins = Vec::new();
+
         let to_map = self
             .space
             .elements()
@@ -65,6 +76,10 @@ impl PanelSpace {
             .filter_map(|w| {
                 let w = match w {
                     CosmicMappedInternal::Window(w) => w,
+                    CosmicMappedInternal::Builtin(b) => {
+                        builtins.push(b);
+                        return None;
+                    },
                     CosmicMappedInternal::OverflowButton(b)
                         if overflow_button::with_id(&b, |id| {
                             &self.left_overflow_button_id == id
@@ -98,62 +113,82 @@ impl PanelSpace {
 
         let is_dock = !self.config.expand_to_edges()
             || self.animate_state.as_ref().is_some_and(|a| !(a.cur.expanded > 0.5));
-        let mut windows_left = to_map
-            .iter()
-            .cloned()
-            .filter_map(|w| {
-                let Some(t) = w.toplevel() else {
-                    tracing::warn!("Window {:?} has no toplevel", w.bbox());
-                    return None;
-                };
-                self.clients_left.lock().unwrap().iter().enumerate().find_map(|(i, c)| {
-                    if Some(c.client.id()) == t.wl_surface().client().map(|c| c.id()) {
-                        Some((i, w.clone(), c.minimize_priority))
-                    } else {
-                        None
-                    }
-                })
-            })
-            .collect_vec();
-        make_indices_contiguous(&mut windows_left);
-
-        let mut windows_center = to_map
-            .iter()
-            .cloned()
-            .filter_map(|w| {
-                let Some(t) = w.toplevel() else {
-                    tracing::warn!("Window {:?} has no toplevel", w.bbox());
-                    return None;
-                };
-                self.clients_center.lock().unwrap().iter().enumerate().find_map(|(i, c)| {
-                    if Some(c.client.id()) == t.wl_surface().client().map(|c| c.id()) {
-                        Some((i, w.clone(), c.minimize_priority))
-                    } else {
-                        None
-                    }
-                })
-            })
-            .collect_vec();
-        make_indices_contiguous(&mut windows_center);
-
-        let mut windows_right = to_map
-            .iter()
-            .cloned()
-            .filter_map(|w| {
-                let Some(t) = w.toplevel() else {
-                    tracing::warn!("Window {:?} has no toplevel", w.bbox());
-                    return None;
-                };
-                self.clients_right.lock().unwrap().iter().enumerate().find_map(|(i, c)| {
-                    if Some(c.client.id()) == t.wl_surface().client().map(|c| c.id()) {
-                        Some((i, w.clone(), c.minimize_priority))
-                    } else {
-                        None
-                    }
+        // applets are ordered by their position in the config, builtin applets
+        // being interleaved with the windows of the applet clients
+        let section_applets = |clients: &Clients, list: Option<Vec<String>>, side: Side| {
+            let list = list.unwrap_or_default();
+            let position = |name: &str| list.iter().position(|n| n == name);
+            let clients = clients.lock().unwrap();
+            let mut applets = to_map
+                .iter()
+                .filter_map(|w| {
+                    let Some(t) = w.toplevel() else {
+                        tracing::warn!("Window {:?} has no toplevel", w.bbox());
+                        return None;
+                    };
+                    clients.iter().enumerate().find_map(|(i, c)| {
+                        if Some(c.client.id()) == t.wl_surface().client().map(|c| c.id()) {
+                            Some((
+                                position(&c.name).unwrap_or(list.len() + i),
+                                CosmicMappedInternal::Window(w.clone()),
+                                c.minimize_priority,
+                            ))
+                        } else {
+                            None
+                        }
+                    })
                 })
+                .chain(builtins.iter().filter_map(|b| {
+                    let pos = b.with_program(|p| (p.section == side).then(|| position(&p.name)));
+                    Some((
+                        pos?.unwrap_or(usize::MAX),
+                        CosmicMappedInternal::Builtin(b.clone()),
+                        None,
+                    ))
+                }))
+                .collect_vec();
+            make_indices_contiguous(&mut applets);
+            applets
+        };
+        let [plugins_left, plugins_center, plugins_right] = self.section_plugins(&self.config);
+        let mut windows_left = section_applets(&self.clients_left, plugins_left, Side::WingStart);
+        let mut windows_center =
+            section_applets(&self.clients_center, plugins_center, Side::Center);
+        let mut windows_right = section_applets(&self.clients_right, plugins_right, Side::WingEnd);
+
+        let constraints = LayoutConstraints {
+            dimensions: self.dimensions,
+            scale: self.scale,
+            visibility: self.visibility,
+            anchor_gap: self.anchor_gap,
+            additional_gap: self.additional_gap,
+            bg_color: self.bg_color(),
+            is_dock,
+            overflow_buttons: [
+                left_overflow_button.is_some(),
+                center_overflow_button.is_some(),
+                right_overflow_button.is_some(),
+            ],
+        };
+        let applets = [&windows_left, &windows_center, &windows_right]
+            .into_iter()
+            .enumerate()
+            .flat_map(|(section, windows)| {
+                windows.iter().map(move |(_, w, _)| AppletLayoutKey::new(section, w))
             })
             .collect_vec();
-        make_indices_contiguous(&mut windows_right);
+        // a full layout is needed while animating, while an earlier relayout is
+        // still being applied, or when the panel itself changed
+        let full = self.animate_state.is_some()
+            || self.dock_growing()
+            || self.remap_attempts > 0
+            || self.layout_cache.constraints.as_ref() != Some(&constraints);
+        if !full && self.layout_cache.applets == applets {
+            return Ok(());
+        }
+        if full {
+            self.layout_cache.sections = None;
+        }
 
         if is_dock {
             windows_center = windows_left
@@ -173,15 +208,19 @@ impl PanelSpace {
         );
         if let Err(e) = res.as_ref() {
             info!("Requires relayout: {:?}", e);
+            self.layout_cache.invalidate();
+        } else {
+            self.layout_cache.constraints = Some(constraints);
+            self.layout_cache.applets = applets;
         }
         res
     }
 
     pub(crate) fn layout(
         &mut self,
-        mut windows_left: Vec<(usize, Window, Option<u32>)>,
-        mut windows_center: Vec<(usize, Window, Option<u32>)>,
-        mut windows_right: Vec<(usize, Window, Option<u32>)>,
+        mut windows_left: Vec<(usize, CosmicMappedInternal, Option<u32>)>,
+        mut windows_center: Vec<(usize, CosmicMappedInternal, Option<u32>)>,
+        mut windows_right: Vec<(usize, CosmicMappedInternal, Option<u32>)>,
         mut left_overflow_button: Option<OverflowButtonElement>,
         mut right_overflow_button: Option<OverflowButtonElement>,
         mut center_overflow_button: Option<OverflowButtonElement>,
@@ -227,7 +266,7 @@ impl PanelSpace {
         }
 
         fn map_fn(
-            (i, w, _): &(usize, Window, Option<u32>),
+            (i, w, _): &(usize, CosmicMappedInternal, Option<u32>),
             anchor: PanelAnchor,
             alignment: Alignment,
         ) -> (Alignment, usize, i32, i32, i32) {
@@ -267,13 +306,20 @@ impl PanelSpace {
             }
         }
 
+        // lengths granted to applets with `request_expansion`
+        let reserved = |windows: &[(usize, CosmicMappedInternal, Option<u32>)]| -> f64 {
+            windows.iter().map(|(_, w, _)| self.expansion_reserve(w)).sum()
+        };
+
         let left = windows_left.iter().map(|e| {
             let l = map_fn(e, anchor, Alignment::Left);
             l
         });
 
         let left_sum_scaled =
-            left.clone().map(|(_, _, _, _, suggested_length)| suggested_length).sum::<i32>() as f64
+            (left.clone().map(|(_, _, _, _, suggested_length)| suggested_length).sum::<i32>()
+                as f64
+                + reserved(&windows_left))
                 * self.scale
                 + spacing_scaled * windows_left.len().saturating_sub(1) as f64;
         let left_sum_scaled = if let Some(left_button) = left_overflow_button.as_ref() {
@@ -287,8 +333,9 @@ impl PanelSpace {
 
         let center = windows_center.iter().map(|e| map_fn(e, anchor, Alignment::Center));
         let center_sum_scaled =
-            center.clone().map(|(_, _, _, _, suggested_length)| suggested_length).sum::<i32>()
+            (center.clone().map(|(_, _, _, _, suggested_length)| suggested_length).sum::<i32>()
                 as f64
+                + reserved(&windows_center))
                 * self.scale
                 + spacing_scaled * windows_center.len().saturating_sub(1) as f64;
         let center_sum_scaled = if let Some(center_button) = center_overflow_button.as_ref() {
@@ -301,11 +348,13 @@ impl PanelSpace {
         };
 
         let right = windows_right.iter().map(|e| map_fn(e, anchor, Alignment::Right));
-        let right_sum_scaled =
-            right.clone().map(|(_, _, _length, _, suggested_length)| suggested_length).sum::<i32>()
-                as f64
-                * self.scale
-                + spacing_scaled * windows_right.len().saturating_sub(1) as f64;
+        let right_sum_scaled = (right
+            .clone()
+            .map(|(_, _, _length, _, suggested_length)| suggested_length)
+            .sum::<i32>() as f64
+            + reserved(&windows_right))
+            * self.scale
+            + spacing_scaled * windows_right.len().saturating_sub(1) as f64;
         let right_sum_scaled = if let Some(right_button) = right_overflow_button.as_ref() {
             let size = right_button.bbox().size.to_f64();
             right_sum_scaled
@@ -320,12 +369,19 @@ impl PanelSpace {
             + padding_scaled * 2.0
             + spacing_scaled * num_lists.saturating_sub(1) as f64)
             as i32;
-        let new_list_thickness = (2.0 * padding_scaled
-            + chain!(left.clone(), center.clone(), right.clone())
-                .map(|(_, _, _, thickness, _)| thickness)
-                .max()
-                .unwrap_or(0) as f64
-                * self.scale) as i32;
+        // while the applet size changes, the thickness animates to the one of
+        // the new size
+        let new_list_thickness = match self.animate_state.as_ref().and_then(|a| a.cur.thickness) {
+            Some(thickness) => (thickness as f64 * self.scale).round() as i32,
+            None => {
+                (2.0 * padding_scaled
+                    + chain!(left.clone(), center.clone(), right.clone())
+                        .map(|(_, _, _, thickness, _)| thickness)
+                        .max()
+                        .unwrap_or(0) as f64
+                        * self.scale) as i32
+            },
+        };
 
         self.actual_size = Size::<i32, Physical>::from(if self.config.is_horizontal() {
             (new_list_length, new_list_thickness)
@@ -336,6 +392,24 @@ impl PanelSpace {
         .to_logical(self.scale)
         .to_i32_round();
 
+        // a dock which grows with its applets is at least as long as its
+        // animated length, and at most as long as its longest length
+        let grown_length = if is_dock && self.animate_state.is_none() {
+            let needed =
+                if self.config.is_horizontal() { self.actual_size.w } else { self.actual_size.h };
+            self.grown_dock_length(needed)
+        } else {
+            None
+        };
+        if let Some(length) = grown_length {
+            let max_length = self.config.dock_auto_grow.map_or(i32::MAX, |g| g.max_length as i32);
+            if self.config.is_horizontal() {
+                self.actual_size.w = self.actual_size.w.max(length).min(max_length);
+            } else {
+                self.actual_size.h = self.actual_size.h.max(length).min(max_length);
+            }
+        }
+
         let actual_size_constrained = self.constrain_dim(self.actual_size, Some(gap as u32));
         if self.config.is_horizontal() {
             self.actual_size.h = actual_size_constrained.h;
@@ -376,7 +450,7 @@ impl PanelSpace {
                 + (new_list_dim_length - new_logical_length) as f32 * anim_state.cur.expanded)
                 as i32
         } else if is_dock {
-            new_logical_length
+            grown_length.map_or(new_logical_length, |l| l.min(new_logical_length))
         } else {
             new_list_dim_length
         };
@@ -391,57 +465,64 @@ impl PanelSpace {
             - right_sum
             - padding_u32 as f64;
 
-        let one_third = (layer_major as f64 - (spacing_u32 * num_lists.saturating_sub(1)) as f64)
-            / (3.min(num_lists) as f64);
-        let one_half = layer_major as f64 / (2.min(num_lists) as f64);
-        let larger_side = left_sum.max(right_sum);
-
-        let mut target_center_len =
-            (layer_major as f64 - larger_side * (2.)).max(one_third).min(layer_major as f64);
-        if num_lists == 1 {
-            target_center_len -= padding_u32 as f64 * 2.;
-        } else {
-            target_center_len -= spacing_u32 as f64;
-        }
-        let target_left_len = if !has_center {
-            (layer_major as f64
-                - right_sum.min(one_half)
-                - (spacing_u32 as f64) / 2.
-                - padding_u32 as f64)
-                .max(one_half)
-        } else {
-            (one_half
-                - target_center_len.min(center_sum) / 2.
-                - (spacing_u32 as f64) / 2.
-                - padding_u32 as f64)
-                .max(one_third)
-        }
-        .min(layer_major as f64);
-
-        let target_right_len = if !has_center {
-            (layer_major as f64
-                - left_sum.min(one_half)
-                - (spacing_u32 as f64) / 2.
-                - padding_u32 as f64)
-                .max(one_half)
-        } else {
-            (one_half
-                - target_center_len.min(center_sum) / 2.
-                - (spacing_u32 as f64) / 2.
-                - padding_u32 as f64)
-                .max(one_third)
-        }
-        .min(layer_major as f64);
+        // pinned applets keep their position, and the other applets of every
+        // section move out of their way. The sections get longer by that, so
+        // the pushed applets shrink or overflow like any other.
+        let slots = |windows: &[(usize, CosmicMappedInternal, Option<u32>)]| -> Vec<Slot> {
+            windows
+                .iter()
+                .map(|(_, w, _)| {
+                    let size = applet_logical_size(w);
+                    let length = if self.config.is_horizontal() { size.w } else { size.h }
+                        + self.expansion_reserve(w);
+                    let pinned =
+                        self.pinned_start(w, length, container_lengthwise_pos, container_length);
+                    Slot { length, pinned }
+                })
+                .collect()
+        };
+        let (slots_left, slots_center, slots_right) =
+            (slots(&windows_left), slots(&windows_center), slots(&windows_right));
+        let pinned: Vec<(f64, f64)> = chain!(&slots_left, &slots_center, &slots_right)
+            .filter_map(|s| s.pinned.map(|start| (start, start + s.length)))
+            .sorted_by(|a, b| a.0.total_cmp(&b.0))
+            .collect();
+        let spacing = spacing_u32 as f64;
+        let left_sum = left_sum + pushed_length(left_pos, spacing, &slots_left, &pinned);
+        let center_sum = center_sum + pushed_length(center_pos, spacing, &slots_center, &pinned);
+        let pushed_right = pushed_length(right_pos, spacing, &slots_right, &pinned);
+        let right_sum = right_sum + pushed_right;
+        // the right section still ends at the end of the panel
+        right_pos -= pushed_right;
+
+        let sums = SectionSums { left: left_sum, center: center_sum, right: right_sum };
+        let targets =
+            section_targets(layer_major, sums, spacing_u32, padding_u32, num_lists, has_center);
+        let SectionTargets {
+            left: target_left_len,
+            center: target_center_len,
+            right: target_right_len,
+        } = targets;
+        // only sections whose length or target changed since the last layout
+        // need their overflow to be resolved again
+        let [relayout_left, relayout_center, relayout_right] = match self.layout_cache.sections {
+            Some((prev_sums, prev_targets)) => [
+                prev_sums.left != sums.left || prev_targets.left != targets.left,
+                prev_sums.center != sums.center || prev_targets.center != targets.center,
+                prev_sums.right != sums.right || prev_targets.right != targets.right,
+            ],
+            None => [true; 3],
+        };
         let suggested_size = ((self.config.size.get_applet_icon_size(true) as f64
             + self.config.size.get_applet_padding(true) as f64 * 2.)
             * -1.5 // allows some wiggle room
             * self.scale) as i32;
 
         let center_overflow = (center_sum - target_center_len) as i32;
-        if center_overflow < suggested_size {
+        if relayout_center && center_overflow < suggested_size {
             // check if it can be expanded
             self.relax_overflow_center(center_overflow.unsigned_abs(), &mut center_overflow_button)
-        } else if center_overflow > 0 {
+        } else if relayout_center && center_overflow > 0 {
             let overflow = self.shrink_center((center_sum - target_center_len) as u32);
             bail!("overflow: {}", overflow)
         }
@@ -449,31 +530,31 @@ impl PanelSpace {
         if !is_dock && self.animate_state.is_none() {
             let left_overflow = (left_sum - target_left_len) as i32;
 
-            if left_overflow < suggested_size {
+            if relayout_left && left_overflow < suggested_size {
                 self.relax_overflow_left(left_overflow.unsigned_abs(), &mut left_overflow_button);
-            } else if left_overflow > 0 {
+            } else if relayout_left && left_overflow > 0 {
                 info!("target: {target_left_len}, actual: {left_sum}");
                 let overflow = self.shrink_left(left_overflow as u32);
                 bail!("left overflow: {} {}", left_overflow, overflow)
             }
 
             let right_overflow = (right_sum - target_right_len) as i32;
-            if right_overflow < suggested_size {
+            if relayout_right && right_overflow < suggested_size {
                 self.relax_overflow_right(
                     right_overflow.unsigned_abs(),
                     &mut right_overflow_button,
                 );
-            } else if right_overflow > 0 {
+            } else if relayout_right && right_overflow > 0 {
                 let overflow = self.shrink_right(right_overflow as u32);
                 bail!("right overflow: {} {}", right_overflow, overflow)
             }
         }
 
-        // update input region of panel when list changes
-        let (input_region, layer) = match (self.input_region.as_ref(), self.layer.as_ref()) {
-            (Some(r), Some(layer)) => (r, layer),
-            _ => panic!("input region or layer missing"),
-        };
+        self.layout_cache.sections = Some((sums, targets));
+
+        // the surface is cloned, as the panel is mutated while it is used. It is
+        // only missing while the layout is benchmarked.
+        let panel_surface = self.panel_surface().cloned();
 
         // must use logical coordinates for layout here
 
@@ -529,27 +610,32 @@ impl PanelSpace {
             center_pos += size.h + spacing_u32 as f64;
         }
 
-        let mut map_windows = |windows: IterMut<'_, (usize, Window, Option<u32>)>,
-                               mut prev|
+        let minimize_app_ids: HashMap<ClientId, Vec<String>> = chain!(
+            self.clients_left.lock().unwrap().iter(),
+            self.clients_center.lock().unwrap().iter(),
+            self.clients_right.lock().unwrap().iter()
+        )
+        .filter(|c| c.minimize_priority.is_some())
+        .map(|c| (c.client.id(), c.minimize_app_ids.clone()))
+        .collect();
+
+        let positions_left = place_slots(left_pos, spacing, &slots_left, &pinned);
+        let positions_center = place_slots(center_pos, spacing, &slots_center, &pinned);
+        let positions_right = place_slots(right_pos, spacing, &slots_right, &pinned);
+
+        let mut map_windows = |windows: IterMut<'_, (usize, CosmicMappedInternal, Option<u32>)>,
+                               positions: Vec<f64>,
+                               slots: &[Slot],
+                               mut prev: f64|
          -> f64 {
-            for (_, w, minimize_priority) in windows {
-                // XXX this is a hack to get the logical size of the window
-                // TODO improve how this is done
-                let mut size = w.bbox().size.to_f64();
-                let configured_size =
-                    w.toplevel().and_then(|t| t.current_state().bounds).unwrap_or_default();
-                if configured_size.w != 0 {
-                    size.w = size.w.min(configured_size.w as f64);
-                }
-                if configured_size.h != 0 {
-                    size.h = size.h.min(configured_size.h as f64);
-                }
-                let cur: f64 = prev;
+            for (((_, w, minimize_priority), cur), slot) in windows.zip(positions).zip(slots) {
+                let size = applet_logical_size(w);
                 let (x, y);
 
                 if self.config.is_horizontal() {
+                    // centered in the length reserved for its expansion
                     let cur = (
-                        cur,
+                        cur + (slot.length - size.w) / 2.,
                         margin_offset
                             + center_in_bar(
                                 new_logical_crosswise_dim.try_into().unwrap(),
@@ -557,8 +643,8 @@ impl PanelSpace {
                             ),
                     );
                     (x, y) = (cur.0 as i32, cur.1);
-                    prev += size.w + spacing_u32 as f64;
-                    self.space.map_element(CosmicMappedInternal::Window(w.clone()), (x, y), false);
+                    prev = prev.max(cur.0 + (slot.length + size.w) / 2. + spacing);
+                    self.space.map_element(w.clone(), (x, y), false);
                 } else {
                     let cur = (
                         margin_offset
@@ -566,30 +652,37 @@ impl PanelSpace {
                                 new_logical_crosswise_dim.try_into().unwrap(),
                                 size.w as u32,
                             ),
-                        cur,
+                        cur + (slot.length - size.h) / 2.,
                     );
                     (x, y) = (cur.0, cur.1 as i32);
-                    prev += size.h + spacing_u32 as f64;
-                    self.space.map_element(CosmicMappedInternal::Window(w.clone()), (x, y), false);
+                    prev = prev.max(cur.1 + (slot.length + size.h) / 2. + spacing);
+                    self.space.map_element(w.clone(), (x, y), false);
                 }
-                if minimize_priority.is_some() {
+                let client = w.toplevel().and_then(|t| t.wl_surface().client()).map(|c| c.id());
+                if let (Some(client), Some(panel_surface)) =
+                    (client.filter(|_| minimize_priority.is_some()), panel_surface.as_ref())
+                {
                     let new_rect = Rectangle {
                         loc: (x, y).into(),
                         size: ((size.w.ceil() as i32).max(1), (size.w.ceil() as i32).max(1)).into(),
                     };
-                    if new_rect != self.minimize_applet_rect
-                        && Instant::now().duration_since(self.last_minimize_update)
-                            > Duration::from_secs(1)
-                    {
-                        self.minimize_applet_rect = new_rect;
-                        self.last_minimize_update = Instant::now();
+                    if self.minimize_applet_rects.get(&client).map_or(
+                        true,
+                        |(rect, last_update)| {
+                            *rect != new_rect && last_update.elapsed() > Duration::from_secs(1)
+                        },
+                    ) {
+                        self.minimize_applet_rects
+                            .insert(client.clone(), (new_rect, Instant::now()));
                         let output = self.output.as_ref().map(|o| o.1.name()).unwrap_or_default();
                         _ = self.panel_tx.send(crate::PanelCalloopMsg::MinimizeRect {
                             output,
                             applet_info: MinimizeApplet {
                                 priority: if is_dock { 1 } else { 0 },
                                 rect: new_rect,
-                                surface: layer.wl_surface().clone(),
+                                surface: panel_surface.clone(),
+                                app_ids: minimize_app_ids.get(&client).cloned().unwrap_or_default(),
+                                client,
                             },
                         });
                     }
@@ -597,12 +690,12 @@ impl PanelSpace {
             }
             prev
         };
-        let left_pos = map_windows(windows_left.iter_mut(), left_pos);
+        let left_pos = map_windows(windows_left.iter_mut(), positions_left, &slots_left, left_pos);
 
         // will be already offset if dock
-        map_windows(windows_center.iter_mut(), center_pos);
+        map_windows(windows_center.iter_mut(), positions_center, &slots_center, center_pos);
 
-        map_windows(windows_right.iter_mut(), right_pos);
+        map_windows(windows_right.iter_mut(), positions_right, &slots_right, right_pos);
         // if there is a left overflow_button, map it
         if let Some(left_button) = left_overflow_button {
             let size = left_button.bbox().size.to_f64();
@@ -686,6 +779,10 @@ impl PanelSpace {
                 drop_shadow: 0.0,
                 bg_color: [0.0, 0.0, 0.0, 1.0],
                 border_color: [0.0, 0.0, 0.0, 0.0],
+                samples: self.config.corner_quality.samples.clamp(1, 4) as f32,
+                smoothing: self.config.corner_quality.smoothing.max(0.0),
+                // the effects are updated every frame
+                ..self.panel_rect_settings
             };
 
             let Some(output) = self.output.as_ref().map(|o| o.1.clone()) else {
@@ -726,6 +823,11 @@ impl PanelSpace {
             self.background_element = Some(bg.clone());
             self.space.map_element(CosmicMappedInternal::Background(bg), (0, 0), false);
         }
+        // update input region of panel when list changes
+        let (input_region, panel_surface) = match (self.input_region.as_ref(), panel_surface) {
+            (Some(r), Some(surface)) => (r, surface),
+            _ => panic!("input region or panel surface missing"),
+        };
         input_region.subtract(0, 0, i32::MAX, i32::MAX);
         let anim_gap = self.anchor_gap;
 
@@ -763,6 +865,7 @@ impl PanelSpace {
             };
 
             input_region.add(loc.0, loc.1, size.0, size.1);
+            self.input_rect = Rectangle::from_loc_and_size(loc, size);
         } else {
             let (loc, size) = match self.config.anchor {
                 PanelAnchor::Left => ((-1, 0), (new_dim.w + 1 + anim_gap, new_dim.h)),
@@ -772,8 +875,11 @@ impl PanelSpace {
             };
 
             input_region.add(loc.0, loc.1, size.0, size.1);
+            self.input_rect = Rectangle::from_loc_and_size(loc, size);
         };
-        layer.wl_surface().set_input_region(Some(input_region.wl_region()));
+        panel_surface.set_input_region(Some(input_region.wl_region()));
+        self.update_opaque_region(&panel_surface);
+        self.handle_region = false;
 
         self.reorder_overflow_space(OverflowSection::Left);
         self.reorder_overflow_space(OverflowSection::Center);
@@ -782,6 +888,61 @@ impl PanelSpace {
         Ok(())
     }
 
+    /// Limit the input region of the hidden panel to the part of its handle
+    /// which reveals it, and restore it once the panel is revealed.
+    pub(crate) fn update_handle_region(&mut self) {
+        let position = self.config.autohide.as_ref().map(|a| a.handle_position).unwrap_or_default();
+        let limited =
+            matches!(self.visibility, Visibility::Hidden) && position != HandlePosition::Full;
+        if limited == self.handle_region {
+            return;
+        }
+        let (Some(input_region), Some(panel_surface)) =
+            (self.input_region.as_ref(), self.panel_surface())
+        else {
+            return;
+        };
+        let mut rect = self.input_rect;
+        if limited {
+            if self.config.is_horizontal() {
+                let (start, len) = position.span(rect.size.w);
+                rect.loc.x += start;
+                rect.size.w = len;
+            } else {
+                let (start, len) = position.span(rect.size.h);
+                rect.loc.y += start;
+                rect.size.h = len;
+            }
+        }
+        input_region.subtract(0, 0, i32::MAX, i32::MAX);
+        input_region.add(rect.loc.x, rect.loc.y, rect.size.w, rect.size.h);
+        panel_surface.set_input_region(Some(input_region.wl_region()));
+        panel_surface.commit();
+        self.handle_region = limited;
+    }
+
+    /// Report the background without its rounded corners as opaque, while it
+    /// is fully opaque and not moving.
+    fn update_opaque_region(&self, panel_surface: &WlSurface) {
+        let Some(opaque_region) = self.opaque_region.as_ref() else {
+            return;
+        };
+        opaque_region.subtract(0, 0, i32::MAX, i32::MAX);
+        let settled = self.animate_state.is_none() && self.anchor_gap == 0;
+        let background = self.background_element.as_ref().filter(|_| settled).map(|bg| {
+            bg.with_program(|p| {
+                (p.logical_pos, p.logical_width, p.logical_height, p.radius, p.color[3])
+            })
+        });
+        if let Some(((x, y), w, h, radius, _)) = background.filter(|b| b.4 >= 1.0) {
+            let r = radius.into_iter().fold(0., f32::max).ceil() as i32;
+            let r = r.min(w / 2).min(h / 2);
+            opaque_region.add(x + r, y, w - 2 * r, h);
+            opaque_region.add(x, y + r, w, h - 2 * r);
+        }
+        panel_surface.set_opaque_region(Some(opaque_region.wl_region()));
+    }
+
     // reorder overflow space windows, and remove dead windows
     fn reorder_overflow_space(&mut self, section: OverflowSection) {
         let (space, clients) = match section {
@@ -985,75 +1146,64 @@ impl PanelSpace {
         info!("Overflow: {overflow} in section {section:?}");
         let unit_size = self.config.size.get_applet_icon_size_with_padding(true);
 
-        let mut sum = 0.;
-        for (w, priority, min_units) in clients.shrinkable.iter_mut() {
-            if overflow == 0 {
-                break;
-            }
-            let suggested_bounds = w
-                .toplevel()
-                .map(|t| {
-                    let s = t.current_state();
-                    s.bounds.unwrap_or_default()
-                })
-                .unwrap();
+        let candidates = clients
+            .shrinkable
+            .iter()
+            .map(|(w, priority, min_units)| {
+                let suggested_bounds =
+                    w.toplevel().and_then(|t| t.current_state().bounds).unwrap_or_default();
 
-            let mut size = w.bbox().size.to_f64();
-            if size.w < 1. {
-                size.w = 1.;
-            }
-            if size.h < 1. {
-                size.h = 1.;
-            }
-            let configured_size = w
-                .toplevel()
-                .and_then(|t| t.current_state().size)
-                .map(|s| s.to_f64())
-                .unwrap_or(size);
-            if configured_size.w >= 1. {
-                size.w = size.w.min(configured_size.w as f64);
-            }
-            if configured_size.h >= 1. {
-                size.h = size.h.min(configured_size.h as f64);
-            }
+                let mut size = w.bbox().size.to_f64();
+                if size.w < 1. {
+                    size.w = 1.;
+                }
+                if size.h < 1. {
+                    size.h = 1.;
+                }
+                let configured_size = w
+                    .toplevel()
+                    .and_then(|t| t.current_state().size)
+                    .map(|s| s.to_f64())
+                    .unwrap_or(size);
+                if configured_size.w >= 1. {
+                    size.w = size.w.min(configured_size.w as f64);
+                }
+                if configured_size.h >= 1. {
+                    size.h = size.h.min(configured_size.h as f64);
+                }
 
-            let (major_dim, suggested_dim) = if self.config.is_horizontal() {
-                (size.w, suggested_bounds.w)
-            } else {
-                (size.h, suggested_bounds.h)
-            };
-            sum += major_dim;
-            if (major_dim < min_units.to_pixels(unit_size) as f64 || *priority < 0)
-                && !force_smaller
-            {
-                continue;
-            }
-            let new_dim = (major_dim as u32).saturating_sub(overflow);
-            let new_dim = if force_smaller || new_dim >= min_units.to_pixels(unit_size) {
-                new_dim
-            } else {
-                min_units.to_pixels(unit_size)
-            }
-            .max(1);
-            let diff = (major_dim as u32).saturating_sub(new_dim);
-            if diff == 0 && suggested_dim as u32 == new_dim {
-                continue;
-            }
-            tracing::info!("Shrinking window {size:?} by {diff} to {new_dim} {suggested_dim}");
+                let (length, suggested) = if self.config.is_horizontal() {
+                    (size.w, suggested_bounds.w)
+                } else {
+                    (size.h, suggested_bounds.h)
+                };
+                ShrinkCandidate {
+                    length,
+                    suggested,
+                    priority: *priority,
+                    min_length: min_units.to_pixels(unit_size),
+                }
+            })
+            .collect_vec();
+        let sum: f64 = candidates.iter().map(|c| c.length).sum();
 
-            if let Some(t) = w.toplevel() {
-                t.with_pending_state(|s| {
-                    if self.config.is_horizontal() {
-                        s.size = None;
-                        s.bounds = Some((new_dim as i32, 0).into());
-                    } else {
-                        s.size = None;
-                        s.bounds = Some((0, new_dim as i32).into());
-                    }
-                });
-                t.send_pending_configure();
-                overflow = overflow.saturating_sub(diff);
-            }
+        let (planned, remaining) = plan_shrink(overflow, &candidates, force_smaller);
+        overflow = remaining;
+        for (i, new_dim) in planned {
+            let Some(t) = clients.shrinkable[i].0.toplevel() else {
+                continue;
+            };
+            tracing::info!("Shrinking window {:?} to {new_dim}", candidates[i]);
+            t.with_pending_state(|s| {
+                if self.config.is_horizontal() {
+                    s.size = None;
+                    s.bounds = Some((new_dim as i32, 0).into());
+                } else {
+                    s.size = None;
+                    s.bounds = Some((0, new_dim as i32).into());
+                }
+            });
+            t.send_pending_configure();
         }
         if overflow > 0 {
             overflow = self.move_to_overflow(
@@ -1508,6 +1658,56 @@ impl PanelSpace {
             }
         }
     }
+
+    /// The start of the applet `w` with the length `applet_length` if it is
+    /// pinned, in the container at `start` with the length `length`.
+    fn pinned_start(
+        &self,
+        w: &CosmicMappedInternal,
+        applet_length: f64,
+        start: i32,
+        length: i32,
+    ) -> Option<f64> {
+        if self.config.pinned_applets.is_empty() {
+            return None;
+        }
+        let name = self.applet_name(w)?;
+        let pin = self.config.pinned_applets.iter().find(|p| p.applet == name)?;
+        let (start, length) = (start as f64, length as f64);
+        Some(match pin.position {
+            PinPosition::Center => start + (length - applet_length) / 2.,
+            PinPosition::FromStart(n) => start + n as f64,
+            PinPosition::FromEnd(n) => start + length - n as f64 - applet_length,
+        })
+    }
+
+    /// The configured name of the applet `w`.
+    fn applet_name(&self, w: &CosmicMappedInternal) -> Option<String> {
+        match w {
+            CosmicMappedInternal::Window(w) => {
+                let client = w.toplevel()?.wl_surface().client()?.id();
+                self.applets.get(&client).map(|a| a.name)
+            },
+            CosmicMappedInternal::Builtin(b) => Some(b.with_program(|p| p.name.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// The logical size of the applet `w`, within the bounds it was configured
+/// with.
+pub(super) fn applet_logical_size(w: &CosmicMappedInternal) -> Size<f64, Logical> {
+    // XXX this is a hack to get the logical size of the window
+    // TODO improve how this is done
+    let mut size = w.bbox().size.to_f64();
+    let configured_size = w.toplevel().and_then(|t| t.current_state().bounds).unwrap_or_default();
+    if configured_size.w != 0 {
+        size.w = size.w.min(configured_size.w as f64);
+    }
+    if configured_size.h != 0 {
+        size.h = size.h.min(configured_size.h as f64);
+    }
+    size
 }
 
 // if middle collides with left or right, it must be constrained
@@ -1536,6 +1736,68 @@ impl PanelSpace {
 // they can have nested popups in a common use case now too.
 // overflow buttons go in the original space.
 
+/// Inputs of the last successful layout. A commit which changes no applet
+/// size doesn't need a layout at all, and the overflow of a section only has
+/// to be resolved again if its length or target length changed.
+#[derive(Debug, Default)]
+pub struct LayoutCache {
+    constraints: Option<LayoutConstraints>,
+    applets: Vec<AppletLayoutKey>,
+    sections: Option<(SectionSums, SectionTargets)>,
+}
+
+impl LayoutCache {
+    /// Force the next layout to be a full one.
+    pub fn invalidate(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Length in logical pixels left free in the left, center and right
+    /// section by the last layout.
+    pub(crate) fn free_lengths(&self) -> Option<[f64; 3]> {
+        let (sums, targets) = self.sections?;
+        Some([
+            (targets.left - sums.left).max(0.),
+            (targets.center - sums.center).max(0.),
+            (targets.right - sums.right).max(0.),
+        ])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct LayoutConstraints {
+    dimensions: Size<i32, Logical>,
+    scale: f64,
+    visibility: Visibility,
+    anchor_gap: i32,
+    additional_gap: i32,
+    bg_color: [f32; 4],
+    is_dock: bool,
+    overflow_buttons: [bool; 3],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct AppletLayoutKey {
+    section: usize,
+    surface: Option<ObjectId>,
+    bbox: Size<i32, Logical>,
+    size: Option<Size<i32, Logical>>,
+    bounds: Option<Size<i32, Logical>>,
+}
+
+impl AppletLayoutKey {
+    fn new(section: usize, w: &CosmicMappedInternal) -> Self {
+        let (size, bounds) = w
+            .toplevel()
+            .map(|t| {
+                let s = t.current_state();
+                (s.size, s.bounds)
+            })
+            .unwrap_or_default();
+        Self { section, surface: w.wl_surface().map(|s| s.id()), bbox: w.bbox().size, size, bounds }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum OverflowSection {
     Left,
@@ -1,16 +1,39 @@
 //! PanelSpace is a container for all running panels, spawning each as a
 //! separate process and compositing them in a layer shell surface as configured
-//! PanelSpace *partially* implements the WrapperSpace abstraction
+//! PanelSpace implements the WrapperSpace abstraction, except for RootSpace,
+//! which is implemented by its container
 
+mod applet_registry;
+#[cfg(feature = "layout-bench")]
+pub(crate) mod bench;
+mod builtin_applets;
+mod context_menu;
 mod corner_element;
+mod debug_overlay;
+mod edit_mode;
+mod effects;
+mod expansion;
+#[cfg(feature = "golden-tests")]
+pub mod golden;
 mod layout;
+mod osd_space;
 mod overflow;
 mod panel_space;
 mod popup;
+mod positioner;
+mod preview;
 mod render;
+pub mod section_layout;
+mod snapshot;
+mod swipe;
 mod wrapper_space;
 
-pub(crate) use panel_space::{AppletMsg, PanelColors, PanelSpace};
+pub(crate) use applet_registry::{AppletHandle, AppletRegistry};
+pub(crate) use debug_overlay::DebugOverlay;
+pub(crate) use edit_mode::{same_sections, EditMode};
+pub(crate) use osd_space::{OsdSpace, OSD_MARGIN, OSD_TIMEOUT};
+pub(crate) use panel_space::{AppletMsg, Clients, PanelColors, PanelSpace};
+pub(crate) use preview::preview_config;
 
 #[derive(Debug)]
 pub enum Alignment {
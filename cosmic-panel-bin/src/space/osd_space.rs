@@ -0,0 +1,421 @@
+//! OsdSpace shows an on-screen display, e.g. for the volume or brightness
+//! level, or a notification toast, for a few seconds.
+//!
+//! Like a PanelSpace, it is a space of the container on one output. It renders
+//! an iced element like the context menu in an overlay layer surface, so it is
+//! shown on outputs without a panel too. It is placed next to the first panel
+//! of the output, or at the bottom of an output without one. It has no clients
+//! and doesn't take input, so it only implements WrapperSpace and
+//! OutputHandler.
+
+use std::{
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use calloop::LoopHandle;
+use cctk::wayland_protocols::wp::{
+    fractional_scale::v1::client::wp_fractional_scale_v1::WpFractionalScaleV1,
+    viewporter::client::wp_viewport::WpViewport,
+};
+use cosmic_panel_config::PanelAnchor;
+use sctk::{
+    compositor::{CompositorState, Region},
+    output::OutputInfo,
+    reexports::client::{
+        protocol::{wl_output as c_wl_output, wl_surface as c_wl_surface},
+        Connection, Proxy, QueueHandle,
+    },
+    shell::{
+        wlr_layer::{
+            Anchor, KeyboardInteractivity, Layer, LayerShell, LayerSurface, LayerSurfaceConfigure,
+        },
+        WaylandSurface,
+    },
+};
+use smithay::{
+    backend::{
+        egl::EGLSurface,
+        renderer::{
+            damage::OutputDamageTracker,
+            element::{memory::MemoryRenderBufferRenderElement, AsRenderElements},
+            gles::GlesRenderer,
+            Bind, Unbind,
+        },
+    },
+    desktop::space::SpaceElement,
+    output::Output,
+    reexports::wayland_server::protocol::wl_surface::WlSurface as s_WlSurface,
+    utils::{Logical, Rectangle, Size},
+};
+use wayland_egl::WlEglSurface;
+
+use crate::{
+    iced::elements::osd::{osd_element, Message, OsdContent, OsdElement},
+    xdg_shell_wrapper::{
+        client::handlers::overlap::OverlapNotifyV1,
+        config::{WrapperConfig, WrapperOutput},
+        shared_state::GlobalState,
+        space::{ClientEglSurface, OutputHandler, WrapperSpace},
+        wp_fractional_scaling::FractionalScalingManager,
+        wp_security_context::SecurityContextManager,
+        wp_viewporter::ViewporterState,
+    },
+};
+
+/// How long an OSD is shown after it was last updated.
+pub const OSD_TIMEOUT: Duration = Duration::from_secs(2);
+/// Logical distance of an OSD from the panel, or from the edge of the output.
+pub const OSD_MARGIN: i32 = 8;
+
+/// The output an OSD is shown on.
+#[derive(Debug, Clone, Default)]
+pub struct OsdConfig {
+    pub output: String,
+}
+
+impl WrapperConfig for OsdConfig {
+    fn outputs(&self) -> WrapperOutput {
+        WrapperOutput::Name(vec![self.output.clone()])
+    }
+
+    fn name(&self) -> &str {
+        "cosmic-panel-osd"
+    }
+}
+
+#[derive(Debug)]
+pub struct OsdSpace {
+    // XXX implicitly drops egl_surface first to avoid segfault
+    egl_surface: Option<Rc<EGLSurface>>,
+    config: OsdConfig,
+    output: Option<(c_wl_output::WlOutput, Output, OutputInfo)>,
+    layer: Option<LayerSurface>,
+    element: OsdElement,
+    anchor: PanelAnchor,
+    margin: i32,
+    size: Size<i32, Logical>,
+    scale: f64,
+    damage_tracked_renderer: OutputDamageTracker,
+    _fractional_scale: Option<WpFractionalScaleV1>,
+    viewport: Option<WpViewport>,
+    _input_region: Option<Region>,
+    configured: bool,
+    has_frame: bool,
+    dirty: bool,
+    expires: Instant,
+}
+
+impl OsdSpace {
+    /// Create the OSD showing `content` on the output named `output`, next to
+    /// the edge `anchor` with the distance `margin`. Its surface is created
+    /// once the output is added to the space.
+    pub fn new(
+        output: String,
+        content: OsdContent,
+        anchor: PanelAnchor,
+        margin: i32,
+        scale: f64,
+        theme: cosmic::Theme,
+        loop_handle: LoopHandle<'static, GlobalState>,
+    ) -> Self {
+        // the OSD doesn't belong to a panel whose redraw its element could request
+        let element = osd_element(content, loop_handle, theme, usize::MAX);
+        Self {
+            egl_surface: None,
+            config: OsdConfig { output },
+            output: None,
+            layer: None,
+            element,
+            anchor,
+            margin,
+            size: Size::default(),
+            scale,
+            damage_tracked_renderer: OutputDamageTracker::new(
+                (1, 1),
+                scale,
+                smithay::utils::Transform::Flipped180,
+            ),
+            _fractional_scale: None,
+            viewport: None,
+            _input_region: None,
+            configured: false,
+            has_frame: true,
+            dirty: false,
+            expires: Instant::now() + OSD_TIMEOUT,
+        }
+    }
+
+    /// Replace the content of the shown OSD and restart its timeout.
+    pub fn set_content(&mut self, content: OsdContent) {
+        self.element.queue_message(Message::Set(content));
+        self.expires = Instant::now() + OSD_TIMEOUT;
+        self.dirty = true;
+    }
+
+    /// When the OSD should be hidden.
+    pub fn expires(&self) -> Instant {
+        self.expires
+    }
+
+    pub fn output(&self) -> Option<&c_wl_output::WlOutput> {
+        self.output.as_ref().map(|o| &o.0)
+    }
+
+    /// Whether `surface` is the surface of the OSD.
+    pub fn has_surface(&self, surface: &c_wl_surface::WlSurface) -> bool {
+        self.layer.as_ref().is_some_and(|l| l.wl_surface() == surface)
+    }
+
+    /// Whether the surface of the OSD was closed, or its output removed.
+    pub fn is_closed(&self) -> bool {
+        self.layer.is_none()
+    }
+
+    pub fn set_theme(&mut self, theme: cosmic::Theme) {
+        self.element.set_theme(theme);
+        self.element.force_redraw();
+        self.dirty = true;
+    }
+
+    /// Render at `scale` from the next frame on. Without a viewport, the
+    /// buffer can only be scaled by whole numbers, so the OSD is rendered at
+    /// the next one.
+    fn set_scale(&mut self, scale: f64) {
+        self.scale = if self.viewport.is_some() { scale } else { scale.ceil() };
+        let buffer_scale = if self.viewport.is_some() { 1 } else { self.scale as i32 };
+        if let Some(layer) = self.layer.as_ref() {
+            layer.wl_surface().set_buffer_scale(buffer_scale);
+        }
+        let scaled_size = self.size.to_f64().to_physical(self.scale).to_i32_round();
+        if let Some(egl_surface) = self.egl_surface.as_ref() {
+            egl_surface.resize(scaled_size.w.max(1), scaled_size.h.max(1), 0, 0);
+        }
+        self.damage_tracked_renderer = OutputDamageTracker::new(
+            scaled_size,
+            self.scale,
+            smithay::utils::Transform::Flipped180,
+        );
+        self.dirty = true;
+    }
+
+    /// Create the rendering surface of the configured layer surface, with the
+    /// renderer which is created with the first panel surface.
+    fn init_egl_surface(&mut self, renderer: &GlesRenderer) -> anyhow::Result<()> {
+        let Some(layer) = self.layer.as_ref() else {
+            return Ok(());
+        };
+        let scaled_size = self.size.to_f64().to_physical(self.scale).to_i32_round::<i32>();
+        let wl_egl_surface =
+            WlEglSurface::new(layer.wl_surface().id(), scaled_size.w.max(1), scaled_size.h.max(1))?;
+        let client_egl_surface =
+            unsafe { ClientEglSurface::new(wl_egl_surface, layer.wl_surface().clone()) };
+        let egl_surface = unsafe {
+            EGLSurface::new(
+                renderer.egl_context().display(),
+                renderer
+                    .egl_context()
+                    .pixel_format()
+                    .expect("Failed to get pixel format from EGL context "),
+                renderer.egl_context().config_id(),
+                client_egl_surface,
+            )?
+        };
+        self.egl_surface = Some(Rc::new(egl_surface));
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Render the OSD if it changed since the last frame.
+    pub fn render(
+        &mut self,
+        renderer: &mut GlesRenderer,
+        qh: &QueueHandle<GlobalState>,
+    ) -> anyhow::Result<()> {
+        if self.configured && self.egl_surface.is_none() {
+            self.init_egl_surface(renderer)?;
+        }
+        let (Some(egl_surface), Some(layer)) = (self.egl_surface.as_ref(), self.layer.as_ref())
+        else {
+            return Ok(());
+        };
+        if !self.dirty || !self.has_frame {
+            return Ok(());
+        }
+        renderer.unbind()?;
+        renderer.bind(egl_surface.clone())?;
+        let elements: Vec<MemoryRenderBufferRenderElement<GlesRenderer>> =
+            self.element.render_elements(renderer, (0, 0).into(), self.scale.into(), 1.0);
+        _ = self.damage_tracked_renderer.render_output(
+            renderer,
+            egl_surface.buffer_age().unwrap_or_default() as usize,
+            &elements,
+            [0.0, 0.0, 0.0, 0.0],
+        );
+        egl_surface.swap_buffers(None)?;
+        let wl_surface = layer.wl_surface();
+        wl_surface.frame(qh, wl_surface.clone());
+        wl_surface.commit();
+        renderer.unbind()?;
+        self.dirty = false;
+        self.has_frame = false;
+        Ok(())
+    }
+}
+
+impl WrapperSpace for OsdSpace {
+    type Config = OsdConfig;
+
+    // the layer surface is created for the output in new_output
+    fn setup(
+        &mut self,
+        _compositor_state: &CompositorState,
+        _fractional_scale_manager: Option<&FractionalScalingManager>,
+        _security_context_manager: Option<SecurityContextManager>,
+        _viewport: Option<&ViewporterState>,
+        _layer_state: &mut LayerShell,
+        _conn: &Connection,
+        _qh: &QueueHandle<GlobalState>,
+        _overlap_notify: Option<OverlapNotifyV1>,
+    ) {
+    }
+
+    fn config(&self) -> Self::Config {
+        self.config.clone()
+    }
+
+    fn configure_layer(&mut self, layer: &LayerSurface, _: LayerSurfaceConfigure) {
+        if self.has_surface(layer.wl_surface()) {
+            self.configured = true;
+            self.dirty = true;
+        }
+    }
+
+    fn close_layer(&mut self, layer: &LayerSurface) {
+        if self.has_surface(layer.wl_surface()) {
+            self.egl_surface = None;
+            self.layer = None;
+        }
+    }
+
+    fn frame(&mut self, surface: &c_wl_surface::WlSurface, _time: u32) {
+        if self.has_surface(surface) {
+            self.has_frame = true;
+        }
+    }
+}
+
+impl OutputHandler for OsdSpace {
+    fn new_output(
+        &mut self,
+        compositor_state: &CompositorState,
+        fractional_scale_manager: Option<&FractionalScalingManager>,
+        viewport: Option<&ViewporterState>,
+        layer_state: &mut LayerShell,
+        _conn: &Connection,
+        qh: &QueueHandle<GlobalState>,
+        c_output: Option<c_wl_output::WlOutput>,
+        s_output: Option<Output>,
+        info: Option<OutputInfo>,
+    ) -> anyhow::Result<()> {
+        if self.output.is_some() {
+            anyhow::bail!("output already setup for this OSD");
+        }
+        let (Some(c_output), Some(s_output), Some(info)) = (c_output, s_output, info) else {
+            anyhow::bail!("the OSD needs an output");
+        };
+        if info.name.as_deref() != Some(self.config.output.as_str()) {
+            anyhow::bail!("output does not match the OSD");
+        }
+        self.element.output_enter(&s_output, Rectangle::default());
+        self.size = self.element.bbox().size;
+
+        let c_wl_surface = compositor_state.create_surface(qh);
+        let layer = layer_state.create_layer_surface(
+            qh,
+            c_wl_surface.clone(),
+            Layer::Overlay,
+            Some(self.config.name()),
+            Some(&c_output),
+        );
+        layer.set_anchor(match self.anchor {
+            PanelAnchor::Left => Anchor::LEFT,
+            PanelAnchor::Right => Anchor::RIGHT,
+            PanelAnchor::Top => Anchor::TOP,
+            PanelAnchor::Bottom => Anchor::BOTTOM,
+        });
+        layer.set_margin(self.margin, self.margin, self.margin, self.margin);
+        layer.set_size(self.size.w.max(1) as u32, self.size.h.max(1) as u32);
+        layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+
+        // pointer events go to whatever is below
+        let input_region = Region::new(compositor_state)?;
+        c_wl_surface.set_input_region(Some(input_region.wl_region()));
+
+        self._fractional_scale =
+            fractional_scale_manager.map(|f| f.fractional_scaling(&c_wl_surface, qh));
+        self.viewport = viewport.map(|v| {
+            let viewport = v.get_viewport(&c_wl_surface, qh);
+            viewport.set_destination(self.size.w.max(1), self.size.h.max(1));
+            viewport
+        });
+        layer.commit();
+
+        self._input_region = Some(input_region);
+        self.layer = Some(layer);
+        self.output = Some((c_output, s_output, info));
+        self.set_scale(self.scale);
+        Ok(())
+    }
+
+    fn update_output(
+        &mut self,
+        c_output: c_wl_output::WlOutput,
+        s_output: Output,
+        info: OutputInfo,
+    ) -> anyhow::Result<bool> {
+        if self.output() != Some(&c_output) {
+            return Ok(false);
+        }
+        self.output = Some((c_output, s_output, info));
+        Ok(true)
+    }
+
+    fn output_leave(
+        &mut self,
+        c_output: c_wl_output::WlOutput,
+        _s_output: Output,
+    ) -> anyhow::Result<()> {
+        if self.output() == Some(&c_output) {
+            self.egl_surface = None;
+            self.layer = None;
+            self.output = None;
+        }
+        Ok(())
+    }
+
+    fn scale_factor_changed(
+        &mut self,
+        surface: &c_wl_surface::WlSurface,
+        new_scale: f64,
+        _legacy: bool,
+    ) {
+        if self.has_surface(surface) {
+            self.set_scale(new_scale);
+        }
+    }
+
+    fn transform_changed(
+        &mut self,
+        _conn: &Connection,
+        _surface: &c_wl_surface::WlSurface,
+        _new_transform: c_wl_output::Transform,
+    ) {
+        // TODO handle the preferred transform
+    }
+
+    // the OSD has no clients
+    fn get_scale_factor(&self, _surface: &s_WlSurface) -> Option<f64> {
+        None
+    }
+}
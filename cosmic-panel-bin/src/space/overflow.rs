@@ -19,7 +19,7 @@ use crate::{
     iced::elements::{CosmicMappedInternal, PopupMappedInternal},
     xdg_shell_wrapper::{
         shared_state::GlobalState,
-        space::{PanelPopup, WrapperPopupState},
+        space::{PanelPopup, PopupAnimation, WrapperPopupState},
         wp_fractional_scaling::FractionalScalingManager,
         wp_viewporter::ViewporterState,
     },
@@ -41,7 +41,8 @@ impl PanelSpace {
     ) -> anyhow::Result<()> {
         if force_hide || self.overflow_popup.is_some() {
             tracing::info!("removing overflow popup");
-            self.overflow_popup = None;
+            let surface = self.overflow_popup.as_ref().map(|(p, _)| p.c_popup.wl_surface().clone());
+            self.close_popups(|p| Some(p.c_popup.wl_surface()) != surface.as_ref());
             return Ok(());
         }
         // get popup location and anchor based on element_id and panel
@@ -79,7 +80,7 @@ impl PanelSpace {
 
         positioner.set_size(popup_bbox.size.w, popup_bbox.size.h);
         let c_popup = popup::Popup::from_surface(
-            None,
+            self.popup_parent(),
             &positioner,
             qh,
             c_wl_surface.clone(),
@@ -94,7 +95,9 @@ impl PanelSpace {
             popup_bbox.size.w.max(1),
             popup_bbox.size.h.max(1),
         );
-        self.layer.as_ref().unwrap().get_popup(c_popup.xdg_popup());
+        if let Some(layer) = self.layer.as_ref() {
+            layer.get_popup(c_popup.xdg_popup());
+        }
 
         let fractional_scale =
             fractional_scale_manager.map(|f| f.fractional_scaling(&c_wl_surface, qh));
@@ -130,7 +133,8 @@ impl PanelSpace {
                 viewport,
                 scale: self.scale,
                 input_region: None,
-                parent: self.layer.as_ref().unwrap().wl_surface().clone(),
+                parent: self.panel_surface().unwrap().clone(),
+                animation: PopupAnimation::new(!self.config.reduce_motion),
             },
             section,
         ));
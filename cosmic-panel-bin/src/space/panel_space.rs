@@ -1,6 +1,6 @@
 use std::{
     cell::{Cell, RefCell},
-    collections::HashSet,
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Debug,
     os::{fd::OwnedFd, unix::net::UnixStream},
     rc::Rc,
@@ -10,15 +10,23 @@ use std::{
 };
 
 use crate::{
-    iced::elements::{background::BackgroundElement, PopupMappedInternal},
+    applet_channel::AppletSender,
+    frame_stats::FrameSample,
+    iced::{
+        buffer_pool,
+        elements::{
+            background::BackgroundElement, frame_stats_hud::FrameStatsHudElement,
+            PopupMappedInternal,
+        },
+    },
     xdg_shell_wrapper::{
         client::handlers::overlap::OverlapNotifyV1,
         client_state::{ClientFocus, FocusStatus},
         server_state::{ServerFocus, ServerPtrFocus},
         shared_state::GlobalState,
         space::{
-            ClientEglDisplay, ClientEglSurface, PanelPopup, SpaceEvent, Visibility, WrapperPopup,
-            WrapperSpace,
+            ClientEglDisplay, ClientEglSurface, ClientHost, PanelPopup, SpaceEvent, Visibility,
+            WrapperPopup, WrapperSpace,
         },
         util::smootherstep,
         wp_security_context::SecurityContextManager,
@@ -30,7 +38,7 @@ use cctk::{
 };
 
 use cosmic::iced::id;
-use launch_pad::process::Process;
+use launch_pad::{process::Process, ProcessKey};
 use sctk::{
     compositor::Region,
     output::OutputInfo,
@@ -42,7 +50,7 @@ use sctk::{
         },
     },
     shell::{
-        wlr_layer::{LayerSurface, LayerSurfaceConfigure},
+        wlr_layer::{LayerSurface, LayerSurfaceConfigure, SurfaceKind},
         xdg::XdgPositioner,
         WaylandSurface,
     },
@@ -56,15 +64,20 @@ use smithay::{
             surface::EGLSurface,
             EGLContext,
         },
-        renderer::{damage::OutputDamageTracker, gles::GlesRenderer, Bind, Unbind},
+        renderer::{
+            damage::OutputDamageTracker, gles::GlesRenderer, utils::with_renderer_surface_state,
+            Bind, Unbind,
+        },
     },
     desktop::{PopupManager, Space},
     output::Output,
     reexports::{
         wayland_protocols::xdg::shell::client::xdg_positioner::{Anchor, Gravity},
-        wayland_server::{backend::ClientId, Client, DisplayHandle},
+        wayland_server::{
+            backend::ClientId, protocol::wl_surface::WlSurface, Client, DisplayHandle,
+        },
     },
-    utils::{Logical, Rectangle, Size},
+    utils::{Logical, Point, Rectangle, Size, Transform},
     wayland::{
         compositor::with_states,
         fractional_scale::with_fractional_scale,
@@ -72,7 +85,7 @@ use smithay::{
         shell::xdg::{PopupSurface, PositionerState},
     },
 };
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::oneshot;
 use tracing::{error, info};
 use wayland_egl::WlEglSurface;
 use wayland_protocols::{
@@ -83,59 +96,175 @@ use wayland_protocols::{
     },
     xdg::shell::client::xdg_positioner::ConstraintAdjustment,
 };
+use wayland_protocols_wlr::layer_shell::v1::client::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
 
-use cosmic_panel_config::{CosmicPanelBackground, CosmicPanelConfig, PanelAnchor};
+use cosmic_panel_config::{CosmicPanelBackground, CosmicPanelConfig, PanelAnchor, Side};
 
-use crate::{iced::elements::CosmicMappedInternal, PanelCalloopMsg};
+use crate::{
+    hooks::{HookRun, PanelHook},
+    iced::elements::CosmicMappedInternal,
+    portal::PortalGrants,
+    space_container::{rules::RuleOutcome, visibility_override::VisibilityOverride},
+    PanelCalloopMsg,
+};
 
 use super::{
+    applet_registry::AppletRegistry,
+    context_menu::ContextMenu,
     corner_element::{init_shaders, RoundedRectangleSettings},
-    layout::OverflowSection,
+    debug_overlay::DebugOverlay,
+    edit_mode::EditMode,
+    effects::EffectsState,
+    expansion::{DockGrowth, Expansion},
+    layout::{LayoutCache, OverflowSection},
+    popup::DismissedPopup,
+    swipe::SwipeState,
 };
 
 pub enum AppletMsg {
     NewProcess(String, Process),
-    NewNotificationsProcess(String, Process, Vec<(String, String)>, Vec<OwnedFd>),
-    NeedNewNotificationFd(oneshot::Sender<OwnedFd>),
+    NewNotificationsProcess(
+        String,
+        Process,
+        Vec<(String, String)>,
+        Vec<OwnedFd>,
+        NotificationsApplet,
+    ),
+    NeedNewNotificationFd(NotificationsApplet, oneshot::Sender<OwnedFd>),
+    /// The notifications daemon is connected, and restarted if `restarted`.
+    NotificationsConnected {
+        restarted: bool,
+    },
     ClientSocketPair(ClientId),
+    /// Stop the process of an applet, which the process manager restarts.
+    Restart(ProcessKey),
     Cleanup(String),
+    RunHook(HookRun),
+    /// Run the hooks delayed by [`crate::hooks::HookRunner`] whose interval
+    /// passed.
+    RunPendingHooks,
+    /// Start a process which isn't an applet, e.g. of a launcher, which is not
+    /// restarted and outlives its panel.
+    Launch(Process),
+    /// Stop the processes of every applet before the panel exits, and reply
+    /// once they are stopped.
+    Shutdown(std::sync::mpsc::Sender<()>),
 }
 
 impl Debug for AppletMsg {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::NewProcess(arg0, _) => f.debug_tuple("NewProcess").field(arg0).finish(),
-            Self::NewNotificationsProcess(arg0, _, arg2, arg3) => f
+            Self::NewNotificationsProcess(arg0, _, arg2, arg3, arg4) => f
                 .debug_tuple("NewNotificationsProcess")
                 .field(arg0)
                 .field(arg2)
                 .field(arg3)
+                .field(&arg4.name)
                 .finish(),
-            Self::NeedNewNotificationFd(arg0) => {
-                f.debug_tuple("NeedNewNotificationFd").field(arg0).finish()
+            Self::NeedNewNotificationFd(arg0, arg1) => {
+                f.debug_tuple("NeedNewNotificationFd").field(&arg0.name).field(arg1).finish()
+            },
+            Self::NotificationsConnected { restarted } => {
+                f.debug_struct("NotificationsConnected").field("restarted", restarted).finish()
             },
             Self::ClientSocketPair(arg0) => f.debug_tuple("ClientSocketPair").field(arg0).finish(),
+            Self::Restart(arg0) => f.debug_tuple("Restart").field(arg0).finish(),
             Self::Cleanup(arg0) => f.debug_tuple("Cleanup").field(arg0).finish(),
+            Self::RunHook(arg0) => f.debug_tuple("RunHook").field(arg0).finish(),
+            Self::RunPendingHooks => write!(f, "RunPendingHooks"),
+            Self::Launch(_) => write!(f, "Launch"),
+            Self::Shutdown(_) => write!(f, "Shutdown"),
         }
     }
 }
 
 pub type Clients = Arc<Mutex<Vec<PanelClient>>>;
 
+/// A notifications applet of a panel, which gets its own fd of the
+/// notifications daemon.
+#[derive(Clone)]
+pub struct NotificationsApplet {
+    pub name: String,
+    pub clients: Clients,
+}
+
+impl NotificationsApplet {
+    /// Record that the applet got an fd of the daemon connection `generation`.
+    pub fn set_generation(&self, generation: u64) {
+        if let Some(c) = self.clients.lock().unwrap().iter_mut().find(|c| c.name == self.name) {
+            c.notifications_generation = Some(generation);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct PanelClient {
     pub name: String,
+    /// localized name from the desktop entry
+    pub display_name: Option<String>,
     pub client: Client,
     pub stream: Option<UnixStream>,
     pub security_ctx: Option<WpSecurityContextV1>,
     pub exec: Option<String>,
     pub minimize_priority: Option<u32>,
+    /// app ids of the windows which minimize to this applet, for applets
+    /// representing specific apps, like dock icons
+    pub minimize_app_ids: Vec<String>,
     pub requests_wayland_display: Option<bool>,
     pub is_notification_applet: Option<bool>,
+    /// generation of the notifications daemon connection the fd of this applet
+    /// is from
+    pub notifications_generation: Option<u64>,
     pub shrink_priority: Option<u32>,
     pub shrink_min_size: Option<ClientShrinkSize>,
     /// If there is an existing popup, this applet with be pressed when hovered.
     pub auto_popup_hover_press: Option<AppletAutoClickAnchor>,
+    /// delay before this applet is pressed when hovered, overriding the
+    /// autohover delay of the panel
+    pub hover_popup_delay: Option<Duration>,
+    /// the applet opted out of generated clicks, which some applets don't
+    /// handle well
+    pub no_generated_clicks: bool,
+    pub process: AppletProcess,
+    /// the applet was stopped to be restarted, see
+    /// [`crate::space_container::SpaceContainer::restart_applet`]
+    pub restart_requested: bool,
+    /// never paused while the panel is hidden
+    pub keep_running: bool,
+    /// app id of a flatpak applet
+    pub flatpak_id: Option<String>,
+}
+
+/// Bookkeeping of the process of an applet, reported over the control
+/// interface.
+#[derive(Debug, Clone, Copy)]
+pub struct AppletProcess {
+    /// when the current process was started
+    pub started: Instant,
+    pub restarts: u32,
+    /// exit code of the last process which failed
+    pub last_exit_code: Option<i32>,
+    /// the process in the process manager, once it was started
+    pub key: Option<ProcessKey>,
+    /// pid of the current process. The credentials of the client are those of
+    /// the panel, which created the socket pair of the applet.
+    pub pid: Option<u32>,
+}
+
+impl Default for AppletProcess {
+    fn default() -> Self {
+        Self { started: Instant::now(), restarts: 0, last_exit_code: None, key: None, pid: None }
+    }
+}
+
+impl AppletProcess {
+    /// The pid signals for the applet are sent to. This is the pid of the
+    /// process the applet was started with, as the credentials of its socket
+    /// pair are those of the panel.
+    pub fn signal_pid(&self) -> Option<i32> {
+        self.pid.and_then(|pid| i32::try_from(pid).ok()).filter(|pid| *pid > 0)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -188,16 +317,25 @@ impl PanelClient {
     pub fn new(name: String, client: Client, stream: Option<UnixStream>) -> Self {
         Self {
             name,
+            display_name: None,
             client,
             stream,
             security_ctx: None,
             exec: None,
             minimize_priority: None,
+            minimize_app_ids: Vec::new(),
             requests_wayland_display: None,
             is_notification_applet: None,
+            notifications_generation: None,
             auto_popup_hover_press: None,
+            hover_popup_delay: None,
+            no_generated_clicks: false,
             shrink_priority: None,
             shrink_min_size: None,
+            process: AppletProcess::default(),
+            restart_requested: false,
+            keep_running: false,
+            flatpak_id: None,
         }
     }
 }
@@ -208,6 +346,8 @@ pub struct AnimatableState {
     border_radius: u32,
     pub expanded: f32,
     gap: u16,
+    /// logical thickness of the panel while its applet size changes
+    pub thickness: Option<f32>,
 }
 
 #[derive(Debug, Clone)]
@@ -220,6 +360,17 @@ pub struct AnimateState {
     duration: Duration,
 }
 
+/// The logical thickness of a panel with `config`, with its applets at their
+/// configured size.
+fn list_thickness(config: &CosmicPanelConfig) -> f32 {
+    let applet = [Side::WingStart, Side::Center, Side::WingEnd]
+        .into_iter()
+        .map(|s| config.get_effective_applet_size(s).get_applet_icon_size_with_padding(true))
+        .max()
+        .unwrap_or_default();
+    (config.padding() * 2 + applet) as f32
+}
+
 #[derive(Debug, Clone)]
 pub struct PanelColors {
     pub theme: cosmic::Theme,
@@ -303,10 +454,20 @@ pub struct PanelSpace {
     pub layer_fractional_scale: Option<WpFractionalScaleV1>,
     pub layer_viewport: Option<WpViewport>,
     pub popups: Vec<WrapperPopup>,
+    pub(crate) dismissed_popups: Vec<DismissedPopup>,
+    /// whether a popup was open in the last frame
+    pub(crate) had_popups: bool,
+    /// when the last popup closed, for the hover session
+    pub(crate) popups_closed_at: Option<Instant>,
+    /// the applet with the last open popup, until the pointer leaves it
+    pub(crate) last_popup_client: Option<ClientId>,
     pub start_instant: Instant,
     pub colors: PanelColors,
-    pub applet_tx: mpsc::Sender<AppletMsg>,
+    pub applet_tx: AppletSender,
     pub input_region: Option<Region>,
+    /// opaque region of the panel surface, for the compositor to skip
+    /// blending behind it
+    pub(crate) opaque_region: Option<Region>,
     pub has_frame: bool,
     pub scale: f64,
     pub output_has_toplevel: bool,
@@ -314,13 +475,23 @@ pub struct PanelSpace {
     pub animate_state: Option<AnimateState>,
     pub maximized: bool,
     pub panel_tx: calloop::channel::Sender<PanelCalloopMsg>,
-    pub minimize_applet_rect: Rectangle<i32, Logical>,
+    /// last minimize rectangle sent for each applet, and when it was sent
+    pub minimize_applet_rects: HashMap<ClientId, (Rectangle<i32, Logical>, Instant)>,
     pub panel_rect_settings: RoundedRectangleSettings,
     pub scale_change_retries: u32,
+    /// logical rectangles of the outputs of the compositor with their scale,
+    /// for the scale of popups reaching onto another output
+    pub(crate) output_scales: Vec<(Rectangle<i32, Logical>, f64)>,
+    /// the part of the output left by the exclusive zones of the panels
+    /// arranged before it, in output-local logical coordinates
+    pub(crate) output_zone: Option<Rectangle<i32, Logical>>,
     /// Extra gap for stacked panels. Logical coordinate space.
     pub additional_gap: i32,
     /// Target gap for the panel on its anchored edge. Logical coordinate space.
     pub anchor_gap: i32,
+    /// Exclusive zone last requested for the layer surface. Logical coordinate
+    /// space.
+    pub exclusive_zone: i32,
     pub loop_handle: calloop::LoopHandle<'static, GlobalState>,
     pub left_overflow_button_id: id::Id,
     pub center_overflow_button_id: id::Id,
@@ -330,12 +501,74 @@ pub struct PanelSpace {
     pub right_overflow_popup_id: id::Id,
     pub overflow_popup: Option<(PanelPopup, OverflowSection)>,
     pub remap_attempts: u32,
+    /// inputs of the last successful layout
+    pub(crate) layout_cache: LayoutCache,
     pub background_element: Option<BackgroundElement>,
-    pub last_minimize_update: Instant,
     pub(crate) toplevel_overlaps: HashSet<wayland_backend::client::ObjectId>,
     pub(crate) notification_subscription: Option<ZcosmicOverlapNotificationV1>,
     pub(crate) overlap_notify: Option<OverlapNotifyV1>,
+    /// `toplevel_overlaps` is approximated by the container
+    pub(crate) overlap_fallback: bool,
+    /// overlapping toplevels hide the panel, after applying the app id
+    /// exceptions of the autohide config
+    pub(crate) hidden_by_toplevels: bool,
     pub(crate) hover_track: HoverTrack,
+    /// damage and swap time of the frames rendered since the last sample
+    pub(crate) pending_frame: Option<FrameSample>,
+    pub(crate) frame_stats_hud: Option<FrameStatsHudElement>,
+    pub(crate) debug_overlay: Option<DebugOverlay>,
+    /// input region of the layer surface, kept for the debug overlay
+    pub(crate) input_rect: Rectangle<i32, Logical>,
+    /// whether the input region is limited to the handle of the hidden panel
+    pub(crate) handle_region: bool,
+    /// whether the layer surface is hidden for the lock screen or a
+    /// presentation
+    pub(crate) suppressed: bool,
+    /// whether the layer surface is moved to the bottom layer for a fullscreen
+    /// window on the output
+    pub(crate) lowered: bool,
+    /// the configured config, while `config` has the condensed size
+    pub(crate) condensed_from: Option<CosmicPanelConfig>,
+    /// when the panel was hidden, for pausing its applets
+    pub(crate) hidden_since: Option<Instant>,
+    /// pids of the applets paused while the panel is hidden
+    pub(crate) frozen_pids: Option<Vec<i32>>,
+    /// whether a host drag is over the panel
+    pub(crate) dragging: bool,
+    /// when the last host drag over the panel ended
+    pub(crate) drag_ended: Option<Instant>,
+    /// applets are reordered by dragging them, instead of receiving input
+    pub(crate) edit_mode: Option<EditMode>,
+    /// the panel menu of an applet slot
+    pub(crate) context_menu: Option<ContextMenu>,
+    pub(crate) effects: EffectsState,
+    pub(crate) swipe: SwipeState,
+    /// extra length granted to applets which requested it
+    pub(crate) expansions: HashMap<ClientId, Expansion>,
+    /// animated length of a dock which grows with its applets
+    pub(crate) dock_growth: Option<DockGrowth>,
+    /// the combined actions of the rules which apply to the panel
+    pub(crate) rules: RuleOutcome,
+    /// visibility set over the control interface
+    pub(crate) visibility_override: VisibilityOverride,
+    /// the panel is revealed by the toggle shortcut, and takes keyboard focus
+    /// on demand
+    pub(crate) keyboard_revealed: bool,
+    /// focus of the hot corners of the panel, if the pointer rested in one
+    pub(crate) hot_corner_focus: Option<FocusStatus>,
+    /// whether the autohidden panel was last settled visible, for the hooks
+    pub(crate) hook_shown: Option<bool>,
+    /// the theme generation of the container last applied to the panel
+    pub(crate) theme_generation: u64,
+    /// the panel renders into a toplevel for the settings, instead of a layer
+    /// surface
+    pub(crate) is_preview: bool,
+    /// the toplevel of a preview panel
+    pub(crate) preview: Option<sctk::shell::xdg::window::Window>,
+    /// grants of flatpak applets, shared with the container
+    pub(crate) portal_grants: PortalGrants,
+    /// applets of all panels by client, shared with the container
+    pub(crate) applets: AppletRegistry,
 }
 
 impl PanelSpace {
@@ -344,7 +577,7 @@ impl PanelSpace {
         config: CosmicPanelConfig,
         c_focused_surface: Rc<RefCell<ClientFocus>>,
         c_hovered_surface: Rc<RefCell<ClientFocus>>,
-        applet_tx: mpsc::Sender<AppletMsg>,
+        applet_tx: AppletSender,
         theme: cosmic::Theme,
         s_display: DisplayHandle,
         security_context_manager: Option<SecurityContextManager>,
@@ -376,6 +609,10 @@ impl PanelSpace {
             layer_viewport: Default::default(),
             egl_surface: Default::default(),
             popups: Default::default(),
+            dismissed_popups: Vec::new(),
+            had_popups: false,
+            popups_closed_at: None,
+            last_popup_client: None,
             visibility,
             start_instant: Instant::now(),
             c_focused_surface,
@@ -386,6 +623,7 @@ impl PanelSpace {
             applet_tx,
             actual_size: (0, 0).into(),
             input_region: None,
+            opaque_region: None,
             damage_tracked_renderer: None,
             is_dirty: false,
             has_frame: true,
@@ -395,10 +633,12 @@ impl PanelSpace {
             animate_state: None,
             maximized: false,
             panel_tx,
-            minimize_applet_rect: Default::default(),
+            minimize_applet_rects: HashMap::new(),
             container_length: 0,
             panel_rect_settings: RoundedRectangleSettings::default(),
             scale_change_retries: 0,
+            output_scales: Vec::new(),
+            output_zone: None,
             additional_gap: 0,
             loop_handle,
             left_overflow_button_id: id::Id::new(format!("{}-left-overflow-button", name)),
@@ -409,13 +649,44 @@ impl PanelSpace {
             right_overflow_popup_id: id::Id::new(format!("{}-right-overflow-popup", name)),
             overflow_popup: None,
             remap_attempts: 0,
+            layout_cache: LayoutCache::default(),
             background_element: None,
-            last_minimize_update: Instant::now() - Duration::from_secs(1),
             anchor_gap: 0,
+            exclusive_zone: 0,
             toplevel_overlaps: HashSet::new(),
             notification_subscription: None,
             overlap_notify: None,
+            overlap_fallback: false,
+            hidden_by_toplevels: false,
             hover_track: HoverTrack::default(),
+            pending_frame: None,
+            frame_stats_hud: None,
+            debug_overlay: None,
+            input_rect: Rectangle::default(),
+            handle_region: false,
+            suppressed: false,
+            lowered: false,
+            condensed_from: None,
+            hidden_since: None,
+            frozen_pids: None,
+            dragging: false,
+            drag_ended: None,
+            edit_mode: None,
+            context_menu: None,
+            effects: EffectsState::default(),
+            swipe: SwipeState::default(),
+            expansions: HashMap::new(),
+            dock_growth: None,
+            rules: RuleOutcome::default(),
+            visibility_override: VisibilityOverride::Auto,
+            keyboard_revealed: false,
+            hot_corner_focus: None,
+            hook_shown: None,
+            theme_generation: 0,
+            is_preview: false,
+            preview: None,
+            portal_grants: PortalGrants::default(),
+            applets: AppletRegistry::default(),
         }
     }
 
@@ -427,6 +698,108 @@ impl PanelSpace {
         }
     }
 
+    /// The location of the panel surface on its output, as the compositor
+    /// arranges it in the zone left by the panels before it.
+    pub(crate) fn output_location(&self) -> Option<Point<i32, Logical>> {
+        let logical_size: Size<i32, Logical> = self.output.as_ref()?.2.logical_size?.into();
+        let zone =
+            self.output_zone.unwrap_or_else(|| Rectangle::from_loc_and_size((0, 0), logical_size));
+        Some(location_in_zone(zone, self.config.anchor(), self.dimensions, self.additional_gap))
+    }
+
+    /// Whether the visibility override keeps the panel shown.
+    pub(crate) fn force_visible(&self) -> bool {
+        self.visibility_override == VisibilityOverride::ForceVisible
+    }
+
+    /// Move the layer surface below all windows while the session is locked or
+    /// a presentation is shown, and back to its configured layer afterwards.
+    pub(crate) fn set_suppressed(&mut self, suppressed: bool) {
+        let Some(layer) = self.layer.as_ref().and_then(|l| match l.kind() {
+            SurfaceKind::Wlr(layer) => Some(layer.clone()),
+            _ => None,
+        }) else {
+            return;
+        };
+        if suppressed {
+            self.close_popups(|_| false);
+            layer.set_layer(zwlr_layer_shell_v1::Layer::Background);
+            layer.set_keyboard_interactivity(zwlr_layer_surface_v1::KeyboardInteractivity::None);
+        } else {
+            layer.set_layer(self.current_layer());
+            layer.set_keyboard_interactivity(self.keyboard_interactivity());
+            self.is_dirty = true;
+        }
+        if let Some(layer) = self.layer.as_ref() {
+            layer.commit();
+        }
+        self.suppressed = suppressed;
+    }
+
+    /// Let the layer surface take keyboard focus on demand while the panel is
+    /// revealed by the toggle shortcut, and restore the configured keyboard
+    /// interactivity afterwards.
+    pub(crate) fn set_keyboard_revealed(&mut self, revealed: bool) {
+        if self.keyboard_revealed == revealed {
+            return;
+        }
+        self.keyboard_revealed = revealed;
+        if self.suppressed {
+            // applied once the panel isn't suppressed anymore
+            return;
+        }
+        let Some(layer) = self.layer.as_ref() else {
+            return;
+        };
+        if let SurfaceKind::Wlr(wlr_layer) = layer.kind() {
+            wlr_layer.set_keyboard_interactivity(self.keyboard_interactivity());
+            layer.commit();
+        }
+    }
+
+    /// The keyboard interactivity of the panel, unless it is suppressed.
+    fn keyboard_interactivity(&self) -> zwlr_layer_surface_v1::KeyboardInteractivity {
+        if self.keyboard_revealed {
+            zwlr_layer_surface_v1::KeyboardInteractivity::OnDemand
+        } else {
+            self.config.keyboard_interactivity()
+        }
+    }
+
+    /// Move the layer surface to the bottom layer while a fullscreen window is
+    /// shown on the output, and back to its configured layer afterwards.
+    pub(crate) fn set_lowered(&mut self, lowered: bool) {
+        self.lowered = lowered;
+        if self.suppressed {
+            // applied once the panel isn't suppressed anymore
+            return;
+        }
+        let Some(layer) = self.layer.as_ref() else {
+            return;
+        };
+        if let SurfaceKind::Wlr(wlr_layer) = layer.kind() {
+            wlr_layer.set_layer(self.current_layer());
+            layer.commit();
+        }
+    }
+
+    /// The layer of the panel, unless it is suppressed.
+    fn current_layer(&self) -> zwlr_layer_shell_v1::Layer {
+        if self.lowered {
+            zwlr_layer_shell_v1::Layer::Bottom
+        } else {
+            self.config.layer()
+        }
+    }
+
+    /// Whether a fullscreen window shown on the output affects the panel.
+    pub(crate) fn yields_to_fullscreen(&self) -> bool {
+        matches!(
+            self.config.layer(),
+            zwlr_layer_shell_v1::Layer::Top | zwlr_layer_shell_v1::Layer::Overlay
+        )
+    }
+
     pub fn bg_color(&self) -> [f32; 4] {
         if let Some(animatable_state) = self.animate_state.as_ref() {
             animatable_state.cur.bg_color
@@ -461,6 +834,242 @@ impl PanelSpace {
         id
     }
 
+    /// The applets of the left, center and right section of the panel with
+    /// `config` on its output.
+    pub(crate) fn section_plugins(&self, config: &CosmicPanelConfig) -> [Option<Vec<String>>; 3] {
+        let output = self.output.as_ref().and_then(|o| o.2.name.as_deref());
+        [
+            config.plugins_left_on(output),
+            config.plugins_center_on(output),
+            config.plugins_right_on(output),
+        ]
+    }
+
+    /// The section of the applet `client`, if it is in this panel.
+    pub(crate) fn applet_side(&self, client: &ClientId) -> Option<Side> {
+        self.applets
+            .get(client)
+            .filter(|a| Arc::ptr_eq(&a.clients, self.section_clients(a.side)))
+            .map(|a| a.side)
+    }
+
+    /// The applets of the section `side`.
+    pub(crate) fn section_clients(&self, side: Side) -> &Clients {
+        match side {
+            Side::WingStart => &self.clients_left,
+            Side::Center => &self.clients_center,
+            Side::WingEnd => &self.clients_right,
+        }
+    }
+
+    /// Whether `client` is one of the applets of this panel.
+    pub fn has_client(&self, client: &Client) -> bool {
+        self.applet_side(&client.id()).is_some()
+    }
+
+    /// The delay before the applet `client` is pressed when hovered, or
+    /// `None` if it opted out of generated clicks.
+    pub(crate) fn applet_hover_delay(
+        &self,
+        client: &ClientId,
+        default: Duration,
+    ) -> Option<Duration> {
+        let Some(c) = self.applets.get(client) else {
+            return Some(default);
+        };
+        if c.no_generated_clicks {
+            return None;
+        }
+        Some(c.hover_popup_delay.unwrap_or(default))
+    }
+
+    /// The output-relative rectangle of the slot of the applet `client`.
+    fn applet_output_geometry(&self, client: &Client) -> Option<Rectangle<i32, Logical>> {
+        let mut geo = self.space.elements().find_map(|e| {
+            let CosmicMappedInternal::Window(w) = e else {
+                return None;
+            };
+            w.toplevel()
+                .is_some_and(|t| t.wl_surface().client().is_some_and(|c| &c == client))
+                .then(|| self.space.element_geometry(e))
+                .flatten()
+        })?;
+        geo.loc += self.output_location()? + self.anchor_gap_offset();
+        Some(geo)
+    }
+
+    /// The offset the content of the panel is rendered at while it slides
+    /// in or out of view.
+    pub(crate) fn anchor_gap_offset(&self) -> Point<i32, Logical> {
+        match self.config.anchor {
+            PanelAnchor::Left => (self.anchor_gap, 0),
+            PanelAnchor::Right => (-self.anchor_gap, 0),
+            PanelAnchor::Top => (0, self.anchor_gap),
+            PanelAnchor::Bottom => (0, -self.anchor_gap),
+        }
+        .into()
+    }
+
+    /// Config entries sent live to the applet `client`, if it is in this panel.
+    pub fn live_config_entries(&self, client: &Client) -> Option<BTreeMap<String, String>> {
+        let side = self.applet_side(&client.id())?;
+        let size = ron::ser::to_string(&self.config.get_effective_applet_size(side)).ok()?;
+        let expansion = self.expansions.get(&client.id()).map_or(0, |e| e.current);
+        let anchor = ron::ser::to_string(&self.config.anchor()).ok()?;
+        let theme_override = ron::ser::to_string(&self.config.theme_override).ok()?;
+        let mut entries = BTreeMap::from([
+            ("size".to_string(), size),
+            ("expansion".to_string(), expansion.to_string()),
+            ("anchor".to_string(), anchor),
+            ("theme_override".to_string(), theme_override),
+        ]);
+        if let Some(geo) = self.applet_output_geometry(client) {
+            let geo = (geo.loc.x, geo.loc.y, geo.size.w, geo.size.h);
+            entries.insert("geometry".to_string(), ron::ser::to_string(&geo).ok()?);
+        }
+        Some(entries)
+    }
+
+    /// Shrink the panel to the condensed size of its autohide config, or
+    /// restore its configured size. Applets get the new size live.
+    pub(crate) fn set_condensed(&mut self, condensed: bool) {
+        if condensed == self.condensed_from.is_some() {
+            return;
+        }
+        if let Some(config) = self.condensed_from.take() {
+            self.config = config;
+        } else {
+            let Some(size) = self.config.autohide.as_ref().and_then(|a| a.condensed_size.clone())
+            else {
+                return;
+            };
+            let mut config = self.config.clone();
+            config.size = size;
+            config.size_wings = None;
+            config.size_center = None;
+            self.condensed_from = Some(std::mem::replace(&mut self.config, config));
+        }
+        self.close_popups(|_| false);
+        self.layout_cache.invalidate();
+        self.is_dirty = true;
+    }
+
+    /// Pause the applets of a panel which has been hidden for the freeze delay
+    /// of its autohide config, and resume them once it is shown again.
+    /// Notification applets and applets with `X-CosmicKeepRunning` keep running.
+    pub(crate) fn update_frozen_applets(&mut self) {
+        if !matches!(self.visibility, Visibility::Hidden) {
+            self.hidden_since = None;
+            self.resume_applets();
+            return;
+        }
+        let hidden_since = *self.hidden_since.get_or_insert_with(Instant::now);
+        let Some(delay) = self.config.autohide.as_ref().and_then(|a| a.freeze_delay) else {
+            return;
+        };
+        if self.frozen_pids.is_some()
+            || hidden_since.elapsed() < Duration::from_millis(delay.into())
+        {
+            return;
+        }
+        let mut pids = Vec::new();
+        for clients in [&self.clients_left, &self.clients_center, &self.clients_right] {
+            for c in clients.lock().unwrap().iter() {
+                if c.keep_running || c.is_notification_applet == Some(true) {
+                    continue;
+                }
+                let Some(pid) = c.process.signal_pid() else {
+                    continue;
+                };
+                // SAFETY: kill has no memory safety requirements
+                if unsafe { libc::kill(pid, libc::SIGSTOP) } == 0 {
+                    pids.push(pid);
+                }
+            }
+        }
+        info!("Paused {} applets of hidden panel {}", pids.len(), self.config.name);
+        self.frozen_pids = Some(pids);
+    }
+
+    fn resume_applets(&mut self) {
+        for pid in self.frozen_pids.take().into_iter().flatten() {
+            // SAFETY: kill has no memory safety requirements
+            unsafe { libc::kill(pid, libc::SIGCONT) };
+        }
+    }
+
+    /// Location of the applet window `surface` in the panel.
+    pub fn surface_location(&self, surface: &WlSurface) -> Option<Point<i32, Logical>> {
+        let w = self
+            .space
+            .elements()
+            .find(|w| w.wl_surface().is_some_and(|w| w.as_ref() == surface))?;
+        self.space.element_location(w)
+    }
+
+    /// Estimated size of the textures of the applet windows and popups of this
+    /// panel, in bytes.
+    pub fn applet_texture_bytes(&self) -> usize {
+        let windows = self
+            .space
+            .elements()
+            .filter_map(|e| match e {
+                CosmicMappedInternal::Window(w) => w.wl_surface().map(|s| s.into_owned()),
+                _ => None,
+            })
+            .chain(
+                self.overflow_left
+                    .elements()
+                    .chain(self.overflow_center.elements())
+                    .chain(self.overflow_right.elements())
+                    .filter_map(|e| match e {
+                        PopupMappedInternal::Window(w) => w.wl_surface().map(|s| s.into_owned()),
+                        _ => None,
+                    }),
+            );
+        windows
+            .chain(self.popups.iter().map(|p| p.s_surface.wl_surface().clone()))
+            .filter_map(|s| {
+                with_renderer_surface_state(&s, |state| {
+                    let scale = state.buffer_scale().max(1);
+                    state.buffer_size().map(|size| {
+                        buffer_pool::byte_size(size.to_buffer(scale, Transform::Normal))
+                    })
+                })
+                .flatten()
+            })
+            .sum()
+    }
+
+    /// Run the configured `hook` of the panel.
+    pub(crate) fn run_hook(&self, hook: PanelHook, applet: Option<&str>) {
+        let output =
+            self.output.as_ref().and_then(|(_, _, info)| info.name.as_deref()).unwrap_or_default();
+        if let Some(run) = HookRun::new(hook, &self.config.hooks, &self.config.name, output, applet)
+        {
+            if let Err(err) = self.applet_tx.try_send(AppletMsg::RunHook(run)) {
+                error!("Failed to send hook {}", err);
+            }
+        }
+    }
+
+    /// Run the shown or hidden hook once an autohidden panel settles in the
+    /// other state.
+    fn run_visibility_hooks(&mut self) {
+        if self.config.autohide.is_none() {
+            self.hook_shown = None;
+            return;
+        }
+        let shown = match self.visibility {
+            Visibility::Visible => true,
+            Visibility::Hidden => false,
+            _ => return,
+        };
+        if self.hook_shown.replace(shown).is_some_and(|prev| prev != shown) {
+            self.run_hook(if shown { PanelHook::Shown } else { PanelHook::Hidden }, None);
+        }
+    }
+
     pub fn handle_focus(&mut self) {
         let (layer_surface, layer_shell_wl_surface) =
             if let Some(layer_surface) = self.layer.as_ref() {
@@ -486,15 +1095,31 @@ impl PanelSpace {
                 return;
             };
 
+            // a drag over the panel reveals it like hover, so it can be
+            // dropped onto applets, and edit mode, rules, the visibility
+            // override and hot corners keep it revealed
             let f = c_hovered_surface.iter().fold(
-                if self.animate_state.is_some() || !self.output_has_toplevel {
+                if self.animate_state.is_some()
+                    || !self.output_has_toplevel
+                    || self.dragging
+                    || self.edit_mode.is_some()
+                    || self.rules.show()
+                    || self.force_visible()
+                    || matches!(self.hot_corner_focus, Some(FocusStatus::Focused))
+                {
                     FocusStatus::Focused
                 } else {
-                    FocusStatus::LastFocused(self.start_instant)
+                    let last = self.drag_ended.unwrap_or(self.start_instant);
+                    match self.hot_corner_focus {
+                        Some(FocusStatus::LastFocused(left)) => {
+                            FocusStatus::LastFocused(last.max(left))
+                        },
+                        _ => FocusStatus::LastFocused(last),
+                    }
                 },
                 |acc, (surface, _, f)| {
                     if surface.is_alive()
-                        && (self.layer.as_ref().is_some_and(|s| *s.wl_surface() == *surface)
+                        && (self.panel_surface().is_some_and(|s| *s == *surface)
                             || self.popups.iter().any(|p| {
                                 &p.popup.c_popup.wl_surface() == &surface
                                     || self
@@ -522,11 +1147,39 @@ impl PanelSpace {
             f
         };
 
-        let intellihide = self.overlap_notify.is_some();
+        // a suppressed panel hides without reacting to hover or toplevels
+        let (cur_hover, hidden_by_toplevels) = if self.suppressed {
+            (FocusStatus::LastFocused(self.start_instant), true)
+        } else {
+            (cur_hover, self.hidden_by_toplevels)
+        };
+        let intellihide = self.overlap_notify.is_some() || self.overlap_fallback || self.suppressed;
+        let condense = !self.suppressed
+            && self.config.autohide.as_ref().is_some_and(|a| a.condensed_size.is_some());
+        if condense {
+            // stay in place, and only change size
+            let condensed = match cur_hover {
+                FocusStatus::LastFocused(t) => {
+                    Instant::now().checked_duration_since(t).is_some_and(|d| {
+                        d > self.config.get_hide_wait().unwrap()
+                            && (!intellihide || hidden_by_toplevels)
+                    })
+                },
+                FocusStatus::Focused => false,
+            };
+            self.set_condensed(condensed);
+            if !matches!(self.visibility, Visibility::Visible) {
+                self.anchor_gap = 0;
+                self.visibility = Visibility::Visible;
+                self.is_dirty = true;
+            }
+            return;
+        }
+        self.set_condensed(false);
         match self.visibility {
             Visibility::Hidden => {
                 if matches!(cur_hover, FocusStatus::Focused)
-                    || (intellihide && self.toplevel_overlaps.is_empty())
+                    || (intellihide && !hidden_by_toplevels)
                 {
                     // start transition to visible
                     let margin = match self.config.anchor() {
@@ -549,7 +1202,7 @@ impl PanelSpace {
                         None => return,
                     };
                     if duration_since_last_focus > self.config.get_hide_wait().unwrap()
-                        && (!intellihide || !self.toplevel_overlaps.is_empty())
+                        && (!intellihide || hidden_by_toplevels)
                     {
                         self.is_dirty = true;
                         self.visibility = Visibility::TransitionToHidden {
@@ -578,7 +1231,7 @@ impl PanelSpace {
                 self.is_dirty = true;
 
                 if matches!(cur_hover, FocusStatus::Focused)
-                    || (intellihide && self.toplevel_overlaps.is_empty())
+                    || (intellihide && !hidden_by_toplevels)
                 {
                     // start transition to visible
                     self.visibility = Visibility::TransitionToVisible {
@@ -598,7 +1251,8 @@ impl PanelSpace {
 
                     if progress > total_t {
                         if self.config.exclusive_zone() {
-                            layer_surface.set_exclusive_zone(panel_size);
+                            self.exclusive_zone = panel_size;
+                            layer_surface.set_exclusive_zone(self.exclusive_zone);
                         }
 
                         self.anchor_gap = target;
@@ -607,7 +1261,8 @@ impl PanelSpace {
                     } else {
                         if prev_margin != cur_pix {
                             if self.config.exclusive_zone() {
-                                layer_surface.set_exclusive_zone(panel_size - cur_pix);
+                                self.exclusive_zone = panel_size - cur_pix;
+                                layer_surface.set_exclusive_zone(self.exclusive_zone);
                             }
 
                             self.anchor_gap = cur_pix;
@@ -639,7 +1294,7 @@ impl PanelSpace {
                 self.is_dirty = true;
 
                 if matches!(cur_hover, FocusStatus::LastFocused(_))
-                    && (!intellihide || !self.toplevel_overlaps.is_empty())
+                    && (!intellihide || hidden_by_toplevels)
                 {
                     // start transition to hide
                     self.close_popups(|_| false);
@@ -660,7 +1315,8 @@ impl PanelSpace {
 
                     if progress > total_t {
                         if self.config.exclusive_zone() {
-                            layer_surface.set_exclusive_zone(panel_size);
+                            self.exclusive_zone = panel_size;
+                            layer_surface.set_exclusive_zone(self.exclusive_zone);
                         }
 
                         self.anchor_gap = 0;
@@ -668,7 +1324,8 @@ impl PanelSpace {
                     } else {
                         if prev_margin != cur_pix {
                             if self.config.exclusive_zone() {
-                                layer_surface.set_exclusive_zone(panel_size - cur_pix);
+                                self.exclusive_zone = panel_size - cur_pix;
+                                layer_surface.set_exclusive_zone(self.exclusive_zone);
                             }
 
                             self.anchor_gap = cur_pix;
@@ -779,6 +1436,11 @@ impl PanelSpace {
                     + ((animation_state.end.gap as f32 - animation_state.start.gap as f32)
                         * progress))
                     .round() as u16,
+                thickness: animation_state
+                    .start
+                    .thickness
+                    .zip(animation_state.end.thickness)
+                    .map(|(start, end)| start + (end - start) * progress),
             };
             animation_state.cur = new_cur;
         }
@@ -819,8 +1481,11 @@ impl PanelSpace {
     ) -> Instant {
         self.space.refresh();
         self.apply_animation_state();
+        self.update_effects();
 
         self.handle_focus();
+        self.update_handle_region();
+        self.run_visibility_hooks();
         let mut should_render = false;
         match self.space_event.take() {
             Some(SpaceEvent::Quit) => {
@@ -831,6 +1496,11 @@ impl PanelSpace {
                 self.space_event.replace(Some(SpaceEvent::WaitConfigure { first, width, height }));
             },
             _ => {
+                if self.preview.is_some() {
+                    if let Some(size) = self.pending_dimensions.take() {
+                        self.resize_preview(size, renderer.as_deref_mut());
+                    }
+                }
                 if let (Some(size), Some(layer_surface)) =
                     (self.pending_dimensions.take(), self.layer.as_ref())
                 {
@@ -847,7 +1517,8 @@ impl PanelSpace {
                     };
 
                     if self.config.autohide.is_none() && self.config.exclusive_zone() {
-                        self.layer.as_ref().unwrap().set_exclusive_zone(list_thickness as i32);
+                        self.exclusive_zone = list_thickness as i32;
+                        self.layer.as_ref().unwrap().set_exclusive_zone(self.exclusive_zone);
                         if self.config.get_effective_anchor_gap() > 0 {
                             Self::set_margin(
                                 self.config.anchor,
@@ -861,7 +1532,8 @@ impl PanelSpace {
                         && matches!(self.visibility, Visibility::Hidden)
                     {
                         if self.config.exclusive_zone() {
-                            layer_surface.set_exclusive_zone(list_thickness as i32);
+                            self.exclusive_zone = list_thickness as i32;
+                            layer_surface.set_exclusive_zone(self.exclusive_zone);
                         }
                         Self::set_margin(
                             self.config.anchor,
@@ -876,7 +1548,7 @@ impl PanelSpace {
                     layer_surface.wl_surface().frame(qh, layer_surface.wl_surface().clone());
 
                     info!("{:?}", self.space_event);
-                } else if self.layer.is_some() {
+                } else if self.panel_surface().is_some() {
                     should_render = true;
                     if self.is_dirty {
                         _ = self.layout_();
@@ -885,6 +1557,10 @@ impl PanelSpace {
             },
         }
 
+        self.finish_dismissed_popups();
+        self.update_popup_session();
+        self.update_popup_scales();
+        self.update_expansions();
         if let Some(renderer) = renderer.as_mut() {
             let prev = self.popups.len();
             self.popups.retain_mut(|p: &mut WrapperPopup| p.handle_events(popup_manager));
@@ -905,9 +1581,18 @@ impl PanelSpace {
         _layer: &LayerSurface,
         configure: LayerSurfaceConfigure,
         renderer: &mut Option<GlesRenderer>,
+    ) {
+        self.configure_panel_surface(configure.new_size, renderer);
+    }
+
+    /// Apply the size suggested by the compositor for the layer surface, or
+    /// the toplevel of a preview.
+    pub(crate) fn configure_panel_surface(
+        &mut self,
+        (w, h): (u32, u32),
+        renderer: &mut Option<GlesRenderer>,
     ) {
         self.is_dirty = true;
-        let (w, h) = configure.new_size;
         match self.space_event.take() {
             Some(e) => match e {
                 SpaceEvent::WaitConfigure { first, mut width, mut height } => {
@@ -940,13 +1625,9 @@ impl PanelSpace {
                         }
                         let client_egl_surface = unsafe {
                             ClientEglSurface::new(
-                                WlEglSurface::new(
-                                    self.layer.as_ref().unwrap().wl_surface().id(),
-                                    dim.w,
-                                    dim.h,
-                                )
-                                .unwrap(), // TODO remove unwrap
-                                self.layer.as_ref().unwrap().wl_surface().clone(),
+                                WlEglSurface::new(self.panel_surface().unwrap().id(), dim.w, dim.h)
+                                    .unwrap(), // TODO remove unwrap
+                                self.panel_surface().unwrap().clone(),
                             )
                         };
                         let new_egl_display = if let Some(renderer) = renderer.as_ref() {
@@ -1059,35 +1740,74 @@ impl PanelSpace {
                     height = 1;
                 }
                 let dim = self.constrain_dim((width, height).into(), Some(self.gap() as u32));
-
-                if let (Some(renderer), Some(egl_surface)) =
-                    (renderer.as_mut(), self.egl_surface.as_ref())
-                {
-                    let _ = renderer.unbind();
-                    let _ = renderer.bind(egl_surface.clone());
-                    let scaled_size = dim.to_f64().to_physical(self.scale).to_i32_round();
-                    egl_surface.resize(scaled_size.w, scaled_size.h, 0, 0);
-                    let _ = renderer.unbind();
-                    if let Some(viewport) = self.layer_viewport.as_ref() {
-                        viewport.set_destination(dim.w, dim.h);
-                    }
-                }
-                self.dimensions = (dim.w, dim.h).into();
-                self.damage_tracked_renderer = Some(OutputDamageTracker::new(
-                    dim.to_f64().to_physical(self.scale).to_i32_round(),
-                    self.scale,
-                    smithay::utils::Transform::Flipped180,
-                ));
+                self.apply_surface_size(dim, renderer.as_mut());
             },
         }
     }
 
-    pub fn is_dark(&self, system_is_dark: bool) -> bool {
-        match &self.config.background {
-            CosmicPanelBackground::ThemeDefault | CosmicPanelBackground::Color(_) => system_is_dark,
-            CosmicPanelBackground::Dark => true,
-            CosmicPanelBackground::Light => false,
+    /// Reset the state of the panel for its new layer surface or preview
+    /// toplevel, and start its applets.
+    pub(super) fn init_panel_surface(
+        &mut self,
+        fractional_scale: Option<WpFractionalScaleV1>,
+        viewport: Option<WpViewport>,
+        dimensions: Size<i32, Logical>,
+        qh: &QueueHandle<GlobalState>,
+    ) {
+        let next_render_event = Rc::new(Cell::new(Some(SpaceEvent::WaitConfigure {
+            first: true,
+            width: dimensions.w,
+            height: dimensions.h,
+        })));
+
+        // the new layer surface is on the configured layer
+        self.suppressed = false;
+        self.lowered = false;
+        self.layer_fractional_scale = fractional_scale;
+        self.layer_viewport = viewport;
+        self.dimensions = dimensions;
+        self.space_event = next_render_event;
+        self.is_dirty = true;
+        self.left_overflow_button_id = id::Id::new(format!("left_overflow_button_{}", self.id()));
+        self.right_overflow_button_id = id::Id::new(format!("right_overflow_button_{}", self.id()));
+        self.center_overflow_button_id =
+            id::Id::new(format!("center_overflow_button_{}", self.id()));
+        self.left_overflow_popup_id = id::Id::new(format!("left_overflow_popup_{}", self.id()));
+        self.right_overflow_popup_id = id::Id::new(format!("right_overflow_popup_{}", self.id()));
+        self.center_overflow_popup_id = id::Id::new(format!("center_overflow_popup_{}", self.id()));
+
+        self.map_builtin_applets();
+        if let Err(err) = self.spawn_clients(
+            self.s_display.clone().unwrap(),
+            qh,
+            self.security_context_manager.clone(),
+        ) {
+            error!(?err, "Failed to spawn clients");
+        }
+    }
+
+    /// Resize the rendering surface of the panel to `dim`.
+    pub(super) fn apply_surface_size(
+        &mut self,
+        dim: Size<i32, Logical>,
+        renderer: Option<&mut GlesRenderer>,
+    ) {
+        if let (Some(renderer), Some(egl_surface)) = (renderer, self.egl_surface.as_ref()) {
+            let _ = renderer.unbind();
+            let _ = renderer.bind(egl_surface.clone());
+            let scaled_size = dim.to_f64().to_physical(self.scale).to_i32_round();
+            egl_surface.resize(scaled_size.w, scaled_size.h, 0, 0);
+            let _ = renderer.unbind();
+            if let Some(viewport) = self.layer_viewport.as_ref() {
+                viewport.set_destination(dim.w, dim.h);
+            }
         }
+        self.dimensions = (dim.w, dim.h).into();
+        self.damage_tracked_renderer = Some(OutputDamageTracker::new(
+            dim.to_f64().to_physical(self.scale).to_i32_round(),
+            self.scale,
+            smithay::utils::Transform::Flipped180,
+        ));
     }
 
     pub fn set_theme(&mut self, colors: PanelColors) {
@@ -1100,6 +1820,7 @@ impl PanelSpace {
                 border_radius: self.config.border_radius,
                 expanded: if self.config.expand_to_edges { 1.0 } else { 0.0 },
                 gap: self.gap(),
+                thickness: None,
             };
             let cur = start.clone();
             let mut end = start.clone();
@@ -1115,11 +1836,17 @@ impl PanelSpace {
         }
 
         for e in self.space.elements() {
-            let CosmicMappedInternal::OverflowButton(b) = e else {
-                continue;
-            };
-            b.set_theme(colors.theme.clone());
-            b.force_redraw();
+            match e {
+                CosmicMappedInternal::OverflowButton(b) => {
+                    b.set_theme(colors.theme.clone());
+                    b.force_redraw();
+                },
+                CosmicMappedInternal::Builtin(b) => {
+                    b.set_theme(colors.theme.clone());
+                    b.force_redraw();
+                },
+                _ => {},
+            }
         }
         for e in self
             .overflow_center
@@ -1133,12 +1860,29 @@ impl PanelSpace {
             e.set_theme(colors.theme.clone());
             e.force_redraw();
         }
+        if let Some(hud) = self.frame_stats_hud.as_ref() {
+            hud.set_theme(colors.theme.clone());
+            hud.force_redraw();
+        }
+        if let Some(menu) = self.context_menu.as_ref() {
+            menu.element.set_theme(colors.theme.clone());
+            menu.element.force_redraw();
+        }
+        if let Some(edit_mode) = self.edit_mode.as_ref() {
+            for slot in edit_mode.placeholders.iter().map(|(_, s)| s).chain(&edit_mode.trash) {
+                slot.element.set_theme(colors.theme.clone());
+                slot.element.force_redraw();
+            }
+        }
+        // the background is rebuilt from the new theme by the animation
+        self.is_dirty = true;
         self.colors = colors;
     }
 
     /// clear the panel
     pub fn clear(&mut self) {
         self.is_dirty = true;
+        self.layout_cache.invalidate();
         self.close_popups(|_| false);
         self.overflow_popup = None;
         self.damage_tracked_renderer = Some(OutputDamageTracker::new(
@@ -1156,6 +1900,7 @@ impl PanelSpace {
         pos_state: PositionerState,
         s_surface: &PopupSurface,
     ) {
+        let mut panel_state = pos_state;
         let PositionerState {
             rect_size,
             anchor_rect,
@@ -1197,6 +1942,8 @@ impl PanelSpace {
             tracing::warn!("No parent surface found for popup");
             (0, 0).into()
         };
+        panel_state.anchor_rect.loc += p_offset;
+        let constrained_offset = self.constrain_popup_offset(panel_state);
 
         positioner.set_size(rect_size.w.max(1), rect_size.h.max(1));
         positioner.set_anchor_rect(
@@ -1211,7 +1958,7 @@ impl PanelSpace {
         positioner.set_constraint_adjustment(
             u32::from(constraint_adjustment).try_into().unwrap_or(ConstraintAdjustment::empty()),
         );
-        positioner.set_offset(offset.x, offset.y);
+        positioner.set_offset(offset.x + constrained_offset.x, offset.y + constrained_offset.y);
         if positioner.version() >= 3 {
             if reactive {
                 positioner.set_reactive();
@@ -1255,7 +2002,8 @@ impl PanelSpace {
                     -1
                 };
 
-                l.set_exclusive_zone(list_thickness);
+                self.exclusive_zone = list_thickness;
+                l.set_exclusive_zone(self.exclusive_zone);
                 needs_commit = true;
             }
         }
@@ -1269,7 +2017,8 @@ impl PanelSpace {
                     PanelAnchor::Left | PanelAnchor::Right => self.dimensions.w,
                     PanelAnchor::Top | PanelAnchor::Bottom => self.dimensions.h,
                 };
-                l.set_exclusive_zone(list_thickness);
+                self.exclusive_zone = list_thickness;
+                l.set_exclusive_zone(self.exclusive_zone);
                 let (width, height) = if self.config.is_horizontal() {
                     (0, self.dimensions.h)
                 } else {
@@ -1307,21 +2056,40 @@ impl PanelSpace {
             }
         }
 
+        // the applets get their new size with their live config, and the
+        // thickness of the panel follows it
+        let size_changed = [Side::WingStart, Side::Center, Side::WingEnd].into_iter().any(|s| {
+            config.get_effective_applet_size(s) != self.config.get_effective_applet_size(s)
+        });
+        if size_changed {
+            self.close_popups(|_| false);
+        }
+
         if animate {
+            let thickness =
+                if self.config.is_horizontal() { self.actual_size.h } else { self.actual_size.w }
+                    as f32;
             let start = AnimatableState {
                 bg_color: self.colors.bg_color(self.config.opacity),
                 border_radius: self.config.border_radius,
                 expanded: if self.config.expand_to_edges { 1.0 } else { 0.0 },
                 gap: self.gap(),
+                thickness: size_changed.then_some(thickness),
             };
-            let end = AnimatableState {
+            let mut end = AnimatableState {
                 bg_color,
                 border_radius: config.border_radius,
                 expanded: if config.expand_to_edges { 1.0 } else { 0.0 },
                 gap: config.get_effective_anchor_gap() as u16,
+                thickness: size_changed.then(|| list_thickness(&config)),
             };
             if let Some(animated_state) = self.animate_state.as_mut() {
                 animated_state.start = animated_state.cur.clone();
+                if size_changed {
+                    animated_state.start.thickness.get_or_insert(thickness);
+                } else {
+                    end.thickness = animated_state.end.thickness;
+                }
                 animated_state.end = end;
                 animated_state.started_at = Instant::now();
                 animated_state.progress = 0.0;
@@ -1337,12 +2105,26 @@ impl PanelSpace {
             }
         }
 
+        // applets were reordered within their sections
+        let reordered = config.plugins_center != self.config.plugins_center
+            || config.plugins_wings != self.config.plugins_wings
+            || config.output_plugins != self.config.output_plugins;
+        if reordered {
+            self.sort_applets(&config);
+        }
+
+        self.layout_cache.invalidate();
         self.config = config;
+        if reordered {
+            self.reset_overflow();
+        }
+        self.map_builtin_applets();
 
         self.clear();
     }
 
     pub fn reset_overflow(&mut self) {
+        self.layout_cache.invalidate();
         // re-map all windows to the main space from overflow
         // remove all overflow buttons and popups
         let overflow = self
@@ -1426,7 +2208,123 @@ impl PanelSpace {
 
 impl Drop for PanelSpace {
     fn drop(&mut self) {
+        // paused applets couldn't handle being stopped
+        self.resume_applets();
         // request processes to stop
         let _ = self.applet_tx.try_send(AppletMsg::Cleanup(self.id()));
+        for clients in [&self.clients_left, &self.clients_center, &self.clients_right] {
+            self.applets.remove(clients.lock().unwrap().iter().map(|c| c.client.id()));
+        }
+    }
+}
+
+/// The location of a panel surface of `size` anchored to `anchor` with the
+/// margin `gap`, as the compositor arranges it in `zone`.
+///
+/// The panel stretches along the zone less its margins at both ends, and is
+/// placed at the gap from its anchored edge. The exclusive zones of other
+/// clients aren't known, so what the configured length lacks is split evenly
+/// between both ends.
+fn location_in_zone(
+    zone: Rectangle<i32, Logical>,
+    anchor: PanelAnchor,
+    size: Size<i32, Logical>,
+    gap: i32,
+) -> Point<i32, Logical> {
+    let centered = |start: i32, zone: i32, panel: i32| start + (zone - panel) / 2;
+    match anchor {
+        PanelAnchor::Top => (centered(zone.loc.x, zone.size.w, size.w), zone.loc.y + gap),
+        PanelAnchor::Bottom => {
+            (centered(zone.loc.x, zone.size.w, size.w), zone.loc.y + zone.size.h - size.h - gap)
+        },
+        PanelAnchor::Left => (zone.loc.x + gap, centered(zone.loc.y, zone.size.h, size.h)),
+        PanelAnchor::Right => {
+            (zone.loc.x + zone.size.w - size.w - gap, centered(zone.loc.y, zone.size.h, size.h))
+        },
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panels_are_placed_at_their_edge_of_the_zone() {
+        let zone = Rectangle::from_loc_and_size((64, 40), (1856, 1040));
+        let size = Size::from((1000, 48));
+        assert_eq!(location_in_zone(zone, PanelAnchor::Top, size, 0), Point::from((492, 40)));
+        assert_eq!(location_in_zone(zone, PanelAnchor::Bottom, size, 8), Point::from((492, 1024)));
+
+        let size = Size::from((48, 600));
+        assert_eq!(location_in_zone(zone, PanelAnchor::Left, size, 4), Point::from((68, 260)));
+        assert_eq!(location_in_zone(zone, PanelAnchor::Right, size, 0), Point::from((1872, 260)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::{fd::AsRawFd, unix::net::UnixStream},
+        process::Command,
+    };
+
+    use super::*;
+
+    fn peer_pid(stream: &UnixStream) -> i32 {
+        // SAFETY: ucred is plain data, and getsockopt writes at most `len` bytes
+        unsafe {
+            let mut credentials: libc::ucred = std::mem::zeroed();
+            let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+            let res = libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut credentials as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            );
+            assert_eq!(res, 0);
+            credentials.pid
+        }
+    }
+
+    fn state(pid: i32) -> char {
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).unwrap();
+        stat.rsplit_once(") ").unwrap().1.chars().next().unwrap()
+    }
+
+    #[test]
+    fn applets_are_signaled_by_the_pid_of_their_process() {
+        // the socket pair of an applet is created by the panel, so its
+        // credentials are those of the panel
+        let (panel_end, _applet_end) = UnixStream::pair().unwrap();
+        assert_eq!(peer_pid(&panel_end) as u32, std::process::id());
+
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let process = AppletProcess { pid: Some(child.id()), ..Default::default() };
+        let pid = process.signal_pid().unwrap();
+        assert_eq!(pid as u32, child.id());
+        assert_ne!(pid as u32, std::process::id());
+
+        // SAFETY: kill has no memory safety requirements
+        assert_eq!(unsafe { libc::kill(pid, libc::SIGSTOP) }, 0);
+        while state(pid) != 'T' {
+            std::thread::yield_now();
+        }
+        // SAFETY: kill has no memory safety requirements
+        assert_eq!(unsafe { libc::kill(pid, libc::SIGCONT) }, 0);
+        while state(pid) == 'T' {
+            std::thread::yield_now();
+        }
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn applets_without_a_process_are_not_signaled() {
+        assert_eq!(AppletProcess::default().signal_pid(), None);
+        assert_eq!(AppletProcess { pid: Some(0), ..Default::default() }.signal_pid(), None);
+        let pid = Some(u32::MAX);
+        assert_eq!(AppletProcess { pid, ..Default::default() }.signal_pid(), None);
     }
 }
@@ -1,46 +1,115 @@
-use std::rc::Rc;
+use std::{
+    mem,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
-use crate::xdg_shell_wrapper::space::{ClientEglSurface, PanelPopup, WrapperPopupState};
-use cctk::wayland_client::Proxy;
+use crate::xdg_shell_wrapper::space::{
+    ClientEglSurface, PanelPopup, WrapperPopup, WrapperPopupState,
+};
+use cctk::wayland_client::{protocol::wl_surface::WlSurface, Proxy};
 use sctk::shell::xdg::popup::{self};
 use smithay::{
     backend::{egl::EGLSurface, renderer::gles::GlesRenderer},
-    utils::Rectangle,
-    wayland::seat::WaylandFocus,
+    reexports::wayland_server::Resource,
+    utils::{Logical, Point, Rectangle},
+    wayland::{
+        compositor::with_states, fractional_scale::with_fractional_scale, seat::WaylandFocus,
+        shell::xdg::PopupSurface,
+    },
 };
 use wayland_egl::WlEglSurface;
 
-use super::PanelSpace;
+use super::{layout::OverflowSection, PanelSpace};
+
+/// The scale `popup` is rendered at for the output `scale`.
+///
+/// The popup is sized by its viewport, like the panel surface. Without one,
+/// the buffer can only be scaled by whole numbers, so the popup is rendered at
+/// the next one.
+fn render_scale(popup: &WrapperPopup, scale: f64) -> f64 {
+    if popup.popup.viewport.is_some() {
+        scale
+    } else {
+        scale.ceil()
+    }
+}
+
+/// Render `popup` at `scale` from the next frame on.
+pub(crate) fn set_popup_scale(popup: &mut WrapperPopup, scale: f64) {
+    let scale = render_scale(popup, scale);
+    popup.popup.scale = scale;
+    let Rectangle { loc, size } = popup.popup.rectangle;
+    if popup.popup.state.is_none() {
+        popup.popup.state = Some(WrapperPopupState::Rectangle {
+            x: loc.x,
+            y: loc.y,
+            width: size.w,
+            height: size.h,
+        });
+    }
+    // a buffer scale set when the popup was created would have to divide the
+    // size of the buffer, which a fractional scale doesn't guarantee
+    let buffer_scale = if popup.popup.viewport.is_some() { 1 } else { scale as i32 };
+    popup.popup.c_popup.wl_surface().set_buffer_scale(buffer_scale);
+    with_states(popup.s_surface.wl_surface(), |states| {
+        with_fractional_scale(states, |fractional_scale| {
+            fractional_scale.set_preferred_scale(scale);
+        });
+    });
+}
+
+/// A popup dismissed by the panel, which is shown until its exit animation
+/// finishes.
+#[derive(Debug)]
+pub(crate) enum DismissedPopup {
+    Applet(WrapperPopup),
+    Overflow(PanelPopup, OverflowSection),
+}
 
 impl PanelSpace {
     pub(crate) fn close_popups<'a>(&mut self, exclude: impl Fn(&PanelPopup) -> bool) {
         tracing::info!("Closing popups");
+        let animate = !self.config.reduce_motion;
         let mut to_destroy = Vec::with_capacity(self.popups.len());
-        self.popups.retain_mut(|p| {
+        let mut to_unfocus = Vec::with_capacity(self.popups.len());
+        for mut p in mem::take(&mut self.popups) {
             if exclude(&p.popup) {
-                return true;
+                self.popups.push(p);
+                continue;
             }
 
             tracing::info!("Closing popup: {:?}", p.popup.c_popup.wl_surface());
-            p.s_surface.send_popup_done();
-            to_destroy.push((
-                p.popup.c_popup.xdg_popup().clone(),
+            to_unfocus.push((
                 p.popup.c_popup.wl_surface().clone(),
                 Some(p.s_surface.wl_surface().clone()),
             ));
-            false
-        });
+            // the applet is told once the popup faded out, so its content stays
+            if animate && p.popup.egl_surface.is_some() && p.s_surface.alive() {
+                p.popup.animation.dismiss();
+                self.dismissed_popups.push(DismissedPopup::Applet(p));
+            } else {
+                p.s_surface.send_popup_done();
+                to_destroy.push(p.popup);
+            }
+        }
         if self.overflow_popup.as_ref().is_some_and(|(p, _)| !exclude(&p)) {
-            let (popup, _) = self.overflow_popup.take().unwrap();
+            let (mut popup, section) = self.overflow_popup.take().unwrap();
             tracing::info!("Closing overflow popup: {:?}", popup.c_popup.wl_surface());
-            to_destroy.push((
-                popup.c_popup.xdg_popup().clone(),
-                popup.c_popup.wl_surface().clone(),
-                None,
-            ));
+            to_unfocus.push((popup.c_popup.wl_surface().clone(), None));
+            if animate && popup.egl_surface.is_some() {
+                popup.animation.dismiss();
+                self.dismissed_popups.push(DismissedPopup::Overflow(popup, section));
+            } else {
+                to_destroy.push(popup);
+            }
         }
 
-        for (popup, surface, s_surface) in to_destroy {
+        if self.context_menu.as_ref().is_some_and(|m| !exclude(&m.popup)) {
+            self.close_context_menu();
+        }
+
+        for (surface, s_surface) in to_unfocus {
             self.c_focused_surface.borrow_mut().retain(|s| &s.0 != &surface);
             self.c_hovered_surface.borrow_mut().retain(|s| &s.0 != &surface);
 
@@ -50,11 +119,134 @@ impl PanelSpace {
                 self.s_hovered_surface
                     .retain(|s| !s.surface.wl_surface().is_some_and(|s| s.as_ref() == &s_surface));
             }
-            popup.destroy();
-            surface.destroy();
+        }
+        for popup in to_destroy {
+            popup.c_popup.xdg_popup().destroy();
+            popup.c_popup.wl_surface().destroy();
         }
     }
 
+    /// Space around the applet popup `s_surface` for its shadow, or 0 if the
+    /// panel doesn't decorate the popups of the applet.
+    pub(crate) fn popup_decoration_margin(&self, s_surface: &PopupSurface) -> i32 {
+        let decorations = &self.config.popup_decorations;
+        if !decorations.enabled {
+            return 0;
+        }
+        let Some(client) = s_surface.wl_surface().client() else {
+            return 0;
+        };
+        let excluded = self
+            .applets
+            .get(&client.id())
+            .is_some_and(|a| decorations.excluded_applets.contains(&a.name));
+        if excluded {
+            0
+        } else {
+            decorations.shadow as i32
+        }
+    }
+
+    /// Destroy the dismissed popups whose exit animation finished.
+    pub(crate) fn finish_dismissed_popups(&mut self) {
+        self.dismissed_popups.retain(|dismissed| {
+            let (popup, s_surface) = match dismissed {
+                DismissedPopup::Applet(p) => (&p.popup, Some(&p.s_surface)),
+                DismissedPopup::Overflow(p, _) => (p, None),
+            };
+            if !popup.animation.is_dismissed() && s_surface.map_or(true, |s| s.alive()) {
+                return true;
+            }
+            if let Some(s_surface) = s_surface {
+                s_surface.send_popup_done();
+            }
+            popup.c_popup.xdg_popup().destroy();
+            popup.c_popup.wl_surface().destroy();
+            false
+        });
+    }
+
+    /// Remember when the last popup of the panel closed, which ends the hover
+    /// session after the session timeout.
+    pub(crate) fn update_popup_session(&mut self) {
+        let has_popups = !self.popups.is_empty() || self.overflow_popup.is_some();
+        if self.had_popups && !has_popups {
+            self.popups_closed_at = Some(Instant::now());
+        }
+        self.had_popups = has_popups;
+        if let Some(client) =
+            self.popups.first().and_then(|p| p.s_surface.wl_surface().client()).map(|c| c.id())
+        {
+            self.last_popup_client = Some(client);
+        }
+    }
+
+    /// The scale of the output most of `geometry` is on, in the logical
+    /// coordinates of the compositor, or the scale of the panel if it is on
+    /// no known output.
+    fn scale_at(&self, geometry: Rectangle<i32, Logical>) -> f64 {
+        self.output_scales
+            .iter()
+            .filter_map(|(output, scale)| {
+                let overlap = output.intersection(geometry)?;
+                Some((overlap.size.w * overlap.size.h, *scale))
+            })
+            .max_by_key(|(area, _)| *area)
+            .map_or(self.scale, |(_, scale)| scale)
+    }
+
+    /// Render applet popups at the scale of the output they mostly occupy,
+    /// unless the compositor prefers a scale for them. The output is checked
+    /// again whenever the compositor moves a popup.
+    pub(crate) fn update_popup_scales(&mut self) {
+        let Some(panel_loc) = self
+            .output
+            .as_ref()
+            .map(|o| Point::from(o.2.location))
+            .zip(self.output_location())
+            .map(|(output, panel)| output + panel)
+        else {
+            return;
+        };
+        // popups are positioned relative to their parent, which is the panel
+        // or an earlier popup
+        let mut parents: Vec<(WlSurface, Point<i32, Logical>)> =
+            self.panel_surface().map(|s| (s.clone(), panel_loc)).into_iter().collect();
+        let mut scales = Vec::with_capacity(self.popups.len());
+        for p in &self.popups {
+            let parent_loc = parents.iter().find(|(s, _)| s == &p.popup.parent).map(|(_, l)| *l);
+            let Some(parent_loc) = parent_loc else {
+                scales.push(None);
+                continue;
+            };
+            let mut geometry = p.popup.wrapper_rectangle;
+            geometry.loc += parent_loc;
+            parents.push((p.popup.c_popup.wl_surface().clone(), geometry.loc));
+            scales.push(p.preferred_scale.is_none().then(|| self.scale_at(geometry)));
+        }
+        for (p, scale) in self.popups.iter_mut().zip(scales) {
+            if let Some(scale) = scale.filter(|s| render_scale(p, *s) != p.popup.scale) {
+                tracing::info!("Rendering popup at the scale {scale} of its output");
+                set_popup_scale(p, scale);
+            }
+        }
+    }
+
+    /// Whether hovering an applet opens its popup without an open popup.
+    /// The applet whose popup closed last isn't reopened until the pointer
+    /// leaves it.
+    pub(crate) fn hover_session_active(&self) -> bool {
+        if self.last_popup_client.is_some() {
+            return false;
+        }
+        if !self.config.autohover_session_only {
+            return true;
+        }
+        self.popups_closed_at.zip(self.config.autohover_session_timeout_ms).is_some_and(
+            |(closed, timeout)| closed.elapsed() < Duration::from_millis(timeout.into()),
+        )
+    }
+
     pub fn configure_panel_popup(
         &mut self,
         popup: &sctk::shell::xdg::popup::Popup,
@@ -125,13 +317,15 @@ impl PanelSpace {
                 popup::ConfigureKind::Reposition { token: _token } => {},
                 _ => {},
             };
-        } else if self
+        } else if let Some(p) = self
             .overflow_popup
-            .as_ref()
-            .is_some_and(|p| p.0.c_popup.wl_surface() == popup.wl_surface())
+            .as_mut()
+            .map(|(p, _)| p)
+            .into_iter()
+            .chain(self.context_menu.as_mut().map(|m| &mut m.popup))
+            .find(|p| p.c_popup.wl_surface() == popup.wl_surface())
         {
-            let (p, _) = self.overflow_popup.as_mut().unwrap();
-            tracing::info!("Configuring overflow popup: {:?}", config);
+            tracing::info!("Configuring panel popup: {:?}", config);
             // use the size that we have already if the new size is 0
             if config.width == 0 {
                 config.width = p.wrapper_rectangle.size.w;
@@ -0,0 +1,148 @@
+//! Constraint solving for popups of applets, before their positioner is passed
+//! to the compositor.
+//!
+//! The compositor only knows the panel surface as the parent of a popup, and
+//! its own adjustments may leave a popup partly outside the output, e.g. when
+//! the logical size of a fractionally scaled output is not a whole number of
+//! physical pixels. The popup is placed in the output-local logical
+//! coordinates of the panel instead, and moved into the output by the offset
+//! of the positioner.
+
+use smithay::{
+    reexports::wayland_protocols::xdg::shell::server::xdg_positioner::{Anchor, Gravity},
+    utils::{Logical, Point, Rectangle, Size},
+    wayland::shell::xdg::PositionerState,
+};
+
+use super::PanelSpace;
+
+/// Move a span at `start` with `len` into the bounds along one axis, keeping
+/// its start inside if it is larger than the bounds.
+fn slide(start: i32, len: i32, bounds_start: i32, bounds_len: i32) -> i32 {
+    if len >= bounds_len {
+        return bounds_start;
+    }
+    start.clamp(bounds_start, bounds_start + bounds_len - len)
+}
+
+fn flip_anchor(anchor: Anchor, x: bool, y: bool) -> Anchor {
+    let (mut left, mut right, mut top, mut bottom) = match anchor {
+        Anchor::Top => (false, false, true, false),
+        Anchor::Bottom => (false, false, false, true),
+        Anchor::Left => (true, false, false, false),
+        Anchor::Right => (false, true, false, false),
+        Anchor::TopLeft => (true, false, true, false),
+        Anchor::BottomLeft => (true, false, false, true),
+        Anchor::TopRight => (false, true, true, false),
+        Anchor::BottomRight => (false, true, false, true),
+        _ => return anchor,
+    };
+    if x {
+        std::mem::swap(&mut left, &mut right);
+    }
+    if y {
+        std::mem::swap(&mut top, &mut bottom);
+    }
+    match (left, right, top, bottom) {
+        (false, false, true, false) => Anchor::Top,
+        (false, false, false, true) => Anchor::Bottom,
+        (true, false, false, false) => Anchor::Left,
+        (false, true, false, false) => Anchor::Right,
+        (true, false, true, false) => Anchor::TopLeft,
+        (true, false, false, true) => Anchor::BottomLeft,
+        (false, true, true, false) => Anchor::TopRight,
+        (false, true, false, true) => Anchor::BottomRight,
+        _ => anchor,
+    }
+}
+
+fn flip_gravity(gravity: Gravity, x: bool, y: bool) -> Gravity {
+    // the anchor and gravity enums share their values
+    let flipped = flip_anchor(Anchor::try_from(gravity as u32).unwrap_or(Anchor::None), x, y);
+    Gravity::try_from(flipped as u32).unwrap_or(gravity)
+}
+
+/// Whether `geometry` overflows `bounds` on the x and y axis.
+fn overflows(geometry: Rectangle<i32, Logical>, bounds: Rectangle<i32, Logical>) -> (bool, bool) {
+    (
+        geometry.loc.x < bounds.loc.x
+            || geometry.loc.x + geometry.size.w > bounds.loc.x + bounds.size.w,
+        geometry.loc.y < bounds.loc.y
+            || geometry.loc.y + geometry.size.h > bounds.loc.y + bounds.size.h,
+    )
+}
+
+/// Place a popup with `state` inside `bounds`, relative to the same parent.
+///
+/// The adjustments the popup allows are tried first, as the compositor would,
+/// and if the popup still overflows it is flipped and slid along the axes it
+/// overflows on.
+fn solve(state: PositionerState, bounds: Rectangle<i32, Logical>) -> Rectangle<i32, Logical> {
+    let geometry = state.get_geometry();
+    if overflows(geometry, bounds) == (false, false) {
+        return geometry;
+    }
+    let mut geometry = state.get_unconstrained_geometry(bounds);
+    let (overflow_x, overflow_y) = overflows(geometry, bounds);
+    if !overflow_x && !overflow_y {
+        return geometry;
+    }
+
+    // flip the popup to the other side of its anchor, if that fits
+    let mut flipped = state;
+    flipped.anchor_edges = flip_anchor(state.anchor_edges, overflow_x, overflow_y);
+    flipped.gravity = flip_gravity(state.gravity, overflow_x, overflow_y);
+    if overflow_x {
+        flipped.offset.x = -flipped.offset.x;
+    }
+    if overflow_y {
+        flipped.offset.y = -flipped.offset.y;
+    }
+    let flipped_geometry = flipped.get_geometry();
+    let (flipped_x, flipped_y) = overflows(flipped_geometry, bounds);
+    if overflow_x && !flipped_x {
+        geometry.loc.x = flipped_geometry.loc.x;
+    }
+    if overflow_y && !flipped_y {
+        geometry.loc.y = flipped_geometry.loc.y;
+    }
+
+    geometry.loc.x = slide(geometry.loc.x, geometry.size.w, bounds.loc.x, bounds.size.w);
+    geometry.loc.y = slide(geometry.loc.y, geometry.size.h, bounds.loc.y, bounds.size.h);
+    geometry
+}
+
+impl PanelSpace {
+    /// The part of the output of the panel popups are placed in, relative to
+    /// the panel surface.
+    ///
+    /// Its size is rounded down to whole physical pixels, so a popup at its
+    /// edge isn't clipped on fractionally scaled outputs.
+    fn popup_bounds(&self) -> Option<Rectangle<i32, Logical>> {
+        let logical_size: Size<i32, Logical> = self.output.as_ref()?.2.logical_size?.into();
+        let output_size = logical_size
+            .to_f64()
+            .to_physical(self.scale)
+            .to_i32_floor::<i32>()
+            .to_f64()
+            .to_logical(self.scale)
+            .to_i32_floor::<i32>();
+        if output_size.w <= 0 || output_size.h <= 0 {
+            return None;
+        }
+        let panel_loc = self.output_location()?;
+        Some(Rectangle::from_loc_and_size((-panel_loc.x, -panel_loc.y), output_size))
+    }
+
+    /// The offset to add to the positioner of a popup with `state`, with its
+    /// anchor rect relative to the panel surface, to keep it on the output of
+    /// the panel.
+    pub(crate) fn constrain_popup_offset(&self, state: PositionerState) -> Point<i32, Logical> {
+        let Some(bounds) = self.popup_bounds() else {
+            return (0, 0).into();
+        };
+        let geometry = state.get_geometry();
+        let solved = solve(state, bounds);
+        solved.loc - geometry.loc
+    }
+}
@@ -0,0 +1,136 @@
+//! Preview mode, in which a panel renders into a toplevel instead of a layer
+//! surface, so the settings can show a live, interactive panel without
+//! affecting the real one.
+//!
+//! The toplevel is created once the xdg shell is available, and it is
+//! configured like the layer surface, with the suggested length of the panel
+//! taken from the size of the window.
+
+use cosmic_panel_config::{CosmicPanelConfig, CosmicPanelOuput};
+use sctk::{
+    compositor::{CompositorState, Region},
+    reexports::{
+        client::{protocol::wl_surface::WlSurface, QueueHandle},
+        protocols::xdg::shell::client::xdg_surface,
+    },
+    shell::{
+        xdg::{
+            window::{WindowConfigure, WindowDecorations},
+            XdgShell, XdgSurface,
+        },
+        WaylandSurface,
+    },
+};
+use smithay::{
+    backend::renderer::gles::GlesRenderer,
+    utils::{Logical, Size},
+};
+
+use crate::xdg_shell_wrapper::{
+    shared_state::GlobalState, wp_fractional_scaling::FractionalScalingManager,
+    wp_viewporter::ViewporterState,
+};
+
+use super::PanelSpace;
+
+pub const PREVIEW_APP_ID: &str = "com.system76.CosmicPanelPreview";
+
+/// The config of a preview panel, which is always shown in its window. The
+/// preview is only shown once, for the first output.
+pub fn preview_config(mut config: CosmicPanelConfig) -> CosmicPanelConfig {
+    config.output = CosmicPanelOuput::All;
+    config.autohide = None;
+    config
+}
+
+impl PanelSpace {
+    /// The surface the panel is rendered to, the layer surface or the toplevel
+    /// of a preview.
+    pub fn panel_surface(&self) -> Option<&WlSurface> {
+        self.layer
+            .as_ref()
+            .map(|l| l.wl_surface())
+            .or_else(|| self.preview.as_ref().map(|w| w.wl_surface()))
+    }
+
+    /// The parent of popups created by the panel. Popups of a layer surface are
+    /// assigned to it after they are created instead.
+    pub(crate) fn popup_parent(&self) -> Option<&xdg_surface::XdgSurface> {
+        self.preview.as_ref().map(|w| w.xdg_surface())
+    }
+
+    /// Create the toplevel of a preview panel, after its output was set up.
+    pub(crate) fn create_preview_window(
+        &mut self,
+        compositor_state: &CompositorState,
+        fractional_scale_manager: Option<&FractionalScalingManager>,
+        viewport: Option<&ViewporterState>,
+        xdg_shell_state: &XdgShell,
+        qh: &QueueHandle<GlobalState>,
+    ) -> anyhow::Result<()> {
+        let dimensions = self.constrain_dim((0, 0).into(), Some(self.gap() as u32));
+
+        let surface = compositor_state.create_surface(qh);
+        let window = xdg_shell_state.create_window(surface, WindowDecorations::RequestServer, qh);
+        window.set_title(format!("Panel preview - {}", self.config.name));
+        window.set_app_id(PREVIEW_APP_ID);
+        // the crosswise size is fixed, the length follows the window
+        if self.config.is_horizontal() {
+            window.set_min_size(Some((1, dimensions.h.max(1) as u32)));
+            window.set_max_size(Some((0, dimensions.h.max(1) as u32)));
+        } else {
+            window.set_min_size(Some((dimensions.w.max(1) as u32, 1)));
+            window.set_max_size(Some((dimensions.w.max(1) as u32, 0)));
+        }
+
+        let input_region = Region::new(compositor_state)?;
+        window.wl_surface().set_input_region(Some(input_region.wl_region()));
+        self.input_region.replace(input_region);
+        self.opaque_region = Region::new(compositor_state).ok();
+
+        let fractional_scale =
+            fractional_scale_manager.map(|f| f.fractional_scaling(window.wl_surface(), qh));
+        let viewport = viewport.map(|v| v.get_viewport(window.wl_surface(), qh));
+
+        window.wl_surface().commit();
+        self.preview = Some(window);
+        self.init_panel_surface(fractional_scale, viewport, dimensions, qh);
+        Ok(())
+    }
+
+    pub(crate) fn configure_panel_preview(
+        &mut self,
+        configure: WindowConfigure,
+        renderer: &mut Option<GlesRenderer>,
+    ) {
+        let (w, h) = configure.new_size;
+        self.configure_panel_surface(
+            (w.map_or(0, |w| w.get()), h.map_or(0, |h| h.get())),
+            renderer,
+        );
+    }
+
+    /// Resize the preview toplevel to the size requested by the layout, which
+    /// a layer surface would request from the compositor instead.
+    pub(super) fn resize_preview(
+        &mut self,
+        size: Size<i32, Logical>,
+        renderer: Option<&mut GlesRenderer>,
+    ) {
+        let Some(window) = self.preview.as_ref() else {
+            return;
+        };
+        let width = if size.w > 0 { size.w } else { self.dimensions.w };
+        let height = if size.h > 0 { size.h } else { self.dimensions.h };
+        let dim = self.constrain_dim((width, height).into(), Some(self.gap() as u32));
+        if self.config.is_horizontal() {
+            window.set_min_size(Some((1, dim.h.max(1) as u32)));
+            window.set_max_size(Some((0, dim.h.max(1) as u32)));
+        } else {
+            window.set_min_size(Some((dim.w.max(1) as u32, 1)));
+            window.set_max_size(Some((dim.w.max(1) as u32, 0)));
+        }
+        self.apply_surface_size(dim, renderer);
+        self.is_dirty = true;
+    }
+}
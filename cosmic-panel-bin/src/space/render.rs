@@ -1,16 +1,31 @@
-use std::{collections::HashSet, time::Duration};
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
 
-use crate::iced::elements::{CosmicMappedInternal, PopupMappedInternal};
+use crate::{
+    frame_stats::FrameSample,
+    iced::{
+        elements::{CosmicMappedInternal, PopupMappedInternal},
+        IcedElement, Program,
+    },
+};
 
 use super::{
-    corner_element::{RoundedRectangleShader, RoundedRectangleShaderElement},
+    corner_element::{
+        RoundedRectangleSettings, RoundedRectangleShader, RoundedRectangleShaderElement,
+    },
     layout::OverflowSection,
+    popup::DismissedPopup,
     PanelSpace,
 };
 use cctk::wayland_client::{Proxy, QueueHandle};
 use itertools::Itertools;
 
-use crate::xdg_shell_wrapper::shared_state::GlobalState;
+use crate::xdg_shell_wrapper::{
+    shared_state::GlobalState,
+    space::{PanelPopup, WrapperPopup},
+};
 use cosmic_panel_config::PanelAnchor;
 use sctk::shell::WaylandSurface;
 use smithay::{
@@ -18,23 +33,27 @@ use smithay::{
         damage::OutputDamageTracker,
         element::{
             memory::MemoryRenderBufferRenderElement,
+            solid::SolidColorRenderElement,
             surface::{render_elements_from_surface_tree, WaylandSurfaceRenderElement},
-            utils::CropRenderElement,
-            AsRenderElements, RenderElement, UnderlyingStorage,
+            utils::{CropRenderElement, Relocate, RelocateRenderElement, RescaleRenderElement},
+            AsRenderElements, Kind, RenderElement, UnderlyingStorage,
         },
         gles::{GlesError, GlesFrame, GlesRenderer},
+        utils::with_renderer_surface_state,
         Bind, Color32F, Frame, Renderer, Unbind,
     },
-    reexports::wayland_server::Resource,
-    utils::{Buffer, Physical, Point, Rectangle},
+    desktop::{PopupKind, Space},
+    reexports::wayland_server::{protocol::wl_surface::WlSurface, Resource},
+    utils::{Buffer, Logical, Physical, Point, Rectangle, Scale, Size},
     wayland::seat::WaylandFocus,
 };
 
 pub(crate) enum PanelRenderElement {
     Wayland(WaylandSurfaceRenderElement<GlesRenderer>),
-    Crop(CropRenderElement<WaylandSurfaceRenderElement<GlesRenderer>>),
+    Crop(CropRenderElement<LetterboxRenderElement>),
     RoundedRectangle(RoundedRectangleShaderElement),
     Iced(MemoryRenderBufferRenderElement<GlesRenderer>),
+    Solid(SolidColorRenderElement),
 }
 
 impl smithay::backend::renderer::element::Element for PanelRenderElement {
@@ -43,6 +62,7 @@ impl smithay::backend::renderer::element::Element for PanelRenderElement {
             Self::Wayland(e, ..) => e.id(),
             Self::Crop(e) => e.id(),
             Self::RoundedRectangle(e) => e.id(),
+            Self::Solid(e) => e.id(),
             Self::Iced(e) => e.id(),
         }
     }
@@ -52,6 +72,7 @@ impl smithay::backend::renderer::element::Element for PanelRenderElement {
             Self::Wayland(e, ..) => e.current_commit(),
             Self::Crop(e) => e.current_commit(),
             Self::RoundedRectangle(e) => e.current_commit(),
+            Self::Solid(e) => e.current_commit(),
             Self::Iced(e) => e.current_commit(),
         }
     }
@@ -61,6 +82,7 @@ impl smithay::backend::renderer::element::Element for PanelRenderElement {
             Self::Wayland(e) => e.src(),
             Self::Crop(e) => e.src(),
             Self::RoundedRectangle(e) => e.src(),
+            Self::Solid(e) => e.src(),
             Self::Iced(e) => e.src(),
         }
     }
@@ -70,6 +92,7 @@ impl smithay::backend::renderer::element::Element for PanelRenderElement {
             Self::Wayland(e) => e.geometry(scale),
             Self::Crop(e) => e.geometry(scale),
             Self::RoundedRectangle(e) => e.geometry(scale),
+            Self::Solid(e) => e.geometry(scale),
             // XXX hack don't know how else to avoid scaling twice
             Self::Iced(e) => e.geometry(1.0.into()),
         }
@@ -90,6 +113,7 @@ impl RenderElement<GlesRenderer> for PanelRenderElement {
             Self::Crop(e) => e.draw(frame, src, dst, damage, opaque_regions),
             Self::RoundedRectangle(e) => e.draw(frame, src, dst, damage, opaque_regions),
             Self::Iced(e) => e.draw(frame, src, dst, damage, opaque_regions),
+            Self::Solid(e) => e.draw(frame, src, dst, damage, opaque_regions),
         }
     }
 
@@ -99,6 +123,7 @@ impl RenderElement<GlesRenderer> for PanelRenderElement {
             PanelRenderElement::Crop(e) => e.underlying_storage(renderer),
             PanelRenderElement::RoundedRectangle(e) => e.underlying_storage(renderer),
             PanelRenderElement::Iced(e) => e.underlying_storage(renderer),
+            PanelRenderElement::Solid(e) => e.underlying_storage(renderer),
         }
     }
 }
@@ -120,12 +145,16 @@ impl PanelSpace {
 
         let clear_color = [0., 0., 0., 0.];
 
-        if self.is_dirty && self.has_frame {
+        if self.is_dirty {
+            self.update_edit_slots();
+        }
+
+        if (self.is_dirty || self.effects_active()) && self.has_frame {
             let hovered_clients: HashSet<_> = self
                 .s_hovered_surface
                 .iter()
                 .chain(self.s_hovered_surface.iter())
-                .filter_map(|c| c.surface.wl_surface().map(|s| s.id()))
+                .filter_map(|c| c.surface.root_surface().map(|s| s.id()))
                 .collect();
             tracing::trace!("Rendering space");
             let my_renderer = match self.damage_tracked_renderer.as_mut() {
@@ -151,7 +180,7 @@ impl PanelSpace {
                         }
                         self.egl_surface.as_ref().unwrap().swap_buffers(None)?;
                     }
-                    let wl_surface = self.layer.as_ref().unwrap().wl_surface();
+                    let wl_surface = self.panel_surface().unwrap();
                     wl_surface.frame(qh, wl_surface.clone());
                     wl_surface.commit();
                     // reset the damage tracker
@@ -179,87 +208,18 @@ impl PanelSpace {
             if let Some((o, _info)) = &self.output.as_ref().map(|(_, o, info)| (o, info)) {
                 let mut elements: Vec<PanelRenderElement> = (self.config.anchor_gap
                     || self.anchor_gap != 0
-                    || self.config.border_radius > 0)
-                    .then(|| {
-                        PanelRenderElement::RoundedRectangle(RoundedRectangleShader::element(
-                            renderer,
-                            Rectangle::from_loc_and_size((0, 0), dim.to_logical(1)),
-                            self.panel_rect_settings,
-                        ))
-                    })
-                    .into_iter()
-                    .chain(
-                        self.space
-                            .elements()
-                            .filter_map(|w| {
-                                let loc = self
-                                    .space
-                                    .element_location(w)
-                                    .unwrap_or_default()
-                                    .to_f64()
-                                    .to_physical(self.scale)
-                                    .to_i32_round()
-                                    + anim_gap_translation;
-
-                                if let CosmicMappedInternal::OverflowButton(b) = w {
-                                    return Some(
-                                        b.render_elements(
-                                            renderer,
-                                            loc,
-                                            smithay::utils::Scale::from(self.scale),
-                                            1.0,
-                                        )
-                                        .into_iter()
-                                        .map(PanelRenderElement::Iced)
-                                        .collect::<Vec<_>>(),
-                                    );
-                                }
-                                w.toplevel().map(|t| {
-                                    let configured_size = t.current_state().size.map(|s| {
-                                        let mut r = Rectangle::from_loc_and_size(
-                                            self.space
-                                                .element_location(w)
-                                                .unwrap_or_default()
-                                                .to_f64()
-                                                .to_physical_precise_round(self.scale),
-                                            s.to_f64().to_physical_precise_round(self.scale),
-                                        );
-                                        if r.size.w == 0 {
-                                            r.size.w = i32::MAX;
-                                        }
-                                        if r.size.h == 0 {
-                                            r.size.h = i32::MAX;
-                                        }
-                                        r
-                                    });
-
-                                    render_elements_from_surface_tree(
-                                        renderer,
-                                        t.wl_surface(),
-                                        loc,
-                                        self.scale,
-                                        1.0,
-                                        smithay::backend::renderer::element::Kind::Unspecified,
-                                    )
-                                    .into_iter()
-                                    .filter_map(|r: WaylandSurfaceRenderElement<GlesRenderer>| {
-                                        if let Some(configured_size) = configured_size {
-                                            return CropRenderElement::from_element(
-                                                r,
-                                                self.scale,
-                                                configured_size,
-                                            )
-                                            .map(PanelRenderElement::Crop);
-                                        }
-
-                                        Some(PanelRenderElement::Wayland(r))
-                                    })
-                                    .collect::<Vec<_>>()
-                                })
-                            })
-                            .flatten(),
-                    )
-                    .collect_vec();
+                    || self.config.border_radius > 0
+                    || self.has_effects())
+                .then(|| {
+                    PanelRenderElement::RoundedRectangle(RoundedRectangleShader::element(
+                        renderer,
+                        Rectangle::from_loc_and_size((0, 0), dim.to_logical(1)),
+                        self.panel_rect_settings,
+                    ))
+                })
+                .into_iter()
+                .chain(self.applet_elements(renderer, self.scale, anim_gap_translation))
+                .collect_vec();
 
                 if let Some(bg) = self.background_element.as_ref().map(|e| {
                     let pos = e.with_program(|p| p.logical_pos);
@@ -278,14 +238,70 @@ impl PanelSpace {
                     elements.extend(bg);
                 };
 
-                _ = my_renderer.render_output(
-                    renderer,
-                    self.egl_surface.as_ref().unwrap().buffer_age().unwrap_or_default() as usize,
-                    &elements,
-                    clear_color,
-                );
+                if let Some(hud) = self.frame_stats_hud.as_ref() {
+                    hud.force_redraw();
+                    let hud_elements = hud
+                        .render_elements(
+                            renderer,
+                            anim_gap_translation,
+                            smithay::utils::Scale::from(self.scale),
+                            1.0,
+                        )
+                        .into_iter()
+                        .map(PanelRenderElement::Iced);
+                    elements.splice(0..0, hud_elements);
+                }
+
+                if let Some(overlay) = self.debug_overlay.as_mut() {
+                    let geometries = self.space.elements().filter_map(|e| {
+                        self.space.element_geometry(e).map(|mut r| {
+                            r.loc +=
+                                anim_gap_translation.to_f64().to_logical(self.scale).to_i32_round();
+                            r
+                        })
+                    });
+                    let overlay_elements = overlay
+                        .render_elements(geometries, self.input_rect, self.scale)
+                        .into_iter()
+                        .map(PanelRenderElement::Solid);
+                    elements.splice(0..0, overlay_elements);
+                }
+
+                if let Some(edit_mode) = self.edit_mode.as_mut() {
+                    let edit_elements = edit_mode.render_elements(
+                        renderer,
+                        anim_gap_translation.to_f64().to_logical(self.scale).to_i32_round(),
+                        self.scale,
+                    );
+                    elements.splice(0..0, edit_elements);
+                }
+
+                let damage_area = my_renderer
+                    .render_output(
+                        renderer,
+                        self.egl_surface.as_ref().unwrap().buffer_age().unwrap_or_default()
+                            as usize,
+                        &elements,
+                        clear_color,
+                    )
+                    .ok()
+                    .and_then(|res| {
+                        res.damage.map(|damage| {
+                            if let Some(overlay) = self.debug_overlay.as_mut() {
+                                overlay.last_damage.clone_from(damage);
+                            }
+                            damage.iter().map(|r| r.size.w as u64 * r.size.h as u64).sum::<u64>()
+                        })
+                    })
+                    .unwrap_or_default();
 
+                let swap_start = Instant::now();
                 self.egl_surface.as_ref().unwrap().swap_buffers(None)?;
+                self.pending_frame.get_or_insert_with(Default::default).merge(FrameSample {
+                    swap: swap_start.elapsed(),
+                    damage_area,
+                    ..Default::default()
+                });
 
                 for window in self.space.elements().filter_map(|w| {
                     if let CosmicMappedInternal::Window(w) = w {
@@ -312,7 +328,7 @@ impl PanelSpace {
                         move |_, _| Some(output.clone()),
                     );
                 }
-                let wl_surface = self.layer.as_ref().unwrap().wl_surface().clone();
+                let wl_surface = self.panel_surface().unwrap().clone();
                 wl_surface.frame(qh, wl_surface.clone());
                 wl_surface.commit();
 
@@ -322,143 +338,432 @@ impl PanelSpace {
         }
         let clear_color = [0.0, 0.0, 0.0, 0.0];
         // TODO Popup rendering optimization
-        for p in self.popups.iter_mut().filter(|p| {
-            p.popup.dirty
-                && p.popup.egl_surface.is_some()
-                && p.popup.state.is_none()
-                && p.s_surface.alive()
-                && p.popup.c_popup.wl_surface().is_alive()
-                && p.popup.has_frame
-        }) {
-            renderer.unbind()?;
-            renderer.bind(p.popup.egl_surface.as_ref().unwrap().clone())?;
-
-            let elements: Vec<WaylandSurfaceRenderElement<_>> = render_elements_from_surface_tree(
-                renderer,
-                p.s_surface.wl_surface(),
-                (0, 0),
-                self.scale,
-                1.0,
-                smithay::backend::renderer::element::Kind::Unspecified,
-            );
-            p.popup.damage_tracked_renderer.render_output(
-                renderer,
-                p.popup.egl_surface.as_ref().unwrap().buffer_age().unwrap_or_default() as usize,
-                &elements,
-                clear_color,
-            )?;
-
-            p.popup.egl_surface.as_ref().unwrap().swap_buffers(None)?;
-
-            let wl_surface = p.popup.c_popup.wl_surface().clone();
-            wl_surface.frame(qh, wl_surface.clone());
-            wl_surface.commit();
-            p.popup.dirty = false;
-            p.popup.has_frame = false;
+        let (scale, anchor) = (self.scale, self.config.anchor);
+        let radius = self.config.popup_decorations.radius;
+        for p in self.popups.iter_mut() {
+            render_applet_popup(renderer, p, anchor, radius, clear_color, qh)?;
         }
 
         // render to overflow_popup
-        if let Some((ref mut p, section)) = self.overflow_popup.as_mut().filter(|(p, _)| {
-            p.dirty
-                && p.egl_surface.is_some()
-                && p.state.is_none()
-                && p.c_popup.wl_surface().is_alive()
-        }) {
-            renderer.unbind()?;
-            renderer.bind(p.egl_surface.as_ref().unwrap().clone())?;
+        if let Some((ref mut p, section)) = self.overflow_popup.as_mut() {
             let space = match section {
                 OverflowSection::Center => &self.overflow_center,
                 OverflowSection::Left => &self.overflow_left,
                 OverflowSection::Right => &self.overflow_right,
             };
-            let mut bg_render_element = None;
-            let mut elements: Vec<PanelRenderElement> = space
-                .elements()
-                .filter_map(|e| match e {
-                    crate::iced::elements::PopupMappedInternal::Popup(e) => {
-                        // move to bg_render_element
-                        bg_render_element = Some(
-                            e.render_elements(renderer, (0, 0).into(), self.scale.into(), 1.0)
-                                .into_iter()
-                                .map(PanelRenderElement::Iced)
-                                .collect::<Vec<_>>(),
-                        );
-                        None
-                    },
-                    crate::iced::elements::PopupMappedInternal::Window(w) => {
-                        let Some(t) = w.toplevel() else {
-                            return None;
-                        };
-
-                        let loc = space
-                            .element_location(&PopupMappedInternal::Window(w.clone()))
-                            .unwrap_or_default()
-                            .to_f64()
-                            .to_physical(self.scale)
-                            .to_i32_round();
-
-                        let configured_size = t.current_state().size.map(|s| {
-                            let mut r = Rectangle::from_loc_and_size(
-                                loc,
-                                s.to_f64().to_physical_precise_round(self.scale),
-                            );
-                            if r.size.w == 0 {
-                                r.size.w = i32::MAX;
-                            }
-                            if r.size.h == 0 {
-                                r.size.h = i32::MAX;
-                            }
-                            r
-                        });
-                        Some(
-                            render_elements_from_surface_tree(
-                                renderer,
-                                t.wl_surface(),
-                                loc,
-                                self.scale,
-                                1.0,
-                                smithay::backend::renderer::element::Kind::Unspecified,
-                            )
-                            .into_iter()
-                            .filter_map(|r: WaylandSurfaceRenderElement<GlesRenderer>| {
-                                if let Some(configured_size) = configured_size {
-                                    return CropRenderElement::from_element(
-                                        r,
-                                        self.scale,
-                                        configured_size,
-                                    )
-                                    .map(PanelRenderElement::Crop);
-                                }
-
-                                Some(PanelRenderElement::Wayland(r))
-                            })
-                            .collect::<Vec<_>>(),
-                        )
-                    },
-                    crate::iced::elements::PopupMappedInternal::_GenericCatcher(_) => None,
-                })
-                .flatten()
-                .collect();
-
-            elements.extend(bg_render_element.unwrap_or_default());
+            render_overflow_popup(renderer, p, space, scale, clear_color, qh)?;
+        }
 
-            _ = p.damage_tracked_renderer.render_output(
-                renderer,
-                p.egl_surface.as_ref().unwrap().buffer_age().unwrap_or_default() as usize,
-                &elements,
-                clear_color,
-            );
-            p.egl_surface.as_ref().unwrap().swap_buffers(None)?;
-            let wl_surface = p.c_popup.wl_surface();
-            wl_surface.frame(qh, wl_surface.clone());
-            wl_surface.commit();
+        // fade out the dismissed popups
+        for dismissed in self.dismissed_popups.iter_mut() {
+            match dismissed {
+                DismissedPopup::Applet(p) => {
+                    render_applet_popup(renderer, p, anchor, radius, clear_color, qh)?;
+                },
+                DismissedPopup::Overflow(p, section) => {
+                    let space = match section {
+                        OverflowSection::Center => &self.overflow_center,
+                        OverflowSection::Left => &self.overflow_left,
+                        OverflowSection::Right => &self.overflow_right,
+                    };
+                    render_overflow_popup(renderer, p, space, scale, clear_color, qh)?;
+                },
+            }
         }
         if self.overflow_popup.is_some() {
             self.update_hidden_applet_frame();
         }
 
+        // render to the context menu
+        let scale = self.scale;
+        if let Some(menu) = self.context_menu.as_mut() {
+            render_iced_popup(renderer, &mut menu.popup, &menu.element, scale, clear_color, qh)?;
+        }
+
         renderer.unbind()?;
 
         Ok(())
     }
+
+    /// The render elements of the applets and overflow buttons, at `scale` and
+    /// moved by `offset`.
+    pub(super) fn applet_elements(
+        &self,
+        renderer: &mut GlesRenderer,
+        scale: f64,
+        offset: Point<i32, Physical>,
+    ) -> Vec<PanelRenderElement> {
+        self.space
+            .elements()
+            .filter_map(|w| {
+                let loc = self
+                    .space
+                    .element_location(w)
+                    .unwrap_or_default()
+                    .to_f64()
+                    .to_physical(scale)
+                    .to_i32_round()
+                    + offset;
+
+                let iced = match w {
+                    CosmicMappedInternal::OverflowButton(b) => Some(b.render_elements(
+                        renderer,
+                        loc,
+                        smithay::utils::Scale::from(scale),
+                        1.0,
+                    )),
+                    CosmicMappedInternal::Builtin(b) => Some(b.render_elements(
+                        renderer,
+                        loc,
+                        smithay::utils::Scale::from(scale),
+                        1.0,
+                    )),
+                    _ => None,
+                };
+                if let Some(elements) = iced {
+                    return Some(
+                        elements.into_iter().map(PanelRenderElement::Iced).collect::<Vec<_>>(),
+                    );
+                }
+                w.toplevel().map(|t| {
+                    let configured_size = t.current_state().size.map(|s| {
+                        let mut r = Rectangle::from_loc_and_size(
+                            self.space
+                                .element_location(w)
+                                .unwrap_or_default()
+                                .to_f64()
+                                .to_physical_precise_round(scale),
+                            s.to_f64().to_physical_precise_round(scale),
+                        );
+                        if r.size.w == 0 {
+                            r.size.w = i32::MAX;
+                        }
+                        if r.size.h == 0 {
+                            r.size.h = i32::MAX;
+                        }
+                        r
+                    });
+
+                    applet_surface_elements(
+                        renderer,
+                        t.wl_surface(),
+                        loc,
+                        configured_size,
+                        scale,
+                        1.0,
+                    )
+                })
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+/// An applet surface, rescaled to the aspect ratio of its buffer and centered
+/// in its slot.
+pub(crate) type LetterboxRenderElement =
+    RelocateRenderElement<RescaleRenderElement<WaylandSurfaceRenderElement<GlesRenderer>>>;
+
+/// Render elements of the applet `surface` at `loc`, cropped to its `slot` if
+/// it has a configured size.
+///
+/// While the panel changes size, applets may commit buffers which don't match
+/// their slot yet, or a viewport which doesn't match their buffer. These are
+/// letterboxed, centered in the slot at the aspect ratio of the buffer, instead
+/// of being stretched or cropped from the top left.
+fn applet_surface_elements(
+    renderer: &mut GlesRenderer,
+    surface: &WlSurface,
+    loc: Point<i32, Physical>,
+    slot: Option<Rectangle<i32, Physical>>,
+    scale: f64,
+    alpha: f32,
+) -> Vec<PanelRenderElement> {
+    let elements: Vec<WaylandSurfaceRenderElement<GlesRenderer>> =
+        render_elements_from_surface_tree(renderer, surface, loc, scale, alpha, Kind::Unspecified);
+    let Some(slot) = slot else {
+        return elements.into_iter().map(PanelRenderElement::Wayland).collect();
+    };
+
+    let sizes =
+        with_renderer_surface_state(surface, |state| state.buffer_size().zip(state.surface_size()))
+            .flatten();
+    let (rescale, drawn) = match sizes {
+        Some((buffer, view)) if buffer.w > 0 && buffer.h > 0 && view.w > 0 && view.h > 0 => {
+            let view = view.to_f64().to_physical(scale);
+            let buffer_ratio = buffer.w as f64 / buffer.h as f64;
+            let view_ratio = view.w / view.h;
+            if (buffer_ratio / view_ratio - 1.).abs() < 0.01 {
+                (Scale::from(1.), view)
+            } else if view_ratio > buffer_ratio {
+                let w = view.h * buffer_ratio;
+                (Scale::from((w / view.w, 1.)), Size::from((w, view.h)))
+            } else {
+                let h = view.w / buffer_ratio;
+                (Scale::from((1., h / view.h)), Size::from((view.w, h)))
+            }
+        },
+        _ => (Scale::from(1.), slot.size.to_f64()),
+    };
+    // center along the axes with a bounded slot
+    let center = |slot: i32, drawn: f64| {
+        if slot == i32::MAX {
+            0
+        } else {
+            ((slot as f64 - drawn) / 2.).round() as i32
+        }
+    };
+    let offset =
+        Point::<i32, Physical>::from((center(slot.size.w, drawn.w), center(slot.size.h, drawn.h)));
+
+    elements
+        .into_iter()
+        .filter_map(|e| {
+            let e = RescaleRenderElement::from_element(e, loc, rescale);
+            let e = RelocateRenderElement::from_element(e, offset, Relocate::Relative);
+            CropRenderElement::from_element(e, scale, slot).map(PanelRenderElement::Crop)
+        })
+        .collect()
+}
+
+/// Scale of a popup when its entrance animation starts.
+const POPUP_START_SCALE: f64 = 0.9;
+
+/// Opacity of the shadow the panel draws around decorated popups.
+const POPUP_SHADOW_ALPHA: f32 = 0.3;
+
+/// Render the applet popup `p`, if it is configured and dirty or animating.
+///
+/// While animating, the popup fades in and grows from the side facing the
+/// panel, or the reverse while it is dismissed. Popups with a decoration margin
+/// get rounded corners of `radius` and a shadow in the margin.
+fn render_applet_popup(
+    renderer: &mut GlesRenderer,
+    p: &mut WrapperPopup,
+    anchor: PanelAnchor,
+    radius: u32,
+    clear_color: [f32; 4],
+    qh: &QueueHandle<GlobalState>,
+) -> anyhow::Result<()> {
+    // the popup may be on an output with another scale than the panel
+    let scale = p.popup.scale;
+    let animating = p.popup.animation.is_running();
+    let Some(egl_surface) = p.popup.egl_surface.clone().filter(|_| {
+        (p.popup.dirty || animating)
+            && p.popup.state.is_none()
+            && p.s_surface.alive()
+            && p.popup.c_popup.wl_surface().is_alive()
+            && p.popup.has_frame
+    }) else {
+        return Ok(());
+    };
+    renderer.unbind()?;
+    renderer.bind(egl_surface.clone())?;
+
+    let progress = p.popup.animation.progress();
+    let size = p.popup.rectangle.size.to_f64().to_physical(scale).to_i32_round();
+    let margin = Point::<i32, Logical>::from((p.decoration_margin, p.decoration_margin));
+    let elements: Vec<WaylandSurfaceRenderElement<_>> = render_elements_from_surface_tree(
+        renderer,
+        p.s_surface.wl_surface(),
+        margin.to_f64().to_physical(scale).to_i32_round(),
+        scale,
+        progress,
+        Kind::Unspecified,
+    );
+    let origin = Point::<i32, Physical>::from(match anchor {
+        PanelAnchor::Top => (size.w / 2, 0),
+        PanelAnchor::Bottom => (size.w / 2, size.h),
+        PanelAnchor::Left => (0, size.h / 2),
+        PanelAnchor::Right => (size.w, size.h / 2),
+    });
+    let popup_scale = POPUP_START_SCALE + (1. - POPUP_START_SCALE) * progress as f64;
+    let mut elements: Vec<PanelRenderElement> = if animating {
+        elements
+            .into_iter()
+            .filter_map(|e| {
+                let e = RescaleRenderElement::from_element(e, origin, popup_scale);
+                let e = RelocateRenderElement::from_element(e, (0, 0), Relocate::Relative);
+                CropRenderElement::from_element(
+                    e,
+                    scale,
+                    Rectangle::from_loc_and_size((0, 0), size),
+                )
+                .map(PanelRenderElement::Crop)
+            })
+            .collect()
+    } else {
+        elements.into_iter().map(PanelRenderElement::Wayland).collect()
+    };
+
+    if p.decoration_margin > 0 {
+        // the window geometry of the applet, scaled like its content
+        let geo = PopupKind::Xdg(p.s_surface.clone()).geometry();
+        let origin = origin.to_f64();
+        let loc = (geo.loc + margin).to_f64().to_physical(scale);
+        let loc = origin + (loc - origin).upscale(popup_scale);
+        let geo_size = geo.size.to_f64().to_physical(scale).upscale(popup_scale);
+        let radius = radius as f32 * (scale * popup_scale) as f32;
+        let mask = RoundedRectangleSettings {
+            rad_tl: radius,
+            rad_tr: radius,
+            rad_bl: radius,
+            rad_br: radius,
+            // the shader has the origin at the bottom left
+            loc: [loc.x as f32, (size.h as f64 - loc.y - geo_size.h) as f32],
+            rect_size: [geo_size.w as f32, geo_size.h as f32],
+            samples: 1.0,
+            smoothing: 1.0,
+            ..Default::default()
+        };
+        let shadow = RoundedRectangleSettings {
+            drop_shadow: (p.decoration_margin as f64 * scale) as f32,
+            bg_color: [0.0, 0.0, 0.0, POPUP_SHADOW_ALPHA * progress],
+            ..mask
+        };
+        let area = Rectangle::from_loc_and_size((0, 0), size.to_logical(1));
+        // the content is masked before the shadow is drawn behind it
+        for settings in [mask, shadow] {
+            elements.insert(
+                0,
+                PanelRenderElement::RoundedRectangle(RoundedRectangleShader::element(
+                    renderer, area, settings,
+                )),
+            );
+        }
+    }
+
+    // the whole popup changes while animating
+    let age = if animating { 0 } else { egl_surface.buffer_age().unwrap_or_default() as usize };
+    p.popup.damage_tracked_renderer.render_output(renderer, age, &elements, clear_color)?;
+
+    egl_surface.swap_buffers(None)?;
+
+    let wl_surface = p.popup.c_popup.wl_surface().clone();
+    wl_surface.frame(qh, wl_surface.clone());
+    wl_surface.commit();
+    p.popup.dirty = false;
+    p.popup.has_frame = false;
+    Ok(())
+}
+
+/// Render the overflow popup `p` with the applets of `space`, if it is
+/// configured and dirty or animating.
+fn render_overflow_popup(
+    renderer: &mut GlesRenderer,
+    p: &mut PanelPopup,
+    space: &Space<PopupMappedInternal>,
+    scale: f64,
+    clear_color: [f32; 4],
+    qh: &QueueHandle<GlobalState>,
+) -> anyhow::Result<()> {
+    let animating = p.animation.is_running();
+    let Some(egl_surface) = p.egl_surface.clone().filter(|_| {
+        (p.dirty || animating) && p.state.is_none() && p.c_popup.wl_surface().is_alive()
+    }) else {
+        return Ok(());
+    };
+    renderer.unbind()?;
+    renderer.bind(egl_surface.clone())?;
+    let alpha = p.animation.progress();
+    let mut bg_render_element = None;
+    let mut elements: Vec<PanelRenderElement> = space
+        .elements()
+        .filter_map(|e| match e {
+            crate::iced::elements::PopupMappedInternal::Popup(e) => {
+                // move to bg_render_element
+                bg_render_element = Some(
+                    e.render_elements(renderer, (0, 0).into(), scale.into(), alpha)
+                        .into_iter()
+                        .map(PanelRenderElement::Iced)
+                        .collect::<Vec<_>>(),
+                );
+                None
+            },
+            crate::iced::elements::PopupMappedInternal::Window(w) => {
+                let Some(t) = w.toplevel() else {
+                    return None;
+                };
+
+                let loc = space
+                    .element_location(&PopupMappedInternal::Window(w.clone()))
+                    .unwrap_or_default()
+                    .to_f64()
+                    .to_physical(scale)
+                    .to_i32_round();
+
+                let configured_size = t.current_state().size.map(|s| {
+                    let mut r = Rectangle::from_loc_and_size(
+                        loc,
+                        s.to_f64().to_physical_precise_round(scale),
+                    );
+                    if r.size.w == 0 {
+                        r.size.w = i32::MAX;
+                    }
+                    if r.size.h == 0 {
+                        r.size.h = i32::MAX;
+                    }
+                    r
+                });
+                Some(applet_surface_elements(
+                    renderer,
+                    t.wl_surface(),
+                    loc,
+                    configured_size,
+                    scale,
+                    alpha,
+                ))
+            },
+            crate::iced::elements::PopupMappedInternal::_GenericCatcher(_) => None,
+        })
+        .flatten()
+        .collect();
+
+    elements.extend(bg_render_element.unwrap_or_default());
+
+    let age = if animating { 0 } else { egl_surface.buffer_age().unwrap_or_default() as usize };
+    _ = p.damage_tracked_renderer.render_output(renderer, age, &elements, clear_color);
+    egl_surface.swap_buffers(None)?;
+    let wl_surface = p.c_popup.wl_surface();
+    wl_surface.frame(qh, wl_surface.clone());
+    wl_surface.commit();
+    Ok(())
+}
+
+/// Render the iced `element` filling `popup`, if the popup is configured and
+/// dirty.
+fn render_iced_popup<P: Program + Send + 'static>(
+    renderer: &mut GlesRenderer,
+    popup: &mut PanelPopup,
+    element: &IcedElement<P>,
+    scale: f64,
+    clear_color: [f32; 4],
+    qh: &QueueHandle<GlobalState>,
+) -> anyhow::Result<()> {
+    let Some(egl_surface) = popup
+        .egl_surface
+        .as_ref()
+        .filter(|_| popup.dirty && popup.state.is_none() && popup.c_popup.wl_surface().is_alive())
+    else {
+        return Ok(());
+    };
+    renderer.unbind()?;
+    renderer.bind(egl_surface.clone())?;
+    let elements: Vec<PanelRenderElement> = element
+        .render_elements(renderer, (0, 0).into(), scale.into(), 1.0)
+        .into_iter()
+        .map(PanelRenderElement::Iced)
+        .collect();
+    _ = popup.damage_tracked_renderer.render_output(
+        renderer,
+        egl_surface.buffer_age().unwrap_or_default() as usize,
+        &elements,
+        clear_color,
+    );
+    egl_surface.swap_buffers(None)?;
+    let wl_surface = popup.c_popup.wl_surface();
+    wl_surface.frame(qh, wl_surface.clone());
+    wl_surface.commit();
+    popup.dirty = false;
+    Ok(())
 }
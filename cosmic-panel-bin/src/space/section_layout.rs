@@ -0,0 +1,211 @@
+//! The parts of the panel layout which don't depend on Wayland state: how the
+//! length of the panel is split between its sections, how much each
+//! shrinkable applet gives up when a section overflows, and where the applets
+//! of a section go around pinned applets.
+
+/// Target lengths of the left, center and right sections in logical pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SectionTargets {
+    pub left: f64,
+    pub center: f64,
+    pub right: f64,
+}
+
+/// Summed lengths of the applets in each section in logical pixels, including
+/// the spacing between them and the overflow button if there is one.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SectionSums {
+    pub left: f64,
+    pub center: f64,
+    pub right: f64,
+}
+
+/// Split `layer_major` between the sections.
+///
+/// The center section may take at least a third of the panel, and grows into
+/// the space the larger side doesn't need. Each side gets at least a third if
+/// there is a center section, or half otherwise.
+pub fn section_targets(
+    layer_major: i32,
+    sums: SectionSums,
+    spacing: u32,
+    padding: u32,
+    num_lists: u32,
+    has_center: bool,
+) -> SectionTargets {
+    let layer_major = layer_major as f64;
+    let spacing = spacing as f64;
+    let padding = padding as f64;
+    let one_third =
+        (layer_major - spacing * num_lists.saturating_sub(1) as f64) / (3.min(num_lists) as f64);
+    let one_half = layer_major / (2.min(num_lists) as f64);
+    let larger_side = sums.left.max(sums.right);
+
+    let mut center = (layer_major - larger_side * (2.)).max(one_third).min(layer_major);
+    if num_lists == 1 {
+        center -= padding * 2.;
+    } else {
+        center -= spacing;
+    }
+    let side = |other_side: f64| {
+        if !has_center {
+            (layer_major - other_side.min(one_half) - spacing / 2. - padding).max(one_half)
+        } else {
+            (one_half - center.min(sums.center) / 2. - spacing / 2. - padding).max(one_third)
+        }
+        .min(layer_major)
+    };
+
+    SectionTargets { left: side(sums.right), center, right: side(sums.left) }
+}
+
+/// A shrinkable applet, measured along the panel.
+#[derive(Debug, Clone, Copy)]
+pub struct ShrinkCandidate {
+    /// current length of the applet
+    pub length: f64,
+    /// length the applet was last constrained to, or 0
+    pub suggested: i32,
+    /// applets with a negative priority are only shrunk when forced
+    pub priority: i32,
+    /// smallest length the applet may be shrunk to, unless forced
+    pub min_length: u32,
+}
+
+/// Decide which applets to shrink, in order, to absorb `overflow` pixels.
+///
+/// Returns the index and new length of each applet that has to be
+/// reconfigured, and the overflow that could not be absorbed. With
+/// `force_smaller`, minimum lengths and priorities are ignored.
+pub fn plan_shrink(
+    mut overflow: u32,
+    candidates: &[ShrinkCandidate],
+    force_smaller: bool,
+) -> (Vec<(usize, u32)>, u32) {
+    let mut planned = Vec::new();
+    for (i, c) in candidates.iter().enumerate() {
+        if overflow == 0 {
+            break;
+        }
+        if (c.length < c.min_length as f64 || c.priority < 0) && !force_smaller {
+            continue;
+        }
+        let new_length = (c.length as u32).saturating_sub(overflow);
+        let new_length =
+            if force_smaller || new_length >= c.min_length { new_length } else { c.min_length }
+                .max(1);
+        let diff = (c.length as u32).saturating_sub(new_length);
+        if diff == 0 && c.suggested as u32 == new_length {
+            continue;
+        }
+        planned.push((i, new_length));
+        overflow = overflow.saturating_sub(diff);
+    }
+    (planned, overflow)
+}
+
+/// An applet measured along the panel.
+#[derive(Debug, Clone, Copy)]
+pub struct Slot {
+    pub length: f64,
+    /// start position of a pinned applet
+    pub pinned: Option<f64>,
+}
+
+/// Start positions of `slots`, placed one after another from `start` with
+/// `spacing` between them.
+///
+/// Pinned slots are placed at their position. Any other slot which would
+/// overlap one of the `pinned` spans, of this or another section, is pushed
+/// after it along with the slots following it. The spans are sorted by their
+/// start.
+pub fn place_slots(start: f64, spacing: f64, slots: &[Slot], pinned: &[(f64, f64)]) -> Vec<f64> {
+    let mut cursor = start;
+    slots
+        .iter()
+        .map(|s| {
+            if let Some(p) = s.pinned {
+                return p;
+            }
+            let mut pos = cursor;
+            for &(from, to) in pinned {
+                if pos < to && pos + s.length > from {
+                    pos = to + spacing;
+                }
+            }
+            cursor = pos + s.length + spacing;
+            pos
+        })
+        .collect()
+}
+
+/// How much longer the slots of a section get when [`place_slots`] pushes them
+/// past the `pinned` spans.
+pub fn pushed_length(start: f64, spacing: f64, slots: &[Slot], pinned: &[(f64, f64)]) -> f64 {
+    let mut unpushed = start;
+    let mut pushed = 0.;
+    for (s, pos) in slots.iter().zip(place_slots(start, spacing, slots, pinned)) {
+        if s.pinned.is_none() {
+            pushed = pos - unpushed;
+            unpushed += s.length + spacing;
+        }
+    }
+    pushed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(length: f64) -> Slot {
+        Slot { length, pinned: None }
+    }
+
+    #[test]
+    fn slots_are_placed_one_after_another() {
+        let slots = [slot(10.), slot(20.), slot(30.)];
+        assert_eq!(place_slots(5., 4., &slots, &[]), vec![5., 19., 43.]);
+        assert_eq!(pushed_length(5., 4., &slots, &[]), 0.);
+    }
+
+    #[test]
+    fn pinned_slots_keep_their_position() {
+        let slots = [slot(10.), Slot { length: 20., pinned: Some(100.) }, slot(30.)];
+        assert_eq!(place_slots(0., 4., &slots, &[(100., 120.)]), vec![0., 100., 14.]);
+        assert_eq!(pushed_length(0., 4., &slots, &[(100., 120.)]), 0.);
+    }
+
+    #[test]
+    fn slots_are_pushed_past_pinned_spans() {
+        // the second slot would overlap the span of a pinned applet of another
+        // section, and is pushed after it with the slot following it
+        let slots = [slot(10.), slot(20.), slot(30.)];
+        let pinned = [(20., 40.)];
+        assert_eq!(place_slots(0., 4., &slots, &pinned), vec![0., 44., 68.]);
+        assert_eq!(pushed_length(0., 4., &slots, &pinned), 30.);
+    }
+
+    #[test]
+    fn slots_are_pushed_past_every_span_in_their_way() {
+        let slots = [slot(10.), slot(10.)];
+        let pinned = [(5., 15.), (17., 25.)];
+        assert_eq!(place_slots(0., 2., &slots, &pinned), vec![27., 39.]);
+        assert_eq!(pushed_length(0., 2., &slots, &pinned), 27.);
+    }
+
+    #[test]
+    fn pushed_sections_overflow() {
+        // the left section fits, until it is pushed past an applet pinned in
+        // the right section
+        let slots = [slot(100.), slot(100.)];
+        let sums = SectionSums { left: 204., center: 0., right: 300. };
+        let targets = section_targets(600, sums, 4, 0, 2, false);
+        assert!(sums.left <= targets.left);
+
+        let pushed = pushed_length(0., 4., &slots, &[(50., 150.)]);
+        assert_eq!(pushed, 154.);
+        let sums = SectionSums { left: sums.left + pushed, ..sums };
+        let targets = section_targets(600, sums, 4, 0, 2, false);
+        assert!(sums.left > targets.left);
+    }
+}
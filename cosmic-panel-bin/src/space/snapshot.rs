@@ -0,0 +1,272 @@
+//! Offscreen snapshots of a panel, for settings previews, documentation and
+//! bug reports.
+//!
+//! The panel is rendered like a frame of its surface, but into a renderbuffer
+//! of the requested size and scale, and the result is encoded as a PNG.
+
+use std::io::Write;
+
+use anyhow::{anyhow, bail, Result};
+use cosmic::iced::id::Id;
+use cosmic_panel_config::PanelAnchor;
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            damage::OutputDamageTracker,
+            element::AsRenderElements,
+            gles::{GlesRenderbuffer, GlesRenderer},
+            Bind, ExportMem, Offscreen, Unbind,
+        },
+    },
+    desktop::space::SpaceElement,
+    output::{Output, PhysicalProperties, Scale, Subpixel},
+    utils::{Logical, Physical, Point, Rectangle, Size, Transform},
+};
+
+use crate::iced::elements::{background::background_element, CosmicMappedInternal};
+
+use super::{
+    corner_element::RoundedRectangleShader, render::PanelRenderElement, PanelColors, PanelSpace,
+};
+
+/// Longest side of a snapshot in physical pixels.
+const MAX_SNAPSHOT_SIZE: i32 = 8192;
+
+/// An output which is never advertised, entered by the iced elements of the
+/// panel so they are drawn at the scale of the snapshot.
+fn snapshot_output(scale: f64) -> Output {
+    let output = Output::new(
+        "snapshot".to_string(),
+        PhysicalProperties {
+            size: (0, 0).into(),
+            subpixel: Subpixel::Unknown,
+            make: String::new(),
+            model: String::new(),
+        },
+    );
+    output.change_current_state(None, None, Some(Scale::Fractional(scale)), None);
+    output
+}
+
+impl PanelSpace {
+    /// Render the panel into a PNG of `size`, or of the size of the panel if
+    /// it is empty. The panel is scaled to fit and centered. `scale` defaults
+    /// to the scale of the panel if it is 0, and `colors` replace the colors
+    /// of the panel background. Both sides are clamped to
+    /// [`MAX_SNAPSHOT_SIZE`].
+    pub(crate) fn snapshot(
+        &self,
+        renderer: &mut GlesRenderer,
+        size: Size<i32, Physical>,
+        scale: f64,
+        colors: Option<PanelColors>,
+    ) -> Result<Vec<u8>> {
+        if self.dimensions.w <= 0 || self.dimensions.h <= 0 {
+            bail!("Panel {} is not configured yet", self.config.name);
+        }
+        let (size, scale, offset) = fit_snapshot(self.dimensions, self.scale, size, scale)?;
+
+        let anim_gap = self.anchor_gap as f64 * scale;
+        let translation = (offset
+            + Point::from(match self.config.anchor {
+                PanelAnchor::Left => (anim_gap, 0.),
+                PanelAnchor::Right => (-anim_gap, 0.),
+                PanelAnchor::Top => (0., anim_gap),
+                PanelAnchor::Bottom => (0., -anim_gap),
+            }))
+        .to_i32_round();
+
+        let output = snapshot_output(scale);
+        let background = self.background_element.as_ref().map(|bg| match colors.as_ref() {
+            Some(colors) => bg.with_program(|p| {
+                background_element(
+                    Id::unique(),
+                    p.logical_width,
+                    p.logical_height,
+                    p.radius,
+                    self.loop_handle.clone(),
+                    colors.theme.clone(),
+                    self.space.id(),
+                    [p.logical_pos.0 as f32, p.logical_pos.1 as f32],
+                    colors.bg_color(self.config.opacity),
+                )
+            }),
+            None => bg.clone(),
+        });
+        let iced_elements = || {
+            self.space
+                .elements()
+                .filter(|e| {
+                    matches!(
+                        e,
+                        CosmicMappedInternal::OverflowButton(_) | CosmicMappedInternal::Builtin(_)
+                    )
+                })
+                .cloned()
+                .chain(background.clone().map(CosmicMappedInternal::Background))
+        };
+        for e in iced_elements() {
+            e.output_enter(&output, Rectangle::default());
+        }
+
+        let mut elements: Vec<PanelRenderElement> =
+            self.applet_elements(renderer, scale, translation);
+        if let Some(bg) = background.as_ref() {
+            let pos = bg.with_program(|p| p.logical_pos);
+            elements.extend(
+                bg.render_elements(
+                    renderer,
+                    Point::from(((pos.0 as f64 * scale) as i32, (pos.1 as f64 * scale) as i32))
+                        + translation,
+                    scale.into(),
+                    1.0,
+                )
+                .into_iter()
+                .map(PanelRenderElement::Iced),
+            );
+        }
+        if self.config.anchor_gap || self.anchor_gap != 0 || self.config.border_radius > 0 {
+            let ratio = (scale / self.scale) as f32;
+            let mut settings = self.panel_rect_settings;
+            settings.rad_tl *= ratio;
+            settings.rad_tr *= ratio;
+            settings.rad_bl *= ratio;
+            settings.rad_br *= ratio;
+            settings.loc = [
+                settings.loc[0] * ratio + offset.x as f32,
+                settings.loc[1] * ratio + offset.y as f32,
+            ];
+            settings.rect_size = [settings.rect_size[0] * ratio, settings.rect_size[1] * ratio];
+            elements.insert(
+                0,
+                PanelRenderElement::RoundedRectangle(RoundedRectangleShader::element(
+                    renderer,
+                    Rectangle::from_loc_and_size((0, 0), size.to_logical(1)),
+                    settings,
+                )),
+            );
+        }
+
+        let pixels = render_offscreen(renderer, size, &elements);
+        for e in iced_elements() {
+            e.output_leave(&output);
+        }
+        let pixels = pixels?;
+
+        let mut png = Vec::new();
+        encode_png(&mut png, size, &pixels)?;
+        Ok(png)
+    }
+}
+
+/// Encode RGBA8 `pixels` of `size` as a PNG.
+fn encode_png(writer: impl Write, size: Size<i32, Physical>, pixels: &[u8]) -> Result<()> {
+    let mut encoder = png::Encoder::new(writer, size.w as u32, size.h as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)?;
+    Ok(())
+}
+
+/// The size, scale and offset of the panel in a snapshot of a panel of
+/// `dimensions`, requested with `size` and `scale` as in
+/// [`PanelSpace::snapshot`].
+fn fit_snapshot(
+    dimensions: Size<i32, Logical>,
+    panel_scale: f64,
+    size: Size<i32, Physical>,
+    scale: f64,
+) -> Result<(Size<i32, Physical>, f64, Point<f64, Physical>)> {
+    if !scale.is_finite() || scale < 0. {
+        bail!("Invalid scale {scale}");
+    }
+    if size.w < 0 || size.h < 0 {
+        bail!("Invalid size {}x{}", size.w, size.h);
+    }
+    let scale = if scale > 0. { scale } else { panel_scale };
+    let panel_size = dimensions.to_f64().to_physical(scale);
+    let length = |requested: i32, panel: f64| {
+        let length = if requested > 0 { requested as f64 } else { panel.round() };
+        length.clamp(1., MAX_SNAPSHOT_SIZE as f64) as i32
+    };
+    let size =
+        Size::<i32, Physical>::from((length(size.w, panel_size.w), length(size.h, panel_size.h)));
+    let fit = (size.w as f64 / panel_size.w).min(size.h as f64 / panel_size.h);
+    let offset = Point::<f64, Physical>::from((
+        (size.w as f64 - panel_size.w * fit) / 2.,
+        (size.h as f64 - panel_size.h * fit) / 2.,
+    ));
+    Ok((size, scale * fit, offset))
+}
+
+fn render_offscreen(
+    renderer: &mut GlesRenderer,
+    size: Size<i32, Physical>,
+    elements: &[PanelRenderElement],
+) -> Result<Vec<u8>> {
+    let buffer: GlesRenderbuffer = renderer
+        .create_buffer(Fourcc::Abgr8888, size.to_logical(1).to_buffer(1, Transform::Normal))?;
+    renderer.unbind()?;
+    renderer.bind(buffer)?;
+
+    let mut damage_tracker = OutputDamageTracker::new(size, 1.0, Transform::Normal);
+    let res = damage_tracker
+        .render_output(renderer, 0, elements, [0., 0., 0., 0.])
+        .map_err(|err| anyhow!("Failed to render snapshot: {err:?}"))?;
+    res.sync.wait()?;
+
+    let region =
+        Rectangle::from_loc_and_size((0, 0), size.to_logical(1).to_buffer(1, Transform::Normal));
+    let mapping = renderer.copy_framebuffer(region, Fourcc::Abgr8888)?;
+    let pixels = renderer.map_texture(&mapping)?.to_vec();
+    renderer.unbind()?;
+    Ok(pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PANEL: (i32, i32) = (400, 40);
+
+    fn fit(
+        size: (i32, i32),
+        scale: f64,
+    ) -> Result<(Size<i32, Physical>, f64, Point<f64, Physical>)> {
+        fit_snapshot(PANEL.into(), 1.5, size.into(), scale)
+    }
+
+    #[test]
+    fn empty_size_and_scale_use_the_panel() {
+        let (size, scale, offset) = fit((0, 0), 0.).unwrap();
+        assert_eq!(size, (600, 60).into());
+        assert_eq!(scale, 1.5);
+        assert_eq!(offset, (0., 0.).into());
+    }
+
+    #[test]
+    fn panel_is_scaled_to_fit_and_centered() {
+        let (size, scale, offset) = fit((200, 200), 1.).unwrap();
+        assert_eq!(size, (200, 200).into());
+        assert_eq!(scale, 0.5);
+        assert_eq!(offset, (0., 90.).into());
+    }
+
+    #[test]
+    fn size_is_clamped() {
+        let (size, _, _) = fit((100_000, 1), 1.).unwrap();
+        assert_eq!(size, (MAX_SNAPSHOT_SIZE, 1).into());
+        let (size, _, _) = fit((0, 0), 100.).unwrap();
+        assert_eq!(size, (MAX_SNAPSHOT_SIZE, 4000).into());
+    }
+
+    #[test]
+    fn invalid_size_and_scale_are_rejected() {
+        assert!(fit((-1, 40), 1.).is_err());
+        assert!(fit((400, -40), 1.).is_err());
+        assert!(fit((0, 0), -1.).is_err());
+        assert!(fit((0, 0), f64::NAN).is_err());
+        assert!(fit((0, 0), f64::INFINITY).is_err());
+    }
+}
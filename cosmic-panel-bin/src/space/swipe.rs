@@ -0,0 +1,76 @@
+//! Switching workspaces with a horizontal swipe over the panel.
+//!
+//! While a horizontal two-finger scroll or a touchpad swipe is on the panel,
+//! a glow grows from the end of the panel in the direction of the workspace it
+//! switches to. Lifting the fingers after a complete swipe switches to that
+//! workspace, and the glow fades out either way. The glow is drawn by the
+//! rounded rectangle shader, like the effects.
+
+use std::time::{Duration, Instant};
+
+use super::PanelSpace;
+
+/// Horizontal distance of a complete swipe, in logical pixels.
+const SWIPE_DISTANCE: f64 = 240.;
+/// How long the glow takes to fade out after a swipe.
+const SWIPE_FADE: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Default)]
+pub struct SwipeState {
+    /// progress of the swipe on the panel, from -1 to 1 with the sign of the
+    /// direction of the workspace it switches to
+    progress: Option<f64>,
+    /// progress when the swipe ended, and when
+    released: Option<(f32, Instant)>,
+}
+
+impl PanelSpace {
+    /// Whether a swipe is on the panel, or its glow is fading out.
+    pub(crate) fn swipe_active(&self) -> bool {
+        self.swipe.progress.is_some() || self.swipe.released.is_some()
+    }
+
+    pub(crate) fn swiping(&self) -> bool {
+        self.swipe.progress.is_some()
+    }
+
+    pub(crate) fn swipe_begin(&mut self) {
+        self.swipe = SwipeState { progress: Some(0.), released: None };
+        self.is_dirty = true;
+    }
+
+    /// Move the swipe by `dx`. Swiping to the left goes to the next workspace.
+    pub(crate) fn swipe_update(&mut self, dx: f64) {
+        if let Some(progress) = self.swipe.progress.as_mut() {
+            *progress = (*progress - dx / SWIPE_DISTANCE).clamp(-1., 1.);
+            self.is_dirty = true;
+        }
+    }
+
+    /// End the swipe. Returns whether it switches to the next workspace, or
+    /// `None` if it was cancelled or incomplete.
+    pub(crate) fn swipe_end(&mut self, cancelled: bool) -> Option<bool> {
+        let progress = self.swipe.progress.take()?;
+        self.swipe.released = Some((progress as f32, Instant::now()));
+        self.is_dirty = true;
+        (!cancelled && progress.abs() >= 1.).then_some(progress > 0.)
+    }
+
+    /// The glow of the swipe for the shader, from -1 to 1.
+    pub(crate) fn swipe_glow(&mut self) -> f32 {
+        if let Some(progress) = self.swipe.progress {
+            return progress as f32;
+        }
+        let Some((progress, released)) = self.swipe.released else {
+            return 0.;
+        };
+        let fade = released.elapsed().as_secs_f32() / SWIPE_FADE.as_secs_f32();
+        if fade >= 1. {
+            // draw the panel once more without the glow
+            self.swipe.released = None;
+            self.is_dirty = true;
+            return 0.;
+        }
+        progress * (1. - fade)
+    }
+}
@@ -0,0 +1,211 @@
+//! Activation of applets by their position, for the Super+1..9 shortcuts.
+//!
+//! While a panel has `activation_shortcuts` enabled, the shortcuts are added
+//! to the custom shortcuts of cosmic-settings-daemon, and run
+//! `cosmic-panel-ctl activate-applet N`. That clicks the center of the Nth
+//! applet of the panel with the server pointer of the applets, leaving the
+//! hover of the panel as it is. The panel on the output of the active window
+//! is preferred if several have it enabled.
+
+use std::{fs, io, path::Path};
+
+use cctk::cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_handle_v1;
+use sctk::seat::pointer::BTN_LEFT;
+use smithay::{
+    backend::input::ButtonState,
+    input::pointer::{ButtonEvent, MotionEvent},
+    reexports::wayland_server::Resource,
+    utils::SERIAL_COUNTER,
+};
+use tracing::{error, warn};
+
+use crate::{
+    iced::elements::{target::SpaceTarget, CosmicMappedInternal},
+    xdg_shell_wrapper::shared_state::GlobalState,
+    PanelMode,
+};
+
+use super::SpaceContainer;
+
+/// The custom shortcuts of cosmic-settings-daemon, relative to the cosmic
+/// config directory.
+const CUSTOM_SHORTCUTS: &str = "com.system76.CosmicSettings.Shortcuts/v1/custom";
+const ACTIVATE_COMMAND: &str = "cosmic-panel-ctl activate-applet";
+
+fn shortcut_action(n: u32) -> String {
+    format!("Spawn(\"{ACTIVATE_COMMAND} {n}\")")
+}
+
+/// Start of the binding before the action of a shortcut, which may have been
+/// reformatted over several lines by cosmic-settings.
+fn binding_start(before: &str) -> Option<usize> {
+    let binding = before.trim_end().strip_suffix(':')?.trim_end();
+    let mut depth = 0;
+    for (i, c) in binding.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' if depth == 1 => return Some(i),
+            '(' => depth -= 1,
+            _ if depth == 0 && !c.is_whitespace() => return None,
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Add or remove the Super+1..9 shortcuts in `custom`, keeping the other
+/// shortcuts. A binding of the user for the same keys comes later in the map,
+/// and wins.
+fn update_shortcuts(custom: &str, enabled: bool) -> Option<String> {
+    let mut custom = if custom.trim().is_empty() { "{\n}".to_string() } else { custom.to_string() };
+    for n in (1..=9).rev() {
+        let action = shortcut_action(n);
+        match (enabled, custom.find(&action)) {
+            (true, None) => {
+                let open = custom.find('{')? + 1;
+                let shortcut = format!("\n    (modifiers: [Super], key: \"{n}\"): {action},");
+                custom.insert_str(open, &shortcut);
+            },
+            (false, Some(i)) => {
+                let start = custom[..binding_start(&custom[..i])?].trim_end().len();
+                let mut end = i + action.len();
+                if custom[end..].trim_start().starts_with(',') {
+                    end = custom.len() - custom[end..].trim_start()[1..].len();
+                }
+                custom.replace_range(start..end, "");
+            },
+            _ => {},
+        }
+    }
+    Some(custom)
+}
+
+fn write_shortcuts(path: &Path, enabled: bool) -> io::Result<()> {
+    let custom = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    let Some(updated) = update_shortcuts(&custom, enabled) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a map of shortcuts"));
+    };
+    if updated == custom {
+        return Ok(());
+    }
+    // replace the file at once, so the daemon doesn't read a partial config
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, updated).and_then(|_| fs::rename(&tmp, path))
+}
+
+impl SpaceContainer {
+    /// Index of the panel whose applets the activation shortcuts activate.
+    fn activation_panel(&self) -> Option<usize> {
+        let active = zcosmic_toplevel_handle_v1::State::Activated;
+        let active_outputs = self
+            .toplevels
+            .iter()
+            .find(|(_, info)| info.state.contains(&active))
+            .map(|(_, info)| info.output.clone())
+            .unwrap_or_default();
+        let enabled =
+            || self.space_list.iter().enumerate().filter(|(_, s)| s.config.activation_shortcuts);
+        enabled()
+            .find(|(_, s)| s.output.as_ref().is_some_and(|(o, ..)| active_outputs.contains(o)))
+            .or_else(|| enabled().next())
+            .map(|(i, _)| i)
+    }
+
+    /// Register the activation shortcuts while a panel has them enabled, and
+    /// remove them otherwise.
+    pub(crate) fn sync_activation_shortcuts(&self) {
+        if self.mode != PanelMode::Desktop {
+            return;
+        }
+        let enabled = self.config.config_list.iter().any(|c| c.activation_shortcuts);
+        let path = match xdg::BaseDirectories::with_prefix("cosmic")
+            .map_err(io::Error::from)
+            .and_then(|dirs| dirs.place_config_file(CUSTOM_SHORTCUTS))
+        {
+            Ok(path) => path,
+            Err(err) => {
+                error!("Failed to find the custom shortcuts: {err:?}");
+                return;
+            },
+        };
+        if let Err(err) = write_shortcuts(&path, enabled) {
+            error!("Failed to update the activation shortcuts in {}: {err:?}", path.display());
+        }
+    }
+}
+
+/// Click the applet at the 1-based `index` of the activation panel. Returns
+/// whether the applet was found.
+pub(crate) fn activate_applet(state: &mut GlobalState, index: u32) -> bool {
+    let Some(space) = state.space.activation_panel().map(|i| &state.space.space_list[i]) else {
+        return false;
+    };
+    let Some((_, name, _, geo)) =
+        index.checked_sub(1).and_then(|i| space.applet_slots().into_iter().nth(i as usize))
+    else {
+        return false;
+    };
+    let applets = space.applets.snapshot();
+    let Some(surface) = space.space.elements().find_map(|e| match e {
+        CosmicMappedInternal::Window(w) => {
+            w.toplevel().map(|t| t.wl_surface().clone()).filter(|s| {
+                s.client().and_then(|c| applets.get(&c.id())).is_some_and(|a| a.name == name)
+            })
+        },
+        _ => None,
+    }) else {
+        return false;
+    };
+    // the server seats always have a pointer, even without a host pointer
+    let Some((seat_name, ptr, kbd)) = state.server_state.seats.first().and_then(|s| {
+        Some((s.name.clone(), s.server.seat.get_pointer()?, s.server.seat.get_keyboard()?))
+    }) else {
+        warn!("Can't activate applet {name} without a seat");
+        return false;
+    };
+
+    // the applet has the pointer for the click only, and the pointer goes
+    // back to the surface it was over, if it is still over one
+    let previous = state
+        .space
+        .space_list
+        .iter()
+        .flat_map(|s| s.s_hovered_surface.iter())
+        .find(|f| f.seat_name == seat_name)
+        .map(|f| (f.surface.clone(), f.s_pos));
+    let location = ptr.current_location();
+    let time = state.start_time.elapsed().as_millis() as u32;
+    let target = SpaceTarget::from(surface);
+    ptr.motion(
+        state,
+        Some((target.clone(), geo.loc.to_f64())),
+        &MotionEvent {
+            location: geo.to_f64().center(),
+            serial: SERIAL_COUNTER.next_serial(),
+            time,
+        },
+    );
+    kbd.set_focus(state, Some(target), SERIAL_COUNTER.next_serial());
+    for button_state in [ButtonState::Pressed, ButtonState::Released] {
+        ptr.button(
+            state,
+            &ButtonEvent {
+                serial: SERIAL_COUNTER.next_serial(),
+                time,
+                button: BTN_LEFT,
+                state: button_state,
+            },
+        );
+    }
+    ptr.motion(
+        state,
+        previous,
+        &MotionEvent { location, serial: SERIAL_COUNTER.next_serial(), time },
+    );
+    ptr.frame(state);
+    true
+}
@@ -0,0 +1,101 @@
+//! Appearance profiles of the panel config, which change the opacity,
+//! background or size of a panel by time of day or theme mode.
+//!
+//! Profiles are applied to the config of a panel before it reaches the panel
+//! spaces, so a change of the applying profiles is handled like a config
+//! change. Opacity, background colors and the thickness of a size change are
+//! animated, and applets get their new size with their live config, while
+//! background mode changes recreate the panel.
+
+use cosmic_panel_config::{AppearanceProfile, CosmicPanelConfig, ProfileSchedule};
+
+use crate::xdg_shell_wrapper::{shared_state::GlobalState, util::local_time};
+
+use super::SpaceContainer;
+
+/// Minutes since local midnight, or `None` if the local time is unknown.
+fn local_minutes() -> Option<u16> {
+    local_time().map(|(hour, minute)| (hour * 60 + minute) as u16)
+}
+
+fn applies(schedule: &ProfileSchedule, minutes: Option<u16>, is_dark: bool) -> bool {
+    match *schedule {
+        ProfileSchedule::Time { from, to } => {
+            let Some(minutes) = minutes else {
+                return false;
+            };
+            let from = from.0 as u16 * 60 + from.1 as u16;
+            let to = to.0 as u16 * 60 + to.1 as u16;
+            if from <= to {
+                (from..to).contains(&minutes)
+            } else {
+                minutes >= from || minutes < to
+            }
+        },
+        ProfileSchedule::Dark => is_dark,
+        ProfileSchedule::Light => !is_dark,
+    }
+}
+
+/// Indices of the profiles in `profiles` which currently apply.
+fn active_profiles(profiles: &[AppearanceProfile], is_dark: bool) -> Vec<usize> {
+    if profiles.is_empty() {
+        return Vec::new();
+    }
+    let minutes = local_minutes();
+    profiles
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| applies(&p.when, minutes, is_dark))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+impl SpaceContainer {
+    /// Remember `entry` as the configured config of its panel, and return it
+    /// with its currently applying profiles.
+    pub(crate) fn apply_appearance_profiles(
+        &mut self,
+        mut entry: CosmicPanelConfig,
+    ) -> CosmicPanelConfig {
+        let active = active_profiles(&entry.appearance_profiles, self.is_dark);
+        self.configured.insert(entry.name.clone(), (entry.clone(), active.clone()));
+        for profile in active.iter().map(|i| &entry.appearance_profiles[*i]) {
+            if let Some(opacity) = profile.opacity {
+                entry.opacity = opacity.clamp(0., 1.);
+            }
+            if let Some(background) = profile.background.clone() {
+                entry.background = background;
+            }
+            if let Some(size) = profile.size.clone() {
+                entry.size = size;
+            }
+        }
+        entry
+    }
+}
+
+/// Update the panels whose applying profiles changed, e.g. because their time
+/// range started or the theme mode was switched.
+pub(crate) fn update_appearance_profiles(state: &mut GlobalState) {
+    let is_dark = state.space.is_dark;
+    let changed: Vec<_> = state
+        .space
+        .configured
+        .values()
+        .filter(|(entry, active)| active_profiles(&entry.appearance_profiles, is_dark) != *active)
+        .map(|(entry, _)| entry.clone())
+        .collect();
+    for entry in changed {
+        state.space.update_space(
+            entry,
+            &state.client_state.compositor_state,
+            state.client_state.fractional_scaling_manager.as_ref(),
+            state.client_state.viewporter_state.as_ref(),
+            &mut state.client_state.layer_state,
+            &state.client_state.queue_handle,
+            None,
+            state.client_state.overlap_notify.clone(),
+        );
+    }
+}
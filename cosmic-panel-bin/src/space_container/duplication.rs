@@ -0,0 +1,89 @@
+//! Clones of panels on other outputs, e.g. for the same dock on every monitor
+//! of a symmetric setup.
+//!
+//! A clone is a panel entry of its own, with a generated name and the output
+//! it was duplicated onto. Whenever the config of its original changes, it is
+//! saved to the clones as well, whose own watchers then update them like any
+//! other panel. Detaching a clone keeps its current config, and lets it be
+//! configured on its own.
+
+use cosmic::cosmic_config::{ConfigSet, CosmicConfigEntry};
+use cosmic_panel_config::{CosmicPanelConfig, CosmicPanelContainerConfig};
+use tracing::{error, info};
+
+use crate::PanelMode;
+
+use super::SpaceContainer;
+
+impl SpaceContainer {
+    /// Clone the panel `panel` onto the output `output`, and return the name
+    /// of the clone. The clone is created by the watcher of the entries.
+    pub fn duplicate_panel(&mut self, panel: &str, output: &str) -> Result<String, String> {
+        if self.mode != PanelMode::Desktop {
+            return Err("Panels can only be duplicated in a user session".to_string());
+        }
+        let mut clone = self.config.duplicate(panel, output)?;
+        // the panels have their appearance profiles applied, so the clone is made of
+        // the configured entry of the original
+        if let Some((original, _)) = clone.clone_of.as_ref().and_then(|o| self.configured.get(o)) {
+            clone = CosmicPanelConfig {
+                name: clone.name,
+                output: clone.output,
+                clone_of: clone.clone_of,
+                ..original.clone()
+            };
+        }
+        let entries: Vec<String> = self
+            .config
+            .config_list
+            .iter()
+            .map(|c| c.name.clone())
+            .chain([clone.name.clone()])
+            .collect();
+        // the entry must be written before it is listed
+        CosmicPanelConfig::cosmic_config(&clone.name)
+            .and_then(|config| clone.write_entry(&config))
+            .and_then(|_| CosmicPanelContainerConfig::cosmic_config()?.set("entries", entries))
+            .map_err(|err| format!("Failed to save the clone of {panel}: {err:?}"))?;
+        info!("Duplicated panel {panel} onto {output} as {}", clone.name);
+        Ok(clone.name)
+    }
+
+    /// Stop the clone `panel` from following its original.
+    pub fn detach_panel(&mut self, panel: &str) -> Result<(), String> {
+        if !self.config.config_list.iter().any(|c| c.name == panel && c.clone_of.is_some()) {
+            return Err(format!("No panel clone {panel}"));
+        }
+        CosmicPanelConfig::cosmic_config(panel)
+            .and_then(|config| config.set("clone_of", None::<String>))
+            .map_err(|err| format!("Failed to detach {panel}: {err:?}"))?;
+        info!("Detached panel {panel} from its original");
+        Ok(())
+    }
+
+    /// Save the config of the panel `name` to its clones.
+    pub(crate) fn sync_clones(&self, name: &str) {
+        if self.mode != PanelMode::Desktop {
+            return;
+        }
+        let Some((original, _)) = self.configured.get(name) else {
+            return;
+        };
+        if original.clone_of.is_some() {
+            return;
+        }
+        for clone in self.config.config_list.iter().filter(|c| c.clone_of.as_deref() == Some(name))
+        {
+            let current = self.configured.get(&clone.name).map_or(clone, |(c, _)| c);
+            let synced = original.synced_clone(current);
+            if synced == *current {
+                continue;
+            }
+            if let Err(err) =
+                CosmicPanelConfig::cosmic_config(&clone.name).and_then(|c| synced.write_entry(&c))
+            {
+                error!("Failed to sync the panel clone {}: {err:?}", clone.name);
+            }
+        }
+    }
+}
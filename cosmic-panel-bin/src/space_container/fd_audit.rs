@@ -0,0 +1,174 @@
+//! Periodic audit of the fds, client sockets and child processes of the panel.
+//!
+//! Every applet holds a socket pair to the embedded compositor, which is
+//! replaced each time it restarts. Clients of the embedded compositor which no
+//! applet owns are closed so that many crash and restart cycles don't exhaust
+//! the fds of the panel. They are only closed once they were seen by two
+//! audits in a row, as an applet which is restarting gets its new client
+//! before it replaces the old one. Sockets of applets which were never
+//! started are closed when spawning skips them, and zombie children are
+//! reaped by the process manager, so both are only reported.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use smithay::reexports::wayland_server::{backend::ClientId, Client};
+use tracing::{debug, warn};
+
+use super::{space_container::disconnect_applet, SpaceContainer};
+
+/// How often the fds of the panel are audited.
+pub const AUDIT_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+pub struct FdAudit {
+    /// clients of the embedded compositor without an applet in the last audit
+    unowned: HashSet<ClientId>,
+    /// fds open after the last audit
+    last_fds: usize,
+}
+
+/// Whether an fd whose link points to `target` is a socket.
+fn is_socket(target: &Path) -> bool {
+    target.to_string_lossy().starts_with("socket:")
+}
+
+/// The pids listed in a `children` file of a task.
+fn child_pids(children: &str) -> impl Iterator<Item = u32> + '_ {
+    children.split_whitespace().filter_map(|pid| pid.parse().ok())
+}
+
+/// The state of a process from its `stat` file.
+fn stat_state(stat: &str) -> Option<&str> {
+    // the command name may contain spaces and parentheses, the state follows
+    // the last parenthesis
+    stat.rsplit_once(')')?.1.split_whitespace().next()
+}
+
+/// The number of open fds of the panel, and how many of them are sockets.
+fn open_fds() -> (usize, usize) {
+    let Ok(dir) = fs::read_dir("/proc/self/fd") else {
+        return (0, 0);
+    };
+    let targets: Vec<PathBuf> =
+        dir.flatten().filter_map(|e| fs::read_link(e.path()).ok()).collect();
+    (targets.len(), targets.iter().filter(|t| is_socket(t)).count())
+}
+
+/// The number of child processes of the panel, and how many of them are
+/// zombies.
+fn children() -> (usize, usize) {
+    let Ok(tasks) = fs::read_dir("/proc/self/task") else {
+        return (0, 0);
+    };
+    let mut total = 0;
+    let mut zombies = 0;
+    for task in tasks.flatten() {
+        let Ok(children) = fs::read_to_string(task.path().join("children")) else {
+            continue;
+        };
+        for pid in child_pids(&children) {
+            total += 1;
+            if fs::read_to_string(format!("/proc/{pid}/stat"))
+                .is_ok_and(|stat| stat_state(&stat) == Some("Z"))
+            {
+                zombies += 1;
+            }
+        }
+    }
+    (total, zombies)
+}
+
+impl SpaceContainer {
+    /// Close the sockets of clients which no applet owns anymore, and log how
+    /// many fds, sockets and children the panel has, and the sockets of
+    /// applets which were never started.
+    pub fn audit_fds(&mut self, audit: &mut FdAudit) {
+        let mut closed = 0;
+        let mut unstarted = 0;
+        let mut owned = HashSet::new();
+        for s in &self.space_list {
+            for clients in [&s.clients_left, &s.clients_center, &s.clients_right] {
+                for c in clients.lock().unwrap().iter() {
+                    owned.insert(c.client.id());
+                    // spawning takes the stream, or drops it if the applet
+                    // can't be started
+                    if c.stream.is_some() {
+                        warn!("{} holds the socket of an applet which was never started", c.name);
+                        unstarted += 1;
+                    }
+                }
+            }
+        }
+
+        let mut unowned = HashSet::new();
+        if let Some(dh) = self.s_display.as_ref() {
+            let clients: Vec<ClientId> =
+                dh.backend_handle().all_clients().filter(|id| !owned.contains(id)).collect();
+            for id in clients {
+                if !audit.unowned.contains(&id) {
+                    unowned.insert(id);
+                    continue;
+                }
+                let Ok(client) = Client::from_id(dh, id) else {
+                    continue;
+                };
+                warn!("Closing a leaked client socket without an applet");
+                disconnect_applet(dh, &client, "the client has no applet");
+                closed += 1;
+            }
+        }
+        audit.unowned = unowned;
+
+        let (fds, sockets) = open_fds();
+        let (children, zombies) = children();
+        let growth = fds as isize - audit.last_fds as isize;
+        audit.last_fds = fds;
+        if closed > 0 || unstarted > 0 || zombies > 0 {
+            warn!(
+                "Audited fds: {fds} open ({growth:+}), {sockets} sockets, {} applet clients, \
+                 {unstarted} never started, {children} children, {zombies} zombies, closed \
+                 {closed} leaked sockets",
+                owned.len()
+            );
+        } else {
+            debug!(
+                "Audited fds: {fds} open ({growth:+}), {sockets} sockets, {} applet clients, \
+                 {children} children",
+                owned.len()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sockets_are_told_apart_from_other_fds() {
+        assert!(is_socket(Path::new("socket:[123456]")));
+        assert!(!is_socket(Path::new("pipe:[123456]")));
+        assert!(!is_socket(Path::new("/dev/null")));
+        assert!(!is_socket(Path::new("/tmp/socket:1")));
+    }
+
+    #[test]
+    fn child_pids_are_parsed() {
+        assert_eq!(child_pids("12 345 6789 ").collect::<Vec<_>>(), vec![12, 345, 6789]);
+        assert_eq!(child_pids("").count(), 0);
+        assert_eq!(child_pids("\n").count(), 0);
+    }
+
+    #[test]
+    fn state_follows_the_last_parenthesis() {
+        assert_eq!(stat_state("42 (cosmic-applet) S 1 42 42 0"), Some("S"));
+        assert_eq!(stat_state("42 (a (b) c) Z 1 42 42 0"), Some("Z"));
+        assert_eq!(stat_state("42 (truncated"), None);
+        assert_eq!(stat_state("42 (empty)"), None);
+    }
+}
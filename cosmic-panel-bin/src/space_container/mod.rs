@@ -1,9 +1,18 @@
 //! space container is a container for all running panels, each panel space is a
-//! separate panel space container implements the WrapperSpace abstraction,
+//! separate panel space container implements the WrapperSpace and RootSpace
+//! abstractions,
 //! calling handle events and other methods of its PanelSpaces as necessary
 
+pub(crate) mod activation;
+pub(crate) mod appearance;
+pub(crate) mod duplication;
+pub(crate) mod fd_audit;
+pub(crate) mod rules;
+pub(crate) mod runtime_state;
 mod space_container;
+mod theme_override;
 pub(crate) mod toplevel;
+pub(crate) mod visibility_override;
 pub(crate) mod workspace;
 mod wrapper_space;
 
@@ -0,0 +1,142 @@
+//! Rules of the panel config, which hide, reveal or change the opacity of a
+//! panel while their conditions hold.
+//!
+//! Rules are evaluated for every panel before its visibility is decided, from
+//! the toplevels and workspaces of its output and the battery state. Hiding
+//! works like the suppression for presentations, revealing keeps an autohidden
+//! panel shown and overrides the fullscreen config, and the opacity replaces
+//! the configured one.
+
+use cctk::{
+    cosmic_protocols::{
+        toplevel_info::v1::client::zcosmic_toplevel_handle_v1,
+        workspace::v1::client::zcosmic_workspace_handle_v1,
+    },
+    wayland_client::{protocol::wl_output::WlOutput, WEnum},
+};
+use cosmic_panel_config::{PanelRule, RuleAction, RuleCondition};
+
+use crate::battery::Battery;
+
+use super::SpaceContainer;
+
+/// The combined actions of the rules which apply to a panel.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RuleOutcome {
+    /// `Some(false)` hides the panel, `Some(true)` keeps it revealed
+    pub visible: Option<bool>,
+    pub opacity: Option<f32>,
+}
+
+impl RuleOutcome {
+    pub fn hide(&self) -> bool {
+        self.visible == Some(false)
+    }
+
+    pub fn show(&self) -> bool {
+        self.visible == Some(true)
+    }
+}
+
+/// The state rule conditions are checked against, for the output of a panel.
+struct RuleFacts<'a> {
+    app_ids: Vec<&'a str>,
+    active_app_id: Option<&'a str>,
+    workspaces: Vec<&'a str>,
+    battery: Option<Battery>,
+}
+
+impl RuleFacts<'_> {
+    fn holds(&self, condition: &RuleCondition) -> bool {
+        match condition {
+            RuleCondition::AppId(app_id) => self.app_ids.contains(&app_id.as_str()),
+            RuleCondition::ActiveAppId(app_id) => self.active_app_id == Some(app_id.as_str()),
+            RuleCondition::Workspace(name) => self.workspaces.contains(&name.as_str()),
+            RuleCondition::OnBattery => self.battery.is_some_and(|b| b.on_battery),
+            RuleCondition::BatteryBelow(percentage) => {
+                self.battery.is_some_and(|b| b.percentage < *percentage)
+            },
+        }
+    }
+
+    fn evaluate(&self, rules: &[PanelRule]) -> RuleOutcome {
+        let mut outcome = RuleOutcome::default();
+        for rule in rules.iter().filter(|r| r.when.iter().all(|c| self.holds(c))) {
+            match rule.then {
+                RuleAction::Hide => outcome.visible = Some(false),
+                RuleAction::Show => outcome.visible = Some(true),
+                RuleAction::Opacity(opacity) => outcome.opacity = Some(opacity.clamp(0., 1.)),
+            }
+        }
+        outcome
+    }
+}
+
+impl SpaceContainer {
+    fn rule_facts(&self, output: Option<&WlOutput>) -> RuleFacts<'_> {
+        let active = zcosmic_toplevel_handle_v1::State::Activated;
+        let active_app_id = self
+            .toplevels
+            .iter()
+            .find(|(_, info)| info.state.contains(&active))
+            .map(|(_, info)| info.app_id.as_str())
+            .or_else(|| {
+                self.fallback_toplevels.values().find(|t| t.activated).map(|t| t.app_id.as_str())
+            });
+        let Some(output) = output else {
+            return RuleFacts {
+                app_ids: Vec::new(),
+                active_app_id,
+                workspaces: Vec::new(),
+                battery: self.battery,
+            };
+        };
+        RuleFacts {
+            app_ids: self.shown_app_ids(output).collect(),
+            active_app_id,
+            workspaces: self
+                .workspace_groups
+                .iter()
+                .filter(|g| g.outputs.contains(output))
+                .flat_map(|g| &g.workspaces)
+                .filter(|w| {
+                    w.state.contains(&WEnum::Value(zcosmic_workspace_handle_v1::State::Active))
+                })
+                .map(|w| w.name.as_str())
+                .collect(),
+            battery: self.battery,
+        }
+    }
+
+    /// Evaluate the rules of every panel, and apply the opacity of those which
+    /// changed it.
+    pub(crate) fn apply_rules(&mut self) {
+        let outcomes: Vec<RuleOutcome> = self
+            .space_list
+            .iter()
+            .map(|s| {
+                if s.config.rules.is_empty() {
+                    return RuleOutcome::default();
+                }
+                self.rule_facts(s.output.as_ref().map(|(o, ..)| o)).evaluate(&s.config.rules)
+            })
+            .collect();
+        for (space, outcome) in self.space_list.iter_mut().zip(outcomes) {
+            space.rules = outcome;
+            if space.maximized || space.config.rules.is_empty() {
+                continue;
+            }
+            let Some(configured) =
+                self.config.config_list.iter().find(|c| c.name == space.config.name)
+            else {
+                continue;
+            };
+            let opacity = outcome.opacity.unwrap_or(configured.opacity);
+            if (space.config.opacity - opacity).abs() >= 0.01 {
+                let mut config = space.config.clone();
+                config.opacity = opacity;
+                space.update_config(config, None, true);
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Runtime state of the panels which is visible to the user, kept across
+//! restarts of the panel.
+//!
+//! The state is written to `$XDG_STATE_HOME/cosmic-panel/state.ron` whenever it
+//! changes, instead of on exit, so it also survives a crash, and is read when
+//! the panel starts. Restored maximized panels only stay maximized until the
+//! toplevels of the compositor are known. Applets in overflow aren't kept, as
+//! the layout assigns them again from the same sizes.
+
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::PanelMode;
+
+use super::{visibility_override::VisibilityOverride, SpaceContainer};
+
+const STATE_FILE: &str = "state.ron";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeState {
+    /// presentation mode requested over the control interface
+    pub presentation: bool,
+    /// names of the outputs whose panels were maximized
+    pub maximized_outputs: Vec<String>,
+    /// visibility overrides by panel name
+    #[serde(default)]
+    pub visibility_overrides: HashMap<String, VisibilityOverride>,
+}
+
+fn state_path() -> Option<PathBuf> {
+    xdg::BaseDirectories::with_prefix("cosmic-panel")
+        .ok()?
+        .place_state_file(STATE_FILE)
+        .map_err(|err| warn!("Failed to create the state directory: {err:?}"))
+        .ok()
+}
+
+impl RuntimeState {
+    /// The state saved by the last panel, or the default state if there is
+    /// none.
+    pub fn load() -> Self {
+        let Some(path) = state_path() else {
+            return Self::default();
+        };
+        let Ok(s) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        ron::from_str(&s).unwrap_or_else(|err| {
+            warn!("Ignoring the invalid panel state in {}: {err:?}", path.display());
+            Self::default()
+        })
+    }
+
+    fn save(&self) {
+        let Some(path) = state_path() else {
+            return;
+        };
+        let s = match ron::ser::to_string_pretty(self, Default::default()) {
+            Ok(s) => s,
+            Err(err) => {
+                error!("Failed to serialize the panel state: {err:?}");
+                return;
+            },
+        };
+        // replace the file at once, so a crash doesn't leave a partial state
+        let tmp = path.with_extension("ron.tmp");
+        if let Err(err) = fs::write(&tmp, s).and_then(|_| fs::rename(&tmp, &path)) {
+            error!("Failed to save the panel state to {}: {err:?}", path.display());
+        }
+    }
+}
+
+impl SpaceContainer {
+    /// Restore the state saved by the last panel.
+    pub fn restore_runtime_state(&mut self) {
+        if self.mode != PanelMode::Desktop {
+            return;
+        }
+        let state = RuntimeState::load();
+        self.presentation_requested = state.presentation;
+        self.restored_maximized = state.maximized_outputs.clone();
+        self.visibility_overrides = state.visibility_overrides.clone();
+        self.runtime_state = state;
+    }
+
+    fn current_runtime_state(&self) -> RuntimeState {
+        let mut maximized_outputs: Vec<String> = self
+            .space_list
+            .iter()
+            .filter(|s| s.maximized)
+            .filter_map(|s| s.output.as_ref().and_then(|o| o.2.name.clone()))
+            .collect();
+        maximized_outputs.sort();
+        maximized_outputs.dedup();
+        RuntimeState {
+            presentation: self.presentation_requested,
+            maximized_outputs,
+            // panels revealed by the toggle shortcut keep their previous override
+            visibility_overrides: self
+                .visibility_overrides
+                .iter()
+                .map(|(name, o)| (name.clone(), *self.shortcut_revealed.get(name).unwrap_or(o)))
+                .filter(|(_, o)| *o != VisibilityOverride::Auto)
+                .collect(),
+        }
+    }
+
+    /// Save the runtime state if it changed since it was last saved.
+    pub fn save_runtime_state(&mut self) {
+        if self.mode != PanelMode::Desktop {
+            return;
+        }
+        let state = self.current_runtime_state();
+        if state != self.runtime_state {
+            state.save();
+            self.runtime_state = state;
+        }
+    }
+
+    /// Stop treating the outputs of the restored state as maximized, once the
+    /// toplevels of the compositor are known.
+    pub(crate) fn drop_restored_maximized(&mut self) {
+        if self.restored_maximized.is_empty() {
+            return;
+        }
+        let pre_maximixed_outputs = self.maximized_outputs();
+        self.restored_maximized.clear();
+        self.apply_maximized_outputs(pre_maximixed_outputs);
+    }
+}
@@ -1,24 +1,39 @@
 use std::{
     cell::RefCell,
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     rc::Rc,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use crate::{
+    applet_channel::AppletSender,
+    battery::Battery,
+    frame_stats::{FrameSample, FrameStats},
+    iced::elements::{frame_stats_hud::frame_stats_hud_element, osd::OsdContent},
+    ipc::{AppletState, PanelEvent, PanelState},
     minimize::MinimizeApplet,
-    space::{AppletMsg, PanelColors, PanelSpace},
+    portal::PortalGrants,
+    space::{
+        preview_config, same_sections, AppletMsg, AppletRegistry, DebugOverlay, EditMode, OsdSpace,
+        PanelColors, PanelSpace, OSD_MARGIN, OSD_TIMEOUT,
+    },
+    space_container::{
+        runtime_state::RuntimeState, theme_override::ThemeBuilders, toplevel::FallbackToplevel,
+        visibility_override::VisibilityOverride,
+    },
+    startup,
     xdg_shell_wrapper::{
         self,
-        client::handlers::overlap::OverlapNotifyV1,
+        client::handlers::{hot_corner::HotCorners, overlap::OverlapNotifyV1},
         client_state::ClientFocus,
         shared_state::GlobalState,
-        space::{Visibility, WrapperSpace},
+        space::{ClientHost, OutputHandler, Visibility},
         wp_fractional_scaling::FractionalScalingManager,
         wp_security_context::SecurityContextManager,
         wp_viewporter::ViewporterState,
     },
-    PanelCalloopMsg,
+    PanelCalloopMsg, PanelMode,
 };
 use cctk::{
     cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_handle_v1::ZcosmicToplevelHandleV1,
@@ -29,7 +44,7 @@ use cctk::{
 use cosmic::{cosmic_config::CosmicConfigEntry, iced::id, theme};
 use cosmic_panel_config::{
     CosmicPanelBackground, CosmicPanelConfig, CosmicPanelContainerConfig, CosmicPanelOuput,
-    PanelAnchor,
+    FullscreenBehavior, HotCorner, PanelAnchor, Side,
 };
 use cosmic_theme::{Theme, ThemeMode};
 use notify::RecommendedWatcher;
@@ -37,17 +52,31 @@ use sctk::{
     output::OutputInfo,
     reexports::{
         calloop,
-        client::{protocol::wl_output::WlOutput, Connection, QueueHandle},
+        client::{
+            protocol::{wl_output::WlOutput, wl_surface::WlSurface},
+            Connection, QueueHandle,
+        },
+    },
+    seat::pointer::{PointerEvent, PointerEventKind, BTN_RIGHT},
+    shell::{
+        wlr_layer::LayerShell,
+        xdg::window::{Window, WindowConfigure},
+        WaylandSurface,
     },
-    shell::wlr_layer::LayerShell,
 };
 use smithay::{
     backend::renderer::gles::GlesRenderer,
+    desktop::space::SpaceElement,
     output::Output,
-    reexports::wayland_server::{self, backend::ClientId},
+    reexports::wayland_server::{
+        self,
+        backend::{protocol::ProtocolError, ClientId},
+    },
+    utils::{Logical, Physical, Rectangle, Size},
 };
 use tokio::sync::mpsc;
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
+use wayland_backend::client::ObjectId;
 use wayland_server::Resource;
 
 pub struct SpaceContainer {
@@ -58,46 +87,94 @@ pub struct SpaceContainer {
     pub(crate) s_display: Option<wayland_server::DisplayHandle>,
     pub(crate) c_focused_surface: Rc<RefCell<ClientFocus>>,
     pub(crate) c_hovered_surface: Rc<RefCell<ClientFocus>>,
-    pub applet_tx: mpsc::Sender<AppletMsg>,
+    pub applet_tx: AppletSender,
     pub panel_tx: calloop::channel::Sender<PanelCalloopMsg>,
     pub(crate) outputs: Vec<(WlOutput, Output, OutputInfo)>,
     pub(crate) watchers: HashMap<String, RecommendedWatcher>,
     pub(crate) maximized_toplevels: Vec<(ZcosmicToplevelHandleV1, ToplevelInfo)>,
     pub(crate) toplevels: Vec<(ZcosmicToplevelHandleV1, ToplevelInfo)>,
+    /// toplevels of wlr-foreign-toplevel-management, without cosmic toplevel info
+    pub(crate) fallback_toplevels: HashMap<ObjectId, FallbackToplevel>,
     pub(crate) workspace_groups: Vec<WorkspaceGroup>,
     pub(crate) is_dark: bool,
     pub(crate) light_theme: cosmic::Theme,
     pub(crate) dark_theme: cosmic::Theme,
+    /// builders of the light and dark theme, for the theme overrides of panels
+    pub(crate) theme_builders: ThemeBuilders,
+    /// bumped on every theme change, panels with an older generation are
+    /// themed again before their next frame
+    pub(crate) theme_generation: u64,
     pub(crate) security_context_manager: Option<SecurityContextManager>,
-    /// map from output name to minimized applet info
-    pub(crate) minimized_applets: HashMap<String, MinimizeApplet>,
+    /// map from output name to the info of its minimize applets
+    pub(crate) minimized_applets: HashMap<String, Vec<MinimizeApplet>>,
+    /// minimize rectangles of single windows by the identifier of their
+    /// forwarded toplevel, relative to the applet surface
+    pub(crate) toplevel_minimize_rects:
+        HashMap<String, (wayland_server::protocol::wl_surface::WlSurface, Rectangle<i32, Logical>)>,
     pub(crate) loop_handle: calloop::LoopHandle<'static, GlobalState>,
     pub(crate) overlap_notify: Option<OverlapNotifyV1>,
+    /// app ids of the toplevel handles sent by overlap notifications
+    pub(crate) overlap_app_ids: HashMap<ObjectId, String>,
+    /// approximate overlaps from toplevel state, without overlap notify
+    pub(crate) geometry_overlaps: bool,
+    pub(crate) frame_stats: FrameStats,
+    /// show the frame statistics HUD on each panel
+    pub(crate) debug_hud: bool,
+    /// draw damage, element geometries and input regions on top of each panel
+    pub(crate) debug_overlay: bool,
+    /// applets are reordered by dragging them on each panel
+    pub(crate) edit_mode: bool,
+    /// the session is locked, and panels are hidden behind the lock screen
+    pub(crate) session_locked: bool,
+    /// presentation mode requested over the control interface, e.g. while the
+    /// screen is shared
+    pub(crate) presentation_requested: bool,
+    /// panels are hidden for a presentation
+    pub(crate) presenting: bool,
+    /// panels were revealed during the current presentation
+    pub(crate) presentation_revealed: bool,
+    /// battery state for the rules of panels, if the system has a battery
+    pub(crate) battery: Option<Battery>,
+    /// badges and attention animations of applets are silenced
+    pub(crate) do_not_disturb: bool,
+    /// grants of flatpak applets from the permission store of the portal
+    pub(crate) portal_grants: PortalGrants,
+    /// applets of all panels by client
+    pub(crate) applets: AppletRegistry,
+    pub(crate) mode: PanelMode,
+    /// the event loop returns, and the panel stops its applets and exits
+    pub(crate) exit_requested: bool,
+    /// panel changes are sent here to be published over the control interface
+    pub(crate) panel_events: Option<mpsc::UnboundedSender<PanelEvent>>,
+    /// panel states last sent to `panel_events`
+    pub(crate) published_panels: Vec<PanelState>,
+    /// on-screen displays by output
+    pub(crate) osds: Vec<OsdSpace>,
+    /// configs by panel name before their appearance profiles were applied,
+    /// with the indices of the profiles which applied
+    pub(crate) configured: HashMap<String, (CosmicPanelConfig, Vec<usize>)>,
+    /// visibility overrides by panel name
+    pub(crate) visibility_overrides: HashMap<String, VisibilityOverride>,
+    /// names of panels revealed by the toggle shortcut, with the override
+    /// they had before
+    pub(crate) shortcut_revealed: HashMap<String, VisibilityOverride>,
+    /// runtime state last saved for the next panel
+    pub(crate) runtime_state: RuntimeState,
+    /// names of outputs which were maximized in the restored state, until the
+    /// toplevels of the compositor are known
+    pub(crate) restored_maximized: Vec<String>,
 }
 
 impl SpaceContainer {
     pub fn new(
         config: CosmicPanelContainerConfig,
-        tx: mpsc::Sender<AppletMsg>,
+        tx: AppletSender,
         panel_tx: calloop::channel::Sender<PanelCalloopMsg>,
         loop_handle: calloop::LoopHandle<'static, GlobalState>,
     ) -> Self {
-        let is_dark = ThemeMode::config()
-            .ok()
-            .and_then(|c| ThemeMode::get_entry(&c).ok())
-            .unwrap_or_default()
-            .is_dark;
-
-        let light = Theme::light_config()
-            .ok()
-            .and_then(|c| Theme::get_entry(&c).ok())
-            .unwrap_or_else(Theme::light_default);
-        let dark = Theme::dark_config()
-            .ok()
-            .and_then(|c| Theme::get_entry(&c).ok())
-            .unwrap_or_else(Theme::dark_default);
-
-        Self {
+        let (is_dark, light, dark) = load_themes();
+
+        let mut container = Self {
             connection: None,
             config,
             space_list: Vec::with_capacity(1),
@@ -111,55 +188,718 @@ impl SpaceContainer {
             watchers: HashMap::new(),
             maximized_toplevels: Vec::with_capacity(1),
             toplevels: Vec::new(),
+            fallback_toplevels: HashMap::new(),
             workspace_groups: Vec::new(),
             is_dark,
             light_theme: cosmic::Theme::system(Arc::new(light)),
             dark_theme: cosmic::Theme::system(Arc::new(dark)),
+            theme_builders: ThemeBuilders::load(),
+            theme_generation: 1,
             security_context_manager: None,
             minimized_applets: HashMap::new(),
+            toplevel_minimize_rects: HashMap::new(),
             loop_handle,
             overlap_notify: None,
+            overlap_app_ids: HashMap::new(),
+            geometry_overlaps: false,
+            frame_stats: FrameStats::default(),
+            debug_hud: std::env::var("COSMIC_PANEL_DEBUG_HUD").is_ok_and(|v| v == "1"),
+            debug_overlay: std::env::var("COSMIC_PANEL_DEBUG_OVERLAY").is_ok_and(|v| v == "1"),
+            edit_mode: false,
+            session_locked: false,
+            presentation_requested: false,
+            presenting: false,
+            presentation_revealed: false,
+            battery: None,
+            do_not_disturb: CosmicPanelContainerConfig::do_not_disturb(),
+            portal_grants: PortalGrants::default(),
+            applets: AppletRegistry::default(),
+            mode: PanelMode::default(),
+            exit_requested: false,
+            panel_events: None,
+            published_panels: Vec::new(),
+            osds: Vec::new(),
+            configured: HashMap::new(),
+            visibility_overrides: HashMap::new(),
+            shortcut_revealed: HashMap::new(),
+            runtime_state: RuntimeState::default(),
+            restored_maximized: Vec::new(),
+        };
+        let config_list = std::mem::take(&mut container.config.config_list);
+        container.config.config_list =
+            config_list.into_iter().map(|c| container.apply_appearance_profiles(c)).collect();
+        container
+    }
+
+    /// Record a frame sample if any panel rendered since the last call.
+    pub fn record_frame(&mut self, dispatch: Duration, render: Duration) {
+        let mut sample: Option<FrameSample> = None;
+        for space in &mut self.space_list {
+            if let Some(frame) = space.pending_frame.take() {
+                sample.get_or_insert_with(Default::default).merge(frame);
+            }
+        }
+        if let Some(mut sample) = sample {
+            sample.dispatch = dispatch;
+            sample.render = render.saturating_sub(sample.swap);
+            self.frame_stats.push(sample);
+            startup::mark(startup::Phase::FirstFrame);
+            self.release_staged_applets(true);
         }
     }
 
-    pub fn set_dark(&mut self, theme: theme::CosmicTheme) {
-        self.dark_theme = cosmic::Theme::system(Arc::new(theme));
+    /// Start the applets staged until the first frame, once it was rendered if
+    /// `first_frame`, or once staging timed out.
+    pub fn release_staged_applets(&self, first_frame: bool) {
+        for (clients, msg) in startup::release(first_frame) {
+            // the panel may have been removed while its applets were staged
+            if !self.space_list.iter().any(|s| {
+                [&s.clients_left, &s.clients_center, &s.clients_right]
+                    .into_iter()
+                    .any(|c| Arc::ptr_eq(c, &clients))
+            }) {
+                // dropping the message closes the client socket of the process
+                debug!("Dropping the staged {msg:?} of a removed panel");
+                continue;
+            }
+            if let Err(err) = self.applet_tx.try_send(msg) {
+                error!("Failed to start a staged applet {err}");
+            }
+        }
+    }
+
+    /// The logical rectangles of the outputs with their scale. The scale of an
+    /// output with a panel is the fractional scale of the panel.
+    pub(crate) fn output_scales(&self) -> Vec<(Rectangle<i32, Logical>, f64)> {
+        self.outputs
+            .iter()
+            .filter_map(|(c_output, _, info)| {
+                let size = info.logical_size?;
+                let scale = self
+                    .space_list
+                    .iter()
+                    .find(|s| s.output.as_ref().is_some_and(|o| &o.0 == c_output))
+                    .map_or(info.scale_factor as f64, |s| s.scale);
+                Some((Rectangle::from_loc_and_size(info.location, size), scale))
+            })
+            .collect()
+    }
+
+    /// The zone of the output of each panel left by the exclusive zones of
+    /// the panels before it. The compositor arranges layer surfaces in the
+    /// order they were created, each in the zone the earlier ones left.
+    pub(crate) fn output_zones(&self) -> Vec<Option<Rectangle<i32, Logical>>> {
+        self.arrange_zones().0
+    }
+
+    /// The zone of each output left by the exclusive zones of all panels,
+    /// which maximized toplevels fill.
+    pub(crate) fn work_areas(&self) -> Vec<(WlOutput, Rectangle<i32, Logical>)> {
+        self.arrange_zones().1
+    }
 
+    /// The zone each panel is arranged in, and the zone of each output left
+    /// after all of them.
+    fn arrange_zones(
+        &self,
+    ) -> (Vec<Option<Rectangle<i32, Logical>>>, Vec<(WlOutput, Rectangle<i32, Logical>)>) {
+        arrange_panels(self.space_list.iter().map(|s| {
+            let (c_output, _, info) = s.output.as_ref()?;
+            // the margin on the anchored edge is part of the zone
+            let exclusive =
+                if s.exclusive_zone > 0 { s.exclusive_zone + s.additional_gap } else { 0 };
+            Some((c_output.clone(), info.logical_size?.into(), s.config.anchor(), exclusive))
+        }))
+    }
+
+    pub fn applet_texture_bytes(&self) -> usize {
+        self.space_list.iter().map(PanelSpace::applet_texture_bytes).sum()
+    }
+
+    /// The panel surface on the host of the applet `client`.
+    pub fn host_surface(&self, client: &wayland_server::Client) -> Option<WlSurface> {
+        self.applet_space(&client.id()).and_then(|i| self.space_list[i].panel_surface()).cloned()
+    }
+
+    /// The index of the panel space of the applet `client`.
+    pub(crate) fn applet_space(&self, client: &ClientId) -> Option<usize> {
+        let applet = self.applets.get(client)?;
+        self.space_list
+            .iter()
+            .position(|s| Arc::ptr_eq(&applet.clients, s.section_clients(applet.side)))
+    }
+
+    pub fn panel_states(&self) -> Vec<PanelState> {
+        self.space_list
+            .iter()
+            .map(|s| PanelState {
+                name: s.config.name.clone(),
+                output: s
+                    .output
+                    .as_ref()
+                    .and_then(|(_, _, info)| info.name.clone())
+                    .unwrap_or_default(),
+                anchor: s.config.anchor.to_string(),
+                visibility: match s.visibility {
+                    Visibility::Visible => "visible",
+                    Visibility::Hidden => "hidden",
+                    Visibility::TransitionToVisible { .. } => "transition-to-visible",
+                    Visibility::TransitionToHidden { .. } => "transition-to-hidden",
+                }
+                .to_string(),
+                width: s.dimensions.w,
+                height: s.dimensions.h,
+                exclusive_zone: s.exclusive_zone,
+            })
+            .collect()
+    }
+
+    /// Send the panels which were mapped, changed or removed since the last
+    /// call to the control interface. Nothing is sent while the panels are
+    /// unchanged, so the state leaves out the size of the contents, which
+    /// changes on every frame of an animation.
+    pub fn publish_panel_states(&mut self) {
+        let Some(tx) = self.panel_events.as_ref() else {
+            return;
+        };
+        let panels = self.panel_states();
+        if panels == self.published_panels {
+            return;
+        }
+        for old in &self.published_panels {
+            if !panels.iter().any(|p| p.name == old.name && p.output == old.output) {
+                _ = tx.send(PanelEvent::Removed {
+                    name: old.name.clone(),
+                    output: old.output.clone(),
+                });
+            }
+        }
+        for panel in &panels {
+            if !self.published_panels.contains(panel) {
+                _ = tx.send(PanelEvent::Changed(panel.clone()));
+            }
+        }
+        self.published_panels = panels;
+    }
+
+    pub fn applet_states(&self) -> Vec<AppletState> {
+        let mut states = Vec::new();
+        for s in &self.space_list {
+            let output =
+                s.output.as_ref().and_then(|(_, _, info)| info.name.clone()).unwrap_or_default();
+            for clients in [&s.clients_left, &s.clients_center, &s.clients_right] {
+                for c in clients.lock().unwrap().iter() {
+                    let pid = c.process.pid.unwrap_or_default();
+                    states.push(AppletState {
+                        panel: s.config.name.clone(),
+                        output: output.clone(),
+                        name: c.name.clone(),
+                        pid,
+                        restarts: c.process.restarts,
+                        last_exit_code: c.process.last_exit_code.unwrap_or_default(),
+                        uptime: c.process.started.elapsed().as_secs(),
+                        rss: if pid == 0 { 0 } else { rss(pid) },
+                    });
+                }
+            }
+        }
+        states
+    }
+
+    pub fn set_debug_overlay(&mut self, enabled: bool) {
+        self.debug_overlay = enabled;
         for space in &mut self.space_list {
-            let is_dark = space.is_dark(self.is_dark);
-            if is_dark {
-                space.set_theme(
-                    PanelColors::new(self.dark_theme.clone())
-                        .with_color_override(space.config.bg_color_override()),
-                );
+            if enabled != space.debug_overlay.is_some() {
+                space.debug_overlay = enabled.then(DebugOverlay::default);
+                space.is_dirty = true;
+            }
+        }
+    }
+
+    /// Turn do not disturb on or off. Applets get the state with their live
+    /// config, and it is saved in the panel config so it persists.
+    pub fn set_do_not_disturb(&mut self, enabled: bool) {
+        if self.do_not_disturb == enabled {
+            return;
+        }
+        info!("Do not disturb {}", if enabled { "on" } else { "off" });
+        self.do_not_disturb = enabled;
+        if matches!(self.mode, PanelMode::Desktop) {
+            if let Err(err) = CosmicPanelContainerConfig::set_do_not_disturb(enabled) {
+                warn!("Failed to save the do not disturb state: {:?}", err);
+            }
+        }
+    }
+
+    pub fn set_edit_mode(&mut self, enabled: bool) {
+        self.edit_mode = enabled;
+        for space in &mut self.space_list {
+            if enabled != space.edit_mode.is_some() {
+                if enabled {
+                    space.close_popups(|_| false);
+                }
+                space.edit_mode = enabled.then(EditMode::default);
+                space.is_dirty = true;
+            }
+        }
+    }
+
+    /// Start edit mode on panels created since it was enabled.
+    pub(crate) fn ensure_edit_mode(&mut self) {
+        if self.edit_mode {
+            for space in self.space_list.iter_mut().filter(|s| s.edit_mode.is_none()) {
+                space.edit_mode = Some(EditMode::default());
+                space.is_dirty = true;
+            }
+        }
+    }
+
+    /// Create the toplevels of preview panels.
+    pub(crate) fn ensure_preview(
+        &mut self,
+        compositor_state: &sctk::compositor::CompositorState,
+        fractional_scale_manager: Option<&FractionalScalingManager>,
+        viewport: Option<&ViewporterState>,
+        xdg_shell_state: &sctk::shell::xdg::XdgShell,
+        qh: &QueueHandle<GlobalState>,
+    ) {
+        for space in self.space_list.iter_mut().filter(|s| s.is_preview && s.preview.is_none()) {
+            if let Err(err) = space.create_preview_window(
+                compositor_state,
+                fractional_scale_manager,
+                viewport,
+                xdg_shell_state,
+                qh,
+            ) {
+                error!("Failed to create the preview window: {}", err);
+            }
+        }
+    }
+
+    pub fn configure_preview(&mut self, window: &Window, configure: WindowConfigure) {
+        if let Some(space) = self
+            .space_list
+            .iter_mut()
+            .find(|s| s.preview.as_ref().is_some_and(|w| w.wl_surface() == window.wl_surface()))
+        {
+            space.configure_panel_preview(configure, &mut self.renderer);
+        }
+    }
+
+    /// Close the preview panel of `window`, and exit once no preview is left.
+    pub fn close_preview(&mut self, window: &Window) {
+        self.space_list
+            .retain(|s| s.preview.as_ref().map(|w| w.wl_surface()) != Some(window.wl_surface()));
+        if self.space_list.is_empty() {
+            info!("Preview window closed");
+            self.exit_requested = true;
+        }
+    }
+
+    /// Handle a pointer event over the layer surface of a panel in edit mode.
+    /// Returns whether the event was consumed.
+    pub fn edit_pointer_event(&mut self, seat_name: &str, e: &PointerEvent) -> bool {
+        self.space_list
+            .iter_mut()
+            .find(|s| s.edit_mode.is_some() && s.panel_surface() == Some(&e.surface))
+            .is_some_and(|s| s.edit_pointer_event(seat_name, e))
+    }
+
+    /// Hide panels while the session is locked or a presentation is shown,
+    /// including panels created meanwhile. A presentation is requested over the
+    /// control interface, which hides every panel, or shown by an active
+    /// fullscreen toplevel, which hides the panels of its output that hide for
+    /// fullscreen windows. Panels stay hidden until it ends, unless they are
+    /// revealed explicitly.
+    ///
+    /// Panels above windows also follow their fullscreen config while a
+    /// fullscreen toplevel is shown on their output, and panels are hidden
+    /// while one of their rules or their visibility override hides them.
+    pub(crate) fn apply_suppression(&mut self) {
+        let presenting_outputs = self.active_fullscreen_outputs();
+        let presenting = self.presentation_requested || !presenting_outputs.is_empty();
+        if presenting != self.presenting {
+            info!("Presentation {}", if presenting { "started" } else { "ended" });
+            self.presenting = presenting;
+            self.presentation_revealed = false;
+        }
+        let (session_locked, revealed) = (self.session_locked, self.presentation_revealed);
+        let (fullscreen, presented): (Vec<bool>, Vec<bool>) = self
+            .space_list
+            .iter()
+            .map(|s| {
+                let output = s.output.as_ref().map(|(o, ..)| o);
+                let fullscreen = s.yields_to_fullscreen()
+                    && output.is_some_and(|o| self.output_has_fullscreen_toplevel(o));
+                let presented = self.presentation_requested
+                    || s.config.fullscreen == FullscreenBehavior::Hide
+                        && output.is_some_and(|o| presenting_outputs.contains(o));
+                (fullscreen, presented)
+            })
+            .unzip();
+        let overrides: Vec<_> = self
+            .space_list
+            .iter()
+            .map(|s| {
+                let name = &s.config.name;
+                (self.visibility_override(name), self.shortcut_revealed.contains_key(name))
+            })
+            .collect();
+        for (((space, fullscreen), presented), (o, keyboard_revealed)) in
+            self.space_list.iter_mut().zip(fullscreen).zip(presented).zip(overrides)
+        {
+            let suppressed = session_locked || presented && !revealed;
+            space.visibility_override = o;
+            space.set_keyboard_revealed(keyboard_revealed);
+            // rules and the visibility override take precedence over the
+            // fullscreen config, but not over the session lock or presentations
+            let fullscreen = fullscreen && !space.rules.show() && !space.force_visible();
+            let (suppressed, lowered) = match space.config.fullscreen {
+                FullscreenBehavior::Hide => (suppressed || fullscreen, false),
+                FullscreenBehavior::Bottom => (suppressed, fullscreen),
+                FullscreenBehavior::Ignore => (suppressed, false),
+            };
+            let suppressed =
+                suppressed || space.rules.hide() || o == VisibilityOverride::ForceHidden;
+            if space.lowered != lowered {
+                space.set_lowered(lowered);
+            }
+            if space.suppressed != suppressed {
+                space.set_suppressed(suppressed);
+            }
+        }
+    }
+
+    /// The hot corners of the autohidden panels, by panel and output.
+    pub(crate) fn hot_corners(&self) -> Vec<(String, WlOutput, HotCorner)> {
+        self.space_list
+            .iter()
+            .filter_map(|s| Some((s, s.config.autohide.as_ref()?, s.output.as_ref()?)))
+            .flat_map(|(s, autohide, (output, ..))| {
+                autohide.hot_corners.iter().map(|c| (s.config.name.clone(), output.clone(), *c))
+            })
+            .collect()
+    }
+
+    /// Pass the focus of their hot corners to the panels.
+    pub(crate) fn apply_hot_corners(&mut self, hot_corners: &HotCorners) {
+        for space in &mut self.space_list {
+            space.hot_corner_focus = space
+                .output
+                .as_ref()
+                .and_then(|(output, ..)| hot_corners.focus(&space.config.name, output));
+        }
+    }
+
+    /// Whether the pointer at `position` on `surface` is in a zone where hover
+    /// doesn't reveal the hidden panel of the surface.
+    pub(crate) fn reveal_blocked(&self, surface: &WlSurface, position: (f64, f64)) -> bool {
+        let Some(space) = self.space_list.iter().find(|s| s.panel_surface() == Some(surface))
+        else {
+            return false;
+        };
+        let Some(autohide) = space.config.autohide.as_ref() else {
+            return false;
+        };
+        if autohide.blocked_zones.is_empty() || !matches!(space.visibility, Visibility::Hidden) {
+            return false;
+        }
+        let Some(loc) = space.output_location() else {
+            return false;
+        };
+        let along = if space.config.is_horizontal() {
+            loc.x as f64 + position.0
+        } else {
+            loc.y as f64 + position.1
+        };
+        autohide.blocked_zones.iter().any(|(from, to)| (*from as f64..*to as f64).contains(&along))
+    }
+
+    /// Create the debug HUD and overlay for panels which don't have them yet.
+    pub(crate) fn ensure_debug_hud(&mut self) {
+        if self.debug_overlay {
+            for space in self.space_list.iter_mut().filter(|s| s.debug_overlay.is_none()) {
+                space.debug_overlay = Some(DebugOverlay::default());
+            }
+        }
+        if !self.debug_hud {
+            return;
+        }
+        for space in &mut self.space_list {
+            if space.frame_stats_hud.is_some() || space.dimensions.w <= 0 {
+                continue;
             }
+            let Some((_, output, _)) = space.output.as_ref() else {
+                continue;
+            };
+            let hud = frame_stats_hud_element(
+                self.frame_stats.clone(),
+                space.dimensions.w.min(320),
+                space.crosswise(),
+                self.loop_handle.clone(),
+                space.colors.theme.clone(),
+                space.space.id(),
+            );
+            hud.output_enter(output, Rectangle::default());
+            space.frame_stats_hud = Some(hud);
         }
     }
 
+    pub fn set_dark(&mut self, theme: theme::CosmicTheme) {
+        self.dark_theme = cosmic::Theme::system(Arc::new(theme));
+        // the builder is saved together with the theme it builds
+        self.theme_builders = ThemeBuilders::load();
+        self.theme_generation += 1;
+        self.sync_osd_themes();
+    }
+
     pub fn set_light(&mut self, theme: theme::CosmicTheme) {
         self.light_theme = cosmic::Theme::system(Arc::new(theme));
+        self.theme_builders = ThemeBuilders::load();
+        self.theme_generation += 1;
+        self.sync_osd_themes();
+    }
 
-        for space in &mut self.space_list {
-            let is_dark = space.is_dark(self.is_dark);
-            if !is_dark {
+    /// Reload the theme mode and the light and dark theme from their config,
+    /// and apply them to every panel, even if they didn't change.
+    pub fn reapply_theme(&mut self) {
+        let (is_dark, light, dark) = load_themes();
+        info!("Reapplying the {} theme", if is_dark { "dark" } else { "light" });
+        self.is_dark = is_dark;
+        self.light_theme = cosmic::Theme::system(Arc::new(light));
+        self.dark_theme = cosmic::Theme::system(Arc::new(dark));
+        self.theme_builders = ThemeBuilders::load();
+        self.theme_generation += 1;
+        self.sync_osd_themes();
+    }
+
+    /// Apply the current theme to the panels which haven't seen the latest
+    /// theme generation.
+    pub(crate) fn sync_panel_themes(&mut self) {
+        let generation = self.theme_generation;
+        let themes: Vec<_> = self
+            .space_list
+            .iter()
+            .map(|s| (s.theme_generation != generation).then(|| self.panel_theme(&s.config)))
+            .collect();
+        for (space, theme) in self.space_list.iter_mut().zip(themes) {
+            if let Some(theme) = theme {
                 space.set_theme(
-                    PanelColors::new(self.light_theme.clone())
-                        .with_color_override(space.config.bg_color_override()),
+                    PanelColors::new(theme).with_color_override(space.config.bg_color_override()),
                 );
+                space.theme_generation = generation;
             }
         }
     }
 
-    pub fn cur_theme(&self) -> cosmic::Theme {
-        if self.is_dark {
-            self.dark_theme.clone()
+    /// Apply the current theme to the shown OSDs.
+    pub(crate) fn sync_osd_themes(&mut self) {
+        let theme = if self.is_dark { &self.dark_theme } else { &self.light_theme };
+        for osd in &mut self.osds {
+            osd.set_theme(theme.clone());
+        }
+    }
+
+    /// Show `content` on the output named `output`, or on the output of the
+    /// first panel if `output` is empty. An OSD already shown on the output is
+    /// updated instead.
+    #[allow(clippy::too_many_arguments)]
+    pub fn show_osd(
+        &mut self,
+        output: &str,
+        content: OsdContent,
+        compositor_state: &sctk::compositor::CompositorState,
+        layer_state: &mut LayerShell,
+        fractional_scale_manager: Option<&FractionalScalingManager>,
+        viewport: Option<&ViewporterState>,
+        qh: &QueueHandle<GlobalState>,
+    ) {
+        let target = if output.is_empty() {
+            self.space_list.iter().find_map(|s| s.output.as_ref()).map(|o| o.0.clone())
         } else {
-            self.light_theme.clone()
+            self.outputs
+                .iter()
+                .find(|(_, _, info)| info.name.as_deref() == Some(output))
+                .map(|o| o.0.clone())
+        };
+        let Some((c_output, s_output, info)) =
+            target.and_then(|target| self.outputs.iter().find(|o| o.0 == target).cloned())
+        else {
+            warn!("No output to show an OSD on {}", output);
+            return;
+        };
+        if let Some(osd) = self.osds.iter_mut().find(|o| o.output() == Some(&c_output)) {
+            osd.set_content(content);
+            return;
         }
+        let Some(conn) = self.connection.clone() else {
+            return;
+        };
+
+        let panel =
+            self.space_list.iter().find(|s| s.output.as_ref().is_some_and(|o| o.0 == c_output));
+        let scale = panel.map_or(info.scale_factor as f64, |s| s.scale);
+        // the compositor keeps the OSD out of the exclusive zone of the panel,
+        // and it keeps its distance from a panel without one
+        let (anchor, margin) = panel.map_or((PanelAnchor::Bottom, OSD_MARGIN), |s| {
+            let margin = if s.exclusive_zone > 0 { 0 } else { s.crosswise() };
+            (s.config.anchor, OSD_MARGIN + margin)
+        });
+        let theme = if self.is_dark { &self.dark_theme } else { &self.light_theme };
+        let mut osd = OsdSpace::new(
+            info.name.clone().unwrap_or_default(),
+            content,
+            anchor,
+            margin,
+            scale,
+            theme.clone(),
+            self.loop_handle.clone(),
+        );
+        if let Err(err) = osd.new_output(
+            compositor_state,
+            fractional_scale_manager,
+            viewport,
+            layer_state,
+            &conn,
+            qh,
+            Some(c_output.clone()),
+            Some(s_output),
+            Some(info),
+        ) {
+            error!("Failed to show the OSD: {}", err);
+            return;
+        }
+        self.osds.push(osd);
+
+        _ = self.loop_handle.insert_source(
+            calloop::timer::Timer::from_duration(OSD_TIMEOUT),
+            move |_, _, data| {
+                let Some(i) = data.space.osds.iter().position(|o| o.output() == Some(&c_output))
+                else {
+                    return calloop::timer::TimeoutAction::Drop;
+                };
+                let expires = data.space.osds[i].expires();
+                if expires > Instant::now() {
+                    return calloop::timer::TimeoutAction::ToInstant(expires);
+                }
+                data.space.osds.remove(i);
+                calloop::timer::TimeoutAction::Drop
+            },
+        );
+    }
+
+    /// Disconnect the notification applets of all panels holding fds from
+    /// connections older than `generation`, so they are restarted with an fd
+    /// of the restarted notifications daemon.
+    pub fn restart_notification_applets(&mut self, generation: u64) {
+        for s in &self.space_list {
+            let Some(dh) = s.s_display.as_ref() else {
+                continue;
+            };
+            for clients in [&s.clients_left, &s.clients_center, &s.clients_right] {
+                for c in clients.lock().unwrap().iter() {
+                    if !c.notifications_generation.is_some_and(|g| g < generation) {
+                        continue;
+                    }
+                    info!("Restarting {}", c.name);
+                    disconnect_applet(dh, &c.client, "the notifications daemon restarted");
+                }
+            }
+        }
+    }
+
+    /// Restart the applet `name` of the panel `panel` on every output, by
+    /// stopping its process. The process manager then starts it again, and its
+    /// exit handler gives it a new client socket, while the other applets keep
+    /// running. Returns whether the applet was found.
+    pub fn restart_applet(&mut self, panel: &str, name: &str) -> bool {
+        let mut found = false;
+        for s in self.space_list.iter().filter(|s| s.config.name == panel) {
+            for clients in [&s.clients_left, &s.clients_center, &s.clients_right] {
+                for c in clients.lock().unwrap().iter_mut().filter(|c| c.name == name) {
+                    found = true;
+                    let Some(key) = c.process.key else {
+                        warn!("{} has not been started, so it can't be restarted", c.name);
+                        continue;
+                    };
+                    info!("Restarting {} on request", c.name);
+                    c.restart_requested = true;
+                    if let Err(err) = self.applet_tx.try_send(AppletMsg::Restart(key)) {
+                        c.restart_requested = false;
+                        error!("Failed to restart {}: {err}", c.name);
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    /// Replace the grants of flatpak applets, and restart the applets whose
+    /// grant changed with their new host socket, like [`Self::restart_applet`].
+    pub fn set_portal_grants(&mut self, grants: HashMap<String, bool>) {
+        let old = std::mem::replace(&mut *self.portal_grants.lock().unwrap(), grants.clone());
+        for s in &self.space_list {
+            for clients in [&s.clients_left, &s.clients_center, &s.clients_right] {
+                for c in clients.lock().unwrap().iter_mut() {
+                    let Some(app_id) = c.flatpak_id.as_ref() else {
+                        continue;
+                    };
+                    if c.requests_wayland_display != Some(true)
+                        || old.get(app_id).copied().unwrap_or_default()
+                            == grants.get(app_id).copied().unwrap_or_default()
+                    {
+                        continue;
+                    }
+                    let Some(key) = c.process.key else {
+                        continue;
+                    };
+                    info!("Restarting {} for its changed portal grant", c.name);
+                    c.restart_requested = true;
+                    if let Err(err) = self.applet_tx.try_send(AppletMsg::Restart(key)) {
+                        c.restart_requested = false;
+                        error!("Failed to restart {}: {err}", c.name);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Render the panel `panel` on `output`, or on its first output if
+    /// `output` is empty, into a PNG. `theme` is "light" or "dark" to override
+    /// the theme of the panel background, or empty to keep it.
+    pub fn snapshot(
+        &mut self,
+        panel: &str,
+        output: &str,
+        size: Size<i32, Physical>,
+        scale: f64,
+        theme: &str,
+    ) -> Result<Vec<u8>, String> {
+        let colors = match theme {
+            "" => None,
+            "light" => Some(PanelColors::new(self.light_theme.clone())),
+            "dark" => Some(PanelColors::new(self.dark_theme.clone())),
+            theme => return Err(format!("Unknown theme {theme}")),
+        };
+        let Some(space) = self.space_list.iter().find(|s| {
+            s.config.name == panel
+                && (output.is_empty()
+                    || s.output.as_ref().is_some_and(|o| o.2.name.as_deref() == Some(output)))
+        }) else {
+            return Err(format!("No panel named {panel} on {output:?}"));
+        };
+        let Some(renderer) = self.renderer.as_mut() else {
+            return Err("The renderer is not initialized yet".to_string());
+        };
+        space.snapshot(renderer, size, scale, colors).map_err(|err| err.to_string())
     }
 
     pub fn cleanup_client(&mut self, old_client_id: ClientId) {
+        for applets in self.minimized_applets.values_mut() {
+            applets.retain(|a| a.client != old_client_id);
+        }
         for s in &mut self.space_list {
+            s.minimize_applet_rects.remove(&old_client_id);
             // cleanup leftover windows
             let w = {
                 s.space
@@ -192,18 +932,10 @@ impl SpaceContainer {
     }
 
     pub(crate) fn set_theme_mode(&mut self, is_dark: bool) {
-        let changed = self.is_dark != is_dark;
-        self.is_dark = is_dark;
-        if changed {
-            let cur = self.cur_theme();
-            for space in &mut self.space_list {
-                if matches!(space.config.background, CosmicPanelBackground::ThemeDefault) {
-                    space.set_theme(
-                        PanelColors::new(cur.clone())
-                            .with_color_override(space.config.bg_color_override()),
-                    );
-                }
-            }
+        if self.is_dark != is_dark {
+            self.is_dark = is_dark;
+            self.theme_generation += 1;
+            self.sync_osd_themes();
         }
     }
 
@@ -211,6 +943,7 @@ impl SpaceContainer {
     pub fn remove_space(&mut self, name: String) {
         self.space_list.retain(|s| s.config.name != name);
         self.config.config_list.retain(|c| c.name != name);
+        self.configured.remove(&name);
         self.watchers.remove(&name);
     }
 
@@ -226,6 +959,22 @@ impl SpaceContainer {
         force_output: Option<WlOutput>,
         overlap_notify: Option<OverlapNotifyV1>,
     ) {
+        if self.mode == PanelMode::Preview {
+            entry = preview_config(entry);
+        }
+        // sizes which only differ by the applying profiles are animated, and
+        // applets get them with their live config
+        let size_from_profiles = self.configured.get(&entry.name).is_some_and(|(prev, _)| {
+            prev.size == entry.size
+                && prev.size_center == entry.size_center
+                && prev.size_wings == entry.size_wings
+        });
+        let mut entry = self.apply_appearance_profiles(entry);
+        // compare with the configured size of condensed panels
+        for space in self.space_list.iter_mut().filter(|s| s.config.name == entry.name) {
+            space.set_condensed(false);
+        }
+
         // if the output is set to "all", we need to check if the config is the same for
         // all outputs if the output is set to a specific output, we need to
         // make sure it doesn't exist on another output
@@ -299,9 +1048,9 @@ impl SpaceContainer {
         output_count_mismatch
         || self.config.config_list.iter().any(|c| {
             // size changed
-            c.name == entry.name && c.size != entry.size
+            c.name == entry.name && !size_from_profiles && c.size != entry.size
             // size overrides changed
-            || (c.name == entry.name && (c.size_center != entry.size_center || c.size_wings != entry.size_wings))
+            || (c.name == entry.name && !size_from_profiles && (c.size_center != entry.size_center || c.size_wings != entry.size_wings))
             // output changed
             || (entry.output != CosmicPanelOuput::All &&
             (c.name == entry.name && c.output != entry.output))
@@ -310,10 +1059,9 @@ impl SpaceContainer {
             // applet restarts are required
             || ((c.name == entry.name
                 && (c.is_horizontal() != entry.is_horizontal()
-                || c.size != entry.size
+                || !size_from_profiles && c.size != entry.size
                 || c.background != entry.background
-                || c.plugins_center != entry.plugins_center
-                || c.plugins_wings != entry.plugins_wings)))
+                || !same_sections(c, &entry))))
             // Priority change to conflict with adjacent panel
             || c.name != entry.name
                 && Some(c.anchor) != opposite_anchor
@@ -321,6 +1069,11 @@ impl SpaceContainer {
             || c.name != entry.name && old_priority != new_priority && c.anchor == entry.anchor
         );
 
+        let theme_changed = self
+            .config
+            .config_list
+            .iter()
+            .any(|c| c.name == entry.name && c.theme_override != entry.theme_override);
         self.config.config_list.retain(|c| c.name != entry.name);
         self.config.config_list.push(entry.clone());
 
@@ -329,6 +1082,7 @@ impl SpaceContainer {
                 CosmicPanelBackground::Color(c) => Some([c[0], c[1], c[2], entry.opacity]),
                 _ => None,
             };
+            let theme = theme_changed.then(|| self.panel_theme(&entry));
 
             for space in &mut self.space_list {
                 if space.config.name != entry.name {
@@ -337,6 +1091,9 @@ impl SpaceContainer {
 
                 entry.output = space.config.output.clone();
                 space.update_config(entry.clone(), bg_color, true);
+                if let Some(theme) = theme.clone() {
+                    space.set_theme(PanelColors::new(theme).with_color_override(bg_color));
+                }
             }
             self.apply_toplevel_changes();
             return;
@@ -360,13 +1117,7 @@ impl SpaceContainer {
                     self.c_focused_surface.clone(),
                     self.c_hovered_surface.clone(),
                     self.applet_tx.clone(),
-                    match entry.background {
-                        CosmicPanelBackground::ThemeDefault | CosmicPanelBackground::Color(_) => {
-                            self.cur_theme()
-                        },
-                        CosmicPanelBackground::Dark => self.dark_theme.clone(),
-                        CosmicPanelBackground::Light => self.light_theme.clone(),
-                    },
+                    self.panel_theme(&entry),
                     self.s_display.clone().unwrap(),
                     self.security_context_manager.clone(),
                     self.connection.as_ref().unwrap(),
@@ -374,6 +1125,9 @@ impl SpaceContainer {
                     xdg_shell_wrapper::space::Visibility::Visible,
                     self.loop_handle.clone(),
                 );
+                space.is_preview = self.mode == PanelMode::Preview;
+                space.portal_grants = self.portal_grants.clone();
+                space.applets = self.applets.clone();
                 if let Err(err) = space.new_output(
                     compositor_state,
                     fractional_scale_manager,
@@ -391,6 +1145,10 @@ impl SpaceContainer {
                 }
                 vec![]
             },
+            // a preview is only shown for the first output
+            CosmicPanelOuput::All if self.mode == PanelMode::Preview => {
+                self.outputs.iter().take(1).collect()
+            },
             CosmicPanelOuput::All => self.outputs.iter().collect(),
             CosmicPanelOuput::Name(name) => {
                 self.outputs.iter().filter(|(_, output, _)| &output.name() == name).collect()
@@ -400,7 +1158,8 @@ impl SpaceContainer {
         let maximized_outputs = self.maximized_outputs();
         for (wl_output, output, info) in outputs {
             let output_name = output.name();
-            let has_toplevel = self.toplevels.iter().any(|(_, t)| t.output.contains(wl_output));
+            let has_toplevel = self.toplevels.iter().any(|(_, t)| t.output.contains(wl_output))
+                || self.output_has_fallback_toplevel(wl_output);
             if force_output.as_ref() != Some(wl_output) && force_output.is_some() {
                 continue;
             }
@@ -445,13 +1204,7 @@ impl SpaceContainer {
                     self.c_focused_surface.clone(),
                     self.c_hovered_surface.clone(),
                     self.applet_tx.clone(),
-                    match entry.background {
-                        CosmicPanelBackground::ThemeDefault | CosmicPanelBackground::Color(_) => {
-                            self.cur_theme()
-                        },
-                        CosmicPanelBackground::Dark => self.dark_theme.clone(),
-                        CosmicPanelBackground::Light => self.light_theme.clone(),
-                    },
+                    self.panel_theme(&entry),
                     self.s_display.clone().unwrap(),
                     self.security_context_manager.clone(),
                     self.connection.as_ref().unwrap(),
@@ -459,6 +1212,9 @@ impl SpaceContainer {
                     visible,
                     self.loop_handle.clone(),
                 );
+                space.is_preview = self.mode == PanelMode::Preview;
+                space.portal_grants = self.portal_grants.clone();
+                space.applets = self.applets.clone();
                 if let Some(s_display) = self.s_display.as_ref() {
                     space.set_display_handle(s_display.clone());
                 }
@@ -503,6 +1259,20 @@ impl SpaceContainer {
         spaces
     }
 
+    /// Close the popups of the panels other than `panel_id`, which is about to
+    /// open one. Only one panel shows popups at a time, so popups of panels
+    /// sharing a corner don't overlap.
+    pub(crate) fn close_other_popups(&mut self, panel_id: usize) {
+        for space in self.space_list.iter_mut().filter(|s| s.space.id() != panel_id) {
+            if !space.popups.is_empty()
+                || space.overflow_popup.is_some()
+                || space.context_menu.is_some()
+            {
+                space.close_popups(|_| false);
+            }
+        }
+    }
+
     pub fn toggle_overflow_popup(
         &mut self,
         panel_id: usize,
@@ -515,6 +1285,9 @@ impl SpaceContainer {
         seat: (u32, WlSeat),
         force_hide: bool,
     ) {
+        if !force_hide {
+            self.close_other_popups(panel_id);
+        }
         for space in &mut self.space_list {
             if space.space.id() == panel_id {
                 if let Err(err) = space.toggle_overflow_popup(
@@ -534,6 +1307,53 @@ impl SpaceContainer {
         }
     }
 
+    pub fn open_context_menu(
+        &mut self,
+        panel_id: usize,
+        applet: String,
+        section: Side,
+        geo: Rectangle<i32, Logical>,
+        compositor_state: &sctk::compositor::CompositorState,
+        fractional_scale_manager: Option<&FractionalScalingManager>,
+        viewport: Option<&ViewporterState>,
+        qh: &QueueHandle<GlobalState>,
+        xdg_shell_state: &mut sctk::shell::xdg::XdgShell,
+        seat: (u32, WlSeat),
+    ) {
+        self.close_other_popups(panel_id);
+        if let Some(space) = self.space_list.iter_mut().find(|s| s.space.id() == panel_id) {
+            if let Err(err) = space.open_context_menu(
+                applet,
+                section,
+                geo,
+                compositor_state,
+                fractional_scale_manager,
+                viewport,
+                qh,
+                xdg_shell_state,
+                seat,
+            ) {
+                error!("Failed to open the context menu: {}", err);
+            }
+        }
+    }
+
+    /// Handle a pointer event over a context menu, or schedule the menu for a
+    /// right click on an applet. Returns whether the event was consumed.
+    pub fn context_menu_pointer_event(&mut self, seat_name: &str, e: &PointerEvent) -> bool {
+        if self.space_list.iter_mut().any(|s| s.context_menu_pointer_event(e)) {
+            return true;
+        }
+        if let PointerEventKind::Release { button: BTN_RIGHT, .. } = e.kind {
+            if let Some(space) =
+                self.space_list.iter_mut().find(|s| s.panel_surface() == Some(&e.surface))
+            {
+                space.schedule_context_menu(seat_name, e.position.into());
+            }
+        }
+        false
+    }
+
     pub fn iced_request_redraw(&mut self, panel_id: usize) {
         for space in &mut self.space_list {
             if space.space.id() == panel_id {
@@ -543,15 +1363,184 @@ impl SpaceContainer {
         }
     }
 
+    /// Mark the panel with the layer or popup surface `surface` as the target
+    /// of a host drag, or no panel.
+    pub fn set_dragging(&mut self, surface: Option<&WlSurface>) {
+        for space in &mut self.space_list {
+            let dragging = surface.is_some_and(|s| {
+                space.panel_surface() == Some(s)
+                    || space.popups.iter().any(|p| p.popup.c_popup.wl_surface() == s)
+            });
+            if space.dragging && !dragging {
+                // the panel hides after the hide wait
+                space.drag_ended = Some(Instant::now());
+            }
+            space.dragging = dragging;
+        }
+    }
+
+    pub fn update_frozen_applets(&mut self) {
+        for space in &mut self.space_list {
+            space.update_frozen_applets();
+        }
+    }
+
     pub fn update_hidden_applet_frame(&mut self) {
         for space in &mut self.space_list {
             space.update_hidden_applet_frame();
         }
     }
 
+    pub fn tick_builtin_applets(&mut self) {
+        for space in &mut self.space_list {
+            space.tick_builtin_applets();
+        }
+    }
+
     pub fn cleanup(&mut self) {
         for space in &mut self.space_list {
             space.cleanup();
         }
+        // toplevels are sent right after binding, so they are known by now
+        self.drop_restored_maximized();
+    }
+}
+
+/// Resident set size of the process `pid` in bytes, or 0 if it can't be read.
+fn rss(pid: u32) -> u64 {
+    std::fs::read_to_string(format!("/proc/{pid}/status"))
+        .ok()
+        .and_then(|status| {
+            let kb = status.lines().find_map(|l| l.strip_prefix("VmRSS:"))?;
+            kb.trim().trim_end_matches("kB").trim().parse::<u64>().ok()
+        })
+        .map_or(0, |kb| kb * 1024)
+}
+
+pub(crate) fn disconnect_applet(
+    dh: &wayland_server::DisplayHandle,
+    client: &wayland_server::Client,
+    reason: &str,
+) {
+    client.kill(
+        dh,
+        ProtocolError {
+            code: 0,
+            object_id: 0,
+            object_interface: String::new(),
+            message: reason.to_string(),
+        },
+    );
+}
+
+/// Whether the system theme is dark, and the light and dark theme, from their
+/// config or the defaults.
+fn load_themes() -> (bool, theme::CosmicTheme, theme::CosmicTheme) {
+    let is_dark = ThemeMode::config()
+        .ok()
+        .and_then(|c| ThemeMode::get_entry(&c).ok())
+        .unwrap_or_default()
+        .is_dark;
+
+    let light = Theme::light_config()
+        .ok()
+        .and_then(|c| Theme::get_entry(&c).ok())
+        .unwrap_or_else(Theme::light_default);
+    let dark = Theme::dark_config()
+        .ok()
+        .and_then(|c| Theme::get_entry(&c).ok())
+        .unwrap_or_else(Theme::dark_default);
+    (is_dark, light, dark)
+}
+
+/// The zone each panel is arranged in, and the zone of each output left after
+/// all of them, for panels given by their output, the logical size of the
+/// output, their anchor and the exclusive zone they take from their edge.
+fn arrange_panels<O: Clone + PartialEq>(
+    panels: impl IntoIterator<Item = Option<(O, Size<i32, Logical>, PanelAnchor, i32)>>,
+) -> (Vec<Option<Rectangle<i32, Logical>>>, Vec<(O, Rectangle<i32, Logical>)>) {
+    let mut zones: Vec<(O, Rectangle<i32, Logical>)> = Vec::new();
+    let arranged_in = panels
+        .into_iter()
+        .map(|panel| {
+            let (output, size, anchor, exclusive) = panel?;
+            let i = match zones.iter().position(|(o, _)| o == &output) {
+                Some(i) => i,
+                None => {
+                    zones.push((output, Rectangle::from_loc_and_size((0, 0), size)));
+                    zones.len() - 1
+                },
+            };
+            let zone = &mut zones[i].1;
+            let arranged_in = *zone;
+            match anchor {
+                PanelAnchor::Top => {
+                    zone.loc.y += exclusive;
+                    zone.size.h -= exclusive;
+                },
+                PanelAnchor::Bottom => zone.size.h -= exclusive,
+                PanelAnchor::Left => {
+                    zone.loc.x += exclusive;
+                    zone.size.w -= exclusive;
+                },
+                PanelAnchor::Right => zone.size.w -= exclusive,
+            }
+            Some(arranged_in)
+        })
+        .collect();
+    (arranged_in, zones)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    #[test]
+    fn panels_are_arranged_in_the_zone_left_by_earlier_panels() {
+        let size = Size::from((1920, 1080));
+        let (arranged_in, zones) = arrange_panels([
+            Some(("DP-1", size, PanelAnchor::Top, 40)),
+            Some(("DP-1", size, PanelAnchor::Left, 64)),
+            Some(("DP-1", size, PanelAnchor::Bottom, 0)),
+            Some(("DP-1", size, PanelAnchor::Right, 8)),
+        ]);
+        assert_eq!(
+            arranged_in,
+            vec![
+                Some(rect(0, 0, 1920, 1080)),
+                Some(rect(0, 40, 1920, 1040)),
+                Some(rect(64, 40, 1856, 1040)),
+                Some(rect(64, 40, 1856, 1040)),
+            ]
+        );
+        // the panel without an exclusive zone leaves its zone as it is
+        assert_eq!(zones, vec![("DP-1", rect(64, 40, 1848, 1040))]);
+    }
+
+    #[test]
+    fn outputs_have_zones_of_their_own() {
+        let (arranged_in, zones) = arrange_panels([
+            Some(("DP-1", Size::from((1920, 1080)), PanelAnchor::Bottom, 48)),
+            None,
+            Some(("HDMI-A-1", Size::from((1280, 1024)), PanelAnchor::Bottom, 32)),
+            Some(("DP-1", Size::from((1920, 1080)), PanelAnchor::Bottom, 48)),
+        ]);
+        assert_eq!(
+            arranged_in,
+            vec![
+                Some(rect(0, 0, 1920, 1080)),
+                None,
+                Some(rect(0, 0, 1280, 1024)),
+                Some(rect(0, 0, 1920, 1032)),
+            ]
+        );
+        assert_eq!(
+            zones,
+            vec![("DP-1", rect(0, 0, 1920, 984)), ("HDMI-A-1", rect(0, 0, 1280, 992)),]
+        );
     }
 }
@@ -0,0 +1,93 @@
+//! Themes of panels with a theme override in their config.
+//!
+//! The override is applied to the theme builder of the light or dark theme the
+//! panel follows, so derived colors like the hover states of the accent are
+//! rebuilt from it. The builders are loaded once, and again whenever the
+//! theme changes. Applets get the override in the `COSMIC_PANEL_THEME_OVERRIDE`
+//! environment variable and the `theme_override` live config entry, and apply
+//! it to their own theme.
+
+use std::sync::Arc;
+
+use cosmic::cosmic_config::CosmicConfigEntry;
+use cosmic_panel_config::{CosmicPanelBackground, CosmicPanelConfig, PanelThemeOverride};
+use cosmic_theme::{
+    palette::{Srgb, Srgba},
+    ThemeBuilder,
+};
+use tracing::warn;
+
+use super::SpaceContainer;
+
+/// Whether a panel with `background` is dark.
+fn is_dark(background: &CosmicPanelBackground, system_is_dark: bool) -> bool {
+    match background {
+        CosmicPanelBackground::ThemeDefault | CosmicPanelBackground::Color(_) => system_is_dark,
+        CosmicPanelBackground::Dark => true,
+        CosmicPanelBackground::Light => false,
+    }
+}
+
+/// The theme builders of the system the overrides are applied to.
+#[derive(Debug, Clone)]
+pub(crate) struct ThemeBuilders {
+    light: ThemeBuilder,
+    dark: ThemeBuilder,
+}
+
+impl ThemeBuilders {
+    pub(crate) fn load() -> Self {
+        Self { light: load_builder(false), dark: load_builder(true) }
+    }
+}
+
+fn load_builder(is_dark: bool) -> ThemeBuilder {
+    if is_dark { ThemeBuilder::dark_config() } else { ThemeBuilder::light_config() }
+        .ok()
+        .and_then(|c| ThemeBuilder::get_entry(&c).ok())
+        .unwrap_or_else(|| {
+            warn!("Failed to load the theme builder, overriding the default theme");
+            if is_dark {
+                ThemeBuilder::dark()
+            } else {
+                ThemeBuilder::light()
+            }
+        })
+}
+
+/// The theme of a panel with `config`, given the light and dark theme of the
+/// system and their builders.
+pub(crate) fn panel_theme(
+    config: &CosmicPanelConfig,
+    light: &cosmic::Theme,
+    dark: &cosmic::Theme,
+    builders: &ThemeBuilders,
+    system_is_dark: bool,
+) -> cosmic::Theme {
+    let is_dark = is_dark(&config.background, system_is_dark);
+    if config.theme_override.is_empty() {
+        return if is_dark { dark.clone() } else { light.clone() };
+    }
+    let builder = if is_dark { &builders.dark } else { &builders.light };
+    cosmic::Theme::custom(Arc::new(apply_override(builder.clone(), &config.theme_override).build()))
+}
+
+fn apply_override(mut builder: ThemeBuilder, theme_override: &PanelThemeOverride) -> ThemeBuilder {
+    if let Some([r, g, b]) = theme_override.accent {
+        builder.accent = Some(Srgb::new(r, g, b));
+    }
+    if let Some([r, g, b]) = theme_override.background {
+        builder.bg_color = Some(Srgba::new(r, g, b, 1.0));
+    }
+    if let Some([r, g, b]) = theme_override.primary_container {
+        builder.primary_container_bg = Some(Srgba::new(r, g, b, 1.0));
+    }
+    builder
+}
+
+impl SpaceContainer {
+    /// The theme of a panel with `config`.
+    pub(crate) fn panel_theme(&self, config: &CosmicPanelConfig) -> cosmic::Theme {
+        panel_theme(config, &self.light_theme, &self.dark_theme, &self.theme_builders, self.is_dark)
+    }
+}
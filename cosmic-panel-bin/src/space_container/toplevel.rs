@@ -4,19 +4,38 @@ use cctk::{
         toplevel_management::v1::client::zcosmic_toplevel_manager_v1, workspace,
     },
     toplevel_info::ToplevelInfo,
-    wayland_client::{protocol::wl_output::WlOutput, Connection},
+    wayland_client::{protocol::wl_output::WlOutput, Connection, Proxy},
 };
+use std::collections::BTreeSet;
+use wayland_backend::client::ObjectId;
 
-use crate::xdg_shell_wrapper::{
-    client_state::FocusStatus,
-    space::{ToplevelInfoSpace, ToplevelManagerSpace},
+use crate::{
+    space::PanelSpace,
+    xdg_shell_wrapper::{
+        client_state::FocusStatus,
+        space::{ToplevelInfoSpace, ToplevelManagerSpace},
+    },
 };
 use cosmic_panel_config::PanelAnchor;
 use itertools::Itertools;
 use sctk::shell::WaylandSurface;
+use smithay::utils::{Logical, Rectangle, Size};
 
 use super::SpaceContainer;
 
+/// A host toplevel known from wlr-foreign-toplevel-management, when the
+/// compositor doesn't provide the cosmic toplevel info.
+#[derive(Debug, Default, Clone)]
+pub struct FallbackToplevel {
+    pub app_id: String,
+    pub title: String,
+    pub outputs: Vec<WlOutput>,
+    pub maximized: bool,
+    pub minimized: bool,
+    pub fullscreen: bool,
+    pub activated: bool,
+}
+
 impl ToplevelInfoSpace for SpaceContainer {
     /// A new toplevel was created
     fn new_toplevel(
@@ -99,17 +118,7 @@ impl SpaceContainer {
     ) {
         let pre_maximixed_outputs = self.maximized_outputs();
         self.maximized_toplevels.push((toplevel.clone(), info.clone()));
-        let post_maximized_outputs = self.maximized_outputs();
-        let outputs = self.outputs.clone();
-        for (o, ..) in &outputs {
-            let max_pre = pre_maximixed_outputs.iter().contains(o);
-            let max_post = post_maximized_outputs.iter().contains(o);
-            if max_post && !max_pre {
-                self.apply_maximized(o, true);
-            } else if !max_post && max_pre {
-                self.apply_maximized(o, false);
-            }
-        }
+        self.apply_maximized_outputs(pre_maximixed_outputs);
     }
 
     fn remove_maximized(&mut self, toplevel: &zcosmic_toplevel_handle_v1::ZcosmicToplevelHandleV1) {
@@ -119,6 +128,133 @@ impl SpaceContainer {
         } else {
             return;
         };
+        self.apply_maximized_outputs(pre_maximixed_outputs);
+        self.apply_toplevel_changes();
+    }
+
+    /// Add, update or remove (`None`) a toplevel of the
+    /// wlr-foreign-toplevel-management fallback.
+    pub(crate) fn update_fallback_toplevel(
+        &mut self,
+        id: ObjectId,
+        toplevel: Option<FallbackToplevel>,
+    ) {
+        let pre_maximixed_outputs = self.maximized_outputs();
+        match toplevel {
+            Some(toplevel) => {
+                self.fallback_toplevels.insert(id, toplevel);
+            },
+            None => {
+                self.fallback_toplevels.remove(&id);
+            },
+        }
+        self.apply_maximized_outputs(pre_maximixed_outputs);
+        self.apply_toplevel_changes();
+    }
+
+    /// App ids of the toplevels overlapping the panel `space`.
+    pub(crate) fn overlapping_app_ids(&self, space: &PanelSpace) -> BTreeSet<String> {
+        space.toplevel_overlaps.iter().filter_map(|id| self.toplevel_app_id(id)).collect()
+    }
+
+    /// App id of a toplevel, by the id of a handle of any of the toplevel
+    /// protocols.
+    pub(crate) fn toplevel_app_id(&self, id: &ObjectId) -> Option<String> {
+        self.overlap_app_ids
+            .get(id)
+            .cloned()
+            .or_else(|| {
+                self.toplevels.iter().find(|(t, _)| &t.id() == id).map(|(_, i)| i.app_id.clone())
+            })
+            .or_else(|| self.fallback_toplevels.get(id).map(|t| t.app_id.clone()))
+    }
+
+    /// App ids of the toplevels shown on `output`.
+    pub(super) fn shown_app_ids<'a>(
+        &'a self,
+        output: &'a WlOutput,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        self.toplevels
+            .iter()
+            .filter(|(_, info)| info.output.contains(output) && self.is_shown(info))
+            .map(|(_, info)| info.app_id.as_str())
+            .chain(
+                self.fallback_toplevels
+                    .values()
+                    .filter(|t| !t.minimized && t.outputs.contains(output))
+                    .map(|t| t.app_id.as_str()),
+            )
+    }
+
+    /// Decide for each panel with autohide whether toplevels hide it.
+    /// Overlapping toplevels of ignored app ids don't, and toplevels of forced
+    /// app ids do, as long as they are shown on the output of the panel.
+    /// Toplevels of which the app id isn't known yet count as overlapping.
+    pub(crate) fn update_intellihide(&mut self) {
+        let hidden: Vec<bool> = self
+            .space_list
+            .iter()
+            .map(|space| {
+                let Some(autohide) = space.config.autohide.as_ref() else {
+                    return false;
+                };
+                let overlapped = space.toplevel_overlaps.iter().any(|id| {
+                    self.toplevel_app_id(id)
+                        .map_or(true, |a| !autohide.ignored_app_ids.contains(&a))
+                });
+                let forced = !autohide.forced_app_ids.is_empty()
+                    && space.output.as_ref().is_some_and(|(o, ..)| {
+                        self.shown_app_ids(o)
+                            .any(|a| autohide.forced_app_ids.iter().any(|f| f == a))
+                    });
+                overlapped || forced
+            })
+            .collect();
+        for (space, hidden) in self.space_list.iter_mut().zip(hidden) {
+            space.hidden_by_toplevels = hidden;
+        }
+    }
+
+    /// The outputs of the active toplevel if it is fullscreen, as for
+    /// presentations.
+    pub(crate) fn active_fullscreen_outputs(&self) -> Vec<WlOutput> {
+        let toplevels = self
+            .toplevels
+            .iter()
+            .filter(|(_, info)| {
+                info.state.contains(&zcosmic_toplevel_handle_v1::State::Activated)
+                    && info.state.contains(&zcosmic_toplevel_handle_v1::State::Fullscreen)
+                    && self.is_shown(info)
+            })
+            .flat_map(|(_, info)| info.output.iter().cloned());
+        let fallback = self
+            .fallback_toplevels
+            .values()
+            .filter(|t| t.activated && t.fullscreen && !t.minimized)
+            .flat_map(|t| t.outputs.iter().cloned());
+        toplevels.chain(fallback).collect()
+    }
+
+    /// Whether a fullscreen toplevel is shown on `output`.
+    pub(crate) fn output_has_fullscreen_toplevel(&self, output: &WlOutput) -> bool {
+        self.toplevels.iter().any(|(_, info)| {
+            info.state.contains(&zcosmic_toplevel_handle_v1::State::Fullscreen)
+                && info.output.contains(output)
+                && self.is_shown(info)
+        }) || self
+            .fallback_toplevels
+            .values()
+            .any(|t| t.fullscreen && !t.minimized && t.outputs.contains(output))
+    }
+
+    /// Whether a fallback toplevel which isn't minimized is on `output`.
+    pub(crate) fn output_has_fallback_toplevel(&self, output: &WlOutput) -> bool {
+        self.fallback_toplevels.values().any(|t| !t.minimized && t.outputs.contains(output))
+    }
+
+    /// Update the panels of outputs which got or lost a maximized toplevel
+    /// since `pre_maximixed_outputs`.
+    pub(crate) fn apply_maximized_outputs(&mut self, pre_maximixed_outputs: Vec<WlOutput>) {
         let post_maximized_outputs = self.maximized_outputs();
         let outputs = self.outputs.clone();
         for (o, ..) in &outputs {
@@ -130,7 +266,6 @@ impl SpaceContainer {
                 self.apply_maximized(o, false);
             }
         }
-        self.apply_toplevel_changes();
     }
 
     pub(crate) fn apply_maximized(&mut self, output: &WlOutput, maximized: bool) {
@@ -156,19 +291,25 @@ impl SpaceContainer {
         }
     }
 
+    /// Whether the toplevel is on an active workspace and not minimized.
+    fn is_shown(&self, info: &ToplevelInfo) -> bool {
+        !info.state.contains(&zcosmic_toplevel_handle_v1::State::Minimized)
+            && self.workspace_groups.iter().any(|g| {
+                g.workspaces.iter().any(|w| {
+                    w.state.contains(&cctk::wayland_client::WEnum::Value(
+                        workspace::v1::client::zcosmic_workspace_handle_v1::State::Active,
+                    )) && info.workspace.contains(&w.handle)
+                })
+            })
+    }
+
     pub(crate) fn apply_toplevel_changes(&mut self) {
         for output in self.outputs.iter().map(|o| (o.0.clone(), o.1.name())).collect::<Vec<_>>() {
-            let has_toplevel = self.toplevels.iter().any(|(_, info)| {
-                info.output.contains(&output.0)
-                    && !info.state.contains(&zcosmic_toplevel_handle_v1::State::Minimized)
-                    && self.workspace_groups.iter().any(|g| {
-                        g.workspaces.iter().any(|w| {
-                            w.state.contains(&cctk::wayland_client::WEnum::Value(
-                                workspace::v1::client::zcosmic_workspace_handle_v1::State::Active,
-                            )) && info.workspace.contains(&w.handle)
-                        })
-                    })
-            });
+            let has_toplevel = self
+                .toplevels
+                .iter()
+                .any(|(_, info)| info.output.contains(&output.0) && self.is_shown(info))
+                || self.output_has_fallback_toplevel(&output.0);
 
             let name = output.1;
             for anchor in
@@ -192,6 +333,63 @@ impl SpaceContainer {
                 }
             }
         }
+        self.update_geometry_overlaps();
+    }
+
+    /// Approximate the toplevels overlapping each panel when the host has no
+    /// overlap notify, so intellihide keeps working. The host doesn't send
+    /// toplevel geometry, so fullscreen toplevels are taken to cover their
+    /// whole output, and maximized toplevels the zone the exclusive zones of
+    /// the panels leave on it.
+    fn update_geometry_overlaps(&mut self) {
+        if !self.geometry_overlaps {
+            return;
+        }
+        // the toplevels with the outputs they are on, and whether they are
+        // fullscreen
+        let covering: Vec<(ObjectId, Vec<WlOutput>, bool)> = self
+            .toplevels
+            .iter()
+            .filter(|(_, info)| {
+                self.is_shown(info)
+                    && (info.state.contains(&zcosmic_toplevel_handle_v1::State::Maximized)
+                        || info.state.contains(&zcosmic_toplevel_handle_v1::State::Fullscreen))
+            })
+            .map(|(t, info)| {
+                let fullscreen =
+                    info.state.contains(&zcosmic_toplevel_handle_v1::State::Fullscreen);
+                (t.id(), info.output.iter().cloned().collect(), fullscreen)
+            })
+            .chain(
+                self.fallback_toplevels
+                    .iter()
+                    .filter(|(_, t)| !t.minimized && (t.maximized || t.fullscreen))
+                    .map(|(id, t)| (id.clone(), t.outputs.clone(), t.fullscreen)),
+            )
+            .collect();
+        let work_areas = self.work_areas();
+
+        for space in &mut self.space_list {
+            space.overlap_fallback = true;
+            let (Some((wl_output, _, info)), Some(panel_loc)) =
+                (space.output.as_ref(), space.output_location())
+            else {
+                continue;
+            };
+            let output_size: Size<i32, Logical> = info.logical_size.unwrap_or_default().into();
+            let output_rect = Rectangle::from_loc_and_size((0, 0), output_size);
+            let work_area =
+                work_areas.iter().find(|(o, _)| o == wl_output).map_or(output_rect, |(_, z)| *z);
+            let panel_rect = Rectangle::from_loc_and_size(panel_loc, space.dimensions);
+            space.toplevel_overlaps = covering
+                .iter()
+                .filter(|(_, outputs, fullscreen)| {
+                    let toplevel_rect = if *fullscreen { output_rect } else { work_area };
+                    outputs.contains(wl_output) && toplevel_rect.overlaps(panel_rect)
+                })
+                .map(|(id, ..)| id.clone())
+                .collect();
+        }
     }
 
     pub(crate) fn maximized_outputs(&self) -> Vec<WlOutput> {
@@ -212,6 +410,18 @@ impl SpaceContainer {
                 }
             })
             .flatten()
+            .chain(
+                self.fallback_toplevels
+                    .values()
+                    .filter(|t| t.maximized && !t.minimized)
+                    .flat_map(|t| t.outputs.iter().cloned()),
+            )
+            .chain(
+                self.outputs
+                    .iter()
+                    .filter(|(_, o, _)| self.restored_maximized.contains(&o.name()))
+                    .map(|(wl_output, ..)| wl_output.clone()),
+            )
             .collect()
     }
 }
@@ -0,0 +1,117 @@
+//! Visibility overrides of panels set over the control interface, e.g. by a
+//! shortcut, to keep a dock shown during a task without changing its config.
+//!
+//! An override keeps a panel revealed like a rule which shows it, or hides it
+//! like a rule which hides it, before autohide, hover and toplevels are
+//! considered. Overrides are kept by panel name, so they apply to panels
+//! created later as well, and across restarts with the runtime state.
+//!
+//! The toggle shortcut reveals autohidden panels with an override, and lets
+//! them take keyboard focus on demand until it hides them again, which
+//! restores the override they had before. Only that override is kept across
+//! restarts.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::SpaceContainer;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VisibilityOverride {
+    /// the panel follows its config
+    #[default]
+    Auto,
+    ForceVisible,
+    ForceHidden,
+}
+
+impl FromStr for VisibilityOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "visible" => Ok(Self::ForceVisible),
+            "hidden" => Ok(Self::ForceHidden),
+            _ => Err(format!("Unknown visibility override {s}, expected auto, visible or hidden")),
+        }
+    }
+}
+
+impl fmt::Display for VisibilityOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::ForceVisible => write!(f, "visible"),
+            Self::ForceHidden => write!(f, "hidden"),
+        }
+    }
+}
+
+impl SpaceContainer {
+    /// Set the visibility override of the panel `panel`, or of every panel if
+    /// it is empty. Returns `false` if there is no such panel.
+    pub fn set_visibility_override(&mut self, panel: &str, o: VisibilityOverride) -> bool {
+        let names: Vec<String> = self
+            .config
+            .config_list
+            .iter()
+            .filter(|c| panel.is_empty() || c.name == panel)
+            .map(|c| c.name.clone())
+            .collect();
+        if names.is_empty() {
+            return false;
+        }
+        for name in names {
+            self.shortcut_revealed.remove(&name);
+            if o == VisibilityOverride::Auto {
+                self.visibility_overrides.remove(&name);
+            } else {
+                self.visibility_overrides.insert(name, o);
+            }
+        }
+        true
+    }
+
+    /// Reveal the panel `panel`, or every panel with autohide if it is empty,
+    /// or restore its previous override if the toggle shortcut revealed it.
+    /// Returns `false` if there is no such panel.
+    pub fn toggle_reveal(&mut self, panel: &str) -> bool {
+        let names: Vec<String> = self
+            .config
+            .config_list
+            .iter()
+            .filter(|c| if panel.is_empty() { c.autohide.is_some() } else { c.name == panel })
+            .map(|c| c.name.clone())
+            .collect();
+        if names.is_empty() {
+            return false;
+        }
+        let revealed = names.iter().any(|n| self.shortcut_revealed.contains_key(n));
+        for name in names {
+            if revealed {
+                match self.shortcut_revealed.remove(&name) {
+                    Some(VisibilityOverride::Auto) => {
+                        self.visibility_overrides.remove(&name);
+                    },
+                    Some(previous) => {
+                        self.visibility_overrides.insert(name, previous);
+                    },
+                    // panels which weren't revealed keep their override
+                    None => {},
+                }
+            } else {
+                let previous = self.visibility_override(&name);
+                self.shortcut_revealed.insert(name.clone(), previous);
+                self.visibility_overrides.insert(name, VisibilityOverride::ForceVisible);
+            }
+        }
+        true
+    }
+
+    /// The visibility override of the panel `name`.
+    pub(crate) fn visibility_override(&self, name: &str) -> VisibilityOverride {
+        self.visibility_overrides.get(name).copied().unwrap_or_default()
+    }
+}
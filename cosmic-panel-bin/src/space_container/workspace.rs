@@ -1,4 +1,13 @@
 use crate::xdg_shell_wrapper::space::WorkspaceHandlerSpace;
+use cctk::{
+    cosmic_protocols::workspace::v1::client::zcosmic_workspace_handle_v1::{
+        self, ZcosmicWorkspaceHandleV1,
+    },
+    wayland_client::{
+        protocol::{wl_output::WlOutput, wl_surface::WlSurface},
+        WEnum,
+    },
+};
 use itertools::Itertools;
 
 use super::SpaceContainer;
@@ -8,6 +17,7 @@ impl WorkspaceHandlerSpace for SpaceContainer {
         // detect workspace changes
         // for now this is limited to changes
         // to / from workspaces with maximized toplevels
+        self.drop_restored_maximized();
         let pre_maximixed_outputs = self.maximized_outputs();
         self.workspace_groups = groups.to_vec();
         let post_maximized_outputs = self.maximized_outputs();
@@ -24,3 +34,49 @@ impl WorkspaceHandlerSpace for SpaceContainer {
         self.apply_toplevel_changes()
     }
 }
+
+impl SpaceContainer {
+    /// Start a swipe on the panel of `surface`.
+    pub(crate) fn swipe_begin(&mut self, surface: &WlSurface) {
+        if let Some(space) =
+            self.space_list.iter_mut().find(|s| !s.suppressed && s.panel_surface() == Some(surface))
+        {
+            space.swipe_begin();
+        }
+    }
+
+    pub(crate) fn swiping(&self) -> bool {
+        self.space_list.iter().any(|s| s.swiping())
+    }
+
+    pub(crate) fn swipe_update(&mut self, dx: f64) {
+        for space in self.space_list.iter_mut().filter(|s| s.swiping()) {
+            space.swipe_update(dx);
+        }
+    }
+
+    /// End the swipe. Returns the output of its panel, and whether it switches
+    /// to the next workspace, if it was complete.
+    pub(crate) fn swipe_end(&mut self, cancelled: bool) -> Option<(WlOutput, bool)> {
+        let space = self.space_list.iter_mut().find(|s| s.swiping())?;
+        let forward = space.swipe_end(cancelled)?;
+        Some((space.output.as_ref()?.0.clone(), forward))
+    }
+
+    /// The workspace after the active one of `output` if `forward`, or before
+    /// it.
+    pub(crate) fn neighbor_workspace(
+        &self,
+        output: &WlOutput,
+        forward: bool,
+    ) -> Option<ZcosmicWorkspaceHandleV1> {
+        let group = self.workspace_groups.iter().find(|g| g.outputs.contains(output))?;
+        let workspaces: Vec<_> =
+            group.workspaces.iter().sorted_by(|a, b| a.coordinates.cmp(&b.coordinates)).collect();
+        let active = workspaces.iter().position(|w| {
+            w.state.contains(&WEnum::Value(zcosmic_workspace_handle_v1::State::Active))
+        })?;
+        let i = if forward { active + 1 } else { active.checked_sub(1)? };
+        workspaces.get(i).map(|w| w.handle.clone())
+    }
+}
@@ -11,14 +11,17 @@ use crate::{
         client_state::{ClientFocus, FocusStatus},
         server_state::ServerPointerFocus,
         shared_state::GlobalState,
-        space::{Visibility, WrapperSpace},
+        space::{
+            ClientHost, InputRouter, OutputHandler, PopupHost, RootSpace, Visibility, WrapperSpace,
+        },
         wp_fractional_scaling::FractionalScalingManager,
         wp_security_context::SecurityContextManager,
         wp_viewporter::ViewporterState,
     },
+    PanelMode,
 };
 use cctk::{cosmic_protocols::overlap_notify, wayland_client::protocol::wl_pointer::WlPointer};
-use cosmic_panel_config::{CosmicPanelBackground, CosmicPanelContainerConfig, CosmicPanelOuput};
+use cosmic_panel_config::{CosmicPanelContainerConfig, CosmicPanelOuput};
 use itertools::Itertools;
 use sctk::{
     compositor::CompositorState,
@@ -38,29 +41,15 @@ use smithay::{
     output::Output,
     reexports::wayland_server::{self, protocol::wl_surface, Resource},
 };
+use tracing::error;
 
 use crate::space::PanelSpace;
 
-use super::SpaceContainer;
+use super::{theme_override::panel_theme, SpaceContainer};
 
 impl WrapperSpace for SpaceContainer {
     type Config = CosmicPanelContainerConfig;
 
-    /// set the display handle of the space
-    fn set_display_handle(&mut self, display: wayland_server::DisplayHandle) {
-        self.s_display.replace(display);
-    }
-
-    /// get the client hovered surface of the space
-    fn get_client_hovered_surface(&self) -> Rc<RefCell<ClientFocus>> {
-        self.c_hovered_surface.clone()
-    }
-
-    /// get the client focused surface of the space
-    fn get_client_focused_surface(&self) -> Rc<RefCell<ClientFocus>> {
-        self.c_focused_surface.clone()
-    }
-
     /// run after the connection is ready
     fn setup(
         &mut self,
@@ -73,6 +62,9 @@ impl WrapperSpace for SpaceContainer {
         qh: &QueueHandle<GlobalState>,
         overlap_notify: Option<OverlapNotifyV1>,
     ) {
+        // applets of the greeter don't get access to the host display
+        let security_context_manager =
+            security_context_manager.filter(|_| self.mode != PanelMode::Greeter);
         self.overlap_notify = overlap_notify.clone();
         self.connection = Some(conn.clone());
         self.security_context_manager = security_context_manager.clone();
@@ -91,12 +83,7 @@ impl WrapperSpace for SpaceContainer {
                             self.c_focused_surface.clone(),
                             self.c_hovered_surface.clone(),
                             self.applet_tx.clone(),
-                            match config.background {
-                                CosmicPanelBackground::ThemeDefault
-                                | CosmicPanelBackground::Color(_) => self.cur_theme(),
-                                CosmicPanelBackground::Dark => self.dark_theme.clone(),
-                                CosmicPanelBackground::Light => self.light_theme.clone(),
-                            },
+                            self.panel_theme(config),
                             self.s_display.clone().unwrap(),
                             self.security_context_manager.clone(),
                             conn,
@@ -108,6 +95,9 @@ impl WrapperSpace for SpaceContainer {
                             },
                             self.loop_handle.clone(),
                         );
+                        s.is_preview = self.mode == PanelMode::Preview;
+                        s.portal_grants = self.portal_grants.clone();
+                        s.applets = self.applets.clone();
                         s.setup(
                             compositor_state,
                             fractional_scale_manager,
@@ -141,6 +131,254 @@ impl WrapperSpace for SpaceContainer {
         );
     }
 
+    fn config(&self) -> Self::Config {
+        self.config.clone()
+    }
+
+    fn visibility(&self) -> Visibility {
+        let visible = self.space_list.iter().any(|s| {
+            self.c_hovered_surface
+                .borrow()
+                .iter()
+                .any(|f| matches!(f.2, FocusStatus::Focused))
+                // transitions should try to be smooth
+                || !matches!(s.visibility, Visibility::Visible | Visibility::Hidden)
+                || s.animate_state.is_some()
+                || !s.popups.is_empty()
+                || s.context_menu.is_some()
+        }) || !self.osds.is_empty();
+
+        if visible {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        }
+    }
+
+    fn configure_layer(&mut self, layer: &LayerSurface, configure: LayerSurfaceConfigure) {
+        if let Some(osd) = self.osds.iter_mut().find(|o| o.has_surface(layer.wl_surface())) {
+            osd.configure_layer(layer, configure);
+            return;
+        }
+        if let Some(space) = self
+            .space_list
+            .iter_mut()
+            .find(|s| s.layer.as_ref().map(|s| s.wl_surface()) == Some(layer.wl_surface()))
+        {
+            space.configure_panel_layer(layer, configure, &mut self.renderer);
+            if matches!(space.visibility(), Visibility::Visible) || !space.output_has_toplevel {
+                space.output.as_ref().map(|o| (o.1.name(), space.config.anchor));
+            }
+        }
+        self.apply_toplevel_changes()
+    }
+
+    fn close_layer(&mut self, layer: &LayerSurface) {
+        if let Some(osd) = self.osds.iter_mut().find(|o| o.has_surface(layer.wl_surface())) {
+            osd.close_layer(layer);
+            self.osds.retain(|o| !o.is_closed());
+            return;
+        }
+        self.space_list
+            .retain(|s| s.layer.as_ref().map(|s| s.wl_surface()) != Some(layer.wl_surface()));
+    }
+
+    fn frame(&mut self, surface: &c_wl_surface::WlSurface, time: u32) {
+        for s in self.space_list.iter_mut() {
+            s.frame(surface, time);
+        }
+        for osd in self.osds.iter_mut() {
+            osd.frame(surface, time);
+        }
+    }
+}
+
+impl RootSpace for SpaceContainer {
+    fn handle_events(
+        &mut self,
+        dh: &smithay::reexports::wayland_server::DisplayHandle,
+        qh: &QueueHandle<GlobalState>,
+        popup_manager: &mut PopupManager,
+        time: u32,
+        throttle: Option<Duration>,
+    ) -> std::time::Instant {
+        let output_scales = self.output_scales();
+        let output_zones = self.output_zones();
+        for (s, zone) in self.space_list.iter_mut().zip(output_zones) {
+            s.output_scales.clone_from(&output_scales);
+            s.output_zone = zone;
+        }
+        let last_dirtied = self
+            .space_list
+            .iter_mut()
+            .fold(None, |mut acc, s| {
+                let last_dirtied =
+                    s.handle_events(dh, popup_manager, time, throttle, self.renderer.as_mut(), qh);
+                if let Some(last_dirty) = acc {
+                    if last_dirty < last_dirtied {
+                        acc = Some(last_dirtied);
+                    }
+                } else {
+                    acc = Some(last_dirtied);
+                }
+                acc
+            })
+            .unwrap_or_else(Instant::now);
+        if let Some(renderer) = self.renderer.as_mut() {
+            for osd in &mut self.osds {
+                if let Err(err) = osd.render(renderer, qh) {
+                    error!("Failed to render the OSD, error: {:?}", err);
+                }
+            }
+        }
+        last_dirtied
+    }
+
+    fn renderer(&mut self) -> Option<&mut smithay::backend::renderer::gles::GlesRenderer> {
+        self.renderer.as_mut()
+    }
+}
+
+impl ClientHost for SpaceContainer {
+    /// set the display handle of the space
+    fn set_display_handle(&mut self, display: wayland_server::DisplayHandle) {
+        self.s_display.replace(display);
+    }
+
+    fn add_window(&mut self, s_top_level: smithay::desktop::Window) {
+        // add window to the space with a client that matches the window
+        let w_client = s_top_level.toplevel().and_then(|t| t.wl_surface().client().map(|c| c.id()));
+
+        if let Some(space) =
+            w_client.and_then(|c| self.applet_space(&c)).and_then(|i| self.space_list.get_mut(i))
+        {
+            space.add_window(s_top_level);
+        }
+    }
+
+    fn spawn_clients(
+        &mut self,
+        _display: smithay::reexports::wayland_server::DisplayHandle,
+        _qh: &QueueHandle<GlobalState>,
+        _: Option<SecurityContextManager>,
+    ) -> anyhow::Result<()> {
+        // spaces spawn their clients when they are created
+        Ok(())
+    }
+
+    fn dirty_window(
+        &mut self,
+        dh: &smithay::reexports::wayland_server::DisplayHandle,
+        w: &smithay::reexports::wayland_server::protocol::wl_surface::WlSurface,
+    ) {
+        // add window to the space with a client that matches the window
+        let w_client = w.client().map(|c| c.id());
+
+        if let Some(space) =
+            w_client.and_then(|c| self.applet_space(&c)).and_then(|i| self.space_list.get_mut(i))
+        {
+            space.dirty_window(dh, w);
+        }
+    }
+
+    fn raise_window(&mut self, _: &smithay::desktop::Window, _: bool) {}
+}
+
+impl PopupHost for SpaceContainer {
+    fn add_popup(
+        &mut self,
+        compositor_state: &CompositorState,
+        fractional_scale_manager: Option<&FractionalScalingManager>,
+        viewport: Option<&ViewporterState>,
+        conn: &Connection,
+        qh: &QueueHandle<GlobalState>,
+        xdg_shell_state: &mut sctk::shell::xdg::XdgShell,
+        s_surface: smithay::wayland::shell::xdg::PopupSurface,
+        positioner: sctk::shell::xdg::XdgPositioner,
+        positioner_state: smithay::wayland::shell::xdg::PositionerState,
+    ) -> anyhow::Result<()> {
+        // add popup to the space with a client that matches the window
+        let p_client = s_surface.wl_surface().client().map(|c| c.id());
+
+        if let Some(i) = p_client.and_then(|c| self.applet_space(&c)) {
+            let panel_id = self.space_list[i].space.id();
+            self.close_other_popups(panel_id);
+            self.space_list[i].add_popup(
+                compositor_state,
+                fractional_scale_manager,
+                viewport,
+                conn,
+                qh,
+                xdg_shell_state,
+                s_surface,
+                positioner,
+                positioner_state,
+            )
+        } else {
+            anyhow::bail!("failed to find a matching panel space for this popup.")
+        }
+    }
+
+    fn reposition_popup(
+        &mut self,
+        popup: smithay::wayland::shell::xdg::PopupSurface,
+        positioner_state: smithay::wayland::shell::xdg::PositionerState,
+        token: u32,
+    ) -> anyhow::Result<()> {
+        // add popup to the space with a client that matches the window
+        let p_client = popup.wl_surface().client().map(|c| c.id());
+
+        if let Some(space) =
+            p_client.and_then(|c| self.applet_space(&c)).and_then(|i| self.space_list.get_mut(i))
+        {
+            space.reposition_popup(popup, positioner_state, token)?
+        }
+        anyhow::bail!("Failed to find popup with matching client id")
+    }
+
+    fn dirty_popup(
+        &mut self,
+        dh: &smithay::reexports::wayland_server::DisplayHandle,
+        w: &smithay::reexports::wayland_server::protocol::wl_surface::WlSurface,
+    ) {
+        // add window to the space with a client that matches the window
+        let p_client = w.client().map(|c| c.id());
+
+        if let Some(space) =
+            p_client.and_then(|c| self.applet_space(&c)).and_then(|i| self.space_list.get_mut(i))
+        {
+            space.dirty_popup(dh, w);
+        }
+    }
+
+    fn configure_popup(
+        &mut self,
+        popup: &sctk::shell::xdg::popup::Popup,
+        config: sctk::shell::xdg::popup::PopupConfigure,
+    ) {
+        for space in &mut self.space_list {
+            space.configure_panel_popup(popup, config.clone(), self.renderer.as_mut());
+        }
+    }
+
+    fn close_popup(&mut self, popup: &sctk::shell::xdg::popup::Popup) {
+        if let Some(space) = self
+            .space_list
+            .iter_mut()
+            .find(|s| s.popups.iter().any(|p| p.popup.c_popup.wl_surface() == popup.wl_surface()))
+        {
+            space.close_popup(popup);
+        } else if let Some(space) = self.space_list.iter_mut().find(|s| {
+            s.context_menu
+                .as_ref()
+                .is_some_and(|m| m.popup.c_popup.wl_surface() == popup.wl_surface())
+        }) {
+            space.close_context_menu();
+        }
+    }
+}
+
+impl OutputHandler for SpaceContainer {
     fn new_output(
         &mut self,
         compositor_state: &sctk::compositor::CompositorState,
@@ -173,10 +411,15 @@ impl WrapperSpace for SpaceContainer {
             None => anyhow::bail!("Output missing name"),
         };
         self.outputs.push((c_output.clone(), s_output.clone(), output_info.clone()));
+        // a preview is only shown for the first output
+        if self.mode == PanelMode::Preview && !self.space_list.is_empty() {
+            return Ok(());
+        }
 
-        let cur = self.cur_theme();
+        let is_dark = self.is_dark;
         let dark = self.dark_theme.clone();
         let light = self.light_theme.clone();
+        let builders = self.theme_builders.clone();
         // TODO error handling
         // create the spaces that are configured to use this output, including spaces
         // configured for All
@@ -192,12 +435,7 @@ impl WrapperSpace for SpaceContainer {
                 };
                 match &config.output {
                     CosmicPanelOuput::All => {
-                        let c = match config.background {
-                            CosmicPanelBackground::ThemeDefault
-                            | CosmicPanelBackground::Color(_) => cur.clone(),
-                            CosmicPanelBackground::Dark => dark.clone(),
-                            CosmicPanelBackground::Light => light.clone(),
-                        };
+                        let c = panel_theme(config, &light, &dark, &builders, is_dark);
                         let mut s = if let Some(s) = self.space_list.iter_mut().position(|s| {
                             s.config.name == config.name
                                 && Some(&c_output) == s.output.as_ref().map(|o| &o.0)
@@ -217,6 +455,9 @@ impl WrapperSpace for SpaceContainer {
                                 visible,
                                 self.loop_handle.clone(),
                             );
+                            s.is_preview = self.mode == PanelMode::Preview;
+                            s.portal_grants = self.portal_grants.clone();
+                            s.applets = self.applets.clone();
                             s.setup(
                                 compositor_state,
                                 fractional_scale_manager,
@@ -262,12 +503,7 @@ impl WrapperSpace for SpaceContainer {
                                 self.c_focused_surface.clone(),
                                 self.c_hovered_surface.clone(),
                                 self.applet_tx.clone(),
-                                match config.background {
-                                    CosmicPanelBackground::ThemeDefault
-                                    | CosmicPanelBackground::Color(_) => cur.clone(),
-                                    CosmicPanelBackground::Dark => dark.clone(),
-                                    CosmicPanelBackground::Light => light.clone(),
-                                },
+                                panel_theme(config, &light, &dark, &builders, is_dark),
                                 self.s_display.clone().unwrap(),
                                 self.security_context_manager.clone(),
                                 conn,
@@ -275,6 +511,9 @@ impl WrapperSpace for SpaceContainer {
                                 visible,
                                 self.loop_handle.clone(),
                             );
+                            s.is_preview = self.mode == PanelMode::Preview;
+                            s.portal_grants = self.portal_grants.clone();
+                            s.applets = self.applets.clone();
 
                             if let Some(s_display) = self.s_display.as_ref() {
                                 s.set_display_handle(s_display.clone());
@@ -316,180 +555,87 @@ impl WrapperSpace for SpaceContainer {
         Ok(())
     }
 
-    fn add_window(&mut self, s_top_level: smithay::desktop::Window) {
-        // add window to the space with a client that matches the window
-        let w_client = s_top_level.toplevel().and_then(|t| t.wl_surface().client().map(|c| c.id()));
-
-        if let Some(space) = self.space_list.iter_mut().find(|space| {
-            space
-                .clients_center
-                .lock()
-                .unwrap()
-                .iter()
-                .chain(space.clients_left.lock().unwrap().iter())
-                .chain(space.clients_right.lock().unwrap().iter())
-                .any(|c| Some(c.client.id()) == w_client)
-        }) {
-            space.add_window(s_top_level);
-        }
-    }
-
-    fn add_popup(
+    fn output_leave(
         &mut self,
-        compositor_state: &CompositorState,
-        fractional_scale_manager: Option<&FractionalScalingManager>,
-        viewport: Option<&ViewporterState>,
-        conn: &Connection,
-        qh: &QueueHandle<GlobalState>,
-        xdg_shell_state: &mut sctk::shell::xdg::XdgShell,
-        s_surface: smithay::wayland::shell::xdg::PopupSurface,
-        positioner: sctk::shell::xdg::XdgPositioner,
-        positioner_state: smithay::wayland::shell::xdg::PositionerState,
+        c_output: sctk::reexports::client::protocol::wl_output::WlOutput,
+        s_output: Output,
     ) -> anyhow::Result<()> {
-        // add popup to the space with a client that matches the window
-        let p_client = s_surface.wl_surface().client().map(|c| c.id());
-
-        if let Some(space) = self.space_list.iter_mut().find(|space| {
-            space
-                .clients_center
-                .lock()
-                .unwrap()
-                .iter()
-                .chain(space.clients_left.lock().unwrap().iter())
-                .chain(space.clients_right.lock().unwrap().iter())
-                .any(|c| Some(c.client.id()) == p_client)
-        }) {
-            space.add_popup(
-                compositor_state,
-                fractional_scale_manager,
-                viewport,
-                conn,
-                qh,
-                xdg_shell_state,
-                s_surface,
-                positioner,
-                positioner_state,
-            )
-        } else {
-            anyhow::bail!("failed to find a matching panel space for this popup.")
+        self.outputs.retain(|o| o.0 != c_output);
+        self.space_list.retain(|s| s.output.as_ref().map(|o| &o.0) != Some(&c_output));
+        for osd in &mut self.osds {
+            osd.output_leave(c_output.clone(), s_output.clone())?;
         }
+        self.osds.retain(|o| !o.is_closed());
+        Ok(())
     }
 
-    fn reposition_popup(
+    fn update_output(
         &mut self,
-        popup: smithay::wayland::shell::xdg::PopupSurface,
-        positioner_state: smithay::wayland::shell::xdg::PositionerState,
-        token: u32,
-    ) -> anyhow::Result<()> {
-        // add popup to the space with a client that matches the window
-        let p_client = popup.wl_surface().client().map(|c| c.id());
-
-        if let Some(space) = self.space_list.iter_mut().find(|space| {
-            space
-                .clients_center
-                .lock()
-                .unwrap()
-                .iter()
-                .chain(space.clients_left.lock().unwrap().iter())
-                .chain(space.clients_right.lock().unwrap().iter())
-                .any(|c| Some(c.client.id()) == p_client)
-        }) {
-            space.reposition_popup(popup, positioner_state, token)?
+        c_output: WlOutput,
+        s_output: Output,
+        info: OutputInfo,
+    ) -> anyhow::Result<bool> {
+        self.outputs.retain(|o| o.0 != c_output);
+        self.outputs.push((c_output.clone(), s_output.clone(), info.clone()));
+        let mut found = false;
+        for s in &mut self.space_list {
+            if s.output.as_ref().map(|o| &o.0) == Some(&c_output) {
+                let _ = s.update_output(c_output.clone(), s_output.clone(), info.clone());
+                found = true;
+            }
         }
-        anyhow::bail!("Failed to find popup with matching client id")
-    }
+        for osd in &mut self.osds {
+            osd.update_output(c_output.clone(), s_output.clone(), info.clone())?;
+        }
+        self.apply_toplevel_changes();
 
-    fn handle_events(
-        &mut self,
-        dh: &smithay::reexports::wayland_server::DisplayHandle,
-        qh: &QueueHandle<GlobalState>,
-        popup_manager: &mut PopupManager,
-        time: u32,
-        throttle: Option<Duration>,
-    ) -> std::time::Instant {
-        self.space_list
-            .iter_mut()
-            .fold(None, |mut acc, s| {
-                let last_dirtied =
-                    s.handle_events(dh, popup_manager, time, throttle, self.renderer.as_mut(), qh);
-                if let Some(last_dirty) = acc {
-                    if last_dirty < last_dirtied {
-                        acc = Some(last_dirtied);
-                    }
-                } else {
-                    acc = Some(last_dirtied);
-                }
-                acc
-            })
-            .unwrap_or_else(Instant::now)
+        Ok(found)
     }
 
-    fn config(&self) -> Self::Config {
-        self.config.clone()
+    fn get_scale_factor(&self, surface: &wl_surface::WlSurface) -> std::option::Option<f64> {
+        let client = surface.client()?;
+        self.applet_space(&client.id()).and_then(|i| self.space_list[i].get_scale_factor(surface))
     }
 
-    fn spawn_clients(
+    fn scale_factor_changed(
         &mut self,
-        _display: smithay::reexports::wayland_server::DisplayHandle,
-        _qh: &QueueHandle<GlobalState>,
-        _: Option<SecurityContextManager>,
-    ) -> anyhow::Result<()> {
-        // spaces spawn their clients when they are created
-        Ok(())
-    }
-
-    fn destroy(&mut self) {
+        surface: &c_wl_surface::WlSurface,
+        scale: f64,
+        legacy: bool,
+    ) {
+        if let Some(osd) = self.osds.iter_mut().find(|o| o.has_surface(surface)) {
+            osd.scale_factor_changed(surface, scale, legacy);
+            return;
+        }
         for s in &mut self.space_list {
-            s.destroy();
+            if s.panel_surface() == Some(surface)
+                || s.popups.iter().any(|p| p.popup.c_popup.wl_surface() == surface)
+            {
+                s.scale_factor_changed(surface, scale, legacy);
+                break;
+            }
         }
     }
 
-    fn dirty_window(
+    fn transform_changed(
         &mut self,
-        dh: &smithay::reexports::wayland_server::DisplayHandle,
-        w: &smithay::reexports::wayland_server::protocol::wl_surface::WlSurface,
+        _conn: &Connection,
+        _surface: &c_wl_surface::WlSurface,
+        _new_transform: cctk::sctk::reexports::client::protocol::wl_output::Transform,
     ) {
-        // add window to the space with a client that matches the window
-        let w_client = w.client().map(|c| c.id());
-
-        if let Some(space) = self.space_list.iter_mut().find(|space| {
-            space
-                .clients_center
-                .lock()
-                .unwrap()
-                .iter()
-                .chain(space.clients_left.lock().unwrap().iter())
-                .chain(space.clients_right.lock().unwrap().iter())
-                .any(|c| Some(c.client.id()) == w_client)
-        }) {
-            space.dirty_window(dh, w);
-        }
+        // TODO handle the preferred transform
     }
+}
 
-    fn dirty_popup(
-        &mut self,
-        dh: &smithay::reexports::wayland_server::DisplayHandle,
-        w: &smithay::reexports::wayland_server::protocol::wl_surface::WlSurface,
-    ) {
-        // add window to the space with a client that matches the window
-        let p_client = w.client().map(|c| c.id());
-
-        if let Some(space) = self.space_list.iter_mut().find(|space| {
-            space
-                .clients_center
-                .lock()
-                .unwrap()
-                .iter()
-                .chain(space.clients_left.lock().unwrap().iter())
-                .chain(space.clients_right.lock().unwrap().iter())
-                .any(|c| Some(c.client.id()) == p_client)
-        }) {
-            space.dirty_popup(dh, w);
-        }
+impl InputRouter for SpaceContainer {
+    /// get the client hovered surface of the space
+    fn get_client_hovered_surface(&self) -> Rc<RefCell<ClientFocus>> {
+        self.c_hovered_surface.clone()
     }
 
-    fn renderer(&mut self) -> Option<&mut smithay::backend::renderer::gles::GlesRenderer> {
-        self.renderer.as_mut()
+    /// get the client focused surface of the space
+    fn get_client_focused_surface(&self) -> Rc<RefCell<ClientFocus>> {
+        self.c_focused_surface.clone()
     }
 
     // all pointer / keyboard handling should be called on any space with an active
@@ -498,7 +644,7 @@ impl WrapperSpace for SpaceContainer {
     // crash
     fn update_pointer(
         &mut self,
-        dim: (i32, i32),
+        dim: (f64, f64),
         seat_name: &str,
         c_wl_surface: c_wl_surface::WlSurface,
         pointer: &WlPointer,
@@ -553,7 +699,7 @@ impl WrapperSpace for SpaceContainer {
                 {
                     continue;
                 }
-                let Some(space_c_wl_surface) = s.layer.as_ref().map(|l| l.wl_surface()) else {
+                let Some(space_c_wl_surface) = s.panel_surface() else {
                     continue;
                 };
                 if s.config.autohide.is_none() {
@@ -658,12 +804,11 @@ impl WrapperSpace for SpaceContainer {
                     s.pointer_leave(seat_name, None)
                 };
             }
-        } else if let Some(space) = self.space_list.iter_mut().find(|s| {
-            surface
-                .as_ref()
-                .zip(s.layer.as_ref().map(|l| l.wl_surface()))
-                .is_some_and(|(s, l)| s == l)
-        }) {
+        } else if let Some(space) = self
+            .space_list
+            .iter_mut()
+            .find(|s| surface.as_ref().zip(s.panel_surface()).is_some_and(|(s, l)| s == l))
+        {
             output_anchor = space.output.as_ref().map(|o| (o.1.name(), space.config.anchor));
             for s in &mut self.space_list {
                 s.pointer_leave(seat_name, None);
@@ -691,7 +836,7 @@ impl WrapperSpace for SpaceContainer {
 
     fn pointer_enter(
         &mut self,
-        dim: (i32, i32),
+        dim: (f64, f64),
         seat_name: &str,
         c_wl_surface: c_wl_surface::WlSurface,
         pointer: &WlPointer,
@@ -718,134 +863,4 @@ impl WrapperSpace for SpaceContainer {
                 .find_map(|s| s.pointer_enter(dim, seat_name, c_wl_surface.clone(), pointer))
         }
     }
-
-    fn configure_popup(
-        &mut self,
-        popup: &sctk::shell::xdg::popup::Popup,
-        config: sctk::shell::xdg::popup::PopupConfigure,
-    ) {
-        for space in &mut self.space_list {
-            space.configure_panel_popup(popup, config.clone(), self.renderer.as_mut());
-        }
-    }
-
-    fn visibility(&self) -> Visibility {
-        let visible = self.space_list.iter().any(|s| {
-            self.c_hovered_surface
-                .borrow()
-                .iter()
-                .any(|f| matches!(f.2, FocusStatus::Focused))
-                // transitions should try to be smooth
-                || !matches!(s.visibility, Visibility::Visible | Visibility::Hidden)
-                || s.animate_state.is_some()
-                || !s.popups.is_empty()
-        });
-
-        if visible {
-            Visibility::Visible
-        } else {
-            Visibility::Hidden
-        }
-    }
-
-    fn raise_window(&mut self, _: &smithay::desktop::Window, _: bool) {}
-
-    fn close_popup(&mut self, popup: &sctk::shell::xdg::popup::Popup) {
-        if let Some(space) = self
-            .space_list
-            .iter_mut()
-            .find(|s| s.popups.iter().any(|p| p.popup.c_popup.wl_surface() == popup.wl_surface()))
-        {
-            space.close_popup(popup);
-        }
-    }
-
-    fn configure_layer(&mut self, layer: &LayerSurface, configure: LayerSurfaceConfigure) {
-        if let Some(space) = self
-            .space_list
-            .iter_mut()
-            .find(|s| s.layer.as_ref().map(|s| s.wl_surface()) == Some(layer.wl_surface()))
-        {
-            space.configure_panel_layer(layer, configure, &mut self.renderer);
-            if matches!(space.visibility(), Visibility::Visible) || !space.output_has_toplevel {
-                space.output.as_ref().map(|o| (o.1.name(), space.config.anchor));
-            }
-        }
-        self.apply_toplevel_changes()
-    }
-
-    fn close_layer(&mut self, layer: &LayerSurface) {
-        self.space_list
-            .retain(|s| s.layer.as_ref().map(|s| s.wl_surface()) != Some(layer.wl_surface()));
-    }
-
-    fn output_leave(
-        &mut self,
-        c_output: sctk::reexports::client::protocol::wl_output::WlOutput,
-        _s_output: Output,
-    ) -> anyhow::Result<()> {
-        self.outputs.retain(|o| o.0 != c_output);
-        self.space_list.retain(|s| s.output.as_ref().map(|o| &o.0) != Some(&c_output));
-        Ok(())
-    }
-
-    fn update_output(
-        &mut self,
-        c_output: WlOutput,
-        s_output: Output,
-        info: OutputInfo,
-    ) -> anyhow::Result<bool> {
-        self.outputs.retain(|o| o.0 != c_output);
-        self.outputs.push((c_output.clone(), s_output.clone(), info.clone()));
-        let mut found = false;
-        for s in &mut self.space_list {
-            if s.output.as_ref().map(|o| &o.0) == Some(&c_output) {
-                let _ = s.update_output(c_output.clone(), s_output.clone(), info.clone());
-                found = true;
-            }
-        }
-        self.apply_toplevel_changes();
-
-        Ok(found)
-    }
-
-    fn frame(&mut self, surface: &c_wl_surface::WlSurface, time: u32) {
-        for s in self.space_list.iter_mut() {
-            s.frame(surface, time);
-        }
-    }
-
-    fn get_scale_factor(&self, surface: &wl_surface::WlSurface) -> std::option::Option<f64> {
-        for s in &self.space_list {
-            if let Some(scale) = s.get_scale_factor(surface) {
-                return Some(scale);
-            }
-        }
-        None
-    }
-
-    fn scale_factor_changed(
-        &mut self,
-        surface: &c_wl_surface::WlSurface,
-        scale: f64,
-        legacy: bool,
-    ) {
-        for s in &mut self.space_list {
-            if s.layer.as_ref().map(|l| l.wl_surface()) == Some(surface)
-                || s.popups.iter().any(|p| p.popup.c_popup.wl_surface() == surface)
-            {
-                s.scale_factor_changed(surface, scale, legacy);
-                break;
-            }
-        }
-    }
-
-    fn transform_changed(
-        &mut self,
-        _conn: &Connection,
-        _surface: &c_wl_surface::WlSurface,
-        _new_transform: cctk::sctk::reexports::client::protocol::wl_output::Transform,
-    ) {
-        // TODO handle the preferred transform
-    }
 }
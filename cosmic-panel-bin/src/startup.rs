@@ -0,0 +1,127 @@
+//! Profiling of the panel start, and staging of the applets started with it.
+//!
+//! Each phase of the start is recorded once, with the time since the panel
+//! started, in a `startup` span. With `--startup-report` the phases are printed
+//! once the first frame is rendered.
+//!
+//! Until then, panels only start the applets of their center section, and the
+//! other applets are staged. They are started after the first frame, or after
+//! [`STAGE_TIMEOUT`] if no panel renders, e.g. because all of them are hidden.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use tracing::{info, info_span, span::EnteredSpan};
+
+use crate::space::{AppletMsg, Clients};
+
+/// How long applets are staged if no panel renders.
+const STAGE_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    ConfigLoad,
+    GlobalBinds,
+    LayerMap,
+    FirstAppletSpawn,
+    FirstFrame,
+}
+
+impl Phase {
+    const ALL: [Self; 5] = [
+        Self::ConfigLoad,
+        Self::GlobalBinds,
+        Self::LayerMap,
+        Self::FirstAppletSpawn,
+        Self::FirstFrame,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::ConfigLoad => "config load",
+            Self::GlobalBinds => "global binds",
+            Self::LayerMap => "layer map",
+            Self::FirstAppletSpawn => "first applet spawn",
+            Self::FirstFrame => "first frame",
+        }
+    }
+}
+
+struct Startup {
+    start: Instant,
+    report: bool,
+    /// time since the start when each phase was reached
+    phases: Vec<(Phase, Duration)>,
+    /// applets held until the first frame with the list of their section, or
+    /// `None` once they were released
+    staged: Option<Vec<(Clients, AppletMsg)>>,
+}
+
+static STARTUP: Lazy<Mutex<Startup>> = Lazy::new(|| {
+    Mutex::new(Startup {
+        start: Instant::now(),
+        report: false,
+        phases: Vec::new(),
+        staged: Some(Vec::new()),
+    })
+});
+
+/// Start profiling, and print the phases after the first frame if `report`.
+pub fn begin(report: bool) {
+    STARTUP.lock().unwrap().report = report;
+}
+
+/// Enter the span of `phase`, which is recorded by [`mark`] once it is done.
+pub fn span(phase: Phase) -> EnteredSpan {
+    info_span!("startup", phase = phase.name()).entered()
+}
+
+/// Record that the start reached `phase`, unless it did before.
+pub fn mark(phase: Phase) {
+    let mut startup = STARTUP.lock().unwrap();
+    if startup.phases.iter().any(|(p, _)| *p == phase) {
+        return;
+    }
+    let elapsed = startup.start.elapsed();
+    startup.phases.push((phase, elapsed));
+    info_span!("startup", phase = phase.name()).in_scope(|| {
+        info!("Reached {} after {:.1} ms", phase.name(), elapsed.as_secs_f64() * 1000.);
+    });
+    if phase == Phase::FirstFrame && startup.report {
+        for phase in Phase::ALL {
+            match startup.phases.iter().find(|(p, _)| *p == phase) {
+                Some((_, elapsed)) => {
+                    eprintln!("{}: {:.1} ms", phase.name(), elapsed.as_secs_f64() * 1000.)
+                },
+                None => eprintln!("{}: not reached", phase.name()),
+            }
+        }
+    }
+}
+
+/// Hold the applet message `msg` of the section `clients` until the first
+/// frame. Returns it if the staged applets were released already.
+pub fn stage(clients: Clients, msg: AppletMsg) -> Option<AppletMsg> {
+    let mut startup = STARTUP.lock().unwrap();
+    match startup.staged.as_mut() {
+        Some(staged) => {
+            staged.push((clients, msg));
+            None
+        },
+        None => Some(msg),
+    }
+}
+
+/// The staged applets with the list of their section, once the first frame
+/// was rendered if `first_frame`, or once staging timed out. Their panels may
+/// have been removed since they were staged.
+pub fn release(first_frame: bool) -> Vec<(Clients, AppletMsg)> {
+    let mut startup = STARTUP.lock().unwrap();
+    if startup.staged.is_none() || (!first_frame && startup.start.elapsed() < STAGE_TIMEOUT) {
+        return Vec::new();
+    }
+    startup.staged.take().unwrap_or_default()
+}
@@ -0,0 +1,45 @@
+use sctk::{
+    activation::{ActivationHandler, RequestData, RequestDataExt},
+    delegate_activation,
+    reexports::client::protocol::{wl_seat::WlSeat, wl_surface::WlSurface},
+};
+use smithay::reexports::{
+    wayland_protocols::xdg::activation::v1::server::xdg_activation_token_v1::XdgActivationTokenV1,
+    wayland_server::Resource,
+};
+
+use crate::xdg_shell_wrapper::shared_state::GlobalState;
+
+/// A host activation token requested on behalf of an applet.
+#[derive(Debug)]
+pub struct ForwardedTokenRequest {
+    pub(crate) data: RequestData,
+    /// the token object of the applet, which receives the host token
+    pub(crate) token: XdgActivationTokenV1,
+}
+
+impl RequestDataExt for ForwardedTokenRequest {
+    fn app_id(&self) -> Option<&str> {
+        self.data.app_id()
+    }
+
+    fn seat_and_serial(&self) -> Option<(&WlSeat, u32)> {
+        self.data.seat_and_serial()
+    }
+
+    fn surface(&self) -> Option<&WlSurface> {
+        self.data.surface()
+    }
+}
+
+impl ActivationHandler for GlobalState {
+    type RequestData = ForwardedTokenRequest;
+
+    fn new_token(&mut self, token: String, data: &ForwardedTokenRequest) {
+        if data.token.is_alive() {
+            data.token.done(token);
+        }
+    }
+}
+
+delegate_activation!(GlobalState, ForwardedTokenRequest);
@@ -7,7 +7,10 @@ use sctk::{
 };
 use smithay::reexports::wayland_server::protocol::wl_output::Transform;
 
-use crate::xdg_shell_wrapper::{shared_state::GlobalState, space::WrapperSpace};
+use crate::xdg_shell_wrapper::{
+    shared_state::GlobalState,
+    space::{OutputHandler, WrapperSpace},
+};
 
 impl CompositorHandler for GlobalState {
     fn scale_factor_changed(
@@ -32,9 +35,9 @@ impl CompositorHandler for GlobalState {
             .server_state
             .seats
             .iter_mut()
-            .find(|s| s.client.dnd_icon.iter().any(|dnd_icon| &dnd_icon.1 == surface))
+            .find(|s| s.client.dnd_icon.iter().any(|dnd_icon| &dnd_icon.surface == surface))
         {
-            seat.client.dnd_icon.as_mut().unwrap().4 = Some(time);
+            seat.client.dnd_icon.as_mut().unwrap().has_frame = Some(time);
             self.draw_dnd_icon();
         } else {
             self.space.frame(surface, time);
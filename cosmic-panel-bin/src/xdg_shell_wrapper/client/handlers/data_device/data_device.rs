@@ -1,10 +1,10 @@
 use std::time::Instant;
 
-use cctk::wayland_client::protocol::wl_surface::WlSurface;
+use cctk::wayland_client::protocol::{wl_pointer::WlPointer, wl_surface::WlSurface};
 use sctk::{
     data_device_manager::{
         data_device::{DataDeviceData, DataDeviceHandler},
-        data_offer::DataOfferData,
+        data_offer::{DataOfferData, DragOffer},
     },
     reexports::client::{
         protocol::{
@@ -28,9 +28,91 @@ use smithay::{
 
 use crate::xdg_shell_wrapper::{
     client_state::FocusStatus, server_state::ServerPointerFocus, shared_state::GlobalState,
-    space::WrapperSpace,
+    space::InputRouter,
 };
 
+use super::transfer;
+
+impl GlobalState {
+    /// Start a drag on the embedded server for the host drag `offer` over the
+    /// panel, unless the drag was started by an applet.
+    fn forward_drag(&mut self, seat_name: &str, offer: DragOffer) {
+        let Some(seat) = self.server_state.seats.iter_mut().find(|s| s.name == seat_name) else {
+            return;
+        };
+        let wl_offer = offer.inner();
+
+        let mime_types = wl_offer
+            .data::<DataOfferData>()
+            .unwrap()
+            .with_mime_types(|m| transfer::filter_mime_types(m.iter().cloned()));
+        let mut dnd_action = DndAction::empty();
+        let c_action = offer.source_actions;
+        if c_action.contains(ClientDndAction::Copy) {
+            dnd_action |= DndAction::Copy;
+        } else if c_action.contains(ClientDndAction::Move) {
+            dnd_action |= DndAction::Move;
+        } else if c_action.contains(ClientDndAction::Ask) {
+            dnd_action |= DndAction::Ask;
+        }
+
+        let metadata = SourceMetadata { mime_types, dnd_action };
+        let (x, y) = (offer.x, offer.y);
+        let Some(ptr) = seat.client.ptr.as_ref().map(|p| p.pointer().clone()) else {
+            tracing::error!("Missing pointer on seat for dnd enter");
+            return;
+        };
+        let server_focus =
+            self.space.update_pointer((x, y), &seat.name, offer.surface.clone(), &ptr);
+
+        seat.client.dnd_offer = Some(offer);
+        // TODO: touch vs pointer start data
+        if !seat.client.next_dnd_offer_is_mine {
+            let focus = server_focus;
+            start_dnd(
+                &self.server_state.display_handle.clone(),
+                &seat.server.seat.clone(),
+                self,
+                SERIAL_COUNTER.next_serial(),
+                Some(GrabStartData {
+                    focus: focus.map(|f| (f.surface, f.s_pos.to_f64())),
+                    button: 0x110, // assume left button for now, maybe there is another way..
+                    location: (x, y).into(),
+                }),
+                None,
+                metadata,
+            );
+        }
+    }
+
+    /// Send a generated click to an applet while a host drag is over the
+    /// panel. The drag grab is lifted for the click, which would drop the drag
+    /// otherwise, and the drag is forwarded again afterwards.
+    pub(crate) fn click_during_drag(
+        &mut self,
+        seat_name: &str,
+        pointer: &WlPointer,
+        mut events: Vec<PointerEvent>,
+    ) {
+        let Some(seat) = self.server_state.seats.iter_mut().find(|s| s.name == seat_name) else {
+            return;
+        };
+        // the host offer must outlive the lifted grab
+        let Some(offer) = seat.client.dnd_offer.take() else {
+            return;
+        };
+        if let Some(ptr) = seat.server.seat.get_pointer() {
+            ptr.unset_grab(self, SERIAL_COUNTER.next_serial(), 0);
+        }
+
+        self.update_generated_event_serial(&mut events);
+        let conn = self.client_state.connection.clone();
+        self.pointer_frame_inner(&conn, pointer, &events);
+
+        self.forward_drag(seat_name, offer);
+    }
+}
+
 impl DataDeviceHandler for GlobalState {
     fn selection(
         &mut self,
@@ -60,7 +142,10 @@ impl DataDeviceHandler for GlobalState {
         };
         let wl_offer = offer.inner();
 
-        let mime_types = wl_offer.data::<DataOfferData>().unwrap().with_mime_types(|m| m.to_vec());
+        let mime_types = wl_offer
+            .data::<DataOfferData>()
+            .unwrap()
+            .with_mime_types(|m| transfer::filter_mime_types(m.iter().cloned()));
         seat.client.selection_offer = Some(offer);
         set_data_device_selection(
             &self.server_state.display_handle,
@@ -115,50 +200,9 @@ impl DataDeviceHandler for GlobalState {
             }
         }
 
-        let wl_offer = offer.inner();
-
-        let mime_types = wl_offer.data::<DataOfferData>().unwrap().with_mime_types(|m| m.to_vec());
-        let mut dnd_action = DndAction::empty();
-        let c_action = offer.source_actions;
-        if c_action.contains(ClientDndAction::Copy) {
-            dnd_action |= DndAction::Copy;
-        } else if c_action.contains(ClientDndAction::Move) {
-            dnd_action |= DndAction::Move;
-        } else if c_action.contains(ClientDndAction::Ask) {
-            dnd_action |= DndAction::Ask;
-        }
-
-        let metadata = SourceMetadata { mime_types, dnd_action };
-        let (x, y) = (offer.x, offer.y);
-        let Some(ptr) = seat.client.ptr.as_ref().map(|p| p.pointer().clone()) else {
-            tracing::error!("Missing pointer on seat for dnd enter");
-            return;
-        };
-        let server_focus = self.space.update_pointer(
-            (x as i32, y as i32),
-            &seat.name,
-            offer.surface.clone(),
-            &ptr,
-        );
-
-        seat.client.dnd_offer = Some(offer);
-        // TODO: touch vs pointer start data
-        if !seat.client.next_dnd_offer_is_mine {
-            let focus = server_focus;
-            start_dnd(
-                &self.server_state.display_handle.clone(),
-                &seat.server.seat.clone(),
-                self,
-                SERIAL_COUNTER.next_serial(),
-                Some(GrabStartData {
-                    focus: focus.map(|f| (f.surface, f.s_pos.to_f64())),
-                    button: 0x110, // assume left button for now, maybe there is another way..
-                    location: (x, y).into(),
-                }),
-                None,
-                metadata,
-            );
-        }
+        let seat_name = seat.name.clone();
+        self.space.set_dragging(Some(surface));
+        self.forward_drag(&seat_name, offer);
     }
 
     fn leave(
@@ -189,11 +233,14 @@ impl DataDeviceHandler for GlobalState {
 
         {
             let mut c_hovered_surface = self.client_state.hovered_surface.borrow_mut();
-            if let Some(i) = c_hovered_surface.iter().position(|f| f.0 == surface) {
+            if let Some(i) =
+                c_hovered_surface.iter().position(|f| f.0 == surface && f.1 == seat.name)
+            {
                 c_hovered_surface[i].2 = FocusStatus::LastFocused(Instant::now());
             }
         }
 
+        self.space.set_dragging(None);
         let duration_since = Instant::now().duration_since(self.start_time).as_millis() as u32;
 
         let leave_event = PointerEvent {
@@ -238,12 +285,8 @@ impl DataDeviceHandler for GlobalState {
             return;
         };
 
-        let server_focus = self.space.update_pointer(
-            (offer.x as i32, offer.y as i32),
-            &seat.name,
-            offer.surface.clone(),
-            &ptr,
-        );
+        let server_focus =
+            self.space.update_pointer((offer.x, offer.y), &seat.name, offer.surface.clone(), &ptr);
 
         let client = if let Some(ServerPointerFocus { surface: w, .. }) = server_focus {
             w.wl_surface().and_then(|s| s.client())
@@ -284,6 +327,7 @@ impl DataDeviceHandler for GlobalState {
             Some(offer) => offer,
             None => return,
         };
+        self.space.set_dragging(None);
 
         let pointer_event = PointerEvent {
             surface: offer.surface,
@@ -12,6 +12,9 @@ use smithay::{
     reexports::wayland_server::protocol::wl_data_device_manager::DndAction, utils::SERIAL_COUNTER,
     wayland::selection::data_device::request_data_device_client_selection,
 };
+use tracing::error;
+
+use super::transfer;
 
 impl DataSourceHandler for GlobalState {
     fn send_request(
@@ -49,14 +52,23 @@ impl DataSourceHandler for GlobalState {
             None => return,
         };
 
-        // TODO write from server source to fd
+        // the applet writes into a buffered transfer to the host fd, so it
+        // can't stall the host
         // could be a selection source or a dnd source
         if is_dnd {
             if let Some(dnd_source) = seat.server.dnd_source.as_ref() {
-                dnd_source.send(mime, fd.as_fd());
+                match transfer::start_transfer(&self.space.loop_handle, fd.into()) {
+                    Ok(fd) => dnd_source.send(mime, fd.as_fd()),
+                    Err(err) => error!("Failed to start a transfer {err:?}"),
+                }
             }
         } else if let Some(_) = seat.server.selection_source.as_ref() {
-            _ = request_data_device_client_selection(&seat.server.seat, mime, fd.into());
+            match transfer::start_transfer(&self.space.loop_handle, fd.into()) {
+                Ok(fd) => {
+                    _ = request_data_device_client_selection(&seat.server.seat, mime, fd);
+                },
+                Err(err) => error!("Failed to start a transfer {err:?}"),
+            }
         }
     }
 
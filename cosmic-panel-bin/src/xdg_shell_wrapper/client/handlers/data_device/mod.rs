@@ -5,5 +5,6 @@ use crate::xdg_shell_wrapper::shared_state::GlobalState;
 pub mod data_device;
 pub mod data_offer;
 pub mod data_source;
+pub mod transfer;
 
 delegate_data_device!(GlobalState);
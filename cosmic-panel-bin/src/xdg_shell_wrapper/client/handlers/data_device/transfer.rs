@@ -0,0 +1,327 @@
+//! Buffered transfers of selection and dnd data between the host and applets.
+//!
+//! Instead of handing the fd of the receiver to the sender, the sender writes
+//! into a pipe of the panel. The data is read into memory, and written to the
+//! receiver once complete, from the event loop and without blocking it.
+//! Transfers which exceed [`MAX_TRANSFER_SIZE`] or don't finish within
+//! [`TRANSFER_TIMEOUT`] are dropped, and at most [`MAX_TRANSFERS`] run at a
+//! time, so applets can't make the panel buffer more than a few hundred MiB.
+
+use std::{
+    cell::{Cell, RefCell},
+    io,
+    os::fd::{AsFd, OwnedFd},
+    rc::Rc,
+    time::Duration,
+};
+
+use rustix::{
+    io::Errno,
+    pipe::{pipe_with, PipeFlags},
+};
+use smithay::reexports::calloop::{
+    generic::Generic,
+    timer::{TimeoutAction, Timer},
+    Interest, LoopHandle, Mode, PostAction, RegistrationToken,
+};
+use tracing::warn;
+
+/// The largest transfer which is forwarded.
+
+pub const MAX_TRANSFER_SIZE: usize = 64 * 1024 * 1024;
+/// How long a transfer may take, from the request until the receiver read all
+/// of the data.
+pub const TRANSFER_TIMEOUT: Duration = Duration::from_secs(10);
+/// The most transfers which are buffered at a time.
+pub const MAX_TRANSFERS: usize = 4;
+/// The most mime types which are forwarded for a single offer.
+const MAX_MIME_TYPES: usize = 64;
+/// The longest mime type which is forwarded.
+const MAX_MIME_TYPE_LEN: usize = 256;
+
+/// The mime types of an offer which are forwarded, without empty, overlong or
+/// duplicate entries.
+pub fn filter_mime_types(mime_types: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut filtered: Vec<String> = Vec::new();
+    for mime_type in mime_types {
+        if filtered.len() == MAX_MIME_TYPES {
+            break;
+        }
+        if mime_type.is_empty()
+            || mime_type.len() > MAX_MIME_TYPE_LEN
+            || mime_type.chars().any(char::is_control)
+            || filtered.contains(&mime_type)
+        {
+            continue;
+        }
+        filtered.push(mime_type);
+    }
+    filtered
+}
+
+/// Whether `mime_type` was forwarded for an offer of `mime_types`.
+pub fn is_forwarded_mime_type(mime_types: &[String], mime_type: &str) -> bool {
+    filter_mime_types(mime_types.iter().cloned()).iter().any(|m| m == mime_type)
+}
+
+thread_local! {
+    /// Transfers of the event loop of this thread which weren't dropped yet.
+    static ACTIVE_TRANSFERS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The number of transfers which are buffered on this thread.
+pub fn active_transfers() -> usize {
+    ACTIVE_TRANSFERS.with(Cell::get)
+}
+
+/// A slot of [`MAX_TRANSFERS`], released when the transfer is dropped.
+#[derive(Debug)]
+struct TransferSlot;
+
+impl TransferSlot {
+    fn acquire() -> Option<Self> {
+        ACTIVE_TRANSFERS.with(|active| {
+            (active.get() < MAX_TRANSFERS).then(|| {
+                active.set(active.get() + 1);
+                TransferSlot
+            })
+        })
+    }
+}
+
+impl Drop for TransferSlot {
+    fn drop(&mut self) {
+        ACTIVE_TRANSFERS.with(|active| active.set(active.get() - 1));
+    }
+}
+
+/// A transfer, dropped with its buffer once all of its sources are removed.
+#[derive(Debug)]
+struct Transfer {
+    _slot: TransferSlot,
+    buf: Vec<u8>,
+    written: usize,
+    /// the fd of the receiver, until the data is complete
+    target: Option<OwnedFd>,
+    read: Option<RegistrationToken>,
+    write: Option<RegistrationToken>,
+    timeout: Option<RegistrationToken>,
+}
+
+impl Transfer {
+    /// Remove the sources of the transfer which are still registered.
+    fn cancel<D>(&mut self, loop_handle: &LoopHandle<'static, D>) {
+        for token in
+            [self.read.take(), self.write.take(), self.timeout.take()].into_iter().flatten()
+        {
+            loop_handle.remove(token);
+        }
+    }
+}
+
+/// Start a transfer to the fd `target` of the receiver, and return the fd the
+/// sender should write into. Fails if [`MAX_TRANSFERS`] are running.
+pub fn start_transfer<D: 'static>(
+    loop_handle: &LoopHandle<'static, D>,
+    target: OwnedFd,
+) -> io::Result<OwnedFd> {
+    let Some(slot) = TransferSlot::acquire() else {
+        return Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            format!("{MAX_TRANSFERS} transfers are running"),
+        ));
+    };
+    let (read_fd, write_fd) = pipe_with(PipeFlags::CLOEXEC)?;
+    rustix::io::ioctl_fionbio(&read_fd, true)?;
+    rustix::io::ioctl_fionbio(&target, true)?;
+
+    let transfer = Rc::new(RefCell::new(Transfer {
+        _slot: slot,
+        buf: Vec::new(),
+        written: 0,
+        target: Some(target),
+        read: None,
+        write: None,
+        timeout: None,
+    }));
+
+    let read_transfer = transfer.clone();
+    let read_handle = loop_handle.clone();
+    let read = loop_handle
+        .insert_source(Generic::new(read_fd, Interest::READ, Mode::Level), move |_, fd, _| {
+            let mut t = read_transfer.borrow_mut();
+            let mut chunk = [0; 16 * 1024];
+            loop {
+                match rustix::io::read(fd.as_fd(), &mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) if t.buf.len() + n > MAX_TRANSFER_SIZE => {
+                        warn!("Dropping a transfer larger than {MAX_TRANSFER_SIZE} bytes");
+                        t.read = None;
+                        t.cancel(&read_handle);
+                        return Ok(PostAction::Remove);
+                    },
+                    Ok(n) => t.buf.extend_from_slice(&chunk[..n]),
+                    Err(Errno::AGAIN) => return Ok(PostAction::Continue),
+                    Err(Errno::INTR) => continue,
+                    Err(err) => {
+                        warn!("Failed to read a transfer {err:?}");
+                        t.read = None;
+                        t.cancel(&read_handle);
+                        return Ok(PostAction::Remove);
+                    },
+                }
+            }
+
+            // the sender is done, write the data to the receiver
+            t.read = None;
+            let Some(target) = t.target.take() else {
+                return Ok(PostAction::Remove);
+            };
+            let write_transfer = read_transfer.clone();
+            let write_handle = read_handle.clone();
+            let write = read_handle.insert_source(
+                Generic::new(target, Interest::WRITE, Mode::Level),
+                move |_, fd, _| {
+                    let mut t = write_transfer.borrow_mut();
+                    while t.written < t.buf.len() {
+                        match rustix::io::write(fd.as_fd(), &t.buf[t.written..]) {
+                            Ok(n) => t.written += n,
+                            Err(Errno::AGAIN) => return Ok(PostAction::Continue),
+                            Err(Errno::INTR) => continue,
+                            Err(err) => {
+                                warn!("Failed to write a transfer {err:?}");
+                                break;
+                            },
+                        }
+                    }
+                    t.write = None;
+                    t.cancel(&write_handle);
+                    Ok(PostAction::Remove)
+                },
+            );
+            match write {
+                Ok(token) => t.write = Some(token),
+                Err(err) => {
+                    warn!("Failed to write a transfer {err:?}");
+                    t.cancel(&read_handle);
+                },
+            }
+            Ok(PostAction::Remove)
+        })
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.error))?;
+    transfer.borrow_mut().read = Some(read);
+
+    let timeout_transfer = transfer.clone();
+    let timeout_handle = loop_handle.clone();
+    let timeout = loop_handle
+        .insert_source(Timer::from_duration(TRANSFER_TIMEOUT), move |_, _, _| {
+            let mut t = timeout_transfer.borrow_mut();
+            t.timeout = None;
+            warn!("Dropping a transfer which took longer than {TRANSFER_TIMEOUT:?}");
+            t.cancel(&timeout_handle);
+            TimeoutAction::Drop
+        })
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.error));
+    match timeout {
+        Ok(token) => transfer.borrow_mut().timeout = Some(token),
+        Err(err) => {
+            transfer.borrow_mut().cancel(loop_handle);
+            return Err(err);
+        },
+    }
+
+    Ok(write_fd)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use smithay::reexports::calloop::EventLoop;
+
+    use super::*;
+
+    fn receiver() -> (std::fs::File, OwnedFd) {
+        let (read_fd, write_fd) = pipe_with(PipeFlags::CLOEXEC).unwrap();
+        (std::fs::File::from(read_fd), write_fd)
+    }
+
+    fn dispatch(event_loop: &mut EventLoop<'static, ()>) {
+        for _ in 0..10 {
+            event_loop.dispatch(Duration::from_millis(10), &mut ()).unwrap();
+        }
+    }
+
+    #[test]
+    fn data_is_written_to_the_receiver_once_complete() {
+        let mut event_loop = EventLoop::<()>::try_new().unwrap();
+        let (mut received, target) = receiver();
+        let sender = start_transfer(&event_loop.handle(), target).unwrap();
+        assert_eq!(active_transfers(), 1);
+
+        std::fs::File::from(sender).write_all(b"data of the selection").unwrap();
+        dispatch(&mut event_loop);
+
+        let mut data = String::new();
+        received.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "data of the selection");
+        assert_eq!(active_transfers(), 0);
+    }
+
+    #[test]
+    fn concurrent_transfers_are_limited() {
+        let mut event_loop = EventLoop::<()>::try_new().unwrap();
+        let mut transfers: Vec<_> = (0..MAX_TRANSFERS)
+            .map(|_| {
+                let (received, target) = receiver();
+                (received, start_transfer(&event_loop.handle(), target).unwrap())
+            })
+            .collect();
+        let (_received, target) = receiver();
+        let err = start_transfer(&event_loop.handle(), target).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        // a finished transfer makes room for the next one
+        let (_received, sender) = transfers.pop().unwrap();
+        drop(sender);
+        dispatch(&mut event_loop);
+        assert_eq!(active_transfers(), MAX_TRANSFERS - 1);
+        let (_received, target) = receiver();
+        assert!(start_transfer(&event_loop.handle(), target).is_ok());
+    }
+
+    #[test]
+    fn aborted_transfers_are_dropped() {
+        let mut event_loop = EventLoop::<()>::try_new().unwrap();
+        let (received, target) = receiver();
+        let sender = start_transfer(&event_loop.handle(), target).unwrap();
+
+        // the receiver is gone before the data could be written
+        drop(received);
+        std::fs::File::from(sender).write_all(b"data of the selection").unwrap();
+        dispatch(&mut event_loop);
+        assert_eq!(active_transfers(), 0);
+    }
+
+    #[test]
+    fn oversized_transfers_are_dropped() {
+        let mut event_loop = EventLoop::<()>::try_new().unwrap();
+        let (mut received, target) = receiver();
+        let sender = start_transfer(&event_loop.handle(), target).unwrap();
+
+        // the sender fails once the transfer is dropped, and the receiver gets
+        // no data
+        let sender = std::thread::spawn(move || {
+            std::fs::File::from(sender).write_all(&vec![0; MAX_TRANSFER_SIZE + 1])
+        });
+        while !sender.is_finished() {
+            event_loop.dispatch(Duration::from_millis(10), &mut ()).unwrap();
+        }
+        assert!(sender.join().unwrap().is_err());
+        dispatch(&mut event_loop);
+        assert_eq!(active_transfers(), 0);
+        let mut data = Vec::new();
+        received.read_to_end(&mut data).unwrap();
+        assert!(data.is_empty());
+    }
+}
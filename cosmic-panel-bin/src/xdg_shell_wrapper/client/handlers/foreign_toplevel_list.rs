@@ -0,0 +1,160 @@
+//! Forwards the ext-foreign-toplevel-list of the host compositor to the
+//! embedded applets, so that task list applets work without the cosmic
+//! toplevel-info protocol.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use cctk::{
+    wayland_client::{
+        self, event_created_child,
+        globals::{BindError, GlobalList},
+        Connection, Dispatch, Proxy, QueueHandle,
+    },
+    wayland_protocols::ext::foreign_toplevel_list::v1::client::{
+        ext_foreign_toplevel_handle_v1::{self, ExtForeignToplevelHandleV1},
+        ext_foreign_toplevel_list_v1::{self, ExtForeignToplevelListV1},
+    },
+};
+use sctk::globals::GlobalData;
+use smithay::wayland::foreign_toplevel_list::ForeignToplevelHandle;
+use wayland_backend::client::ObjectId;
+
+use crate::xdg_shell_wrapper::shared_state::GlobalState;
+
+#[derive(Debug)]
+pub struct ForeignToplevelListV1 {
+    pub(crate) _list: ExtForeignToplevelListV1,
+    /// embedded server handles of the host toplevels
+    pub(crate) toplevels: HashMap<ObjectId, ForeignToplevelHandle>,
+    /// identifiers the host gave its toplevels
+    pub(crate) host_identifiers: HashMap<ObjectId, String>,
+}
+
+impl ForeignToplevelListV1 {
+    pub fn bind(
+        globals: &GlobalList,
+        qh: &QueueHandle<GlobalState>,
+    ) -> Result<ForeignToplevelListV1, BindError> {
+        let _list = globals.bind(qh, 1..=1, GlobalData)?;
+        Ok(ForeignToplevelListV1 {
+            _list,
+            toplevels: HashMap::new(),
+            host_identifiers: HashMap::new(),
+        })
+    }
+}
+
+/// Title and app id of a host toplevel, applied on the next `done` event.
+#[derive(Debug, Default)]
+pub struct ForeignToplevelData {
+    pending: Mutex<(String, String)>,
+    /// the handle was sent by an overlap notification, not the toplevel list
+    overlap: bool,
+}
+
+impl ForeignToplevelData {
+    pub fn overlap() -> Self {
+        ForeignToplevelData { overlap: true, ..Default::default() }
+    }
+}
+
+impl Dispatch<ExtForeignToplevelListV1, GlobalData, GlobalState> for ForeignToplevelListV1 {
+    fn event(
+        state: &mut GlobalState,
+        list: &ExtForeignToplevelListV1,
+        event: <ExtForeignToplevelListV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+        if let ext_foreign_toplevel_list_v1::Event::Finished = event {
+            if let Some(forward) = state.client_state.foreign_toplevel_list.take() {
+                for (_, handle) in forward.toplevels {
+                    state.server_state.foreign_toplevel_list_state.remove_toplevel(&handle);
+                }
+            }
+            list.destroy();
+        }
+    }
+
+    event_created_child!(GlobalState, ExtForeignToplevelListV1, [
+        ext_foreign_toplevel_list_v1::EVT_TOPLEVEL_OPCODE => (ExtForeignToplevelHandleV1, ForeignToplevelData::default())
+    ]);
+}
+
+impl Dispatch<ExtForeignToplevelHandleV1, ForeignToplevelData, GlobalState>
+    for ForeignToplevelListV1
+{
+    fn event(
+        state: &mut GlobalState,
+        toplevel: &ExtForeignToplevelHandleV1,
+        event: <ExtForeignToplevelHandleV1 as Proxy>::Event,
+        data: &ForeignToplevelData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+        if data.overlap {
+            // only the app id is needed for the applets of the overlapped panel
+            match event {
+                ext_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                    data.pending.lock().unwrap().1 = app_id;
+                },
+                ext_foreign_toplevel_handle_v1::Event::Done => {
+                    let app_id = data.pending.lock().unwrap().1.clone();
+                    state.space.overlap_app_ids.insert(toplevel.id(), app_id);
+                },
+                ext_foreign_toplevel_handle_v1::Event::Closed => {
+                    state.space.overlap_app_ids.remove(&toplevel.id());
+                    toplevel.destroy();
+                },
+                _ => {},
+            }
+            return;
+        }
+        let Some(forward) = state.client_state.foreign_toplevel_list.as_mut() else {
+            return;
+        };
+        match event {
+            ext_foreign_toplevel_handle_v1::Event::Title { title } => {
+                data.pending.lock().unwrap().0 = title;
+            },
+            ext_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                data.pending.lock().unwrap().1 = app_id;
+            },
+            // the embedded server assigns its own identifiers, and the host one
+            // links the toplevel to its cosmic toplevel info
+            ext_foreign_toplevel_handle_v1::Event::Identifier { identifier } => {
+                forward.host_identifiers.insert(toplevel.id(), identifier);
+            },
+            ext_foreign_toplevel_handle_v1::Event::Done => {
+                let (title, app_id) = data.pending.lock().unwrap().clone();
+                if let Some(handle) = forward.toplevels.get(&toplevel.id()) {
+                    if handle.title() != title {
+                        handle.send_title(&title);
+                    }
+                    if handle.app_id() != app_id {
+                        handle.send_app_id(&app_id);
+                    }
+                    handle.send_done();
+                } else {
+                    let handle = state
+                        .server_state
+                        .foreign_toplevel_list_state
+                        .new_toplevel::<GlobalState>(title, app_id);
+                    forward.toplevels.insert(toplevel.id(), handle);
+                }
+            },
+            ext_foreign_toplevel_handle_v1::Event::Closed => {
+                forward.host_identifiers.remove(&toplevel.id());
+                if let Some(handle) = forward.toplevels.remove(&toplevel.id()) {
+                    state.server_state.foreign_toplevel_list_state.remove_toplevel(&handle);
+                }
+                toplevel.destroy();
+            },
+            _ => {},
+        }
+    }
+}
+
+wayland_client::delegate_dispatch!(GlobalState: [ExtForeignToplevelListV1: GlobalData] => ForeignToplevelListV1);
+wayland_client::delegate_dispatch!(GlobalState: [ExtForeignToplevelHandleV1: ForeignToplevelData] => ForeignToplevelListV1);
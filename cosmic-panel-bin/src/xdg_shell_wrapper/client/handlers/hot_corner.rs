@@ -0,0 +1,235 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Hot corners which reveal autohidden panels when the pointer rests in them.
+//!
+//! Each corner is a 1x1 layer surface in the overlay layer of the output of the
+//! panel, with a transparent buffer so it is mapped and gets pointer input. It
+//! doesn't reserve space and never takes keyboard focus.
+
+use std::time::{Duration, Instant};
+
+use cctk::wayland_client::{
+    protocol::{wl_output::WlOutput, wl_shm},
+    QueueHandle,
+};
+use cosmic_panel_config::{HotCorner, PanelCorner};
+use sctk::{
+    compositor::CompositorState,
+    seat::pointer::{PointerEvent, PointerEventKind},
+    shell::{
+        wlr_layer::{Anchor, KeyboardInteractivity, Layer, LayerShell, LayerSurface},
+        WaylandSurface,
+    },
+    shm::{
+        slot::{Buffer, SlotPool},
+        Shm,
+    },
+};
+use tracing::warn;
+
+use crate::xdg_shell_wrapper::{client_state::FocusStatus, shared_state::GlobalState};
+
+#[derive(Debug)]
+struct CornerSurface {
+    panel: String,
+    output: WlOutput,
+    config: HotCorner,
+    layer: LayerSurface,
+    buffer: Option<Buffer>,
+    /// the compositor closed the surface, so it isn't created again while
+    /// the corner is wanted
+    closed: bool,
+    /// when the pointer entered the corner
+    entered: Option<Instant>,
+    /// when the pointer left the corner after it revealed the panel
+    left: Option<Instant>,
+}
+
+impl CornerSurface {
+    fn dwell_time(&self) -> Duration {
+        Duration::from_millis(self.config.dwell_time.into())
+    }
+
+    fn focus(&self) -> Option<FocusStatus> {
+        if self.entered.is_some_and(|t| t.elapsed() >= self.dwell_time()) {
+            Some(FocusStatus::Focused)
+        } else {
+            self.left.map(FocusStatus::LastFocused)
+        }
+    }
+}
+
+/// The hot corner surfaces of the panels.
+#[derive(Debug, Default)]
+pub struct HotCorners {
+    pool: Option<SlotPool>,
+    corners: Vec<CornerSurface>,
+}
+
+impl HotCorners {
+    /// Create the surfaces of the corners in `wanted`, by panel and output, and
+    /// remove the others.
+    pub fn sync(
+        &mut self,
+        wanted: Vec<(String, WlOutput, HotCorner)>,
+        compositor_state: &CompositorState,
+        layer_state: &LayerShell,
+        shm: &Shm,
+        qh: &QueueHandle<GlobalState>,
+    ) {
+        self.corners.retain(|c| {
+            wanted.iter().any(|(panel, output, config)| {
+                *panel == c.panel && *output == c.output && config.corner == c.config.corner
+            })
+        });
+        for (panel, output, config) in wanted {
+            if let Some(c) = self.corners.iter_mut().find(|c| {
+                c.panel == panel && c.output == output && c.config.corner == config.corner
+            }) {
+                c.config = config;
+                continue;
+            }
+
+            if self.pool.is_none() {
+                match SlotPool::new(4, shm) {
+                    Ok(pool) => self.pool = Some(pool),
+                    Err(err) => {
+                        warn!("Failed to create the hot corner pool {err:?}");
+                        return;
+                    },
+                }
+            }
+            let Some(pool) = self.pool.as_mut() else {
+                return;
+            };
+            let buffer = match pool.create_buffer(1, 1, 4, wl_shm::Format::Argb8888) {
+                Ok((buffer, canvas)) => {
+                    canvas.fill(0);
+                    Some(buffer)
+                },
+                Err(err) => {
+                    warn!("Failed to create a hot corner buffer {err:?}");
+                    None
+                },
+            };
+
+            let surface = compositor_state.create_surface(qh);
+            let layer = layer_state.create_layer_surface(
+                qh,
+                surface,
+                Layer::Overlay,
+                Some(format!("{panel}-hot-corner")),
+                Some(&output),
+            );
+            layer.set_anchor(match config.corner {
+                PanelCorner::TopLeft => Anchor::TOP | Anchor::LEFT,
+                PanelCorner::TopRight => Anchor::TOP | Anchor::RIGHT,
+                PanelCorner::BottomLeft => Anchor::BOTTOM | Anchor::LEFT,
+                PanelCorner::BottomRight => Anchor::BOTTOM | Anchor::RIGHT,
+            });
+            layer.set_size(1, 1);
+            layer.set_exclusive_zone(-1);
+            layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+            layer.commit();
+
+            self.corners.push(CornerSurface {
+                panel,
+                output,
+                config,
+                layer,
+                buffer,
+                closed: false,
+                entered: None,
+                left: None,
+            });
+        }
+    }
+
+    /// Attach the buffer of the corner `layer` on its configure. Returns
+    /// `false` if it isn't a hot corner.
+    pub fn configure(&mut self, layer: &LayerSurface) -> bool {
+        let Some(c) = self.corners.iter().find(|c| c.layer.wl_surface() == layer.wl_surface())
+        else {
+            return false;
+        };
+        let surface = c.layer.wl_surface();
+        if let Some(buffer) = c.buffer.as_ref() {
+            if let Err(err) = buffer.attach_to(surface) {
+                warn!("Failed to attach the hot corner buffer {err:?}");
+            }
+            surface.damage_buffer(0, 0, 1, 1);
+        }
+        c.layer.commit();
+        true
+    }
+
+    /// Stop using the corner `layer` after the compositor closed it. Returns
+    /// `false` if it isn't a hot corner.
+    pub fn closed(&mut self, layer: &LayerSurface) -> bool {
+        let Some(c) = self.corners.iter_mut().find(|c| c.layer.wl_surface() == layer.wl_surface())
+        else {
+            return false;
+        };
+        c.closed = true;
+        c.entered = None;
+        c.left = None;
+        true
+    }
+
+    /// Track the pointer over the corners. Returns `true` if the event was
+    /// on a hot corner.
+    pub fn pointer_event(&mut self, e: &PointerEvent) -> bool {
+        let Some(c) =
+            self.corners.iter_mut().find(|c| !c.closed && *c.layer.wl_surface() == e.surface)
+        else {
+            return false;
+        };
+        match e.kind {
+            PointerEventKind::Enter { .. } => {
+                c.entered = Some(Instant::now());
+            },
+            PointerEventKind::Leave { .. } => {
+                if let Some(entered) = c.entered.take() {
+                    if entered.elapsed() >= c.dwell_time() {
+                        c.left = Some(Instant::now());
+                    }
+                }
+            },
+            _ => {},
+        }
+        true
+    }
+
+    /// The focus of the hot corners of the panel `panel` on `output`, if the
+    /// pointer rested in one of them.
+    pub fn focus(&self, panel: &str, output: &WlOutput) -> Option<FocusStatus> {
+        self.corners
+            .iter()
+            .filter(|c| c.panel == panel && c.output == *output)
+            .filter_map(CornerSurface::focus)
+            .reduce(|acc, f| match (acc, f) {
+                (FocusStatus::LastFocused(t_acc), FocusStatus::LastFocused(t_cur)) => {
+                    FocusStatus::LastFocused(t_acc.max(t_cur))
+                },
+                (FocusStatus::LastFocused(_), FocusStatus::Focused) => f,
+                _ => acc,
+            })
+    }
+}
+
+impl GlobalState {
+    /// Keep the hot corners of the autohidden panels mapped, and reveal the
+    /// panels whose corner the pointer rested in.
+    pub(crate) fn update_hot_corners(&mut self) {
+        let wanted = self.space.hot_corners();
+        let client_state = &mut self.client_state;
+        client_state.hot_corners.sync(
+            wanted,
+            &client_state.compositor_state,
+            &client_state.layer_state,
+            &client_state.shm_state,
+            &client_state.queue_handle,
+        );
+        self.space.apply_hot_corners(&self.client_state.hot_corners);
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use cctk::wayland_client::{
+    self,
+    globals::{BindError, GlobalList},
+    protocol::wl_surface::WlSurface,
+    Connection, Dispatch, Proxy, QueueHandle,
+};
+use sctk::globals::GlobalData;
+use smithay::reexports::wayland_server::backend::ObjectId;
+use wayland_protocols::wp::idle_inhibit::zv1::client::{
+    zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1, zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
+};
+
+use crate::xdg_shell_wrapper::shared_state::GlobalState;
+
+/// Host idle inhibitors held for applet surfaces.
+#[derive(Debug)]
+pub struct IdleInhibitManager {
+    manager: ZwpIdleInhibitManagerV1,
+    /// host inhibitors by embedded surface
+    inhibitors: HashMap<ObjectId, ZwpIdleInhibitorV1>,
+}
+
+impl IdleInhibitManager {
+    pub fn bind(
+        globals: &GlobalList,
+        qh: &QueueHandle<GlobalState>,
+    ) -> Result<IdleInhibitManager, BindError> {
+        let manager = globals.bind(qh, 1..=1, GlobalData)?;
+        Ok(IdleInhibitManager { manager, inhibitors: HashMap::new() })
+    }
+
+    /// Inhibit idle on the host for as long as the embedded surface `id` wants
+    /// it, using the panel surface `host_surface`.
+    pub fn inhibit(
+        &mut self,
+        qh: &QueueHandle<GlobalState>,
+        id: ObjectId,
+        host_surface: &WlSurface,
+    ) {
+        self.inhibitors
+            .entry(id)
+            .or_insert_with(|| self.manager.create_inhibitor(host_surface, qh, GlobalData));
+    }
+
+    pub fn uninhibit(&mut self, id: &ObjectId) {
+        if let Some(inhibitor) = self.inhibitors.remove(id) {
+            inhibitor.destroy();
+        }
+    }
+}
+
+impl Dispatch<ZwpIdleInhibitManagerV1, GlobalData, GlobalState> for IdleInhibitManager {
+    fn event(
+        _: &mut GlobalState,
+        _: &ZwpIdleInhibitManagerV1,
+        _: <ZwpIdleInhibitManagerV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwpIdleInhibitorV1, GlobalData, GlobalState> for IdleInhibitManager {
+    fn event(
+        _: &mut GlobalState,
+        _: &ZwpIdleInhibitorV1,
+        _: <ZwpIdleInhibitorV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+    }
+}
+
+wayland_client::delegate_dispatch!(GlobalState: [ZwpIdleInhibitManagerV1: GlobalData] => IdleInhibitManager);
+wayland_client::delegate_dispatch!(GlobalState: [ZwpIdleInhibitorV1: GlobalData] => IdleInhibitManager);
@@ -2,14 +2,18 @@ use std::time::Instant;
 
 use crate::xdg_shell_wrapper::{
     client_state::FocusStatus, server_state::SeatPair, shared_state::GlobalState,
-    space::WrapperSpace,
+    space::InputRouter,
 };
 use sctk::{
     delegate_keyboard,
     seat::keyboard::{KeyCode, KeyboardHandler, Keysym, RawModifiers, RepeatInfo},
     shell::WaylandSurface,
 };
-use smithay::{backend::input::KeyState, input::keyboard::FilterResult, utils::SERIAL_COUNTER};
+use smithay::{
+    backend::input::KeyState,
+    input::keyboard::{FilterResult, Layout},
+    utils::SERIAL_COUNTER,
+};
 
 impl KeyboardHandler for GlobalState {
     fn enter(
@@ -23,6 +27,10 @@ impl KeyboardHandler for GlobalState {
         _keysyms: &[Keysym],
     ) {
         let _ = _keysyms;
+        // applets don't get input while the session is locked
+        if self.space.session_locked {
+            return;
+        }
         let (seat_name, kbd) = if let Some((name, Some(kbd))) = self
             .server_state
             .seats
@@ -91,7 +99,7 @@ impl KeyboardHandler for GlobalState {
 
         let kbd_focus = {
             let mut c_focused_surface = self.client_state.focused_surface.borrow_mut();
-            if let Some(i) = c_focused_surface.iter().position(|f| &f.0 == surface) {
+            if let Some(i) = c_focused_surface.iter().position(|f| &f.0 == surface && f.1 == name) {
                 c_focused_surface[i].2 = FocusStatus::LastFocused(Instant::now());
                 true
             } else {
@@ -119,6 +127,9 @@ impl KeyboardHandler for GlobalState {
         serial: u32,
         event: sctk::seat::keyboard::KeyEvent,
     ) {
+        if self.space.session_locked {
+            return;
+        }
         let (seat_name, kbd) = if let Some((name, Some(kbd), last_key_pressed)) = self
             .server_state
             .seats
@@ -191,7 +202,7 @@ impl KeyboardHandler for GlobalState {
 
         self.client_state
             .last_key_pressed
-            .retain(|(seat_name, raw_code, _s)| seat_name != &name && raw_code.0 == event.raw_code);
+            .retain(|(seat_name, raw_code, _s)| seat_name != &name || raw_code.0 != event.raw_code);
 
         kbd.input::<(), _>(
             self,
@@ -222,9 +233,10 @@ impl KeyboardHandler for GlobalState {
             })
         {
             match info {
-                RepeatInfo::Repeat { rate, delay } => {
-                    kbd.change_repeat_info(u32::from(rate) as i32, delay.try_into().unwrap())
-                },
+                RepeatInfo::Repeat { rate, delay } => kbd.change_repeat_info(
+                    u32::from(rate).min(i32::MAX as u32) as i32,
+                    delay.min(i32::MAX as u32) as i32,
+                ),
                 RepeatInfo::Disable => kbd.change_repeat_info(0, 0),
             };
         }
@@ -253,6 +265,11 @@ impl KeyboardHandler for GlobalState {
 
         if let Err(err) = kbd.set_keymap_from_string(self, keymap.as_string()) {
             tracing::error!("Failed to set keymap for seat {}: {}", name, err);
+            return;
+        }
+        // a new keymap starts with its first layout
+        if let Some(seat) = self.server_state.seats.iter_mut().find(|s| s.name == name) {
+            seat.client.layout = 0;
         }
     }
 
@@ -260,13 +277,29 @@ impl KeyboardHandler for GlobalState {
         &mut self,
         _conn: &sctk::reexports::client::Connection,
         _qh: &sctk::reexports::client::QueueHandle<Self>,
-        _keyboard: &sctk::reexports::client::protocol::wl_keyboard::WlKeyboard,
+        keyboard: &sctk::reexports::client::protocol::wl_keyboard::WlKeyboard,
         _serial: u32,
         _modifiers: sctk::seat::keyboard::Modifiers,
         _: RawModifiers,
-        _: u32,
+        layout: u32,
     ) {
-        // TODO should these be handled specially
+        // Modifiers follow from the forwarded keys, but layout switches are
+        // done by the host and only show up here.
+        let Some(seat) = self
+            .server_state
+            .seats
+            .iter_mut()
+            .find(|SeatPair { client, .. }| client.kbd.as_ref() == Some(keyboard))
+        else {
+            return;
+        };
+        if seat.client.layout == layout {
+            return;
+        }
+        seat.client.layout = layout;
+        if let Some(kbd) = seat.server.seat.get_keyboard() {
+            kbd.with_xkb_state(self, |mut context| context.set_layout(Layout(layout)));
+        }
     }
 }
 
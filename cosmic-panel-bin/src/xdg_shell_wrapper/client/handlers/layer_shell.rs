@@ -26,7 +26,7 @@ impl LayerShellHandler for GlobalState {
             .position(|(_, _, _, s, ..)| s.wl_surface() == layer.wl_surface())
         {
             self.client_state.proxied_layer_surfaces.remove(i);
-        } else {
+        } else if !self.client_state.hot_corners.closed(layer) {
             self.space.close_layer(layer);
         }
     }
@@ -69,7 +69,8 @@ impl LayerShellHandler for GlobalState {
                 });
                 s_layer_surface.layer_surface().send_configure();
             }
-        } else {
+        } else if !self.client_state.hot_corners.configure(layer) {
+            crate::startup::mark(crate::startup::Phase::LayerMap);
             self.space.configure_layer(layer, configure);
         }
     }
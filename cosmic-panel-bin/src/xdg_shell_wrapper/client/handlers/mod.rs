@@ -11,17 +11,27 @@ use sctk::{
 
 use crate::xdg_shell_wrapper::shared_state::GlobalState;
 
+pub mod activation;
 pub mod compositor;
 pub mod data_device;
+pub mod foreign_toplevel_list;
+pub mod hot_corner;
+pub mod idle_inhibit;
 pub mod keyboard;
 pub mod layer_shell;
 /// output helpers
 pub mod output;
 pub mod overlap;
 pub mod pointer;
+pub mod pointer_constraints;
+pub mod pointer_gestures;
+pub mod primary_selection;
+pub mod screencopy;
 pub mod seat;
 pub mod shell;
+pub mod text_input;
 pub mod toplevel;
+pub mod wlr_toplevel;
 pub mod workspace;
 pub mod wp_fractional_scaling;
 pub mod wp_security_context;
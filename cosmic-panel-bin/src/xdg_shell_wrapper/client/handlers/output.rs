@@ -3,7 +3,10 @@
 use sctk::{
     output::{Mode as c_Mode, OutputHandler, OutputInfo, OutputState},
     reexports::client::{
-        protocol::{wl_output, wl_output::Subpixel as c_Subpixel},
+        protocol::{
+            wl_output,
+            wl_output::{Subpixel as c_Subpixel, Transform as c_Transform},
+        },
         Connection, QueueHandle,
     },
 };
@@ -16,8 +19,10 @@ use tracing::{error, info, warn};
 use xdg_shell_wrapper_config::WrapperConfig;
 
 use crate::xdg_shell_wrapper::{
-    client_state::ClientState, server_state::ServerState, shared_state::GlobalState,
-    space::WrapperSpace,
+    client_state::ClientState,
+    server_state::ServerState,
+    shared_state::GlobalState,
+    space::{OutputHandler, WrapperSpace},
 };
 
 impl OutputHandler for GlobalState {
@@ -111,6 +116,7 @@ impl OutputHandler for GlobalState {
         if configured_outputs.iter().any(|configured| Some(configured) == info.name.as_ref()) {
             if let Some(saved_output) = self.client_state.outputs.iter_mut().find(|o| o.0 == output)
             {
+                update_s_output(&saved_output.1, &info);
                 let res = space.update_output(output.clone(), saved_output.1.clone(), info.clone());
                 if let Err(err) = res {
                     error!("{}", err);
@@ -187,21 +193,54 @@ pub fn c_output_as_s_output(dh: &DisplayHandle, info: &OutputInfo) -> (Output, G
             model: info.model.clone(),       // model of the monitor
         },
     );
-    for c_Mode { dimensions, refresh_rate, current, preferred } in &info.modes {
-        let s_mode = s_Mode { size: (*dimensions).into(), refresh: *refresh_rate };
+    s_output.change_current_state(None, None, Some(Scale::Integer(info.scale_factor)), None);
+    update_s_output(&s_output, info);
+    let s_output_global = s_output.create_global::<GlobalState>(dh);
+    (s_output, s_output_global)
+}
+
+fn c_transform_as_s_transform(transform: c_Transform) -> Transform {
+    match transform {
+        c_Transform::_90 => Transform::_90,
+        c_Transform::_180 => Transform::_180,
+        c_Transform::_270 => Transform::_270,
+        c_Transform::Flipped => Transform::Flipped,
+        c_Transform::Flipped90 => Transform::Flipped90,
+        c_Transform::Flipped180 => Transform::Flipped180,
+        c_Transform::Flipped270 => Transform::Flipped270,
+        _ => Transform::Normal,
+    }
+}
+
+/// Update the modes, transform and location of a server output from its
+/// client output, so applets see the geometry of the output of their panel.
+///
+/// The scale is left alone, because it is set from the preferred scale of the
+/// panel surface, which may be fractional.
+pub fn update_s_output(s_output: &Output, info: &OutputInfo) {
+    let modes: Vec<_> = info
+        .modes
+        .iter()
+        .map(|m| (s_Mode { size: m.dimensions.into(), refresh: m.refresh_rate }, m))
+        .collect();
+    for old in s_output.modes() {
+        if !modes.iter().any(|(m, _)| *m == old) {
+            s_output.delete_mode(old);
+        }
+    }
+    for (s_mode, c_Mode { current, preferred, .. }) in modes {
+        s_output.add_mode(s_mode);
         if *preferred {
             s_output.set_preferred(s_mode);
         }
         if *current {
-            s_output.change_current_state(
-                Some(s_mode),
-                Some(Transform::Normal),
-                Some(Scale::Integer(info.scale_factor)),
-                Some(info.location.into()),
-            )
+            s_output.change_current_state(Some(s_mode), None, None, None);
         }
-        s_output.add_mode(s_mode);
     }
-    let s_output_global = s_output.create_global::<GlobalState>(dh);
-    (s_output, s_output_global)
+    s_output.change_current_state(
+        None,
+        Some(c_transform_as_s_transform(info.transform)),
+        None,
+        Some(info.location.into()),
+    );
 }
@@ -15,6 +15,8 @@ use sctk::{globals::GlobalData, shell::WaylandSurface};
 
 use crate::xdg_shell_wrapper::shared_state::GlobalState;
 
+use super::foreign_toplevel_list::ForeignToplevelData;
+
 #[derive(Debug, Clone)]
 pub struct OverlapNotifyV1 {
     pub(crate) notify: ZcosmicOverlapNotifyV1,
@@ -69,6 +71,9 @@ impl Dispatch<ZcosmicOverlapNotificationV1, OverlapNotificationV1, GlobalState>
                 },
                 zcosmic_overlap_notification_v1::Event::ToplevelLeave { ref toplevel } => {
                     s.toplevel_overlaps.remove(&toplevel.id());
+                    // the handle is only used for this overlap
+                    state.space.overlap_app_ids.remove(&toplevel.id());
+                    toplevel.destroy();
                 },
                 _ => {},
             }
@@ -76,7 +81,7 @@ impl Dispatch<ZcosmicOverlapNotificationV1, OverlapNotificationV1, GlobalState>
     }
 
     event_created_child!(GlobalState, ZcosmicOverlapNotifyV1, [
-        0 => (ExtForeignToplevelHandleV1, Default::default())
+        0 => (ExtForeignToplevelHandleV1, ForeignToplevelData::overlap())
     ]);
 }
 
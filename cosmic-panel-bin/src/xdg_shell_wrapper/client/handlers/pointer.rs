@@ -4,7 +4,7 @@ use crate::xdg_shell_wrapper::{
     client_state::FocusStatus,
     server_state::{SeatPair, ServerPointerFocus},
     shared_state::GlobalState,
-    space::WrapperSpace,
+    space::InputRouter,
 };
 use sctk::{
     delegate_pointer,
@@ -65,21 +65,49 @@ impl GlobalState {
         pointer: &sctk::reexports::client::protocol::wl_pointer::WlPointer,
         events: &[sctk::seat::pointer::PointerEvent],
     ) {
+        // applets don't get input while the session is locked
+        if self.space.session_locked {
+            return;
+        }
         let start_time = self.start_time;
         let time = start_time.elapsed().as_millis();
 
-        let seat_index = self
-            .server_state
-            .seats
-            .iter()
-            .position(|SeatPair { client, .. }| {
+        // events may still arrive for a seat which was just removed
+        let Some(seat_index) =
+            self.server_state.seats.iter().position(|SeatPair { client, .. }| {
                 client.ptr.as_ref().map(|p| p.pointer() == pointer).unwrap_or(false)
             })
-            .unwrap();
+        else {
+            return;
+        };
         let seat_name = self.server_state.seats[seat_index].name.to_string();
-        let ptr = self.server_state.seats[seat_index].server.seat.get_pointer().unwrap();
-        let kbd = self.server_state.seats[seat_index].server.seat.get_keyboard().unwrap();
+        let (Some(ptr), Some(kbd)) = (
+            self.server_state.seats[seat_index].server.seat.get_pointer(),
+            self.server_state.seats[seat_index].server.seat.get_keyboard(),
+        ) else {
+            return;
+        };
         for e in events {
+            if self.client_state.hot_corners.pointer_event(e) {
+                continue;
+            }
+            // applets are dragged instead of receiving input in edit mode, and
+            // the panel menu handles its own input
+            if self.space.edit_pointer_event(&seat_name, e)
+                || self.space.context_menu_pointer_event(&seat_name, e)
+            {
+                ptr.motion(
+                    self,
+                    None,
+                    &MotionEvent {
+                        location: e.position.into(),
+                        serial: SERIAL_COUNTER.next_serial(),
+                        time: time.try_into().unwrap(),
+                    },
+                );
+                ptr.frame(self);
+                continue;
+            }
             let seat = &mut self.server_state.seats[seat_index];
             match e.kind {
                 sctk::seat::pointer::PointerEventKind::Leave { .. } => {
@@ -104,7 +132,10 @@ impl GlobalState {
                     }
 
                     let mut c_hovered_surface = self.client_state.hovered_surface.borrow_mut();
-                    for f in c_hovered_surface.iter_mut().filter(|f| f.0 == e.surface) {
+                    for f in c_hovered_surface
+                        .iter_mut()
+                        .filter(|f| f.0 == e.surface && f.1 == seat_name)
+                    {
                         f.2 = FocusStatus::LastFocused(Instant::now());
                     }
                     drop(c_hovered_surface);
@@ -118,13 +149,17 @@ impl GlobalState {
                     let (surface_x, surface_y) = e.position;
 
                     {
+                        // hover in a reveal-blocking zone doesn't reveal the
+                        // hidden panel
+                        let status = if self.space.reveal_blocked(&e.surface, e.position) {
+                            FocusStatus::LastFocused(Instant::now())
+                        } else {
+                            FocusStatus::Focused
+                        };
+                        // other seats keep hovering their surfaces
                         let mut c_hovered_surface = self.client_state.hovered_surface.borrow_mut();
-                        c_hovered_surface.clear();
-                        c_hovered_surface.push((
-                            e.surface.clone(),
-                            seat_name.to_string(),
-                            FocusStatus::Focused,
-                        ));
+                        c_hovered_surface.retain(|f| f.1 != seat_name);
+                        c_hovered_surface.push((e.surface.clone(), seat_name.to_string(), status));
                     }
 
                     // check tracked layer shell surface
@@ -154,7 +189,7 @@ impl GlobalState {
 
                     if let Some(ServerPointerFocus { surface, c_pos, s_pos, .. }) =
                         self.space.pointer_enter(
-                            (surface_x as i32, surface_y as i32),
+                            (surface_x, surface_y),
                             &seat_name,
                             e.surface.clone(),
                             pointer,
@@ -203,6 +238,25 @@ impl GlobalState {
                         None => continue,
                     };
 
+                    {
+                        let blocked = self.space.reveal_blocked(&e.surface, e.position);
+                        let mut c_hovered_surface = self.client_state.hovered_surface.borrow_mut();
+                        if let Some(f) = c_hovered_surface
+                            .iter_mut()
+                            .find(|f| f.0 == e.surface && f.1 == seat_name)
+                        {
+                            match (blocked, f.2) {
+                                (true, FocusStatus::Focused) => {
+                                    f.2 = FocusStatus::LastFocused(Instant::now());
+                                },
+                                (false, FocusStatus::LastFocused(_)) => {
+                                    f.2 = FocusStatus::Focused;
+                                },
+                                _ => {},
+                            }
+                        }
+                    }
+
                     // check tracked layer shell surface
                     let s_surface = self.client_state.proxied_layer_surfaces.iter_mut().find_map(
                         |(_, _, s, c, ..)| {
@@ -230,7 +284,7 @@ impl GlobalState {
 
                     if let Some(ServerPointerFocus { surface, c_pos, s_pos, .. }) =
                         self.space.update_pointer(
-                            (surface_x as i32, surface_y as i32),
+                            (surface_x, surface_y),
                             &seat_name,
                             c_focused_surface,
                             &pointer,
@@ -274,6 +328,11 @@ impl GlobalState {
                 sctk::seat::pointer::PointerEventKind::Press { time, button, serial, .. } => {
                     self.server_state.last_button.replace(button);
                     seat.client.last_pointer_press = (serial, time);
+                    self.client_state.pressed_buttons.push((
+                        seat_name.clone(),
+                        (button, time),
+                        e.surface.clone(),
+                    ));
                     // check tracked layer shell surface
                     let s_surface = self.client_state.proxied_layer_surfaces.iter_mut().find_map(
                         |(_, _, s, c, ..)| {
@@ -317,6 +376,9 @@ impl GlobalState {
                 },
                 sctk::seat::pointer::PointerEventKind::Release { time, button, .. } => {
                     self.server_state.last_button.replace(button);
+                    self.client_state
+                        .pressed_buttons
+                        .retain(|(name, pressed, _)| name != &seat_name || pressed.0 != button);
 
                     // check tracked layer shell surface
                     let s_surface = self.client_state.proxied_layer_surfaces.iter_mut().find_map(
@@ -378,6 +440,24 @@ impl GlobalState {
                         _ => continue,
                     };
 
+                    // a horizontal two-finger scroll on a panel swipes between
+                    // workspaces, until the fingers are lifted
+                    if source == input::AxisSource::Finger {
+                        if !self.space.swiping()
+                            && horizontal.absolute.abs() > vertical.absolute.abs()
+                        {
+                            self.space.swipe_begin(&e.surface);
+                        }
+                        if self.space.swiping() {
+                            if horizontal.stop || vertical.stop {
+                                self.swipe_end(false);
+                            } else {
+                                self.space.swipe_update(horizontal.absolute);
+                            }
+                            continue;
+                        }
+                    }
+
                     let mut af = AxisFrame::new(time).source(source);
 
                     if !horizontal.is_none() {
@@ -409,6 +489,7 @@ impl GlobalState {
                 },
             }
         }
+        self.update_host_pointer_constraints();
     }
 }
 
@@ -0,0 +1,312 @@
+//! Pointer constraints of applets, forwarded to the host.
+//!
+//! While an applet constraint is active, the host pointer is locked or confined
+//! on the panel surface the same way, with the region of the applet moved into
+//! the panel. The host constraint is always oneshot, and is created for each
+//! activation of the applet constraint. When the pointer leaves the applet, its
+//! constraint is deactivated, which destroys oneshot constraints. Persistent
+//! constraints stay, and are activated again once the pointer is back in their
+//! region.
+
+use sctk::{
+    compositor::Region,
+    delegate_pointer_constraints, delegate_relative_pointer,
+    reexports::{
+        client::{
+            protocol::{wl_pointer::WlPointer, wl_surface::WlSurface},
+            Connection, QueueHandle,
+        },
+        protocols::wp::{
+            pointer_constraints::zv1::client::{
+                zwp_confined_pointer_v1::ZwpConfinedPointerV1,
+                zwp_locked_pointer_v1::ZwpLockedPointerV1, zwp_pointer_constraints_v1::Lifetime,
+            },
+            relative_pointer::zv1::client::zwp_relative_pointer_v1::ZwpRelativePointerV1,
+        },
+    },
+    seat::{
+        pointer_constraints::PointerConstraintsHandler,
+        relative_pointer::{RelativeMotionEvent, RelativePointerHandler},
+    },
+};
+use smithay::{
+    input::pointer::RelativeMotionEvent as ServerRelativeMotionEvent,
+    reexports::wayland_server::{protocol::wl_surface::WlSurface as ServerSurface, Resource},
+    utils::{Logical, Point},
+    wayland::{
+        compositor::{RectangleKind, RegionAttributes},
+        pointer_constraints::{with_pointer_constraint, PointerConstraint},
+        seat::WaylandFocus,
+    },
+};
+
+use crate::xdg_shell_wrapper::{server_state::SeatPair, shared_state::GlobalState};
+
+/// A constraint of the host pointer, held for an applet surface with an
+/// active pointer constraint.
+#[derive(Debug)]
+pub enum HostPointerConstraint {
+    Locked(ZwpLockedPointerV1, ServerSurface),
+    Confined(ZwpConfinedPointerV1, ServerSurface),
+}
+
+impl HostPointerConstraint {
+    fn surface(&self) -> &ServerSurface {
+        match self {
+            HostPointerConstraint::Locked(_, s) | HostPointerConstraint::Confined(_, s) => s,
+        }
+    }
+
+    pub fn destroy(self) {
+        match self {
+            HostPointerConstraint::Locked(l, _) => l.destroy(),
+            HostPointerConstraint::Confined(c, _) => c.destroy(),
+        }
+    }
+
+    /// Move the cursor position hint of a locked pointer, in coordinates of
+    /// the panel surface.
+    pub fn set_cursor_position_hint(&self, x: f64, y: f64) {
+        if let HostPointerConstraint::Locked(l, _) = self {
+            l.set_cursor_position_hint(x, y);
+        }
+    }
+}
+
+impl GlobalState {
+    /// Activate the constraint of the applet `surface` under the pointer of
+    /// `seat_index` if the pointer is in its region, and constrain the host
+    /// pointer like it.
+    pub(crate) fn activate_pointer_constraint(
+        &mut self,
+        seat_index: usize,
+        surface: &ServerSurface,
+    ) {
+        let seat = &self.server_state.seats[seat_index];
+        let Some(ptr) = seat.server.seat.get_pointer() else {
+            return;
+        };
+        // the location of the applet surface, relative to the panel surface and
+        // to the compositor space of the pointer
+        let Some((offset, s_pos)) = self.space.space_list.iter().find_map(|s| {
+            s.s_hovered_surface
+                .iter()
+                .find(|f| f.seat_name == seat.name)
+                .map(|f| ((f.s_pos - f.c_pos.to_f64()).to_i32_round(), f.s_pos))
+        }) else {
+            return;
+        };
+        let location = (ptr.current_location() - s_pos).to_i32_round();
+        let Some((lock, region)) = with_pointer_constraint(surface, &ptr, |constraint| {
+            let constraint = constraint.filter(|c| !c.is_active())?;
+            if !constraint.region().map_or(true, |r| r.contains(location)) {
+                return None;
+            }
+            constraint.activate();
+            Some((
+                matches!(&*constraint, PointerConstraint::Locked(_)),
+                constraint.region().cloned(),
+            ))
+        }) else {
+            return;
+        };
+        self.constrain_host_pointer(seat_index, surface, lock, region.as_ref(), offset);
+    }
+
+    /// Constrain the host pointer of `seat_index` on the panel surface it
+    /// hovers, for the applet `surface` at `offset` in the panel surface.
+    fn constrain_host_pointer(
+        &mut self,
+        seat_index: usize,
+        surface: &ServerSurface,
+        lock: bool,
+        region: Option<&RegionAttributes>,
+        offset: Point<i32, Logical>,
+    ) {
+        let Some(constraints) = self.client_state.pointer_constraints_state.as_ref() else {
+            return;
+        };
+        let seat = &mut self.server_state.seats[seat_index];
+        let Some(pointer) = seat.client.ptr.as_ref().map(|p| p.pointer().clone()) else {
+            return;
+        };
+        let Some(host_surface) = self
+            .client_state
+            .hovered_surface
+            .borrow()
+            .iter()
+            .find(|f| f.1 == seat.name)
+            .map(|f| f.0.clone())
+        else {
+            return;
+        };
+        if let Some(old) = seat.client.pointer_constraint.take() {
+            old.destroy();
+        }
+
+        // the host copies the region when the constraint is created
+        let host_region = match region {
+            Some(region) => match Region::new(&self.client_state.compositor_state) {
+                Ok(host_region) => {
+                    for (kind, rect) in &region.rects {
+                        let (x, y) = (rect.loc.x + offset.x, rect.loc.y + offset.y);
+                        match kind {
+                            RectangleKind::Add => host_region.add(x, y, rect.size.w, rect.size.h),
+                            RectangleKind::Subtract => {
+                                host_region.subtract(x, y, rect.size.w, rect.size.h)
+                            },
+                        }
+                    }
+                    Some(host_region)
+                },
+                Err(err) => {
+                    tracing::warn!("Failed to create the region of a pointer constraint: {err:?}");
+                    return;
+                },
+            },
+            None => None,
+        };
+        let host_region = host_region.as_ref().map(|r| r.wl_region());
+
+        let qh = &self.client_state.queue_handle;
+        let constraint = if lock {
+            constraints
+                .lock_pointer(&host_surface, &pointer, host_region, Lifetime::Oneshot, qh)
+                .map(|l| HostPointerConstraint::Locked(l, surface.clone()))
+        } else {
+            constraints
+                .confine_pointer(&host_surface, &pointer, host_region, Lifetime::Oneshot, qh)
+                .map(|c| HostPointerConstraint::Confined(c, surface.clone()))
+        };
+        match constraint {
+            Ok(constraint) => seat.client.pointer_constraint = Some(constraint),
+            Err(err) => tracing::warn!("Failed to constrain the host pointer: {err:?}"),
+        }
+    }
+
+    /// Release host constraints whose applet constraint is gone or inactive,
+    /// and activate persistent constraints of the applets under the pointer
+    /// again.
+    pub(crate) fn update_host_pointer_constraints(&mut self) {
+        let mut unconstrained = Vec::new();
+        for (i, seat) in self.server_state.seats.iter_mut().enumerate() {
+            let Some(ptr) = seat.server.seat.get_pointer() else {
+                continue;
+            };
+            let Some(constraint) = seat.client.pointer_constraint.as_ref() else {
+                if let Some(surface) = ptr.current_focus().and_then(|f| f.wl_surface()) {
+                    unconstrained.push((i, surface.into_owned()));
+                }
+                continue;
+            };
+            let surface = constraint.surface();
+            let active = surface.is_alive()
+                && with_pointer_constraint(surface, &ptr, |c| c.is_some_and(|c| c.is_active()));
+            if !active {
+                seat.client.pointer_constraint.take().unwrap().destroy();
+            }
+        }
+        for (seat_index, surface) in unconstrained {
+            self.activate_pointer_constraint(seat_index, &surface);
+        }
+    }
+}
+
+impl PointerConstraintsHandler for GlobalState {
+    fn confined(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _confined_pointer: &ZwpConfinedPointerV1,
+        _surface: &WlSurface,
+        _pointer: &WlPointer,
+    ) {
+    }
+
+    fn unconfined(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        confined_pointer: &ZwpConfinedPointerV1,
+        _surface: &WlSurface,
+        _pointer: &WlPointer,
+    ) {
+        // oneshot constraints are dead once they are released
+        for seat in &mut self.server_state.seats {
+            if let Some(HostPointerConstraint::Confined(c, _)) = &seat.client.pointer_constraint {
+                if c == confined_pointer {
+                    seat.client.pointer_constraint.take().unwrap().destroy();
+                }
+            }
+        }
+    }
+
+    fn locked(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _locked_pointer: &ZwpLockedPointerV1,
+        _surface: &WlSurface,
+        _pointer: &WlPointer,
+    ) {
+    }
+
+    fn unlocked(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        locked_pointer: &ZwpLockedPointerV1,
+        _surface: &WlSurface,
+        _pointer: &WlPointer,
+    ) {
+        for seat in &mut self.server_state.seats {
+            if let Some(HostPointerConstraint::Locked(l, _)) = &seat.client.pointer_constraint {
+                if l == locked_pointer {
+                    seat.client.pointer_constraint.take().unwrap().destroy();
+                }
+            }
+        }
+    }
+}
+
+impl RelativePointerHandler for GlobalState {
+    fn relative_pointer_motion(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _relative_pointer: &ZwpRelativePointerV1,
+        pointer: &WlPointer,
+        event: RelativeMotionEvent,
+    ) {
+        let Some((seat_name, ptr)) = self
+            .server_state
+            .seats
+            .iter()
+            .find(|SeatPair { client, .. }| {
+                client.ptr.as_ref().is_some_and(|p| p.pointer() == pointer)
+            })
+            .and_then(|s| Some((s.name.clone(), s.server.seat.get_pointer()?)))
+        else {
+            return;
+        };
+        let focus = self.space.space_list.iter().find_map(|s| {
+            s.s_hovered_surface
+                .iter()
+                .find(|f| f.seat_name == seat_name)
+                .map(|f| (f.surface.clone(), f.s_pos))
+        });
+        ptr.relative_motion(
+            self,
+            focus,
+            &ServerRelativeMotionEvent {
+                delta: event.delta.into(),
+                delta_unaccel: event.delta_unaccel.into(),
+                utime: event.utime,
+            },
+        );
+        ptr.frame(self);
+    }
+}
+
+delegate_pointer_constraints!(GlobalState);
+delegate_relative_pointer!(GlobalState);
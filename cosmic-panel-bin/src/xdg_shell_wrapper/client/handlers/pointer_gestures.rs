@@ -0,0 +1,103 @@
+use cctk::wayland_client::{
+    self,
+    globals::{BindError, GlobalList},
+    protocol::wl_pointer::WlPointer,
+    Connection, Dispatch, Proxy, QueueHandle,
+};
+use sctk::globals::GlobalData;
+use wayland_protocols::wp::pointer_gestures::zv1::client::{
+    zwp_pointer_gesture_swipe_v1::{self, ZwpPointerGestureSwipeV1},
+    zwp_pointer_gestures_v1::ZwpPointerGesturesV1,
+};
+
+use crate::xdg_shell_wrapper::shared_state::GlobalState;
+
+/// Touchpad swipe gestures of the host pointers, which switch workspaces when
+/// they are on a panel. The compositor usually takes swipes of three or more
+/// fingers for itself, and two-finger swipes are scrolls, handled with the
+/// other pointer events.
+#[derive(Debug)]
+pub struct PointerGestures {
+    manager: ZwpPointerGesturesV1,
+}
+
+impl PointerGestures {
+    pub fn bind(
+        globals: &GlobalList,
+        qh: &QueueHandle<GlobalState>,
+    ) -> Result<PointerGestures, BindError> {
+        let manager = globals.bind(qh, 1..=3, GlobalData)?;
+        Ok(PointerGestures { manager })
+    }
+
+    /// Get the swipe gestures of the host pointer `pointer`.
+    pub fn swipe(
+        &self,
+        qh: &QueueHandle<GlobalState>,
+        pointer: &WlPointer,
+    ) -> ZwpPointerGestureSwipeV1 {
+        self.manager.get_swipe_gesture(pointer, qh, GlobalData)
+    }
+}
+
+impl GlobalState {
+    /// Finish the swipe on a panel, and switch to the workspace it was swiped
+    /// to if it was complete.
+    pub(crate) fn swipe_end(&mut self, cancelled: bool) {
+        let Some((output, forward)) = self.space.swipe_end(cancelled) else {
+            return;
+        };
+        let Some(workspace) = self.space.neighbor_workspace(&output, forward) else {
+            return;
+        };
+        let Some(manager) = self
+            .client_state
+            .workspace_state
+            .as_ref()
+            .and_then(|w| w.workspace_manager().get().ok())
+        else {
+            return;
+        };
+        workspace.activate();
+        manager.commit();
+    }
+}
+
+impl Dispatch<ZwpPointerGesturesV1, GlobalData, GlobalState> for PointerGestures {
+    fn event(
+        _: &mut GlobalState,
+        _: &ZwpPointerGesturesV1,
+        _: <ZwpPointerGesturesV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwpPointerGestureSwipeV1, GlobalData, GlobalState> for PointerGestures {
+    fn event(
+        state: &mut GlobalState,
+        _: &ZwpPointerGestureSwipeV1,
+        event: <ZwpPointerGestureSwipeV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+        match event {
+            zwp_pointer_gesture_swipe_v1::Event::Begin { surface, .. } => {
+                state.space.swipe_begin(&surface);
+            },
+            zwp_pointer_gesture_swipe_v1::Event::Update { dx, .. } => {
+                state.space.swipe_update(dx);
+            },
+            zwp_pointer_gesture_swipe_v1::Event::End { cancelled, .. } => {
+                state.swipe_end(cancelled != 0);
+            },
+            _ => {},
+        }
+    }
+}
+
+wayland_client::delegate_dispatch!(GlobalState: [ZwpPointerGesturesV1: GlobalData] => PointerGestures);
+wayland_client::delegate_dispatch!(GlobalState: [ZwpPointerGestureSwipeV1: GlobalData] => PointerGestures);
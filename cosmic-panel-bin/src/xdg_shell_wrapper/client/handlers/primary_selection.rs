@@ -0,0 +1,113 @@
+//! Forwarding of the host primary selection to applets, and of the primary
+//! selection of applets to the host, like the clipboard in `data_device`.
+
+use sctk::{
+    data_device_manager::WritePipe,
+    delegate_primary_selection,
+    primary_selection::{
+        device::{PrimarySelectionDeviceData, PrimarySelectionDeviceHandler},
+        selection::PrimarySelectionSourceHandler,
+    },
+    reexports::{
+        client::{Connection, Proxy, QueueHandle},
+        protocols::wp::primary_selection::zv1::client::{
+            zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1,
+            zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
+        },
+    },
+};
+use smithay::wayland::selection::primary_selection::{
+    request_primary_client_selection, set_primary_selection,
+};
+use tracing::error;
+
+use crate::xdg_shell_wrapper::shared_state::GlobalState;
+
+use super::data_device::transfer;
+
+impl PrimarySelectionDeviceHandler for GlobalState {
+    fn selection(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        primary_selection_device: &ZwpPrimarySelectionDeviceV1,
+    ) {
+        let seat = match self.server_state.seats.iter_mut().find(|sp| {
+            sp.client
+                .primary_selection_device
+                .as_ref()
+                .is_some_and(|d| d.inner() == primary_selection_device)
+        }) {
+            Some(sp) => sp,
+            None => return,
+        };
+
+        // ignore our own selection offer
+        if seat.client.next_primary_selection_offer_is_mine {
+            seat.client.next_primary_selection_offer_is_mine = false;
+            return;
+        }
+
+        let offer = match primary_selection_device
+            .data::<PrimarySelectionDeviceData>()
+            .and_then(|d| d.selection_offer())
+        {
+            Some(offer) => offer,
+            None => return,
+        };
+
+        let mime_types = offer.with_mime_types(|m| transfer::filter_mime_types(m.iter().cloned()));
+        seat.client.primary_selection_offer = Some(offer);
+        set_primary_selection(&self.server_state.display_handle, &seat.server.seat, mime_types, ())
+    }
+}
+
+impl PrimarySelectionSourceHandler for GlobalState {
+    fn send_request(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        source: &ZwpPrimarySelectionSourceV1,
+        mime: String,
+        write_pipe: WritePipe,
+    ) {
+        let seat = match self.server_state.seats.iter().find(|seat| {
+            seat.client.primary_selection_source.as_ref().is_some_and(|s| s.inner() == source)
+        }) {
+            Some(seat) => seat,
+            None => return,
+        };
+        if seat.server.primary_selection_source.is_none() {
+            return;
+        }
+
+        match transfer::start_transfer(&self.space.loop_handle, write_pipe.into()) {
+            Ok(fd) => {
+                _ = request_primary_client_selection(&seat.server.seat, mime, fd);
+            },
+            Err(err) => error!("Failed to start a transfer {err:?}"),
+        }
+    }
+
+    fn cancelled(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        source: &ZwpPrimarySelectionSourceV1,
+    ) {
+        let seat = match self.server_state.seats.iter_mut().find(|seat| {
+            seat.client.primary_selection_source.as_ref().is_some_and(|s| s.inner() == source)
+        }) {
+            Some(seat) => seat,
+            None => return,
+        };
+
+        // the host has a new primary selection
+        if let Some(source) = seat.client.primary_selection_source.take() {
+            source.inner().destroy();
+        }
+        seat.server.primary_selection_source = None;
+    }
+}
+
+delegate_primary_selection!(GlobalState);
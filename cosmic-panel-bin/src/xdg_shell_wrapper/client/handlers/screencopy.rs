@@ -0,0 +1,193 @@
+//! Captures of host toplevels with cosmic screencopy, for the window
+//! thumbnails offered to applets.
+
+use cctk::{
+    cosmic_protocols::{
+        image_source::v1::client::{
+            zcosmic_image_source_v1::{self, ZcosmicImageSourceV1},
+            zcosmic_toplevel_image_source_manager_v1::{self, ZcosmicToplevelImageSourceManagerV1},
+        },
+        screencopy::v2::client::{
+            zcosmic_screencopy_frame_v2::{self, ZcosmicScreencopyFrameV2},
+            zcosmic_screencopy_manager_v2::{self, ZcosmicScreencopyManagerV2},
+            zcosmic_screencopy_session_v2::{self, ZcosmicScreencopySessionV2},
+        },
+        toplevel_info::v1::client::zcosmic_toplevel_handle_v1::ZcosmicToplevelHandleV1,
+    },
+    wayland_client::{
+        self,
+        globals::{BindError, GlobalList},
+        protocol::wl_shm,
+        Connection, Dispatch, Proxy, QueueHandle, WEnum,
+    },
+};
+use sctk::globals::GlobalData;
+use smithay::reexports::wayland_server::backend::ObjectId;
+use std::sync::Mutex;
+
+use crate::xdg_shell_wrapper::shared_state::GlobalState;
+
+#[derive(Debug)]
+pub struct ToplevelCapture {
+    screencopy: ZcosmicScreencopyManagerV2,
+    sources: ZcosmicToplevelImageSourceManagerV1,
+}
+
+/// What a capture session offers, once it is complete.
+#[derive(Debug, Default, Clone)]
+pub struct CaptureFormats {
+    pub width: u32,
+    pub height: u32,
+    pub shm_formats: Vec<wl_shm::Format>,
+}
+
+/// A capture session of a host toplevel, for the thumbnail resource `thumbnail`
+/// of the embedded server.
+#[derive(Debug)]
+pub struct CaptureSessionData {
+    pub thumbnail: ObjectId,
+    source: ZcosmicImageSourceV1,
+    pending: Mutex<CaptureFormats>,
+}
+
+#[derive(Debug)]
+pub struct CaptureFrameData {
+    pub thumbnail: ObjectId,
+}
+
+impl ToplevelCapture {
+    pub fn bind(
+        globals: &GlobalList,
+        qh: &QueueHandle<GlobalState>,
+    ) -> Result<ToplevelCapture, BindError> {
+        let screencopy = globals.bind(qh, 1..=1, GlobalData)?;
+        let sources = globals.bind(qh, 1..=1, GlobalData)?;
+        Ok(ToplevelCapture { screencopy, sources })
+    }
+
+    /// Start capturing `toplevel` for the thumbnail `thumbnail`. The formats
+    /// are passed to [`GlobalState::thumbnail_formats`] once known.
+    pub fn create_session(
+        &self,
+        toplevel: &ZcosmicToplevelHandleV1,
+        thumbnail: ObjectId,
+        qh: &QueueHandle<GlobalState>,
+    ) -> ZcosmicScreencopySessionV2 {
+        let source = self.sources.create_source(toplevel, qh, GlobalData);
+        self.screencopy.create_session(
+            &source,
+            zcosmic_screencopy_manager_v2::Options::empty(),
+            qh,
+            CaptureSessionData { thumbnail, source, pending: Default::default() },
+        )
+    }
+}
+
+/// Destroy `session` along with its image source.
+pub fn destroy_session(session: &ZcosmicScreencopySessionV2) {
+    if let Some(data) = session.data::<CaptureSessionData>() {
+        data.source.destroy();
+    }
+    session.destroy();
+}
+
+impl Dispatch<ZcosmicScreencopyManagerV2, GlobalData, GlobalState> for ToplevelCapture {
+    fn event(
+        _: &mut GlobalState,
+        _: &ZcosmicScreencopyManagerV2,
+        _: zcosmic_screencopy_manager_v2::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+    }
+}
+
+impl Dispatch<ZcosmicToplevelImageSourceManagerV1, GlobalData, GlobalState> for ToplevelCapture {
+    fn event(
+        _: &mut GlobalState,
+        _: &ZcosmicToplevelImageSourceManagerV1,
+        _: zcosmic_toplevel_image_source_manager_v1::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+    }
+}
+
+impl Dispatch<ZcosmicImageSourceV1, GlobalData, GlobalState> for ToplevelCapture {
+    fn event(
+        _: &mut GlobalState,
+        _: &ZcosmicImageSourceV1,
+        _: zcosmic_image_source_v1::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+    }
+}
+
+impl Dispatch<ZcosmicScreencopySessionV2, CaptureSessionData, GlobalState> for ToplevelCapture {
+    fn event(
+        state: &mut GlobalState,
+        session: &ZcosmicScreencopySessionV2,
+        event: zcosmic_screencopy_session_v2::Event,
+        data: &CaptureSessionData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+        let mut pending = data.pending.lock().unwrap();
+        match event {
+            zcosmic_screencopy_session_v2::Event::BufferSize { width, height } => {
+                pending.width = width;
+                pending.height = height;
+            },
+            zcosmic_screencopy_session_v2::Event::ShmFormat { format } => {
+                if let WEnum::Value(format) = format {
+                    pending.shm_formats.push(format);
+                }
+            },
+            zcosmic_screencopy_session_v2::Event::Done => {
+                let formats = std::mem::take(&mut *pending);
+                drop(pending);
+                state.thumbnail_formats(&data.thumbnail, formats);
+            },
+            zcosmic_screencopy_session_v2::Event::Stopped => {
+                drop(pending);
+                state.thumbnail_stopped(&data.thumbnail);
+                session.destroy();
+                data.source.destroy();
+            },
+            _ => {},
+        }
+    }
+}
+
+impl Dispatch<ZcosmicScreencopyFrameV2, CaptureFrameData, GlobalState> for ToplevelCapture {
+    fn event(
+        state: &mut GlobalState,
+        frame: &ZcosmicScreencopyFrameV2,
+        event: zcosmic_screencopy_frame_v2::Event,
+        data: &CaptureFrameData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+        match event {
+            zcosmic_screencopy_frame_v2::Event::Ready => {
+                state.thumbnail_captured(&data.thumbnail, true);
+                frame.destroy();
+            },
+            zcosmic_screencopy_frame_v2::Event::Failed { .. } => {
+                state.thumbnail_captured(&data.thumbnail, false);
+                frame.destroy();
+            },
+            _ => {},
+        }
+    }
+}
+
+wayland_client::delegate_dispatch!(GlobalState: [ZcosmicScreencopyManagerV2: GlobalData] => ToplevelCapture);
+wayland_client::delegate_dispatch!(GlobalState: [ZcosmicToplevelImageSourceManagerV1: GlobalData] => ToplevelCapture);
+wayland_client::delegate_dispatch!(GlobalState: [ZcosmicImageSourceV1: GlobalData] => ToplevelCapture);
+wayland_client::delegate_dispatch!(GlobalState: [ZcosmicScreencopySessionV2: CaptureSessionData] => ToplevelCapture);
+wayland_client::delegate_dispatch!(GlobalState: [ZcosmicScreencopyFrameV2: CaptureFrameData] => ToplevelCapture);
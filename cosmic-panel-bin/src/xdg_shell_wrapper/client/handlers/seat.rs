@@ -2,14 +2,25 @@
 
 use sctk::{
     delegate_seat,
-    reexports::client::{protocol::wl_seat, Connection, QueueHandle},
+    reexports::{
+        client::{
+            protocol::{wl_pointer::WlPointer, wl_seat},
+            Connection, Proxy, QueueHandle,
+        },
+        protocols::wp::{
+            pointer_gestures::zv1::client::zwp_pointer_gesture_swipe_v1::ZwpPointerGestureSwipeV1,
+            relative_pointer::zv1::client::zwp_relative_pointer_v1::ZwpRelativePointerV1,
+        },
+    },
     seat::{pointer::ThemeSpec, SeatHandler},
 };
+use smithay::utils::SERIAL_COUNTER;
 
 use crate::xdg_shell_wrapper::{
     client_state::ClientSeat,
     server_state::{SeatPair, ServerSeat},
     shared_state::GlobalState,
+    space::InputRouter,
 };
 
 impl SeatHandler for GlobalState {
@@ -18,81 +29,40 @@ impl SeatHandler for GlobalState {
     }
 
     fn new_seat(&mut self, _conn: &Connection, qh: &QueueHandle<Self>, seat: wl_seat::WlSeat) {
-        if let Some(info) = self.client_state.seat_state.info(&seat) {
-            let name = info.name.unwrap_or_default();
-
-            let mut new_server_seat = self
-                .server_state
-                .seat_state
-                .new_wl_seat(&self.server_state.display_handle, name.clone());
-
-            let kbd = if info.has_keyboard {
-                if let Ok(kbd) = self.client_state.seat_state.get_keyboard(qh, &seat, None) {
-                    Some(kbd)
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
-
-            let ptr = if info.has_pointer {
-                if let Ok(ptr) = self.client_state.seat_state.get_pointer_with_theme(
-                    qh,
-                    &seat,
-                    self.client_state.shm_state.wl_shm(),
-                    self.client_state.compositor_state.create_surface(qh),
-                    ThemeSpec::System,
-                ) {
-                    Some(ptr)
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
-
-            // A lot of clients bind keyboard and pointer unconditionally once on launch..
-            // Initial clients might race the compositor on adding periheral and
-            // end up in a state, where they are not able to receive input.
-            // Additionally a lot of clients don't handle keyboards/pointer objects being
-            // removed very well either and we don't want to crash applications, because the
-            // user is replugging their keyboard or mouse.
-            //
-            // So instead of doing the right thing (and initialize these capabilities as
-            // matching devices appear), we have to surrender to reality and
-            // just always expose a keyboard and pointer.
-            new_server_seat.add_keyboard(Default::default(), 200, 20).unwrap();
-            new_server_seat.add_pointer();
-
-            let data_device = self.client_state.data_device_manager.get_data_device(qh, &seat);
-
-            self.server_state.seats.push(SeatPair {
-                name,
-                client: ClientSeat {
-                    _seat: seat.clone(),
-                    kbd,
-                    ptr,
-                    data_device,
-                    copy_paste_source: None,
-                    dnd_source: None,
-                    last_enter: 0,
-                    last_key_press: (0, 0),
-                    last_pointer_press: (0, 0),
-                    selection_offer: None,
-                    dnd_offer: None,
-                    next_dnd_offer_is_mine: false,
-                    next_selection_offer_is_mine: false,
-                    dnd_icon: None,
-                    // TODO forward touch
-                },
-                server: ServerSeat {
-                    seat: new_server_seat,
-                    selection_source: None,
-                    dnd_source: None,
-                    dnd_icon: None,
-                },
-            });
+        let Some(info) = self.client_state.seat_state.info(&seat) else {
+            return;
+        };
+        let kbd = info
+            .has_keyboard
+            .then(|| self.client_state.seat_state.get_keyboard(qh, &seat, None).ok())
+            .flatten();
+        let ptr = info
+            .has_pointer
+            .then(|| {
+                self.client_state
+                    .seat_state
+                    .get_pointer_with_theme(
+                        qh,
+                        &seat,
+                        self.client_state.shm_state.wl_shm(),
+                        self.client_state.compositor_state.create_surface(qh),
+                        ThemeSpec::System,
+                    )
+                    .ok()
+            })
+            .flatten();
+
+        let relative_ptr = ptr.as_ref().and_then(|p| self.relative_pointer(qh, p.pointer()));
+        let swipe = ptr.as_ref().and_then(|p| self.swipe_gesture(qh, p.pointer()));
+
+        let sp = self.seat_pair(qh, &seat, info.name);
+        if kbd.is_some() {
+            sp.client.kbd = kbd;
+        }
+        if ptr.is_some() {
+            sp.client.ptr = ptr;
+            sp.client.relative_ptr = relative_ptr;
+            sp.client.swipe = swipe;
         }
     }
 
@@ -108,62 +78,40 @@ impl SeatHandler for GlobalState {
         } else {
             return;
         };
-        let sp = if let Some(sp) =
-            self.server_state.seats.iter_mut().find(|sp| sp.client._seat == seat)
-        {
-            sp
-        } else {
-            let name = info.name.clone().unwrap_or_default();
-            let server =
-                self.server_state.seat_state.new_wl_seat(&self.server_state.display_handle, &name);
-            self.server_state.seats.push(SeatPair {
-                name,
-                client: ClientSeat {
-                    _seat: seat.clone(),
-                    kbd: None,
-                    ptr: None,
-                    data_device: self.client_state.data_device_manager.get_data_device(qh, &seat),
-                    copy_paste_source: None,
-                    dnd_source: None,
-                    selection_offer: None,
-                    dnd_offer: None,
-                    last_enter: 0,
-                    last_key_press: (0, 0),
-                    last_pointer_press: (0, 0),
-                    next_selection_offer_is_mine: false,
-                    next_dnd_offer_is_mine: false,
-                    dnd_icon: None, // TODO forward touch
-                },
-                server: ServerSeat {
-                    seat: server,
-                    selection_source: None,
-                    dnd_source: None,
-                    dnd_icon: None,
-                },
-            });
-            self.server_state.seats.last_mut().unwrap()
-        };
 
         match capability {
             sctk::seat::Capability::Keyboard => {
                 if info.has_keyboard {
+                    let kbd = self.client_state.seat_state.get_keyboard(qh, &seat, None).ok();
+                    let sp = self.seat_pair(qh, &seat, info.name);
                     sp.server.seat.add_keyboard(Default::default(), 200, 20).unwrap();
-                    if let Ok(kbd) = self.client_state.seat_state.get_keyboard(qh, &seat, None) {
+                    if let Some(kbd) = kbd {
                         sp.client.kbd.replace(kbd);
                     }
                 }
             },
             sctk::seat::Capability::Pointer => {
                 if info.has_pointer {
+                    let ptr = self
+                        .client_state
+                        .seat_state
+                        .get_pointer_with_theme(
+                            qh,
+                            &seat,
+                            self.client_state.shm_state.wl_shm(),
+                            self.client_state.compositor_state.create_surface(qh),
+                            ThemeSpec::System,
+                        )
+                        .ok();
+                    let relative_ptr =
+                        ptr.as_ref().and_then(|p| self.relative_pointer(qh, p.pointer()));
+                    let swipe = ptr.as_ref().and_then(|p| self.swipe_gesture(qh, p.pointer()));
+                    let sp = self.seat_pair(qh, &seat, info.name);
                     sp.server.seat.add_pointer();
-                    if let Ok(ptr) = self.client_state.seat_state.get_pointer_with_theme(
-                        qh,
-                        &seat,
-                        self.client_state.shm_state.wl_shm(),
-                        self.client_state.compositor_state.create_surface(qh),
-                        ThemeSpec::System,
-                    ) {
+                    if let Some(ptr) = ptr {
                         sp.client.ptr.replace(ptr);
+                        sp.client.relative_ptr = relative_ptr;
+                        sp.client.swipe = swipe;
                     }
                 }
             },
@@ -189,9 +137,25 @@ impl SeatHandler for GlobalState {
         match capability {
             sctk::seat::Capability::Keyboard => {
                 sp.server.seat.remove_keyboard();
+                if let Some(kbd) = sp.client.kbd.take() {
+                    if kbd.version() >= 3 {
+                        kbd.release();
+                    }
+                }
             },
             sctk::seat::Capability::Pointer => {
                 sp.server.seat.remove_pointer();
+                // dropping the themed pointer releases it
+                sp.client.ptr.take();
+                if let Some(relative_ptr) = sp.client.relative_ptr.take() {
+                    relative_ptr.destroy();
+                }
+                if let Some(swipe) = sp.client.swipe.take() {
+                    swipe.destroy();
+                }
+                if let Some(constraint) = sp.client.pointer_constraint.take() {
+                    constraint.destroy();
+                }
             },
             sctk::seat::Capability::Touch => {}, // TODO
             _ => unimplemented!(),
@@ -199,13 +163,132 @@ impl SeatHandler for GlobalState {
     }
 
     fn remove_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, seat: wl_seat::WlSeat) {
-        let _ = if let Some(sp_i) =
+        let removed = if let Some(sp_i) =
             self.server_state.seats.iter().position(|sp| sp.client._seat == seat)
         {
             self.server_state.seats.swap_remove(sp_i)
         } else {
             return;
         };
+        if let Some(text_input_manager) = self.client_state.text_input_manager.as_mut() {
+            text_input_manager.remove_seat(&removed.name);
+        }
+
+        // drop the focus of the seat, so it can't keep a panel revealed or route
+        // input to an applet
+        self.client_state.focused_surface.borrow_mut().retain(|f| f.1 != removed.name);
+        self.client_state.hovered_surface.borrow_mut().retain(|f| f.1 != removed.name);
+        self.client_state.last_key_pressed.retain(|(name, ..)| name != &removed.name);
+        self.client_state.pressed_buttons.retain(|(name, ..)| name != &removed.name);
+        self.space.keyboard_leave(&removed.name, None);
+        self.space.pointer_leave(&removed.name, None);
+        if let Some(kbd) = removed.server.seat.get_keyboard() {
+            kbd.set_focus(self, None, SERIAL_COUNTER.next_serial());
+        }
+        self.server_state.seat_state.remove_seat(&removed.server.seat);
+        if let Some(relative_ptr) = removed.client.relative_ptr {
+            relative_ptr.destroy();
+        }
+        if let Some(swipe) = removed.client.swipe {
+            swipe.destroy();
+        }
+        if let Some(constraint) = removed.client.pointer_constraint {
+            constraint.destroy();
+        }
+    }
+}
+
+impl GlobalState {
+    fn relative_pointer(
+        &self,
+        qh: &QueueHandle<Self>,
+        pointer: &WlPointer,
+    ) -> Option<ZwpRelativePointerV1> {
+        self.client_state.relative_pointer_state.as_ref()?.get_relative_pointer(pointer, qh).ok()
+    }
+
+    fn swipe_gesture(
+        &self,
+        qh: &QueueHandle<Self>,
+        pointer: &WlPointer,
+    ) -> Option<ZwpPointerGestureSwipeV1> {
+        Some(self.client_state.pointer_gestures.as_ref()?.swipe(qh, pointer))
+    }
+
+    /// The seat pair of the host seat `seat`, which is created if it doesn't
+    /// exist yet. Seat pairs are told apart by name, so every embedded seat
+    /// gets a unique one.
+    fn seat_pair(
+        &mut self,
+        qh: &QueueHandle<Self>,
+        seat: &wl_seat::WlSeat,
+        name: Option<String>,
+    ) -> &mut SeatPair {
+        if let Some(i) = self.server_state.seats.iter().position(|sp| &sp.client._seat == seat) {
+            return &mut self.server_state.seats[i];
+        }
+
+        let mut name = name.unwrap_or_default();
+        if name.is_empty() || self.server_state.seats.iter().any(|sp| sp.name == name) {
+            name = format!("{name}-{}", seat.id().protocol_id());
+        }
+        let mut new_server_seat =
+            self.server_state.seat_state.new_wl_seat(&self.server_state.display_handle, &name);
+
+        // A lot of clients bind keyboard and pointer unconditionally once on launch..
+        // Initial clients might race the compositor on adding periheral and
+        // end up in a state, where they are not able to receive input.
+        // Additionally a lot of clients don't handle keyboards/pointer objects being
+        // removed very well either and we don't want to crash applications, because the
+        // user is replugging their keyboard or mouse.
+        //
+        // So instead of doing the right thing (and initialize these capabilities as
+        // matching devices appear), we have to surrender to reality and
+        // just always expose a keyboard and pointer.
+        new_server_seat.add_keyboard(Default::default(), 200, 20).unwrap();
+        new_server_seat.add_pointer();
+
+        self.server_state.seats.push(SeatPair {
+            name,
+            client: ClientSeat {
+                _seat: seat.clone(),
+                kbd: None,
+                ptr: None,
+                relative_ptr: None,
+                swipe: None,
+                pointer_constraint: None,
+                data_device: self.client_state.data_device_manager.get_data_device(qh, seat),
+                copy_paste_source: None,
+                dnd_source: None,
+                last_enter: 0,
+                last_key_press: (0, 0),
+                last_pointer_press: (0, 0),
+                layout: 0,
+                selection_offer: None,
+                dnd_offer: None,
+                next_dnd_offer_is_mine: false,
+                next_selection_offer_is_mine: false,
+                dnd_icon: None,
+                primary_selection_device: self
+                    .client_state
+                    .primary_selection_manager
+                    .as_ref()
+                    .map(|m| m.get_selection_device(qh, seat)),
+                primary_selection_source: None,
+                primary_selection_offer: None,
+                next_primary_selection_offer_is_mine: false,
+                // TODO forward touch
+            },
+            server: ServerSeat {
+                seat: new_server_seat,
+                selection_source: None,
+                primary_selection_source: None,
+                dnd_source: None,
+                dnd_icon: None,
+                popup_focus_return: Vec::new(),
+            },
+        });
+        self.server_state.seats.last_mut().unwrap()
     }
 }
 
@@ -3,7 +3,7 @@ use sctk::{
     shell::xdg::{popup::PopupHandler, window::WindowHandler},
 };
 
-use crate::xdg_shell_wrapper::{shared_state::GlobalState, space::WrapperSpace};
+use crate::xdg_shell_wrapper::{shared_state::GlobalState, space::PopupHost};
 
 impl PopupHandler for GlobalState {
     fn configure(
@@ -31,20 +31,21 @@ impl WindowHandler for GlobalState {
         &mut self,
         _conn: &sctk::reexports::client::Connection,
         _qh: &sctk::reexports::client::QueueHandle<Self>,
-        _window: &sctk::shell::xdg::window::Window,
+        window: &sctk::shell::xdg::window::Window,
     ) {
-        // nothing to be done
+        self.space.close_preview(window);
     }
 
     fn configure(
         &mut self,
         _conn: &sctk::reexports::client::Connection,
         _qh: &sctk::reexports::client::QueueHandle<Self>,
-        _window: &sctk::shell::xdg::window::Window,
-        _configure: sctk::shell::xdg::window::WindowConfigure,
+        window: &sctk::shell::xdg::window::Window,
+        configure: sctk::shell::xdg::window::WindowConfigure,
         _serial: u32,
     ) {
-        // nothing to be done
+        // only preview panels are toplevels
+        self.space.configure_preview(window, configure);
     }
 }
 
@@ -0,0 +1,114 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use cctk::wayland_client::{
+    self,
+    globals::{BindError, GlobalList},
+    protocol::wl_seat::WlSeat,
+    Connection, Dispatch, Proxy, QueueHandle,
+};
+use sctk::globals::GlobalData;
+use wayland_protocols::wp::text_input::zv3::client::{
+    zwp_text_input_manager_v3::ZwpTextInputManagerV3,
+    zwp_text_input_v3::{self, ZwpTextInputV3},
+};
+
+use crate::xdg_shell_wrapper::shared_state::GlobalState;
+
+/// Host text inputs, one per seat, used for the applet with keyboard focus.
+#[derive(Debug)]
+pub struct TextInputManager {
+    manager: ZwpTextInputManagerV3,
+    inputs: HashMap<String, ZwpTextInputV3>,
+}
+
+/// Text input events of the host, applied on the next `done` event.
+#[derive(Debug, Default, Clone)]
+pub struct TextInputEvents {
+    pub preedit: Option<(String, i32, i32)>,
+    pub commit: Option<String>,
+    pub delete_surrounding: Option<(u32, u32)>,
+}
+
+#[derive(Debug)]
+pub struct TextInputData {
+    seat_name: String,
+    pending: Mutex<TextInputEvents>,
+}
+
+impl TextInputManager {
+    pub fn bind(
+        globals: &GlobalList,
+        qh: &QueueHandle<GlobalState>,
+    ) -> Result<TextInputManager, BindError> {
+        let manager = globals.bind(qh, 1..=1, GlobalData)?;
+        Ok(TextInputManager { manager, inputs: HashMap::new() })
+    }
+
+    /// The host text input of the seat named `seat_name`.
+    pub fn text_input(
+        &mut self,
+        qh: &QueueHandle<GlobalState>,
+        seat_name: &str,
+        seat: &WlSeat,
+    ) -> &ZwpTextInputV3 {
+        self.inputs.entry(seat_name.to_string()).or_insert_with(|| {
+            self.manager.get_text_input(
+                seat,
+                qh,
+                TextInputData { seat_name: seat_name.to_string(), pending: Default::default() },
+            )
+        })
+    }
+
+    pub fn remove_seat(&mut self, seat_name: &str) {
+        if let Some(input) = self.inputs.remove(seat_name) {
+            input.destroy();
+        }
+    }
+}
+
+impl Dispatch<ZwpTextInputManagerV3, GlobalData, GlobalState> for TextInputManager {
+    fn event(
+        _: &mut GlobalState,
+        _: &ZwpTextInputManagerV3,
+        _: <ZwpTextInputManagerV3 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwpTextInputV3, TextInputData, GlobalState> for TextInputManager {
+    fn event(
+        state: &mut GlobalState,
+        _: &ZwpTextInputV3,
+        event: <ZwpTextInputV3 as Proxy>::Event,
+        data: &TextInputData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+        let mut pending = data.pending.lock().unwrap();
+        match event {
+            zwp_text_input_v3::Event::PreeditString { text, cursor_begin, cursor_end } => {
+                pending.preedit = Some((text.unwrap_or_default(), cursor_begin, cursor_end));
+            },
+            zwp_text_input_v3::Event::CommitString { text } => {
+                pending.commit = text;
+            },
+            zwp_text_input_v3::Event::DeleteSurroundingText { before_length, after_length } => {
+                pending.delete_surrounding = Some((before_length, after_length));
+            },
+            zwp_text_input_v3::Event::Done { .. } => {
+                let events = std::mem::take(&mut *pending);
+                drop(pending);
+                state.server_state.text_input_forward_state.host_done(&data.seat_name, events);
+            },
+            // focus follows the keyboard focus of the panel surfaces
+            _ => {},
+        }
+    }
+}
+
+wayland_client::delegate_dispatch!(GlobalState: [ZwpTextInputManagerV3: GlobalData] => TextInputManager);
+wayland_client::delegate_dispatch!(GlobalState: [ZwpTextInputV3: TextInputData] => TextInputManager);
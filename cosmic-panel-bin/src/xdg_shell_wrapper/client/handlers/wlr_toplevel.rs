@@ -0,0 +1,117 @@
+//! Toplevel state from wlr-foreign-toplevel-management, used instead of the
+//! cosmic toplevel info on other compositors. It has no workspaces, so all
+//! toplevels of an output count, even if they are on a hidden workspace.
+
+use std::sync::Mutex;
+
+use cctk::wayland_client::{
+    self, event_created_child, globals::BindError, Connection, Dispatch, Proxy, QueueHandle,
+};
+use sctk::{globals::GlobalData, registry::RegistryState};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+use crate::{
+    space_container::toplevel::FallbackToplevel, xdg_shell_wrapper::shared_state::GlobalState,
+};
+
+#[derive(Debug)]
+pub struct WlrToplevelManager {
+    pub(crate) _manager: ZwlrForeignToplevelManagerV1,
+}
+
+impl WlrToplevelManager {
+    pub fn bind(
+        registry_state: &RegistryState,
+        qh: &QueueHandle<GlobalState>,
+    ) -> Result<WlrToplevelManager, BindError> {
+        let _manager = registry_state.bind_one(qh, 1..=3, GlobalData)?;
+        Ok(WlrToplevelManager { _manager })
+    }
+}
+
+/// State of a host toplevel, applied on the next `done` event.
+#[derive(Debug, Default)]
+pub struct WlrToplevelData {
+    pending: Mutex<FallbackToplevel>,
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, GlobalData, GlobalState> for WlrToplevelManager {
+    fn event(
+        state: &mut GlobalState,
+        manager: &ZwlrForeignToplevelManagerV1,
+        event: <ZwlrForeignToplevelManagerV1 as Proxy>::Event,
+        _: &GlobalData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Finished = event {
+            state.client_state.wlr_toplevel_manager = None;
+            let ids: Vec<_> = state.space.fallback_toplevels.keys().cloned().collect();
+            for id in ids {
+                state.space.update_fallback_toplevel(id, None);
+            }
+            manager.stop();
+        }
+    }
+
+    event_created_child!(GlobalState, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, WlrToplevelData::default())
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, WlrToplevelData, GlobalState> for WlrToplevelManager {
+    fn event(
+        state: &mut GlobalState,
+        toplevel: &ZwlrForeignToplevelHandleV1,
+        event: <ZwlrForeignToplevelHandleV1 as Proxy>::Event,
+        data: &WlrToplevelData,
+        _: &Connection,
+        _: &QueueHandle<GlobalState>,
+    ) {
+        let mut pending = data.pending.lock().unwrap();
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => pending.title = title,
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => pending.app_id = app_id,
+            zwlr_foreign_toplevel_handle_v1::Event::OutputEnter { output } => {
+                if !pending.outputs.contains(&output) {
+                    pending.outputs.push(output);
+                }
+            },
+            zwlr_foreign_toplevel_handle_v1::Event::OutputLeave { output } => {
+                pending.outputs.retain(|o| o != &output);
+            },
+            zwlr_foreign_toplevel_handle_v1::Event::State { state: toplevel_state } => {
+                let states: Vec<_> = toplevel_state
+                    .chunks_exact(4)
+                    .filter_map(|s| s.try_into().ok().map(u32::from_ne_bytes))
+                    .filter_map(|s| zwlr_foreign_toplevel_handle_v1::State::try_from(s).ok())
+                    .collect();
+                pending.maximized =
+                    states.contains(&zwlr_foreign_toplevel_handle_v1::State::Maximized);
+                pending.minimized =
+                    states.contains(&zwlr_foreign_toplevel_handle_v1::State::Minimized);
+                pending.fullscreen =
+                    states.contains(&zwlr_foreign_toplevel_handle_v1::State::Fullscreen);
+                pending.activated =
+                    states.contains(&zwlr_foreign_toplevel_handle_v1::State::Activated);
+            },
+            zwlr_foreign_toplevel_handle_v1::Event::Done => {
+                let toplevel_state = pending.clone();
+                drop(pending);
+                state.space.update_fallback_toplevel(toplevel.id(), Some(toplevel_state));
+            },
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                drop(pending);
+                state.space.update_fallback_toplevel(toplevel.id(), None);
+                toplevel.destroy();
+            },
+            _ => {},
+        }
+    }
+}
+
+wayland_client::delegate_dispatch!(GlobalState: [ZwlrForeignToplevelManagerV1: GlobalData] => WlrToplevelManager);
+wayland_client::delegate_dispatch!(GlobalState: [ZwlrForeignToplevelHandleV1: WlrToplevelData] => WlrToplevelManager);
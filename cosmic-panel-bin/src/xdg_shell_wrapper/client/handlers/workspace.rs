@@ -10,6 +10,8 @@ impl WorkspaceHandler for GlobalState {
     fn done(&mut self) {
         let groups = self.client_state.workspace_state.as_ref().unwrap().workspace_groups();
         WorkspaceHandlerSpace::update(&mut self.space, groups);
+        let dh = self.server_state.display_handle.clone();
+        self.server_state.workspace_forward_state.update(&dh, groups, &self.space.outputs);
     }
 }
 
@@ -1,15 +1,19 @@
 use crate::{
     space_container::SpaceContainer,
-    xdg_shell_wrapper::{
-        server_state::ServerState, shared_state::GlobalState, space::WrapperSpace,
-    },
+    xdg_shell_wrapper::{server_state::ServerState, shared_state::GlobalState, space::InputRouter},
 };
 use cctk::{
-    cosmic_protocols::overlap_notify, toplevel_info::ToplevelInfoState,
-    toplevel_management::ToplevelManagerState, wayland_client::protocol::wl_pointer::WlPointer,
+    cosmic_protocols::{
+        overlap_notify,
+        workspace::v1::client::zcosmic_workspace_manager_v1::ZcosmicWorkspaceManagerV1,
+    },
+    toplevel_info::ToplevelInfoState,
+    toplevel_management::ToplevelManagerState,
+    wayland_client::{protocol::wl_pointer::WlPointer, Proxy},
     workspace::WorkspaceState,
 };
 use sctk::{
+    activation::ActivationState,
     compositor::CompositorState,
     data_device_manager::{
         data_device::DataDevice,
@@ -18,9 +22,14 @@ use sctk::{
         DataDeviceManagerState,
     },
     output::OutputState,
+    primary_selection::{
+        device::PrimarySelectionDevice, offer::PrimarySelectionOffer,
+        selection::PrimarySelectionSource, PrimarySelectionManagerState,
+    },
     reexports::{
         calloop_wayland_source::WaylandSource,
         client::{
+            backend::WaylandError,
             globals::registry_queue_init,
             protocol::{
                 wl_keyboard,
@@ -34,6 +43,8 @@ use sctk::{
     registry::RegistryState,
     seat::{
         pointer::{PointerEvent, ThemedPointer},
+        pointer_constraints::PointerConstraintsState,
+        relative_pointer::RelativePointerState,
         SeatState,
     },
     shell::{
@@ -74,12 +85,18 @@ use std::{
 use tracing::error;
 use wayland_protocols::wp::{
     fractional_scale::v1::client::wp_fractional_scale_v1::WpFractionalScaleV1,
+    pointer_gestures::zv1::client::zwp_pointer_gesture_swipe_v1::ZwpPointerGestureSwipeV1,
+    relative_pointer::zv1::client::zwp_relative_pointer_v1::ZwpRelativePointerV1,
     viewporter::client::wp_viewport::WpViewport,
 };
 
 use super::handlers::{
-    overlap::OverlapNotifyV1, wp_fractional_scaling::FractionalScalingManager,
-    wp_security_context::SecurityContextManager, wp_viewporter::ViewporterState,
+    foreign_toplevel_list::ForeignToplevelListV1, hot_corner::HotCorners,
+    idle_inhibit::IdleInhibitManager, overlap::OverlapNotifyV1,
+    pointer_constraints::HostPointerConstraint, pointer_gestures::PointerGestures,
+    screencopy::ToplevelCapture, text_input::TextInputManager, wlr_toplevel::WlrToplevelManager,
+    wp_fractional_scaling::FractionalScalingManager, wp_security_context::SecurityContextManager,
+    wp_viewporter::ViewporterState,
 };
 
 #[derive(Debug)]
@@ -87,9 +104,16 @@ pub(crate) struct ClientSeat {
     pub(crate) _seat: WlSeat,
     pub(crate) kbd: Option<wl_keyboard::WlKeyboard>,
     pub(crate) ptr: Option<ThemedPointer>,
+    pub(crate) relative_ptr: Option<ZwpRelativePointerV1>,
+    /// swipe gestures of the pointer, which switch workspaces on a panel
+    pub(crate) swipe: Option<ZwpPointerGestureSwipeV1>,
+    /// host constraint held for an applet which constrains the pointer
+    pub(crate) pointer_constraint: Option<HostPointerConstraint>,
     pub(crate) last_enter: u32,
     pub(crate) last_key_press: (u32, u32),
     pub(crate) last_pointer_press: (u32, u32),
+    /// active layout of the host keymap
+    pub(crate) layout: u32,
     pub(crate) data_device: DataDevice,
     pub(crate) copy_paste_source: Option<CopyPasteSource>,
     pub(crate) dnd_source: Option<DragSource>,
@@ -97,8 +121,33 @@ pub(crate) struct ClientSeat {
     pub(crate) dnd_offer: Option<DragOffer>,
     pub(crate) next_selection_offer_is_mine: bool,
     pub(crate) next_dnd_offer_is_mine: bool,
-    pub(crate) dnd_icon:
-        Option<(Option<Rc<EGLSurface>>, WlSurface, OutputDamageTracker, bool, Option<u32>)>,
+    pub(crate) dnd_icon: Option<DndIcon>,
+    pub(crate) primary_selection_device: Option<PrimarySelectionDevice>,
+    pub(crate) primary_selection_source: Option<PrimarySelectionSource>,
+    pub(crate) primary_selection_offer: Option<PrimarySelectionOffer>,
+    pub(crate) next_primary_selection_offer_is_mine: bool,
+}
+
+/// The host surface showing the dnd icon of a drag started by an applet.
+#[derive(Debug)]
+pub(crate) struct DndIcon {
+    pub(crate) egl_surface: Option<Rc<EGLSurface>>,
+    pub(crate) surface: WlSurface,
+    pub(crate) damage_tracked_renderer: OutputDamageTracker,
+    pub(crate) is_dirty: bool,
+    pub(crate) has_frame: Option<u32>,
+    /// scale of the panel of the applet, which the icon is rendered at
+    pub(crate) scale: f64,
+    /// viewport scaling the icon back to its logical size
+    pub(crate) viewport: Option<WpViewport>,
+}
+
+impl Drop for DndIcon {
+    fn drop(&mut self) {
+        if let Some(viewport) = self.viewport.take() {
+            viewport.destroy();
+        }
+    }
 }
 
 impl ClientSeat {
@@ -157,6 +206,27 @@ pub struct ClientState {
     pub security_context_manager: Option<SecurityContextManager>,
     /// overlap notifications subscription
     pub overlap_notify: Option<OverlapNotifyV1>,
+    /// host toplevels without cosmic toplevel info
+    pub wlr_toplevel_manager: Option<WlrToplevelManager>,
+    /// host toplevel list, forwarded to the embedded server
+    pub foreign_toplevel_list: Option<ForeignToplevelListV1>,
+    /// host activation, for tokens requested by applets
+    pub activation_state: Option<ActivationState>,
+    /// host idle inhibitors requested by applets
+    pub idle_inhibit_manager: Option<IdleInhibitManager>,
+    /// hot corners which reveal autohidden panels
+    pub(crate) hot_corners: HotCorners,
+    /// host text inputs, bridged to the applet with keyboard focus
+    pub text_input_manager: Option<TextInputManager>,
+    /// host pointer constraints, for applets which lock or confine the pointer
+    pub pointer_constraints_state: Option<PointerConstraintsState>,
+    pub relative_pointer_state: Option<RelativePointerState>,
+    /// host swipe gestures
+    pub pointer_gestures: Option<PointerGestures>,
+    /// host toplevel captures, for the window thumbnails of applets
+    pub toplevel_capture: Option<ToplevelCapture>,
+    /// primary selection manager, forwarding the primary selection
+    pub primary_selection_manager: Option<PrimarySelectionManagerState>,
 
     pub(crate) connection: Connection,
     /// queue handle
@@ -169,6 +239,8 @@ pub struct ClientState {
     pub(crate) multipool: Option<MultiPool<(WlSurface, usize)>>,
     pub(crate) multipool_ctr: usize,
     pub(crate) last_key_pressed: Vec<(String, (u32, u32), wl_surface::WlSurface)>,
+    /// buttons pressed over a panel surface, by seat, with their time
+    pub(crate) pressed_buttons: Vec<(String, (u32, u32), wl_surface::WlSurface)>,
     pub(crate) outputs: Vec<(WlOutput, Output, GlobalId)>,
 
     pub delayed_surface_motion: HashMap<SmithayWlSurface, (PointerEvent, WlPointer, u128)>,
@@ -201,6 +273,7 @@ impl Debug for ClientState {
             .field("xdg_shell_state", &self.xdg_shell_state)
             .field("layer_state", &self.layer_state)
             .field("data_device_manager", &self.data_device_manager)
+            .field("primary_selection_manager", &self.primary_selection_manager)
             .field("fractional_scaling_manager", &self.fractional_scaling_manager)
             .field("viewporter_state", &self.viewporter_state)
             .field("toplevel_info_state", &self.toplevel_info_state)
@@ -213,6 +286,7 @@ impl Debug for ClientState {
             .field("multipool", &self.multipool)
             .field("multipool_ctr", &self.multipool_ctr)
             .field("last_key_pressed", &self.last_key_pressed)
+            .field("pressed_buttons", &self.pressed_buttons)
             .field("outputs", &self.outputs)
             .field("pending_layer_surfaces", &self.pending_layer_surfaces)
             .field("proxied_layer_surfaces", &self.proxied_layer_surfaces)
@@ -283,6 +357,36 @@ impl ClientState {
         if let Err(err) = &overlap_notify {
             tracing::warn!("Failed to bind to overlap notify {err:?}");
         }
+        let activation_state = ActivationState::bind(&globals, &qh);
+        if let Err(err) = &activation_state {
+            tracing::warn!("Failed to bind to xdg activation {err:?}");
+        }
+        let idle_inhibit_manager = IdleInhibitManager::bind(&globals, &qh);
+        if let Err(err) = &idle_inhibit_manager {
+            tracing::warn!("Failed to bind to idle inhibit manager {err:?}");
+        }
+        let text_input_manager = TextInputManager::bind(&globals, &qh);
+        if let Err(err) = &text_input_manager {
+            tracing::warn!("Failed to bind to text input manager {err:?}");
+        }
+        let pointer_constraints_state = PointerConstraintsState::bind(&globals, &qh);
+        let relative_pointer_state = RelativePointerState::bind(&globals, &qh);
+        let pointer_gestures = PointerGestures::bind(&globals, &qh);
+        if let Err(err) = &pointer_gestures {
+            tracing::warn!("Failed to bind to pointer gestures {err:?}");
+        }
+        let foreign_toplevel_list = ForeignToplevelListV1::bind(&globals, &qh);
+        if let Err(err) = &foreign_toplevel_list {
+            tracing::warn!("Failed to bind to foreign toplevel list {err:?}");
+        }
+        let toplevel_capture = ToplevelCapture::bind(&globals, &qh);
+        if let Err(err) = &toplevel_capture {
+            tracing::warn!("Failed to bind to screencopy {err:?}");
+        }
+        let primary_selection_manager = PrimarySelectionManagerState::bind(&globals, &qh);
+        if let Err(err) = &primary_selection_manager {
+            tracing::warn!("Failed to bind to primary selection manager {err:?}");
+        }
 
         let client_state = ClientState {
             focused_surface: space.get_client_focused_surface(),
@@ -302,6 +406,16 @@ impl ClientState {
             data_device_manager: DataDeviceManagerState::bind(&globals, &qh)
                 .expect("data device manager is not available"),
             overlap_notify: overlap_notify.ok(),
+            foreign_toplevel_list: foreign_toplevel_list.ok(),
+            activation_state: activation_state.ok(),
+            idle_inhibit_manager: idle_inhibit_manager.ok(),
+            hot_corners: HotCorners::default(),
+            text_input_manager: text_input_manager.ok(),
+            pointer_constraints_state: pointer_constraints_state.ok(),
+            relative_pointer_state: relative_pointer_state.ok(),
+            pointer_gestures: pointer_gestures.ok(),
+            toplevel_capture: toplevel_capture.ok(),
+            primary_selection_manager: primary_selection_manager.ok(),
 
             outputs: Default::default(),
             registry_state,
@@ -309,9 +423,11 @@ impl ClientState {
             multipool_ctr: 0,
             cursor_surface: None,
             last_key_pressed: Vec::new(),
+            pressed_buttons: Vec::new(),
             fractional_scaling_manager,
             viewporter_state,
             toplevel_info_state: None,
+            wlr_toplevel_manager: None,
             toplevel_manager_state: None,
             workspace_state: None,
             security_context_manager,
@@ -323,6 +439,12 @@ impl ClientState {
         Ok(client_state)
     }
 
+    /// Whether the connection to the compositor was closed, e.g. because the
+    /// compositor exited.
+    pub fn connection_lost(&self) -> bool {
+        matches!(self.connection.flush(), Err(WaylandError::Io(_)))
+    }
+
     /// draw the proxied layer shell surfaces
     pub fn draw_layer_surfaces(&mut self, renderer: &mut GlesRenderer, time: u32) {
         let clear_color = &[0.0, 0.0, 0.0, 0.0];
@@ -365,10 +487,21 @@ impl ClientState {
         }
     }
 
-    /// initialize the toplevel info state
+    /// initialize the toplevel info state, falling back to
+    /// wlr-foreign-toplevel-management on compositors without cosmic protocols
     pub fn init_toplevel_info_state(&mut self) {
         self.toplevel_info_state =
             ToplevelInfoState::try_new(&self.registry_state, &self.queue_handle);
+        if self.toplevel_info_state.is_some() {
+            return;
+        }
+        tracing::info!(
+            "Cosmic toplevel info is not available, using wlr foreign toplevel management"
+        );
+        match WlrToplevelManager::bind(&self.registry_state, &self.queue_handle) {
+            Ok(m) => self.wlr_toplevel_manager = Some(m),
+            Err(err) => tracing::warn!("Failed to bind to wlr foreign toplevel management {err:?}"),
+        }
     }
 
     /// initialize the toplevel manager state
@@ -379,6 +512,15 @@ impl ClientState {
 
     /// initialize the toplevel manager state
     pub fn init_workspace_state(&mut self) {
+        if !self.has_global(ZcosmicWorkspaceManagerV1::interface().name) {
+            tracing::info!("Cosmic workspaces are not available");
+            return;
+        }
         self.workspace_state = Some(WorkspaceState::new(&self.registry_state, &self.queue_handle));
     }
+
+    /// Whether the host compositor advertises a global of `interface`.
+    pub fn has_global(&self, interface: &str) -> bool {
+        self.registry_state.globals_by_interface(interface).next().is_some()
+    }
 }
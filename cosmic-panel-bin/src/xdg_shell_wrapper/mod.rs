@@ -3,7 +3,10 @@
 
 //! Provides the core functionality for cosmic-panel
 
-use std::time::{Duration, Instant};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
 use sctk::{reexports::client::Proxy, shm::multi::MultiPool};
@@ -22,7 +25,7 @@ pub use client::{
 pub use server::state as server_state;
 use server::state::ServerState;
 use shared_state::GlobalState;
-use space::{Visibility, WrapperSpace};
+use space::{ClientHost, RootSpace, Visibility, WrapperSpace};
 pub use xdg_shell_wrapper_config as config;
 
 use crate::space_container::SpaceContainer;
@@ -35,8 +38,22 @@ pub mod shared_state;
 pub mod space;
 /// utilities
 pub mod util;
+mod watchdog;
+
+/// The error [`run`] returns when the connection to the compositor was lost.
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionLost;
+
+impl fmt::Display for ConnectionLost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the connection to the compositor was lost")
+    }
+}
 
-/// run the cosmic panel xdg wrapper with the provided config
+impl std::error::Error for ConnectionLost {}
+
+/// run the cosmic panel xdg wrapper with the provided config, until the
+/// container requests to exit
 pub fn run(
     mut space: SpaceContainer,
     client_state: ClientState,
@@ -109,11 +126,37 @@ pub fn run(
         }
         .max(prev_dur);
 
-        event_loop.dispatch(dur, &mut global_state)?;
+        if let Err(err) = event_loop.dispatch(dur, &mut global_state) {
+            if global_state.client_state.connection_lost() {
+                return Err(ConnectionLost.into());
+            }
+            return Err(err.into());
+        }
+        if global_state.space.exit_requested {
+            return Ok(());
+        }
+        global_state.release_stale_input();
+        global_state.update_hot_corners();
 
         // rendering
+        let render_start = Instant::now();
         {
+            let client_state = &global_state.client_state;
+            global_state.space.ensure_preview(
+                &client_state.compositor_state,
+                client_state.fractional_scaling_manager.as_ref(),
+                client_state.viewporter_state.as_ref(),
+                &client_state.xdg_shell_state,
+                &client_state.queue_handle,
+            );
             let space = &mut global_state.space;
+            space.sync_panel_themes();
+            space.ensure_debug_hud();
+            space.ensure_edit_mode();
+            space.apply_rules();
+            space.apply_suppression();
+            space.update_intellihide();
+            space.update_frozen_applets();
 
             let _ = space.handle_events(
                 &s_dh,
@@ -122,8 +165,12 @@ pub fn run(
                 global_state.start_time.elapsed().as_millis().try_into()?,
                 Some(dur),
             );
+            space.publish_panel_states();
+            space.save_runtime_state();
         }
         global_state.draw_dnd_icon();
+        global_state.update_overlap_info();
+        global_state.update_live_config();
 
         if let Some(renderer) = global_state.space.renderer() {
             global_state.client_state.draw_layer_surfaces(
@@ -132,11 +179,18 @@ pub fn run(
             );
         }
 
+        let render_dur = render_start.elapsed();
+        let texture_usage =
+            crate::iced::buffer_pool::trim(global_state.space.applet_texture_bytes());
+        global_state.space.frame_stats.set_texture_usage(texture_usage);
+
         // dispatch server events
+        let server_dispatch_start = Instant::now();
         {
             server_display.dispatch_clients(&mut global_state)?;
             server_display.flush_clients()?;
         }
+        global_state.space.record_frame(server_dispatch_start.elapsed(), render_dur);
         global_state.iter_count += 1;
 
         let new_visibility_hidden = matches!(global_state.space.visibility(), Visibility::Hidden);
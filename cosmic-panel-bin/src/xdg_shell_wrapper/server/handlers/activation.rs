@@ -0,0 +1,155 @@
+//! xdg-activation for applets.
+//!
+//! Tokens requested by applets are requested from the host compositor for the
+//! panel surface of the applet, with the serial of the last input event of the
+//! host seat, so that windows launched from applets are allowed to take focus.
+
+use std::sync::Mutex;
+
+use sctk::activation::RequestData;
+use smithay::{
+    input::Seat,
+    reexports::{
+        wayland_protocols::xdg::activation::v1::server::{
+            xdg_activation_token_v1::{self, XdgActivationTokenV1},
+            xdg_activation_v1::{self, XdgActivationV1},
+        },
+        wayland_server::{
+            backend::GlobalId, protocol::wl_seat::WlSeat, Client, DataInit, Dispatch,
+            DisplayHandle, GlobalDispatch, New, Resource,
+        },
+    },
+};
+use tracing::warn;
+
+use crate::xdg_shell_wrapper::{
+    client::handlers::activation::ForwardedTokenRequest, shared_state::GlobalState,
+};
+
+#[derive(Debug)]
+pub struct XdgActivationForwardState {
+    _global: GlobalId,
+}
+
+impl XdgActivationForwardState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        XdgActivationForwardState {
+            _global: dh.create_global::<GlobalState, XdgActivationV1, _>(1, ()),
+        }
+    }
+}
+
+/// Properties of a token set by the applet before it commits.
+#[derive(Debug, Default)]
+pub struct PendingToken {
+    app_id: Option<String>,
+    seat: Option<WlSeat>,
+    committed: bool,
+}
+
+impl GlobalState {
+    fn request_host_token(&mut self, client: &Client, token: &XdgActivationTokenV1) {
+        let Some(activation) = self.client_state.activation_state.as_ref() else {
+            // no token the host would accept can be provided
+            token.done(String::new());
+            return;
+        };
+        let Some(pending) = token.data::<Mutex<PendingToken>>() else {
+            return;
+        };
+        let pending = pending.lock().unwrap();
+
+        let seat = pending
+            .seat
+            .as_ref()
+            .and_then(Seat::<GlobalState>::from_resource)
+            .and_then(|seat| self.server_state.seats.iter().find(|s| s.server.seat == seat))
+            .or_else(|| self.server_state.seats.first())
+            .map(|s| (s.client._seat.clone(), s.client.get_serial_of_last_seat_event()));
+        // the host only knows the panel surfaces, so use the one of the applet
+        let surface = self.space.host_surface(client);
+
+        activation.request_token_with_data(
+            &self.client_state.queue_handle,
+            ForwardedTokenRequest {
+                data: RequestData {
+                    app_id: pending.app_id.clone(),
+                    seat_and_serial: seat,
+                    surface,
+                },
+                token: token.clone(),
+            },
+        );
+    }
+}
+
+impl GlobalDispatch<XdgActivationV1, ()> for GlobalState {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<XdgActivationV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<XdgActivationV1, ()> for GlobalState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &XdgActivationV1,
+        request: xdg_activation_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            xdg_activation_v1::Request::GetActivationToken { id } => {
+                data_init.init(id, Mutex::new(PendingToken::default()));
+            },
+            // applet surfaces are always shown, so there is nothing to activate
+            xdg_activation_v1::Request::Activate { .. } => {},
+            _ => {},
+        }
+    }
+}
+
+impl Dispatch<XdgActivationTokenV1, Mutex<PendingToken>> for GlobalState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &XdgActivationTokenV1,
+        request: xdg_activation_token_v1::Request,
+        data: &Mutex<PendingToken>,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let mut pending = data.lock().unwrap();
+        if pending.committed && !matches!(request, xdg_activation_token_v1::Request::Destroy) {
+            resource.post_error(
+                xdg_activation_token_v1::Error::AlreadyUsed,
+                "The activation token has already been committed",
+            );
+            return;
+        }
+        match request {
+            xdg_activation_token_v1::Request::SetSerial { seat, .. } => {
+                pending.seat = Some(seat);
+            },
+            xdg_activation_token_v1::Request::SetAppId { app_id } => {
+                pending.app_id = Some(app_id);
+            },
+            xdg_activation_token_v1::Request::Commit => {
+                pending.committed = true;
+                drop(pending);
+                state.request_host_token(client, resource);
+            },
+            xdg_activation_token_v1::Request::SetSurface { .. } => {},
+            xdg_activation_token_v1::Request::Destroy => {},
+            _ => warn!("Unknown activation token request"),
+        }
+    }
+}
@@ -14,7 +14,10 @@ use smithay::{
     },
     delegate_compositor, delegate_shm,
     desktop::{utils::bbox_from_surface_tree, LayerSurface as SmithayLayerSurface},
-    reexports::wayland_server::protocol::{wl_buffer, wl_surface::WlSurface},
+    reexports::wayland_server::{
+        protocol::{wl_buffer, wl_surface::WlSurface},
+        Resource,
+    },
     utils::{Logical, Size, Transform},
     wayland::{
         buffer::BufferHandler,
@@ -29,7 +32,7 @@ use wayland_egl::WlEglSurface;
 use crate::xdg_shell_wrapper::{
     client_state::{SurfaceState, WrapperClientCompositorState},
     shared_state::GlobalState,
-    space::{ClientEglSurface, WrapperSpace},
+    space::{ClientEglSurface, ClientHost, PopupHost, RootSpace},
 };
 
 impl CompositorHandler for GlobalState {
@@ -263,23 +266,36 @@ impl CompositorHandler for GlobalState {
             };
             if let Some(c_icon) = seat.client.dnd_icon.as_mut() {
                 let size = bbox_from_surface_tree(surface, (0, 0)).size;
+                // the icon is rendered at the scale of the panel, and scaled back
+                // by the viewport, or the buffer scale without one
+                let scaled_size: Size<i32, _> =
+                    size.to_f64().to_physical(c_icon.scale).to_i32_round();
+                match c_icon.viewport.as_ref() {
+                    Some(viewport) => viewport.set_destination(size.w.max(1), size.h.max(1)),
+                    None => c_icon.surface.set_buffer_scale(c_icon.scale.round() as i32),
+                }
 
                 if let Some(renderer) = self.space.renderer() {
                     _ = renderer.unbind();
 
-                    match c_icon.0.clone() {
+                    match c_icon.egl_surface.clone() {
                         Some(egl_surface) => {
                             _ = renderer.bind(egl_surface.clone());
-                            if !egl_surface.resize(size.w.max(1), size.h.max(1), 0, 0) {
+                            if !egl_surface.resize(scaled_size.w.max(1), scaled_size.h.max(1), 0, 0)
+                            {
                                 error!("Failed to resize egl surface");
                             }
                         },
                         None => {
-                            let c_surface = &c_icon.1;
+                            let c_surface = &c_icon.surface;
                             let client_egl_surface = unsafe {
                                 ClientEglSurface::new(
-                                    WlEglSurface::new(c_surface.id(), size.w.max(1), size.h.max(1))
-                                        .unwrap(), /* TODO remove unwrap */
+                                    WlEglSurface::new(
+                                        c_surface.id(),
+                                        scaled_size.w.max(1),
+                                        scaled_size.h.max(1),
+                                    )
+                                    .unwrap(), /* TODO remove unwrap */
                                     c_surface.clone(),
                                 )
                             };
@@ -297,27 +313,34 @@ impl CompositorHandler for GlobalState {
                                 .expect("Failed to create EGL Surface")
                             });
                             _ = renderer.bind(egl_surface.clone());
-                            c_icon.0 = Some(egl_surface);
+                            c_icon.egl_surface = Some(egl_surface);
                         },
                     };
 
                     let _ = renderer.unbind();
-                    c_icon.2 = OutputDamageTracker::new(
-                        (size.w.max(1), size.h.max(1)),
-                        self.space.space_list[0].scale,
+                    c_icon.damage_tracked_renderer = OutputDamageTracker::new(
+                        (scaled_size.w.max(1), scaled_size.h.max(1)),
+                        c_icon.scale,
                         Transform::Flipped180,
                     );
                 }
 
-                c_icon.3 = true;
-                c_icon.1.commit();
-                c_icon.1.frame(&self.client_state.queue_handle, c_icon.1.clone());
+                c_icon.is_dirty = true;
+                c_icon.surface.commit();
+                c_icon.surface.frame(&self.client_state.queue_handle, c_icon.surface.clone());
             }
         } else {
             trace!("{:?}", surface);
         }
     }
 
+    fn destroyed(&mut self, surface: &WlSurface) {
+        // release host idle inhibitors of the surface
+        if let Some(manager) = self.client_state.idle_inhibit_manager.as_mut() {
+            manager.uninhibit(&surface.id());
+        }
+    }
+
     fn client_compositor_state<'a>(
         &self,
         client: &'a smithay::reexports::wayland_server::Client,
@@ -0,0 +1,42 @@
+use cctk::cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_handle_v1::ZcosmicToplevelHandleV1;
+use smithay::{
+    delegate_foreign_toplevel_list,
+    wayland::foreign_toplevel_list::{ForeignToplevelListHandler, ForeignToplevelListState},
+};
+
+use crate::xdg_shell_wrapper::shared_state::GlobalState;
+
+impl GlobalState {
+    /// The host toplevel shown to applets as the forwarded toplevel
+    /// `identifier`. It is matched by the identifier the host gave it, which
+    /// the cosmic toplevel info shares with the toplevel list.
+    pub(crate) fn forwarded_host_toplevel(
+        &self,
+        identifier: &str,
+    ) -> Option<ZcosmicToplevelHandleV1> {
+        let forward = self.client_state.foreign_toplevel_list.as_ref()?;
+        let (id, _) = forward.toplevels.iter().find(|(_, h)| h.identifier() == identifier)?;
+        let host_identifier = forward.host_identifiers.get(id)?;
+        self.space
+            .toplevels
+            .iter()
+            .find(|(_, info)| &info.identifier == host_identifier)
+            .map(|(toplevel, _)| toplevel.clone())
+    }
+
+    /// The identifier of the forwarded toplevel which shows the host toplevel
+    /// with the host identifier `host_identifier` to applets.
+    pub(crate) fn forwarded_identifier(&self, host_identifier: &str) -> Option<String> {
+        let forward = self.client_state.foreign_toplevel_list.as_ref()?;
+        let (id, _) = forward.host_identifiers.iter().find(|(_, i)| *i == host_identifier)?;
+        forward.toplevels.get(id).map(|h| h.identifier())
+    }
+}
+
+impl ForeignToplevelListHandler for GlobalState {
+    fn foreign_toplevel_list_state(&mut self) -> &mut ForeignToplevelListState {
+        &mut self.server_state.foreign_toplevel_list_state
+    }
+}
+
+delegate_foreign_toplevel_list!(GlobalState);
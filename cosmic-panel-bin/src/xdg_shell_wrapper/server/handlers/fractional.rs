@@ -7,7 +7,7 @@ use smithay::{
     },
 };
 
-use crate::xdg_shell_wrapper::{shared_state::GlobalState, space::WrapperSpace};
+use crate::xdg_shell_wrapper::{shared_state::GlobalState, space::OutputHandler};
 
 impl FractionalScaleHandler for GlobalState {
     fn new_fractional_scale(&mut self, surface: WlSurface) {
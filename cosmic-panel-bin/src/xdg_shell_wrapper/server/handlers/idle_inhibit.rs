@@ -0,0 +1,28 @@
+use smithay::{
+    delegate_idle_inhibit,
+    reexports::wayland_server::{protocol::wl_surface::WlSurface, Resource},
+    wayland::idle_inhibit::IdleInhibitHandler,
+};
+
+use crate::xdg_shell_wrapper::shared_state::GlobalState;
+
+impl IdleInhibitHandler for GlobalState {
+    fn inhibit(&mut self, surface: WlSurface) {
+        // the host only knows the panel surfaces, so inhibit with the one of
+        // the applet
+        let Some(host_surface) = surface.client().and_then(|c| self.space.host_surface(&c)) else {
+            return;
+        };
+        if let Some(manager) = self.client_state.idle_inhibit_manager.as_mut() {
+            manager.inhibit(&self.client_state.queue_handle, surface.id(), &host_surface);
+        }
+    }
+
+    fn uninhibit(&mut self, surface: WlSurface) {
+        if let Some(manager) = self.client_state.idle_inhibit_manager.as_mut() {
+            manager.uninhibit(&surface.id());
+        }
+    }
+}
+
+delegate_idle_inhibit!(GlobalState);
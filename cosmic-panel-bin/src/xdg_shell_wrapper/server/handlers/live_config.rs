@@ -0,0 +1,138 @@
+//! Sends applets the config of their panel while it changes at runtime, with
+//! the `cosmic_panel_config_v1` protocol of `protocols/cosmic-panel-config-v1.xml`.
+//!
+//! The entries of each applet are compared with the ones sent last once per
+//! loop iteration, and only the changed entries are sent again. Applets also
+//! request extra length along the panel with it, see `space/expansion.rs`.
+
+use std::collections::BTreeMap;
+
+use smithay::reexports::wayland_server::{
+    backend::{ClientId, GlobalId},
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+};
+
+use crate::xdg_shell_wrapper::shared_state::GlobalState;
+
+use self::protocol::{
+    cosmic_panel_config_manager_v1::{self, CosmicPanelConfigManagerV1},
+    cosmic_panel_config_v1::{self, CosmicPanelConfigV1},
+};
+
+#[allow(non_upper_case_globals, non_camel_case_types, unused_imports, missing_docs)]
+pub mod protocol {
+    use smithay::reexports::wayland_server;
+
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::backend as wayland_backend;
+        wayland_scanner::generate_interfaces!("protocols/cosmic-panel-config-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!("protocols/cosmic-panel-config-v1.xml");
+}
+
+#[derive(Debug)]
+pub struct LiveConfigState {
+    _global: GlobalId,
+    /// subscriptions of applets, with the entries sent last
+    configs: Vec<(CosmicPanelConfigV1, BTreeMap<String, String>)>,
+}
+
+impl LiveConfigState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        LiveConfigState {
+            _global: dh.create_global::<GlobalState, CosmicPanelConfigManagerV1, _>(2, ()),
+            configs: Vec::new(),
+        }
+    }
+}
+
+impl GlobalState {
+    /// Send the changed config entries to applets.
+    pub(crate) fn update_live_config(&mut self) {
+        for (config, sent) in &mut self.server_state.live_config_state.configs {
+            let Some(mut entries) = config
+                .client()
+                .and_then(|c| self.space.space_list.iter().find_map(|s| s.live_config_entries(&c)))
+            else {
+                continue;
+            };
+            entries.insert("do_not_disturb".to_string(), self.space.do_not_disturb.to_string());
+            if *sent == entries {
+                continue;
+            }
+            for (key, value) in &entries {
+                if sent.get(key) != Some(value) {
+                    config.entry(key.clone(), value.clone());
+                }
+            }
+            config.done();
+            *sent = entries;
+        }
+    }
+}
+
+impl GlobalDispatch<CosmicPanelConfigManagerV1, ()> for GlobalState {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<CosmicPanelConfigManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<CosmicPanelConfigManagerV1, ()> for GlobalState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &CosmicPanelConfigManagerV1,
+        request: cosmic_panel_config_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let cosmic_panel_config_manager_v1::Request::GetConfig { id } = request {
+            let config = data_init.init(id, ());
+            // all entries are sent on the next update
+            state.server_state.live_config_state.configs.push((config, BTreeMap::new()));
+        }
+    }
+}
+
+impl Dispatch<CosmicPanelConfigV1, ()> for GlobalState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        _resource: &CosmicPanelConfigV1,
+        request: cosmic_panel_config_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let client = client.id();
+        match request {
+            cosmic_panel_config_v1::Request::RequestExpansion { length } => {
+                for s in &mut state.space.space_list {
+                    if s.request_expansion(&client, length) {
+                        break;
+                    }
+                }
+            },
+            cosmic_panel_config_v1::Request::ReleaseExpansion => {
+                for s in &mut state.space.space_list {
+                    s.release_expansion(&client);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, resource: &CosmicPanelConfigV1, _data: &()) {
+        state.server_state.live_config_state.configs.retain(|(c, _)| c != resource);
+    }
+}
@@ -1,20 +1,28 @@
 use std::{os::fd::OwnedFd, sync::Mutex};
 
-use itertools::Itertools;
 use sctk::{
-    data_device_manager::data_offer::receive_to_fd,
-    reexports::client::protocol::wl_data_device_manager::DndAction as ClientDndAction,
+    data_device_manager::data_offer::{receive_to_fd, DataOfferData},
+    reexports::client::{
+        protocol::{
+            wl_data_device_manager::DndAction as ClientDndAction, wl_data_offer::WlDataOffer,
+        },
+        Proxy,
+    },
 };
 use smithay::{
     backend::renderer::{damage::OutputDamageTracker, ImportDma},
     delegate_data_device, delegate_dmabuf, delegate_output, delegate_primary_selection,
     delegate_seat,
     input::{pointer::CursorImageAttributes, Seat, SeatHandler, SeatState},
-    reexports::wayland_server::{
-        protocol::{
-            wl_data_device_manager::DndAction, wl_data_source::WlDataSource, wl_surface::WlSurface,
+    reexports::{
+        calloop::LoopHandle,
+        wayland_server::{
+            protocol::{
+                wl_data_device_manager::DndAction, wl_data_source::WlDataSource,
+                wl_surface::WlSurface,
+            },
+            Resource,
         },
-        Resource,
     },
     utils::Transform,
     wayland::{
@@ -34,19 +42,33 @@ use smithay::{
         },
     },
 };
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
 
 use crate::{
     iced::elements::target::SpaceTarget,
     xdg_shell_wrapper::{
-        shared_state::GlobalState, space::WrapperSpace, util::write_and_attach_buffer,
+        client::handlers::data_device::transfer,
+        client_state::DndIcon,
+        shared_state::GlobalState,
+        space::{OutputHandler, RootSpace},
+        util::write_and_attach_buffer,
     },
 };
 
+pub(crate) mod activation;
 pub(crate) mod compositor;
+pub(crate) mod foreign_toplevel_list;
 pub(crate) mod fractional;
+pub(crate) mod idle_inhibit;
 pub(crate) mod layer;
+pub(crate) mod live_config;
+pub(crate) mod overlap_info;
+pub(crate) mod pointer_constraints;
+pub(crate) mod text_input;
+pub(crate) mod thumbnail;
+pub(crate) mod toplevel_minimize;
 pub(crate) mod viewporter;
+pub(crate) mod workspace;
 pub(crate) mod xdg_shell;
 
 impl PrimarySelectionHandler for GlobalState {
@@ -74,6 +96,13 @@ impl SeatHandler for GlobalState {
         seat: &smithay::input::Seat<Self>,
         focused: Option<&Self::KeyboardFocus>,
     ) {
+        if let Some(name) =
+            self.server_state.seats.iter().find(|s| &s.server.seat == seat).map(|s| s.name.clone())
+        {
+            let surface = focused.and_then(|s| s.wl_surface()).map(|s| s.into_owned());
+            self.text_input_focus_changed(&name, surface.as_ref());
+        }
+
         let dh = &self.server_state.display_handle;
         let Some(id) = focused.and_then(|s| s.wl_surface()).map(|s| s.id()) else {
             return;
@@ -194,7 +223,7 @@ impl ClientDndGrabHandler for GlobalState {
 
             let dnd_source = self.client_state.data_device_manager.create_drag_and_drop_source(
                 &self.client_state.queue_handle,
-                metadata.mime_types.iter().map(|m| m.as_str()).collect_vec(),
+                transfer::filter_mime_types(metadata.mime_types.iter().cloned()),
                 actions,
             );
             if let Some(focus) =
@@ -211,17 +240,31 @@ impl ClientDndGrabHandler for GlobalState {
                     c_icon_surface.as_ref(),
                     seat.client.get_serial_of_last_seat_event(),
                 );
-                if let Some(client_surface) = c_icon_surface.as_ref() {
+                if let Some((client_surface, icon)) = c_icon_surface.as_ref().zip(icon.as_ref()) {
+                    // the drag may start in the layer surface or any popup of
+                    // the panel, and the icon follows the scale of the panel
+                    let viewport =
+                        self.client_state.viewporter_state.as_ref().map(|v| {
+                            v.get_viewport(client_surface, &self.client_state.queue_handle)
+                        });
+                    let scale = self.space.get_scale_factor(icon).unwrap_or(1.0);
+                    let scale = if viewport.is_some() { scale } else { scale.ceil() };
                     client_surface.frame(&self.client_state.queue_handle, client_surface.clone());
                     client_surface.commit();
 
-                    seat.client.dnd_icon = Some((
-                        None,
-                        client_surface.clone(),
-                        OutputDamageTracker::new((32, 32), 1., Transform::Flipped180),
-                        false,
-                        Some(0),
-                    ));
+                    seat.client.dnd_icon = Some(DndIcon {
+                        egl_surface: None,
+                        surface: client_surface.clone(),
+                        damage_tracked_renderer: OutputDamageTracker::new(
+                            (32, 32),
+                            scale,
+                            Transform::Flipped180,
+                        ),
+                        is_dirty: false,
+                        has_frame: Some(0),
+                        scale,
+                        viewport,
+                    });
                 }
             }
             seat.client.dnd_source = Some(dnd_source);
@@ -250,7 +293,7 @@ impl ServerDndGrabHandler for GlobalState {
             None => return,
         };
         if let Some(offer) = seat.client.dnd_offer.as_ref() {
-            receive_to_fd(offer.inner(), mime_type, fd)
+            receive_from_offer(&self.space.loop_handle, offer.inner(), mime_type, fd);
         }
     }
 
@@ -325,12 +368,46 @@ impl DmabufHandler for GlobalState {
     }
 }
 
+/// Start a buffered transfer of `mime_type` from a host offer of `mime_types`
+/// into the applet fd `fd`, if the type was forwarded to the applet, and
+/// return the fd the host should write into.
+fn start_offer_transfer(
+    loop_handle: &LoopHandle<'static, GlobalState>,
+    mime_types: &[String],
+    mime_type: &str,
+    fd: OwnedFd,
+) -> Option<OwnedFd> {
+    if !transfer::is_forwarded_mime_type(mime_types, mime_type) {
+        warn!("Applet requested the mime type {mime_type:?}, which wasn't offered");
+        return None;
+    }
+    transfer::start_transfer(loop_handle, fd)
+        .map_err(|err| error!("Failed to start a transfer {err:?}"))
+        .ok()
+}
+
+/// Receive `mime_type` of the host data offer into the applet fd `fd`.
+fn receive_from_offer(
+    loop_handle: &LoopHandle<'static, GlobalState>,
+    offer: &WlDataOffer,
+    mime_type: String,
+    fd: OwnedFd,
+) {
+    let mime_types = offer
+        .data::<DataOfferData>()
+        .map(|d| d.with_mime_types(|m| m.to_vec()))
+        .unwrap_or_default();
+    if let Some(fd) = start_offer_transfer(loop_handle, &mime_types, &mime_type, fd) {
+        receive_to_fd(offer, mime_type, fd);
+    }
+}
+
 impl SelectionHandler for GlobalState {
     type SelectionUserData = ();
 
     fn new_selection(
         &mut self,
-        _target: SelectionTarget,
+        target: SelectionTarget,
         source: Option<SelectionSource>,
         seat: Seat<GlobalState>,
     ) {
@@ -341,9 +418,30 @@ impl SelectionHandler for GlobalState {
 
         let serial = seat.client.get_serial_of_last_seat_event();
 
+        if matches!(target, SelectionTarget::Primary) {
+            let (Some(manager), Some(device)) = (
+                self.client_state.primary_selection_manager.as_ref(),
+                seat.client.primary_selection_device.as_ref(),
+            ) else {
+                return;
+            };
+            if let Some(source) = source {
+                seat.client.next_primary_selection_offer_is_mine = true;
+                let mime_types = transfer::filter_mime_types(source.mime_types());
+                let primary_selection_source =
+                    manager.create_selection_source(&self.client_state.queue_handle, mime_types);
+                primary_selection_source.set_selection(device, serial);
+                seat.client.primary_selection_source = Some(primary_selection_source);
+                seat.server.primary_selection_source = Some(source);
+            } else {
+                device.unset_selection(serial);
+            }
+            return;
+        }
+
         if let Some(source) = source {
             seat.client.next_selection_offer_is_mine = true;
-            let mime_types = source.mime_types();
+            let mime_types = transfer::filter_mime_types(source.mime_types());
             let copy_paste_source = self
                 .client_state
                 .data_device_manager
@@ -358,7 +456,7 @@ impl SelectionHandler for GlobalState {
 
     fn send_selection(
         &mut self,
-        _target: SelectionTarget,
+        target: SelectionTarget,
         mime_type: String,
         fd: OwnedFd,
         seat: Seat<Self>,
@@ -368,8 +466,23 @@ impl SelectionHandler for GlobalState {
             Some(s) => s,
             None => return,
         };
-        if let Some(offer) = seat.client.selection_offer.as_ref() {
-            receive_to_fd(offer.inner(), mime_type, fd)
+        match target {
+            SelectionTarget::Clipboard => {
+                if let Some(offer) = seat.client.selection_offer.as_ref() {
+                    receive_from_offer(&self.space.loop_handle, offer.inner(), mime_type, fd);
+                }
+            },
+            SelectionTarget::Primary => {
+                let Some(offer) = seat.client.primary_selection_offer.as_ref() else {
+                    return;
+                };
+                let mime_types = offer.with_mime_types(|m| m.to_vec());
+                if let Some(fd) =
+                    start_offer_transfer(&self.space.loop_handle, &mime_types, &mime_type, fd)
+                {
+                    offer.receive_to_fd(mime_type, fd);
+                }
+            },
         }
     }
 }
@@ -0,0 +1,117 @@
+//! Tells applets which windows overlap their panel, with the
+//! `cosmic_panel_overlap_v1` protocol of `protocols/cosmic-panel-overlap-v1.xml`.
+//!
+//! The set of overlapping app ids of each panel is compared with the last one
+//! sent once per loop iteration, and sent again when it changed.
+
+use std::collections::BTreeSet;
+
+use smithay::reexports::wayland_server::{
+    backend::{ClientId, GlobalId},
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+};
+
+use crate::xdg_shell_wrapper::shared_state::GlobalState;
+
+use self::protocol::{
+    cosmic_panel_overlap_manager_v1::{self, CosmicPanelOverlapManagerV1},
+    cosmic_panel_overlap_v1::{self, CosmicPanelOverlapV1},
+};
+
+#[allow(non_upper_case_globals, non_camel_case_types, unused_imports, missing_docs)]
+pub mod protocol {
+    use smithay::reexports::wayland_server;
+
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::backend as wayland_backend;
+        wayland_scanner::generate_interfaces!("protocols/cosmic-panel-overlap-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!("protocols/cosmic-panel-overlap-v1.xml");
+}
+
+#[derive(Debug)]
+pub struct OverlapInfoState {
+    _global: GlobalId,
+    /// subscriptions of applets, with the app ids sent last
+    overlaps: Vec<(CosmicPanelOverlapV1, Option<BTreeSet<String>>)>,
+}
+
+impl OverlapInfoState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        OverlapInfoState {
+            _global: dh.create_global::<GlobalState, CosmicPanelOverlapManagerV1, _>(1, ()),
+            overlaps: Vec::new(),
+        }
+    }
+}
+
+impl GlobalState {
+    /// Send the overlapping app ids to applets whose panel changed them.
+    pub(crate) fn update_overlap_info(&mut self) {
+        for (overlap, sent) in &mut self.server_state.overlap_info_state.overlaps {
+            let app_ids = overlap
+                .client()
+                .and_then(|c| self.space.space_list.iter().find(|s| s.has_client(&c)))
+                .map(|s| self.space.overlapping_app_ids(s))
+                .unwrap_or_default();
+            if sent.as_ref() == Some(&app_ids) {
+                continue;
+            }
+            for app_id in &app_ids {
+                overlap.app_id(app_id.clone());
+            }
+            overlap.done();
+            *sent = Some(app_ids);
+        }
+    }
+}
+
+impl GlobalDispatch<CosmicPanelOverlapManagerV1, ()> for GlobalState {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<CosmicPanelOverlapManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<CosmicPanelOverlapManagerV1, ()> for GlobalState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &CosmicPanelOverlapManagerV1,
+        request: cosmic_panel_overlap_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let cosmic_panel_overlap_manager_v1::Request::GetOverlap { id } = request {
+            let overlap = data_init.init(id, ());
+            // the current state is sent on the next update
+            state.server_state.overlap_info_state.overlaps.push((overlap, None));
+        }
+    }
+}
+
+impl Dispatch<CosmicPanelOverlapV1, ()> for GlobalState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &CosmicPanelOverlapV1,
+        _request: cosmic_panel_overlap_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, resource: &CosmicPanelOverlapV1, _data: &()) {
+        state.server_state.overlap_info_state.overlaps.retain(|(o, _)| o != resource);
+    }
+}
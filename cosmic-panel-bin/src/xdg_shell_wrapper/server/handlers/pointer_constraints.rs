@@ -0,0 +1,61 @@
+use smithay::{
+    delegate_pointer_constraints, delegate_relative_pointer,
+    input::pointer::PointerHandle,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point},
+    wayland::{pointer_constraints::PointerConstraintsHandler, seat::WaylandFocus},
+};
+
+use crate::xdg_shell_wrapper::shared_state::GlobalState;
+
+impl PointerConstraintsHandler for GlobalState {
+    fn new_constraint(&mut self, surface: &WlSurface, pointer: &PointerHandle<Self>) {
+        // constraints only take effect while the pointer is over the surface
+        if pointer.current_focus().and_then(|f| f.wl_surface().map(|s| s.into_owned())).as_ref()
+            != Some(surface)
+        {
+            return;
+        }
+        let Some(seat_index) = self
+            .server_state
+            .seats
+            .iter()
+            .position(|s| s.server.seat.get_pointer().as_ref() == Some(pointer))
+        else {
+            return;
+        };
+        self.activate_pointer_constraint(seat_index, surface);
+    }
+
+    fn cursor_position_hint(
+        &mut self,
+        _surface: &WlSurface,
+        pointer: &PointerHandle<Self>,
+        location: Point<f64, Logical>,
+    ) {
+        let Some(seat) = self
+            .server_state
+            .seats
+            .iter()
+            .find(|s| s.server.seat.get_pointer().as_ref() == Some(pointer))
+        else {
+            return;
+        };
+        let Some(constraint) = seat.client.pointer_constraint.as_ref() else {
+            return;
+        };
+        // the hint is relative to the applet, the host expects it relative to
+        // the panel
+        let offset = self
+            .space
+            .space_list
+            .iter()
+            .find_map(|s| s.s_hovered_surface.iter().find(|f| f.seat_name == seat.name))
+            .map(|f| f.s_pos - f.c_pos.to_f64())
+            .unwrap_or_default();
+        constraint.set_cursor_position_hint(location.x + offset.x, location.y + offset.y);
+    }
+}
+
+delegate_pointer_constraints!(GlobalState);
+delegate_relative_pointer!(GlobalState);
@@ -0,0 +1,316 @@
+//! text-input-v3 for applets.
+//!
+//! Each applet text input is bridged to a host text input of the same seat.
+//! An applet text input is entered when one of the applet's surfaces gets the
+//! keyboard focus of the panel, and its state is forwarded to the host while
+//! it has focus. Input method events from the host go to the focused applet.
+
+use smithay::{
+    input::Seat,
+    reexports::{
+        wayland_protocols::wp::text_input::zv3::server::{
+            zwp_text_input_manager_v3::{self, ZwpTextInputManagerV3},
+            zwp_text_input_v3::{self, ZwpTextInputV3},
+        },
+        wayland_server::{
+            backend::{ClientId, GlobalId},
+            protocol::wl_surface::WlSurface,
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+        },
+    },
+    utils::{Logical, Rectangle},
+    wayland::seat::WaylandFocus,
+};
+use wayland_protocols::wp::text_input::zv3::client::zwp_text_input_v3 as host_text_input;
+
+use crate::xdg_shell_wrapper::{
+    client::handlers::text_input::TextInputEvents, shared_state::GlobalState,
+};
+
+#[derive(Debug)]
+pub struct TextInputForwardState {
+    _global: GlobalId,
+    inputs: Vec<ForwardedTextInput>,
+}
+
+/// Double buffered state of an applet text input.
+#[derive(Debug, Default, Clone)]
+struct TextInputRequests {
+    enabled: Option<bool>,
+    surrounding_text: Option<(String, i32, i32)>,
+    change_cause: Option<u32>,
+    content_type: Option<(u32, u32)>,
+    cursor_rectangle: Option<Rectangle<i32, Logical>>,
+}
+
+#[derive(Debug)]
+struct ForwardedTextInput {
+    resource: ZwpTextInputV3,
+    seat_name: String,
+    focus: Option<WlSurface>,
+    enabled: bool,
+    pending: TextInputRequests,
+    /// number of commits, as expected in `done` events
+    commits: u32,
+}
+
+impl TextInputForwardState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        TextInputForwardState {
+            _global: dh.create_global::<GlobalState, ZwpTextInputManagerV3, _>(1, ()),
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Send the input method events of the host to the focused applet.
+    pub fn host_done(&mut self, seat_name: &str, events: TextInputEvents) {
+        for input in self
+            .inputs
+            .iter()
+            .filter(|i| i.seat_name == seat_name && i.enabled && i.focus.is_some())
+        {
+            if let Some((text, begin, end)) = events.preedit.clone() {
+                input.resource.preedit_string(Some(text), begin, end);
+            }
+            if let Some(text) = events.commit.clone() {
+                input.resource.commit_string(Some(text));
+            }
+            if let Some((before, after)) = events.delete_surrounding {
+                input.resource.delete_surrounding_text(before, after);
+            }
+            input.resource.done(input.commits);
+        }
+    }
+}
+
+impl GlobalState {
+    /// Move the text input focus of the seat named `seat_name` to `surface`.
+    pub(crate) fn text_input_focus_changed(
+        &mut self,
+        seat_name: &str,
+        surface: Option<&WlSurface>,
+    ) {
+        let mut left_focused = false;
+        for input in self
+            .server_state
+            .text_input_forward_state
+            .inputs
+            .iter_mut()
+            .filter(|i| i.seat_name == seat_name)
+        {
+            if let Some(old) = input.focus.take() {
+                left_focused |= input.enabled;
+                input.enabled = false;
+                input.resource.leave(&old);
+            }
+            if let Some(surface) = surface.filter(|s| s.client() == input.resource.client()) {
+                input.resource.enter(surface);
+                input.focus = Some(surface.clone());
+            }
+        }
+        if left_focused {
+            if let Some(host) = self.host_text_input(seat_name) {
+                host.disable();
+                host.commit();
+            }
+        }
+    }
+
+    fn host_text_input(&mut self, seat_name: &str) -> Option<host_text_input::ZwpTextInputV3> {
+        let seat = self.server_state.seats.iter().find(|s| s.name == seat_name)?;
+        let manager = self.client_state.text_input_manager.as_mut()?;
+        Some(
+            manager
+                .text_input(&self.client_state.queue_handle, seat_name, &seat.client._seat)
+                .clone(),
+        )
+    }
+
+    fn commit_text_input(&mut self, resource: &ZwpTextInputV3) {
+        let Some(input) = self
+            .server_state
+            .text_input_forward_state
+            .inputs
+            .iter_mut()
+            .find(|i| &i.resource == resource)
+        else {
+            return;
+        };
+        input.commits = input.commits.wrapping_add(1);
+        let requests = std::mem::take(&mut input.pending);
+        if let Some(enabled) = requests.enabled {
+            input.enabled = enabled;
+        }
+        let Some(focus) = input.focus.clone() else {
+            return;
+        };
+        let seat_name = input.seat_name.clone();
+        // cursor rectangles are relative to the panel surface on the host
+        let offset = focus
+            .client()
+            .and_then(|c| self.space.space_list.iter().find(|s| s.has_client(&c)))
+            .and_then(|s| s.surface_location(&focus))
+            .unwrap_or_default();
+        let Some(host) = self.host_text_input(&seat_name) else {
+            return;
+        };
+
+        match requests.enabled {
+            Some(true) => host.enable(),
+            Some(false) => host.disable(),
+            None => {},
+        }
+        if let Some((text, cursor, anchor)) = requests.surrounding_text {
+            host.set_surrounding_text(text, cursor, anchor);
+        }
+        if let Some(cause) = requests.change_cause {
+            host.set_text_change_cause(
+                host_text_input::ChangeCause::try_from(cause)
+                    .unwrap_or(host_text_input::ChangeCause::Other),
+            );
+        }
+        if let Some((hint, purpose)) = requests.content_type {
+            host.set_content_type(
+                host_text_input::ContentHint::from_bits_truncate(hint),
+                host_text_input::ContentPurpose::try_from(purpose)
+                    .unwrap_or(host_text_input::ContentPurpose::Normal),
+            );
+        }
+        if let Some(rect) = requests.cursor_rectangle {
+            host.set_cursor_rectangle(
+                rect.loc.x + offset.x,
+                rect.loc.y + offset.y,
+                rect.size.w,
+                rect.size.h,
+            );
+        }
+        host.commit();
+    }
+}
+
+impl GlobalDispatch<ZwpTextInputManagerV3, ()> for GlobalState {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpTextInputManagerV3>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwpTextInputManagerV3, ()> for GlobalState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &ZwpTextInputManagerV3,
+        request: zwp_text_input_manager_v3::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_text_input_manager_v3::Request::GetTextInput { id, seat } = request {
+            let resource = data_init.init(id, ());
+            let Some(pair) = Seat::<GlobalState>::from_resource(&seat)
+                .and_then(|seat| state.server_state.seats.iter().find(|s| s.server.seat == seat))
+            else {
+                return;
+            };
+            let seat_name = pair.name.clone();
+            // the applet may already have keyboard focus
+            let focus = pair
+                .server
+                .seat
+                .get_keyboard()
+                .and_then(|k| k.current_focus())
+                .and_then(|f| f.wl_surface().map(|s| s.into_owned()))
+                .filter(|s| s.client() == resource.client());
+            if let Some(surface) = focus.as_ref() {
+                resource.enter(surface);
+            }
+            state.server_state.text_input_forward_state.inputs.push(ForwardedTextInput {
+                resource,
+                seat_name,
+                focus,
+                enabled: false,
+                pending: Default::default(),
+                commits: 0,
+            });
+        }
+    }
+}
+
+impl Dispatch<ZwpTextInputV3, ()> for GlobalState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &ZwpTextInputV3,
+        request: zwp_text_input_v3::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_text_input_v3::Request::Commit = request {
+            state.commit_text_input(resource);
+            return;
+        }
+        let Some(input) = state
+            .server_state
+            .text_input_forward_state
+            .inputs
+            .iter_mut()
+            .find(|i| &i.resource == resource)
+        else {
+            return;
+        };
+        let pending = &mut input.pending;
+        match request {
+            zwp_text_input_v3::Request::Enable => {
+                // enabling resets the state of the text input
+                *pending = TextInputRequests { enabled: Some(true), ..Default::default() };
+            },
+            zwp_text_input_v3::Request::Disable => pending.enabled = Some(false),
+            zwp_text_input_v3::Request::SetSurroundingText { text, cursor, anchor } => {
+                pending.surrounding_text = Some((text, cursor, anchor));
+            },
+            zwp_text_input_v3::Request::SetTextChangeCause { cause } => {
+                pending.change_cause = Some(match cause {
+                    WEnum::Value(cause) => cause.into(),
+                    WEnum::Unknown(cause) => cause,
+                });
+            },
+            zwp_text_input_v3::Request::SetContentType { hint, purpose } => {
+                let hint = match hint {
+                    WEnum::Value(hint) => hint.bits(),
+                    WEnum::Unknown(hint) => hint,
+                };
+                let purpose = match purpose {
+                    WEnum::Value(purpose) => purpose.into(),
+                    WEnum::Unknown(purpose) => purpose,
+                };
+                pending.content_type = Some((hint, purpose));
+            },
+            zwp_text_input_v3::Request::SetCursorRectangle { x, y, width, height } => {
+                pending.cursor_rectangle =
+                    Some(Rectangle::from_loc_and_size((x, y), (width, height)));
+            },
+            _ => {},
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, resource: &ZwpTextInputV3, _data: &()) {
+        let inputs = &mut state.server_state.text_input_forward_state.inputs;
+        let Some(i) = inputs.iter().position(|i| &i.resource == resource) else {
+            return;
+        };
+        let input = inputs.remove(i);
+        if input.enabled && input.focus.is_some() {
+            if let Some(host) = state.host_text_input(&input.seat_name) {
+                host.disable();
+                host.commit();
+            }
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//! Window thumbnails for applets, with the `cosmic_panel_thumbnail_v1` protocol
+//! of `protocols/cosmic-panel-thumbnail-v1.xml`.
+//!
+//! The panel captures host toplevels with cosmic screencopy into its own shm
+//! pool, and copies each frame into the wl_shm buffer of the applet, so applets
+//! don't need privileged access to the compositor for hover previews.
+
+use cctk::{
+    cosmic_protocols::screencopy::v2::client::zcosmic_screencopy_session_v2::ZcosmicScreencopySessionV2,
+    wayland_client::protocol::wl_shm,
+};
+use sctk::shm::slot::{Buffer, SlotPool};
+use smithay::{
+    reexports::wayland_server::{
+        backend::{ClientId, GlobalId, ObjectId},
+        protocol::{wl_buffer::WlBuffer, wl_shm::Format as BufferFormat},
+        Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    },
+    wayland::shm::{with_buffer_contents, with_buffer_contents_mut, BufferData},
+};
+use tracing::warn;
+
+use crate::xdg_shell_wrapper::{
+    client::handlers::screencopy::{destroy_session, CaptureFormats, CaptureFrameData},
+    shared_state::GlobalState,
+};
+
+use self::protocol::{
+    cosmic_panel_thumbnail_manager_v1::{self, CosmicPanelThumbnailManagerV1},
+    cosmic_panel_thumbnail_v1::{self, CosmicPanelThumbnailV1},
+};
+
+#[allow(non_upper_case_globals, non_camel_case_types, unused_imports, missing_docs)]
+pub mod protocol {
+    use smithay::reexports::wayland_server::{self, protocol::*};
+
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::{
+            backend as wayland_backend, protocol::__interfaces::*,
+        };
+        wayland_scanner::generate_interfaces!("protocols/cosmic-panel-thumbnail-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!("protocols/cosmic-panel-thumbnail-v1.xml");
+}
+
+#[derive(Debug)]
+struct Thumbnail {
+    resource: CosmicPanelThumbnailV1,
+    session: Option<ZcosmicScreencopySessionV2>,
+    formats: Option<CaptureFormats>,
+    /// the applet buffer of the pending capture, with the host buffer it is
+    /// captured into
+    pending: Option<(WlBuffer, Buffer)>,
+}
+
+#[derive(Debug)]
+pub struct ThumbnailState {
+    _global: GlobalId,
+    thumbnails: Vec<Thumbnail>,
+    /// host buffers of the captures, created on the first capture
+    pool: Option<SlotPool>,
+}
+
+impl ThumbnailState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        ThumbnailState {
+            _global: dh.create_global::<GlobalState, CosmicPanelThumbnailManagerV1, _>(1, ()),
+            thumbnails: Vec::new(),
+            pool: None,
+        }
+    }
+
+    fn thumbnail_mut(&mut self, id: &ObjectId) -> Option<&mut Thumbnail> {
+        self.thumbnails.iter_mut().find(|t| &t.resource.id() == id)
+    }
+}
+
+impl GlobalState {
+    /// The capture session of thumbnail `id` is ready for frames of `formats`.
+    pub(crate) fn thumbnail_formats(&mut self, id: &ObjectId, formats: CaptureFormats) {
+        let Some(thumbnail) = self.server_state.thumbnail_state.thumbnail_mut(id) else {
+            return;
+        };
+        if !formats.shm_formats.contains(&wl_shm::Format::Argb8888) {
+            warn!("Window can't be captured in argb8888 for a thumbnail");
+            if let Some(session) = thumbnail.session.take() {
+                destroy_session(&session);
+            }
+            thumbnail.resource.stopped();
+            return;
+        }
+        if thumbnail.formats.as_ref().map(|f| (f.width, f.height))
+            != Some((formats.width, formats.height))
+        {
+            thumbnail.resource.size(formats.width, formats.height);
+        }
+        thumbnail.formats = Some(formats);
+    }
+
+    /// The window of thumbnail `id` can't be captured anymore.
+    pub(crate) fn thumbnail_stopped(&mut self, id: &ObjectId) {
+        let Some(thumbnail) = self.server_state.thumbnail_state.thumbnail_mut(id) else {
+            return;
+        };
+        // the session is destroyed by its handler
+        thumbnail.session = None;
+        thumbnail.pending = None;
+        thumbnail.resource.stopped();
+    }
+
+    /// The pending capture of thumbnail `id` finished, and is copied into the
+    /// applet buffer if it succeeded.
+    pub(crate) fn thumbnail_captured(&mut self, id: &ObjectId, ok: bool) {
+        let state = &mut self.server_state.thumbnail_state;
+        let Some(thumbnail) = state.thumbnails.iter_mut().find(|t| &t.resource.id() == id) else {
+            return;
+        };
+        let Some((applet_buffer, buffer)) = thumbnail.pending.take() else {
+            return;
+        };
+        let canvas = if ok { state.pool.as_mut().and_then(|p| p.canvas(&buffer)) } else { None };
+        let Some(canvas) = canvas else {
+            thumbnail.resource.failed();
+            return;
+        };
+
+        let (src_stride, height) = (buffer.stride() as usize, buffer.height() as usize);
+        let copied = with_buffer_contents_mut(&applet_buffer, |ptr, len, data| {
+            // SAFETY: the pool of the buffer is mapped for `len` bytes
+            let pool = unsafe { std::slice::from_raw_parts_mut(ptr, len) };
+            copy_rows(canvas, src_stride, height, pool, &data);
+        });
+        if copied.is_ok() {
+            thumbnail.resource.ready();
+        } else {
+            thumbnail.resource.failed();
+        }
+    }
+
+    fn capture_thumbnail(&mut self, resource: &CosmicPanelThumbnailV1, applet_buffer: WlBuffer) {
+        let qh = self.client_state.queue_handle.clone();
+        let state = &mut self.server_state.thumbnail_state;
+        let Some(thumbnail) = state.thumbnails.iter_mut().find(|t| &t.resource == resource) else {
+            return;
+        };
+        let (Some(session), Some(formats)) =
+            (thumbnail.session.as_ref(), thumbnail.formats.as_ref())
+        else {
+            resource.failed();
+            return;
+        };
+        if thumbnail.pending.is_some() {
+            resource.post_error(
+                cosmic_panel_thumbnail_v1::Error::AlreadyCapturing,
+                "a capture is already pending",
+            );
+            return;
+        }
+        // frames are copied with the cpu, so dmabufs aren't supported
+        let is_valid = with_buffer_contents(&applet_buffer, |_, _, data| {
+            is_valid_buffer(&data, formats.width, formats.height)
+        });
+        if !is_valid.unwrap_or(false) {
+            resource.post_error(
+                cosmic_panel_thumbnail_v1::Error::InvalidBuffer,
+                "the buffer is not a wl_shm buffer in the argb8888 format as large as the window",
+            );
+            return;
+        }
+
+        let (width, height) = (formats.width as i32, formats.height as i32);
+        if state.pool.is_none() {
+            match SlotPool::new((width * height * 4) as usize, &self.client_state.shm_state) {
+                Ok(pool) => state.pool = Some(pool),
+                Err(err) => {
+                    warn!("Failed to create the thumbnail pool {err:?}");
+                    resource.failed();
+                    return;
+                },
+            }
+        }
+        let Some(pool) = state.pool.as_mut() else {
+            return;
+        };
+        let buffer = match pool.create_buffer(width, height, width * 4, wl_shm::Format::Argb8888) {
+            Ok((buffer, _)) => buffer,
+            Err(err) => {
+                warn!("Failed to create a thumbnail buffer {err:?}");
+                resource.failed();
+                return;
+            },
+        };
+
+        let frame = session.create_frame(&qh, CaptureFrameData { thumbnail: resource.id() });
+        frame.attach_buffer(buffer.wl_buffer());
+        frame.damage_buffer(0, 0, width, height);
+        frame.capture();
+        thumbnail.pending = Some((applet_buffer, buffer));
+    }
+}
+
+/// Whether an applet buffer can hold a capture of a window of `width` x
+/// `height`.
+fn is_valid_buffer(data: &BufferData, width: u32, height: u32) -> bool {
+    data.format == BufferFormat::Argb8888
+        && data.width as i64 >= width as i64
+        && data.height as i64 >= height as i64
+        && data.stride as i64 >= data.width as i64 * 4
+}
+
+/// Copy `rows` rows of `src` into the applet buffer `data` in `pool`, as far
+/// as they fit into the buffer and the pool.
+fn copy_rows(src: &[u8], src_stride: usize, rows: usize, pool: &mut [u8], data: &BufferData) {
+    let dst_stride = data.stride.max(0) as usize;
+    let row = src_stride.min(dst_stride);
+    for y in 0..rows.min(data.height.max(0) as usize) {
+        let (src_start, dst_start) = (y * src_stride, data.offset.max(0) as usize + y * dst_stride);
+        let (Some(src), Some(dst)) =
+            (src.get(src_start..src_start + row), pool.get_mut(dst_start..dst_start + row))
+        else {
+            break;
+        };
+        dst.copy_from_slice(src);
+    }
+}
+
+impl GlobalDispatch<CosmicPanelThumbnailManagerV1, ()> for GlobalState {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<CosmicPanelThumbnailManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<CosmicPanelThumbnailManagerV1, ()> for GlobalState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &CosmicPanelThumbnailManagerV1,
+        request: cosmic_panel_thumbnail_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let cosmic_panel_thumbnail_manager_v1::Request::GetThumbnail { id, identifier } = request
+        {
+            let resource = data_init.init(id, ());
+            let toplevel = state.forwarded_host_toplevel(&identifier);
+            let session = toplevel.zip(state.client_state.toplevel_capture.as_ref()).map(
+                |(toplevel, capture)| {
+                    capture.create_session(
+                        &toplevel,
+                        resource.id(),
+                        &state.client_state.queue_handle,
+                    )
+                },
+            );
+            if session.is_none() {
+                resource.stopped();
+            }
+            state.server_state.thumbnail_state.thumbnails.push(Thumbnail {
+                resource,
+                session,
+                formats: None,
+                pending: None,
+            });
+        }
+    }
+}
+
+impl Dispatch<CosmicPanelThumbnailV1, ()> for GlobalState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &CosmicPanelThumbnailV1,
+        request: cosmic_panel_thumbnail_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let cosmic_panel_thumbnail_v1::Request::Capture { buffer } = request {
+            state.capture_thumbnail(resource, buffer);
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &CosmicPanelThumbnailV1,
+        _data: &(),
+    ) {
+        let thumbnails = &mut state.server_state.thumbnail_state.thumbnails;
+        if let Some(i) = thumbnails.iter().position(|t| &t.resource == resource) {
+            if let Some(session) = thumbnails.remove(i).session {
+                destroy_session(&session);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(width: i32, height: i32, format: BufferFormat) -> BufferData {
+        BufferData { offset: 0, width, height, stride: width * 4, format }
+    }
+
+    #[test]
+    fn buffers_must_be_argb8888() {
+        assert!(is_valid_buffer(&buffer(64, 32, BufferFormat::Argb8888), 64, 32));
+        assert!(!is_valid_buffer(&buffer(64, 32, BufferFormat::Xrgb8888), 64, 32));
+        assert!(!is_valid_buffer(&buffer(64, 32, BufferFormat::Rgb565), 64, 32));
+    }
+
+    #[test]
+    fn buffers_must_be_as_large_as_the_window() {
+        assert!(is_valid_buffer(&buffer(128, 64, BufferFormat::Argb8888), 64, 32));
+        assert!(!is_valid_buffer(&buffer(63, 32, BufferFormat::Argb8888), 64, 32));
+        assert!(!is_valid_buffer(&buffer(64, 31, BufferFormat::Argb8888), 64, 32));
+        let short_stride = BufferData { stride: 64, ..buffer(64, 32, BufferFormat::Argb8888) };
+        assert!(!is_valid_buffer(&short_stride, 64, 32));
+    }
+
+    #[test]
+    fn rows_are_copied_with_the_stride_of_the_buffer() {
+        // 2x2 capture into a 3x3 buffer at offset 4
+        let src = [1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4];
+        let data = BufferData { offset: 4, stride: 12, ..buffer(3, 3, BufferFormat::Argb8888) };
+        let mut pool = vec![0; 4 + 36];
+        copy_rows(&src, 8, 2, &mut pool, &data);
+        assert_eq!(&pool[..4], &[0; 4]);
+        assert_eq!(&pool[4..16], &[1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0]);
+        assert_eq!(&pool[16..28], &[3, 3, 3, 3, 4, 4, 4, 4, 0, 0, 0, 0]);
+        assert_eq!(&pool[28..], &[0; 12]);
+    }
+
+    #[test]
+    fn rows_past_the_pool_are_not_copied() {
+        let src = [1; 32];
+        let data = buffer(2, 4, BufferFormat::Argb8888);
+        // the pool was shrunk below the size of the buffer
+        let mut pool = vec![0; 20];
+        copy_rows(&src, 8, 4, &mut pool, &data);
+        assert_eq!(&pool[..16], &[1; 16]);
+        assert_eq!(&pool[16..], &[0; 4]);
+    }
+}
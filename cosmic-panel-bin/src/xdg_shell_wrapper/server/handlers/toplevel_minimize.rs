@@ -0,0 +1,122 @@
+//! Minimize rectangles of single windows, set by applets with the
+//! `cosmic_panel_minimize_v1` protocol of `protocols/cosmic-panel-minimize-v1.xml`.
+//!
+//! Rectangles are kept relative to the applet surface, and moved to the layer
+//! surface of the panel whenever they are sent to the host.
+
+use smithay::{
+    reexports::wayland_server::{
+        backend::{ClientId, GlobalId},
+        Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    },
+    utils::Rectangle,
+};
+
+use crate::{minimize, xdg_shell_wrapper::shared_state::GlobalState};
+
+use self::protocol::cosmic_panel_minimize_manager_v1::{self, CosmicPanelMinimizeManagerV1};
+
+#[allow(non_upper_case_globals, non_camel_case_types, unused_imports, missing_docs)]
+pub mod protocol {
+    use smithay::reexports::wayland_server::{self, protocol::*};
+
+    pub mod __interfaces {
+        use smithay::reexports::wayland_server::{
+            backend as wayland_backend, protocol::__interfaces::*,
+        };
+        wayland_scanner::generate_interfaces!("protocols/cosmic-panel-minimize-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_server_code!("protocols/cosmic-panel-minimize-v1.xml");
+}
+
+#[derive(Debug)]
+pub struct ToplevelMinimizeState {
+    _global: GlobalId,
+}
+
+impl ToplevelMinimizeState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        ToplevelMinimizeState {
+            _global: dh.create_global::<GlobalState, CosmicPanelMinimizeManagerV1, _>(1, ()),
+        }
+    }
+}
+
+impl GlobalState {
+    /// Send the minimize rectangle of the host toplevel shown as the forwarded
+    /// toplevel `identifier`.
+    fn update_toplevel_minimize(&mut self, identifier: &str) {
+        if let Some(toplevel) = self.forwarded_host_toplevel(identifier) {
+            minimize::update_toplevel(self, toplevel);
+        }
+    }
+}
+
+impl GlobalDispatch<CosmicPanelMinimizeManagerV1, ()> for GlobalState {
+    fn bind(
+        _state: &mut Self,
+        _dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<CosmicPanelMinimizeManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<CosmicPanelMinimizeManagerV1, ()> for GlobalState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &CosmicPanelMinimizeManagerV1,
+        request: cosmic_panel_minimize_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            cosmic_panel_minimize_manager_v1::Request::SetRectangle {
+                identifier,
+                surface,
+                x,
+                y,
+                width,
+                height,
+            } => {
+                state.space.toplevel_minimize_rects.insert(
+                    identifier.clone(),
+                    (surface, Rectangle::from_loc_and_size((x, y), (width, height))),
+                );
+                state.update_toplevel_minimize(&identifier);
+            },
+            cosmic_panel_minimize_manager_v1::Request::UnsetRectangle { identifier } => {
+                if state.space.toplevel_minimize_rects.remove(&identifier).is_some() {
+                    state.update_toplevel_minimize(&identifier);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        client: ClientId,
+        _resource: &CosmicPanelMinimizeManagerV1,
+        _data: &(),
+    ) {
+        let identifiers: Vec<_> = state
+            .space
+            .toplevel_minimize_rects
+            .iter()
+            .filter(|(_, (surface, _))| surface.client().map_or(true, |c| c.id() == client))
+            .map(|(identifier, _)| identifier.clone())
+            .collect();
+        for identifier in identifiers {
+            state.space.toplevel_minimize_rects.remove(&identifier);
+            state.update_toplevel_minimize(&identifier);
+        }
+    }
+}
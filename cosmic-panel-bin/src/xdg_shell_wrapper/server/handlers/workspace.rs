@@ -1,42 +1,385 @@
-use cstk::{
-    delegate_workspace,
-    toplevel_info::{ToplevelInfoHandler, ToplevelInfoState},
-    workspace::{WorkspaceClientHandler, WorkspaceClientState, WorkspaceHandler, WorkspaceState},
+//! Mirrors the workspaces of the host compositor in the embedded server, so
+//! workspace applets don't need their own connection to the host.
+//!
+//! Applets may only activate and deactivate workspaces. Those requests are
+//! forwarded to the host handles, and applied on the host when the applet
+//! commits.
+
+use cctk::{
+    cosmic_protocols::workspace::v1::client::{
+        zcosmic_workspace_group_handle_v1::ZcosmicWorkspaceGroupHandleV1 as HostGroupHandle,
+        zcosmic_workspace_handle_v1::{
+            self as host_workspace_handle, ZcosmicWorkspaceHandleV1 as HostWorkspaceHandle,
+        },
+    },
+    wayland_client::{protocol::wl_output::WlOutput as HostOutput, WEnum},
+    workspace::{Workspace, WorkspaceGroup},
 };
-use smithay::reexports::wayland_server::{
-    backend::{ClientData, ClientId, DisconnectReason},
-    DisplayHandle,
+use cosmic_protocols::workspace::v1::server::{
+    zcosmic_workspace_group_handle_v1::{self, ZcosmicWorkspaceGroupHandleV1},
+    zcosmic_workspace_handle_v1::{self, ZcosmicWorkspaceHandleV1},
+    zcosmic_workspace_manager_v1::{self, ZcosmicWorkspaceManagerV1},
 };
-
-use crate::xdg_shell_wrapper::{
-    server_state::WrapperWorkspaceClientState, shared_state::GlobalState, space::WrapperSpace,
+use sctk::output::OutputInfo;
+use smithay::{
+    output::Output,
+    reexports::wayland_server::{
+        backend::{ClientId, GlobalId},
+        Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+    },
 };
 
-impl WorkspaceClientHandler for WrapperWorkspaceClientState {
-    fn workspace_state(&self) -> &WorkspaceClientState {
-        &self.workspace_client_state
+use crate::xdg_shell_wrapper::shared_state::GlobalState;
+
+/// Embedded server side of the forwarded workspaces.
+#[derive(Debug)]
+pub struct WorkspaceForwardState {
+    _global: GlobalId,
+    managers: Vec<ZcosmicWorkspaceManagerV1>,
+    groups: Vec<ForwardedGroup>,
+}
+
+#[derive(Debug)]
+struct ForwardedGroup {
+    host: HostGroupHandle,
+    outputs: Vec<HostOutput>,
+    workspaces: Vec<ForwardedWorkspace>,
+    resources: Vec<ZcosmicWorkspaceGroupHandleV1>,
+}
+
+#[derive(Debug)]
+struct ForwardedWorkspace {
+    host: HostWorkspaceHandle,
+    name: String,
+    coordinates: Vec<u8>,
+    state: Vec<u8>,
+    capabilities: Vec<u8>,
+    resources: Vec<ZcosmicWorkspaceHandleV1>,
+}
+
+fn to_array<T: Into<u32> + Copy>(values: impl IntoIterator<Item = T>) -> Vec<u8> {
+    values.into_iter().flat_map(|v| v.into().to_ne_bytes()).collect()
+}
+
+fn enum_array<T: Into<u32> + Copy>(values: &[WEnum<T>]) -> Vec<u8> {
+    to_array(values.iter().map(|v| match v {
+        WEnum::Value(v) => (*v).into(),
+        WEnum::Unknown(v) => *v,
+    }))
+}
+
+impl ForwardedWorkspace {
+    fn new(w: &Workspace) -> Self {
+        // only the requests which are forwarded are advertised
+        let capabilities = w.capabilities.iter().filter_map(|c| match c {
+            WEnum::Value(
+                c @ (host_workspace_handle::ZcosmicWorkspaceCapabilitiesV1::Activate
+                | host_workspace_handle::ZcosmicWorkspaceCapabilitiesV1::Deactivate),
+            ) => Some(u32::from(*c)),
+            _ => None,
+        });
+        ForwardedWorkspace {
+            host: w.handle.clone(),
+            name: w.name.clone(),
+            coordinates: to_array(w.coordinates.iter().copied()),
+            state: enum_array(&w.state),
+            capabilities: to_array(capabilities),
+            resources: Vec::new(),
+        }
+    }
+
+    fn send_all(&self, resource: &ZcosmicWorkspaceHandleV1) {
+        resource.name(self.name.clone());
+        resource.coordinates(self.coordinates.clone());
+        resource.state(self.state.clone());
+        resource.capabilities(self.capabilities.clone());
+    }
+
+    fn create_resource(
+        &mut self,
+        dh: &DisplayHandle,
+        client: &Client,
+        group: &ZcosmicWorkspaceGroupHandleV1,
+    ) {
+        let Ok(resource) = client.create_resource::<ZcosmicWorkspaceHandleV1, _, GlobalState>(
+            dh,
+            group.version(),
+            self.host.clone(),
+        ) else {
+            return;
+        };
+        group.workspace(&resource);
+        self.send_all(&resource);
+        self.resources.push(resource);
     }
 }
 
-impl WorkspaceHandler for GlobalState {
-    type Client = WrapperWorkspaceClientState;
+impl ForwardedGroup {
+    fn send_outputs(
+        &self,
+        resource: &ZcosmicWorkspaceGroupHandleV1,
+        outputs: &[(HostOutput, Output, OutputInfo)],
+        enter: bool,
+    ) {
+        let Some(client) = resource.client() else {
+            return;
+        };
+        for (_, output, _) in outputs.iter().filter(|(o, ..)| self.outputs.contains(o)) {
+            for wl_output in output.client_outputs(&client) {
+                if enter {
+                    resource.output_enter(&wl_output);
+                } else {
+                    resource.output_leave(&wl_output);
+                }
+            }
+        }
+    }
 
-    fn workspace_state(&self) -> &WorkspaceState<Self> {
-        &self.server_state.workspace_state
+    fn create_resource(
+        &mut self,
+        dh: &DisplayHandle,
+        manager: &ZcosmicWorkspaceManagerV1,
+        outputs: &[(HostOutput, Output, OutputInfo)],
+    ) {
+        let Some(client) = manager.client() else {
+            return;
+        };
+        let Ok(resource) = client.create_resource::<ZcosmicWorkspaceGroupHandleV1, _, GlobalState>(
+            dh,
+            manager.version(),
+            self.host.clone(),
+        ) else {
+            return;
+        };
+        manager.workspace_group(&resource);
+        resource.capabilities(Vec::new());
+        self.send_outputs(&resource, outputs, true);
+        for w in &mut self.workspaces {
+            w.create_resource(dh, &client, &resource);
+        }
+        self.resources.push(resource);
     }
 
-    fn workspace_state_mut(&mut self) -> &mut WorkspaceState<Self> {
-        &mut self.server_state.workspace_state
+    fn remove(self) {
+        for w in self.workspaces {
+            w.resources.iter().for_each(|r| r.remove());
+        }
+        self.resources.iter().for_each(|r| r.remove());
     }
+}
 
-    fn commit_requests(&mut self, dh: &DisplayHandle, requests: Vec<cstk::workspace::Request>) {
-        // TODO
+impl WorkspaceForwardState {
+    pub fn new(dh: &DisplayHandle) -> Self {
+        WorkspaceForwardState {
+            _global: dh.create_global::<GlobalState, ZcosmicWorkspaceManagerV1, _>(1, ()),
+            managers: Vec::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    /// Apply the current workspace groups of the host, sending only what
+    /// changed to the applets.
+    pub fn update(
+        &mut self,
+        dh: &DisplayHandle,
+        groups: &[WorkspaceGroup],
+        outputs: &[(HostOutput, Output, OutputInfo)],
+    ) {
+        let (kept, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.groups)
+            .into_iter()
+            .partition(|g| groups.iter().any(|h| h.handle == g.host));
+        self.groups = kept;
+        removed.into_iter().for_each(ForwardedGroup::remove);
+
+        for host_group in groups {
+            let Some(group) = self.groups.iter_mut().find(|g| g.host == host_group.handle) else {
+                let mut group = ForwardedGroup {
+                    host: host_group.handle.clone(),
+                    outputs: host_group.outputs.clone(),
+                    workspaces: host_group.workspaces.iter().map(ForwardedWorkspace::new).collect(),
+                    resources: Vec::new(),
+                };
+                for manager in &self.managers {
+                    group.create_resource(dh, manager, outputs);
+                }
+                self.groups.push(group);
+                continue;
+            };
+
+            if group.outputs != host_group.outputs {
+                for resource in &group.resources {
+                    group.send_outputs(resource, outputs, false);
+                }
+                group.outputs = host_group.outputs.clone();
+                for resource in &group.resources {
+                    group.send_outputs(resource, outputs, true);
+                }
+            }
+
+            let (kept, removed): (Vec<_>, Vec<_>) = std::mem::take(&mut group.workspaces)
+                .into_iter()
+                .partition(|w| host_group.workspaces.iter().any(|h| h.handle == w.host));
+            group.workspaces = kept;
+            for w in removed {
+                w.resources.iter().for_each(|r| r.remove());
+            }
+
+            for host_workspace in &host_group.workspaces {
+                let new = ForwardedWorkspace::new(host_workspace);
+                if let Some(w) = group.workspaces.iter_mut().find(|w| w.host == new.host) {
+                    for resource in &w.resources {
+                        if w.name != new.name {
+                            resource.name(new.name.clone());
+                        }
+                        if w.coordinates != new.coordinates {
+                            resource.coordinates(new.coordinates.clone());
+                        }
+                        if w.state != new.state {
+                            resource.state(new.state.clone());
+                        }
+                        if w.capabilities != new.capabilities {
+                            resource.capabilities(new.capabilities.clone());
+                        }
+                    }
+                    w.name = new.name;
+                    w.coordinates = new.coordinates;
+                    w.state = new.state;
+                    w.capabilities = new.capabilities;
+                } else {
+                    let mut new = new;
+                    for resource in &group.resources {
+                        if let Some(client) = resource.client() {
+                            new.create_resource(dh, &client, resource);
+                        }
+                    }
+                    group.workspaces.push(new);
+                }
+            }
+        }
+
+        for manager in &self.managers {
+            manager.done();
+        }
+    }
+
+    fn add_manager(
+        &mut self,
+        dh: &DisplayHandle,
+        manager: ZcosmicWorkspaceManagerV1,
+        outputs: &[(HostOutput, Output, OutputInfo)],
+    ) {
+        for group in &mut self.groups {
+            group.create_resource(dh, &manager, outputs);
+        }
+        manager.done();
+        self.managers.push(manager);
+    }
+
+    fn remove_client(&mut self, client: &ClientId) {
+        let other = |c: Option<Client>| c.map(|c| c.id()).as_ref() != Some(client);
+        self.managers.retain(|m| other(m.client()));
+        for group in &mut self.groups {
+            group.resources.retain(|r| other(r.client()));
+            for w in &mut group.workspaces {
+                w.resources.retain(|r| other(r.client()));
+            }
+        }
+    }
+}
+
+impl GlobalDispatch<ZcosmicWorkspaceManagerV1, ()> for GlobalState {
+    fn bind(
+        state: &mut Self,
+        dh: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZcosmicWorkspaceManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let manager = data_init.init(resource, ());
+        state.server_state.workspace_forward_state.add_manager(dh, manager, &state.space.outputs);
+    }
+}
+
+impl Dispatch<ZcosmicWorkspaceManagerV1, ()> for GlobalState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &ZcosmicWorkspaceManagerV1,
+        request: zcosmic_workspace_manager_v1::Request,
+        _data: &(),
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zcosmic_workspace_manager_v1::Request::Commit => {
+                if let Some(manager) = state
+                    .client_state
+                    .workspace_state
+                    .as_ref()
+                    .and_then(|w| w.workspace_manager().get().ok())
+                {
+                    manager.commit();
+                }
+            },
+            zcosmic_workspace_manager_v1::Request::Stop => {
+                resource.finished();
+                state.server_state.workspace_forward_state.managers.retain(|m| m != resource);
+            },
+            _ => {},
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        client: ClientId,
+        _resource: &ZcosmicWorkspaceManagerV1,
+        _: &(),
+    ) {
+        state.server_state.workspace_forward_state.remove_client(&client);
     }
 }
 
-impl ClientData for WrapperWorkspaceClientState {
-    fn initialized(&self, _client_id: ClientId) {}
-    fn disconnected(&self, _client_id: ClientId, _reason: DisconnectReason) {}
+impl Dispatch<ZcosmicWorkspaceGroupHandleV1, HostGroupHandle> for GlobalState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &ZcosmicWorkspaceGroupHandleV1,
+        request: zcosmic_workspace_group_handle_v1::Request,
+        _data: &HostGroupHandle,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // workspaces can't be created by applets
+        if let zcosmic_workspace_group_handle_v1::Request::Destroy = request {
+            for group in &mut state.server_state.workspace_forward_state.groups {
+                group.resources.retain(|r| r != resource);
+            }
+        }
+    }
 }
 
-delegate_workspace!(GlobalState);
+impl Dispatch<ZcosmicWorkspaceHandleV1, HostWorkspaceHandle> for GlobalState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &ZcosmicWorkspaceHandleV1,
+        request: zcosmic_workspace_handle_v1::Request,
+        host: &HostWorkspaceHandle,
+        _dh: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zcosmic_workspace_handle_v1::Request::Activate => host.activate(),
+            zcosmic_workspace_handle_v1::Request::Deactivate => host.deactivate(),
+            zcosmic_workspace_handle_v1::Request::Destroy => {
+                for group in &mut state.server_state.workspace_forward_state.groups {
+                    for w in &mut group.workspaces {
+                        w.resources.retain(|r| r != resource);
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+}
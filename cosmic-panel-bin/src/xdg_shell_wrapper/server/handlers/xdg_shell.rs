@@ -4,17 +4,25 @@ use smithay::{
     delegate_xdg_shell,
     desktop::{PopupKind, Window},
     reexports::{
-        wayland_protocols::xdg::shell::server::xdg_toplevel, wayland_server::protocol::wl_seat,
+        wayland_protocols::xdg::shell::server::xdg_toplevel,
+        wayland_server::protocol::{wl_seat, wl_surface::WlSurface},
     },
-    utils::{Serial, SERIAL_COUNTER},
-    wayland::shell::xdg::{
-        PopupSurface, PositionerState, ToplevelSurface, XdgShellHandler, XdgShellState,
+    utils::{IsAlive, Serial, SERIAL_COUNTER},
+    wayland::{
+        seat::WaylandFocus,
+        shell::xdg::{
+            PopupSurface, PositionerState, ToplevelSurface, XdgShellHandler, XdgShellState,
+        },
     },
 };
 
 use crate::{
     iced::elements::target::SpaceTarget,
-    xdg_shell_wrapper::{shared_state::GlobalState, space::WrapperSpace},
+    xdg_shell_wrapper::{
+        client_state::FocusStatus,
+        shared_state::GlobalState,
+        space::{ClientHost, PopupHost},
+    },
 };
 
 impl XdgShellHandler for GlobalState {
@@ -52,13 +60,19 @@ impl XdgShellHandler for GlobalState {
         {
             self.server_state.popup_manager.track_popup(PopupKind::Xdg(surface.clone())).unwrap();
             self.server_state.popup_manager.commit(surface.wl_surface());
-            for kbd in self
+            let keyboards = self
                 .server_state
                 .seats
-                .iter()
-                .filter_map(|s| s.server.seat.get_keyboard())
-                .collect_vec()
-            {
+                .iter_mut()
+                .filter_map(|s| {
+                    let kbd = s.server.seat.get_keyboard()?;
+                    s.server
+                        .popup_focus_return
+                        .push((surface.wl_surface().clone(), kbd.current_focus()));
+                    Some(kbd)
+                })
+                .collect_vec();
+            for kbd in keyboards {
                 kbd.set_focus(
                     self,
                     Some(SpaceTarget::Surface(surface.wl_surface().clone())),
@@ -110,6 +124,53 @@ impl XdgShellHandler for GlobalState {
 
     fn popup_destroyed(&mut self, surface: PopupSurface) {
         self.server_state.popup_manager.commit(surface.wl_surface());
+        self.return_popup_focus(surface.wl_surface());
+    }
+}
+
+impl GlobalState {
+    /// Return the keyboard focus a destroyed popup took to the surface which
+    /// had it before, if the panel still has the keyboard focus of the seat.
+    /// Otherwise the focus is cleared, so the popup gets a leave event and no
+    /// applet keeps receiving input.
+    fn return_popup_focus(&mut self, popup: &WlSurface) {
+        let c_focused_surface = self.client_state.focused_surface.borrow();
+        let mut returns = Vec::new();
+        for seat in &mut self.server_state.seats {
+            let focus_return = &mut seat.server.popup_focus_return;
+            let Some(i) = focus_return.iter().position(|(s, _)| s == popup) else {
+                continue;
+            };
+            let (_, prev) = focus_return.remove(i);
+            // popups opened from this popup return to where it would
+            for (_, later_prev) in focus_return.iter_mut().skip(i) {
+                if later_prev.as_ref().and_then(|t| t.wl_surface()).is_some_and(|s| *s == *popup) {
+                    *later_prev = prev.clone();
+                }
+            }
+
+            let Some(kbd) = seat.server.seat.get_keyboard() else {
+                continue;
+            };
+            let current = kbd.current_focus();
+            if current
+                .as_ref()
+                .is_some_and(|t| t.alive() && t.wl_surface().map_or(true, |s| *s != *popup))
+            {
+                // the focus already moved on
+                continue;
+            }
+            let panel_focused = c_focused_surface.iter().any(|(_, name, status)| {
+                name == &seat.name && matches!(status, FocusStatus::Focused)
+            });
+            let target = prev.filter(|t| panel_focused && t.alive());
+            returns.push((kbd, target));
+        }
+        drop(c_focused_surface);
+
+        for (kbd, target) in returns {
+            kbd.set_focus(self, target, SERIAL_COUNTER.next_serial());
+        }
     }
 }
 
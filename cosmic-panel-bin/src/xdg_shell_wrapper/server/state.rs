@@ -9,8 +9,12 @@ use smithay::{
     wayland::{
         compositor::CompositorState,
         dmabuf::{DmabufGlobal, DmabufState},
+        foreign_toplevel_list::ForeignToplevelListState,
         fractional_scale::FractionalScaleManagerState,
+        idle_inhibit::IdleInhibitManagerState,
         output::OutputManagerState,
+        pointer_constraints::PointerConstraintsState,
+        relative_pointer::RelativePointerManagerState,
         selection::{
             data_device::DataDeviceState, primary_selection::PrimarySelectionState, SelectionSource,
         },
@@ -20,6 +24,11 @@ use smithay::{
     },
 };
 
+use super::handlers::{
+    activation::XdgActivationForwardState, live_config::LiveConfigState,
+    overlap_info::OverlapInfoState, text_input::TextInputForwardState, thumbnail::ThumbnailState,
+    toplevel_minimize::ToplevelMinimizeState, workspace::WorkspaceForwardState,
+};
 use crate::{
     iced::elements::target::SpaceTarget,
     xdg_shell_wrapper::{client_state::ClientSeat, shared_state::GlobalState},
@@ -67,6 +76,17 @@ pub struct ServerState {
     pub(crate) layer_shell_state: WlrLayerShellState,
     pub(crate) _fractional_scale_state: FractionalScaleManagerState,
     pub(crate) _viewporter_state: ViewporterState,
+    pub(crate) foreign_toplevel_list_state: ForeignToplevelListState,
+    pub(crate) workspace_forward_state: WorkspaceForwardState,
+    pub(crate) _activation_state: XdgActivationForwardState,
+    pub(crate) _idle_inhibit_state: IdleInhibitManagerState,
+    pub(crate) text_input_forward_state: TextInputForwardState,
+    pub(crate) overlap_info_state: OverlapInfoState,
+    pub(crate) live_config_state: LiveConfigState,
+    pub(crate) _toplevel_minimize_state: ToplevelMinimizeState,
+    pub(crate) thumbnail_state: ThumbnailState,
+    pub(crate) _pointer_constraints_state: PointerConstraintsState,
+    pub(crate) _relative_pointer_state: RelativePointerManagerState,
 }
 
 impl ServerState {
@@ -87,6 +107,17 @@ impl ServerState {
             layer_shell_state: WlrLayerShellState::new::<GlobalState>(&dh),
             _fractional_scale_state: FractionalScaleManagerState::new::<GlobalState>(&dh),
             _viewporter_state: ViewporterState::new::<GlobalState>(&dh),
+            foreign_toplevel_list_state: ForeignToplevelListState::new::<GlobalState>(&dh),
+            workspace_forward_state: WorkspaceForwardState::new(&dh),
+            _activation_state: XdgActivationForwardState::new(&dh),
+            _idle_inhibit_state: IdleInhibitManagerState::new::<GlobalState>(&dh),
+            text_input_forward_state: TextInputForwardState::new(&dh),
+            overlap_info_state: OverlapInfoState::new(&dh),
+            live_config_state: LiveConfigState::new(&dh),
+            _toplevel_minimize_state: ToplevelMinimizeState::new(&dh),
+            thumbnail_state: ThumbnailState::new(&dh),
+            _pointer_constraints_state: PointerConstraintsState::new::<GlobalState>(&dh),
+            _relative_pointer_state: RelativePointerManagerState::new::<GlobalState>(&dh),
 
             dmabuf_state: None,
         }
@@ -96,8 +127,12 @@ impl ServerState {
 pub(crate) struct ServerSeat {
     pub(crate) seat: Seat<GlobalState>,
     pub(crate) selection_source: Option<SelectionSource>,
+    pub(crate) primary_selection_source: Option<SelectionSource>,
     pub(crate) dnd_source: Option<WlDataSource>,
     pub(crate) dnd_icon: Option<WlSurface>,
+    /// keyboard focus before each open popup took it, returned when the popup
+    /// is destroyed
+    pub(crate) popup_focus_return: Vec<(WlSurface, Option<SpaceTarget>)>,
 }
 
 pub(crate) struct SeatPair {
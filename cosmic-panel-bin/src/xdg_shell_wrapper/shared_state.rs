@@ -2,23 +2,17 @@
 
 use std::time::Duration;
 
-use cctk::wayland_client::Proxy;
 use itertools::Itertools;
 use sctk::{reexports::client::protocol::wl_surface::WlSurface, shell::WaylandSurface};
 use smithay::{
-    backend::{
-        input::KeyState,
-        renderer::{
-            damage::OutputDamageTracker,
-            element::surface::{render_elements_from_surface_tree, WaylandSurfaceRenderElement},
-            gles::GlesRenderer,
-            Bind, ImportDma, ImportEgl, Unbind,
-        },
+    backend::renderer::{
+        damage::OutputDamageTracker,
+        element::surface::{render_elements_from_surface_tree, WaylandSurfaceRenderElement},
+        gles::GlesRenderer,
+        Bind, ImportDma, ImportEgl, Unbind,
     },
     desktop::utils::send_frames_surface_tree,
-    input::keyboard::FilterResult,
     reexports::wayland_server::DisplayHandle,
-    utils::SERIAL_COUNTER,
     wayland::{
         compositor::with_states, dmabuf::DmabufState, fractional_scale::with_fractional_scale,
     },
@@ -28,7 +22,9 @@ use tracing::{error, info};
 use crate::{
     space_container::SpaceContainer,
     xdg_shell_wrapper::{
-        client_state::ClientState, server_state::ServerState, space::WrapperSpace,
+        client_state::ClientState,
+        server_state::ServerState,
+        space::{OutputHandler, RootSpace},
     },
 };
 
@@ -61,40 +57,6 @@ impl GlobalState {
         // cleanup popup manager
         self.server_state.popup_manager.cleanup();
 
-        // handle funky keyboard state.
-        // if a client layer shell surface is closed, then it won't receive the release
-        // event then the client will keep receiving input
-        // so we send the release here instead
-        let press = if let Some((key_pressed, kbd)) = self
-            .client_state
-            .last_key_pressed
-            .iter()
-            .position(|(_, _, layer_shell_wl_surface)| !layer_shell_wl_surface.is_alive())
-            .and_then(|key_pressed| {
-                self.server_state
-                    .seats
-                    .iter()
-                    .find(|s| s.name == self.client_state.last_key_pressed[key_pressed].0)
-                    .and_then(|s| {
-                        s.server.seat.get_keyboard().map(|kbd| {
-                            (self.client_state.last_key_pressed.remove(key_pressed), kbd)
-                        })
-                    })
-            }) {
-            Some((key_pressed, kbd))
-        } else {
-            None
-        };
-        if let Some((key_pressed, kbd)) = press {
-            kbd.input::<(), _>(
-                self,
-                key_pressed.1 .0.into(),
-                KeyState::Released,
-                SERIAL_COUNTER.next_serial(),
-                key_pressed.1 .1.wrapping_add(1),
-                move |_, _modifiers, _keysym| FilterResult::Forward,
-            );
-        }
         self.space.cleanup();
     }
 
@@ -157,19 +119,16 @@ impl GlobalState {
     /// draw the dnd icon if it exists and is ready
     pub fn draw_dnd_icon(&mut self) {
         // TODO proxied layer surfaces
-        if let Some((
-            (egl_surface, wl_surface, ref mut dmg_tracked_renderer, is_dirty, has_frame),
-            s_icon,
-        )) = self
+        if let Some((icon, s_icon)) = self
             .server_state
             .seats
             .iter_mut()
             .find_map(|s| s.client.dnd_icon.as_mut().zip(s.server.dnd_icon.as_mut()))
         {
-            if !*is_dirty || has_frame.is_none() {
+            if !icon.is_dirty || icon.has_frame.is_none() {
                 return;
             }
-            let Some(egl_surface) = egl_surface.as_ref() else {
+            let Some(egl_surface) = icon.egl_surface.as_ref() else {
                 return;
             };
             info!("draw_dnd_icon actually happening");
@@ -191,12 +150,12 @@ impl GlobalState {
                     renderer,
                     s_icon,
                     (1, 1),
-                    1.0,
+                    icon.scale,
                     1.0,
                     smithay::backend::renderer::element::Kind::Unspecified,
                 );
 
-            _ = dmg_tracked_renderer.render_output(
+            _ = icon.damage_tracked_renderer.render_output(
                 renderer,
                 egl_surface.buffer_age().unwrap_or_default() as usize,
                 &elements,
@@ -221,11 +180,11 @@ impl GlobalState {
                     move |_, _| Some(output.clone()),
                 );
             }
-            wl_surface.frame(&self.client_state.queue_handle, wl_surface.clone());
-            wl_surface.commit();
+            icon.surface.frame(&self.client_state.queue_handle, icon.surface.clone());
+            icon.surface.commit();
 
-            *is_dirty = false;
-            *has_frame = None;
+            icon.is_dirty = false;
+            icon.has_frame = None;
         }
     }
 }
@@ -1,6 +1,9 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use std::rc::Rc;
+use std::{
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use cctk::wayland_client::protocol::wl_surface::WlSurface;
 use sctk::{
@@ -36,6 +39,84 @@ pub enum WrapperPopupState {
     },
 }
 
+/// duration of the entrance and exit animation of a popup
+pub const POPUP_ANIMATION_DURATION: Duration = Duration::from_millis(150);
+
+/// Entrance and exit animation of a popup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PopupAnimation {
+    /// Not animated, or the entrance animation finished
+    Shown,
+    /// Waiting for the first frame to start the entrance animation
+    Pending,
+    /// Entrance animation started at the instant
+    Mapping(Instant),
+    /// Exit animation started at the instant
+    Dismissing(Instant),
+}
+
+impl PopupAnimation {
+    /// The animation of a new popup
+    pub fn new(animate: bool) -> Self {
+        if animate {
+            PopupAnimation::Pending
+        } else {
+            PopupAnimation::Shown
+        }
+    }
+
+    /// Start the exit animation, from the current progress of the entrance
+    /// animation
+    pub fn dismiss(&mut self) {
+        let shown = match self {
+            PopupAnimation::Dismissing(_) => return,
+            PopupAnimation::Pending => 0.,
+            _ => self.linear_progress(),
+        };
+        let now = Instant::now();
+        *self = PopupAnimation::Dismissing(
+            now.checked_sub(POPUP_ANIMATION_DURATION.mul_f32(1. - shown)).unwrap_or(now),
+        );
+    }
+
+    /// How far the popup is shown, from 0 to 1, eased
+    pub fn progress(&mut self) -> f32 {
+        // ease out cubic
+        1. - (1. - self.linear_progress()).powi(3)
+    }
+
+    fn linear_progress(&mut self) -> f32 {
+        match *self {
+            PopupAnimation::Shown => 1.,
+            PopupAnimation::Pending => {
+                *self = PopupAnimation::Mapping(Instant::now());
+                0.
+            },
+            PopupAnimation::Mapping(started) => {
+                let progress =
+                    started.elapsed().as_secs_f32() / POPUP_ANIMATION_DURATION.as_secs_f32();
+                if progress >= 1. {
+                    *self = PopupAnimation::Shown;
+                }
+                progress.min(1.)
+            },
+            PopupAnimation::Dismissing(started) => (1.
+                - started.elapsed().as_secs_f32() / POPUP_ANIMATION_DURATION.as_secs_f32())
+            .max(0.),
+        }
+    }
+
+    /// Whether the popup needs more frames for the animation
+    pub fn is_running(&self) -> bool {
+        !matches!(self, PopupAnimation::Shown)
+    }
+
+    /// Whether the exit animation finished
+    pub fn is_dismissed(&self) -> bool {
+        matches!(self, PopupAnimation::Dismissing(started) if started.elapsed() >= POPUP_ANIMATION_DURATION)
+    }
+}
+
 /// Popup
 #[derive(Debug)]
 pub struct WrapperPopup {
@@ -43,6 +124,12 @@ pub struct WrapperPopup {
     pub popup: PanelPopup,
     /// the embedded popup
     pub s_surface: PopupSurface,
+    /// space on each side of the embedded popup for decorations drawn around
+    /// it
+    pub decoration_margin: i32,
+    /// scale the compositor prefers for the popup surface, which overrides
+    /// the scale of the output the popup mostly occupies
+    pub preferred_scale: Option<f64>,
 }
 
 #[derive(Debug)]
@@ -78,6 +165,8 @@ pub struct PanelPopup {
     pub damage_tracked_renderer: OutputDamageTracker,
     /// parent of the popup
     pub parent: WlSurface,
+    /// entrance and exit animation
+    pub animation: PopupAnimation,
 }
 
 impl WrapperPopup {
@@ -94,25 +94,17 @@ impl Default for Visibility {
     }
 }
 
-// TODO break this trait into several traits so that it can be better organized
-// not all "space" implementations really need all of these exact methods as
-// long as they are wrapped by a space that does see cosmic-panel for an example
-
 /// Wrapper Space
 /// manages and renders xdg-shell-window(s) on a layer shell surface
+///
+/// Spaces which host clients, popups or outputs, or route input, implement
+/// the corresponding [`ClientHost`], [`PopupHost`], [`OutputHandler`] and
+/// [`InputRouter`] as well. The space held by the wrapper also implements
+/// [`RootSpace`].
 pub trait WrapperSpace {
     /// Wrapper config type
     type Config: WrapperConfig;
 
-    /// set the display handle of the space
-    fn set_display_handle(&mut self, display: wayland_server::DisplayHandle);
-
-    /// get the client hovered surface of the space
-    fn get_client_hovered_surface(&self) -> Rc<RefCell<ClientFocus>>;
-
-    /// get the client focused surface of the space
-    fn get_client_focused_surface(&self) -> Rc<RefCell<ClientFocus>>;
-
     /// setup of the space after the wayland connection is ready
     fn setup(
         &mut self,
@@ -126,100 +118,30 @@ pub trait WrapperSpace {
         overlap_notify: Option<OverlapNotifyV1>,
     );
 
-    /// add the configured output to the space
-    fn new_output(
-        &mut self,
-        compositor_state: &CompositorState,
-        fractional_scale_manager: Option<&FractionalScalingManager>,
-        viewport: Option<&ViewporterState>,
-        layer_state: &mut LayerShell,
-        conn: &Connection,
-        qh: &QueueHandle<GlobalState>,
-        c_output: Option<c_wl_output::WlOutput>,
-        s_output: Option<Output>,
-        info: Option<OutputInfo>,
-    ) -> anyhow::Result<()>;
-
-    /// update the configured output in the space
-    fn update_output(
-        &mut self,
-        c_output: c_wl_output::WlOutput,
-        s_output: Output,
-        info: OutputInfo,
-    ) -> anyhow::Result<bool>;
-
-    /// remove the configured output from the space
-    fn output_leave(
-        &mut self,
-        c_output: c_wl_output::WlOutput,
-        s_output: Output,
-    ) -> anyhow::Result<()>;
-
-    /// handle pointer motion on the space
-    fn update_pointer(
-        &mut self,
-        dim: (i32, i32),
-        seat_name: &str,
-        surface: wl_surface::WlSurface,
-        pointer: &WlPointer,
-    ) -> Option<ServerPointerFocus>;
-
-    /// add a top level window to the space
-    fn add_window(&mut self, s_top_level: Window);
-
-    /// add a popup to the space
-    fn add_popup(
-        &mut self,
-        compositor_state: &CompositorState,
-        fractional_scale_manager: Option<&FractionalScalingManager>,
-        viewport: Option<&ViewporterState>,
-        conn: &Connection,
-        qh: &QueueHandle<GlobalState>,
-        xdg_shell_state: &mut XdgShell,
-        s_surface: PopupSurface,
-        positioner: XdgPositioner,
-        positioner_state: PositionerState,
-    ) -> anyhow::Result<()>;
-
-    /// handle a button press or release on a client surface
-    /// optionally returns an interacted server wl surface
-    fn handle_button(&mut self, seat_name: &str, press: bool) -> Option<SpaceTarget>;
-
-    /// keyboard focus lost handler
-    fn keyboard_leave(&mut self, seat_name: &str, surface: Option<wl_surface::WlSurface>);
+    /// gets the config
+    fn config(&self) -> Self::Config;
 
-    /// keyboard focus gained handler
-    /// optionally returns a focused server wl surface
-    fn keyboard_enter(
-        &mut self,
-        seat_name: &str,
-        surface: wl_surface::WlSurface,
-    ) -> Option<s_WlSurface>;
+    /// gets visibility of the wrapper
+    fn visibility(&self) -> Visibility {
+        Visibility::Visible
+    }
 
-    /// pointer focus lost handler
-    fn pointer_leave(&mut self, seat_name: &str, surface: Option<wl_surface::WlSurface>);
+    /// configure layer
+    fn configure_layer(&mut self, layer: &LayerSurface, configure: LayerSurfaceConfigure);
 
-    /// pointer focus gained handler
-    fn pointer_enter(
-        &mut self,
-        dim: (i32, i32),
-        seat_name: &str,
-        surface: wl_surface::WlSurface,
-        pointer: &WlPointer,
-    ) -> Option<ServerPointerFocus>;
+    /// close layer in space
+    fn close_layer(&mut self, layer: &LayerSurface);
 
-    /// repositions a popup
-    fn reposition_popup(
-        &mut self,
-        popup: PopupSurface,
-        positioner_state: PositionerState,
-        token: u32,
-    ) -> anyhow::Result<()>;
+    /// received a frame event for the given surface
+    fn frame(&mut self, surface: &wl_surface::WlSurface, time: u32);
+}
 
+/// The space held by the wrapper, which owns the renderer and handles the
+/// events of the spaces it contains
+pub trait RootSpace: WrapperSpace {
     /// called in a loop by xdg-shell-wrapper
     /// handles events for the space
-    /// returns the Instant it was last updated by clients and a list of
-    /// surfaces to request frames for
+    /// returns the Instant it was last updated by clients
     fn handle_events(
         &mut self,
         dh: &DisplayHandle,
@@ -229,8 +151,17 @@ pub trait WrapperSpace {
         throttle: Option<Duration>,
     ) -> Instant;
 
-    /// gets the config
-    fn config(&self) -> Self::Config;
+    /// gets the renderer for the space
+    fn renderer(&mut self) -> Option<&mut GlesRenderer>;
+}
+
+/// Spawns the clients of a space and manages their toplevel windows
+pub trait ClientHost {
+    /// set the display handle of the space
+    fn set_display_handle(&mut self, display: wayland_server::DisplayHandle);
+
+    /// add a top level window to the space
+    fn add_window(&mut self, s_top_level: Window);
 
     /// spawns the clients for the wrapper
     fn spawn_clients(
@@ -240,14 +171,6 @@ pub trait WrapperSpace {
         security_context_manager: Option<SecurityContextManager>,
     ) -> anyhow::Result<()>;
 
-    /// gets visibility of the wrapper
-    fn visibility(&self) -> Visibility {
-        Visibility::Visible
-    }
-
-    /// cleanup
-    fn destroy(&mut self);
-
     /// Moves an already mapped Window to top of the stack
     /// This function does nothing for unmapped windows.
     /// If activate is true it will set the new windows state to be activate and
@@ -256,6 +179,31 @@ pub trait WrapperSpace {
 
     /// marks the window as dirtied
     fn dirty_window(&mut self, dh: &DisplayHandle, w: &s_WlSurface);
+}
+
+/// Manages the popups of the clients of a space
+pub trait PopupHost {
+    /// add a popup to the space
+    fn add_popup(
+        &mut self,
+        compositor_state: &CompositorState,
+        fractional_scale_manager: Option<&FractionalScalingManager>,
+        viewport: Option<&ViewporterState>,
+        conn: &Connection,
+        qh: &QueueHandle<GlobalState>,
+        xdg_shell_state: &mut XdgShell,
+        s_surface: PopupSurface,
+        positioner: XdgPositioner,
+        positioner_state: PositionerState,
+    ) -> anyhow::Result<()>;
+
+    /// repositions a popup
+    fn reposition_popup(
+        &mut self,
+        popup: PopupSurface,
+        positioner_state: PositionerState,
+        token: u32,
+    ) -> anyhow::Result<()>;
 
     /// marks the popup as dirtied()
     fn dirty_popup(&mut self, dh: &DisplayHandle, w: &s_WlSurface);
@@ -269,18 +217,38 @@ pub trait WrapperSpace {
 
     /// finished popup
     fn close_popup(&mut self, popup: &sctk::shell::xdg::popup::Popup);
+}
 
-    /// configure layer
-    fn configure_layer(&mut self, layer: &LayerSurface, configure: LayerSurfaceConfigure);
-
-    /// close layer in space
-    fn close_layer(&mut self, layer: &LayerSurface);
+/// Tracks the outputs of a space and the scale and transform of its surfaces
+pub trait OutputHandler {
+    /// add the configured output to the space
+    fn new_output(
+        &mut self,
+        compositor_state: &CompositorState,
+        fractional_scale_manager: Option<&FractionalScalingManager>,
+        viewport: Option<&ViewporterState>,
+        layer_state: &mut LayerShell,
+        conn: &Connection,
+        qh: &QueueHandle<GlobalState>,
+        c_output: Option<c_wl_output::WlOutput>,
+        s_output: Option<Output>,
+        info: Option<OutputInfo>,
+    ) -> anyhow::Result<()>;
 
-    /// gets the renderer for twl_surface::wl_surface::he space
-    fn renderer(&mut self) -> Option<&mut GlesRenderer>;
+    /// update the configured output in the space
+    fn update_output(
+        &mut self,
+        c_output: c_wl_output::WlOutput,
+        s_output: Output,
+        info: OutputInfo,
+    ) -> anyhow::Result<bool>;
 
-    /// received a frame event for the given surface
-    fn frame(&mut self, surface: &wl_surface::WlSurface, time: u32);
+    /// remove the configured output from the space
+    fn output_leave(
+        &mut self,
+        c_output: c_wl_output::WlOutput,
+        s_output: Output,
+    ) -> anyhow::Result<()>;
 
     /// scale factor changed for the given surface
     /// if this is a surface for this space, it should be tracked
@@ -303,3 +271,48 @@ pub trait WrapperSpace {
     /// returns none if the surface is not tracked by this space
     fn get_scale_factor(&self, surface: &s_WlSurface) -> Option<f64>;
 }
+
+/// Routes pointer and keyboard input of the surfaces of a space to its clients
+pub trait InputRouter {
+    /// get the client hovered surface of the space
+    fn get_client_hovered_surface(&self) -> Rc<RefCell<ClientFocus>>;
+
+    /// get the client focused surface of the space
+    fn get_client_focused_surface(&self) -> Rc<RefCell<ClientFocus>>;
+
+    /// handle pointer motion on the space
+    fn update_pointer(
+        &mut self,
+        dim: (f64, f64),
+        seat_name: &str,
+        surface: wl_surface::WlSurface,
+        pointer: &WlPointer,
+    ) -> Option<ServerPointerFocus>;
+
+    /// handle a button press or release on a client surface
+    /// optionally returns an interacted server wl surface
+    fn handle_button(&mut self, seat_name: &str, press: bool) -> Option<SpaceTarget>;
+
+    /// keyboard focus lost handler
+    fn keyboard_leave(&mut self, seat_name: &str, surface: Option<wl_surface::WlSurface>);
+
+    /// keyboard focus gained handler
+    /// optionally returns a focused server wl surface
+    fn keyboard_enter(
+        &mut self,
+        seat_name: &str,
+        surface: wl_surface::WlSurface,
+    ) -> Option<s_WlSurface>;
+
+    /// pointer focus lost handler
+    fn pointer_leave(&mut self, seat_name: &str, surface: Option<wl_surface::WlSurface>);
+
+    /// pointer focus gained handler
+    fn pointer_enter(
+        &mut self,
+        dim: (f64, f64),
+        seat_name: &str,
+        surface: wl_surface::WlSurface,
+        pointer: &WlPointer,
+    ) -> Option<ServerPointerFocus>;
+}
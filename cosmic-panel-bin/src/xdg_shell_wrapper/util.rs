@@ -29,6 +29,21 @@ pub fn smootherstep(t: f32) -> f32 {
     (6.0 * t.powi(5) - 15.0 * t.powi(4) + 10.0 * t.powi(3)).clamp(0.0, 1.0)
 }
 
+/// The local hour and minute, or `None` if the local time is unknown.
+pub fn local_time() -> Option<(i32, i32)> {
+    // SAFETY: with a null pointer, `time` only returns the current time
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    // SAFETY: `tm` only holds integers and a pointer, for which all zeroes
+    // are valid
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    // SAFETY: both pointers are valid for the call, and `localtime_r` only
+    // writes to `tm`
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        return None;
+    }
+    Some((tm.tm_hour, tm.tm_min))
+}
+
 /// helper function for inserting a wrapped applet client
 pub fn get_client_sock(display: &mut wayland_server::DisplayHandle) -> (Client, UnixStream) {
     let (display_sock, client_sock) = UnixStream::pair().unwrap();
@@ -0,0 +1,107 @@
+//! Watchdog for keys and buttons which are stuck pressed in embedded clients.
+//!
+//! Applets only see a key or button released if the release reaches the panel
+//! surface which received the press. If that surface is destroyed, or loses
+//! the keyboard or pointer focus of its seat first, no release arrives and the
+//! applet would keep seeing the key or button held. Every dispatch cycle, the
+//! pressed keys and buttons are audited against the aliveness and focus of
+//! their surfaces, and releases are synthesized for stale ones.
+
+use sctk::{
+    reexports::client::{protocol::wl_surface::WlSurface, Proxy},
+    seat::keyboard::KeyCode,
+};
+use smithay::{
+    backend::input::{ButtonState, KeyState},
+    input::{keyboard::FilterResult, pointer::ButtonEvent},
+    utils::SERIAL_COUNTER,
+};
+
+use crate::xdg_shell_wrapper::{
+    client_state::{ClientFocus, FocusStatus},
+    shared_state::GlobalState,
+};
+
+/// Whether `surface` is alive and has the focus of `seat_name`.
+fn holds_focus(focus: &ClientFocus, seat_name: &str, surface: &WlSurface) -> bool {
+    surface.is_alive()
+        && focus.iter().any(|(s, name, status)| {
+            s == surface && name == seat_name && matches!(status, FocusStatus::Focused)
+        })
+}
+
+/// Remove the presses of `pressed` whose surface doesn't hold the focus of
+/// their seat anymore.
+fn take_stale(
+    pressed: &mut Vec<(String, (u32, u32), WlSurface)>,
+    focus: &ClientFocus,
+) -> Vec<(String, (u32, u32))> {
+    let mut stale = Vec::new();
+    pressed.retain(|(seat_name, press, surface)| {
+        if holds_focus(focus, seat_name, surface) {
+            return true;
+        }
+        stale.push((seat_name.clone(), *press));
+        false
+    });
+    stale
+}
+
+impl GlobalState {
+    /// Release the keys and buttons whose release can't reach the panel
+    /// anymore.
+    pub(crate) fn release_stale_input(&mut self) {
+        let stale_keys = take_stale(
+            &mut self.client_state.last_key_pressed,
+            &self.client_state.focused_surface.borrow(),
+        );
+        let stale_buttons = take_stale(
+            &mut self.client_state.pressed_buttons,
+            &self.client_state.hovered_surface.borrow(),
+        );
+
+        for (seat_name, (raw_code, time)) in stale_keys {
+            let Some(kbd) = self
+                .server_state
+                .seats
+                .iter()
+                .find(|s| s.name == seat_name)
+                .and_then(|s| s.server.seat.get_keyboard())
+            else {
+                continue;
+            };
+            tracing::debug!("Releasing stale key {raw_code} of seat {seat_name}");
+            kbd.input::<(), _>(
+                self,
+                KeyCode::new(raw_code.saturating_add(8)),
+                KeyState::Released,
+                SERIAL_COUNTER.next_serial(),
+                time.wrapping_add(1),
+                move |_, _modifiers, _keysym| FilterResult::Forward,
+            );
+        }
+
+        for (seat_name, (button, time)) in stale_buttons {
+            let Some(ptr) = self
+                .server_state
+                .seats
+                .iter()
+                .find(|s| s.name == seat_name)
+                .and_then(|s| s.server.seat.get_pointer())
+            else {
+                continue;
+            };
+            tracing::debug!("Releasing stale button {button} of seat {seat_name}");
+            ptr.button(
+                self,
+                &ButtonEvent {
+                    serial: SERIAL_COUNTER.next_serial(),
+                    time: time.wrapping_add(1),
+                    button,
+                    state: ButtonState::Released,
+                },
+            );
+            ptr.frame(self);
+        }
+    }
+}
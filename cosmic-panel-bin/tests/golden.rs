@@ -0,0 +1,253 @@
+//! Compares offscreen renders of the panel background with the golden images
+//! in `tests/golden`, and the applet layout planned for a few panels with the
+//! golden files next to them. Run with `GOLDEN_UPDATE=1` to rewrite them
+//! after an intended change. Renders which don't match are written to
+//! `cosmic-panel-golden` in the temporary directory.
+//!
+//! Needs the `golden-tests` feature. The background is skipped when no EGL
+//! device with a render node is available.
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use cosmic_panel::{
+    section_layout::{
+        place_slots, plan_shrink, pushed_length, section_targets, SectionSums, ShrinkCandidate,
+        Slot,
+    },
+    BackgroundRenderer,
+};
+
+/// Largest per channel difference which still counts as a match, to tolerate
+/// small rasterization differences between drivers.
+const TOLERANCE: u8 = 2;
+
+const SIZES: &[(i32, i32)] = &[(400, 40), (40, 400), (120, 64)];
+const SCALES: &[f64] = &[1.0, 1.25, 2.0];
+const RADII: &[f32] = &[0.0, 8.0, 32.0];
+/// Light and dark panel backgrounds. They are fixed rather than taken from the
+/// default themes, so palette changes of libcosmic don't invalidate the images.
+const THEMES: &[(&str, [f32; 4])] =
+    &[("light", [0.92, 0.92, 0.92, 1.0]), ("dark", [0.11, 0.11, 0.11, 1.0])];
+
+/// Padding and spacing of a panel with the default config, in logical pixels.
+const PADDING: u32 = 4;
+const SPACING: u32 = 4;
+
+/// A panel of `length` with applets of the given lengths in its sections.
+/// Applets may be pinned to a start position. When their section overflows,
+/// they are shrunk in order down to half their length, except for those with
+/// a negative priority.
+struct LayoutCase {
+    name: &'static str,
+    length: i32,
+    sections: [&'static [(f64, Option<f64>, i32)]; 3],
+}
+
+const LAYOUT_CASES: &[LayoutCase] = &[
+    LayoutCase {
+        name: "sides",
+        length: 1920,
+        sections: [&[(40., None, 0), (120., None, 0)], &[], &[(32., None, 0), (32., None, 0)]],
+    },
+    LayoutCase {
+        name: "sections",
+        length: 1920,
+        sections: [
+            &[(40., None, 0), (40., None, 0)],
+            &[(200., None, 0)],
+            &[(32., None, 0), (32., None, 0), (80., None, 0)],
+        ],
+    },
+    LayoutCase {
+        name: "center-only",
+        length: 800,
+        sections: [&[], &[(48., None, 0), (48., None, 0), (48., None, 0)], &[]],
+    },
+    LayoutCase {
+        name: "pinned",
+        length: 1280,
+        sections: [&[(40., None, 0), (40., None, 0), (40., None, 0)], &[], &[(60., Some(60.), 0)]],
+    },
+    LayoutCase {
+        name: "overflow",
+        length: 600,
+        sections: [
+            &[(200., None, 1), (150., None, 2), (40., None, -1)],
+            &[(100., None, 0)],
+            &[(40., None, 0)],
+        ],
+    },
+];
+
+/// Where files which don't match their golden file are written, outside the
+/// source tree.
+fn actual_dir() -> PathBuf {
+    std::env::temp_dir().join("cosmic-panel-golden")
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn update() -> bool {
+    std::env::var_os("GOLDEN_UPDATE").is_some_and(|v| !v.is_empty() && v != "0")
+}
+
+/// Compare `contents` with the golden file `name`, or rewrite it when
+/// updating. Returns whether it matched.
+fn compare(name: &str, contents: &[u8], matches: impl Fn(&[u8]) -> bool) -> bool {
+    let path = golden_dir().join(name);
+    if update() {
+        std::fs::write(&path, contents).unwrap();
+        eprintln!("wrote {}", path.display());
+        return true;
+    }
+    let Ok(expected) = std::fs::read(&path) else {
+        eprintln!("{name} has no golden file, run with GOLDEN_UPDATE=1 to write it");
+        return false;
+    };
+    if matches(&expected) {
+        return true;
+    }
+    std::fs::create_dir_all(actual_dir()).unwrap();
+    let actual = actual_dir().join(name);
+    std::fs::write(&actual, contents).unwrap();
+    eprintln!("{name} does not match its golden file, see {}", actual.display());
+    false
+}
+
+fn encode_png((w, h): (u32, u32), pixels: &[u8]) -> Vec<u8> {
+    let mut png = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png, w, h);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header().unwrap().write_image_data(pixels).unwrap();
+    }
+    png
+}
+
+/// Whether the PNG `expected` has `size` and matches `pixels` within the
+/// tolerance.
+fn png_matches(expected: &[u8], (w, h): (u32, u32), pixels: &[u8]) -> bool {
+    let Ok(mut reader) = png::Decoder::new(expected).read_info() else {
+        return false;
+    };
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let Ok(info) = reader.next_frame(&mut buf) else {
+        return false;
+    };
+    info.width == w
+        && info.height == h
+        && buf[..info.buffer_size()].iter().zip(pixels).all(|(a, b)| a.abs_diff(*b) <= TOLERANCE)
+}
+
+#[test]
+fn background_matches_golden_images() {
+    let has_render_node = std::fs::read_dir("/dev/dri")
+        .map(|d| d.flatten().any(|e| e.file_name().to_string_lossy().starts_with("renderD")))
+        .unwrap_or(false);
+    if !has_render_node {
+        eprintln!("skipping: no render node available");
+        return;
+    }
+
+    let mut renderer = BackgroundRenderer::new().expect("failed to create the renderer");
+    let mut ok = true;
+    for &(w, h) in SIZES {
+        for &scale in SCALES {
+            for &radius in RADII {
+                for &(theme_name, bg_color) in THEMES {
+                    let name = format!("background-{w}x{h}-{scale}x-r{radius}-{theme_name}.png");
+                    let (size, pixels) =
+                        renderer.render((w, h), scale, radius, bg_color).expect("failed to render");
+                    ok &= compare(&name, &encode_png(size, &pixels), |expected| {
+                        png_matches(expected, size, &pixels)
+                    });
+                }
+            }
+        }
+    }
+    assert!(ok, "rendering differs from the golden images in {}", golden_dir().display());
+}
+
+/// The section lengths, applet positions and shrunk applets planned for
+/// `case`, like a layout of a horizontal panel at scale 1 without overflow
+/// buttons.
+fn plan_layout(case: &LayoutCase) -> String {
+    let spacing = SPACING as f64;
+    let padding = PADDING as f64;
+    let major = case.length as f64;
+    let slots = case.sections.map(|applets| {
+        applets.iter().map(|&(length, pinned, _)| Slot { length, pinned }).collect::<Vec<_>>()
+    });
+    let sum = |slots: &[Slot]| {
+        slots.iter().map(|s| s.length).sum::<f64>() + spacing * slots.len().saturating_sub(1) as f64
+    };
+    let mut pinned: Vec<(f64, f64)> = slots
+        .iter()
+        .flatten()
+        .filter_map(|s| s.pinned.map(|start| (start, start + s.length)))
+        .collect();
+    pinned.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // the sections get longer by the length their applets are pushed past
+    // pinned applets, and the right section still ends at the end of the panel
+    let center_sum = sum(&slots[1]);
+    let mut starts = [padding, major / 2. - center_sum / 2., major - sum(&slots[2]) - padding];
+    let [left, center, right] =
+        [0, 1, 2].map(|i| sum(&slots[i]) + pushed_length(starts[i], spacing, &slots[i], &pinned));
+    starts[2] -= right - sum(&slots[2]);
+    let sums = SectionSums { left, center, right };
+    let has_sides = !slots[0].is_empty() || !slots[2].is_empty();
+    let has_center = !slots[1].is_empty();
+    let num_lists = 2 * u32::from(has_sides) + u32::from(has_center);
+    let targets = section_targets(case.length, sums, SPACING, PADDING, num_lists, has_center);
+
+    let mut out = String::new();
+    writeln!(out, "panel {}", case.length).unwrap();
+    for (((name, slots), (start, target)), (applets, sum)) in ["left", "center", "right"]
+        .into_iter()
+        .zip(&slots)
+        .zip(starts.into_iter().zip([targets.left, targets.center, targets.right]))
+        .zip(case.sections.iter().zip([sums.left, sums.center, sums.right]))
+    {
+        writeln!(out, "{name} length {sum} target {target}").unwrap();
+        for (slot, pos) in slots.iter().zip(place_slots(start, spacing, slots, &pinned)) {
+            writeln!(out, "  applet at {pos} length {}", slot.length).unwrap();
+        }
+        let overflow = sum - target;
+        if overflow > 0. {
+            let candidates = applets
+                .iter()
+                .map(|&(length, _, priority)| ShrinkCandidate {
+                    length,
+                    suggested: 0,
+                    priority,
+                    min_length: (length / 2.) as u32,
+                })
+                .collect::<Vec<_>>();
+            let (planned, remaining) = plan_shrink(overflow as u32, &candidates, false);
+            for (i, length) in planned {
+                writeln!(out, "  shrink applet {i} to {length}").unwrap();
+            }
+            writeln!(out, "  overflow {remaining}").unwrap();
+        }
+    }
+    out
+}
+
+#[test]
+fn layout_matches_golden_files() {
+    let mut ok = true;
+    for case in LAYOUT_CASES {
+        let plan = plan_layout(case);
+        ok &= compare(&format!("layout-{}.txt", case.name), plan.as_bytes(), |expected| {
+            expected == plan.as_bytes()
+        });
+    }
+    assert!(ok, "the layout differs from the golden files in {}", golden_dir().display());
+}
@@ -0,0 +1,243 @@
+//! Runs the panel against a headless wlroots compositor on a private session
+//! bus, and checks its state through the control interface.
+//!
+//! The tests need `sway` and `dbus-daemon` in `PATH`, so they are ignored by
+//! default. Run them with `cargo test --test headless -- --ignored`; they fail
+//! if either program is missing.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use tempfile::TempDir;
+use zbus::proxy;
+
+#[proxy(
+    default_service = "com.system76.CosmicPanel",
+    interface = "com.system76.CosmicPanel",
+    default_path = "/com/system76/CosmicPanel"
+)]
+trait Panel {
+    fn panels(&self) -> zbus::Result<Vec<(String, String, String, String, i32, i32, i32)>>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PanelState {
+    name: String,
+    output: String,
+    anchor: String,
+    visibility: String,
+    size: (i32, i32),
+}
+
+struct Harness {
+    dir: TempDir,
+    dbus: Child,
+    compositor: Child,
+    panel: Option<Child>,
+    bus_address: String,
+    wayland_display: String,
+}
+
+impl Harness {
+    /// Start a headless compositor and a private session bus.
+    fn new() -> Self {
+        for program in ["sway", "dbus-daemon"] {
+            assert!(has_program(program), "{program} is required for the headless tests");
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let runtime_dir = dir.path().join("runtime");
+        fs::create_dir_all(&runtime_dir).unwrap();
+        fs::write(dir.path().join("sway.conf"), "output HEADLESS-1 resolution 1920x1080\n")
+            .unwrap();
+
+        let mut dbus = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to spawn dbus-daemon");
+        let mut bus_address = String::new();
+        BufReader::new(dbus.stdout.take().unwrap()).read_line(&mut bus_address).unwrap();
+        let bus_address = bus_address.trim().to_string();
+
+        let compositor = Command::new("sway")
+            .args(["--unsupported-gpu", "-c"])
+            .arg(dir.path().join("sway.conf"))
+            .env("WLR_BACKENDS", "headless")
+            .env("WLR_RENDERER", "pixman")
+            .env("WLR_LIBINPUT_NO_DEVICES", "1")
+            .env("XDG_RUNTIME_DIR", &runtime_dir)
+            .env("DBUS_SESSION_BUS_ADDRESS", &bus_address)
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to spawn sway");
+
+        let wayland_display = wait_for(Duration::from_secs(5), || {
+            fs::read_dir(&runtime_dir).ok()?.flatten().find_map(|e| {
+                let name = e.file_name().to_string_lossy().to_string();
+                (name.starts_with("wayland-") && !name.ends_with(".lock")).then_some(name)
+            })
+        })
+        .expect("sway never created its wayland socket");
+
+        Self { dir, dbus, compositor, panel: None, bus_address, wayland_display }
+    }
+
+    fn config_dir(&self) -> PathBuf {
+        self.dir.path().join("config")
+    }
+
+    /// Install the default panel configuration, then apply `overrides` as
+    /// (entry, key, ron value) triples.
+    fn write_config(&self, entries: &[&str], overrides: &[(&str, &str, &str)]) {
+        let schema = Path::new(env!("CARGO_MANIFEST_DIR")).join("../data/default_schema");
+        let cosmic_dir = self.config_dir().join("cosmic");
+        copy_dir(&schema, &cosmic_dir);
+        let entries = entries.iter().map(|e| format!("{e:?}")).collect::<Vec<_>>().join(", ");
+        fs::write(cosmic_dir.join("com.system76.CosmicPanel/v1/entries"), format!("[{entries}]"))
+            .unwrap();
+        for (entry, key, value) in overrides {
+            let path = cosmic_dir.join(format!("com.system76.CosmicPanel.{entry}/v1/{key}"));
+            fs::write(path, value).unwrap();
+        }
+    }
+
+    fn spawn_panel(&mut self) {
+        let panel = Command::new(env!("CARGO_BIN_EXE_cosmic-panel"))
+            .env("XDG_CONFIG_HOME", self.config_dir())
+            .env("XDG_RUNTIME_DIR", self.dir.path().join("runtime"))
+            .env("WAYLAND_DISPLAY", &self.wayland_display)
+            .env("DBUS_SESSION_BUS_ADDRESS", &self.bus_address)
+            .env("RUST_LOG", "debug")
+            .spawn()
+            .expect("failed to spawn cosmic-panel");
+        self.panel = Some(panel);
+    }
+
+    fn panels(&self) -> Option<Vec<PanelState>> {
+        let conn = zbus::blocking::connection::Builder::address(self.bus_address.as_str())
+            .ok()?
+            .build()
+            .ok()?;
+        let proxy = PanelProxyBlocking::new(&conn).ok()?;
+        let panels = proxy.panels().ok()?;
+        Some(
+            panels
+                .into_iter()
+                .map(|(name, output, anchor, visibility, w, h, ..)| PanelState {
+                    name,
+                    output,
+                    anchor,
+                    visibility,
+                    size: (w, h),
+                })
+                .collect(),
+        )
+    }
+
+    /// Poll the panel state until `f` accepts it.
+    fn wait_for_panels(
+        &self,
+        timeout: Duration,
+        f: impl Fn(&[PanelState]) -> bool,
+    ) -> Vec<PanelState> {
+        let mut last = Vec::new();
+        let res = wait_for(timeout, || {
+            last = self.panels()?;
+            f(&last).then(|| last.clone())
+        });
+        res.unwrap_or_else(|| panic!("panels never reached the expected state: {last:?}"))
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        if let Some(panel) = self.panel.as_mut() {
+            _ = panel.kill();
+            _ = panel.wait();
+        }
+        _ = self.compositor.kill();
+        _ = self.compositor.wait();
+        _ = self.dbus.kill();
+        _ = self.dbus.wait();
+    }
+}
+
+fn has_program(name: &str) -> bool {
+    Command::new("sh")
+        .args(["-c", &format!("command -v {name}")])
+        .stdout(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap().flatten() {
+        let dest = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &dest);
+        } else {
+            fs::copy(entry.path(), dest).unwrap();
+        }
+    }
+}
+
+fn wait_for<T>(timeout: Duration, mut f: impl FnMut() -> Option<T>) -> Option<T> {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if let Some(res) = f() {
+            return Some(res);
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    None
+}
+
+#[test]
+#[ignore = "needs sway and dbus-daemon"]
+fn panel_maps_along_its_edge() {
+    let mut harness = Harness::new();
+    harness.write_config(
+        &["Panel"],
+        &[
+            ("Panel", "plugins_wings", "None"),
+            ("Panel", "plugins_center", "None"),
+            ("Panel", "expand_to_edges", "true"),
+        ],
+    );
+    harness.spawn_panel();
+
+    let panels = harness.wait_for_panels(Duration::from_secs(10), |p| {
+        p.iter().any(|p| p.visibility == "visible" && p.size.0 > 0)
+    });
+    let panel = &panels[0];
+    assert_eq!(panel.name, "Panel");
+    assert_eq!(panel.output, "HEADLESS-1");
+    assert_eq!(panel.anchor, "Top");
+    assert_eq!(panel.size.0, 1920);
+    assert!(panel.size.1 > 0 && panel.size.1 < 100);
+}
+
+#[test]
+#[ignore = "needs sway and dbus-daemon"]
+fn autohide_hides_without_hover() {
+    let mut harness = Harness::new();
+    harness.write_config(
+        &["Dock"],
+        &[
+            ("Dock", "plugins_wings", "None"),
+            ("Dock", "plugins_center", "None"),
+            ("Dock", "autohide", "Some((wait_time: 100, transition_time: 100, handle_size: 4))"),
+        ],
+    );
+    harness.spawn_panel();
+
+    harness.wait_for_panels(Duration::from_secs(10), |p| {
+        p.iter().any(|p| p.name == "Dock" && p.visibility == "hidden")
+    });
+}
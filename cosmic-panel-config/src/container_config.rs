@@ -1,8 +1,13 @@
-use crate::{CosmicPanelBackground, CosmicPanelConfig, CosmicPanelOuput};
+use std::{collections::HashSet, fmt};
+
+use crate::{
+    CornerQuality, CosmicPanelBackground, CosmicPanelConfig, CosmicPanelOuput, FullscreenBehavior,
+    PanelEffects, PanelHooks, PanelThemeOverride, PopupDecorations,
+};
 use cosmic_config::{Config, ConfigGet, ConfigSet, CosmicConfigEntry};
 use serde::{Deserialize, Serialize};
 use tracing::warn;
-use xdg_shell_wrapper_config::{Layer, WrapperConfig, WrapperOutput};
+use xdg_shell_wrapper_config::{KeyboardInteractivity, Layer, WrapperConfig, WrapperOutput};
 
 /// Config structure for the cosmic panel
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -32,7 +37,71 @@ impl WrapperConfig for CosmicPanelContainerConfig {
 }
 
 pub const NAME: &str = "com.system76.CosmicPanel";
+/// name of the system config used by the panel of the greeter
+pub const GREETER_NAME: &str = "com.system76.CosmicPanelGreeter";
 pub const VERSION: u64 = 1;
+/// key of the do not disturb state in the config of the panel
+pub const DO_NOT_DISTURB: &str = "do_not_disturb";
+/// key of the applets allowed to use the privileged protocols of the compositor
+pub const PRIVILEGED_APPLETS: &str = "privileged_applets";
+
+/// A conflict between the panel entries of a config.
+///
+/// Panels on the same edge of an output which both have an exclusive zone
+/// don't conflict, they are stacked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigConflict {
+    /// the entry is listed more than once, only the first one is used
+    DuplicateName(String),
+    /// an applet is in two panels which may be shown on the same output. It is
+    /// shown in both, e.g. the default panel and dock share some applets.
+    DuplicateApplet { applet: String, first: String, second: String },
+}
+
+impl ConfigConflict {
+    /// Whether the conflict involves the panel entry `name`.
+    pub fn involves(&self, name: &str) -> bool {
+        match self {
+            ConfigConflict::DuplicateName(n) => n == name,
+            ConfigConflict::DuplicateApplet { first, second, .. } => {
+                first == name || second == name
+            },
+        }
+    }
+}
+
+impl fmt::Display for ConfigConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigConflict::DuplicateName(name) => write!(
+                f,
+                "Panel entry {name} is listed more than once, only the first one is used. Remove \
+                 the duplicate from the entries list."
+            ),
+            ConfigConflict::DuplicateApplet { applet, first, second } => write!(
+                f,
+                "Applet {applet} is in panels {first} and {second}, which may be shown on the \
+                 same output. Remove it from one of the panels unless both should show it."
+            ),
+        }
+    }
+}
+
+/// Whether panels with the outputs `a` and `b` may be shown on the same output.
+fn outputs_overlap(a: &CosmicPanelOuput, b: &CosmicPanelOuput) -> bool {
+    match (a, b) {
+        (CosmicPanelOuput::Name(a), CosmicPanelOuput::Name(b)) => a == b,
+        _ => true,
+    }
+}
+
+fn applets(config: &CosmicPanelConfig) -> Vec<String> {
+    [config.plugins_left(), config.plugins_center(), config.plugins_right()]
+        .into_iter()
+        .flatten()
+        .flatten()
+        .collect()
+}
 
 impl CosmicPanelContainerConfig {
     /// load config with the provided name
@@ -47,9 +116,38 @@ impl CosmicPanelContainerConfig {
         Self::load_from_config(&config, false)
     }
 
+    /// load the config of the panel shown by the greeter
+    ///
+    /// The greeter config is only read from the system config, and applets
+    /// never get keyboard focus.
+    pub fn load_greeter() -> Result<Self, (Vec<cosmic_config::Error>, Self)> {
+        let res = match Config::system(GREETER_NAME, VERSION) {
+            Ok(config) => Self::load_named(&config, GREETER_NAME, true),
+            Err(e) => {
+                warn!("Falling back to default greeter panel configuration");
+                Err((vec![e], Self::greeter_default()))
+            },
+        };
+        let without_keyboard = |mut c: Self| {
+            for entry in &mut c.config_list {
+                entry.keyboard_interactivity = KeyboardInteractivity::None;
+            }
+            c
+        };
+        res.map(without_keyboard).map_err(|(errors, c)| (errors, without_keyboard(c)))
+    }
+
     pub fn load_from_config(
         config: &Config,
         system: bool,
+    ) -> Result<Self, (Vec<cosmic_config::Error>, Self)> {
+        Self::load_named(config, NAME, system)
+    }
+
+    fn load_named(
+        config: &Config,
+        name: &str,
+        system: bool,
     ) -> Result<Self, (Vec<cosmic_config::Error>, Self)> {
         let entry_names = match config.get::<Vec<String>>("entries") {
             Ok(names) => names,
@@ -61,11 +159,11 @@ impl CosmicPanelContainerConfig {
         let mut config_list = Vec::new();
         let mut entry_errors = Vec::new();
 
-        for name in entry_names {
+        for entry_name in entry_names {
             let config = match if system {
-                Config::system(format!("{}.{}", NAME, name).as_str(), VERSION)
+                Config::system(format!("{}.{}", name, entry_name).as_str(), VERSION)
             } else {
-                Config::new(format!("{}.{}", NAME, name).as_str(), VERSION)
+                Config::new(format!("{}.{}", name, entry_name).as_str(), VERSION)
             } {
                 Ok(config) => config,
                 Err(e) => {
@@ -83,13 +181,112 @@ impl CosmicPanelContainerConfig {
                 },
             };
         }
+        let mut container = Self { config_list };
+        container.sync_clones();
+        for conflict in container.resolve_conflicts() {
+            warn!("Panel config conflict: {conflict}");
+        }
         if entry_errors.is_empty() {
-            Ok(Self { config_list })
+            Ok(container)
         } else {
-            Err((entry_errors, Self { config_list }))
+            Err((entry_errors, container))
+        }
+    }
+
+    /// Find the conflicts between the entries. Of an entry listed more than
+    /// once, only the first one is kept. The other conflicts are only
+    /// reported, and the entries are used as configured.
+    ///
+    /// This runs when the config is loaded, and for each entry that changes
+    /// afterwards.
+    pub fn resolve_conflicts(&mut self) -> Vec<ConfigConflict> {
+        let mut conflicts = Vec::new();
+
+        let mut names = HashSet::new();
+        self.config_list.retain(|c| {
+            let first = names.insert(c.name.clone());
+            if !first {
+                conflicts.push(ConfigConflict::DuplicateName(c.name.clone()));
+            }
+            first
+        });
+
+        for (n, config) in self.config_list.iter().enumerate() {
+            let config_applets = applets(config);
+            for other in &self.config_list[n + 1..] {
+                if !outputs_overlap(&config.output, &other.output) {
+                    continue;
+                }
+                for applet in applets(other).into_iter().filter(|a| config_applets.contains(a)) {
+                    conflicts.push(ConfigConflict::DuplicateApplet {
+                        applet,
+                        first: config.name.clone(),
+                        second: other.name.clone(),
+                    });
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Let the clones of panels follow the config of their original, e.g. if
+    /// it changed while the panel wasn't running. Clones whose original was
+    /// removed keep their config.
+    pub fn sync_clones(&mut self) {
+        let originals: Vec<CosmicPanelConfig> =
+            self.config_list.iter().filter(|c| c.clone_of.is_none()).cloned().collect();
+        for config in &mut self.config_list {
+            let Some(original) = config
+                .clone_of
+                .as_ref()
+                .and_then(|name| originals.iter().find(|c| c.name == *name))
+            else {
+                continue;
+            };
+            *config = original.synced_clone(config);
         }
     }
 
+    /// A clone of the panel `name` on the output `output`, with a new name.
+    /// The clone follows the config of the panel until it is detached, by
+    /// clearing its `clone_of`. A clone of a clone follows the same original.
+    pub fn duplicate(&self, name: &str, output: &str) -> Result<CosmicPanelConfig, String> {
+        let config = self
+            .config_list
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| format!("No panel {name}"))?;
+        let original = config
+            .clone_of
+            .as_ref()
+            .and_then(|o| self.config_list.iter().find(|c| c.name == *o))
+            .unwrap_or(config);
+
+        let new_output = CosmicPanelOuput::Name(output.to_string());
+        if self
+            .config_list
+            .iter()
+            .filter(|c| c.name == original.name || c.clone_of.as_ref() == Some(&original.name))
+            .any(|c| outputs_overlap(&c.output, &new_output))
+        {
+            return Err(format!("Panel {} is already shown on {output}", original.name));
+        }
+
+        let base = format!("{}-{output}", original.name);
+        let mut new_name = base.clone();
+        let mut n = 1;
+        while self.config_list.iter().any(|c| c.name == new_name) {
+            n += 1;
+            new_name = format!("{base}-{n}");
+        }
+
+        let mut clone = original.clone();
+        clone.name = new_name;
+        clone.output = new_output;
+        clone.clone_of = Some(original.name.clone());
+        Ok(clone)
+    }
+
     pub fn configs_for_output(&self, output_name: &str) -> Vec<&CosmicPanelConfig> {
         let mut configs: Vec<_> = self
             .config_list
@@ -104,10 +301,56 @@ impl CosmicPanelContainerConfig {
         configs
     }
 
+    /// A single top panel with the applets which are useful before login.
+    pub fn greeter_default() -> Self {
+        let mut panel = Self::default()
+            .config_list
+            .into_iter()
+            .find(|c| c.name == "Panel")
+            .expect("the default config has a panel");
+        panel.plugins_wings = Some((
+            Vec::new(),
+            vec![
+                "com.system76.CosmicAppletInputSources".to_string(),
+                "com.system76.CosmicAppletAudio".to_string(),
+                "com.system76.CosmicAppletNetwork".to_string(),
+                "com.system76.CosmicAppletBattery".to_string(),
+            ],
+        ));
+        panel.keyboard_interactivity = KeyboardInteractivity::None;
+        Self { config_list: vec![panel] }
+    }
+
     pub fn cosmic_config() -> Result<Config, cosmic_config::Error> {
         Config::new(NAME, VERSION)
     }
 
+    /// Whether do not disturb is on, which silences the badges and attention
+    /// animations of applets.
+    pub fn do_not_disturb() -> bool {
+        Self::cosmic_config().and_then(|c| c.get::<bool>(DO_NOT_DISTURB)).unwrap_or_default()
+    }
+
+    pub fn set_do_not_disturb(enabled: bool) -> Result<(), cosmic_config::Error> {
+        Self::cosmic_config()?.set(DO_NOT_DISTURB, enabled)
+    }
+
+    /// The applets which get a privileged socket to the compositor, or `None`
+    /// if there is no allowlist, not even the default one of the schema.
+    pub fn privileged_applets() -> Option<Vec<String>> {
+        Self::cosmic_config().and_then(|c| c.get::<Vec<String>>(PRIVILEGED_APPLETS)).ok()
+    }
+
+    /// The allowlist of privileged applets set by the user, or `None` if the
+    /// config predates the allowlist or uses the default one.
+    pub fn user_privileged_applets() -> Option<Vec<String>> {
+        Self::cosmic_config().and_then(|c| c.get_local::<Vec<String>>(PRIVILEGED_APPLETS)).ok()
+    }
+
+    pub fn set_privileged_applets(applets: Vec<String>) -> Result<(), cosmic_config::Error> {
+        Self::cosmic_config()?.set(PRIVILEGED_APPLETS, applets)
+    }
+
     pub fn write_entries(&self) -> Result<(), cosmic_config::Error> {
         let config = Self::cosmic_config()?;
         let entry_names = self.config_list.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
@@ -163,6 +406,23 @@ impl Default for CosmicPanelContainerConfig {
                     margin: 0,
                     opacity: 1.0,
                     autohover_delay_ms: Some(500),
+                    fullscreen: FullscreenBehavior::default(),
+                    hooks: PanelHooks::default(),
+                    rules: Vec::new(),
+                    theme_override: PanelThemeOverride::default(),
+                    appearance_profiles: Vec::new(),
+                    effects: PanelEffects::default(),
+                    corner_quality: CornerQuality::default(),
+                    reduce_motion: false,
+                    popup_decorations: PopupDecorations::default(),
+                    autohover_session_only: true,
+                    autohover_session_timeout_ms: None,
+                    activation_shortcuts: false,
+                    max_applet_expansion: 320,
+                    dock_auto_grow: None,
+                    pinned_applets: Vec::new(),
+                    clone_of: None,
+                    output_plugins: Vec::new(),
                 },
                 CosmicPanelConfig {
                     name: "Dock".to_string(),
@@ -193,12 +453,178 @@ impl Default for CosmicPanelContainerConfig {
                         wait_time: 500,
                         transition_time: 200,
                         handle_size: 2,
+                        handle_position: crate::HandlePosition::Full,
+                        ignored_app_ids: Vec::new(),
+                        forced_app_ids: Vec::new(),
+                        condensed_size: None,
+                        freeze_delay: None,
+                        hot_corners: Vec::new(),
+                        blocked_zones: Vec::new(),
                     }),
                     margin: 0,
                     opacity: 1.0,
                     autohover_delay_ms: Some(500),
+                    fullscreen: FullscreenBehavior::default(),
+                    hooks: PanelHooks::default(),
+                    rules: Vec::new(),
+                    theme_override: PanelThemeOverride::default(),
+                    appearance_profiles: Vec::new(),
+                    effects: PanelEffects::default(),
+                    corner_quality: CornerQuality::default(),
+                    reduce_motion: false,
+                    popup_decorations: PopupDecorations::default(),
+                    autohover_session_only: true,
+                    autohover_session_timeout_ms: None,
+                    activation_shortcuts: true,
+                    max_applet_expansion: 320,
+                    dock_auto_grow: None,
+                    pinned_applets: Vec::new(),
+                    clone_of: None,
+                    output_plugins: Vec::new(),
                 },
             ],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::PanelAnchor;
+
+    fn panel(name: &str, output: CosmicPanelOuput, center: &[&str]) -> CosmicPanelConfig {
+        CosmicPanelConfig {
+            name: name.to_string(),
+            output,
+            plugins_wings: None,
+            plugins_center: Some(center.iter().map(|a| a.to_string()).collect()),
+            ..Default::default()
+        }
+    }
+
+    /// The entries of the default schema installed with the panel.
+    fn default_schema() -> CosmicPanelContainerConfig {
+        let schema = Path::new(env!("CARGO_MANIFEST_DIR")).join("../data/default_schema");
+        let read = |path: String| std::fs::read_to_string(schema.join(path)).unwrap();
+        let entries: Vec<String> = ron::from_str(&read(format!("{NAME}/v1/entries"))).unwrap();
+        let config_list = entries
+            .iter()
+            .map(|entry| {
+                let key = |key: &str| read(format!("{NAME}.{entry}/v1/{key}"));
+                CosmicPanelConfig {
+                    name: ron::from_str(&key("name")).unwrap(),
+                    anchor: ron::from_str(&key("anchor")).unwrap(),
+                    output: ron::from_str(&key("output")).unwrap(),
+                    exclusive_zone: ron::from_str(&key("exclusive_zone")).unwrap(),
+                    expand_to_edges: ron::from_str(&key("expand_to_edges")).unwrap(),
+                    plugins_wings: ron::from_str(&key("plugins_wings")).unwrap(),
+                    plugins_center: ron::from_str(&key("plugins_center")).unwrap(),
+                    ..Default::default()
+                }
+            })
+            .collect();
+        CosmicPanelContainerConfig { config_list }
+    }
+
+    #[test]
+    fn duplicate_names_keep_the_first_entry() {
+        let mut config = CosmicPanelContainerConfig {
+            config_list: vec![
+                panel("Panel", CosmicPanelOuput::All, &["a"]),
+                panel("Panel", CosmicPanelOuput::All, &["b"]),
+            ],
+        };
+        let conflicts = config.resolve_conflicts();
+        assert_eq!(conflicts, vec![ConfigConflict::DuplicateName("Panel".to_string())]);
+        assert_eq!(config.config_list.len(), 1);
+        assert_eq!(config.config_list[0].plugins_center, Some(vec!["a".to_string()]));
+    }
+
+    #[test]
+    fn duplicate_applets_are_only_reported() {
+        let mut config = CosmicPanelContainerConfig {
+            config_list: vec![
+                panel("Panel", CosmicPanelOuput::All, &["a", "b"]),
+                panel("Dock", CosmicPanelOuput::Name("DP-1".to_string()), &["b", "c"]),
+                panel("Other", CosmicPanelOuput::Name("DP-2".to_string()), &["c"]),
+            ],
+        };
+        let before = config.config_list.clone();
+        let conflicts = config.resolve_conflicts();
+        assert_eq!(
+            conflicts,
+            vec![ConfigConflict::DuplicateApplet {
+                applet: "b".to_string(),
+                first: "Panel".to_string(),
+                second: "Dock".to_string(),
+            }]
+        );
+        assert!(conflicts[0].involves("Dock") && !conflicts[0].involves("Other"));
+        for (config, before) in config.config_list.iter().zip(&before) {
+            assert_eq!(config.plugins_center, before.plugins_center);
+        }
+    }
+
+    #[test]
+    fn stacked_panels_keep_their_exclusive_zones() {
+        let mut config = CosmicPanelContainerConfig {
+            config_list: vec![
+                panel("Panel", CosmicPanelOuput::All, &["a"]),
+                panel("Stacked", CosmicPanelOuput::All, &["b"]),
+            ],
+        };
+        for c in &mut config.config_list {
+            c.anchor = PanelAnchor::Top;
+            c.exclusive_zone = true;
+        }
+        assert!(config.resolve_conflicts().is_empty());
+        assert!(config.config_list.iter().all(|c| c.exclusive_zone));
+    }
+
+    #[test]
+    fn default_schema_is_used_as_configured() {
+        let mut config = default_schema();
+        let before = config.config_list.clone();
+        let conflicts = config.resolve_conflicts();
+        assert!(conflicts.iter().all(|c| matches!(c, ConfigConflict::DuplicateApplet { .. })));
+        assert_eq!(config.config_list.len(), before.len());
+        for (config, before) in config.config_list.iter().zip(&before) {
+            assert_eq!(config.name, before.name);
+            assert_eq!(config.exclusive_zone, before.exclusive_zone);
+            assert_eq!(config.plugins_wings, before.plugins_wings);
+            assert_eq!(config.plugins_center, before.plugins_center);
+        }
+        let dock = config.config_list.iter().find(|c| c.name == "Dock").unwrap();
+        let dock_applets = applets(dock);
+        assert!(dock_applets.iter().any(|a| a == "com.system76.CosmicPanelWorkspacesButton"));
+        assert!(dock_applets.iter().any(|a| a == "com.system76.CosmicPanelAppButton"));
+    }
+
+    #[test]
+    fn default_schema_has_privileged_applets() {
+        let schema = Path::new(env!("CARGO_MANIFEST_DIR")).join("../data/default_schema");
+        let privileged =
+            std::fs::read_to_string(schema.join(format!("{NAME}/v1/{PRIVILEGED_APPLETS}")));
+        let privileged: Vec<String> = ron::from_str(&privileged.unwrap()).unwrap();
+        assert_eq!(
+            privileged,
+            [
+                "com.system76.CosmicAppList",
+                "com.system76.CosmicAppletMinimize",
+                "com.system76.CosmicAppletTiling",
+                "com.system76.CosmicAppletWorkspaces",
+            ]
+        );
+    }
+
+    #[test]
+    fn fullscreen_windows_are_ignored_by_default() {
+        assert_eq!(CosmicPanelConfig::default().fullscreen, FullscreenBehavior::Ignore);
+        assert!(CosmicPanelContainerConfig::default()
+            .config_list
+            .iter()
+            .all(|c| c.fullscreen == FullscreenBehavior::Ignore));
+    }
+}
@@ -226,6 +226,61 @@ const fn _default_true() -> bool {
     true
 }
 
+/// Corner of the output of a panel
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum PanelCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// A corner of the output which reveals an autohidden panel when the pointer
+/// rests in it
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct HotCorner {
+    pub corner: PanelCorner,
+    /// time in milliseconds the pointer has to rest in the corner
+    pub dwell_time: u32,
+}
+
+/// Part of the edge of an autohidden panel whose handle reveals it, e.g. to
+/// leave room for the horizontal scrollbars of windows below a bottom panel
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum HandlePosition {
+    /// the whole length of the panel
+    #[default]
+    Full,
+    /// the given length in pixels at the start of the panel
+    Start(u32),
+    /// the given length in pixels at the center of the panel
+    Center(u32),
+    /// the given length in pixels at the end of the panel
+    End(u32),
+    /// the part between two percentages of the length of the panel
+    Range(u32, u32),
+}
+
+impl HandlePosition {
+    /// The offset and length of the handle along a panel of `length`.
+    pub fn span(&self, length: i32) -> (i32, i32) {
+        let length = length.max(0);
+        let part = |l: u32| l.min(length as u32) as i32;
+        match *self {
+            HandlePosition::Full => (0, length),
+            HandlePosition::Start(l) => (0, part(l)),
+            HandlePosition::Center(l) => ((length - part(l)) / 2, part(l)),
+            HandlePosition::End(l) => (length - part(l), part(l)),
+            HandlePosition::Range(from, to) => {
+                let from = from.min(100) as i32;
+                let to = (to.min(100) as i32).max(from);
+                (length * from / 100, length * (to - from) / 100)
+            },
+        }
+    }
+}
+
 // TODO configurable interpolation type?
 /// configurable autohide behavior
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
@@ -238,14 +293,273 @@ pub struct AutoHide {
     /// size of the handle in pixels
     /// should be > 0
     pub handle_size: u32,
+    /// part of the edge whose handle reveals the panel
+    #[serde(default)]
+    pub handle_position: HandlePosition,
+    /// app ids whose windows never hide the panel when they overlap it
+    #[serde(default)]
+    pub ignored_app_ids: Vec<String>,
+    /// app ids whose windows hide the panel even without overlapping it
+    #[serde(default)]
+    pub forced_app_ids: Vec<String>,
+    /// shrink the panel to this size instead of hiding it
+    #[serde(default)]
+    pub condensed_size: Option<PanelSize>,
+    /// time in milliseconds hidden before the applets are paused, or never
+    #[serde(default)]
+    pub freeze_delay: Option<u32>,
+    /// corners of the output which reveal the panel
+    #[serde(default)]
+    pub hot_corners: Vec<HotCorner>,
+    /// segments of the edge of the output where hover doesn't reveal the
+    /// panel, from and to in pixels along the edge
+    #[serde(default)]
+    pub blocked_zones: Vec<(u32, u32)>,
 }
 
 impl Default for AutoHide {
     fn default() -> Self {
-        Self { wait_time: 1000, transition_time: 200, handle_size: 4 }
+        Self {
+            wait_time: 1000,
+            transition_time: 200,
+            handle_size: 4,
+            handle_position: HandlePosition::Full,
+            ignored_app_ids: Vec::new(),
+            forced_app_ids: Vec::new(),
+            condensed_size: None,
+            freeze_delay: None,
+            hot_corners: Vec::new(),
+            blocked_zones: Vec::new(),
+        }
+    }
+}
+
+/// Colors replacing those of the cosmic theme for a single panel, its iced
+/// elements and its applets. Colors are RGB, from 0 to 1.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PanelThemeOverride {
+    pub accent: Option<[f32; 3]>,
+    /// background of the panel and its popups
+    pub background: Option<[f32; 3]>,
+    /// background of containers on the panel background
+    pub primary_container: Option<[f32; 3]>,
+}
+
+impl PanelThemeOverride {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Anti-aliasing of the rounded corners of the panel background
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CornerQuality {
+    /// samples per pixel along each axis, from 1 to 4
+    pub samples: u8,
+    /// width of the smoothed edge relative to the default, or 0 for a hard
+    /// edge
+    pub smoothing: f32,
+}
+
+impl Default for CornerQuality {
+    fn default() -> Self {
+        Self { samples: 1, smoothing: 1.0 }
+    }
+}
+
+/// Shadow and rounded corners drawn by the panel around applet popups, so
+/// popups look alike regardless of the toolkit of the applet
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PopupDecorations {
+    /// decorate the popups of the applets which aren't excluded
+    pub enabled: bool,
+    /// radius of the corners, in logical pixels
+    pub radius: u32,
+    /// size of the shadow, in logical pixels
+    pub shadow: u32,
+    /// applets which draw their own decorations
+    pub excluded_applets: Vec<String>,
+}
+
+impl Default for PopupDecorations {
+    fn default() -> Self {
+        Self { enabled: false, radius: 8, shadow: 12, excluded_applets: Vec::new() }
+    }
+}
+
+/// Position along the panel an applet is pinned to
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum PinPosition {
+    /// centered on the panel
+    Center,
+    /// the given logical pixels from the start of the panel
+    FromStart(u32),
+    /// the given logical pixels from the end of the panel
+    FromEnd(u32),
+}
+
+/// An applet placed at a fixed position along the panel, regardless of the
+/// applets next to it
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct PinnedApplet {
+    pub applet: String,
+    pub position: PinPosition,
+}
+
+/// Applets of a panel on one output, replacing the applets of the panel there
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct OutputPlugins {
+    /// name of the output
+    pub output: String,
+    /// replaces plugins_wings on the output, if set
+    #[serde(default)]
+    pub plugins_wings: Option<(Vec<String>, Vec<String>)>,
+    /// replaces plugins_center on the output, if set
+    #[serde(default)]
+    pub plugins_center: Option<Vec<String>>,
+}
+
+/// Length of a dock which grows and shrinks with its applets
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "DockAutoGrowConfig")]
+pub struct DockAutoGrow {
+    /// shortest length of the dock, in logical pixels
+    pub min_length: u32,
+    /// longest length of the dock in logical pixels, up to which applets are
+    /// granted the extra length they request
+    pub max_length: u32,
+}
+
+/// [`DockAutoGrow`] as it is written in the config, before it is checked
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DockAutoGrowConfig {
+    min_length: u32,
+    max_length: u32,
+}
+
+impl TryFrom<DockAutoGrowConfig> for DockAutoGrow {
+    type Error = String;
+
+    fn try_from(config: DockAutoGrowConfig) -> Result<Self, Self::Error> {
+        let DockAutoGrowConfig { min_length, max_length } = config;
+        if min_length > max_length {
+            return Err(format!("min_length {min_length} is longer than max_length {max_length}"));
+        }
+        Ok(Self { min_length, max_length })
     }
 }
 
+/// Animated effects drawn on the panel background
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PanelEffects {
+    /// slowly shift a subtle gradient along the panel
+    pub gradient: bool,
+    /// sweep a shimmer along the panel when the pointer enters it
+    pub hover_shimmer: bool,
+}
+
+/// Shell commands run on events of a panel, with `COSMIC_PANEL_NAME`,
+/// `COSMIC_PANEL_OUTPUT` and `COSMIC_PANEL_EVENT` set, and `COSMIC_PANEL_APPLET`
+/// for applet events
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PanelHooks {
+    /// the autohidden panel was revealed
+    pub shown: Option<String>,
+    /// the autohidden panel was hidden
+    pub hidden: Option<String>,
+    /// the panel was placed on an output, or its output was resized or scaled
+    pub output_changed: Option<String>,
+    /// an applet exited with an error
+    pub applet_crashed: Option<String>,
+}
+
+/// Condition of a [`PanelRule`]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub enum RuleCondition {
+    /// a toplevel with the app id is shown on the output of the panel
+    AppId(String),
+    /// the active toplevel has the app id
+    ActiveAppId(String),
+    /// the active workspace on the output of the panel has the name
+    Workspace(String),
+    /// the system runs on battery
+    OnBattery,
+    /// the battery charge is below the percentage
+    BatteryBelow(f32),
+}
+
+/// Effect of a [`PanelRule`]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub enum RuleAction {
+    /// hide the panel, like while presenting
+    Hide,
+    /// keep the panel revealed, even if it autohides or a fullscreen window
+    /// would hide it
+    Show,
+    /// replace the configured opacity
+    Opacity(f32),
+}
+
+/// Applies `then` while all conditions in `when` hold. Of the rules which
+/// apply, later ones override earlier ones with a conflicting action.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PanelRule {
+    pub when: Vec<RuleCondition>,
+    pub then: RuleAction,
+}
+
+/// When an [`AppearanceProfile`] applies
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub enum ProfileSchedule {
+    /// from one local time of day to another, as hours and minutes. The range
+    /// continues past midnight if it ends before it starts.
+    Time { from: (u8, u8), to: (u8, u8) },
+    /// the system theme is dark
+    Dark,
+    /// the system theme is light
+    Light,
+}
+
+/// Appearance of the panel while `when` applies. Of the profiles which apply,
+/// later ones override the values of earlier ones.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AppearanceProfile {
+    pub when: ProfileSchedule,
+    #[serde(default)]
+    pub opacity: Option<f32>,
+    #[serde(default)]
+    pub background: Option<CosmicPanelBackground>,
+    #[serde(default)]
+    pub size: Option<PanelSize>,
+}
+
+/// What a panel on the top or overlay layer does while a fullscreen window is
+/// shown on its output. Panels keep their layer by default, like before the
+/// option existed.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum FullscreenBehavior {
+    /// hide the panel, like while presenting
+    Hide,
+    /// move the panel to the bottom layer, below windows
+    Bottom,
+    /// stay on the configured layer
+    #[default]
+    Ignore,
+}
+
 /// Configuration for the panel's ouput
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 #[serde(deny_unknown_fields)]
@@ -346,6 +660,48 @@ pub struct CosmicPanelConfig {
     /// autohover popup delay duration in milliseconds
     /// If None, then it is disabled
     pub autohover_delay_ms: Option<u32>,
+    /// only open popups on hover while another popup is open, otherwise
+    /// hovering an applet opens its popup from a cold hover too
+    pub autohover_session_only: bool,
+    /// how long hovering applets still opens their popups after the last
+    /// popup closed, in milliseconds
+    pub autohover_session_timeout_ms: Option<u32>,
+    /// activate the Nth applet of this panel with `cosmic-panel-ctl
+    /// activate-applet N`, which is meant to be bound to Super+1..9
+    pub activation_shortcuts: bool,
+    /// longest extra length in logical pixels an applet may request for a
+    /// while, e.g. for an expanding search field, or 0 to deny requests
+    pub max_applet_expansion: u32,
+    /// animate the length of a panel without expand_to_edges as its applets
+    /// need more or less space
+    pub dock_auto_grow: Option<DockAutoGrow>,
+    /// applets placed at fixed positions, which the other applets of the
+    /// panel move out of the way of
+    pub pinned_applets: Vec<PinnedApplet>,
+    /// name of the panel this one is a clone of, whose config it follows
+    /// except for its name and output
+    pub clone_of: Option<String>,
+    /// applets of the panel on specific outputs, e.g. to only show the
+    /// notifications applet on the primary output
+    pub output_plugins: Vec<OutputPlugins>,
+    /// behavior while a fullscreen window is shown on the output
+    pub fullscreen: FullscreenBehavior,
+    /// commands run on events of the panel
+    pub hooks: PanelHooks,
+    /// rules changing the visibility and opacity of the panel
+    pub rules: Vec<PanelRule>,
+    /// colors replacing those of the theme for this panel
+    pub theme_override: PanelThemeOverride,
+    /// appearance variations by time of day or theme mode
+    pub appearance_profiles: Vec<AppearanceProfile>,
+    /// animated effects of the panel background
+    pub effects: PanelEffects,
+    /// anti-aliasing of the rounded corners
+    pub corner_quality: CornerQuality,
+    /// skip the animations of popups
+    pub reduce_motion: bool,
+    /// shadow and rounded corners of applet popups
+    pub popup_decorations: PopupDecorations,
 }
 
 impl PartialEq for CosmicPanelConfig {
@@ -372,6 +728,23 @@ impl PartialEq for CosmicPanelConfig {
             && self.size_center == other.size_center
             && self.size_wings == other.size_wings
             && (self.opacity - other.opacity).abs() < 0.01
+            && self.fullscreen == other.fullscreen
+            && self.hooks == other.hooks
+            && self.rules == other.rules
+            && self.theme_override == other.theme_override
+            && self.appearance_profiles == other.appearance_profiles
+            && self.effects == other.effects
+            && self.corner_quality == other.corner_quality
+            && self.reduce_motion == other.reduce_motion
+            && self.popup_decorations == other.popup_decorations
+            && self.autohover_session_only == other.autohover_session_only
+            && self.autohover_session_timeout_ms == other.autohover_session_timeout_ms
+            && self.activation_shortcuts == other.activation_shortcuts
+            && self.max_applet_expansion == other.max_applet_expansion
+            && self.dock_auto_grow == other.dock_auto_grow
+            && self.pinned_applets == other.pinned_applets
+            && self.clone_of == other.clone_of
+            && self.output_plugins == other.output_plugins
     }
 }
 
@@ -400,11 +773,28 @@ impl Default for CosmicPanelConfig {
             margin: 4,
             opacity: 0.8,
             autohover_delay_ms: Some(500),
+            fullscreen: FullscreenBehavior::default(),
+            hooks: PanelHooks::default(),
+            rules: Vec::new(),
+            theme_override: PanelThemeOverride::default(),
+            appearance_profiles: Vec::new(),
+            effects: PanelEffects::default(),
+            corner_quality: CornerQuality::default(),
+            reduce_motion: false,
+            popup_decorations: PopupDecorations::default(),
+            autohover_session_only: true,
+            autohover_session_timeout_ms: None,
+            activation_shortcuts: false,
+            max_applet_expansion: 320,
+            dock_auto_grow: None,
+            pinned_applets: Vec::new(),
+            clone_of: None,
+            output_plugins: Vec::new(),
         }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Side {
     WingStart,
     Center,
@@ -537,6 +927,36 @@ impl CosmicPanelConfig {
         self.plugins_wings.as_ref().map(|w| w.1.clone())
     }
 
+    /// The applets of the panel on the output `output`.
+    pub fn output_plugins(&self, output: Option<&str>) -> Option<&OutputPlugins> {
+        let output = output?;
+        self.output_plugins.iter().find(|o| o.output == output)
+    }
+
+    /// The applets on the left / top of the panel on the output `output`.
+    pub fn plugins_left_on(&self, output: Option<&str>) -> Option<Vec<String>> {
+        match self.output_plugins(output).and_then(|o| o.plugins_wings.as_ref()) {
+            Some(w) => Some(w.0.clone()),
+            None => self.plugins_left(),
+        }
+    }
+
+    /// The applets in the center of the panel on the output `output`.
+    pub fn plugins_center_on(&self, output: Option<&str>) -> Option<Vec<String>> {
+        match self.output_plugins(output).and_then(|o| o.plugins_center.as_ref()) {
+            Some(c) => Some(c.clone()),
+            None => self.plugins_center(),
+        }
+    }
+
+    /// The applets on the right / bottom of the panel on the output `output`.
+    pub fn plugins_right_on(&self, output: Option<&str>) -> Option<Vec<String>> {
+        match self.output_plugins(output).and_then(|o| o.plugins_wings.as_ref()) {
+            Some(w) => Some(w.1.clone()),
+            None => self.plugins_right(),
+        }
+    }
+
     pub fn anchor(&self) -> PanelAnchor {
         self.anchor
     }
@@ -597,6 +1017,17 @@ impl CosmicPanelConfig {
         Config::new(&entry_name, VERSION)
     }
 
+    /// The config of `clone` following this panel, which keeps the name,
+    /// output and original of `clone`.
+    pub fn synced_clone(&self, clone: &CosmicPanelConfig) -> CosmicPanelConfig {
+        CosmicPanelConfig {
+            name: clone.name.clone(),
+            output: clone.output.clone(),
+            clone_of: clone.clone_of.clone(),
+            ..self.clone()
+        }
+    }
+
     pub fn maximize(&mut self) {
         self.opacity = 1.0;
         if self.autohide().is_some() {